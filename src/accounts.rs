@@ -0,0 +1,613 @@
+use crate::display;
+use crate::encryption;
+use crate::history;
+use crate::node_ui;
+use crate::ui_util;
+use crate::worker::{self, AppCommand, CommandKind, Worker};
+use ldk_node::bitcoin::{Address, Network, Txid};
+use ldk_node::lightning::ln::channelmanager::PaymentId;
+use ldk_node::lightning::ln::types::PaymentHash;
+use ldk_node::lightning_invoice::Bolt11Invoice;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{error, info, warn};
+
+const ACCOUNTS_FILE_NAME: &str = "accounts.json";
+
+/// Withdrawals listed in the queue, newest first
+const MAX_QUEUE_ROWS: usize = 20;
+
+/// An invoice generated for deposits into an account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DepositInvoice {
+    account: String,
+    amount_msats: u64,
+}
+
+/// Where a withdrawal is paid to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum Destination {
+    Lightning { invoice: String },
+    Onchain { address: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum WithdrawalStatus {
+    /// Waiting for the operator to approve or reject it
+    Requested,
+    /// Approved and handed to the worker
+    Sending,
+    /// Lightning payment sent and not yet resolved, by payment id in hex
+    InFlight { payment_id: String },
+    /// Paid; the reference is the payment id or txid
+    Completed { reference: String },
+    Failed { reason: String },
+    Rejected,
+}
+
+/// A withdrawal requested from an account. Its amount is held from the request until it
+/// completes, and given back if it is rejected or fails.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Withdrawal {
+    id: u64,
+    account: String,
+    amount_msats: u64,
+    destination: Destination,
+    requested_at: i64,
+    approved_at: Option<i64>,
+    /// Why the operator approved or rejected it
+    note: String,
+    status: WithdrawalStatus,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct AccountsData {
+    /// Balances in msats, keyed by account name
+    balances: BTreeMap<String, u64>,
+    /// Received payments no deposit invoice was generated for
+    unattributed_msats: u64,
+    /// Deposit invoices, keyed by payment hash in hex
+    invoices: HashMap<String, DepositInvoice>,
+    /// Payment hashes already credited, so a replayed event isn't counted twice
+    credited: HashSet<String>,
+    /// Withdrawals in request order, resolved ones included
+    withdrawal_queue: Vec<Withdrawal>,
+    next_withdrawal_id: u64,
+    /// Withdrawals up to this many sats are approved without the operator; zero approves
+    /// none
+    auto_approve_sats: u64,
+}
+
+/// The exchange's customer accounts: deposits are credited when an invoice generated for
+/// an account is paid, and withdrawals wait in a queue for the operator before being paid
+/// out of an account's balance. Persisted to the data dir.
+pub struct Accounts {
+    path: PathBuf,
+    data: AccountsData,
+    network: Network,
+    new_account: String,
+    deposit_account: String,
+    deposit_amount: String,
+    deposit_invoice: String,
+    withdraw_account: String,
+    /// Withdraw to an on-chain address rather than a Lightning invoice
+    withdraw_onchain: bool,
+    /// Invoice or address, per `withdraw_onchain`
+    withdraw_destination: String,
+    withdraw_amount: String,
+    /// Note recorded with the next approval or rejection
+    operator_note: String,
+    auto_approve_input: String,
+}
+
+impl Accounts {
+    pub fn load(data_dir: &Path, network: Network) -> Self {
+        let path = data_dir.join(ACCOUNTS_FILE_NAME);
+        let mut data = if path.exists() {
+            match encryption::read_state_file(&path).map(|s| serde_json::from_str(&s)) {
+                Ok(Ok(data)) => data,
+                Ok(Err(e)) => {
+                    error!("Ignoring unreadable {}: {}", path.display(), e);
+                    AccountsData::default()
+                }
+                Err(e) => {
+                    error!("Failed to read {}: {}", path.display(), e);
+                    AccountsData::default()
+                }
+            }
+        } else {
+            AccountsData::default()
+        };
+        // A withdrawal handed to the worker before a restart has no recorded outcome; put it
+        // back in front of the operator rather than guess
+        for withdrawal in data.withdrawal_queue.iter_mut().filter(|w| w.status == WithdrawalStatus::Sending) {
+            warn!("Withdrawal {} was being sent at shutdown; queued for approval again", withdrawal.id);
+            withdrawal.status = WithdrawalStatus::Requested;
+            withdrawal.note = "Interrupted by a restart; check the payments list before approving again".to_string();
+        }
+        let auto_approve_input = data.auto_approve_sats.to_string();
+        Self {
+            path,
+            data,
+            network,
+            new_account: String::new(),
+            deposit_account: String::new(),
+            deposit_amount: String::new(),
+            deposit_invoice: String::new(),
+            withdraw_account: String::new(),
+            withdraw_onchain: false,
+            withdraw_destination: String::new(),
+            withdraw_amount: String::new(),
+            operator_note: String::new(),
+            auto_approve_input,
+        }
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string(&self.data)
+            .map_err(|e| e.to_string())
+            .and_then(|json| encryption::write_state_file(&self.path, &json).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            error!("Failed to save {}: {}", self.path.display(), e);
+        }
+    }
+
+    pub fn create(&mut self, name: &str) -> Result<(), String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Account name is empty".to_string());
+        }
+        if self.data.balances.contains_key(name) {
+            return Err(format!("Account {} already exists", name));
+        }
+        self.data.balances.insert(name.to_string(), 0);
+        self.save();
+        Ok(())
+    }
+
+    /// Tags a newly generated invoice as a deposit into `account`
+    pub fn record_deposit_invoice(&mut self, account: &str, invoice: &Bolt11Invoice) {
+        let amount_msats = invoice.amount_milli_satoshis().unwrap_or(0);
+        let deposit = DepositInvoice { account: account.to_string(), amount_msats };
+        self.data.invoices.insert(invoice.payment_hash().to_string(), deposit);
+        self.deposit_invoice = invoice.to_string();
+        self.save();
+    }
+
+    /// Credits a received payment to the account its invoice was generated for, or to the
+    /// unattributed bucket. ldk-node reports a payment received only once every part of it
+    /// has been claimed, so a partial MPP receipt is never credited.
+    pub fn credit(&mut self, payment_hash: &PaymentHash, amount_msats: u64) {
+        let hash = payment_hash.to_string();
+        if !self.data.credited.insert(hash.clone()) {
+            return;
+        }
+        match self.data.invoices.remove(&hash) {
+            Some(deposit) => {
+                if amount_msats < deposit.amount_msats {
+                    warn!(
+                        "Deposit {} for {} paid {} msats of the {} invoiced",
+                        hash, deposit.account, amount_msats, deposit.amount_msats
+                    );
+                }
+                *self.data.balances.entry(deposit.account.clone()).or_insert(0) += amount_msats;
+                info!("Credited {} msats to account {}", amount_msats, deposit.account);
+            }
+            None => {
+                self.data.unattributed_msats += amount_msats;
+                info!("Received {} msats with no deposit invoice; left unattributed", amount_msats);
+            }
+        }
+        self.save();
+    }
+
+    /// Takes `amount_msats` out of `account` for a requested withdrawal
+    fn hold(&mut self, account: &str, amount_msats: u64) -> Result<(), String> {
+        let balance = self.data.balances.get_mut(account).ok_or_else(|| format!("No account {}", account))?;
+        if *balance < amount_msats {
+            return Err(format!(
+                "Account {} holds {}, not enough for {}",
+                account,
+                display::sats(*balance / 1000),
+                display::sats(amount_msats / 1000)
+            ));
+        }
+        *balance -= amount_msats;
+        Ok(())
+    }
+
+    /// Gives held funds back to `account`
+    fn release(&mut self, account: &str, amount_msats: u64) {
+        *self.data.balances.entry(account.to_string()).or_insert(0) += amount_msats;
+    }
+
+    fn withdrawal_mut(&mut self, id: u64) -> Option<&mut Withdrawal> {
+        self.data.withdrawal_queue.iter_mut().find(|w| w.id == id)
+    }
+
+    /// Ends a withdrawal unpaid, giving its amount back to its account
+    fn fail_withdrawal(&mut self, id: u64, status: WithdrawalStatus) {
+        let Some(withdrawal) = self.withdrawal_mut(id) else {
+            return;
+        };
+        withdrawal.status = status;
+        let (account, amount_msats) = (withdrawal.account.clone(), withdrawal.amount_msats);
+        self.release(&account, amount_msats);
+        self.save();
+    }
+
+    /// Approves a requested withdrawal and asks the worker to send it
+    fn approve(&mut self, id: u64, note: String, worker: &mut Worker) -> String {
+        let network = self.network;
+        let Some(withdrawal) = self.withdrawal_mut(id).filter(|w| w.status == WithdrawalStatus::Requested) else {
+            return format!("Withdrawal {} is not waiting for approval", id);
+        };
+        let command = match &withdrawal.destination {
+            Destination::Lightning { invoice } => Bolt11Invoice::from_str(invoice).ok().map(|invoice| {
+                let amount_msats = invoice.amount_milli_satoshis().is_none().then_some(withdrawal.amount_msats);
+                AppCommand::AccountWithdraw { withdrawal_id: id, invoice, amount_msats }
+            }),
+            Destination::Onchain { address } => parse_address(address, network).ok().map(|address| {
+                AppCommand::AccountSendOnchain { withdrawal_id: id, address, amount_sats: withdrawal.amount_msats / 1000 }
+            }),
+        };
+        withdrawal.approved_at = Some(now());
+        withdrawal.note = note;
+        let account = withdrawal.account.clone();
+        match command {
+            Some(command) => {
+                withdrawal.status = WithdrawalStatus::Sending;
+                self.save();
+                worker.submit(command);
+                format!("Sending withdrawal {} from {}...", id, account)
+            }
+            None => {
+                self.fail_withdrawal(id, WithdrawalStatus::Failed { reason: "Invalid destination".to_string() });
+                format!("Withdrawal {} has an invalid destination", id)
+            }
+        }
+    }
+
+    fn reject(&mut self, id: u64, note: String) -> String {
+        match self.withdrawal_mut(id).filter(|w| w.status == WithdrawalStatus::Requested) {
+            Some(withdrawal) => {
+                withdrawal.approved_at = Some(now());
+                withdrawal.note = note;
+                self.fail_withdrawal(id, WithdrawalStatus::Rejected);
+                format!("Rejected withdrawal {}", id)
+            }
+            None => format!("Withdrawal {} is not waiting for approval", id),
+        }
+    }
+
+    /// Records what happened when the worker sent a Lightning withdrawal
+    pub fn withdrawal_sent(&mut self, id: u64, result: Result<PaymentId, String>) {
+        match result {
+            Ok(payment_id) => {
+                if let Some(withdrawal) = self.withdrawal_mut(id) {
+                    withdrawal.status = WithdrawalStatus::InFlight { payment_id: hex::encode(payment_id.0) };
+                    self.save();
+                }
+            }
+            Err(reason) => self.fail_withdrawal(id, WithdrawalStatus::Failed { reason }),
+        }
+    }
+
+    /// Records what happened when the worker sent an on-chain withdrawal. The miner fee is
+    /// the exchange's, not the account's.
+    pub fn onchain_withdrawal_sent(&mut self, id: u64, result: Result<Txid, String>) {
+        match result {
+            Ok(txid) => {
+                if let Some(withdrawal) = self.withdrawal_mut(id) {
+                    withdrawal.status = WithdrawalStatus::Completed { reference: txid.to_string() };
+                    info!("Withdrew {} msats on-chain from account {}", withdrawal.amount_msats, withdrawal.account);
+                    self.save();
+                }
+            }
+            Err(reason) => self.fail_withdrawal(id, WithdrawalStatus::Failed { reason }),
+        }
+    }
+
+    fn in_flight(&self, payment_id: &PaymentId) -> Option<u64> {
+        let payment_id = hex::encode(payment_id.0);
+        self.data
+            .withdrawal_queue
+            .iter()
+            .find(|w| matches!(&w.status, WithdrawalStatus::InFlight { payment_id: id } if *id == payment_id))
+            .map(|w| w.id)
+    }
+
+    /// Completes a Lightning withdrawal that succeeded, also charging the routing fee to
+    /// its account
+    pub fn withdrawal_succeeded(&mut self, payment_id: &PaymentId, fee_msats: u64) {
+        let Some(withdrawal) = self.in_flight(payment_id).and_then(|id| self.withdrawal_mut(id)) else {
+            return;
+        };
+        withdrawal.status = WithdrawalStatus::Completed { reference: hex::encode(payment_id.0) };
+        let (account, amount_msats) = (withdrawal.account.clone(), withdrawal.amount_msats);
+        if let Some(balance) = self.data.balances.get_mut(&account) {
+            *balance = balance.saturating_sub(fee_msats);
+        }
+        info!("Withdrew {} msats from account {}", amount_msats + fee_msats, account);
+        self.save();
+    }
+
+    /// Returns a failed Lightning withdrawal's amount to its account
+    pub fn withdrawal_failed(&mut self, payment_id: &PaymentId, reason: String) {
+        if let Some(id) = self.in_flight(payment_id) {
+            warn!("Withdrawal {} failed: {}", id, reason);
+            self.fail_withdrawal(id, WithdrawalStatus::Failed { reason });
+        }
+    }
+
+    /// Asks the worker for an invoice depositing the typed amount into the chosen account
+    fn request_deposit(&mut self, worker: &mut Worker) -> String {
+        if !self.data.balances.contains_key(&self.deposit_account) {
+            return "Choose an account to deposit to".to_string();
+        }
+        match node_ui::parse_sats(&self.deposit_amount) {
+            Ok(sats) if sats > 0 => {
+                worker.submit(AppCommand::DepositInvoice {
+                    account: self.deposit_account.clone(),
+                    amount_msats: sats * 1000,
+                });
+                "Generating deposit invoice...".to_string()
+            }
+            Ok(_) => "Invalid amount".to_string(),
+            Err(e) => e,
+        }
+    }
+
+    /// Holds the withdrawal's amount in its account and queues it, approving it at once if
+    /// it is under the auto-approve threshold
+    fn request_withdrawal(&mut self, worker: &mut Worker) -> String {
+        let destination = self.withdraw_destination.trim().to_string();
+        let typed_msats = || match node_ui::parse_sats(&self.withdraw_amount) {
+            Ok(sats) if sats > 0 => Ok(sats * 1000),
+            Ok(_) => Err("Enter an amount".to_string()),
+            Err(e) => Err(e),
+        };
+        let (destination, amount_msats) = if self.withdraw_onchain {
+            if let Err(e) = parse_address(&destination, self.network) {
+                return e;
+            }
+            match typed_msats() {
+                Ok(msats) => (Destination::Onchain { address: destination }, msats),
+                Err(e) => return e,
+            }
+        } else {
+            let invoice = match Bolt11Invoice::from_str(&destination) {
+                Ok(invoice) => invoice,
+                Err(e) => return format!("Invalid invoice: {}", e),
+            };
+            let amount_msats = match invoice.amount_milli_satoshis() {
+                Some(msats) => msats,
+                None => match typed_msats() {
+                    Ok(msats) => msats,
+                    Err(_) => return "Invoice has no amount; enter one".to_string(),
+                },
+            };
+            (Destination::Lightning { invoice: destination }, amount_msats)
+        };
+
+        let account = self.withdraw_account.clone();
+        if let Err(e) = self.hold(&account, amount_msats) {
+            return e;
+        }
+        let id = self.data.next_withdrawal_id;
+        self.data.next_withdrawal_id += 1;
+        self.data.withdrawal_queue.push(Withdrawal {
+            id,
+            account: account.clone(),
+            amount_msats,
+            destination,
+            requested_at: now(),
+            approved_at: None,
+            note: String::new(),
+            status: WithdrawalStatus::Requested,
+        });
+        self.save();
+        self.withdraw_destination.clear();
+        self.withdraw_amount.clear();
+
+        if amount_msats <= self.data.auto_approve_sats * 1000 {
+            return self.approve(id, "Auto-approved under the threshold".to_string(), worker);
+        }
+        format!("Withdrawal {} from {} is waiting for approval", id, account)
+    }
+
+    /// "Accounts" panel: balances, then forms for adding an account, depositing and
+    /// requesting a withdrawal, then the withdrawal queue
+    pub fn show(&mut self, ui: &mut egui::Ui, worker: &mut Worker, btc_price: f64) -> Option<String> {
+        let mut status = None;
+        egui::CollapsingHeader::new("Accounts").show(ui, |ui| {
+            egui::Grid::new("accounts").striped(true).num_columns(3).show(ui, |ui| {
+                for header in ["Account", "Balance", "USD"] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+                for (name, msats) in &self.data.balances {
+                    ui.label(name);
+                    ui.label(display::sats(msats / 1000));
+                    ui.label(format!("${:.2}", msats_to_usd(*msats, btc_price)));
+                    ui.end_row();
+                }
+                ui.label(egui::RichText::new("Unattributed").italics());
+                ui.label(display::sats(self.data.unattributed_msats / 1000));
+                ui.label(format!("${:.2}", msats_to_usd(self.data.unattributed_msats, btc_price)));
+                ui.end_row();
+            });
+            ui.horizontal(|ui| {
+                ui.label("New account:");
+                ui.text_edit_singleline(&mut self.new_account);
+                if ui.button("Add").clicked() {
+                    let name = std::mem::take(&mut self.new_account);
+                    status = Some(match self.create(&name) {
+                        Ok(()) => format!("Added account {}", name.trim()),
+                        Err(e) => e,
+                    });
+                }
+            });
+
+            ui.separator();
+            ui.label("Deposit");
+            ui.horizontal(|ui| {
+                account_picker(ui, "deposit_account", &self.data.balances, &mut self.deposit_account);
+                ui.label("Amount (sats):");
+                ui.text_edit_singleline(&mut self.deposit_amount);
+                if worker::command_button(ui, worker, CommandKind::DepositInvoice, "Get Invoice") {
+                    status = Some(self.request_deposit(worker));
+                }
+            });
+            if !self.deposit_invoice.is_empty() {
+                if let Some(qr) = ui_util::qr_texture(ui.ctx(), &self.deposit_invoice) {
+                    ui.image(&qr);
+                }
+                ui.text_edit_multiline(&mut self.deposit_invoice);
+                ui_util::copy_button(ui, egui::Button::new("Copy"), || self.deposit_invoice.clone());
+            }
+
+            ui.separator();
+            ui.label("Withdraw");
+            ui.horizontal(|ui| {
+                account_picker(ui, "withdraw_account", &self.data.balances, &mut self.withdraw_account);
+                ui.selectable_value(&mut self.withdraw_onchain, false, "Lightning");
+                ui.selectable_value(&mut self.withdraw_onchain, true, "On-chain");
+            });
+            ui.horizontal(|ui| {
+                ui.label(if self.withdraw_onchain { "Address:" } else { "Invoice:" });
+                ui.text_edit_singleline(&mut self.withdraw_destination);
+            });
+            ui.horizontal(|ui| {
+                ui.label(if self.withdraw_onchain { "Amount (sats):" } else { "Amount (sats, if the invoice has none):" });
+                ui.text_edit_singleline(&mut self.withdraw_amount);
+                if ui.button("Request").clicked() {
+                    status = Some(self.request_withdrawal(worker));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Auto-approve up to (sats):");
+                ui.text_edit_singleline(&mut self.auto_approve_input);
+                if ui.button("Save").clicked() {
+                    status = Some(match self.auto_approve_input.trim().parse::<u64>() {
+                        Ok(sats) => {
+                            self.data.auto_approve_sats = sats;
+                            self.save();
+                            format!("Withdrawals up to {} are approved automatically", display::sats(sats))
+                        }
+                        Err(_) => "Invalid auto-approve threshold".to_string(),
+                    });
+                }
+            });
+
+            if let Some(queue_status) = self.show_queue(ui, worker) {
+                status = Some(queue_status);
+            }
+        });
+        status
+    }
+
+    /// Requested withdrawals with approve and reject buttons, then the resolved ones
+    fn show_queue(&mut self, ui: &mut egui::Ui, worker: &mut Worker) -> Option<String> {
+        ui.separator();
+        ui.label("Withdrawal queue");
+        if self.data.withdrawal_queue.is_empty() {
+            ui.label("No withdrawals requested yet.");
+            return None;
+        }
+        ui.horizontal(|ui| {
+            ui.label("Operator note:");
+            ui.add(egui::TextEdit::singleline(&mut self.operator_note).hint_text("recorded with the next decision"));
+        });
+        let mut decision = None;
+        egui::Grid::new("withdrawal_queue").striped(true).num_columns(6).show(ui, |ui| {
+            for header in ["#", "Account", "Amount", "Requested", "Status", ""] {
+                ui.strong(header);
+            }
+            ui.end_row();
+            for withdrawal in self.data.withdrawal_queue.iter().rev().take(MAX_QUEUE_ROWS) {
+                ui.label(withdrawal.id.to_string());
+                ui.label(&withdrawal.account);
+                let kind = match withdrawal.destination {
+                    Destination::Lightning { .. } => "Lightning",
+                    Destination::Onchain { .. } => "on-chain",
+                };
+                ui.label(format!("{} {}", display::sats(withdrawal.amount_msats / 1000), kind));
+                ui.label(format_time(withdrawal.requested_at));
+                let (color, text) = match &withdrawal.status {
+                    WithdrawalStatus::Requested => (egui::Color32::YELLOW, "Awaiting approval".to_string()),
+                    WithdrawalStatus::Sending => (egui::Color32::LIGHT_BLUE, "Sending".to_string()),
+                    WithdrawalStatus::InFlight { .. } => (egui::Color32::LIGHT_BLUE, "In flight".to_string()),
+                    WithdrawalStatus::Completed { .. } => (egui::Color32::GREEN, "Completed".to_string()),
+                    WithdrawalStatus::Failed { reason } => (egui::Color32::RED, format!("Failed: {}", reason)),
+                    WithdrawalStatus::Rejected => (egui::Color32::GRAY, "Rejected".to_string()),
+                };
+                let mut details = match &withdrawal.destination {
+                    Destination::Lightning { invoice } => invoice.clone(),
+                    Destination::Onchain { address } => address.clone(),
+                };
+                if let Some(approved_at) = withdrawal.approved_at {
+                    details.push_str(&format!("\nDecided {}", format_time(approved_at)));
+                }
+                if let WithdrawalStatus::Completed { reference } = &withdrawal.status {
+                    details.push_str(&format!("\nReference {}", reference));
+                }
+                if !withdrawal.note.is_empty() {
+                    details.push_str(&format!("\nNote: {}", withdrawal.note));
+                }
+                ui.colored_label(color, text).on_hover_text(details);
+                if withdrawal.status == WithdrawalStatus::Requested {
+                    ui.horizontal(|ui| {
+                        if ui.small_button("Approve").clicked() {
+                            decision = Some((withdrawal.id, true));
+                        }
+                        if ui.small_button("Reject").clicked() {
+                            decision = Some((withdrawal.id, false));
+                        }
+                    });
+                } else {
+                    ui.label("");
+                }
+                ui.end_row();
+            }
+        });
+        let (id, approve) = decision?;
+        let note = std::mem::take(&mut self.operator_note);
+        Some(if approve { self.approve(id, note, worker) } else { self.reject(id, note) })
+    }
+}
+
+fn parse_address(address: &str, network: Network) -> Result<Address, String> {
+    Address::from_str(address.trim())
+        .map_err(|_| "Invalid address".to_string())?
+        .require_network(network)
+        .map_err(|_| "Invalid address for this network".to_string())
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+/// A unix timestamp as "YYYY-MM-DD HH:MM" UTC
+fn format_time(timestamp: i64) -> String {
+    let seconds = timestamp.rem_euclid(86_400);
+    format!("{} {:02}:{:02}", history::format_date(timestamp), seconds / 3600, seconds % 3600 / 60)
+}
+
+fn account_picker(ui: &mut egui::Ui, id: &str, balances: &BTreeMap<String, u64>, selected: &mut String) {
+    egui::ComboBox::from_id_salt(id)
+        .selected_text(if selected.is_empty() { "Account" } else { selected.as_str() })
+        .show_ui(ui, |ui| {
+            for name in balances.keys() {
+                ui.selectable_value(selected, name.clone(), name);
+            }
+        });
+}
+
+fn msats_to_usd(msats: u64, btc_price: f64) -> f64 {
+    msats as f64 / 100_000_000_000.0 * btc_price
+}
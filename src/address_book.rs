@@ -0,0 +1,256 @@
+use crate::encryption;
+use ldk_node::bitcoin::secp256k1::PublicKey;
+use ldk_node::lightning::ln::msgs::SocketAddress;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tracing::{error, info};
+
+const ADDRESS_BOOK_FILE_NAME: &str = "address_book.json";
+
+/// Written by "Export", in plain JSON so it can be copied to the other machine
+const EXPORT_FILE_NAME: &str = "address_book_export.json";
+
+/// A labelled node the operator deals with regularly
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Contact {
+    pub label: String,
+    /// Hex pubkey
+    pub node_id: String,
+    /// `host:port`, if the node is reachable
+    pub address: Option<String>,
+}
+
+/// Labelled node ids persisted to the data dir, offered next to node id inputs
+pub struct AddressBook {
+    path: PathBuf,
+    contacts: Vec<Contact>,
+}
+
+impl AddressBook {
+    pub fn load(data_dir: &Path) -> Self {
+        let path = data_dir.join(ADDRESS_BOOK_FILE_NAME);
+        let contacts = if path.exists() {
+            match encryption::read_state_file(&path).map(|s| serde_json::from_str(&s)) {
+                Ok(Ok(contacts)) => contacts,
+                Ok(Err(e)) => {
+                    error!("Ignoring unreadable {}: {}", path.display(), e);
+                    Vec::new()
+                }
+                Err(e) => {
+                    error!("Failed to read {}: {}", path.display(), e);
+                    Vec::new()
+                }
+            }
+        } else {
+            Vec::new()
+        };
+        Self { path, contacts }
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string_pretty(&self.contacts)
+            .map_err(|e| e.to_string())
+            .and_then(|json| encryption::write_state_file(&self.path, &json).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            error!("Failed to save {}: {}", self.path.display(), e);
+        }
+    }
+
+    pub fn contacts(&self) -> &[Contact] {
+        &self.contacts
+    }
+
+    pub fn label_for(&self, node_id: &PublicKey) -> Option<&str> {
+        let node_id = node_id.to_string();
+        self.contacts.iter().find(|c| c.node_id == node_id).map(|c| c.label.as_str())
+    }
+
+    /// Adds the contact, replacing any existing entry for the same node
+    pub fn upsert(&mut self, contact: Contact) {
+        self.insert(contact);
+        self.save();
+    }
+
+    fn insert(&mut self, contact: Contact) {
+        match self.contacts.iter_mut().find(|c| c.node_id == contact.node_id) {
+            Some(existing) => *existing = contact,
+            None => self.contacts.push(contact),
+        }
+        self.contacts.sort_by_key(|c| c.label.to_lowercase());
+    }
+
+    pub fn remove(&mut self, node_id: &str) {
+        self.contacts.retain(|c| c.node_id != node_id);
+        self.save();
+    }
+
+    /// Writes the contacts to `address_book_export.json` in the data dir
+    pub fn export(&self) -> Result<PathBuf, String> {
+        let json = serde_json::to_string_pretty(&self.contacts).map_err(|e| e.to_string())?;
+        let path = self.path.with_file_name(EXPORT_FILE_NAME);
+        std::fs::write(&path, json).map_err(|e| e.to_string())?;
+        info!("Exported {} contacts to {}", self.contacts.len(), path.display());
+        Ok(path)
+    }
+
+    /// Merges contacts from an exported file, returning how many were read. Imported
+    /// entries replace local ones for the same node.
+    pub fn import(&mut self, path: &Path) -> Result<usize, String> {
+        let json = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let imported: Vec<Contact> = serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if let Some(bad) = imported.iter().find(|c| PublicKey::from_str(&c.node_id).is_err()) {
+            return Err(format!("Invalid node ID for {}", bad.label));
+        }
+        let count = imported.len();
+        for contact in imported {
+            self.insert(contact);
+        }
+        self.save();
+        info!("Imported {} contacts from {}", count, path.display());
+        Ok(count)
+    }
+
+    /// Dropdown of contacts that fills `node_id`, and `address` when given and known
+    pub fn picker(&self, ui: &mut egui::Ui, id_salt: &str, node_id: &mut String, address: Option<&mut String>) {
+        if self.contacts.is_empty() {
+            return;
+        }
+        let mut picked = None;
+        egui::ComboBox::from_id_salt(id_salt).selected_text("Address book").show_ui(ui, |ui| {
+            for contact in &self.contacts {
+                if ui.selectable_label(false, &contact.label).on_hover_text(&contact.node_id).clicked() {
+                    picked = Some(contact);
+                }
+            }
+        });
+        if let Some(contact) = picked {
+            *node_id = contact.node_id.clone();
+            if let (Some(address), Some(known)) = (address, &contact.address) {
+                *address = known.clone();
+            }
+        }
+    }
+
+    /// Like `picker`, filling a single `pubkey@host:port` field
+    pub fn peer_picker(&self, ui: &mut egui::Ui, id_salt: &str, peer: &mut String) {
+        let mut node_id = String::new();
+        let mut address = String::new();
+        self.picker(ui, id_salt, &mut node_id, Some(&mut address));
+        if !node_id.is_empty() {
+            *peer = if address.is_empty() { node_id } else { format!("{}@{}", node_id, address) };
+        }
+    }
+}
+
+/// "Address book" panel: the contacts with forms to add, remove, import and export them
+#[derive(Default)]
+pub struct AddressBookPanel {
+    label: String,
+    node_id: String,
+    address: String,
+    import_path: String,
+    status: String,
+}
+
+impl AddressBookPanel {
+    pub fn show(&mut self, ui: &mut egui::Ui, book: &mut AddressBook) {
+        egui::CollapsingHeader::new("Address book").show(ui, |ui| {
+            let mut remove = None;
+            if book.contacts().is_empty() {
+                ui.label("No contacts yet.");
+            } else {
+                egui::Grid::new("address_book").striped(true).num_columns(4).show(ui, |ui| {
+                    for header in ["Label", "Node", "Address", ""] {
+                        ui.strong(header);
+                    }
+                    ui.end_row();
+                    for contact in book.contacts() {
+                        let node_id = &contact.node_id;
+                        ui.label(&contact.label);
+                        ui.label(&node_id[..16.min(node_id.len())]).on_hover_text(node_id);
+                        ui.label(contact.address.as_deref().unwrap_or("-"));
+                        ui.horizontal(|ui| {
+                            if ui.small_button("Edit").clicked() {
+                                self.label = contact.label.clone();
+                                self.node_id = node_id.clone();
+                                self.address = contact.address.clone().unwrap_or_default();
+                            }
+                            if ui.small_button("Remove").clicked() {
+                                remove = Some(contact.node_id.clone());
+                            }
+                        });
+                        ui.end_row();
+                    }
+                });
+            }
+            if let Some(node_id) = remove {
+                book.remove(&node_id);
+            }
+
+            ui.add_space(5.0);
+            ui.horizontal(|ui| {
+                ui.label("Label:");
+                ui.text_edit_singleline(&mut self.label);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Node ID:");
+                ui.text_edit_singleline(&mut self.node_id);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Address (optional):");
+                ui.add(egui::TextEdit::singleline(&mut self.address).hint_text("host:port"));
+            });
+            if ui.button("Save contact").clicked() {
+                self.status = match self.contact() {
+                    Ok(contact) => {
+                        let label = contact.label.clone();
+                        book.upsert(contact);
+                        self.label.clear();
+                        self.node_id.clear();
+                        self.address.clear();
+                        format!("Saved {}", label)
+                    }
+                    Err(e) => e,
+                };
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Export").clicked() {
+                    self.status = match book.export() {
+                        Ok(path) => format!("Saved to {}", path.display()),
+                        Err(e) => format!("Export failed: {}", e),
+                    };
+                }
+                ui.add(egui::TextEdit::singleline(&mut self.import_path).hint_text("path to exported JSON"));
+                if ui.button("Import").clicked() {
+                    self.status = match book.import(Path::new(self.import_path.trim())) {
+                        Ok(count) => format!("Imported {} contacts", count),
+                        Err(e) => format!("Import failed: {}", e),
+                    };
+                }
+            });
+            if !self.status.is_empty() {
+                ui.label(&self.status);
+            }
+        });
+    }
+
+    fn contact(&self) -> Result<Contact, String> {
+        let label = self.label.trim();
+        if label.is_empty() {
+            return Err("Enter a label".to_string());
+        }
+        let node_id = PublicKey::from_str(self.node_id.trim()).map_err(|_| "Invalid node ID".to_string())?;
+        let address = self.address.trim();
+        if !address.is_empty() && SocketAddress::from_str(address).is_err() {
+            return Err("Invalid address; expected host:port".to_string());
+        }
+        Ok(Contact {
+            label: label.to_string(),
+            node_id: node_id.to_string(),
+            address: (!address.is_empty()).then(|| address.to_string()),
+        })
+    }
+}
@@ -0,0 +1,314 @@
+use crate::node_ui::NodeBalances;
+use crate::price_feeds::get_cached_price;
+use crate::stable::StableChannelSettings;
+use crate::shutdown;
+use crate::worker::{self, AppCommand, AppResult};
+use ldk_node::{Node, UserChannelId};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::Read;
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing::{error, info, warn};
+
+/// Bearer token clients must send; the admin API refuses to start without one
+pub const ADMIN_TOKEN_ENV: &str = "STABLE_CHANNELS_ADMIN_TOKEN";
+
+/// How long a request waits for the app to answer before failing
+const APP_REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest request body accepted
+const MAX_BODY_BYTES: u64 = 64 * 1024;
+
+/// The value of `--admin-listen <addr>` or `--admin-listen=<addr>`, if given
+pub fn listen_addr() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--admin-listen" {
+            return args.next();
+        }
+        if let Some(addr) = arg.strip_prefix("--admin-listen=") {
+            return Some(addr.to_string());
+        }
+    }
+    None
+}
+
+/// Requests that need the app's stable channel state, answered from the UI thread
+pub enum AdminRequest {
+    ListStableChannels,
+    /// Summed stable liability, for monitoring
+    Exposure,
+    /// Forwarded volume and routing fees earned
+    Routing,
+    Designate { target: String, expected_usd: f64, settings: StableChannelSettings },
+    Remove { channel_id: String, settle_first: bool },
+    /// Cooperatively closes a channel, as the channel table's Close button does
+    CloseChannel { channel_id: String },
+    /// A channel the console opened, so it isn't mistaken for a JIT channel
+    ManualChannel(UserChannelId),
+}
+
+/// HTTP status and JSON body
+pub type AdminReply = (u16, Value);
+
+pub struct PendingAdminRequest {
+    pub request: AdminRequest,
+    reply: mpsc::Sender<AdminReply>,
+}
+
+impl PendingAdminRequest {
+    pub fn reply(self, status: u16, body: Value) {
+        // The HTTP thread may have timed out and gone; nothing to do then
+        let _ = self.reply.send((status, body));
+    }
+}
+
+#[derive(Deserialize)]
+struct DesignateBody {
+    /// Channel id, or a counterparty pubkey to follow that peer's largest channel
+    channel: String,
+    target_usd: f64,
+    #[serde(flatten)]
+    settings: StableChannelSettings,
+}
+
+#[derive(Deserialize)]
+struct SignBody {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct InvoiceBody {
+    amount_sats: u64,
+    #[serde(default)]
+    description: Option<String>,
+}
+
+/// JSON admin API on a background thread. Node-only endpoints are served directly;
+/// stable channel endpoints are forwarded to the app through `try_recv`.
+pub struct AdminServer {
+    server: Arc<Server>,
+    requests: mpsc::Receiver<PendingAdminRequest>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl AdminServer {
+    pub fn start(addr: &str, token: String, node: Arc<Node>) -> Result<Self, String> {
+        let server = Arc::new(Server::http(addr).map_err(|e| e.to_string())?);
+        let (request_tx, request_rx) = mpsc::channel();
+
+        let thread_server = Arc::clone(&server);
+        let handle = std::thread::spawn(move || {
+            // Ends once `stop` unblocks the server
+            for request in thread_server.incoming_requests() {
+                handle_request(request, &token, &node, &request_tx);
+            }
+        });
+
+        info!("Admin API listening on {}", addr);
+        Ok(Self { server, requests: request_rx, handle: Some(handle) })
+    }
+
+    /// The next request waiting for the app, if any, without blocking
+    pub fn try_recv(&self) -> Option<PendingAdminRequest> {
+        self.requests.try_recv().ok()
+    }
+
+    pub fn stop(&mut self) {
+        self.server.unblock();
+        if let Some(handle) = self.handle.take() {
+            shutdown::join_with_timeout(handle, shutdown::THREAD_JOIN_TIMEOUT);
+        }
+        info!("Admin API stopped");
+    }
+}
+
+fn handle_request(mut request: Request, token: &str, node: &Node, app: &mpsc::Sender<PendingAdminRequest>) {
+    // A browser asks before sending the Authorization header to another origin
+    if *request.method() == Method::Options {
+        let response = with_cors(Response::empty(204))
+            .with_header(Header::from_bytes("Access-Control-Allow-Methods", "GET, POST, DELETE").unwrap())
+            .with_header(Header::from_bytes("Access-Control-Allow-Headers", "Authorization, Content-Type").unwrap());
+        if let Err(e) = request.respond(response) {
+            error!("Failed to answer admin preflight: {}", e);
+        }
+        return;
+    }
+
+    let (status, body) = if !authorized(&request, token) {
+        warn!("Rejected unauthorized admin request: {} {}", request.method(), request.url());
+        (401, json!({ "error": "unauthorized" }))
+    } else {
+        let mut body = String::new();
+        let read = request.as_reader().take(MAX_BODY_BYTES).read_to_string(&mut body);
+        match read {
+            Ok(_) => route(request.method(), request.url(), &body, node, app),
+            Err(e) => (400, json!({ "error": format!("unreadable body: {}", e) })),
+        }
+    };
+
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", "application/json").unwrap());
+    if let Err(e) = request.respond(with_cors(response)) {
+        error!("Failed to answer admin request: {}", e);
+    }
+}
+
+/// Lets the browser build of the user app call the API from wherever it is served. Every
+/// request still needs the bearer token, and no cookies are involved, so any origin will do.
+fn with_cors<R: Read>(response: Response<R>) -> Response<R> {
+    response.with_header(Header::from_bytes("Access-Control-Allow-Origin", "*").unwrap())
+}
+
+fn authorized(request: &Request, token: &str) -> bool {
+    let expected = format!("Bearer {}", token);
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Authorization"))
+        .map_or(false, |h| constant_time_eq(h.value.as_str().as_bytes(), expected.as_bytes()))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn route(method: &Method, url: &str, body: &str, node: &Node, app: &mpsc::Sender<PendingAdminRequest>) -> AdminReply {
+    let path = url.split('?').next().unwrap_or(url).trim_end_matches('/');
+    match (method, path) {
+        (Method::Get, "/balances") => (200, balances_json(node)),
+        (Method::Get, "/channels") => (200, channels_json(node)),
+        (Method::Post, "/invoice") => match serde_json::from_str::<InvoiceBody>(body) {
+            Ok(invoice) => {
+                let command = AppCommand::GenerateInvoice {
+                    amount_msats: invoice.amount_sats * 1000,
+                    description: invoice.description.unwrap_or_else(|| "Invoice".to_string()),
+                };
+                match worker::execute(node, command) {
+                    AppResult::InvoiceGenerated(Ok(invoice)) => (200, json!({ "invoice": invoice.to_string() })),
+                    AppResult::InvoiceGenerated(Err(e)) => (500, json!({ "error": e.to_string() })),
+                    _ => (500, json!({ "error": "unexpected result" })),
+                }
+            }
+            Err(e) => (400, json!({ "error": e.to_string() })),
+        },
+        (Method::Post, "/sign") => match serde_json::from_str::<SignBody>(body) {
+            Ok(body) => (200, sign_json(node, &body.message)),
+            Err(e) => (400, json!({ "error": e.to_string() })),
+        },
+        (Method::Get, "/stable-channels") => forward(app, AdminRequest::ListStableChannels),
+        (Method::Get, "/exposure") => forward(app, AdminRequest::Exposure),
+        (Method::Get, "/routing") => forward(app, AdminRequest::Routing),
+        (Method::Post, "/stable-channels") => match serde_json::from_str::<DesignateBody>(body) {
+            Ok(body) if body.target_usd > 0.0 => forward(
+                app,
+                AdminRequest::Designate { target: body.channel, expected_usd: body.target_usd, settings: body.settings },
+            ),
+            Ok(_) => (400, json!({ "error": "target_usd must be positive" })),
+            Err(e) => (400, json!({ "error": e.to_string() })),
+        },
+        (Method::Delete, path) if path.starts_with("/stable-channels/") => {
+            let channel_id = path.trim_start_matches("/stable-channels/").to_string();
+            let settle_first = !url.contains("settle=false");
+            forward(app, AdminRequest::Remove { channel_id, settle_first })
+        }
+        _ => (404, json!({ "error": "not found" })),
+    }
+}
+
+pub fn balances_json(node: &Node) -> Value {
+    let balances = NodeBalances::new(&node.list_balances(), &node.list_channels(), get_cached_price());
+    json!({
+        "lightning_btc": balances.lightning_btc,
+        "onchain_btc": balances.onchain_btc,
+        "total_btc": balances.total_btc,
+        "lightning_usd": balances.lightning_usd,
+        "onchain_usd": balances.onchain_usd,
+        "total_usd": balances.total_usd,
+        "spendable_sats": balances.spendable_sats,
+        "pending_sats": balances.pending_sats,
+        "reserved_sats": balances.reserved_sats,
+    })
+}
+
+pub fn channels_json(node: &Node) -> Value {
+    let channels: Vec<Value> = node
+        .list_channels()
+        .iter()
+        .map(|c| json!({
+            "channel_id": c.channel_id.to_string(),
+            "counterparty": c.counterparty_node_id.to_string(),
+            "value_sats": c.channel_value_sats,
+            "outbound_capacity_msat": c.outbound_capacity_msat,
+            "inbound_capacity_msat": c.inbound_capacity_msat,
+            "is_channel_ready": c.is_channel_ready,
+            "is_usable": c.is_usable,
+        }))
+        .collect();
+    Value::Array(channels)
+}
+
+/// zbase32 signature of `message` by the node key, as lnd and Core Lightning make them
+pub fn sign_json(node: &Node, message: &str) -> Value {
+    json!({
+        "signature": node.sign_message(message.as_bytes()),
+        "node_id": node.node_id().to_string(),
+    })
+}
+
+/// Hands a request to the app and waits for its answer
+pub fn forward(app: &mpsc::Sender<PendingAdminRequest>, request: AdminRequest) -> AdminReply {
+    let (reply_tx, reply_rx) = mpsc::channel();
+    if app.send(PendingAdminRequest { request, reply: reply_tx }).is_err() {
+        return (503, json!({ "error": "app is shutting down" }));
+    }
+    reply_rx
+        .recv_timeout(APP_REPLY_TIMEOUT)
+        .unwrap_or_else(|_| (504, json!({ "error": "app did not answer in time" })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ldk_node::bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use ldk_node::bitcoin::Network;
+    use ldk_node::Builder;
+
+    fn test_node(name: &str) -> Node {
+        let dir = std::env::temp_dir().join(format!("stable-channels-sign-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut builder = Builder::new();
+        builder.set_network(Network::Regtest);
+        builder.set_chain_source_esplora("http://127.0.0.1:1".to_string(), None);
+        builder.set_storage_dir_path(dir.display().to_string());
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn signed_message_verifies_against_the_node_id() {
+        let node = test_node("round-trip");
+        let (app, _requests) = mpsc::channel();
+        let (status, reply) = route(&Method::Post, "/sign", r#"{"message":"stable channels"}"#, &node, &app);
+        assert_eq!(status, 200);
+        assert_eq!(reply["node_id"], node.node_id().to_string());
+
+        let signature = reply["signature"].as_str().unwrap();
+        assert!(node.verify_signature(b"stable channels", signature, &node.node_id()));
+        assert!(!node.verify_signature(b"stable channel", signature, &node.node_id()));
+        let stranger = PublicKey::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&[1; 32]).unwrap());
+        assert!(!node.verify_signature(b"stable channels", signature, &stranger));
+    }
+
+    #[test]
+    fn sign_without_a_message_is_a_bad_request() {
+        let node = test_node("bad-request");
+        let (app, _requests) = mpsc::channel();
+        let (status, reply) = route(&Method::Post, "/sign", "{}", &node, &app);
+        assert_eq!(status, 400);
+        assert!(reply["error"].is_string());
+    }
+}
@@ -0,0 +1,336 @@
+use crate::history;
+use crate::price_feeds::PriceStats;
+use crate::stable::{self, StabilityAction};
+use crate::types::StableChannel;
+use ldk_node::lightning::ln::channelmanager::PaymentId;
+use rusqlite::{params, Connection};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{error, info, warn};
+
+const AUDIT_DB_FILE_NAME: &str = "stability_audit.sqlite";
+const AUDIT_CSV_FILE_NAME: &str = "stability_audit.csv";
+
+/// Days of stability checks kept in the audit log; 0 keeps everything
+pub const AUDIT_RETENTION_DAYS_ENV: &str = "STABLE_CHANNELS_AUDIT_RETENTION_DAYS";
+const DEFAULT_RETENTION_DAYS: u64 = 90;
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Rows shown in the query screen; the CSV export has every match
+const QUERY_LIMIT: usize = 500;
+
+/// One stability check as recorded
+#[derive(Debug, Clone)]
+pub struct AuditRow {
+    pub timestamp: i64,
+    pub channel_id: String,
+    pub price: f64,
+    pub price_sources: usize,
+    pub receiver_sats: u64,
+    pub provider_sats: u64,
+    pub deviation_usd: f64,
+    pub action: String,
+    pub payment_id: Option<String>,
+    pub amount_msats: Option<u64>,
+    /// What the check did, then how its payment ended once it resolves
+    pub result: String,
+}
+
+/// SQLite log of every stability check, for working out after the fact why a payment was
+/// or wasn't made
+pub struct AuditLog {
+    conn: Connection,
+    retention_days: u64,
+    last_prune: Option<Instant>,
+}
+
+impl AuditLog {
+    pub fn open(data_dir: &Path) -> Result<Self, rusqlite::Error> {
+        let conn = Connection::open(data_dir.join(AUDIT_DB_FILE_NAME))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS stability_checks (
+                id INTEGER PRIMARY KEY,
+                timestamp INTEGER NOT NULL,
+                channel_id TEXT NOT NULL,
+                price REAL NOT NULL,
+                price_sources INTEGER NOT NULL,
+                receiver_sats INTEGER NOT NULL,
+                provider_sats INTEGER NOT NULL,
+                deviation_usd REAL NOT NULL,
+                action TEXT NOT NULL,
+                payment_id TEXT,
+                amount_msats INTEGER,
+                result TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS stability_checks_channel_time
+                ON stability_checks (channel_id, timestamp);
+            CREATE INDEX IF NOT EXISTS stability_checks_payment ON stability_checks (payment_id);",
+        )?;
+        let retention_days = match std::env::var(AUDIT_RETENTION_DAYS_ENV) {
+            Ok(days) => days.trim().parse().unwrap_or_else(|_| {
+                warn!("Invalid {} {:?}; keeping {} days", AUDIT_RETENTION_DAYS_ENV, days, DEFAULT_RETENTION_DAYS);
+                DEFAULT_RETENTION_DAYS
+            }),
+            Err(_) => DEFAULT_RETENTION_DAYS,
+        };
+        let mut log = Self { conn, retention_days, last_prune: None };
+        log.prune_if_due();
+        Ok(log)
+    }
+
+    /// Records the outcome of a check on `sc`. Balances are those the check read, i.e.
+    /// before any payment it started.
+    pub fn record_check(&mut self, sc: &StableChannel, price: f64, stats: &PriceStats, action: &StabilityAction) {
+        let (payment_id, amount_msats) = match action {
+            StabilityAction::Paid { amount_msats, payment_id } => (Some(payment_id_hex(payment_id)), Some(*amount_msats)),
+            StabilityAction::PaymentPending(payment_id) => (Some(payment_id_hex(payment_id)), None),
+            StabilityAction::NeedsApproval { amount_msats } => (None, Some(*amount_msats)),
+            _ => (None, None),
+        };
+        let result = match action {
+            StabilityAction::Paid { .. } => format!("pending (attempt {})", sc.payment_attempt + 1),
+            other => other.to_string(),
+        };
+        let row = AuditRow {
+            timestamp: unix_now(),
+            channel_id: sc.channel_id.to_string(),
+            price,
+            price_sources: stats.sources,
+            receiver_sats: sc.stable_receiver_btc.to_msats() / 1000,
+            provider_sats: sc.stable_provider_btc.to_msats() / 1000,
+            deviation_usd: stable::dollars_from_par(sc).to_f64(),
+            action: action_name(action).to_string(),
+            payment_id,
+            amount_msats,
+            result,
+        };
+        if let Err(e) = self.insert(&row) {
+            error!("Failed to record stability check: {}", e);
+        }
+        self.prune_if_due();
+    }
+
+    /// Fills in how a stability payment started by a check ended
+    pub fn record_payment_result(&mut self, payment_id: &PaymentId, result: &str) {
+        let updated = self.conn.execute(
+            "UPDATE stability_checks SET result = ?1 WHERE payment_id = ?2 AND action = 'paid'",
+            params![result, payment_id_hex(payment_id)],
+        );
+        if let Err(e) = updated {
+            error!("Failed to record stability payment result: {}", e);
+        }
+    }
+
+    fn insert(&self, row: &AuditRow) -> Result<(), rusqlite::Error> {
+        self.conn.execute(
+            "INSERT INTO stability_checks (timestamp, channel_id, price, price_sources, receiver_sats,
+                provider_sats, deviation_usd, action, payment_id, amount_msats, result)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                row.timestamp,
+                row.channel_id,
+                row.price,
+                row.price_sources as i64,
+                row.receiver_sats as i64,
+                row.provider_sats as i64,
+                row.deviation_usd,
+                row.action,
+                row.payment_id,
+                row.amount_msats.map(|m| m as i64),
+                row.result,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn prune_if_due(&mut self) {
+        if self.retention_days == 0 || self.last_prune.is_some_and(|t| t.elapsed() < PRUNE_INTERVAL) {
+            return;
+        }
+        self.last_prune = Some(Instant::now());
+        let cutoff = unix_now() - (self.retention_days * 24 * 60 * 60) as i64;
+        match self.conn.execute("DELETE FROM stability_checks WHERE timestamp < ?1", params![cutoff]) {
+            Ok(0) => {}
+            Ok(deleted) => info!("Pruned {} stability checks older than {} days", deleted, self.retention_days),
+            Err(e) => error!("Failed to prune stability audit log: {}", e),
+        }
+    }
+
+    /// Checks matching the filters, newest first. `from` and `to` are unix timestamps;
+    /// `to` is exclusive.
+    pub fn query(
+        &self,
+        channel_id: Option<&str>,
+        from: Option<i64>,
+        to: Option<i64>,
+        limit: Option<usize>,
+    ) -> Result<Vec<AuditRow>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT timestamp, channel_id, price, price_sources, receiver_sats, provider_sats,
+                    deviation_usd, action, payment_id, amount_msats, result
+             FROM stability_checks
+             WHERE (?1 IS NULL OR channel_id = ?1) AND (?2 IS NULL OR timestamp >= ?2)
+                   AND (?3 IS NULL OR timestamp < ?3)
+             ORDER BY timestamp DESC
+             LIMIT ?4",
+        )?;
+        let limit = limit.map_or(-1, |l| l as i64);
+        let rows = stmt.query_map(params![channel_id, from, to, limit], |row| {
+            Ok(AuditRow {
+                timestamp: row.get(0)?,
+                channel_id: row.get(1)?,
+                price: row.get(2)?,
+                price_sources: row.get::<_, i64>(3)? as usize,
+                receiver_sats: row.get::<_, i64>(4)? as u64,
+                provider_sats: row.get::<_, i64>(5)? as u64,
+                deviation_usd: row.get(6)?,
+                action: row.get(7)?,
+                payment_id: row.get(8)?,
+                amount_msats: row.get::<_, Option<i64>>(9)?.map(|m| m as u64),
+                result: row.get(10)?,
+            })
+        })?;
+        rows.collect()
+    }
+}
+
+/// Query screen over the audit log, with CSV export of the matching checks
+#[derive(Default)]
+pub struct AuditPanel {
+    channel_filter: String,
+    /// Dates as YYYY-MM-DD; `to` is inclusive
+    from_date: String,
+    to_date: String,
+    rows: Vec<AuditRow>,
+    status: String,
+}
+
+impl AuditPanel {
+    pub fn show(&mut self, ui: &mut egui::Ui, log: Option<&AuditLog>, data_dir: &Path) {
+        egui::CollapsingHeader::new("Stability audit log").show(ui, |ui| {
+            let Some(log) = log else {
+                ui.label("The audit log couldn't be opened; see the logs.");
+                return;
+            };
+            ui.horizontal(|ui| {
+                ui.label("Channel:");
+                ui.add(egui::TextEdit::singleline(&mut self.channel_filter).hint_text("all").desired_width(200.0));
+                ui.label("From:");
+                ui.add(egui::TextEdit::singleline(&mut self.from_date).hint_text("YYYY-MM-DD").desired_width(90.0));
+                ui.label("To:");
+                ui.add(egui::TextEdit::singleline(&mut self.to_date).hint_text("YYYY-MM-DD").desired_width(90.0));
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Search").clicked() {
+                    self.status = match self.search(log, Some(QUERY_LIMIT)) {
+                        Ok(rows) => {
+                            let status = format!("{} checks{}", rows.len(), if rows.len() == QUERY_LIMIT { " (newest shown)" } else { "" });
+                            self.rows = rows;
+                            status
+                        }
+                        Err(e) => e,
+                    };
+                }
+                if ui.button("Export CSV").clicked() {
+                    self.status = match self.search(log, None).and_then(|rows| export_csv(data_dir, &rows)) {
+                        Ok(path) => format!("Saved to {}", path.display()),
+                        Err(e) => format!("Export failed: {}", e),
+                    };
+                }
+            });
+            if !self.status.is_empty() {
+                ui.label(&self.status);
+            }
+
+            egui::ScrollArea::vertical().max_height(300.0).id_salt("audit_rows").show(ui, |ui| {
+                egui::Grid::new("audit_rows").striped(true).num_columns(7).show(ui, |ui| {
+                    for header in ["Time", "Channel", "Price", "Receiver / provider", "Off par", "Action", "Result"] {
+                        ui.strong(header);
+                    }
+                    ui.end_row();
+                    for row in &self.rows {
+                        ui.label(history::format_timestamp(row.timestamp as u64));
+                        ui.label(&row.channel_id[..row.channel_id.len().min(12)]);
+                        ui.label(format!("${:.2} ({} feeds)", row.price, row.price_sources));
+                        ui.label(format!("{} / {} sats", row.receiver_sats, row.provider_sats));
+                        ui.label(format!("${:+.2}", row.deviation_usd));
+                        ui.label(match row.amount_msats {
+                            Some(msats) => format!("{} ({} sats)", row.action, msats / 1000),
+                            None => row.action.clone(),
+                        });
+                        ui.label(&row.result);
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+    }
+
+    fn search(&self, log: &AuditLog, limit: Option<usize>) -> Result<Vec<AuditRow>, String> {
+        let parse = |date: &str| -> Result<Option<i64>, String> {
+            match date.trim() {
+                "" => Ok(None),
+                date => history::parse_date(date).map(Some).ok_or_else(|| format!("Invalid date {:?}", date)),
+            }
+        };
+        let from = parse(&self.from_date)?;
+        let to = parse(&self.to_date)?.map(|midnight| midnight + 24 * 60 * 60);
+        let channel = Some(self.channel_filter.trim()).filter(|c| !c.is_empty());
+        log.query(channel, from, to, limit).map_err(|e| e.to_string())
+    }
+}
+
+fn export_csv(data_dir: &Path, rows: &[AuditRow]) -> Result<PathBuf, String> {
+    let mut csv = String::from(
+        "timestamp,channel_id,price,price_sources,receiver_sats,provider_sats,deviation_usd,action,payment_id,amount_msats,result\n",
+    );
+    for row in rows {
+        let _ = writeln!(
+            csv,
+            "{},{},{:.2},{},{},{},{:.2},{},{},{},\"{}\"",
+            row.timestamp,
+            row.channel_id,
+            row.price,
+            row.price_sources,
+            row.receiver_sats,
+            row.provider_sats,
+            row.deviation_usd,
+            row.action,
+            row.payment_id.as_deref().unwrap_or_default(),
+            row.amount_msats.map(|m| m.to_string()).unwrap_or_default(),
+            row.result.replace('"', "\"\""),
+        );
+    }
+    let path = data_dir.join(AUDIT_CSV_FILE_NAME);
+    std::fs::write(&path, csv).map_err(|e| e.to_string())?;
+    info!("Exported {} stability checks to {}", rows.len(), path.display());
+    Ok(path)
+}
+
+/// Short machine-friendly name of an action, for the action column
+fn action_name(action: &StabilityAction) -> &'static str {
+    match action {
+        StabilityAction::Stable => "stable",
+        StabilityAction::HighRisk(_) => "high_risk",
+        StabilityAction::WaitingOnCounterparty => "waiting_on_counterparty",
+        StabilityAction::Paid { .. } => "paid",
+        StabilityAction::PaymentFailed(_) => "payment_failed",
+        StabilityAction::PaymentPending(_) => "payment_pending",
+        StabilityAction::NeedsApproval { .. } => "needs_approval",
+        StabilityAction::RateLimited { .. } => "rate_limited",
+        StabilityAction::CannotRebalance => "cannot_rebalance",
+        StabilityAction::NoPrice => "no_price",
+        StabilityAction::PeerDisconnected => "peer_disconnected",
+        StabilityAction::CircuitBreakerTripped(_) => "circuit_breaker_tripped",
+        StabilityAction::Suspended { .. } => "suspended",
+    }
+}
+
+fn payment_id_hex(payment_id: &PaymentId) -> String {
+    hex::encode(payment_id.0)
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
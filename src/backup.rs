@@ -0,0 +1,275 @@
+use crate::history;
+use crate::lockfile;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{error, info, warn};
+
+/// Directory backups are written to; defaults to `<data dir>-backups` next to the data dir
+pub const BACKUP_DIR_ENV: &str = "STABLE_CHANNELS_BACKUP_DIR";
+/// Seconds between automatic backups; 0 turns them off
+pub const BACKUP_INTERVAL_ENV: &str = "STABLE_CHANNELS_BACKUP_INTERVAL_SECS";
+/// Number of automatic backups kept
+pub const BACKUP_RETENTION_ENV: &str = "STABLE_CHANNELS_BACKUP_RETENTION";
+/// Set to 1 to start from a restored backup without being asked on the terminal
+pub const ACCEPT_RESTORED_ENV: &str = "STABLE_CHANNELS_ACCEPT_RESTORED_BACKUP";
+
+const DEFAULT_BACKUP_INTERVAL_SECS: u64 = 24 * 60 * 60;
+const DEFAULT_BACKUP_RETENTION: usize = 7;
+
+const BACKUP_PREFIX: &str = "backup-";
+
+/// Written into every backup. Its presence in a data dir means the dir was restored from
+/// a backup and may hold channel state older than the peer's.
+const RESTORED_MARKER_FILE_NAME: &str = "RESTORED_FROM_BACKUP";
+
+#[derive(Debug, Clone, Default)]
+pub struct BackupStatus {
+    /// Unix time of the last backup that completed
+    pub last_success: Option<i64>,
+    pub last_path: Option<PathBuf>,
+    pub last_error: Option<String>,
+}
+
+/// Copies of the node's data dir, taken on request and on a timer. Copies run on a
+/// background thread; `status` is updated when one finishes.
+///
+/// To restore, stop the app and copy a backup directory's contents over the data dir.
+/// The next start asks for confirmation before the node connects to peers: the channel
+/// state in a backup can be older than the peer's, and broadcasting it loses the channel
+/// balance to a penalty transaction. Prefer a cooperative close right after restoring.
+pub struct Backups {
+    data_dir: PathBuf,
+    /// Destination typed in the UI
+    pub dest_dir: String,
+    interval: Option<Duration>,
+    retention: usize,
+    status: Arc<Mutex<BackupStatus>>,
+    running: Arc<AtomicBool>,
+    last_auto: Instant,
+}
+
+impl Backups {
+    pub fn from_env(data_dir: &Path) -> Self {
+        let dest_dir = std::env::var(BACKUP_DIR_ENV).map(PathBuf::from).unwrap_or_else(|_| default_dest(data_dir));
+        let interval_secs = std::env::var(BACKUP_INTERVAL_ENV)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_BACKUP_INTERVAL_SECS);
+        let retention = std::env::var(BACKUP_RETENTION_ENV)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .filter(|n| *n > 0)
+            .unwrap_or(DEFAULT_BACKUP_RETENTION);
+
+        let status = BackupStatus {
+            last_success: newest_backup(&dest_dir).map(|(ts, _)| ts),
+            last_path: newest_backup(&dest_dir).map(|(_, path)| path),
+            last_error: None,
+        };
+        Self {
+            data_dir: data_dir.to_path_buf(),
+            dest_dir: dest_dir.display().to_string(),
+            interval: (interval_secs > 0).then(|| Duration::from_secs(interval_secs)),
+            retention,
+            status: Arc::new(Mutex::new(status)),
+            running: Arc::new(AtomicBool::new(false)),
+            last_auto: Instant::now(),
+        }
+    }
+
+    pub fn status(&self) -> BackupStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Starts a backup into `dest_dir` unless one is already running
+    pub fn start(&mut self) {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let data_dir = self.data_dir.clone();
+        let dest_dir = PathBuf::from(self.dest_dir.trim());
+        let retention = self.retention;
+        let status = Arc::clone(&self.status);
+        let running = Arc::clone(&self.running);
+        std::thread::spawn(move || {
+            let result = export(&data_dir, &dest_dir).and_then(|path| {
+                prune(&dest_dir, retention)?;
+                Ok(path)
+            });
+            let mut status = status.lock().unwrap();
+            match result {
+                Ok(path) => {
+                    info!("Backed up {} to {}", data_dir.display(), path.display());
+                    status.last_success = Some(unix_now());
+                    status.last_path = Some(path);
+                    status.last_error = None;
+                }
+                Err(e) => {
+                    error!("Backup of {} failed: {}", data_dir.display(), e);
+                    status.last_error = Some(e.to_string());
+                }
+            }
+            running.store(false, Ordering::SeqCst);
+        });
+    }
+
+    /// Starts an automatic backup if the interval has passed since the last one
+    pub fn run_if_due(&mut self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        if self.last_auto.elapsed() >= interval {
+            self.last_auto = Instant::now();
+            self.start();
+        }
+    }
+
+    /// Destination field, export button and the last backup's outcome
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Backup folder:");
+            ui.text_edit_singleline(&mut self.dest_dir);
+            let running = self.is_running();
+            if ui.add_enabled(!running, egui::Button::new("Export backup")).clicked() {
+                self.start();
+            }
+            if running {
+                ui.spinner();
+            }
+        });
+
+        let status = self.status();
+        match status.last_success {
+            Some(ts) => ui.label(format!(
+                "Last backup: {} ({})",
+                history::format_timestamp(ts as u64),
+                status.last_path.map(|p| p.display().to_string()).unwrap_or_default()
+            )),
+            None => ui.colored_label(egui::Color32::YELLOW, "No backup yet"),
+        };
+        if let Some(e) = status.last_error {
+            ui.colored_label(egui::Color32::RED, format!("Last backup failed: {}", e));
+        }
+    }
+}
+
+fn default_dest(data_dir: &Path) -> PathBuf {
+    let name = data_dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    data_dir.with_file_name(format!("{}-backups", name))
+}
+
+/// Copies `data_dir` into a new timestamped directory under `dest_dir`
+pub fn export(data_dir: &Path, dest_dir: &Path) -> io::Result<PathBuf> {
+    let now = unix_now();
+    let target = dest_dir.join(format!("{}{}", BACKUP_PREFIX, now));
+    if target.starts_with(data_dir) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "backup folder is inside the data directory"));
+    }
+
+    // Written to a temporary name so a half-finished copy is never taken for a backup
+    let partial = dest_dir.join(format!(".partial-{}", now));
+    fs::create_dir_all(&partial)?;
+    copy_dir(data_dir, &partial)?;
+    fs::write(partial.join(RESTORED_MARKER_FILE_NAME), now.to_string())?;
+    fs::rename(&partial, &target)?;
+    Ok(target)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == lockfile::LOCK_FILE_NAME || name == RESTORED_MARKER_FILE_NAME {
+            continue;
+        }
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            fs::create_dir_all(to.join(&name))?;
+            copy_dir(&path, &to.join(&name))?;
+        } else {
+            fs::copy(&path, to.join(&name))?;
+        }
+    }
+    Ok(())
+}
+
+/// Backups in `dest_dir`, newest first
+fn list_backups(dest_dir: &Path) -> Vec<(i64, PathBuf)> {
+    let mut backups: Vec<(i64, PathBuf)> = fs::read_dir(dest_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let ts = name.strip_prefix(BACKUP_PREFIX)?.parse().ok()?;
+            Some((ts, entry.path()))
+        })
+        .collect();
+    backups.sort_by(|a, b| b.0.cmp(&a.0));
+    backups
+}
+
+fn newest_backup(dest_dir: &Path) -> Option<(i64, PathBuf)> {
+    list_backups(dest_dir).into_iter().next()
+}
+
+/// Deletes all but the newest `retention` backups
+fn prune(dest_dir: &Path, retention: usize) -> io::Result<()> {
+    for (_, path) in list_backups(dest_dir).into_iter().skip(retention) {
+        fs::remove_dir_all(&path)?;
+        info!("Removed old backup {}", path.display());
+    }
+    Ok(())
+}
+
+/// Call before the node starts. If `data_dir` was restored from a backup, warns that its
+/// channel state may be stale and asks to go ahead; exits if the answer is no. Without a
+/// terminal, `ACCEPT_RESTORED_ENV` must be set to start.
+pub fn confirm_restored_start(data_dir: &Path) {
+    let marker = data_dir.join(RESTORED_MARKER_FILE_NAME);
+    let Ok(taken_at) = fs::read_to_string(&marker) else {
+        return;
+    };
+    let taken_at = taken_at.trim().parse::<i64>().map(history::format_date).unwrap_or_default();
+
+    warn!(
+        "{} was restored from a backup taken {}. Its channel state may be older than the peer's; \
+         broadcasting stale state forfeits the channel balance. Close channels cooperatively \
+         and don't force-close.",
+        data_dir.display(),
+        taken_at
+    );
+
+    let accepted = if std::env::var(ACCEPT_RESTORED_ENV).is_ok_and(|v| v == "1") {
+        true
+    } else if io::stdin().is_terminal() {
+        print!("Start the node from this backup anyway? [y/N] ");
+        let _ = io::stdout().flush();
+        let mut input = String::new();
+        io::stdin().read_line(&mut input).is_ok() && input.trim().eq_ignore_ascii_case("y")
+    } else {
+        warn!("Set {}=1 to start from a restored backup without a terminal", ACCEPT_RESTORED_ENV);
+        false
+    };
+
+    if !accepted {
+        error!("Not starting from a restored backup");
+        std::process::exit(1);
+    }
+    if let Err(e) = fs::remove_file(&marker) {
+        error!("Failed to remove {}: {}", marker.display(), e);
+    }
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
@@ -0,0 +1,196 @@
+use crate::encryption;
+use crate::types::StableChannel;
+use egui_plot::{Legend, Line, Plot, PlotPoints};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use tracing::{error, info};
+
+const BALANCE_HISTORY_FILE_NAME: &str = "balance_history.json";
+const CSV_FILE_NAME: &str = "balance_history.csv";
+
+/// A week of samples at the background loop's 30-second cadence
+const MAX_SAMPLES: usize = 7 * 24 * 60 * 2;
+
+/// Samples taken between writes to disk; the rest are written on shutdown
+const SAVE_EVERY: usize = 10;
+
+/// Balances and price at one tick of the background loop
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct BalanceSample {
+    pub timestamp: i64,
+    /// The user's balance in the peg's currency
+    pub stable_usd: f64,
+    pub btc: f64,
+    pub price: f64,
+}
+
+/// Ring buffer of balance samples, persisted to the data directory
+pub struct BalanceHistory {
+    path: PathBuf,
+    samples: VecDeque<BalanceSample>,
+    unsaved: usize,
+}
+
+impl BalanceHistory {
+    pub fn load(data_dir: &Path) -> Self {
+        let path = data_dir.join(BALANCE_HISTORY_FILE_NAME);
+        let samples = if path.exists() {
+            match encryption::read_state_file(&path).map(|s| serde_json::from_str(&s)) {
+                Ok(Ok(samples)) => samples,
+                Ok(Err(e)) => {
+                    error!("Ignoring unreadable {}: {}", path.display(), e);
+                    VecDeque::new()
+                }
+                Err(e) => {
+                    error!("Failed to read {}: {}", path.display(), e);
+                    VecDeque::new()
+                }
+            }
+        } else {
+            VecDeque::new()
+        };
+        Self { path, samples, unsaved: 0 }
+    }
+
+    /// Samples our side of the channels pegged in the first priced one's currency,
+    /// dropping the oldest sample once full
+    pub fn record(&mut self, channels: &[StableChannel], timestamp: i64) {
+        let Some(first) = channels.iter().find(|sc| sc.latest_price > 0.0) else {
+            return;
+        };
+        let (mut stable_usd, mut btc) = (0.0, 0.0);
+        for sc in channels.iter().filter(|sc| sc.currency == first.currency) {
+            let (sc_usd, sc_btc) = if sc.is_stable_receiver {
+                (sc.stable_receiver_usd, sc.stable_receiver_btc)
+            } else {
+                (sc.stable_provider_usd, sc.stable_provider_btc)
+            };
+            stable_usd += sc_usd.to_f64();
+            btc += sc_btc.to_btc();
+        }
+        self.samples.push_back(BalanceSample {
+            timestamp,
+            stable_usd,
+            btc,
+            price: first.latest_price,
+        });
+        while self.samples.len() > MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.unsaved += 1;
+        if self.unsaved >= SAVE_EVERY {
+            self.save();
+        }
+    }
+
+    pub fn save(&mut self) {
+        if self.unsaved == 0 {
+            return;
+        }
+        let result = serde_json::to_string(&self.samples)
+            .map_err(|e| e.to_string())
+            .and_then(|json| encryption::write_state_file(&self.path, &json).map_err(|e| e.to_string()));
+        match result {
+            Ok(()) => self.unsaved = 0,
+            Err(e) => error!("Failed to save {}: {}", self.path.display(), e),
+        }
+    }
+
+    fn since(&self, from: i64) -> impl Iterator<Item = &BalanceSample> {
+        self.samples.iter().filter(move |s| s.timestamp >= from)
+    }
+
+    /// Writes every sample to `balance_history.csv` next to the history file
+    fn export_csv(&self) -> std::io::Result<PathBuf> {
+        let mut csv = String::from("timestamp,stable_usd,btc,price\n");
+        for sample in &self.samples {
+            let _ = writeln!(csv, "{},{:.2},{:.8},{:.2}", sample.timestamp, sample.stable_usd, sample.btc, sample.price);
+        }
+        let path = self.path.with_file_name(CSV_FILE_NAME);
+        std::fs::write(&path, csv)?;
+        info!("Exported balance history to {}", path.display());
+        Ok(path)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum ChartRange {
+    Hour,
+    #[default]
+    Day,
+    Week,
+}
+
+impl ChartRange {
+    const ALL: [ChartRange; 3] = [ChartRange::Hour, ChartRange::Day, ChartRange::Week];
+
+    fn secs(self) -> i64 {
+        match self {
+            ChartRange::Hour => 60 * 60,
+            ChartRange::Day => 24 * 60 * 60,
+            ChartRange::Week => 7 * 24 * 60 * 60,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            ChartRange::Hour => "1h",
+            ChartRange::Day => "24h",
+            ChartRange::Week => "7d",
+        }
+    }
+}
+
+/// Line chart of the stable balance against the BTC price. Both are shown as a change from
+/// the start of the range, so a flat balance through a price move is plain to see.
+#[derive(Default)]
+pub struct BalanceChart {
+    range: ChartRange,
+    export_status: String,
+}
+
+impl BalanceChart {
+    pub fn show(&mut self, ui: &mut egui::Ui, history: &BalanceHistory, now: i64) {
+        ui.horizontal(|ui| {
+            for range in ChartRange::ALL {
+                ui.selectable_value(&mut self.range, range, range.label());
+            }
+            if ui.button("Export CSV").clicked() {
+                self.export_status = match history.export_csv() {
+                    Ok(path) => format!("Saved to {}", path.display()),
+                    Err(e) => format!("Export failed: {}", e),
+                };
+            }
+        });
+        if !self.export_status.is_empty() {
+            ui.label(&self.export_status);
+        }
+
+        let samples: Vec<&BalanceSample> = history.since(now - self.range.secs()).collect();
+        let Some(first) = samples.iter().find(|s| s.stable_usd > 0.0 && s.btc > 0.0) else {
+            ui.label("No balance samples in this range yet");
+            return;
+        };
+        // Hours before now on the x axis, percent change from the first sample on the y axis
+        let series = |value: fn(&BalanceSample) -> f64| -> PlotPoints {
+            let base = value(first);
+            samples
+                .iter()
+                .map(|s| [(s.timestamp - now) as f64 / 3600.0, (value(s) / base - 1.0) * 100.0])
+                .collect()
+        };
+        Plot::new("balance_chart")
+            .legend(Legend::default())
+            .height(180.0)
+            .x_axis_label("hours")
+            .y_axis_label("% change")
+            .allow_scroll(false)
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(series(|s| s.stable_usd)).name("Stable balance"));
+                plot_ui.line(Line::new(series(|s| s.price)).name("BTC price"));
+                plot_ui.line(Line::new(series(|s| s.btc)).name("Balance in BTC"));
+            });
+    }
+}
@@ -0,0 +1,234 @@
+use crate::fees::{self, FeeEstimates};
+use ldk_node::bitcoin::blockdata::constants::genesis_block;
+use ldk_node::bitcoin::Network;
+use ldk_node::Builder;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tracing::{info, warn};
+use ureq::Agent;
+
+/// Chain backend, as `esplora:<url>`, `bitcoind:<host>:<port>` or a bare Esplora URL.
+/// `--chain-source <spec>` on the command line takes precedence.
+pub const CHAIN_SOURCE_ENV: &str = "STABLE_CHANNELS_CHAIN_SOURCE";
+/// bitcoind RPC credentials, kept out of the command line
+pub const BITCOIND_RPC_USER_ENV: &str = "STABLE_CHANNELS_BITCOIND_RPC_USER";
+pub const BITCOIND_RPC_PASSWORD_ENV: &str = "STABLE_CHANNELS_BITCOIND_RPC_PASSWORD";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where the node reads the chain from
+#[derive(Debug, Clone)]
+pub enum ChainSource {
+    Esplora { url: String },
+    BitcoindRpc { host: String, port: u16, user: String, password: String },
+}
+
+impl ChainSource {
+    /// The backend from `--chain-source` or `CHAIN_SOURCE_ENV`, else Esplora at `default_esplora_url`
+    pub fn from_args_or_env(default_esplora_url: &str) -> Result<Self, String> {
+        match cli_spec().or_else(|| std::env::var(CHAIN_SOURCE_ENV).ok().filter(|s| !s.trim().is_empty())) {
+            Some(spec) => Self::parse(spec.trim()),
+            None => Ok(Self::Esplora { url: default_esplora_url.to_string() }),
+        }
+    }
+
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        if spec.starts_with("http://") || spec.starts_with("https://") {
+            return Ok(Self::Esplora { url: spec.to_string() });
+        }
+        let (kind, rest) = spec.split_once(':').ok_or_else(|| format!("invalid chain source {:?}", spec))?;
+        match kind {
+            "esplora" => Ok(Self::Esplora { url: rest.to_string() }),
+            "bitcoind" => {
+                let (host, port) = rest
+                    .rsplit_once(':')
+                    .ok_or_else(|| format!("bitcoind chain source needs <host>:<port>, got {:?}", rest))?;
+                let port = port.parse().map_err(|_| format!("invalid bitcoind RPC port {:?}", port))?;
+                let user = std::env::var(BITCOIND_RPC_USER_ENV)
+                    .map_err(|_| format!("{} must be set for a bitcoind chain source", BITCOIND_RPC_USER_ENV))?;
+                let password = std::env::var(BITCOIND_RPC_PASSWORD_ENV)
+                    .map_err(|_| format!("{} must be set for a bitcoind chain source", BITCOIND_RPC_PASSWORD_ENV))?;
+                Ok(Self::BitcoindRpc { host: host.to_string(), port, user, password })
+            }
+            "electrum" => Err("Electrum isn't supported by this version of ldk-node; use esplora or bitcoind".to_string()),
+            other => Err(format!("unknown chain source type {:?}; expected esplora or bitcoind", other)),
+        }
+    }
+
+    pub fn configure(&self, builder: &mut Builder) {
+        info!("Using chain source: {}", self);
+        match self {
+            Self::Esplora { url } => {
+                builder.set_chain_source_esplora(url.clone(), None);
+            }
+            Self::BitcoindRpc { host, port, user, password } => {
+                builder.set_chain_source_bitcoind_rpc(host.clone(), *port, user.clone(), password.clone());
+            }
+        }
+    }
+
+    /// The Esplora API URL, for explorer links
+    pub fn esplora_url(&self) -> Option<&str> {
+        match self {
+            Self::Esplora { url } => Some(url),
+            Self::BitcoindRpc { .. } => None,
+        }
+    }
+
+    pub fn tip_height(&self, agent: &Agent) -> Result<u32, String> {
+        match self {
+            Self::Esplora { url } => {
+                let body = esplora_get(agent, url, "blocks/tip/height")?;
+                body.parse().map_err(|_| format!("unexpected tip height {:?}", body))
+            }
+            Self::BitcoindRpc { .. } => {
+                let count = self.rpc_call(agent, "getblockcount", json!([]))?;
+                count.as_u64().map(|h| h as u32).ok_or_else(|| format!("unexpected block count {}", count))
+            }
+        }
+    }
+
+    /// Fee rates for the slow, normal and fast confirmation targets
+    pub fn fee_estimates(&self, agent: &Agent) -> Result<FeeEstimates, String> {
+        let esplora_estimates = match self {
+            Self::Esplora { url } => {
+                let body = esplora_get(agent, url, "fee-estimates")?;
+                Some(serde_json::from_str::<Value>(&body).map_err(|e| e.to_string())?)
+            }
+            Self::BitcoindRpc { .. } => None,
+        };
+        let rate_for = |target: u16| -> Result<f64, String> {
+            let rate = match &esplora_estimates {
+                // Esplora keys estimates by target; take the nearest target it has at or below ours
+                Some(estimates) => estimates
+                    .as_object()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|(blocks, rate)| Some((blocks.parse::<u16>().ok()?, rate.as_f64()?)))
+                    .filter(|(blocks, _)| *blocks <= target)
+                    .max_by_key(|(blocks, _)| *blocks)
+                    .map(|(_, rate)| rate),
+                // bitcoind answers in BTC per kvB
+                None => self
+                    .rpc_call(agent, "estimatesmartfee", json!([target]))?
+                    .get("feerate")
+                    .and_then(Value::as_f64)
+                    .map(|btc_per_kvb| btc_per_kvb * 100_000.0),
+            };
+            rate.ok_or_else(|| format!("no fee estimate for {} blocks", target))
+        };
+        Ok(FeeEstimates {
+            slow: rate_for(fees::SLOW_TARGET_BLOCKS)?,
+            normal: rate_for(fees::NORMAL_TARGET_BLOCKS)?,
+            fast: rate_for(fees::FAST_TARGET_BLOCKS)?,
+        })
+    }
+
+    fn genesis_hash(&self, agent: &Agent) -> Result<String, String> {
+        match self {
+            Self::Esplora { url } => esplora_get(agent, url, "block-height/0"),
+            Self::BitcoindRpc { .. } => {
+                let hash = self.rpc_call(agent, "getblockhash", json!([0]))?;
+                hash.as_str().map(str::to_string).ok_or_else(|| format!("unexpected block hash {}", hash))
+            }
+        }
+    }
+
+    /// Startup check that the backend answers and follows `network`. An unreachable backend
+    /// is only logged, as the node retries on its own; a backend on another chain is an error.
+    pub fn validate(&self, network: Network) -> Result<(), String> {
+        let agent = Agent::new();
+        match self.tip_height(&agent) {
+            Ok(height) => info!("Chain source {} is at height {}", self, height),
+            Err(e) => {
+                warn!("Chain source {} is unreachable: {}", self, e);
+                return Ok(());
+            }
+        }
+
+        let genesis = self.genesis_hash(&agent)?;
+        if genesis == genesis_block(network).block_hash().to_string() {
+            return Ok(());
+        }
+        let actual = [Network::Bitcoin, Network::Testnet, Network::Signet, Network::Regtest]
+            .into_iter()
+            .find(|n| genesis == genesis_block(*n).block_hash().to_string())
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| format!("an unknown chain (genesis {})", genesis));
+        Err(format!(
+            "Chain source {} is on {}, but the node runs on {}. Point --chain-source or {} at a {} backend.",
+            self, actual, network, CHAIN_SOURCE_ENV, network
+        ))
+    }
+
+    /// Calls bitcoind's JSON-RPC; fails for other backends
+    pub fn rpc_call(&self, agent: &Agent, method: &str, params: Value) -> Result<Value, String> {
+        let Self::BitcoindRpc { host, port, user, password } = self else {
+            return Err("not a bitcoind chain source".to_string());
+        };
+        let response: Value = agent
+            .post(&format!("http://{}:{}/", host, port))
+            .timeout(REQUEST_TIMEOUT)
+            .set("Authorization", &format!("Basic {}", base64_encode(format!("{}:{}", user, password).as_bytes())))
+            .send_json(json!({ "jsonrpc": "1.0", "id": "stable-channels", "method": method, "params": params }))
+            .map_err(|e| e.to_string())?
+            .into_json()
+            .map_err(|e| e.to_string())?;
+        match response.get("error") {
+            Some(error) if !error.is_null() => Err(error.to_string()),
+            _ => Ok(response.get("result").cloned().unwrap_or(Value::Null)),
+        }
+    }
+}
+
+impl std::fmt::Display for ChainSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Esplora { url } => write!(f, "Esplora {}", url),
+            Self::BitcoindRpc { host, port, .. } => write!(f, "bitcoind RPC {}:{}", host, port),
+        }
+    }
+}
+
+/// The value of `--chain-source <spec>` or `--chain-source=<spec>`, if given
+fn cli_spec() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--chain-source" {
+            return args.next();
+        }
+        if let Some(spec) = arg.strip_prefix("--chain-source=") {
+            return Some(spec.to_string());
+        }
+    }
+    None
+}
+
+fn esplora_get(agent: &Agent, url: &str, path: &str) -> Result<String, String> {
+    let body = agent
+        .get(&format!("{}/{}", url.trim_end_matches('/'), path))
+        .timeout(REQUEST_TIMEOUT)
+        .call()
+        .map_err(|e| e.to_string())?
+        .into_string()
+        .map_err(|e| e.to_string())?;
+    Ok(body.trim().to_string())
+}
+
+/// Standard padded base64, for the RPC's basic auth header
+fn base64_encode(input: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 0x3F] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,304 @@
+use crate::encryption;
+use ldk_node::bitcoin::secp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tracing::{error, warn};
+
+const CHANNEL_POLICY_FILE_NAME: &str = "channel_policy.json";
+
+/// Which channels peers may open to us. ldk-node accepts every open request, so the
+/// policy is checked once the channel is pending and violating channels are closed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChannelPolicy {
+    pub min_channel_sats: u64,
+    /// Zero for no maximum
+    pub max_channel_sats: u64,
+    /// Peers whose channels are accepted whatever the size and count limits; if any are
+    /// listed, channels from anyone else are rejected
+    pub allowlist: Vec<String>,
+    pub denylist: Vec<String>,
+    /// Zero for no limit
+    pub max_channels_per_peer: usize,
+    /// Trust allowlisted peers to open channels we can use before they confirm. Read at
+    /// startup, since the node is built with its trusted peers.
+    pub allow_zero_conf: bool,
+}
+
+impl Default for ChannelPolicy {
+    fn default() -> Self {
+        Self {
+            min_channel_sats: 20_000,
+            max_channel_sats: 0,
+            allowlist: Vec::new(),
+            denylist: Vec::new(),
+            max_channels_per_peer: 0,
+            allow_zero_conf: false,
+        }
+    }
+}
+
+/// An inbound channel as far as the policy is concerned
+pub struct InboundChannel {
+    pub counterparty: PublicKey,
+    pub channel_value_sats: u64,
+    pub zero_conf: bool,
+    /// Other channels we already have with the counterparty
+    pub existing_with_peer: usize,
+}
+
+impl ChannelPolicy {
+    fn path(data_dir: &Path) -> PathBuf {
+        data_dir.join(CHANNEL_POLICY_FILE_NAME)
+    }
+
+    /// The saved policy, or the default if none is saved or the saved one is invalid
+    pub fn load(data_dir: &Path) -> Self {
+        let path = Self::path(data_dir);
+        if !path.exists() {
+            return Self::default();
+        }
+        let policy = match encryption::read_state_file(&path).map(|s| serde_json::from_str::<Self>(&s)) {
+            Ok(Ok(policy)) => policy,
+            Ok(Err(e)) => {
+                error!("Ignoring unreadable {}: {}", path.display(), e);
+                return Self::default();
+            }
+            Err(e) => {
+                error!("Failed to read {}: {}", path.display(), e);
+                return Self::default();
+            }
+        };
+        match policy.validate() {
+            Ok(()) => policy,
+            Err(e) => {
+                warn!("Ignoring invalid channel policy in {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, data_dir: &Path) -> Result<(), String> {
+        self.validate()?;
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        encryption::write_state_file(&Self::path(data_dir), &json).map_err(|e| e.to_string())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_channel_sats > 0 && self.min_channel_sats > self.max_channel_sats {
+            return Err("Min channel size is larger than max channel size".to_string());
+        }
+        for pubkey in self.allowlist.iter().chain(&self.denylist) {
+            PublicKey::from_str(pubkey).map_err(|_| format!("Invalid pubkey: {}", pubkey))?;
+        }
+        if let Some(pubkey) = self.allowlist.iter().find(|pk| self.denylist.contains(pk)) {
+            return Err(format!("{} is both allowed and denied", pubkey));
+        }
+        Ok(())
+    }
+
+    fn is_allowlisted(&self, peer: &PublicKey) -> bool {
+        let peer = peer.to_string();
+        self.allowlist.iter().any(|pk| *pk == peer)
+    }
+
+    /// Peers the node trusts to open zero-conf channels
+    pub fn zero_conf_peers(&self) -> Vec<PublicKey> {
+        if !self.allow_zero_conf {
+            return Vec::new();
+        }
+        self.allowlist.iter().filter_map(|pk| PublicKey::from_str(pk).ok()).collect()
+    }
+
+    /// Why the channel breaks the policy, or Ok if it may stay open
+    pub fn evaluate(&self, channel: &InboundChannel) -> Result<(), String> {
+        let peer = channel.counterparty.to_string();
+        if self.denylist.contains(&peer) {
+            return Err(format!("{} is denylisted", peer));
+        }
+        let allowlisted = self.is_allowlisted(&channel.counterparty);
+        if !self.allowlist.is_empty() && !allowlisted {
+            return Err(format!("{} is not allowlisted", peer));
+        }
+        if channel.zero_conf && !(self.allow_zero_conf && allowlisted) {
+            return Err(format!("zero-conf channels from {} are not allowed", peer));
+        }
+        if allowlisted {
+            return Ok(());
+        }
+        if channel.channel_value_sats < self.min_channel_sats {
+            return Err(format!("{} sats is below the {} sat minimum", channel.channel_value_sats, self.min_channel_sats));
+        }
+        if self.max_channel_sats > 0 && channel.channel_value_sats > self.max_channel_sats {
+            return Err(format!("{} sats is above the {} sat maximum", channel.channel_value_sats, self.max_channel_sats));
+        }
+        if self.max_channels_per_peer > 0 && channel.existing_with_peer >= self.max_channels_per_peer {
+            return Err(format!("{} already has {} channels", peer, channel.existing_with_peer));
+        }
+        Ok(())
+    }
+}
+
+/// "Inbound channel policy" form. Saving applies the policy right away, except for
+/// zero-conf trust which needs a restart.
+pub struct ChannelPolicyForm {
+    draft: ChannelPolicy,
+    /// One pubkey per line
+    allowlist: String,
+    denylist: String,
+    message: Option<Result<String, String>>,
+}
+
+impl ChannelPolicyForm {
+    pub fn new(policy: &ChannelPolicy) -> Self {
+        Self {
+            draft: policy.clone(),
+            allowlist: policy.allowlist.join("\n"),
+            denylist: policy.denylist.join("\n"),
+            message: None,
+        }
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui, data_dir: &Path, policy: &mut ChannelPolicy) {
+        egui::Grid::new("channel_policy").num_columns(2).show(ui, |ui| {
+            ui.label("Min channel (sats)");
+            ui.add(egui::DragValue::new(&mut self.draft.min_channel_sats));
+            ui.end_row();
+            ui.label("Max channel (sats, 0 for none)");
+            ui.add(egui::DragValue::new(&mut self.draft.max_channel_sats));
+            ui.end_row();
+            ui.label("Max channels per peer (0 for none)");
+            ui.add(egui::DragValue::new(&mut self.draft.max_channels_per_peer));
+            ui.end_row();
+            ui.label("Allowlist (one pubkey per line)");
+            ui.text_edit_multiline(&mut self.allowlist);
+            ui.end_row();
+            ui.label("Denylist (one pubkey per line)");
+            ui.text_edit_multiline(&mut self.denylist);
+            ui.end_row();
+        });
+        ui.checkbox(&mut self.draft.allow_zero_conf, "Allow zero-conf channels from allowlisted peers");
+
+        if ui.button("Save policy").clicked() {
+            self.draft.allowlist = pubkey_lines(&self.allowlist);
+            self.draft.denylist = pubkey_lines(&self.denylist);
+            self.message = Some(self.draft.save(data_dir).map(|()| {
+                let restart = self.draft.zero_conf_peers() != policy.zero_conf_peers();
+                *policy = self.draft.clone();
+                if restart {
+                    "Saved; zero-conf changes apply on the next start".to_string()
+                } else {
+                    "Saved".to_string()
+                }
+            }));
+        }
+        match &self.message {
+            Some(Ok(message)) => {
+                ui.colored_label(egui::Color32::GREEN, message);
+            }
+            Some(Err(e)) => {
+                ui.colored_label(egui::Color32::RED, e);
+            }
+            None => {}
+        }
+    }
+}
+
+fn pubkey_lines(text: &str) -> Vec<String> {
+    text.lines().map(str::trim).filter(|l| !l.is_empty()).map(str::to_string).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ldk_node::bitcoin::secp256k1::{Secp256k1, SecretKey};
+
+    fn peer(byte: u8) -> PublicKey {
+        PublicKey::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&[byte; 32]).unwrap())
+    }
+
+    fn open(byte: u8, channel_value_sats: u64) -> InboundChannel {
+        InboundChannel { counterparty: peer(byte), channel_value_sats, zero_conf: false, existing_with_peer: 0 }
+    }
+
+    fn bounded() -> ChannelPolicy {
+        ChannelPolicy {
+            min_channel_sats: 100_000,
+            max_channel_sats: 10_000_000,
+            max_channels_per_peer: 2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn default_policy_only_refuses_tiny_and_zero_conf_channels() {
+        let policy = ChannelPolicy::default();
+
+        assert_eq!(policy.evaluate(&open(1, 20_000)), Ok(()));
+        assert_eq!(policy.evaluate(&open(1, u64::MAX)), Ok(()));
+        assert!(policy.evaluate(&open(1, 19_999)).unwrap_err().contains("below"));
+        let zero_conf = InboundChannel { zero_conf: true, ..open(1, 1_000_000) };
+        assert!(policy.evaluate(&zero_conf).unwrap_err().contains("zero-conf"));
+    }
+
+    #[test]
+    fn size_limits_are_inclusive() {
+        let policy = bounded();
+
+        assert_eq!(policy.evaluate(&open(1, 100_000)), Ok(()));
+        assert_eq!(policy.evaluate(&open(1, 10_000_000)), Ok(()));
+        assert!(policy.evaluate(&open(1, 99_999)).unwrap_err().contains("below"));
+        assert!(policy.evaluate(&open(1, 10_000_001)).unwrap_err().contains("above"));
+    }
+
+    #[test]
+    fn peer_may_not_open_more_than_its_share() {
+        let policy = bounded();
+
+        assert_eq!(policy.evaluate(&InboundChannel { existing_with_peer: 1, ..open(1, 1_000_000) }), Ok(()));
+        let third = InboundChannel { existing_with_peer: 2, ..open(1, 1_000_000) };
+        assert!(policy.evaluate(&third).unwrap_err().contains("already has 2 channels"));
+    }
+
+    #[test]
+    fn denylisted_peer_is_refused_whatever_the_channel() {
+        let policy = ChannelPolicy { denylist: vec![peer(2).to_string()], ..bounded() };
+
+        assert!(policy.evaluate(&open(2, 1_000_000)).unwrap_err().contains("denylisted"));
+        assert_eq!(policy.evaluate(&open(1, 1_000_000)), Ok(()));
+    }
+
+    #[test]
+    fn allowlist_admits_only_its_peers_and_lifts_the_limits_for_them() {
+        let policy = ChannelPolicy { allowlist: vec![peer(1).to_string()], ..bounded() };
+
+        assert!(policy.evaluate(&open(2, 1_000_000)).unwrap_err().contains("not allowlisted"));
+        assert_eq!(policy.evaluate(&open(1, 1_000)), Ok(()));
+        assert_eq!(policy.evaluate(&open(1, 100_000_000)), Ok(()));
+        assert_eq!(policy.evaluate(&InboundChannel { existing_with_peer: 9, ..open(1, 1_000_000) }), Ok(()));
+    }
+
+    #[test]
+    fn zero_conf_needs_both_the_flag_and_the_allowlist() {
+        let zero_conf = InboundChannel { zero_conf: true, ..open(1, 1_000_000) };
+        let listed = ChannelPolicy { allowlist: vec![peer(1).to_string()], ..bounded() };
+
+        assert!(listed.evaluate(&zero_conf).is_err());
+        let trusting = ChannelPolicy { allow_zero_conf: true, ..listed };
+        assert_eq!(trusting.evaluate(&zero_conf), Ok(()));
+        assert_eq!(trusting.zero_conf_peers(), vec![peer(1)]);
+        let unlisted = ChannelPolicy { allow_zero_conf: true, ..bounded() };
+        assert!(unlisted.evaluate(&zero_conf).is_err());
+        assert!(unlisted.zero_conf_peers().is_empty());
+    }
+
+    #[test]
+    fn invalid_policies_are_rejected() {
+        assert!(ChannelPolicy { min_channel_sats: 2, max_channel_sats: 1, ..Default::default() }.validate().is_err());
+        assert!(ChannelPolicy { denylist: vec!["not a key".to_string()], ..Default::default() }.validate().is_err());
+        let both = vec![peer(1).to_string()];
+        assert!(ChannelPolicy { allowlist: both.clone(), denylist: both, ..Default::default() }.validate().is_err());
+        assert_eq!(bounded().validate(), Ok(()));
+    }
+}
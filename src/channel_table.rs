@@ -0,0 +1,217 @@
+use crate::address_book::AddressBook;
+use crate::display;
+use crate::types::group_thousands;
+use crate::ui_util;
+use ldk_node::lightning::ln::types::ChannelId;
+use ldk_node::ChannelDetails;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Seconds a cooperative close may take before force-closing is offered
+pub const COOP_CLOSE_TIMEOUT_ENV: &str = "STABLE_CHANNELS_COOP_CLOSE_TIMEOUT_SECS";
+const DEFAULT_COOP_CLOSE_TIMEOUT_SECS: u64 = 600;
+
+/// Rough block interval, for showing timelocks in hours
+const BLOCK_MINUTES: u64 = 10;
+
+/// A per-channel button the operator clicked in the table
+pub enum ChannelAction {
+    Close(ChannelDetails),
+    /// Asks for confirmation before force-closing
+    ForceClose(ChannelDetails),
+    /// Pre-fills the designation form with this channel
+    Designate(ChannelId),
+}
+
+/// Cooperative closes in progress, so a close stuck on an unresponsive peer can be
+/// force-closed instead
+pub struct PendingCloses {
+    started: HashMap<ChannelId, Instant>,
+    timeout: Duration,
+}
+
+impl PendingCloses {
+    pub fn from_env() -> Self {
+        let secs = std::env::var(COOP_CLOSE_TIMEOUT_ENV)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_COOP_CLOSE_TIMEOUT_SECS);
+        Self { started: HashMap::new(), timeout: Duration::from_secs(secs) }
+    }
+
+    pub fn start(&mut self, channel_id: ChannelId) {
+        self.started.entry(channel_id).or_insert_with(Instant::now);
+    }
+
+    /// Forgets the close once `ChannelClosed` arrives
+    pub fn finish(&mut self, channel_id: &ChannelId) {
+        self.started.remove(channel_id);
+    }
+
+    pub fn is_closing(&self, channel_id: &ChannelId) -> bool {
+        self.started.contains_key(channel_id)
+    }
+
+    /// The close has taken longer than the timeout
+    pub fn is_stalled(&self, channel_id: &ChannelId) -> bool {
+        self.started.get(channel_id).is_some_and(|t| t.elapsed() >= self.timeout)
+    }
+}
+
+/// "Public" or "Private", for tagging channels in lists
+pub fn announcement_tag(channel: &ChannelDetails) -> &'static str {
+    if channel.is_announced {
+        "Public"
+    } else {
+        "Private"
+    }
+}
+
+/// The channel is in use on the strength of a zero-conf agreement, with its funding
+/// transaction still unconfirmed
+pub fn is_unconfirmed_zero_conf(channel: &ChannelDetails) -> bool {
+    channel.is_channel_ready && channel.confirmations.unwrap_or(0) == 0
+}
+
+/// `block x tx x output` form of a short channel id
+fn format_short_channel_id(scid: u64) -> String {
+    format!("{}x{}x{}", scid >> 40, (scid >> 16) & 0xFF_FFFF, scid & 0xFFFF)
+}
+
+/// The pubkey's label in the address book, else its first and last characters, with the
+/// full key on hover
+fn short_pubkey(ui: &mut egui::Ui, pubkey: &str, label: Option<&str>) {
+    let short = if let Some(label) = label {
+        label.to_string()
+    } else if pubkey.len() > 16 {
+        format!("{}…{}", &pubkey[..8], &pubkey[pubkey.len() - 8..])
+    } else {
+        pubkey.to_string()
+    };
+    ui.label(short).on_hover_text(pubkey);
+}
+
+/// One row per channel with balances, limits and actions. Channels in `stable_ids` are
+/// tagged [STABLE]; the Designate button is only offered if `can_designate`.
+pub fn show_channel_table(
+    ui: &mut egui::Ui,
+    id_salt: &str,
+    channels: &[ChannelDetails],
+    book: &AddressBook,
+    stable_ids: &[ChannelId],
+    closes: &PendingCloses,
+    can_designate: bool,
+) -> Option<ChannelAction> {
+    if channels.is_empty() {
+        ui.label("No channels found.");
+        return None;
+    }
+
+    let mut action = None;
+    egui::ScrollArea::horizontal().id_salt(id_salt).show(ui, |ui| {
+        egui::Grid::new(id_salt).striped(true).num_columns(9).show(ui, |ui| {
+            for header in ["Channel", "Peer", "Capacity", "Ours / theirs", "Reserve", "Next HTLC", "Type", "State", ""] {
+                ui.strong(header);
+            }
+            ui.end_row();
+
+            for channel in channels {
+                let scid = channel.short_channel_id.map(format_short_channel_id).unwrap_or_else(|| "pending".to_string());
+                ui.label(scid).on_hover_text(channel.channel_id.to_string());
+                short_pubkey(ui, &channel.counterparty_node_id.to_string(), book.label_for(&channel.counterparty_node_id));
+                ui.label(display::sats(channel.channel_value_sats));
+
+                let ours = channel.outbound_capacity_msat / 1000;
+                let theirs = channel.inbound_capacity_msat / 1000;
+                let fraction = if ours + theirs > 0 { ours as f32 / (ours + theirs) as f32 } else { 0.0 };
+                ui.add(
+                    egui::ProgressBar::new(fraction)
+                        .desired_width(140.0)
+                        .text(format!("{} / {}", group_thousands(ours), group_thousands(theirs))),
+                );
+
+                ui.label(format!(
+                    "{} / {}",
+                    channel.unspendable_punishment_reserve.unwrap_or(0),
+                    channel.counterparty_unspendable_punishment_reserve
+                ));
+                ui.label(display::sats(channel.next_outbound_htlc_limit_msat / 1000));
+
+                let is_stable = stable_ids.contains(&channel.channel_id);
+                ui.horizontal(|ui| {
+                    ui.label(announcement_tag(channel));
+                    if is_stable {
+                        ui.colored_label(egui::Color32::LIGHT_BLUE, "[STABLE]");
+                    }
+                });
+                if closes.is_stalled(&channel.channel_id) {
+                    ui.colored_label(egui::Color32::RED, "Close stalled")
+                        .on_hover_text("The peer hasn't completed the cooperative close; consider force-closing");
+                } else if closes.is_closing(&channel.channel_id) {
+                    ui.colored_label(egui::Color32::YELLOW, "Closing");
+                } else if is_unconfirmed_zero_conf(channel) {
+                    ui.colored_label(egui::Color32::YELLOW, "0-conf (unconfirmed)")
+                        .on_hover_text("Usable now; the funding transaction hasn't confirmed yet");
+                } else {
+                    ui.label(if channel.is_usable {
+                        "Ready"
+                    } else if channel.is_channel_ready {
+                        "Offline"
+                    } else {
+                        "Pending"
+                    });
+                }
+
+                ui.horizontal(|ui| {
+                    if !closes.is_closing(&channel.channel_id) && ui.small_button("Close").clicked() {
+                        action = Some(ChannelAction::Close(channel.clone()));
+                    }
+                    let force_label = egui::RichText::new("Force-close").color(egui::Color32::RED);
+                    if ui.small_button(force_label).clicked() {
+                        action = Some(ChannelAction::ForceClose(channel.clone()));
+                    }
+                    if can_designate && !is_stable && ui.small_button("Designate stable").clicked() {
+                        action = Some(ChannelAction::Designate(channel.channel_id));
+                    }
+                    ui_util::copy_button(ui, egui::Button::new("Copy ID").small(), || channel.channel_id.to_string());
+                });
+                ui.end_row();
+            }
+        });
+    });
+    action
+}
+
+/// Explains what force-closing `channel` costs and asks to go ahead. Returns Some(true) to
+/// force-close, Some(false) to cancel, None while undecided.
+pub fn show_force_close_confirmation(ui: &mut egui::Ui, channel: &ChannelDetails, is_stable: bool) -> Option<bool> {
+    let mut decision = None;
+    ui.group(|ui| {
+        ui.colored_label(egui::Color32::RED, format!("Force-close channel {}?", channel.channel_id));
+        ui.label("Force-closing broadcasts our latest commitment transaction without the peer's cooperation.");
+        match channel.force_close_spend_delay {
+            Some(blocks) => ui.label(format!(
+                "Our balance is locked for {} blocks (about {} hours) before it can be swept.",
+                blocks,
+                (blocks as u64 * BLOCK_MINUTES).div_ceil(60)
+            )),
+            None => ui.label("Our balance is locked by a timelock before it can be swept."),
+        };
+        ui.label("The commitment and sweep transactions pay on-chain fees out of our balance.");
+        if is_stable {
+            ui.colored_label(
+                egui::Color32::YELLOW,
+                "This is a stable channel: the peg ends at the current price and no final adjustment is made.",
+            );
+        }
+        ui.horizontal(|ui| {
+            if ui.button(egui::RichText::new("Force-close").color(egui::Color32::RED)).clicked() {
+                decision = Some(true);
+            }
+            if ui.button("Cancel").clicked() {
+                decision = Some(false);
+            }
+        });
+    });
+    decision
+}
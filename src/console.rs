@@ -0,0 +1,180 @@
+use crate::admin::{self, AdminReply, AdminRequest, PendingAdminRequest};
+use crate::node_ui;
+use crate::stable::StableChannelSettings;
+use crate::worker::{self, AppCommand, AppResult};
+use ldk_node::lightning_invoice::Bolt11Invoice;
+use ldk_node::Node;
+use serde_json::json;
+use std::io::BufRead;
+use std::str::FromStr;
+use std::sync::{mpsc, Arc};
+use tracing::info;
+
+const HELP: &str = "\
+balances                              on-chain and lightning balances
+channels                              all channels
+stable list                           stable channels with their distance from par
+stable designate <channel|pubkey> <usd>
+stable remove <channel_id> [--no-settle]
+invoice <sats> [description]
+pay <bolt11> [amount_sats]            amount only for invoices without one
+address                               new on-chain address
+open <pubkey@host:port> <sats> [--private]
+close <channel_id>
+sign <message>                        signature by the node key";
+
+/// Runs the console if `--console` was given
+pub fn enabled() -> bool {
+    std::env::args().any(|arg| arg == "--console")
+}
+
+/// Indents the console's JSON output if `--pretty` was given
+fn pretty() -> bool {
+    std::env::args().any(|arg| arg == "--pretty")
+}
+
+/// Reads commands from stdin on a background thread and prints one JSON reply per line.
+/// Node operations go through the same `AppCommand`s as the GUI; stable channel
+/// operations are answered by the app like admin API requests.
+pub struct Console {
+    requests: mpsc::Receiver<PendingAdminRequest>,
+}
+
+impl Console {
+    pub fn start(node: Arc<Node>) -> Self {
+        let (request_tx, request_rx) = mpsc::channel();
+        let pretty = pretty();
+        // Left detached: it is blocked reading stdin and ends with the process
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let (status, body) = run(&line, &node, &request_tx);
+                let output = json!({ "status": status, "result": body });
+                let text = if pretty { serde_json::to_string_pretty(&output) } else { serde_json::to_string(&output) };
+                println!("{}", text.unwrap_or_default());
+            }
+        });
+        info!("Console reading commands from stdin; type help for a list");
+        Self { requests: request_rx }
+    }
+
+    /// The next request waiting for the app, if any, without blocking
+    pub fn try_recv(&self) -> Option<PendingAdminRequest> {
+        self.requests.try_recv().ok()
+    }
+}
+
+fn run(line: &str, node: &Node, app: &mpsc::Sender<PendingAdminRequest>) -> AdminReply {
+    match parse(line) {
+        Ok(Command::Help) => (200, json!({ "help": HELP })),
+        Ok(Command::Balances) => (200, admin::balances_json(node)),
+        Ok(Command::Channels) => (200, admin::channels_json(node)),
+        Ok(Command::Sign(message)) => (200, admin::sign_json(node, &message)),
+        Ok(Command::App(request)) => admin::forward(app, request),
+        Ok(Command::Node(command)) => {
+            let result = worker::execute(node, command);
+            if let AppResult::ChannelOpened { result: Ok(user_channel_id), .. } = &result {
+                admin::forward(app, AdminRequest::ManualChannel(*user_channel_id));
+            }
+            result_json(result)
+        }
+        Err(e) => (400, json!({ "error": e })),
+    }
+}
+
+enum Command {
+    Help,
+    Balances,
+    Channels,
+    Sign(String),
+    /// Answered by the app, which holds the stable channel state
+    App(AdminRequest),
+    /// Run against the node directly, as the GUI's worker does
+    Node(AppCommand),
+}
+
+fn parse(line: &str) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let amount = |word: Option<&&str>, name: &str| -> Result<u64, String> {
+        word.ok_or(format!("missing {}", name))?.parse().map_err(|_| format!("invalid {}", name))
+    };
+    let command = match words.as_slice() {
+        ["help"] => Command::Help,
+        ["balances"] => Command::Balances,
+        ["channels"] => Command::Channels,
+        ["stable", "list"] => Command::App(AdminRequest::ListStableChannels),
+        ["stable", "designate", target, usd] => {
+            let expected_usd: f64 = usd.parse().map_err(|_| "invalid target".to_string())?;
+            if expected_usd <= 0.0 {
+                return Err("target must be positive".to_string());
+            }
+            Command::App(AdminRequest::Designate {
+                target: target.to_string(),
+                expected_usd,
+                settings: StableChannelSettings::default(),
+            })
+        }
+        ["stable", "remove", channel_id, rest @ ..] => Command::App(AdminRequest::Remove {
+            channel_id: channel_id.to_string(),
+            settle_first: !rest.contains(&"--no-settle"),
+        }),
+        ["invoice", rest @ ..] => {
+            let amount_sats = amount(rest.first(), "amount")?;
+            let description = if rest.len() > 1 { rest[1..].join(" ") } else { "Invoice".to_string() };
+            Command::Node(AppCommand::GenerateInvoice { amount_msats: amount_sats * 1000, description })
+        }
+        ["pay", invoice, rest @ ..] => {
+            let invoice = Bolt11Invoice::from_str(invoice).map_err(|e| format!("invalid invoice: {}", e))?;
+            let amount_msats = match rest.first() {
+                Some(_) => Some(amount(rest.first(), "amount")? * 1000),
+                None => None,
+            };
+            Command::Node(AppCommand::PayInvoice { invoice, amount_msats, max_fee_msats: None })
+        }
+        ["address"] => Command::Node(AppCommand::NewAddress),
+        ["open", peer, rest @ ..] => {
+            let (node_id, address) = node_ui::parse_peer(peer).ok_or("invalid peer; expected pubkey@host:port")?;
+            Command::Node(AppCommand::OpenChannel {
+                node_id,
+                address,
+                amount_sats: amount(rest.first(), "amount")?,
+                push_msat: None,
+                announce: !rest.contains(&"--private"),
+                channel_config: None,
+            })
+        }
+        ["sign", ..] => {
+            // Sign the text as typed, not re-joined words, so spacing is kept
+            let message = line.trim_start().strip_prefix("sign").unwrap_or_default().trim();
+            if message.is_empty() {
+                return Err("missing message".to_string());
+            }
+            Command::Sign(message.to_string())
+        }
+        ["close", channel_id] => Command::App(AdminRequest::CloseChannel { channel_id: channel_id.to_string() }),
+        _ => return Err("unknown command; type help for a list".to_string()),
+    };
+    Ok(command)
+}
+
+/// Status and JSON body for the result of a console command
+fn result_json(result: AppResult) -> AdminReply {
+    match result {
+        AppResult::InvoiceGenerated(Ok(invoice)) => (200, json!({ "invoice": invoice.to_string() })),
+        AppResult::InvoicePaid(Ok(payment_id)) => (200, json!({ "payment_id": payment_id.to_string() })),
+        AppResult::AddressGenerated(Ok(address)) => (200, json!({ "address": address.to_string() })),
+        AppResult::ChannelOpened { node_id, amount_sats, result: Ok(user_channel_id) } => (200, json!({
+            "node_id": node_id.to_string(),
+            "amount_sats": amount_sats,
+            "user_channel_id": user_channel_id.0.to_string(),
+        })),
+        AppResult::InvoiceGenerated(Err(e))
+        | AppResult::InvoicePaid(Err(e))
+        | AppResult::AddressGenerated(Err(e))
+        | AppResult::ChannelOpened { result: Err(e), .. } => (500, json!({ "error": e.to_string() })),
+        _ => (500, json!({ "error": "unexpected result" })),
+    }
+}
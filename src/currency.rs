@@ -0,0 +1,86 @@
+// Fiat currencies and their formatting, kept free of the node's types so the web build
+// can use them too
+
+use serde::{Deserialize, Serialize};
+
+/// Millionths of a currency unit, the precision fiat amounts are kept to
+pub(crate) const MICROS_PER_UNIT: i64 = 1_000_000;
+
+/// `price` if it can be converted at: finite and above zero. A price of zero means none
+/// has been fetched yet.
+pub fn valid_price(price: f64) -> Option<f64> {
+    (price.is_finite() && price > 0.0).then_some(price)
+}
+
+/// `value` with a comma every three digits, e.g. "1,234,567"
+pub fn group_thousands(value: u64) -> String {
+    let digits = value.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+/// `micros` of a fiat currency to the cent, with separators, e.g. "-$1,234.50"
+pub(crate) fn format_fiat(symbol: &str, micros: i64) -> String {
+    let cents = (micros.unsigned_abs() + 5_000) / 10_000;
+    let sign = if micros < 0 && cents > 0 { "-" } else { "" };
+    format!("{}{}{}.{:02}", sign, symbol, group_thousands(cents / 100), cents % 100)
+}
+
+/// Fiat currency a stable channel is pegged to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Currency {
+    #[default]
+    USD,
+    EUR,
+    GBP,
+    CHF,
+}
+
+impl Currency {
+    pub const ALL: [Currency; 4] = [Currency::USD, Currency::EUR, Currency::GBP, Currency::CHF];
+
+    /// ISO 4217 code, also used on the wire
+    pub fn code(self) -> &'static str {
+        match self {
+            Currency::USD => "USD",
+            Currency::EUR => "EUR",
+            Currency::GBP => "GBP",
+            Currency::CHF => "CHF",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.code().eq_ignore_ascii_case(code))
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Currency::USD => "$",
+            Currency::EUR => "€",
+            Currency::GBP => "£",
+            Currency::CHF => "CHF ",
+        }
+    }
+
+    /// `amount` with this currency's symbol, separators and two decimals, e.g. "€1,012.50"
+    pub fn format(self, amount: f64) -> String {
+        format_fiat(self.symbol(), (amount * MICROS_PER_UNIT as f64).round() as i64)
+    }
+
+    /// A BTC price in this currency, or "waiting for price…" before there is one
+    pub fn format_price(self, price: f64) -> String {
+        valid_price(price).map_or_else(|| "waiting for price…".to_string(), |price| self.format(price))
+    }
+}
+
+impl std::fmt::Display for Currency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.code())
+    }
+}
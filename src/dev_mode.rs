@@ -0,0 +1,79 @@
+use crate::price_feeds;
+
+/// Set to 1 to turn on developer tools such as the price override. `--dev-mode` on the
+/// command line does the same.
+pub const DEV_MODE_ENV: &str = "STABLE_CHANNELS_DEV_MODE";
+
+lazy_static::lazy_static! {
+    static ref ENABLED: bool = std::env::args().skip(1).any(|arg| arg == "--dev-mode")
+        || std::env::var(DEV_MODE_ENV).is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"));
+}
+
+/// Whether developer tools are available. The regtest run always has them.
+pub fn enabled() -> bool {
+    cfg!(feature = "regtest") || *ENABLED
+}
+
+/// Warning shown at the top of a screen while the price is forced
+pub fn show_banner(ui: &mut egui::Ui) {
+    if let Some(price) = price_feeds::price_override() {
+        ui.label(
+            egui::RichText::new(format!("⚠ SIMULATED PRICE: ${:.2}, not the market", price))
+                .strong()
+                .color(egui::Color32::BLACK)
+                .background_color(egui::Color32::YELLOW),
+        );
+    }
+}
+
+/// Developer panel forcing the BTC/USD price, by a percentage of the feed price or exactly
+#[derive(Default)]
+pub struct PriceOverrideControl {
+    percent: f64,
+    input: String,
+    error: Option<String>,
+}
+
+impl PriceOverrideControl {
+    /// Draws nothing outside developer mode
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        if !enabled() {
+            return;
+        }
+        egui::CollapsingHeader::new("Developer: simulated price").show(ui, |ui| {
+            let feed_price = price_feeds::feed_price();
+            ui.label(format!("Feed price: ${:.2}", feed_price));
+            ui.horizontal(|ui| {
+                ui.label("Move from feed:");
+                let slider = ui.add(egui::Slider::new(&mut self.percent, -50.0..=50.0).suffix("%"));
+                // Apply once the drag ends rather than on every frame of it
+                if (slider.drag_stopped() || (slider.changed() && !slider.dragged())) && feed_price > 0.0 {
+                    let price = feed_price * (1.0 + self.percent / 100.0);
+                    self.input = format!("{:.2}", price);
+                    self.error = None;
+                    price_feeds::set_price_override(Some(price));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Price (USD):");
+                ui.add(egui::TextEdit::singleline(&mut self.input).desired_width(80.0));
+                if ui.button("Set").clicked() {
+                    match self.input.trim().parse::<f64>() {
+                        Ok(price) if price > 0.0 => {
+                            self.error = None;
+                            price_feeds::set_price_override(Some(price));
+                        }
+                        _ => self.error = Some("Invalid price".to_string()),
+                    }
+                }
+                if ui.button("Clear").clicked() {
+                    *self = Self::default();
+                    price_feeds::set_price_override(None);
+                }
+            });
+            if let Some(error) = &self.error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+        });
+    }
+}
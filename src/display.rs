@@ -0,0 +1,90 @@
+use crate::encryption;
+use crate::settings;
+use crate::types::{AmountUnit, Bitcoin, Currency};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::error;
+
+const DISPLAY_SETTINGS_FILE_NAME: &str = "display_settings.json";
+
+/// Colour scheme of the apps
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+}
+
+/// How amounts are shown, shared by every screen of the app
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplaySettings {
+    pub unit: AmountUnit,
+    /// Currency new pegs default to
+    pub fiat: Currency,
+    pub theme: Theme,
+}
+
+impl DisplaySettings {
+    /// The settings saved on their own before they moved into the settings file, or the
+    /// defaults if none are saved
+    pub fn load(data_dir: &Path) -> Self {
+        let path = data_dir.join(DISPLAY_SETTINGS_FILE_NAME);
+        if !path.exists() {
+            return Self::default();
+        }
+        match encryption::read_state_file(&path).map(|s| serde_json::from_str::<Self>(&s)) {
+            Ok(Ok(settings)) => settings,
+            Ok(Err(e)) => {
+                error!("Ignoring unreadable {}: {}", path.display(), e);
+                Self::default()
+            }
+            Err(e) => {
+                error!("Failed to read {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+}
+
+pub fn unit() -> AmountUnit {
+    settings::get().display.unit
+}
+
+/// Switches the unit and saves it
+pub fn set_unit(unit: AmountUnit) {
+    if let Err(e) = settings::update(|settings| settings.display.unit = unit) {
+        error!("Failed to switch to {}: {}", unit, e);
+    }
+}
+
+pub fn fiat() -> Currency {
+    settings::get().display.fiat
+}
+
+/// Switches `ctx` to the chosen theme, if it isn't already
+pub fn apply_theme(ctx: &egui::Context) {
+    let dark = settings::get().display.theme == Theme::Dark;
+    if ctx.style().visuals.dark_mode != dark {
+        ctx.set_visuals(if dark { egui::Visuals::dark() } else { egui::Visuals::light() });
+    }
+}
+
+/// `sats` in the chosen unit, e.g. "1,234 sats" or "0.00001234 BTC"
+pub fn sats(sats: u64) -> String {
+    Bitcoin::from_sats(sats).format(unit())
+}
+
+/// `btc` in the chosen unit
+pub fn btc(btc: Bitcoin) -> String {
+    btc.format(unit())
+}
+
+/// Sats/BTC switch for the top of a screen
+pub fn unit_toggle(ui: &mut egui::Ui) {
+    let mut selected = unit();
+    ui.label("Show amounts in:");
+    ui.selectable_value(&mut selected, AmountUnit::Sats, "sats");
+    ui.selectable_value(&mut selected, AmountUnit::Btc, "BTC");
+    set_unit(selected);
+}
@@ -0,0 +1,226 @@
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::fs;
+
+/// Environment variable holding the passphrase for app state files
+pub const PASSPHRASE_ENV: &str = "STABLE_CHANNELS_PASSPHRASE";
+
+// Encrypted files start with this marker, followed by the salt, nonce and ciphertext
+const MAGIC: &[u8] = b"SCENC1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+static PASSPHRASE: OnceLock<Option<String>> = OnceLock::new();
+
+#[derive(Debug)]
+pub enum EncryptionError {
+    MissingPassphrase,
+    WrongPassphrase,
+    Corrupt,
+    KeyDerivation(String),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncryptionError::MissingPassphrase => {
+                write!(f, "file is encrypted but no passphrase was provided (set {})", PASSPHRASE_ENV)
+            }
+            EncryptionError::WrongPassphrase => write!(f, "wrong passphrase, or the file has been tampered with"),
+            EncryptionError::Corrupt => write!(f, "encrypted file is truncated or corrupt"),
+            EncryptionError::KeyDerivation(e) => write!(f, "failed to derive key: {}", e),
+            EncryptionError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for EncryptionError {}
+
+/// The passphrase for app state, read from `PASSPHRASE_ENV` or prompted for once on a
+/// terminal. `None` means files are stored in plaintext.
+pub fn passphrase() -> Option<&'static str> {
+    PASSPHRASE
+        .get_or_init(|| {
+            if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+                return Some(passphrase).filter(|p| !p.is_empty());
+            }
+
+            // Tests never prompt, even when run from a terminal
+            if cfg!(test) || !std::io::stdin().is_terminal() {
+                return None;
+            }
+
+            // Not echoed, so it doesn't end up on screen or in the terminal's scrollback
+            let passphrase =
+                rpassword::prompt_password("Passphrase for stable channel data (leave empty for none): ").ok()?;
+            Some(passphrase).filter(|p| !p.is_empty())
+        })
+        .as_deref()
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key, EncryptionError> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| EncryptionError::KeyDerivation(e.to_string()))?;
+    Ok(key)
+}
+
+pub fn encrypt(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>, EncryptionError> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let key = derive_key(passphrase, &salt)?;
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+    let ciphertext = ChaCha20Poly1305::new(&key)
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| EncryptionError::Corrupt)?;
+
+    let mut out = Vec::with_capacity(MAGIC.len() + SALT_LEN + NONCE_LEN + ciphertext.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+pub fn decrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>, EncryptionError> {
+    let body = data.strip_prefix(MAGIC).ok_or(EncryptionError::Corrupt)?;
+    if body.len() < SALT_LEN + NONCE_LEN {
+        return Err(EncryptionError::Corrupt);
+    }
+
+    let (salt, rest) = body.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let key = derive_key(passphrase, salt)?;
+
+    ChaCha20Poly1305::new(&key)
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| EncryptionError::WrongPassphrase)
+}
+
+/// Reads an app state file, decrypting it if needed. Plaintext files are returned as-is
+/// and get encrypted on the next `write_state_file` once a passphrase is set.
+pub fn read_state_file(path: &Path) -> Result<String, EncryptionError> {
+    read_state_file_with(path, passphrase())
+}
+
+fn read_state_file_with(path: &Path, passphrase: Option<&str>) -> Result<String, EncryptionError> {
+    let data = fs::read(path).map_err(EncryptionError::Io)?;
+
+    let plaintext = if is_encrypted(&data) {
+        let passphrase = passphrase.ok_or(EncryptionError::MissingPassphrase)?;
+        decrypt(&data, passphrase)?
+    } else {
+        data
+    };
+
+    String::from_utf8(plaintext).map_err(|_| EncryptionError::Corrupt)
+}
+
+/// Writes an app state file, encrypted when a passphrase is configured
+pub fn write_state_file(path: &Path, contents: &str) -> Result<(), EncryptionError> {
+    write_state_file_with(path, contents, passphrase())
+}
+
+/// Writes `contents` as `write_state_file` does, unless it is what `last_written` says the
+/// file already holds. Returns whether it wrote, and remembers what it wrote.
+pub fn write_state_file_if_changed(
+    path: &Path,
+    contents: String,
+    last_written: &mut Option<String>,
+) -> Result<bool, EncryptionError> {
+    if last_written.as_deref() == Some(contents.as_str()) && path.exists() {
+        return Ok(false);
+    }
+    write_state_file(path, &contents)?;
+    *last_written = Some(contents);
+    Ok(true)
+}
+
+fn write_state_file_with(path: &Path, contents: &str, passphrase: Option<&str>) -> Result<(), EncryptionError> {
+    let data = match passphrase {
+        Some(passphrase) => encrypt(contents.as_bytes(), passphrase)?,
+        None => contents.as_bytes().to_vec(),
+    };
+    write_atomically(path, &data).map_err(EncryptionError::Io)
+}
+
+/// Writes `data` to a temporary file beside `path`, flushes it to disk and renames it over
+/// `path`, so a crash mid-write leaves either the old file or the new one, never a torn one
+fn write_atomically(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let file_name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let partial = path.with_file_name(format!(".{}.partial", file_name));
+    let mut file = fs::File::create(&partial)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&partial, path)?;
+
+    // The rename itself is only durable once the directory is synced
+    #[cfg(unix)]
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let data = encrypt(b"stable channels", "correct horse").unwrap();
+
+        assert!(is_encrypted(&data));
+        assert_eq!(decrypt(&data, "correct horse").unwrap(), b"stable channels");
+    }
+
+    #[test]
+    fn wrong_passphrase_is_rejected() {
+        let data = encrypt(b"stable channels", "correct horse").unwrap();
+
+        assert!(matches!(decrypt(&data, "battery staple"), Err(EncryptionError::WrongPassphrase)));
+    }
+
+    #[test]
+    fn plaintext_file_is_encrypted_on_next_write() {
+        let path = std::env::temp_dir().join(format!("stable-channels-encryption-{}.json", std::process::id()));
+        fs::write(&path, "{\"expected_usd\":100.0}").unwrap();
+
+        // Read as-is, then written back encrypted once there is a passphrase
+        let contents = read_state_file_with(&path, None).unwrap();
+        assert_eq!(contents, "{\"expected_usd\":100.0}");
+        write_state_file_with(&path, &contents, Some("correct horse")).unwrap();
+
+        assert!(is_encrypted(&fs::read(&path).unwrap()));
+        assert_eq!(read_state_file_with(&path, Some("correct horse")).unwrap(), contents);
+        assert!(matches!(read_state_file_with(&path, None), Err(EncryptionError::MissingPassphrase)));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn write_replaces_the_file_and_leaves_no_partial_behind() {
+        let dir = std::env::temp_dir().join(format!("stable-channels-atomic-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("stablechannels.json");
+        fs::write(&path, "old").unwrap();
+
+        write_state_file_with(&path, "new", None).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,100 @@
+use crate::chain_source::ChainSource;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+use ureq::Agent;
+
+/// Estimates are refetched when older than this
+const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Lowest fee rate nodes relay, in sat/vB
+pub const MIN_RELAY_FEE_SAT_PER_VB: u64 = 1;
+
+/// Confirmation targets, in blocks, behind each priority
+pub const SLOW_TARGET_BLOCKS: u16 = 144;
+pub const NORMAL_TARGET_BLOCKS: u16 = 6;
+pub const FAST_TARGET_BLOCKS: u16 = 1;
+
+/// Size of a send spending one segwit input, with and without a change output. Each extra
+/// input adds about 68 vB, so fees for wallets with many small UTXOs are underestimated.
+pub const TX_VSIZE_WITH_CHANGE: u64 = 141;
+pub const TX_VSIZE_NO_CHANGE: u64 = 110;
+
+/// Fee rates in sat/vB for each priority, as reported by the chain source
+#[derive(Debug, Clone, Copy)]
+pub struct FeeEstimates {
+    pub slow: f64,
+    pub normal: f64,
+    pub fast: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeePriority {
+    Slow,
+    Normal,
+    Fast,
+    /// Rate typed by the user
+    Custom,
+}
+
+impl FeePriority {
+    pub const ALL: [FeePriority; 4] = [FeePriority::Slow, FeePriority::Normal, FeePriority::Fast, FeePriority::Custom];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FeePriority::Slow => "Slow (~1 day)",
+            FeePriority::Normal => "Normal (~1 hour)",
+            FeePriority::Fast => "Fast (next block)",
+            FeePriority::Custom => "Custom",
+        }
+    }
+}
+
+impl FeeEstimates {
+    /// Whole sat/vB for `priority`, rounded up so the estimate isn't undercut; None for Custom
+    pub fn sat_per_vb(&self, priority: FeePriority) -> Option<u64> {
+        let rate = match priority {
+            FeePriority::Slow => self.slow,
+            FeePriority::Normal => self.normal,
+            FeePriority::Fast => self.fast,
+            FeePriority::Custom => return None,
+        };
+        Some((rate.ceil() as u64).max(MIN_RELAY_FEE_SAT_PER_VB))
+    }
+}
+
+/// Fee estimates from the chain source, fetched in the background and cached
+pub struct FeeEstimator {
+    chain_source: ChainSource,
+    latest: Arc<Mutex<Option<Result<FeeEstimates, String>>>>,
+    last_fetch: Option<Instant>,
+}
+
+impl FeeEstimator {
+    pub fn new(chain_source: ChainSource) -> Self {
+        Self { chain_source, latest: Arc::new(Mutex::new(None)), last_fetch: None }
+    }
+
+    /// Starts a fetch if the estimates are missing or old
+    pub fn refresh_if_stale(&mut self) {
+        if self.last_fetch.is_some_and(|t| t.elapsed() < REFRESH_INTERVAL) {
+            return;
+        }
+        self.last_fetch = Some(Instant::now());
+
+        let chain_source = self.chain_source.clone();
+        let latest = Arc::clone(&self.latest);
+        std::thread::spawn(move || {
+            let result = chain_source.fee_estimates(&Agent::new());
+            if let Err(e) = &result {
+                warn!("Failed to fetch fee estimates from {}: {}", chain_source, e);
+            }
+            *latest.lock().unwrap() = Some(result);
+        });
+    }
+
+    /// The last estimates, None while the first fetch is running
+    pub fn latest(&self) -> Option<Result<FeeEstimates, String>> {
+        self.latest.lock().unwrap().clone()
+    }
+}
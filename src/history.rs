@@ -0,0 +1,471 @@
+use crate::encryption;
+use crate::jit_fee;
+use crate::tax::{self, CostBasis, TaxEvent};
+use crate::ui_util;
+use ldk_node::lightning::ln::types::{ChannelId, PaymentHash};
+use ldk_node::lightning::ln::channelmanager::PaymentId;
+use ldk_node::payment::{PaymentDetails, PaymentDirection, PaymentKind, PaymentStatus};
+use ldk_node::Node;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{error, info};
+
+const ANNOTATIONS_FILE_NAME: &str = "payment_annotations.json";
+/// Labels given to invoices and payments, keyed by payment hash or payment id
+const LABELS_FILE_NAME: &str = "payment_labels.json";
+const CSV_FILE_NAME: &str = "payment_history.csv";
+
+/// Number of rows shown initially and added by each "Load more"
+const PAGE_SIZE: usize = 50;
+
+/// What we know about a payment beyond ldk-node's own record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PaymentAnnotation {
+    /// BTC price when the payment completed
+    price: f64,
+    stability: bool,
+    /// Stability fee the payment carried
+    #[serde(default)]
+    fee_msats: u64,
+    /// Stable channel a stability payment adjusted
+    #[serde(default)]
+    channel_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DirectionFilter {
+    All,
+    Inbound,
+    Outbound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StatusFilter {
+    All,
+    Pending,
+    Succeeded,
+    Failed,
+}
+
+struct HistoryEntry {
+    id: String,
+    direction: PaymentDirection,
+    status: PaymentStatus,
+    kind: &'static str,
+    amount_msat: Option<u64>,
+    timestamp: u64,
+    price: Option<f64>,
+    stability: bool,
+    fee_msats: u64,
+    channel_id: Option<String>,
+    /// For JIT payments, the opening fee the LSP quoted and the fee it took
+    quoted_lsp_fee_msats: Option<u64>,
+    lsp_fee_msats: Option<u64>,
+    label: Option<String>,
+}
+
+impl HistoryEntry {
+    fn usd(&self) -> Option<f64> {
+        Some(self.amount_msat? as f64 / 100_000_000_000.0 * self.price?)
+    }
+}
+
+/// Lightning payment history, cached from `node.list_payments()` and refreshed only when
+/// invalidated by a payment event
+pub struct PaymentHistory {
+    data_dir: PathBuf,
+    annotations: HashMap<String, PaymentAnnotation>,
+    labels: HashMap<String, String>,
+    entries: Vec<HistoryEntry>,
+    stale: bool,
+    direction_filter: DirectionFilter,
+    status_filter: StatusFilter,
+    /// Shows only payments whose label contains this, ignoring case
+    search: String,
+    visible: usize,
+    export_status: String,
+}
+
+impl PaymentHistory {
+    pub fn load(data_dir: &Path) -> Self {
+        Self {
+            data_dir: data_dir.to_path_buf(),
+            annotations: read_map(&data_dir.join(ANNOTATIONS_FILE_NAME)),
+            labels: read_map(&data_dir.join(LABELS_FILE_NAME)),
+            entries: Vec::new(),
+            stale: true,
+            direction_filter: DirectionFilter::All,
+            status_filter: StatusFilter::All,
+            search: String::new(),
+            visible: PAGE_SIZE,
+            export_status: String::new(),
+        }
+    }
+
+    /// Labels the invoice or payment with hash or id `key`, given as hex
+    pub fn set_label(&mut self, key: String, label: String) {
+        self.labels.insert(key, label);
+        self.save_labels();
+        self.stale = true;
+    }
+
+    /// Gives a received payment the label of the invoice it paid, if that was labelled
+    pub fn label_received(&mut self, payment_id: &PaymentId, payment_hash: &PaymentHash) {
+        let id = hex::encode(payment_id.0);
+        if self.labels.contains_key(&id) {
+            return;
+        }
+        if let Some(label) = self.labels.get(&hex::encode(payment_hash.0)).cloned() {
+            self.set_label(id, label);
+        }
+    }
+
+    /// Records the price at completion, the stable channel if the payment was a stability
+    /// adjustment, and the stability fee it carried
+    pub fn annotate(&mut self, payment_id: &PaymentId, price: f64, stability: Option<&ChannelId>, fee_msats: u64) {
+        let annotation = PaymentAnnotation {
+            price,
+            stability: stability.is_some(),
+            fee_msats,
+            channel_id: stability.map(|id| id.to_string()),
+        };
+        self.annotations.insert(hex::encode(payment_id.0), annotation);
+        self.save_annotations();
+        self.stale = true;
+    }
+
+    /// Marks the cached list as out of date; it is re-read the next time it is drawn
+    pub fn invalidate(&mut self) {
+        self.stale = true;
+    }
+
+    fn save_annotations(&self) {
+        let path = self.data_dir.join(ANNOTATIONS_FILE_NAME);
+        match serde_json::to_string(&self.annotations) {
+            Ok(json) => {
+                if let Err(e) = encryption::write_state_file(&path, &json) {
+                    error!("Error writing {}: {}", path.display(), e);
+                }
+            }
+            Err(e) => error!("Error serializing payment annotations: {}", e),
+        }
+    }
+
+    fn save_labels(&self) {
+        let path = self.data_dir.join(LABELS_FILE_NAME);
+        match serde_json::to_string(&self.labels) {
+            Ok(json) => {
+                if let Err(e) = encryption::write_state_file(&path, &json) {
+                    error!("Error writing {}: {}", path.display(), e);
+                }
+            }
+            Err(e) => error!("Error serializing payment labels: {}", e),
+        }
+    }
+
+    fn refresh(&mut self, node: &Node) {
+        let mut entries: Vec<HistoryEntry> = node
+            .list_payments()
+            .into_iter()
+            .filter(|p| !matches!(p.kind, PaymentKind::Onchain { .. }))
+            .map(|p| self.entry_from(p))
+            .collect();
+        entries.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        self.entries = entries;
+        self.stale = false;
+    }
+
+    fn entry_from(&self, payment: PaymentDetails) -> HistoryEntry {
+        let id = hex::encode(payment.id.0);
+        let annotation = self.annotations.get(&id);
+        let (quoted_lsp_fee_msats, lsp_fee_msats) = jit_fee::lsp_fees(&payment.kind).unwrap_or((None, None));
+        HistoryEntry {
+            kind: kind_label(&payment.kind),
+            direction: payment.direction,
+            status: payment.status,
+            amount_msat: payment.amount_msat,
+            timestamp: payment.latest_update_timestamp,
+            price: annotation.map(|a| a.price),
+            stability: annotation.map_or(false, |a| a.stability),
+            fee_msats: annotation.map_or(0, |a| a.fee_msats),
+            channel_id: annotation.and_then(|a| a.channel_id.clone()),
+            quoted_lsp_fee_msats,
+            lsp_fee_msats,
+            label: self.labels.get(&id).cloned(),
+            id,
+        }
+    }
+
+    fn matches_filters(&self, entry: &HistoryEntry) -> bool {
+        let direction = match self.direction_filter {
+            DirectionFilter::All => true,
+            DirectionFilter::Inbound => entry.direction == PaymentDirection::Inbound,
+            DirectionFilter::Outbound => entry.direction == PaymentDirection::Outbound,
+        };
+        let status = match self.status_filter {
+            StatusFilter::All => true,
+            StatusFilter::Pending => entry.status == PaymentStatus::Pending,
+            StatusFilter::Succeeded => entry.status == PaymentStatus::Succeeded,
+            StatusFilter::Failed => entry.status == PaymentStatus::Failed,
+        };
+        let search = self.search.trim().to_lowercase();
+        let label = search.is_empty()
+            || entry.label.as_ref().map_or(false, |label| label.to_lowercase().contains(&search));
+        direction && status && label
+    }
+
+    /// Writes the filtered history to `payment_history.csv` in the data directory
+    fn export_csv(&self) -> std::io::Result<PathBuf> {
+        let mut csv = String::from("payment_id,timestamp,direction,kind,status,amount_sats,usd,price,stability,stability_fee_msats,lsp_fee_quoted_msats,lsp_fee_msats,label\n");
+        for entry in self.entries.iter().filter(|e| self.matches_filters(e)) {
+            let _ = writeln!(
+                csv,
+                "{},{},{:?},{},{:?},{},{},{},{},{},{},{},\"{}\"",
+                entry.id,
+                entry.timestamp,
+                entry.direction,
+                entry.kind,
+                entry.status,
+                entry.amount_msat.map(|m| (m / 1000).to_string()).unwrap_or_default(),
+                entry.usd().map(|u| format!("{:.2}", u)).unwrap_or_default(),
+                entry.price.map(|p| format!("{:.2}", p)).unwrap_or_default(),
+                entry.stability,
+                entry.fee_msats,
+                entry.quoted_lsp_fee_msats.map(|m| m.to_string()).unwrap_or_default(),
+                entry.lsp_fee_msats.map(|m| m.to_string()).unwrap_or_default(),
+                entry.label.as_deref().unwrap_or_default().replace('"', "\"\""),
+            );
+        }
+
+        let path = self.data_dir.join(CSV_FILE_NAME);
+        std::fs::write(&path, csv)?;
+        info!("Exported payment history to {}", path.display());
+        Ok(path)
+    }
+
+    /// Succeeded payments with a recorded price, whatever the filters. Returns how many
+    /// succeeded payments were left out for lack of a price.
+    fn tax_events(&self) -> (Vec<TaxEvent>, usize) {
+        let mut unpriced = 0;
+        let events = self
+            .entries
+            .iter()
+            .filter(|e| e.status == PaymentStatus::Succeeded)
+            .filter_map(|entry| {
+                let (Some(amount_msats), Some(price)) = (entry.amount_msat, entry.price.filter(|p| *p > 0.0)) else {
+                    unpriced += 1;
+                    return None;
+                };
+                Some(TaxEvent {
+                    timestamp: entry.timestamp,
+                    inbound: entry.direction == PaymentDirection::Inbound,
+                    stability: entry.stability,
+                    amount_msats,
+                    price,
+                    channel_id: entry.channel_id.clone(),
+                })
+            })
+            .collect();
+        (events, unpriced)
+    }
+
+    fn export_tax(&self, basis: &CostBasis) -> Result<String, String> {
+        let (events, unpriced) = self.tax_events();
+        let path = tax::export(&self.data_dir, &tax::build_rows(&events, basis))?;
+        let mut status = format!("Saved to {} (and .json)", path.display());
+        if unpriced > 0 {
+            let _ = write!(status, "; {} payments without a recorded price left out", unpriced);
+        }
+        Ok(status)
+    }
+
+    /// Collapsible panel with filters, a paged payment table, CSV export and an export of
+    /// realized gains against `basis` for tax
+    pub fn show(&mut self, ui: &mut egui::Ui, node: &Node, basis: &CostBasis) {
+        egui::CollapsingHeader::new("History").show(ui, |ui| {
+            if self.stale {
+                self.refresh(node);
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("Direction:");
+                for (filter, label) in [
+                    (DirectionFilter::All, "All"),
+                    (DirectionFilter::Inbound, "In"),
+                    (DirectionFilter::Outbound, "Out"),
+                ] {
+                    if ui.selectable_label(self.direction_filter == filter, label).clicked() {
+                        self.direction_filter = filter;
+                        self.visible = PAGE_SIZE;
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Status:");
+                for (filter, label) in [
+                    (StatusFilter::All, "All"),
+                    (StatusFilter::Pending, "Pending"),
+                    (StatusFilter::Succeeded, "Succeeded"),
+                    (StatusFilter::Failed, "Failed"),
+                ] {
+                    if ui.selectable_label(self.status_filter == filter, label).clicked() {
+                        self.status_filter = filter;
+                        self.visible = PAGE_SIZE;
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Label:");
+                if ui.add(egui::TextEdit::singleline(&mut self.search).hint_text("search")).changed() {
+                    self.visible = PAGE_SIZE;
+                }
+            });
+
+            let filtered: Vec<&HistoryEntry> = self.entries.iter().filter(|e| self.matches_filters(e)).collect();
+            if filtered.is_empty() {
+                ui.label("No payments yet.");
+            }
+
+            egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                egui::Grid::new("payment_history").striped(true).num_columns(5).show(ui, |ui| {
+                    for entry in filtered.iter().take(self.visible) {
+                        let arrow = match entry.direction {
+                            PaymentDirection::Inbound => "⬇",
+                            PaymentDirection::Outbound => "⬆",
+                        };
+                        ui.label(format!("{} {}", arrow, entry.kind));
+                        ui.label(match entry.amount_msat {
+                            Some(msat) => format!("{} sats", msat / 1000),
+                            None => "-".to_string(),
+                        });
+                        ui.label(entry.usd().map(|u| format!("${:.2}", u)).unwrap_or_else(|| "-".to_string()));
+                        ui.label(format!("{:?}", entry.status));
+                        ui.label(format_timestamp(entry.timestamp));
+                        if let Some(label) = &entry.label {
+                            ui.label(egui::RichText::new(label).italics());
+                        }
+                        if entry.stability {
+                            ui.colored_label(egui::Color32::LIGHT_BLUE, "stability");
+                        }
+                        if let Some(fee) = entry.lsp_fee_msats {
+                            let label = match entry.quoted_lsp_fee_msats {
+                                Some(quoted) if quoted != fee => {
+                                    format!("LSP fee {} sats (quoted {})", fee / 1000, quoted / 1000)
+                                }
+                                _ => format!("LSP fee {} sats", fee / 1000),
+                            };
+                            ui.colored_label(egui::Color32::GRAY, label);
+                        }
+                        ui_util::copy_button(ui, egui::Button::new("Copy ID").small(), || entry.id.clone())
+                            .on_hover_text(&entry.id);
+                        ui.end_row();
+                    }
+                });
+
+                if filtered.len() > self.visible && ui.button("Load more").clicked() {
+                    self.visible += PAGE_SIZE;
+                }
+            });
+
+            ui.horizontal(|ui| {
+                if ui.button("Export CSV").clicked() {
+                    self.export_status = match self.export_csv() {
+                        Ok(path) => format!("Saved to {}", path.display()),
+                        Err(e) => format!("Export failed: {}", e),
+                    };
+                }
+                if ui.button("Export for taxes").clicked() {
+                    self.export_status = self.export_tax(basis).unwrap_or_else(|e| format!("Export failed: {}", e));
+                }
+                if ui.button("Refresh").clicked() {
+                    self.stale = true;
+                }
+            });
+            if !self.export_status.is_empty() {
+                ui.label(&self.export_status);
+            }
+        });
+    }
+}
+
+/// A JSON map saved with `encryption::write_state_file`, or an empty one if there is none
+fn read_map<T: serde::de::DeserializeOwned>(path: &Path) -> HashMap<String, T> {
+    if !path.exists() {
+        return HashMap::new();
+    }
+    match encryption::read_state_file(path)
+        .map_err(|e| e.to_string())
+        .and_then(|contents| serde_json::from_str(&contents).map_err(|e| e.to_string()))
+    {
+        Ok(map) => map,
+        Err(e) => {
+            error!("Error reading {}: {}", path.display(), e);
+            HashMap::new()
+        }
+    }
+}
+
+/// True if `payment_id` was paid to one of our BOLT12 offers
+pub fn is_offer_payment(node: &Node, payment_id: &PaymentId) -> bool {
+    node.payment(payment_id).map_or(false, |p| matches!(p.kind, PaymentKind::Bolt12Offer { .. }))
+}
+
+fn kind_label(kind: &PaymentKind) -> &'static str {
+    match kind {
+        PaymentKind::Onchain { .. } => "on-chain",
+        PaymentKind::Bolt11 { .. } => "bolt11",
+        PaymentKind::Bolt11Jit { .. } => "bolt11 jit",
+        PaymentKind::Bolt12Offer { .. } => "bolt12 offer",
+        PaymentKind::Bolt12Refund { .. } => "bolt12 refund",
+        PaymentKind::Spontaneous { .. } => "keysend",
+    }
+}
+
+/// Calendar date (UTC) of a unix timestamp, e.g. "2025-03-14"
+pub fn format_date(timestamp: i64) -> String {
+    // Days since 1970-01-01 to a proleptic Gregorian date, counting eras of 400 years
+    // from 0000-03-01 so the leap day falls at the end of each year
+    let days = timestamp.div_euclid(86_400) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Unix timestamp of midnight UTC on a date given as "YYYY-MM-DD", the inverse of `format_date`
+pub fn parse_date(date: &str) -> Option<i64> {
+    let mut parts = date.trim().splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_index = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some((era * 146_097 + day_of_era - 719_468) * 86_400)
+}
+
+/// Age of a unix timestamp, e.g. "5m ago"
+pub fn format_timestamp(timestamp: u64) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let age = now.saturating_sub(timestamp);
+    match age {
+        0..=59 => format!("{}s ago", age),
+        60..=3599 => format!("{}m ago", age / 60),
+        3600..=86399 => format!("{}h ago", age / 3600),
+        _ => format!("{}d ago", age / 86400),
+    }
+}
@@ -0,0 +1,72 @@
+use ldk_node::bitcoin::hashes::Hash;
+use ldk_node::lightning::ln::channelmanager::PaymentId;
+use ldk_node::lightning_invoice::Bolt11Invoice;
+use ldk_node::payment::PaymentKind;
+use ldk_node::Node;
+
+/// Opening fee the LSP quoted for a JIT channel invoice. ldk-node agrees the fee with the
+/// LSP when it creates the invoice; the LSP skims it from the payment before forwarding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JitFeeQuote {
+    pub payment_id: PaymentId,
+    /// What the payer sends
+    pub amount_msats: u64,
+    pub fee_msats: u64,
+}
+
+impl JitFeeQuote {
+    /// The quote behind `invoice`, None if it isn't a JIT invoice with a fixed amount
+    pub fn for_invoice(node: &Node, invoice: &Bolt11Invoice) -> Option<Self> {
+        let amount_msats = invoice.amount_milli_satoshis()?;
+        let payment_id = PaymentId(invoice.payment_hash().to_byte_array());
+        let (quoted, _) = lsp_fees(&node.payment(&payment_id)?.kind)?;
+        Some(Self { payment_id, amount_msats, fee_msats: quoted? })
+    }
+
+    /// What lands in the channel once the LSP takes its fee
+    pub fn received_msats(&self) -> u64 {
+        self.amount_msats.saturating_sub(self.fee_msats)
+    }
+
+    /// Table of amount sent, LSP fee and amount received, in sats and USD
+    pub fn show(&self, ui: &mut egui::Ui, btc_price: f64) {
+        egui::Grid::new("jit_fee_quote").num_columns(2).show(ui, |ui| {
+            for (label, msats) in [
+                ("Amount to send:", self.amount_msats),
+                ("LSP channel fee:", self.fee_msats),
+                ("Lands in your channel:", self.received_msats()),
+            ] {
+                ui.label(label);
+                ui.label(format_sats_usd(msats, btc_price));
+                ui.end_row();
+            }
+        });
+    }
+
+    /// How the fee the LSP took compares with the quote
+    pub fn reconcile(&self, skimmed_msats: u64) -> String {
+        if skimmed_msats == self.fee_msats {
+            format!("LSP took the quoted fee of {} sats", skimmed_msats / 1000)
+        } else {
+            format!("LSP took {} sats; it quoted {} sats", skimmed_msats / 1000, self.fee_msats / 1000)
+        }
+    }
+}
+
+/// Quoted and actually skimmed LSP fees of a JIT payment, None for other kinds
+pub fn lsp_fees(kind: &PaymentKind) -> Option<(Option<u64>, Option<u64>)> {
+    match kind {
+        PaymentKind::Bolt11Jit { lsp_fee_limits, counterparty_skimmed_fee_msat, .. } => {
+            Some((lsp_fee_limits.max_total_opening_fee_msat, *counterparty_skimmed_fee_msat))
+        }
+        _ => None,
+    }
+}
+
+fn format_sats_usd(msats: u64, btc_price: f64) -> String {
+    let sats = msats / 1000;
+    if btc_price <= 0.0 {
+        return format!("{} sats", sats);
+    }
+    format!("{} sats (${:.2})", sats, sats as f64 / 100_000_000.0 * btc_price)
+}
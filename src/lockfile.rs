@@ -0,0 +1,91 @@
+use fs2::FileExt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+pub const LOCK_FILE_NAME: &str = "app.lock";
+
+/// Exclusive advisory lock on a node data directory. The OS drops the lock when the
+/// holding process exits, so a crash never leaves the directory locked; the PID written
+/// into the file is only used to say who holds it. The file itself stays behind: deleting
+/// it on drop would let another instance lock a fresh file while a third still held the
+/// old one.
+pub struct DataDirLock {
+    file: File,
+}
+
+#[derive(Debug)]
+pub enum LockError {
+    HeldBy(Option<u32>),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for LockError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LockError::HeldBy(Some(pid)) => {
+                write!(f, "data directory is already in use by another instance (PID {})", pid)
+            }
+            LockError::HeldBy(None) => write!(f, "data directory is already in use by another instance"),
+            LockError::Io(e) => write!(f, "failed to lock data directory: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LockError {}
+
+impl DataDirLock {
+    pub fn acquire(data_dir: &Path) -> Result<Self, LockError> {
+        fs::create_dir_all(data_dir).map_err(LockError::Io)?;
+        let path = data_dir.join(LOCK_FILE_NAME);
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(LockError::Io)?;
+
+        if file.try_lock_exclusive().is_err() {
+            let mut contents = String::new();
+            let _ = file.read_to_string(&mut contents);
+            return Err(LockError::HeldBy(contents.trim().parse().ok()));
+        }
+
+        file.set_len(0).map_err(LockError::Io)?;
+        file.seek(SeekFrom::Start(0)).map_err(LockError::Io)?;
+        write!(file, "{}", std::process::id()).map_err(LockError::Io)?;
+        file.sync_all().map_err(LockError::Io)?;
+
+        Ok(Self { file })
+    }
+}
+
+impl Drop for DataDirLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_acquire_fails_until_the_first_is_dropped() {
+        let data_dir = std::env::temp_dir().join(format!("stable-channels-lock-{}", std::process::id()));
+
+        let lock = DataDirLock::acquire(&data_dir).unwrap();
+        match DataDirLock::acquire(&data_dir) {
+            Err(LockError::HeldBy(pid)) => assert_eq!(pid, Some(std::process::id())),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("data directory locked twice"),
+        }
+
+        drop(lock);
+        assert!(data_dir.join(LOCK_FILE_NAME).exists());
+        assert!(DataDirLock::acquire(&data_dir).is_ok());
+        let _ = fs::remove_dir_all(&data_dir);
+    }
+}
@@ -0,0 +1,90 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::sync::Mutex;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter, Layer};
+
+/// Number of recent log records kept for the in-app log panel
+const LOG_BUFFER_LEN: usize = 500;
+
+/// Filter used when RUST_LOG is not set
+const DEFAULT_FILTER: &str = "info";
+
+lazy_static::lazy_static! {
+    static ref LOG_BUFFER: Mutex<VecDeque<String>> = Mutex::new(VecDeque::with_capacity(LOG_BUFFER_LEN));
+}
+
+/// Installs the global subscriber: console output filtered by RUST_LOG
+/// (e.g. `RUST_LOG=stable_channels::stable=debug`) plus the in-memory ring buffer.
+pub fn init() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer())
+        .with(BufferLayer)
+        .init();
+}
+
+/// The most recent log records, oldest first
+pub fn recent_logs() -> Vec<String> {
+    LOG_BUFFER.lock().unwrap().iter().cloned().collect()
+}
+
+/// Collapsible panel listing the most recent log records
+pub fn show_logs_panel(ui: &mut egui::Ui) {
+    egui::CollapsingHeader::new("Logs").show(ui, |ui| {
+        egui::ScrollArea::vertical()
+            .max_height(200.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for line in recent_logs() {
+                    ui.label(egui::RichText::new(line).monospace().size(11.0));
+                }
+            });
+    });
+}
+
+struct BufferLayer;
+
+impl<S> Layer<S> for BufferLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut line = format!("{:<5} {}", metadata.level(), metadata.target());
+
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                let _ = write!(line, " {}", span.name());
+            }
+        }
+
+        let mut visitor = LineVisitor(String::new());
+        event.record(&mut visitor);
+        let _ = write!(line, ":{}", visitor.0);
+
+        let mut buffer = LOG_BUFFER.lock().unwrap();
+        if buffer.len() == LOG_BUFFER_LEN {
+            buffer.pop_front();
+        }
+        buffer.push_back(line);
+    }
+}
+
+struct LineVisitor(String);
+
+impl Visit for LineVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, " {:?}", value);
+        } else {
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+}
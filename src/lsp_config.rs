@@ -0,0 +1,252 @@
+use crate::encryption;
+use ldk_node::bitcoin::secp256k1::PublicKey;
+use ldk_node::lightning::ln::msgs::SocketAddress;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tracing::{error, warn};
+
+const LSP_CONFIG_FILE_NAME: &str = "lsp_config.json";
+
+pub const DEFAULT_LSP_PUBKEY: &str = "02d3db21cb7de67f543c6bfa576e5122109325e308013d11cdfda18c6ce4f91a89";
+pub const DEFAULT_LSP_ADDRESS: &str = "54.210.112.22:9737";
+/// Most the LSP may charge for opening a JIT channel unless the profile says otherwise
+pub const DEFAULT_MAX_JIT_FEE_SATS: u64 = 10_000;
+
+/// An LSP the user app can open channels with and peg against
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LspProfile {
+    pub name: String,
+    pub pubkey: String,
+    pub address: String,
+    /// LSPS2 token the LSP issued us for JIT channels; blank if it requires none
+    #[serde(default)]
+    pub token: String,
+    /// Most we'll pay the LSP to open a JIT channel; None for `DEFAULT_MAX_JIT_FEE_SATS`
+    #[serde(default)]
+    pub max_jit_fee_sats: Option<u64>,
+    /// Accept the LSP's channels as usable before their funding confirms. The LSP could
+    /// double-spend the funding, so only for LSPs we trust.
+    #[serde(default)]
+    pub trust_zero_conf: bool,
+}
+
+impl LspProfile {
+    fn default_profile() -> Self {
+        Self {
+            name: "Default".to_string(),
+            pubkey: DEFAULT_LSP_PUBKEY.to_string(),
+            address: DEFAULT_LSP_ADDRESS.to_string(),
+            token: String::new(),
+            max_jit_fee_sats: None,
+            trust_zero_conf: false,
+        }
+    }
+
+    /// The parsed node id and address, or why they don't parse
+    pub fn endpoint(&self) -> Result<(PublicKey, SocketAddress), String> {
+        let pubkey = PublicKey::from_str(self.pubkey.trim()).map_err(|_| "Invalid LSP pubkey".to_string())?;
+        let address = SocketAddress::from_str(self.address.trim()).map_err(|_| "Invalid LSP address".to_string())?;
+        Ok((pubkey, address))
+    }
+
+    /// The JIT channel token, if one is set
+    pub fn lsps2_token(&self) -> Option<String> {
+        Some(self.token.trim().to_string()).filter(|t| !t.is_empty())
+    }
+
+    pub fn max_jit_fee_msats(&self) -> u64 {
+        self.max_jit_fee_sats.unwrap_or(DEFAULT_MAX_JIT_FEE_SATS) * 1000
+    }
+
+    /// Whether switching from `other` to this profile needs the node rebuilt. The fee cap
+    /// is read per invoice, so it applies right away.
+    pub fn needs_restart(&self, other: &LspProfile) -> bool {
+        self.pubkey != other.pubkey
+            || self.address != other.address
+            || self.token != other.token
+            || self.trust_zero_conf != other.trust_zero_conf
+    }
+}
+
+impl std::fmt::Display for LspProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} ({}@{})", self.name, self.pubkey, self.address)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LspConfigRecord {
+    profiles: Vec<LspProfile>,
+    selected: usize,
+}
+
+/// Saved LSP profiles and which one the node is built with on the next start
+pub struct LspConfig {
+    path: PathBuf,
+    pub profiles: Vec<LspProfile>,
+    pub selected: usize,
+}
+
+impl LspConfig {
+    /// Loads the saved profiles, or a single default profile if none are saved
+    pub fn load(data_dir: &Path) -> Self {
+        let path = data_dir.join(LSP_CONFIG_FILE_NAME);
+        let record = if path.exists() {
+            match encryption::read_state_file(&path).map(|s| serde_json::from_str::<LspConfigRecord>(&s)) {
+                Ok(Ok(record)) if !record.profiles.is_empty() => Some(record),
+                Ok(Ok(_)) => None,
+                Ok(Err(e)) => {
+                    error!("Ignoring unreadable {}: {}", path.display(), e);
+                    None
+                }
+                Err(e) => {
+                    error!("Failed to read {}: {}", path.display(), e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let record = record
+            .unwrap_or_else(|| LspConfigRecord { profiles: vec![LspProfile::default_profile()], selected: 0 });
+        let selected = record.selected.min(record.profiles.len() - 1);
+        Self { path, profiles: record.profiles, selected }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let record = LspConfigRecord { profiles: self.profiles.clone(), selected: self.selected };
+        let json = serde_json::to_string_pretty(&record).map_err(|e| e.to_string())?;
+        encryption::write_state_file(&self.path, &json).map_err(|e| e.to_string())
+    }
+
+    pub fn active(&self) -> &LspProfile {
+        &self.profiles[self.selected]
+    }
+
+    /// The selected profile, or the default LSP if its endpoint doesn't parse
+    pub fn active_or_default(&self) -> LspProfile {
+        match self.active().endpoint() {
+            Ok(_) => self.active().clone(),
+            Err(e) => {
+                warn!("{} in profile {}; using the default LSP", e, self.active().name);
+                LspProfile::default_profile()
+            }
+        }
+    }
+
+    /// The endpoint of `active_or_default`
+    pub fn active_endpoint(&self) -> (PublicKey, SocketAddress) {
+        self.active_or_default().endpoint().unwrap()
+    }
+}
+
+/// Editing form for the saved LSP profiles
+pub struct LspSettingsFlow {
+    editing: usize,
+    draft: LspProfile,
+    error: Option<String>,
+}
+
+impl LspSettingsFlow {
+    pub fn new(config: &LspConfig) -> Self {
+        Self { editing: config.selected, draft: config.active().clone(), error: None }
+    }
+
+    /// Draws the form. `in_use` is the profile the running node was built with. Returns
+    /// the endpoint to test when the user asks for a connection test.
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        config: &mut LspConfig,
+        in_use: &LspProfile,
+    ) -> Option<(PublicKey, SocketAddress)> {
+        let mut test = None;
+
+        ui.horizontal(|ui| {
+            ui.label("Profile:");
+            let before = self.editing;
+            egui::ComboBox::from_id_salt("lsp_profile")
+                .selected_text(config.profiles[self.editing].name.clone())
+                .show_ui(ui, |ui| {
+                    for (i, profile) in config.profiles.iter().enumerate() {
+                        ui.selectable_value(&mut self.editing, i, profile.name.clone());
+                    }
+                });
+            if self.editing != before {
+                self.draft = config.profiles[self.editing].clone();
+                self.error = None;
+            }
+            if ui.button("New").clicked() {
+                config.profiles.push(LspProfile { name: format!("LSP {}", config.profiles.len() + 1), ..Default::default() });
+                self.editing = config.profiles.len() - 1;
+                self.draft = config.profiles[self.editing].clone();
+                self.error = None;
+            }
+        });
+
+        egui::Grid::new("lsp_profile_fields").num_columns(2).show(ui, |ui| {
+            ui.label("Name:");
+            ui.text_edit_singleline(&mut self.draft.name);
+            ui.end_row();
+            ui.label("Pubkey:");
+            ui.text_edit_singleline(&mut self.draft.pubkey);
+            ui.end_row();
+            ui.label("Address (host:port):");
+            ui.text_edit_singleline(&mut self.draft.address);
+            ui.end_row();
+            ui.label("JIT channel token:");
+            ui.add(egui::TextEdit::singleline(&mut self.draft.token).hint_text("if the LSP requires one"));
+            ui.end_row();
+            ui.label("Max JIT channel fee (sats):");
+            let mut max_fee = self.draft.max_jit_fee_sats.unwrap_or(DEFAULT_MAX_JIT_FEE_SATS);
+            if ui.add(egui::DragValue::new(&mut max_fee)).changed() {
+                self.draft.max_jit_fee_sats = Some(max_fee);
+            }
+            ui.end_row();
+            ui.label("Zero-conf channels:");
+            ui.checkbox(&mut self.draft.trust_zero_conf, "Use before confirmation")
+                .on_hover_text("Faster onboarding, but the LSP could reverse an unconfirmed channel");
+            ui.end_row();
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Save and use").clicked() {
+                match self.draft.endpoint() {
+                    Ok(_) => {
+                        config.profiles[self.editing] = self.draft.clone();
+                        config.selected = self.editing;
+                        self.error = config.save().err();
+                    }
+                    Err(e) => self.error = Some(e),
+                }
+            }
+            if ui.button("Test connection").clicked() {
+                match self.draft.endpoint() {
+                    Ok(endpoint) => test = Some(endpoint),
+                    Err(e) => self.error = Some(e),
+                }
+            }
+            let removable = config.profiles.len() > 1 && self.editing != config.selected;
+            if ui.add_enabled(removable, egui::Button::new("Delete")).clicked() {
+                config.profiles.remove(self.editing);
+                if config.selected > self.editing {
+                    config.selected -= 1;
+                }
+                self.editing = config.selected;
+                self.draft = config.active().clone();
+                self.error = config.save().err();
+            }
+        });
+
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+        if config.active().needs_restart(in_use) {
+            ui.colored_label(egui::Color32::YELLOW, format!("Restart required to switch to {}", config.active().name));
+        }
+
+        test
+    }
+}
@@ -0,0 +1,353 @@
+use crate::encryption;
+use crate::worker::{self, AppCommand, CommandKind, Worker};
+use ldk_node::bitcoin::secp256k1::PublicKey;
+use ldk_node::lightning::ln::channelmanager::PaymentId;
+use ldk_node::lightning::ln::types::ChannelId;
+use ldk_node::lightning_invoice::Bolt11Invoice;
+use ldk_node::lightning_liquidity::lsps1::msgs::{OrderId, PaymentState};
+use ldk_node::liquidity::LSPS1OrderStatus;
+use ldk_node::NodeError;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{error, info};
+
+/// An order in progress, so a restart can resume polling it
+const LSPS1_ORDER_FILE_NAME: &str = "lsps1_order.json";
+
+/// How often an open order's status is re-read from the LSP
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Blocks the LSP keeps the channel open for, about two weeks
+const CHANNEL_EXPIRY_BLOCKS: u32 = 2016;
+
+/// Channel order placed with the LSP over LSPS1
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingOrder {
+    pub order_id: String,
+    pub lsp_balance_sat: u64,
+    pub client_balance_sat: u64,
+    pub fee_total_sat: u64,
+    /// Fee plus the client balance; what the invoice asks for
+    pub order_total_sat: u64,
+    pub invoice: String,
+    /// Unix time the invoice stops being payable
+    pub invoice_expires_at: i64,
+    /// We've sent the payment, whether or not the LSP has seen it yet
+    pub paid: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderState {
+    AwaitingPayment,
+    /// The LSP has the payment and is opening the channel
+    Paid,
+    /// The channel's funding transaction is out; waiting for it to confirm
+    ChannelFunded { funding_outpoint: String },
+    /// Never paid before the invoice expired
+    Expired,
+    /// The LSP gave up on the channel and refunded the payment
+    Refunded,
+    /// The LSP offers no Lightning payment for the order
+    Unpayable,
+}
+
+/// What the app needs to do after the screen was drawn
+pub enum OrderAction {
+    Close,
+    /// Make the ordered channel the stable channel
+    MakeStable(ChannelId),
+}
+
+/// "Buy inbound liquidity" screen and the order it tracks
+pub struct Lsps1OrderFlow {
+    path: PathBuf,
+    pub lsp_balance: String,
+    pub client_balance: String,
+    order: Option<PendingOrder>,
+    state: Option<OrderState>,
+    message: Option<String>,
+    last_poll: Option<Instant>,
+    /// Channel from an order that became ready, offered for stable designation
+    ready_channel: Option<ChannelId>,
+}
+
+impl Lsps1OrderFlow {
+    /// Loads an order left over from a previous run; polling resumes on the next `poll_if_due`
+    pub fn load(data_dir: &Path) -> Self {
+        let path = data_dir.join(LSPS1_ORDER_FILE_NAME);
+        let order = if path.exists() {
+            match encryption::read_state_file(&path).map(|s| serde_json::from_str::<PendingOrder>(&s)) {
+                Ok(Ok(order)) => {
+                    info!("Resuming LSPS1 order {}", order.order_id);
+                    Some(order)
+                }
+                Ok(Err(e)) => {
+                    error!("Ignoring unreadable {}: {}", path.display(), e);
+                    None
+                }
+                Err(e) => {
+                    error!("Failed to read {}: {}", path.display(), e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        Self {
+            path,
+            lsp_balance: "100000".to_string(),
+            client_balance: "0".to_string(),
+            order,
+            state: None,
+            message: None,
+            last_poll: None,
+            ready_channel: None,
+        }
+    }
+
+    fn save(&self) {
+        let Some(order) = &self.order else {
+            return;
+        };
+        let result = serde_json::to_string_pretty(order)
+            .map_err(|e| e.to_string())
+            .and_then(|json| encryption::write_state_file(&self.path, &json).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            error!("Failed to save {}: {}", self.path.display(), e);
+        }
+    }
+
+    /// Forgets the order once it can no longer produce a channel
+    fn finish(&mut self) {
+        self.order = None;
+        if let Err(e) = fs::remove_file(&self.path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                error!("Failed to remove {}: {}", self.path.display(), e);
+            }
+        }
+    }
+
+    /// An order is open and may still produce a channel
+    pub fn has_open_order(&self) -> bool {
+        self.order.is_some()
+            && !matches!(self.state, Some(OrderState::Expired | OrderState::Refunded | OrderState::Unpayable))
+    }
+
+    fn request_quote(&mut self, worker: &mut Worker) {
+        let lsp_balance_sat = match self.lsp_balance.trim().parse::<u64>() {
+            Ok(sats) if sats > 0 => sats,
+            _ => {
+                self.message = Some("Enter the inbound liquidity to buy, in sats".to_string());
+                return;
+            }
+        };
+        let Ok(client_balance_sat) = self.client_balance.trim().parse::<u64>() else {
+            self.message = Some("Invalid balance on your side".to_string());
+            return;
+        };
+        worker.submit(AppCommand::RequestLsps1Channel {
+            lsp_balance_sat,
+            client_balance_sat,
+            channel_expiry_blocks: CHANNEL_EXPIRY_BLOCKS,
+        });
+        self.message = Some("Requesting a quote from the LSP...".to_string());
+    }
+
+    /// Re-reads the order from the LSP while it's open
+    pub fn poll_if_due(&mut self, worker: &mut Worker) {
+        let Some(order) = &self.order else {
+            return;
+        };
+        if !self.has_open_order() || worker.is_busy(CommandKind::Lsps1Order) {
+            return;
+        }
+        if self.last_poll.is_some_and(|t| t.elapsed() < POLL_INTERVAL) {
+            return;
+        }
+        self.last_poll = Some(Instant::now());
+        worker.submit(AppCommand::CheckLsps1Order { order_id: OrderId(order.order_id.clone()) });
+    }
+
+    /// Takes a quote or a status update from the LSP
+    pub fn apply_status(&mut self, result: Result<LSPS1OrderStatus, NodeError>) {
+        let status = match result {
+            Ok(status) => status,
+            Err(e) => {
+                self.message = Some(match &self.order {
+                    Some(_) => format!("Couldn't reach the LSP for the order status: {}. Retrying", e),
+                    None => format!("The LSP didn't give a quote: {}", e),
+                });
+                return;
+            }
+        };
+
+        let paid = self.order.as_ref().is_some_and(|o| o.paid);
+        let bolt11 = status.payment_options.bolt11.as_ref();
+        self.order = Some(PendingOrder {
+            order_id: status.order_id.0.clone(),
+            lsp_balance_sat: status.order_params.lsp_balance_sat,
+            client_balance_sat: status.order_params.client_balance_sat,
+            fee_total_sat: bolt11.map_or(0, |b| b.fee_total_sat),
+            order_total_sat: bolt11.map_or(0, |b| b.order_total_sat),
+            invoice: bolt11.map(|b| b.invoice.to_string()).unwrap_or_default(),
+            invoice_expires_at: bolt11.map_or(0, |b| b.expires_at.timestamp()),
+            paid,
+        });
+
+        let state = order_state(&status, paid);
+        self.message = Some(match &state {
+            OrderState::AwaitingPayment => "Quote received; pay the invoice to place the order".to_string(),
+            OrderState::Paid => "Paid; waiting for the LSP to open the channel".to_string(),
+            OrderState::ChannelFunded { funding_outpoint } => {
+                format!("Channel funded ({}); waiting for confirmations", funding_outpoint)
+            }
+            OrderState::Expired => "The order expired before it was paid; request a new quote".to_string(),
+            OrderState::Refunded => {
+                "The LSP couldn't open the channel and refunded the payment; request a new quote".to_string()
+            }
+            OrderState::Unpayable => "The LSP offers no Lightning invoice for this order".to_string(),
+        });
+        match state {
+            OrderState::Expired | OrderState::Refunded | OrderState::Unpayable => self.finish(),
+            _ => self.save(),
+        }
+        self.state = Some(state);
+    }
+
+    pub fn apply_payment(&mut self, result: Result<PaymentId, NodeError>) {
+        match result {
+            Ok(_) => {
+                if let Some(order) = self.order.as_mut() {
+                    order.paid = true;
+                }
+                self.save();
+                self.state = Some(OrderState::Paid);
+                self.message = Some("Payment sent; waiting for the LSP to open the channel".to_string());
+                self.last_poll = None;
+            }
+            Err(e) => self.message = Some(format!("Paying for the order failed: {}", e)),
+        }
+    }
+
+    /// Called on `ChannelReady`. Completes a paid order if the channel is with `lsp_pubkey`.
+    pub fn channel_ready(&mut self, channel_id: ChannelId, counterparty: Option<PublicKey>, lsp_pubkey: PublicKey) -> bool {
+        let paid = self.order.as_ref().is_some_and(|o| o.paid);
+        if !paid || counterparty != Some(lsp_pubkey) {
+            return false;
+        }
+        info!("LSPS1 order completed with channel {}", channel_id);
+        self.finish();
+        self.state = None;
+        self.ready_channel = Some(channel_id);
+        self.message = Some(format!("Channel {} is ready", channel_id));
+        true
+    }
+
+    /// Draws the screen
+    pub fn show(&mut self, ui: &mut egui::Ui, worker: &mut Worker, btc_price: f64) -> Option<OrderAction> {
+        let mut action = None;
+        let busy = worker.is_busy(CommandKind::Lsps1Order);
+
+        match self.order.clone() {
+            None => {
+                ui.label("Buy a channel from the LSP with inbound liquidity, so you can receive payments.");
+                egui::Grid::new("lsps1_order_form").num_columns(2).show(ui, |ui| {
+                    ui.label("Inbound liquidity (sats):");
+                    ui.text_edit_singleline(&mut self.lsp_balance);
+                    ui.end_row();
+                    ui.label("Your side of the channel (sats):");
+                    ui.text_edit_singleline(&mut self.client_balance);
+                    ui.end_row();
+                });
+                if worker::command_button(ui, worker, CommandKind::Lsps1Order, "Get quote") {
+                    self.request_quote(worker);
+                }
+            }
+            Some(order) => {
+                ui.label(format!("Order {}", order.order_id));
+                ui.label(format!(
+                    "Inbound: {} sats, your side: {} sats",
+                    order.lsp_balance_sat, order.client_balance_sat
+                ));
+                ui.label(format!("Fee: {} sats{}", order.fee_total_sat, usd(order.fee_total_sat, btc_price)));
+                ui.label(format!("Total to pay: {} sats{}", order.order_total_sat, usd(order.order_total_sat, btc_price)));
+
+                if self.state == Some(OrderState::AwaitingPayment) && !order.paid {
+                    let expires_in = order.invoice_expires_at - unix_now();
+                    ui.label(format!("Quote expires in {} min", (expires_in / 60).max(0)));
+                    ui.horizontal(|ui| {
+                        if worker::command_button(ui, worker, CommandKind::Lsps1Order, "Pay and order") {
+                            match Bolt11Invoice::from_str(&order.invoice) {
+                                Ok(invoice) => worker.submit(AppCommand::PayLsps1Order { invoice }),
+                                Err(e) => self.message = Some(format!("The LSP's invoice is invalid: {}", e)),
+                            }
+                        }
+                        if ui.add_enabled(!busy, egui::Button::new("Cancel")).clicked() {
+                            self.finish();
+                            self.state = None;
+                            self.message = None;
+                        }
+                    });
+                } else if busy || self.has_open_order() {
+                    ui.spinner();
+                }
+            }
+        }
+
+        if let Some(message) = &self.message {
+            ui.label(message);
+        }
+
+        if let Some(channel_id) = self.ready_channel {
+            ui.group(|ui| {
+                ui.label(format!("Channel {} is ready.", channel_id));
+                ui.horizontal(|ui| {
+                    if ui.button("Make it stable").clicked() {
+                        action = Some(OrderAction::MakeStable(channel_id));
+                        self.ready_channel = None;
+                    }
+                    if ui.button("Keep as BTC").clicked() {
+                        self.ready_channel = None;
+                    }
+                });
+            });
+        }
+
+        ui.add_space(20.0);
+        if ui.button("Done").clicked() {
+            action = action.or(Some(OrderAction::Close));
+        }
+        action
+    }
+}
+
+fn order_state(status: &LSPS1OrderStatus, paid: bool) -> OrderState {
+    if let Some(channel) = &status.channel_state {
+        return OrderState::ChannelFunded { funding_outpoint: channel.funding_outpoint.to_string() };
+    }
+    let Some(bolt11) = &status.payment_options.bolt11 else {
+        return OrderState::Unpayable;
+    };
+    match bolt11.state {
+        PaymentState::Refunded => OrderState::Refunded,
+        PaymentState::Paid => OrderState::Paid,
+        // The LSP may not have seen our payment yet
+        PaymentState::ExpectPayment if paid => OrderState::Paid,
+        PaymentState::ExpectPayment if bolt11.expires_at.timestamp() <= unix_now() => OrderState::Expired,
+        PaymentState::ExpectPayment => OrderState::AwaitingPayment,
+    }
+}
+
+fn usd(sats: u64, btc_price: f64) -> String {
+    if btc_price <= 0.0 {
+        return String::new();
+    }
+    format!(" (${:.2})", sats as f64 / 100_000_000.0 * btc_price)
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
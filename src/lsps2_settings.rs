@@ -0,0 +1,202 @@
+use crate::encryption;
+use ldk_node::liquidity::LSPS2ServiceConfig;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::{error, warn};
+
+const LSPS2_SETTINGS_FILE_NAME: &str = "lsps2_settings.json";
+
+/// JIT channel (LSPS2) service parameters the LSP node is built with. Read once at
+/// startup, so edits apply on the next start. Kept free of UI so a node started without
+/// the GUI can load the same file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Lsps2Settings {
+    /// Clients must present one of the LSP's tokens to buy a JIT channel
+    pub require_token: bool,
+    pub channel_opening_fee_ppm: u32,
+    pub min_channel_opening_fee_msat: u64,
+    /// Extra capacity opened on top of the payment, in parts per million of it
+    pub channel_over_provisioning_ppm: u32,
+    /// Blocks the LSP promises to keep a JIT channel open
+    pub min_channel_lifetime: u32,
+    pub max_client_to_self_delay: u32,
+    pub min_payment_size_msat: u64,
+    pub max_payment_size_msat: u64,
+    /// Let token-holding clients use JIT channels before the funding confirms. We fund
+    /// them, so this trusts nothing of the client's; whether a channel ends up zero-conf is
+    /// the client's choice to accept it as such.
+    pub zero_conf: bool,
+}
+
+impl Default for Lsps2Settings {
+    fn default() -> Self {
+        Self {
+            require_token: false,
+            channel_opening_fee_ppm: 0,
+            min_channel_opening_fee_msat: 0,
+            channel_over_provisioning_ppm: 1_000_000,
+            min_channel_lifetime: 100,
+            max_client_to_self_delay: 1024,
+            min_payment_size_msat: 0,
+            max_payment_size_msat: 100_000_000_000,
+            zero_conf: false,
+        }
+    }
+}
+
+impl Lsps2Settings {
+    fn path(data_dir: &Path) -> PathBuf {
+        data_dir.join(LSPS2_SETTINGS_FILE_NAME)
+    }
+
+    /// The saved settings, or the defaults if none are saved or the saved ones are invalid
+    pub fn load(data_dir: &Path) -> Self {
+        let path = Self::path(data_dir);
+        if !path.exists() {
+            return Self::default();
+        }
+        let settings = match encryption::read_state_file(&path).map(|s| serde_json::from_str::<Self>(&s)) {
+            Ok(Ok(settings)) => settings,
+            Ok(Err(e)) => {
+                error!("Ignoring unreadable {}: {}", path.display(), e);
+                return Self::default();
+            }
+            Err(e) => {
+                error!("Failed to read {}: {}", path.display(), e);
+                return Self::default();
+            }
+        };
+        match settings.validate() {
+            Ok(()) => settings,
+            Err(e) => {
+                warn!("Ignoring invalid LSPS2 settings in {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, data_dir: &Path) -> Result<(), String> {
+        self.validate()?;
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        encryption::write_state_file(&Self::path(data_dir), &json).map_err(|e| e.to_string())
+    }
+
+    /// Rejects combinations no JIT channel could satisfy
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_payment_size_msat == 0 {
+            return Err("Max payment size must be above zero".to_string());
+        }
+        if self.min_payment_size_msat > self.max_payment_size_msat {
+            return Err("Min payment size is larger than max payment size".to_string());
+        }
+        if self.channel_opening_fee_ppm >= 1_000_000 {
+            return Err("Opening fee must be below 1,000,000 ppm (100%)".to_string());
+        }
+        if self.zero_conf && !self.require_token {
+            return Err("Zero-conf JIT channels are only offered to token holders; require a token".to_string());
+        }
+        if self.min_channel_opening_fee_msat >= self.max_payment_size_msat {
+            return Err("Min opening fee would take the whole of the largest payment".to_string());
+        }
+        Ok(())
+    }
+
+    /// `token` is the service token from the token store, used if tokens are required
+    pub fn service_config(&self, token: Option<String>) -> LSPS2ServiceConfig {
+        LSPS2ServiceConfig {
+            require_token: token.filter(|_| self.require_token),
+            advertise_service: true,
+            channel_opening_fee_ppm: self.channel_opening_fee_ppm,
+            channel_over_provisioning_ppm: self.channel_over_provisioning_ppm,
+            min_channel_opening_fee_msat: self.min_channel_opening_fee_msat,
+            min_channel_lifetime: self.min_channel_lifetime,
+            max_client_to_self_delay: self.max_client_to_self_delay,
+            min_payment_size_msat: self.min_payment_size_msat,
+            max_payment_size_msat: self.max_payment_size_msat,
+        }
+    }
+}
+
+/// "LSP Settings" form editing a copy of the saved settings
+pub struct Lsps2SettingsForm {
+    draft: Lsps2Settings,
+    message: Option<Result<String, String>>,
+}
+
+impl Lsps2SettingsForm {
+    pub fn new(data_dir: &Path) -> Self {
+        Self { draft: Lsps2Settings::load(data_dir), message: None }
+    }
+
+    /// Draws the form. `effective` holds the values the running node was built with.
+    pub fn show(&mut self, ui: &mut egui::Ui, data_dir: &Path, effective: &Lsps2Settings) {
+        egui::Grid::new("lsps2_settings").num_columns(3).striped(true).show(ui, |ui| {
+            ui.strong("Setting");
+            ui.strong("New value");
+            ui.strong("In effect");
+            ui.end_row();
+
+            let draft = &mut self.draft;
+            setting_row(ui, "Opening fee (ppm)", &mut draft.channel_opening_fee_ppm, effective.channel_opening_fee_ppm);
+            setting_row(
+                ui,
+                "Min opening fee (msat)",
+                &mut draft.min_channel_opening_fee_msat,
+                effective.min_channel_opening_fee_msat,
+            );
+            setting_row(
+                ui,
+                "Over-provisioning (ppm)",
+                &mut draft.channel_over_provisioning_ppm,
+                effective.channel_over_provisioning_ppm,
+            );
+            setting_row(ui, "Min channel lifetime (blocks)", &mut draft.min_channel_lifetime, effective.min_channel_lifetime);
+            setting_row(ui, "Min payment (msat)", &mut draft.min_payment_size_msat, effective.min_payment_size_msat);
+            setting_row(ui, "Max payment (msat)", &mut draft.max_payment_size_msat, effective.max_payment_size_msat);
+
+            ui.label("Require token");
+            ui.checkbox(&mut draft.require_token, "");
+            ui.label(if effective.require_token { "required" } else { "not required" });
+            ui.end_row();
+
+            ui.label("Zero-conf for token holders");
+            ui.checkbox(&mut draft.zero_conf, "");
+            ui.label(if effective.zero_conf { "on" } else { "off" });
+            ui.end_row();
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("Save").clicked() {
+                self.message = Some(
+                    self.draft
+                        .save(data_dir)
+                        .map(|()| "Saved; applies on the next start".to_string()),
+                );
+            }
+            if ui.button("Reset to defaults").clicked() {
+                self.draft = Lsps2Settings::default();
+                self.message = None;
+            }
+        });
+        match &self.message {
+            Some(Ok(message)) => {
+                ui.colored_label(egui::Color32::GREEN, message);
+            }
+            Some(Err(e)) => {
+                ui.colored_label(egui::Color32::RED, e);
+            }
+            None => {}
+        }
+        if &self.draft != effective {
+            ui.colored_label(egui::Color32::YELLOW, "Differs from the running node; save and restart to apply");
+        }
+    }
+}
+
+fn setting_row<N: egui::emath::Numeric + ToString>(ui: &mut egui::Ui, label: &str, value: &mut N, current: N) {
+    ui.label(label);
+    ui.add(egui::DragValue::new(value));
+    ui.label(current.to_string());
+    ui.end_row();
+}
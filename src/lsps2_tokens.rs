@@ -0,0 +1,283 @@
+use crate::encryption;
+use crate::history;
+use crate::ui_util;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{error, info, warn};
+
+const LSPS2_TOKENS_FILE_NAME: &str = "lsps2_tokens.json";
+
+/// Access token handed to a client so it can buy JIT channels
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lsps2Token {
+    pub name: String,
+    pub token: String,
+    pub created_at: u64,
+    /// Unix time after which the token is no longer accepted
+    pub expires_at: Option<u64>,
+    /// JIT channels the token may open in total
+    pub max_channels: Option<u32>,
+    pub revoked: bool,
+    /// JIT channels opened while this token was in effect
+    #[serde(default)]
+    pub channels: Vec<String>,
+}
+
+impl Lsps2Token {
+    /// Why the token can't open new channels, if it can't
+    pub fn unusable_reason(&self, now: u64) -> Option<&'static str> {
+        if self.revoked {
+            Some("Revoked")
+        } else if self.expires_at.is_some_and(|t| now >= t) {
+            Some("Expired")
+        } else if self.max_channels.is_some_and(|max| self.channels.len() >= max as usize) {
+            Some("Used up")
+        } else {
+            None
+        }
+    }
+}
+
+/// The token the LSPS2 service is built with. ldk-node checks a single token and gives us no
+/// hook at the buy or intercept step, so the newest usable token at startup is the only one
+/// accepted until the next start; when there is none, a random token nobody holds is used so
+/// every JIT request is refused.
+#[derive(Debug, Clone)]
+pub struct ServiceToken {
+    /// Name of the token in effect, None if no token was usable
+    pub name: Option<String>,
+    pub token: String,
+}
+
+/// Named LSPS2 tokens, persisted with the other state files. Only one is accepted at a time:
+/// the service token is chosen again on each start, so generating, revoking, expiry and
+/// channel limits all take effect at the next restart. Channels already open are left alone.
+pub struct TokenStore {
+    path: PathBuf,
+    pub tokens: Vec<Lsps2Token>,
+}
+
+impl TokenStore {
+    pub fn load(data_dir: &Path) -> Self {
+        let path = data_dir.join(LSPS2_TOKENS_FILE_NAME);
+        let tokens = if path.exists() {
+            match encryption::read_state_file(&path).map(|s| serde_json::from_str(&s)) {
+                Ok(Ok(tokens)) => tokens,
+                Ok(Err(e)) => {
+                    error!("Ignoring unreadable {}: {}", path.display(), e);
+                    Vec::new()
+                }
+                Err(e) => {
+                    error!("Failed to read {}: {}", path.display(), e);
+                    Vec::new()
+                }
+            }
+        } else {
+            Vec::new()
+        };
+        Self { path, tokens }
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string_pretty(&self.tokens)
+            .map_err(|e| e.to_string())
+            .and_then(|json| encryption::write_state_file(&self.path, &json).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            error!("Failed to save {}: {}", self.path.display(), e);
+        }
+    }
+
+    /// Creates and saves a token; names must be unique
+    pub fn generate(&mut self, name: &str, expires_at: Option<u64>, max_channels: Option<u32>) -> Result<(), String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("Token name is empty".to_string());
+        }
+        if self.tokens.iter().any(|t| t.name == name) {
+            return Err(format!("A token named {} already exists", name));
+        }
+        self.tokens.push(Lsps2Token {
+            name: name.to_string(),
+            token: random_token(),
+            created_at: unix_now(),
+            expires_at,
+            max_channels,
+            revoked: false,
+            channels: Vec::new(),
+        });
+        self.save();
+        info!("Created LSPS2 token {}", name);
+        Ok(())
+    }
+
+    pub fn revoke(&mut self, name: &str) {
+        if let Some(token) = self.tokens.iter_mut().find(|t| t.name == name) {
+            token.revoked = true;
+            self.save();
+            info!("Revoked LSPS2 token {}", name);
+        }
+    }
+
+    /// Why the token called `name` can't open new channels, if it can't
+    pub fn unusable_reason(&self, name: &str) -> Option<&'static str> {
+        let now = unix_now();
+        self.tokens.iter().find(|t| t.name == name).map_or(Some("Deleted"), |t| t.unusable_reason(now))
+    }
+
+    /// The token the next start would accept
+    fn newest_usable(&self) -> Option<&Lsps2Token> {
+        let now = unix_now();
+        self.tokens.iter().filter(|t| t.unusable_reason(now).is_none()).max_by_key(|t| t.created_at)
+    }
+
+    /// Picks the token to build the LSPS2 service with
+    pub fn service_token(&self) -> ServiceToken {
+        match self.newest_usable() {
+            Some(token) => ServiceToken { name: Some(token.name.clone()), token: token.token.clone() },
+            None => {
+                warn!("No usable LSPS2 token; JIT channel requests will be refused");
+                ServiceToken { name: None, token: random_token() }
+            }
+        }
+    }
+
+    /// Attributes a JIT channel to the token it was opened under, which is always the
+    /// service token since the service refuses every other
+    pub fn record_channel(&mut self, name: &str, channel_id: String) {
+        if let Some(token) = self.tokens.iter_mut().find(|t| t.name == name) {
+            if !token.channels.contains(&channel_id) {
+                token.channels.push(channel_id);
+                self.save();
+            }
+        }
+    }
+}
+
+/// Token list and generation form for the LSP Settings panel
+#[derive(Default)]
+pub struct TokenForm {
+    name: String,
+    /// Days until expiry; blank for none
+    expiry_days: String,
+    /// Blank for no limit
+    max_channels: String,
+    error: Option<String>,
+}
+
+impl TokenForm {
+    /// Draws the tokens. `in_effect` is the service token the running node was built with.
+    pub fn show(&mut self, ui: &mut egui::Ui, store: &mut TokenStore, in_effect: Option<&ServiceToken>) {
+        let now = unix_now();
+        ui.label(
+            "The LSPS2 service accepts a single token: the newest usable one when the node starts. \
+             Every other token is refused until a restart picks it.",
+        );
+        match in_effect {
+            None => {
+                ui.label("Tokens are not required; anyone can request JIT channels.");
+            }
+            Some(ServiceToken { name: None, .. }) => {
+                ui.colored_label(egui::Color32::YELLOW, "No usable token at startup; all JIT requests are refused.");
+            }
+            Some(ServiceToken { name: Some(name), .. }) => {
+                ui.label(format!("Token in effect: {}", name));
+            }
+        }
+        if in_effect.is_some_and(|t| t.name.as_deref() != store.newest_usable().map(|t| t.name.as_str())) {
+            ui.colored_label(egui::Color32::YELLOW, "Tokens changed since startup; restart to apply");
+        }
+
+        let mut revoke = None;
+        if !store.tokens.is_empty() {
+            egui::Grid::new("lsps2_tokens").striped(true).num_columns(6).show(ui, |ui| {
+                for header in ["Name", "Token", "Expires", "Channels", "Status", ""] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+                for token in &store.tokens {
+                    ui.label(&token.name);
+                    ui.horizontal(|ui| {
+                        ui.monospace(format!("{}…", &token.token[..8]));
+                        ui_util::copy_button(ui, egui::Button::new("Copy").small(), || token.token.clone());
+                    });
+                    ui.label(token.expires_at.map(history::format_timestamp).unwrap_or_else(|| "never".to_string()));
+                    ui.label(match token.max_channels {
+                        Some(max) => format!("{} / {}", token.channels.len(), max),
+                        None => token.channels.len().to_string(),
+                    });
+                    let is_in_effect = in_effect.and_then(|t| t.name.as_deref()) == Some(token.name.as_str());
+                    match (token.unusable_reason(now), is_in_effect) {
+                        (Some(reason), true) => {
+                            ui.colored_label(egui::Color32::YELLOW, format!("{}; accepted until restart", reason));
+                        }
+                        (Some(reason), false) => {
+                            ui.label(reason);
+                        }
+                        (None, true) => {
+                            ui.colored_label(egui::Color32::GREEN, "In effect");
+                        }
+                        (None, false) => {
+                            ui.label("Refused; not the token in effect");
+                        }
+                    }
+                    if !token.revoked && ui.small_button("Revoke").clicked() {
+                        revoke = Some(token.name.clone());
+                    }
+                    ui.end_row();
+                }
+            });
+        }
+        if let Some(name) = revoke {
+            store.revoke(&name);
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Name:");
+            ui.add(egui::TextEdit::singleline(&mut self.name).desired_width(100.0));
+            ui.label("Expires in (days):");
+            ui.add(egui::TextEdit::singleline(&mut self.expiry_days).desired_width(40.0));
+            ui.label("Max channels:");
+            ui.add(egui::TextEdit::singleline(&mut self.max_channels).desired_width(40.0));
+            if ui.button("Generate token").clicked() {
+                self.error = self.generate(store).err();
+            }
+        });
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+        ui.label("Generating, revoking, expiry and channel limits apply to JIT requests after a restart.");
+    }
+
+    fn generate(&mut self, store: &mut TokenStore) -> Result<(), String> {
+        let expires_at = match self.expiry_days.trim() {
+            "" => None,
+            days => match days.parse::<u64>() {
+                Ok(days) if days > 0 => Some(unix_now() + days * 24 * 60 * 60),
+                _ => return Err("Invalid expiry".to_string()),
+            },
+        };
+        let max_channels = match self.max_channels.trim() {
+            "" => None,
+            max => match max.parse::<u32>() {
+                Ok(max) if max > 0 => Some(max),
+                _ => return Err("Invalid max channels".to_string()),
+            },
+        };
+        store.generate(&self.name, expires_at, max_channels)?;
+        *self = Self::default();
+        Ok(())
+    }
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
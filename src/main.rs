@@ -1,5 +1,47 @@
+pub mod accounts;
+pub mod address_book;
+pub mod audit;
+pub mod backup;
+pub mod balance_chart;
+pub mod chain_source;
+pub mod channel_policy;
+pub mod channel_table;
+pub mod currency;
+pub mod dev_mode;
+pub mod display;
+pub mod encryption;
+pub mod fees;
+pub mod history;
+pub mod jit_fee;
+pub mod lockfile;
+pub mod logging;
+pub mod lsp_config;
+pub mod lsps1_order;
+pub mod lsps2_settings;
+pub mod lsps2_tokens;
+pub mod network;
+pub mod node_ui;
+pub mod notify;
+pub mod onchain;
+pub mod paths;
+pub mod pending_payments;
+pub mod payment_retry;
 pub mod price_feeds;
+pub mod price_sources;
+pub mod reconnect;
+pub mod routing;
+pub mod seed;
+pub mod settings;
+pub mod shutdown;
+pub mod simulation;
+pub mod snapshot;
+pub mod status_log;
+pub mod sync_status;
+pub mod tax;
 pub mod types;
+pub mod vss;
+pub mod ui_util;
+pub mod worker;
 pub mod stable;
 
 #[cfg(feature = "user")]
@@ -8,13 +50,24 @@ mod user;
 #[cfg(any(feature = "lsp", feature = "exchange"))]
 mod server;
 
+#[cfg(any(feature = "user", feature = "lsp", feature = "exchange"))]
+mod admin;
+
+#[cfg(any(feature = "lsp", feature = "exchange"))]
+mod console;
+
+#[cfg(feature = "regtest")]
+mod regtest;
+
 #[cfg(all(feature = "user", not(any(feature = "lsp", feature = "exchange"))))]
 fn main() {
+    logging::init();
     user::run();
 }
 
 #[cfg(all(not(feature = "user"), any(feature = "lsp", feature = "exchange")))]
 fn main() {
+    logging::init();
     let mode = if cfg!(feature = "lsp") {
         "lsp"
     } else {
@@ -24,7 +77,17 @@ fn main() {
     server::run_with_mode(mode);
 }
 
-#[cfg(not(any(feature = "user", feature = "lsp", feature = "exchange")))]
+#[cfg(all(feature = "regtest", not(any(feature = "user", feature = "lsp", feature = "exchange"))))]
+fn main() {
+    logging::init();
+    if let Err(e) = regtest::run() {
+        tracing::error!("Regtest stable loop failed: {}", e);
+        std::process::exit(1);
+    }
+    tracing::info!("Regtest stable loop passed");
+}
+
+#[cfg(not(any(feature = "user", feature = "lsp", feature = "exchange", feature = "regtest")))]
 fn main() {
-    panic!("Must compile with --features user, lsp, or exchange");
+    panic!("Must compile with --features user, lsp, exchange or regtest");
 }
\ No newline at end of file
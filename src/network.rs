@@ -0,0 +1,37 @@
+use ldk_node::bitcoin::Network;
+
+/// Bitcoin network to run on: bitcoin, testnet, signet or regtest. `--network <name>` on the
+/// command line takes precedence.
+pub const NETWORK_ENV: &str = "STABLE_CHANNELS_NETWORK";
+
+/// The network from `--network` or `NETWORK_ENV`, else `default`
+pub fn from_args_or_env(default: &str) -> Result<Network, String> {
+    let name = cli_network()
+        .or_else(|| std::env::var(NETWORK_ENV).ok().filter(|s| !s.trim().is_empty()))
+        .unwrap_or_else(|| default.to_string());
+    parse(name.trim())
+}
+
+pub fn parse(name: &str) -> Result<Network, String> {
+    match name.to_lowercase().as_str() {
+        "bitcoin" | "mainnet" => Ok(Network::Bitcoin),
+        "testnet" => Ok(Network::Testnet),
+        "signet" => Ok(Network::Signet),
+        "regtest" => Ok(Network::Regtest),
+        other => Err(format!("unknown network {:?}; expected bitcoin, testnet, signet or regtest", other)),
+    }
+}
+
+/// The value of `--network <name>` or `--network=<name>`, if given
+fn cli_network() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--network" {
+            return args.next();
+        }
+        if let Some(name) = arg.strip_prefix("--network=") {
+            return Some(name.to_string());
+        }
+    }
+    None
+}
@@ -0,0 +1,995 @@
+use crate::address_book::{AddressBook, AddressBookPanel};
+use crate::chain_source::ChainSource;
+use crate::fees::{self, FeeEstimator, FeePriority};
+use crate::price_feeds::get_price_stats;
+use crate::display;
+use crate::settings;
+use crate::types::{AmountUnit, Bitcoin, USD};
+use crate::ui_util;
+use crate::worker::{self, AppCommand, AppResult, CommandKind, Worker};
+use ldk_node::bitcoin::secp256k1::PublicKey;
+use ldk_node::bitcoin::{Address, FeeRate, Network};
+use ldk_node::lightning::ln::msgs::SocketAddress;
+use ldk_node::lightning::offers::offer::{Amount, Offer};
+use ldk_node::lightning_invoice::Bolt11Invoice;
+use ldk_node::{BalanceDetails, ChannelDetails, LightningBalance, Node, PendingSweepBalance};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tracing::error;
+
+/// The node's reusable BOLT12 offer, kept so restarts show the same one
+const OFFER_FILE_NAME: &str = "bolt12_offer.txt";
+
+/// Age past which a USD amount is converted with a warning that the price may be off
+const PRICE_STALE_SECS: u64 = 60;
+
+/// Balances in BTC, valued in USD at the price they were last updated with
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NodeBalances {
+    pub lightning_btc: f64,
+    pub onchain_btc: f64,
+    pub lightning_usd: f64,
+    pub onchain_usd: f64,
+    /// On-chain funds that can be sent now, excluding the anchor reserve
+    pub onchain_spendable_sats: u64,
+    pub total_btc: f64,
+    pub total_usd: f64,
+    /// Sendable over usable channels plus confirmed on-chain funds
+    pub spendable_sats: u64,
+    /// Unconfirmed on-chain funds and claims from closed channels
+    pub pending_sats: u64,
+    /// Channel reserves and the on-chain reserve kept for anchor fee bumps
+    pub reserved_sats: u64,
+    /// BTC price in USD the balances were valued at
+    pub btc_price: f64,
+}
+
+impl NodeBalances {
+    pub fn new(balances: &BalanceDetails, channels: &[ChannelDetails], btc_price: f64) -> Self {
+        let lightning_btc = balances.total_lightning_balance_sats as f64 / 100_000_000.0;
+        let onchain_btc = balances.total_onchain_balance_sats as f64 / 100_000_000.0;
+        let lightning_usd = lightning_btc * btc_price;
+        let onchain_usd = onchain_btc * btc_price;
+
+        let sendable_sats: u64 = channels.iter().filter(|c| c.is_usable).map(|c| c.outbound_capacity_msat / 1000).sum();
+        let channel_reserve_sats: u64 = channels.iter().filter_map(|c| c.unspendable_punishment_reserve).sum();
+        let unconfirmed_onchain_sats = balances
+            .total_onchain_balance_sats
+            .saturating_sub(balances.spendable_onchain_balance_sats)
+            .saturating_sub(balances.total_anchor_channels_reserve_sats);
+        // Open channels are counted above; everything else is on its way on-chain
+        let closing_sats: u64 = balances
+            .lightning_balances
+            .iter()
+            .filter(|b| !matches!(b, LightningBalance::ClaimableOnChannelClose { .. }))
+            .map(lightning_balance_sats)
+            .sum();
+        let sweeping_sats: u64 = balances.pending_balances_from_channel_closures.iter().map(sweep_balance_sats).sum();
+
+        Self {
+            lightning_btc,
+            onchain_btc,
+            lightning_usd,
+            onchain_usd,
+            onchain_spendable_sats: balances.spendable_onchain_balance_sats,
+            total_btc: lightning_btc + onchain_btc,
+            total_usd: lightning_usd + onchain_usd,
+            spendable_sats: sendable_sats + balances.spendable_onchain_balance_sats,
+            pending_sats: unconfirmed_onchain_sats + closing_sats + sweeping_sats,
+            reserved_sats: channel_reserve_sats + balances.total_anchor_channels_reserve_sats,
+            btc_price,
+        }
+    }
+
+    /// "Spendable now", "Pending" and "Reserved" rows in BTC and USD
+    pub fn show_breakdown(&self, ui: &mut egui::Ui) {
+        let rows = [
+            ("Spendable now:", self.spendable_sats, "Sendable over usable channels, plus confirmed on-chain funds"),
+            ("Pending:", self.pending_sats, "Unconfirmed on-chain funds and claims from closed channels still confirming"),
+            ("Reserved:", self.reserved_sats, "Channel reserves the protocol won't let us spend, and on-chain funds kept to fee-bump anchor channels"),
+        ];
+        for (label, sats, explanation) in rows {
+            ui.horizontal(|ui| {
+                ui.label(label).on_hover_text(explanation);
+                ui.monospace(display::sats(sats));
+                ui.monospace(format!("({})", USD::from_bitcoin(Bitcoin::from_sats(sats), self.btc_price).format_usd()));
+            });
+        }
+    }
+}
+
+fn lightning_balance_sats(balance: &LightningBalance) -> u64 {
+    match balance {
+        LightningBalance::ClaimableOnChannelClose { amount_satoshis, .. }
+        | LightningBalance::ClaimableAwaitingConfirmations { amount_satoshis, .. }
+        | LightningBalance::ContentiousClaimable { amount_satoshis, .. }
+        | LightningBalance::MaybeTimeoutClaimableHTLC { amount_satoshis, .. }
+        | LightningBalance::MaybePreimageClaimableHTLC { amount_satoshis, .. }
+        | LightningBalance::CounterpartyRevokedOutputClaimable { amount_satoshis, .. } => *amount_satoshis,
+    }
+}
+
+fn sweep_balance_sats(balance: &PendingSweepBalance) -> u64 {
+    match balance {
+        PendingSweepBalance::PendingBroadcast { amount_satoshis, .. }
+        | PendingSweepBalance::BroadcastAwaitingConfirmation { amount_satoshis, .. }
+        | PendingSweepBalance::AwaitingThresholdConfirmations { amount_satoshis, .. } => *amount_satoshis,
+    }
+}
+
+/// Wallet operations and form state shared by the user, LSP and exchange apps. Each
+/// operation validates its form input, queues the node call on the worker and returns
+/// the status message to show.
+pub struct NodeUi {
+    pub network: Network,
+    pub balances: NodeBalances,
+    pub invoice_amount: String,
+    /// `invoice_amount` is in USD rather than sats
+    pub invoice_in_usd: bool,
+    /// Describes the invoice and is kept with it locally; blank for "Invoice"
+    pub invoice_label: String,
+    /// Label of the invoice being generated, until it is
+    pending_invoice_label: Option<String>,
+    pub invoice_result: String,
+    /// The invoice in `invoice_result`, for checking it can be paid
+    generated_invoice: Option<Bolt11Invoice>,
+    pub invoice_to_pay: String,
+    /// Amount to send when `invoice_to_pay` has none
+    pub pay_amount: String,
+    /// `pay_amount` is in USD rather than sats
+    pub pay_in_usd: bool,
+    /// Kept locally with the payment, to remember what it was for
+    pub pay_label: String,
+    /// Label of the payment being sent, until it is
+    pending_pay_label: Option<String>,
+    /// Labels of invoices generated and payments sent, as (hash or id in hex, label), for
+    /// the app to record in its payment history
+    new_labels: Vec<(String, String)>,
+    /// Routing fee cap, in sats or with a `ppm` suffix; blank for the default from the
+    /// settings
+    pub max_fee: String,
+    pub on_chain_address: String,
+    pub on_chain_amount: String,
+    /// `on_chain_amount` is in USD rather than sats
+    pub onchain_in_usd: bool,
+    /// Sends everything spendable, ignoring `on_chain_amount`
+    pub onchain_send_max: bool,
+    pub onchain_fee_priority: FeePriority,
+    /// Fee rate in sat/vB for `FeePriority::Custom`
+    pub onchain_custom_fee: String,
+    /// Send awaiting confirmation of its fee
+    pending_onchain_send: Option<OnchainSend>,
+    fee_estimator: FeeEstimator,
+    /// Amount in sats for a new offer; blank for any amount
+    pub offer_amount: String,
+    pub offer_description: String,
+    /// The node's current offer, empty until one is created
+    pub offer: String,
+    offer_path: PathBuf,
+    pub offer_to_pay: String,
+    /// Amount in sats to send when `offer_to_pay` has none
+    pub offer_pay_amount: String,
+    /// `pubkey@host:port` typed into the Peers panel
+    pub peer_to_connect: String,
+    /// Reconnect to the peer on startup
+    pub peer_persist: bool,
+    /// Labelled node ids offered next to node id inputs
+    pub address_book: AddressBook,
+    address_book_panel: AddressBookPanel,
+    sign_form: SignForm,
+}
+
+/// Signs text with the node key, and checks others' signatures. Signatures are the
+/// zbase32 format lnd and Core Lightning use for `signmessage`.
+#[derive(Default)]
+struct SignForm {
+    message: String,
+    signature: String,
+    verify_message: String,
+    verify_signature: String,
+    verify_node_id: String,
+    verify_result: Option<Result<(), String>>,
+}
+
+impl SignForm {
+    fn show(&mut self, ui: &mut egui::Ui, node: &Node) {
+        ui.label("Sign with this node's key");
+        ui.text_edit_multiline(&mut self.message);
+        if ui.button("Sign").clicked() {
+            self.signature = node.sign_message(self.message.as_bytes());
+        }
+        if !self.signature.is_empty() {
+            ui.horizontal(|ui| {
+                ui.monospace(&self.signature);
+                ui_util::copy_button(ui, egui::Button::new("Copy"), || self.signature.clone());
+            });
+        }
+
+        ui.separator();
+        ui.label("Verify a signature");
+        ui.label("Message:");
+        ui.text_edit_multiline(&mut self.verify_message);
+        ui.horizontal(|ui| {
+            ui.label("Signature:");
+            ui.text_edit_singleline(&mut self.verify_signature);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Node ID:");
+            ui.text_edit_singleline(&mut self.verify_node_id);
+        });
+        if ui.button("Verify").clicked() {
+            self.verify_result = Some(match PublicKey::from_str(self.verify_node_id.trim()) {
+                Ok(node_id) => {
+                    if node.verify_signature(self.verify_message.as_bytes(), self.verify_signature.trim(), &node_id) {
+                        Ok(())
+                    } else {
+                        Err("Not signed by this node".to_string())
+                    }
+                }
+                Err(_) => Err("Invalid node ID".to_string()),
+            });
+        }
+        match &self.verify_result {
+            Some(Ok(())) => {
+                ui.colored_label(egui::Color32::GREEN, "✔ Valid signature");
+            }
+            Some(Err(e)) => {
+                ui.colored_label(egui::Color32::RED, format!("✗ {}", e));
+            }
+            None => {}
+        }
+    }
+}
+
+impl NodeUi {
+    pub fn new(
+        network: Network,
+        data_dir: &Path,
+        invoice_amount: &str,
+        on_chain_amount: &str,
+        chain_source: ChainSource,
+    ) -> Self {
+        let offer_path = data_dir.join(OFFER_FILE_NAME);
+        let offer = fs::read_to_string(&offer_path).map(|s| s.trim().to_string()).unwrap_or_default();
+        Self {
+            network,
+            balances: NodeBalances::default(),
+            invoice_amount: invoice_amount.to_string(),
+            invoice_in_usd: false,
+            invoice_label: String::new(),
+            pending_invoice_label: None,
+            invoice_result: String::new(),
+            generated_invoice: None,
+            invoice_to_pay: String::new(),
+            pay_amount: String::new(),
+            pay_in_usd: false,
+            pay_label: String::new(),
+            pending_pay_label: None,
+            new_labels: Vec::new(),
+            max_fee: String::new(),
+            on_chain_address: String::new(),
+            on_chain_amount: on_chain_amount.to_string(),
+            onchain_in_usd: false,
+            onchain_send_max: false,
+            onchain_fee_priority: FeePriority::Normal,
+            onchain_custom_fee: String::new(),
+            pending_onchain_send: None,
+            fee_estimator: FeeEstimator::new(chain_source),
+            offer_amount: String::new(),
+            offer_description: String::new(),
+            offer,
+            offer_path,
+            offer_to_pay: String::new(),
+            offer_pay_amount: String::new(),
+            peer_to_connect: String::new(),
+            peer_persist: true,
+            address_book: AddressBook::load(data_dir),
+            address_book_panel: AddressBookPanel::default(),
+            sign_form: SignForm::default(),
+        }
+    }
+
+    /// Sats for an amount typed in sats or BTC, or in USD converted at the last balance price
+    fn amount_sats(&self, input: &str, in_usd: bool) -> Result<u64, String> {
+        if !in_usd {
+            return parse_sats(input);
+        }
+        let usd = match USD::parse(input) {
+            Ok(usd) if usd > USD::default() => usd,
+            _ => return Err("Invalid amount".to_string()),
+        };
+        usd.to_sats_at(self.balances.btc_price).ok_or_else(|| "No BTC price yet to convert USD".to_string())
+    }
+
+    pub fn generate_invoice(&mut self, worker: &mut Worker) -> String {
+        match self.amount_sats(&self.invoice_amount, self.invoice_in_usd) {
+            Ok(amount) => {
+                let label = self.invoice_label.trim();
+                let description = match (label.is_empty(), self.invoice_in_usd) {
+                    (false, _) => label.to_string(),
+                    (true, true) => format!("${} at ${:.2}/BTC", self.invoice_amount.trim(), self.balances.btc_price),
+                    (true, false) => "Invoice".to_string(),
+                };
+                self.pending_invoice_label = (!label.is_empty()).then(|| label.to_string());
+                worker.submit(AppCommand::GenerateInvoice { amount_msats: amount * 1000, description });
+                "Generating invoice...".to_string()
+            }
+            Err(e) => e,
+        }
+    }
+
+    /// Pays the invoice in the form, asking for an amount if it has none. Payments larger
+    /// than the usable outbound capacity of `channels` are refused up front.
+    pub fn pay_invoice(&mut self, worker: &mut Worker, channels: &[ChannelDetails]) -> String {
+        let invoice = match Bolt11Invoice::from_str(self.invoice_to_pay.trim()) {
+            Ok(invoice) => invoice,
+            Err(e) => return format!("Invalid invoice: {}", e),
+        };
+        let (amount_msats, amount_override) = match invoice.amount_milli_satoshis() {
+            Some(amount_msats) => (amount_msats, None),
+            None => match self.amount_sats(&self.pay_amount, self.pay_in_usd) {
+                Ok(sats) if sats > 0 => (sats * 1000, Some(sats * 1000)),
+                Ok(_) | Err(_) => return "Invoice has no amount; enter how much to pay".to_string(),
+            },
+        };
+        let max_fee_msats = match parse_max_fee(&self.max_fee, amount_msats) {
+            Ok(max_fee_msats) => max_fee_msats,
+            Err(e) => return e,
+        };
+
+        let outbound_msats: u64 = channels.iter().filter(|c| c.is_usable).map(|c| c.outbound_capacity_msat).sum();
+        if amount_msats + max_fee_msats.unwrap_or(0) > outbound_msats {
+            return format!(
+                "Not enough outbound capacity: can send at most {} sats, invoice needs {} sats",
+                outbound_msats / 1000,
+                amount_msats / 1000
+            );
+        }
+
+        let label = self.pay_label.trim();
+        self.pending_pay_label = (!label.is_empty()).then(|| label.to_string());
+        worker.submit(AppCommand::PayInvoice { invoice, amount_msats: amount_override, max_fee_msats });
+        "Sending payment...".to_string()
+    }
+
+    pub fn create_offer(&mut self, worker: &mut Worker) -> String {
+        let amount_msats = match self.offer_amount.trim() {
+            "" => None,
+            amount => match parse_sats(amount) {
+                Ok(sats) if sats > 0 => Some(sats * 1000),
+                _ => return "Invalid amount".to_string(),
+            },
+        };
+        let description = match self.offer_description.trim() {
+            "" => "Stable Channels".to_string(),
+            description => description.to_string(),
+        };
+        worker.submit(AppCommand::CreateOffer { amount_msats, description });
+        "Creating offer...".to_string()
+    }
+
+    /// Pays the offer in the form, asking for an amount if it has none
+    pub fn pay_offer(&mut self, worker: &mut Worker) -> String {
+        let offer = match Offer::from_str(self.offer_to_pay.trim()) {
+            Ok(offer) => offer,
+            Err(e) => return format!("Invalid offer: {:?}", e),
+        };
+        let amount_msats = match offer.amount() {
+            Some(Amount::Bitcoin { .. }) => None,
+            Some(Amount::Currency { .. }) => return "Offers priced in fiat are not supported".to_string(),
+            None => match parse_sats(&self.offer_pay_amount) {
+                Ok(sats) if sats > 0 => Some(sats * 1000),
+                _ => return "Offer has no amount; enter how much to pay".to_string(),
+            },
+        };
+        worker.submit(AppCommand::PayOffer { offer, amount_msats });
+        "Sending offer payment...".to_string()
+    }
+
+    pub fn get_address(&mut self, worker: &mut Worker) -> String {
+        worker.submit(AppCommand::NewAddress);
+        "Generating address...".to_string()
+    }
+
+    /// Fee rate in sat/vB for the selected priority
+    fn onchain_fee_rate(&self) -> Result<u64, String> {
+        let sat_per_vb = match self.onchain_fee_priority {
+            FeePriority::Custom => {
+                self.onchain_custom_fee.trim().parse::<u64>().map_err(|_| "Invalid fee rate".to_string())?
+            }
+            priority => match self.fee_estimator.latest() {
+                Some(Ok(estimates)) => estimates.sat_per_vb(priority).unwrap_or(fees::MIN_RELAY_FEE_SAT_PER_VB),
+                Some(Err(e)) => return Err(format!("No fee estimates ({}); enter a custom fee rate", e)),
+                None => return Err("Fee estimates are still loading".to_string()),
+            },
+        };
+        if sat_per_vb < fees::MIN_RELAY_FEE_SAT_PER_VB {
+            return Err(format!(
+                "Fee rate of {} sat/vB is below the relay minimum of {} sat/vB",
+                sat_per_vb,
+                fees::MIN_RELAY_FEE_SAT_PER_VB
+            ));
+        }
+        Ok(sat_per_vb)
+    }
+
+    /// Checks the send in the form: the address must be for the node's own network, and
+    /// neither the amount nor what's left of a send-max after fees may be dust
+    fn prepare_onchain_send(&self) -> Result<OnchainSend, String> {
+        let address = Address::from_str(self.on_chain_address.trim())
+            .map_err(|_| "Invalid address".to_string())?
+            .require_network(self.network)
+            .map_err(|_| "Invalid address for this network".to_string())?;
+        let sat_per_vb = self.onchain_fee_rate()?;
+        let dust_limit = address.script_pubkey().minimal_non_dust().to_sat();
+        let spendable = self.balances.onchain_spendable_sats;
+
+        if self.onchain_send_max {
+            let fee_sats = sat_per_vb * fees::TX_VSIZE_NO_CHANGE;
+            let amount_sats = spendable.saturating_sub(fee_sats);
+            if spendable == 0 {
+                return Err("No spendable on-chain funds".to_string());
+            }
+            if amount_sats < dust_limit {
+                return Err(format!(
+                    "After the estimated {} sat fee only {} sats would be sent, below the {} sat dust limit",
+                    fee_sats, amount_sats, dust_limit
+                ));
+            }
+            return Ok(OnchainSend { address, amount_sats, send_max: true, sat_per_vb, fee_sats });
+        }
+
+        let amount_sats = self.amount_sats(&self.on_chain_amount, self.onchain_in_usd)?;
+        if amount_sats < dust_limit {
+            return Err(format!("{} sats is below the {} sat dust limit for this address", amount_sats, dust_limit));
+        }
+        let fee_sats = sat_per_vb * fees::TX_VSIZE_WITH_CHANGE;
+        if amount_sats + fee_sats > spendable {
+            return Err(format!(
+                "Not enough spendable on-chain funds: {} sats plus an estimated {} sat fee is more than the {} sats spendable",
+                amount_sats, fee_sats, spendable
+            ));
+        }
+        Ok(OnchainSend { address, amount_sats, send_max: false, sat_per_vb, fee_sats })
+    }
+
+    /// Checks the send in the form and asks for confirmation of its fee
+    pub fn send_onchain(&mut self) -> String {
+        match self.prepare_onchain_send() {
+            Ok(send) => {
+                self.pending_onchain_send = Some(send);
+                "Confirm the on-chain send".to_string()
+            }
+            Err(e) => e,
+        }
+    }
+
+    fn confirm_onchain_send(&mut self, worker: &mut Worker, send: OnchainSend) -> String {
+        let Some(fee_rate) = FeeRate::from_sat_per_vb(send.sat_per_vb) else {
+            return "Invalid fee rate".to_string();
+        };
+        let amount_sats = (!send.send_max).then_some(send.amount_sats);
+        worker.submit(AppCommand::SendOnchain { address: send.address, amount_sats, fee_rate });
+        "Sending transaction...".to_string()
+    }
+
+    /// USD value of `sats` at the balance price, for showing next to fees
+    fn sats_usd(&self, sats: u64) -> String {
+        if self.balances.btc_price <= 0.0 {
+            return String::new();
+        }
+        format!(" ({})", USD::from_bitcoin(Bitcoin::from_sats(sats), self.balances.btc_price).format_usd())
+    }
+
+    pub fn connect_peer(&mut self, worker: &mut Worker) -> String {
+        let Some((node_id, address)) = parse_peer(&self.peer_to_connect) else {
+            return "Invalid peer; expected pubkey@host:port".to_string();
+        };
+        worker.submit(AppCommand::ConnectPeer { node_id, address, persist: self.peer_persist });
+        format!("Connecting to {}...", node_id)
+    }
+
+    /// Applies the result of a command queued by one of the operations above. Returns the
+    /// status message, or None for results that belong to the app itself.
+    pub fn apply_result(&mut self, result: AppResult) -> Option<String> {
+        let status = match result {
+            AppResult::InvoiceGenerated(Ok(invoice)) => {
+                if let Some(label) = self.pending_invoice_label.take() {
+                    self.new_labels.push((invoice.payment_hash().to_string(), label));
+                    self.invoice_label.clear();
+                }
+                self.invoice_result = invoice.to_string();
+                self.generated_invoice = Some(invoice);
+                "Invoice generated".to_string()
+            }
+            AppResult::InvoicePaid(Ok(payment_id)) => {
+                if let Some(label) = self.pending_pay_label.take() {
+                    self.new_labels.push((hex::encode(payment_id.0), label));
+                    self.pay_label.clear();
+                }
+                self.invoice_to_pay.clear();
+                self.pay_amount.clear();
+                format!("Payment sent, ID: {}", payment_id)
+            }
+            AppResult::AddressGenerated(Ok(address)) => {
+                self.on_chain_address = address.to_string();
+                "Address generated".to_string()
+            }
+            AppResult::OnchainSent(Ok(txid)) => format!("Transaction sent: {}", txid),
+            AppResult::PeerConnected { node_id, result: Ok(()) } => {
+                self.peer_to_connect.clear();
+                format!("Connected to {}", node_id)
+            }
+            AppResult::PeerConnected { node_id, result: Err(e) } => format!("Could not connect to {}: {}", node_id, e),
+            AppResult::OfferCreated(Ok(offer)) => {
+                self.offer = offer.to_string();
+                if let Err(e) = fs::write(&self.offer_path, &self.offer) {
+                    error!("Failed to save offer to {}: {}", self.offer_path.display(), e);
+                }
+                "Offer created".to_string()
+            }
+            AppResult::OfferPaid(Ok(payment_id)) => {
+                self.offer_to_pay.clear();
+                self.offer_pay_amount.clear();
+                format!("Offer payment sent, ID: {}", payment_id)
+            }
+            AppResult::OfferCreated(Err(e)) => format!("Error: {}", e),
+            AppResult::OfferPaid(Err(e)) => format!("Payment error: {}", e),
+            AppResult::InvoiceGenerated(Err(e)) => {
+                self.pending_invoice_label = None;
+                format!("Error: {}", e)
+            }
+            AppResult::AddressGenerated(Err(e)) => format!("Error: {}", e),
+            AppResult::InvoicePaid(Err(e)) => {
+                self.pending_pay_label = None;
+                format!("Payment error: {}", e)
+            }
+            AppResult::OnchainSent(Err(e)) => format!("Transaction error: {}", e),
+            AppResult::JitInvoiceGenerated(_)
+            | AppResult::TopUpInvoiceGenerated(_)
+            | AppResult::WithdrawSent(_)
+            | AppResult::ChannelOpened { .. }
+            | AppResult::Connected { .. }
+            | AppResult::Lsps1OrderStatus(_)
+            | AppResult::Lsps1OrderPaid(_)
+            | AppResult::DepositInvoiceGenerated { .. }
+            | AppResult::AccountWithdrawSent { .. }
+            | AppResult::AccountOnchainSent { .. } => return None,
+        };
+        Some(status)
+    }
+
+    /// Labels given since the last call, as (payment hash or id in hex, label)
+    pub fn take_new_labels(&mut self) -> Vec<(String, String)> {
+        std::mem::take(&mut self.new_labels)
+    }
+
+    /// Draws the invoice form. The generated invoice is checked against `channels` and
+    /// a warning shown if it can't be paid.
+    pub fn show_invoice_section(
+        &mut self,
+        ui: &mut egui::Ui,
+        worker: &mut Worker,
+        channels: &[ChannelDetails],
+    ) -> Option<String> {
+        let mut status = None;
+        ui.group(|ui| {
+            ui.label("Generate Invoice");
+            ui.horizontal(|ui| {
+                ui.label(amount_label(self.invoice_in_usd));
+                ui.text_edit_singleline(&mut self.invoice_amount);
+                ui.checkbox(&mut self.invoice_in_usd, "USD");
+            });
+            ui.horizontal(|ui| {
+                ui.label("Label:");
+                ui.add(egui::TextEdit::singleline(&mut self.invoice_label).hint_text("optional, shown to the payer"));
+                if worker::command_button(ui, worker, CommandKind::GenerateInvoice, "Get Invoice") {
+                    status = Some(self.generate_invoice(worker));
+                }
+            });
+            if self.invoice_in_usd {
+                self.show_conversion(ui, &self.invoice_amount);
+            }
+
+            if !self.invoice_result.is_empty() {
+                let expired = self.generated_invoice.as_ref().map_or(false, |i| i.is_expired());
+                if let Some(invoice) = &self.generated_invoice {
+                    let color = if expired { egui::Color32::RED } else { egui::Color32::GRAY };
+                    ui.colored_label(color, expiry_label(invoice));
+                }
+                if let Some(warning) = self.generated_invoice.as_ref().and_then(|i| unpayable_reason(i, channels)) {
+                    ui.colored_label(egui::Color32::YELLOW, warning);
+                }
+                if let Some(qr) = ui_util::qr_texture(ui.ctx(), &self.invoice_result) {
+                    let tint = if expired { egui::Color32::from_gray(60) } else { egui::Color32::WHITE };
+                    ui.add(egui::Image::new(&qr).tint(tint));
+                }
+                ui.add_enabled(!expired, egui::TextEdit::multiline(&mut self.invoice_result));
+                ui.add_enabled_ui(!expired, |ui| {
+                    ui_util::copy_button(ui, egui::Button::new("Copy"), || self.invoice_result.clone());
+                });
+            }
+        });
+        status
+    }
+
+    pub fn show_pay_invoice_section(
+        &mut self,
+        ui: &mut egui::Ui,
+        worker: &mut Worker,
+        channels: &[ChannelDetails],
+    ) -> Option<String> {
+        let mut status = None;
+        ui.group(|ui| {
+            ui.label("Pay Invoice");
+            ui.text_edit_multiline(&mut self.invoice_to_pay);
+            let amountless = Bolt11Invoice::from_str(self.invoice_to_pay.trim())
+                .map_or(false, |invoice| invoice.amount_milli_satoshis().is_none());
+            if amountless {
+                ui.horizontal(|ui| {
+                    ui.label(amount_label(self.pay_in_usd));
+                    ui.text_edit_singleline(&mut self.pay_amount);
+                    ui.checkbox(&mut self.pay_in_usd, "USD");
+                });
+                if self.pay_in_usd {
+                    self.show_conversion(ui, &self.pay_amount);
+                }
+            }
+            ui.horizontal(|ui| {
+                ui.label("Max fee (sats, or e.g. 5000ppm):");
+                ui.text_edit_singleline(&mut self.max_fee);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Label:");
+                ui.add(egui::TextEdit::singleline(&mut self.pay_label).hint_text("optional, kept locally"));
+            });
+            if worker::command_button(ui, worker, CommandKind::PayInvoice, "Pay Invoice") {
+                status = Some(self.pay_invoice(worker, channels));
+            }
+        });
+        status
+    }
+
+    /// Lists the node's peers with a form to connect to another and per-peer controls to
+    /// disconnect or stop reconnecting on startup
+    pub fn show_peers_section(&mut self, ui: &mut egui::Ui, node: &Node, worker: &mut Worker) -> Option<String> {
+        let mut status = None;
+        egui::CollapsingHeader::new("Peers").show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Peer:");
+                ui.add(egui::TextEdit::singleline(&mut self.peer_to_connect).hint_text("pubkey@host:port"));
+                self.address_book.peer_picker(ui, "peer_address_book", &mut self.peer_to_connect);
+                ui.checkbox(&mut self.peer_persist, "Reconnect on startup");
+                if worker::command_button(ui, worker, CommandKind::ConnectPeer, "Connect") {
+                    status = Some(self.connect_peer(worker));
+                }
+            });
+
+            let peers = node.list_peers();
+            if peers.is_empty() {
+                ui.label("No peers.");
+                return;
+            }
+            let channels = node.list_channels();
+            egui::Grid::new("peers").striped(true).num_columns(5).show(ui, |ui| {
+                for header in ["Node", "Address", "Status", "Channels", ""] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+                for peer in peers {
+                    let node_id = peer.node_id.to_string();
+                    let name = self.address_book.label_for(&peer.node_id).unwrap_or(&node_id[..16]);
+                    ui.label(name).on_hover_text(&node_id);
+                    ui.label(peer.address.to_string());
+                    if peer.is_connected {
+                        ui.colored_label(egui::Color32::GREEN, "connected");
+                    } else {
+                        ui.colored_label(egui::Color32::GRAY, "disconnected");
+                    }
+                    ui.label(channels.iter().filter(|c| c.counterparty_node_id == peer.node_id).count().to_string());
+                    ui.horizontal(|ui| {
+                        let mut persisted = peer.is_persisted;
+                        if ui.checkbox(&mut persisted, "Reconnect on startup").changed() {
+                            // Disconnecting is the only way to forget a persisted peer
+                            if !persisted {
+                                if let Err(e) = node.disconnect(peer.node_id) {
+                                    status = Some(format!("Could not disconnect from {}: {}", peer.node_id, e));
+                                    return;
+                                }
+                            }
+                            worker.submit(AppCommand::ConnectPeer {
+                                node_id: peer.node_id,
+                                address: peer.address.clone(),
+                                persist: persisted,
+                            });
+                        }
+                        if peer.is_connected && ui.button("Disconnect").clicked() {
+                            status = Some(match node.disconnect(peer.node_id) {
+                                Ok(()) => format!("Disconnected from {}", peer.node_id),
+                                Err(e) => format!("Could not disconnect from {}: {}", peer.node_id, e),
+                            });
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+        });
+        status
+    }
+
+    pub fn show_address_book_section(&mut self, ui: &mut egui::Ui) {
+        self.address_book_panel.show(ui, &mut self.address_book);
+    }
+
+    pub fn show_sign_section(&mut self, ui: &mut egui::Ui, node: &Node) {
+        egui::CollapsingHeader::new("Sign / verify message").show(ui, |ui| self.sign_form.show(ui, node));
+    }
+
+    pub fn show_offer_section(&mut self, ui: &mut egui::Ui, worker: &mut Worker) -> Option<String> {
+        let mut status = None;
+        ui.group(|ui| {
+            ui.label("BOLT12 Offer");
+            if !self.offer.is_empty() {
+                if let Some(qr) = ui_util::qr_texture(ui.ctx(), &self.offer) {
+                    ui.image(&qr);
+                }
+                ui.add(egui::Label::new(egui::RichText::new(&self.offer).monospace()).wrap());
+                ui_util::copy_button(ui, egui::Button::new("Copy"), || self.offer.clone());
+            }
+            ui.horizontal(|ui| {
+                ui.label("Amount (sats, blank for any):");
+                ui.text_edit_singleline(&mut self.offer_amount);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Description:");
+                ui.text_edit_singleline(&mut self.offer_description);
+            });
+            let label = if self.offer.is_empty() { "Create Offer" } else { "Replace Offer" };
+            if worker::command_button(ui, worker, CommandKind::CreateOffer, label) {
+                status = Some(self.create_offer(worker));
+            }
+
+            ui.separator();
+            ui.label("Pay Offer");
+            ui.text_edit_multiline(&mut self.offer_to_pay);
+            if let Ok(offer) = Offer::from_str(self.offer_to_pay.trim()) {
+                if let Some(description) = offer.description() {
+                    ui.label(format!("For: {}", description));
+                }
+                match offer.amount() {
+                    Some(Amount::Bitcoin { amount_msats }) => {
+                        ui.label(format!("Amount: {}", display::sats(amount_msats / 1000)));
+                    }
+                    Some(Amount::Currency { .. }) => {
+                        ui.label("Amount: priced in fiat (not supported)");
+                    }
+                    None => {
+                        ui.horizontal(|ui| {
+                            ui.label("Amount (sats):");
+                            ui.text_edit_singleline(&mut self.offer_pay_amount);
+                        });
+                    }
+                }
+                if worker::command_button(ui, worker, CommandKind::PayOffer, "Pay Offer") {
+                    status = Some(self.pay_offer(worker));
+                }
+            }
+        });
+        status
+    }
+
+    pub fn show_onchain_address_section(&mut self, ui: &mut egui::Ui, worker: &mut Worker) -> Option<String> {
+        let mut status = None;
+        ui.group(|ui| {
+            ui.label("On-chain Address");
+            if worker::command_button(ui, worker, CommandKind::NewAddress, "Get Address") {
+                status = Some(self.get_address(worker));
+            }
+
+            if !self.on_chain_address.is_empty() {
+                let uri = self.bip21_uri();
+                if let Some(qr) = ui_util::qr_texture(ui.ctx(), &uri) {
+                    ui.image(&qr);
+                }
+                ui.label(self.on_chain_address.clone());
+                ui_util::copy_button(ui, egui::Button::new("Copy"), || self.on_chain_address.clone());
+            }
+        });
+        status
+    }
+
+    /// `bitcoin:` URI for the address in the form, with the entered amount if there is one
+    fn bip21_uri(&self) -> String {
+        match self.amount_sats(&self.on_chain_amount, self.onchain_in_usd) {
+            Ok(sats) if sats > 0 => {
+                format!("bitcoin:{}?amount={:.8}", self.on_chain_address.trim(), sats as f64 / 100_000_000.0)
+            }
+            _ => format!("bitcoin:{}", self.on_chain_address.trim()),
+        }
+    }
+
+    pub fn show_onchain_send_section(&mut self, ui: &mut egui::Ui, worker: &mut Worker) -> Option<String> {
+        self.fee_estimator.refresh_if_stale();
+        let mut status = None;
+        ui.group(|ui| {
+            ui.label("On-chain Send");
+            ui.horizontal(|ui| {
+                ui.label("Address:");
+                ui.text_edit_singleline(&mut self.on_chain_address);
+            });
+            ui.horizontal(|ui| {
+                ui.label(amount_label(self.onchain_in_usd));
+                ui.add_enabled(!self.onchain_send_max, egui::TextEdit::singleline(&mut self.on_chain_amount));
+                ui.checkbox(&mut self.onchain_in_usd, "USD");
+                ui.checkbox(&mut self.onchain_send_max, "Send max");
+            });
+            if self.onchain_in_usd && !self.onchain_send_max {
+                self.show_conversion(ui, &self.on_chain_amount);
+            }
+
+            let estimates = self.fee_estimator.latest();
+            ui.horizontal(|ui| {
+                ui.label("Fee:");
+                let rate_label = |priority: FeePriority| match &estimates {
+                    Some(Ok(estimates)) => match estimates.sat_per_vb(priority) {
+                        Some(rate) => format!("{} – {} sat/vB", priority.label(), rate),
+                        None => priority.label().to_string(),
+                    },
+                    _ => priority.label().to_string(),
+                };
+                egui::ComboBox::from_id_salt("onchain_fee_priority")
+                    .selected_text(rate_label(self.onchain_fee_priority))
+                    .show_ui(ui, |ui| {
+                        for priority in FeePriority::ALL {
+                            ui.selectable_value(&mut self.onchain_fee_priority, priority, rate_label(priority));
+                        }
+                    });
+                if self.onchain_fee_priority == FeePriority::Custom {
+                    ui.add(egui::TextEdit::singleline(&mut self.onchain_custom_fee).desired_width(60.0));
+                    ui.label("sat/vB");
+                }
+                if estimates.is_none() {
+                    ui.spinner();
+                }
+            });
+            if let Some(Err(e)) = &estimates {
+                ui.colored_label(egui::Color32::YELLOW, format!("No fee estimates: {}", e));
+            }
+
+            match self.pending_onchain_send.take() {
+                Some(send) => {
+                    let mut keep = true;
+                    ui.group(|ui| {
+                        let amount = if send.send_max {
+                            format!("about {} (everything spendable)", display::sats(send.amount_sats))
+                        } else {
+                            display::sats(send.amount_sats)
+                        };
+                        ui.label(format!("Send {}{} to {}?", amount, self.sats_usd(send.amount_sats), send.address));
+                        ui.label(format!(
+                            "Estimated fee: {} sats{} at {} sat/vB",
+                            send.fee_sats,
+                            self.sats_usd(send.fee_sats),
+                            send.sat_per_vb
+                        ));
+                        ui.horizontal(|ui| {
+                            if worker::command_button(ui, worker, CommandKind::SendOnchain, "Confirm send") {
+                                status = Some(self.confirm_onchain_send(worker, send.clone()));
+                                keep = false;
+                            }
+                            if ui.button("Cancel").clicked() {
+                                keep = false;
+                            }
+                        });
+                    });
+                    if keep {
+                        self.pending_onchain_send = Some(send);
+                    }
+                }
+                None => {
+                    if worker::command_button(ui, worker, CommandKind::SendOnchain, "Send On-chain") {
+                        status = Some(self.send_onchain());
+                    }
+                }
+            }
+        });
+        status
+    }
+
+    /// The sats a USD amount converts to, and a warning if the price is old
+    fn show_conversion(&self, ui: &mut egui::Ui, input: &str) {
+        match self.amount_sats(input, true) {
+            Ok(sats) => {
+                ui.label(format!("= {} at {}/BTC", display::sats(sats), USD::from_f64(self.balances.btc_price).format_usd()));
+                let age_secs = get_price_stats().age_secs;
+                if age_secs > PRICE_STALE_SECS {
+                    ui.colored_label(
+                        egui::Color32::YELLOW,
+                        format!("Price is {}s old; the amount may be off", age_secs),
+                    );
+                }
+            }
+            Err(e) => {
+                ui.label(e);
+            }
+        }
+    }
+}
+
+/// An on-chain send checked by `prepare_onchain_send`, shown for confirmation
+#[derive(Clone)]
+struct OnchainSend {
+    address: Address,
+    /// For a send-max, the balance less the estimated fee
+    amount_sats: u64,
+    send_max: bool,
+    sat_per_vb: u64,
+    /// Estimated from a typical transaction size
+    fee_sats: u64,
+}
+
+/// Routing fee cap in msats for a payment of `amount_msats`: blank for none, a number of
+/// sats, or parts per million of the amount with a `ppm` suffix
+/// The typed routing fee cap for sending `amount_msats`; left blank, the default from the
+/// settings, if one is set
+fn parse_max_fee(input: &str, amount_msats: u64) -> Result<Option<u64>, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        let ppm = settings::get().payments.max_fee_ppm;
+        return Ok((ppm > 0).then(|| amount_msats.saturating_mul(ppm) / 1_000_000));
+    }
+    if let Some(ppm) = input.strip_suffix("ppm") {
+        let ppm = ppm.trim().parse::<u64>().map_err(|_| "Invalid max fee".to_string())?;
+        return Ok(Some(amount_msats.saturating_mul(ppm) / 1_000_000));
+    }
+    let sats = input.parse::<u64>().map_err(|_| "Invalid max fee".to_string())?;
+    Ok(Some(sats * 1000))
+}
+
+/// Time left to pay `invoice`, e.g. "Expires in 59:12", read from the invoice itself
+pub fn expiry_label(invoice: &Bolt11Invoice) -> String {
+    if invoice.is_expired() {
+        return "Expired".to_string();
+    }
+    let secs = invoice.duration_until_expiry().as_secs();
+    if secs >= 3600 {
+        format!("Expires in {}:{:02}:{:02}", secs / 3600, secs % 3600 / 60, secs % 60)
+    } else {
+        format!("Expires in {}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+/// Sats typed with or without separators, or in BTC with a "BTC" suffix
+pub fn parse_sats(input: &str) -> Result<u64, String> {
+    Bitcoin::parse(input, AmountUnit::Sats).map(Bitcoin::to_sats)
+}
+
+/// A `pubkey@host:port` peer URI
+pub fn parse_peer(s: &str) -> Option<(PublicKey, SocketAddress)> {
+    let (pubkey, address) = s.trim().split_once('@')?;
+    Some((PublicKey::from_str(pubkey).ok()?, SocketAddress::from_str(address).ok()?))
+}
+
+/// Why `invoice` can't be paid to this node with its current `channels`, if it can't.
+/// Nodes with only private channels rely on the invoice's route hints to be found.
+pub fn unpayable_reason(invoice: &Bolt11Invoice, channels: &[ChannelDetails]) -> Option<String> {
+    let amount_msats = invoice.amount_milli_satoshis().unwrap_or(0);
+    let receiving: Vec<&ChannelDetails> =
+        channels.iter().filter(|c| c.is_usable && c.inbound_capacity_msat >= amount_msats).collect();
+    if receiving.is_empty() {
+        return Some("No usable channel can receive this amount; the invoice can't be paid yet".to_string());
+    }
+    if receiving.iter().all(|c| !c.is_announced) && invoice.route_hints().is_empty() {
+        return Some("Only private channels and no route hints; payers won't find a route".to_string());
+    }
+    None
+}
+
+fn amount_label(in_usd: bool) -> &'static str {
+    if in_usd {
+        "Amount (USD):"
+    } else {
+        "Amount (sats):"
+    }
+}
@@ -0,0 +1,575 @@
+use crate::encryption;
+use crate::price_feeds::PriceStats;
+use crate::stable::{self, StabilityAction};
+use crate::shutdown;
+use crate::types::{Currency, StableChannel};
+use ldk_node::lightning::events::ClosureReason;
+use ldk_node::lightning::ln::types::ChannelId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info, warn};
+use ureq::Agent;
+
+/// Comma-separated webhook URLs; webhooks are off when unset
+pub const WEBHOOKS_ENV: &str = "STABLE_CHANNELS_WEBHOOKS";
+
+/// Telegram bot token and the chat alerts go to; both are needed to enable Telegram
+pub const TELEGRAM_TOKEN_ENV: &str = "STABLE_CHANNELS_TELEGRAM_TOKEN";
+pub const TELEGRAM_CHAT_ID_ENV: &str = "STABLE_CHANNELS_TELEGRAM_CHAT_ID";
+
+/// ntfy topic alerts are published to, and the server if not ntfy.sh
+pub const NTFY_TOPIC_ENV: &str = "STABLE_CHANNELS_NTFY_TOPIC";
+pub const NTFY_SERVER_ENV: &str = "STABLE_CHANNELS_NTFY_SERVER";
+const DEFAULT_NTFY_SERVER: &str = "https://ntfy.sh";
+
+/// Alert thresholds, overridable through the environment
+pub const ALERT_OFF_PEG_PERCENT_ENV: &str = "STABLE_CHANNELS_ALERT_OFF_PEG_PERCENT";
+pub const ALERT_OFF_PEG_MINUTES_ENV: &str = "STABLE_CHANNELS_ALERT_OFF_PEG_MINUTES";
+pub const ALERT_STALE_PRICE_MINUTES_ENV: &str = "STABLE_CHANNELS_ALERT_STALE_PRICE_MINUTES";
+/// Alert once the price can move less than this percent before the paying side runs out
+pub const ALERT_RUNWAY_PERCENT_ENV: &str = "STABLE_CHANNELS_ALERT_RUNWAY_PERCENT";
+const DEFAULT_OFF_PEG_PERCENT: f64 = 5.0;
+const DEFAULT_OFF_PEG_MINUTES: u64 = 10;
+const DEFAULT_STALE_PRICE_MINUTES: u64 = 10;
+
+const ALERT_STATE_FILE_NAME: &str = "alert_state.json";
+
+/// Notifications waiting for delivery before new ones are dropped
+const QUEUE_LEN: usize = 100;
+
+/// Delivery attempts per URL, with the delay doubling after each failure
+const MAX_ATTEMPTS: u32 = 4;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// JSON body POSTed to each webhook
+#[derive(Debug, Clone, Serialize)]
+pub struct StabilityNotification {
+    pub channel_id: String,
+    pub action: &'static str,
+    pub message: String,
+    pub amount_msats: Option<u64>,
+    /// Currency of `price` and `deviation_usd`
+    pub currency: Currency,
+    pub price: f64,
+    pub deviation_usd: f64,
+    pub deviation_percent: f64,
+    pub timestamp: u64,
+}
+
+impl StabilityNotification {
+    pub fn new(sc: &StableChannel, action: &StabilityAction, price: f64) -> Self {
+        let amount_msats = match action {
+            StabilityAction::Paid { amount_msats, .. } | StabilityAction::NeedsApproval { amount_msats } => {
+                Some(*amount_msats)
+            }
+            _ => None,
+        };
+        Self {
+            channel_id: sc.channel_id.to_string(),
+            action: action_name(action),
+            message: action.to_string(),
+            amount_msats,
+            currency: sc.currency,
+            price,
+            deviation_usd: stable::dollars_from_par(sc).to_f64(),
+            deviation_percent: stable::percent_from_par(sc),
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        }
+    }
+}
+
+fn action_name(action: &StabilityAction) -> &'static str {
+    match action {
+        StabilityAction::Stable => "stable",
+        StabilityAction::HighRisk(_) => "high_risk",
+        StabilityAction::WaitingOnCounterparty => "waiting_on_counterparty",
+        StabilityAction::Paid { .. } => "paid",
+        StabilityAction::PaymentFailed(_) => "payment_failed",
+        StabilityAction::PaymentPending(_) => "payment_pending",
+        StabilityAction::NeedsApproval { .. } => "needs_approval",
+        StabilityAction::RateLimited { .. } => "rate_limited",
+        StabilityAction::CannotRebalance => "cannot_rebalance",
+        StabilityAction::NoPrice => "no_price",
+        StabilityAction::PeerDisconnected => "peer_disconnected",
+        StabilityAction::CircuitBreakerTripped(_) => "circuit_breaker_tripped",
+        StabilityAction::Suspended { .. } => "suspended",
+    }
+}
+
+/// Outcomes that repeat on every check while nothing changes aren't worth a webhook
+fn is_notable(action: &StabilityAction) -> bool {
+    !matches!(
+        action,
+        StabilityAction::Stable
+            | StabilityAction::WaitingOnCounterparty
+            | StabilityAction::PaymentPending(_)
+            | StabilityAction::Suspended { .. }
+    )
+}
+
+/// Cloneable handle for queueing notifications from any thread. Never blocks: when the
+/// queue is full the notification is dropped.
+#[derive(Clone)]
+pub struct NotifyQueue(Option<SyncSender<StabilityNotification>>);
+
+impl NotifyQueue {
+    /// Queues a notification for `action` if it is worth one
+    pub fn notify(&self, sc: &StableChannel, action: &StabilityAction, price: f64) {
+        let Some(sender) = &self.0 else {
+            return;
+        };
+        if !is_notable(action) {
+            return;
+        }
+        match sender.try_send(StabilityNotification::new(sc, action, price)) {
+            Ok(()) => {}
+            Err(TrySendError::Full(n)) => warn!("Webhook queue full; dropping {} notification for {}", n.action, n.channel_id),
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+}
+
+/// Delivers stability outcomes to webhooks on a background thread, so a slow or dead
+/// endpoint never holds up the stability loop
+pub struct WebhookNotifier {
+    queue: NotifyQueue,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl WebhookNotifier {
+    /// Starts delivering to the URLs in `WEBHOOKS_ENV`, or does nothing if it is unset
+    pub fn from_env() -> Self {
+        let urls: Vec<String> = std::env::var(WEBHOOKS_ENV)
+            .unwrap_or_default()
+            .split(',')
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty())
+            .collect();
+        Self::start(urls)
+    }
+
+    pub fn start(urls: Vec<String>) -> Self {
+        if urls.is_empty() {
+            return Self { queue: NotifyQueue(None), handle: None };
+        }
+
+        for url in &urls {
+            info!("Sending stability webhooks to {}", redact(url));
+        }
+
+        let (sender, receiver) = mpsc::sync_channel::<StabilityNotification>(QUEUE_LEN);
+        let handle = std::thread::spawn(move || {
+            let agent = Agent::new();
+            // Ends once every queue handle has been dropped
+            for notification in receiver {
+                for url in &urls {
+                    deliver(&agent, url, &notification);
+                }
+            }
+            debug!("Webhook thread stopped");
+        });
+
+        Self { queue: NotifyQueue(Some(sender)), handle: Some(handle) }
+    }
+
+    pub fn queue(&self) -> NotifyQueue {
+        self.queue.clone()
+    }
+
+    pub fn notify(&self, sc: &StableChannel, action: &StabilityAction, price: f64) {
+        self.queue.notify(sc, action, price);
+    }
+
+    /// Stops accepting notifications and waits for the queued ones to go out
+    pub fn stop(&mut self) {
+        self.queue = NotifyQueue(None);
+        if let Some(handle) = self.handle.take() {
+            shutdown::join_with_timeout(handle, shutdown::THREAD_JOIN_TIMEOUT);
+        }
+    }
+}
+
+fn deliver(agent: &Agent, url: &str, notification: &StabilityNotification) {
+    let target = format!("webhook {}", redact(url));
+    let result = with_backoff(&target, || {
+        agent.post(url).timeout(REQUEST_TIMEOUT).send_json(notification).map(|_| ()).map_err(describe_error)
+    });
+    if let Err(e) = result {
+        warn!("Giving up on {} notification for {} to {}: {}", notification.action, notification.channel_id, target, e);
+    }
+}
+
+/// Runs `attempt` up to `MAX_ATTEMPTS` times, doubling the delay after each failure
+fn with_backoff(target: &str, mut attempt: impl FnMut() -> Result<(), String>) -> Result<(), String> {
+    let mut backoff = INITIAL_BACKOFF;
+    let mut tries = 1;
+    loop {
+        match attempt() {
+            Ok(()) => return Ok(()),
+            Err(e) if tries == MAX_ATTEMPTS => return Err(e),
+            Err(e) => debug!("Sending to {} failed ({}), retrying in {:?}", target, e, backoff),
+        }
+        std::thread::sleep(backoff);
+        backoff *= 2;
+        tries += 1;
+    }
+}
+
+/// ureq's own error messages include the URL, which may hold a secret, so only the
+/// status or error kind is kept
+fn describe_error(e: ureq::Error) -> String {
+    match e {
+        ureq::Error::Status(code, _) => format!("HTTP {}", code),
+        ureq::Error::Transport(transport) => transport.kind().to_string(),
+    }
+}
+
+/// Scheme and host only: webhook URLs often carry a secret in the path, query or userinfo
+fn redact(url: &str) -> String {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let host = authority.rsplit('@').next().unwrap_or(authority);
+    if scheme.is_empty() {
+        format!("{}/…", host)
+    } else {
+        format!("{}://{}/…", scheme, host)
+    }
+}
+
+/// A push channel alerts can be sent through
+pub trait Notifier: Send {
+    /// Name for logs; must not contain credentials
+    fn name(&self) -> String;
+    fn send(&self, agent: &Agent, title: &str, message: &str) -> Result<(), String>;
+}
+
+pub struct TelegramNotifier {
+    token: String,
+    chat_id: String,
+}
+
+impl TelegramNotifier {
+    pub fn new(token: String, chat_id: String) -> Self {
+        Self { token, chat_id }
+    }
+}
+
+impl Notifier for TelegramNotifier {
+    fn name(&self) -> String {
+        format!("Telegram chat {}", self.chat_id)
+    }
+
+    fn send(&self, agent: &Agent, title: &str, message: &str) -> Result<(), String> {
+        let url = format!("https://api.telegram.org/bot{}/sendMessage", self.token);
+        agent
+            .post(&url)
+            .timeout(REQUEST_TIMEOUT)
+            .send_json(serde_json::json!({
+                "chat_id": self.chat_id,
+                "text": format!("{}\n{}", title, message),
+            }))
+            .map(|_| ())
+            .map_err(describe_error)
+    }
+}
+
+pub struct NtfyNotifier {
+    server: String,
+    topic: String,
+}
+
+impl NtfyNotifier {
+    pub fn new(server: String, topic: String) -> Self {
+        Self { server: server.trim_end_matches('/').to_string(), topic }
+    }
+}
+
+impl Notifier for NtfyNotifier {
+    fn name(&self) -> String {
+        // The topic name is the only access control on ntfy, so it stays out of logs
+        format!("ntfy {}", redact(&self.server))
+    }
+
+    fn send(&self, agent: &Agent, title: &str, message: &str) -> Result<(), String> {
+        agent
+            .post(&format!("{}/{}", self.server, self.topic))
+            .timeout(REQUEST_TIMEOUT)
+            .set("Title", title)
+            .send_string(message)
+            .map(|_| ())
+            .map_err(describe_error)
+    }
+}
+
+/// Telegram and ntfy notifiers for whichever of them are configured in the environment
+pub fn notifiers_from_env() -> Vec<Box<dyn Notifier>> {
+    let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+    if let (Ok(token), Ok(chat_id)) = (std::env::var(TELEGRAM_TOKEN_ENV), std::env::var(TELEGRAM_CHAT_ID_ENV)) {
+        notifiers.push(Box::new(TelegramNotifier::new(token, chat_id)));
+    }
+    if let Ok(topic) = std::env::var(NTFY_TOPIC_ENV) {
+        let server = std::env::var(NTFY_SERVER_ENV).unwrap_or_else(|_| DEFAULT_NTFY_SERVER.to_string());
+        notifiers.push(Box::new(NtfyNotifier::new(server, topic)));
+    }
+    notifiers
+}
+
+/// When a breach turns into an alert
+#[derive(Debug, Clone, Copy)]
+pub struct AlertThresholds {
+    pub off_peg_percent: f64,
+    pub off_peg_secs: u64,
+    pub stale_price_secs: u64,
+    pub runway_percent: f64,
+}
+
+impl AlertThresholds {
+    pub fn from_env() -> Self {
+        fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+            std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+        }
+        Self {
+            off_peg_percent: env_or(ALERT_OFF_PEG_PERCENT_ENV, DEFAULT_OFF_PEG_PERCENT),
+            off_peg_secs: env_or(ALERT_OFF_PEG_MINUTES_ENV, DEFAULT_OFF_PEG_MINUTES) * 60,
+            stale_price_secs: env_or(ALERT_STALE_PRICE_MINUTES_ENV, DEFAULT_STALE_PRICE_MINUTES) * 60,
+            runway_percent: env_or(ALERT_RUNWAY_PERCENT_ENV, stable::DEFAULT_LOW_RUNWAY_PERCENT),
+        }
+    }
+}
+
+/// A condition that is currently breached
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AlertState {
+    /// Unix seconds when the breach was first seen
+    since: u64,
+    /// An alert has gone out for this breach
+    sent: bool,
+}
+
+struct Alert {
+    title: String,
+    message: String,
+}
+
+/// Push alerts for threshold breaches. Each condition alerts once per breach and is
+/// re-armed when it clears; the state is saved so a restart doesn't alert again.
+pub struct Alerts {
+    thresholds: AlertThresholds,
+    state: HashMap<String, AlertState>,
+    state_path: PathBuf,
+    queue: Option<SyncSender<Alert>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Alerts {
+    /// Starts the configured notifiers; without any, breaches are not tracked
+    pub fn load(data_dir: &Path) -> Self {
+        let state_path = data_dir.join(ALERT_STATE_FILE_NAME);
+        let notifiers = notifiers_from_env();
+        if notifiers.is_empty() {
+            return Self {
+                thresholds: AlertThresholds::from_env(),
+                state: HashMap::new(),
+                state_path,
+                queue: None,
+                handle: None,
+            };
+        }
+
+        let state = if state_path.exists() {
+            match encryption::read_state_file(&state_path)
+                .map_err(|e| e.to_string())
+                .and_then(|contents| serde_json::from_str(&contents).map_err(|e| e.to_string()))
+            {
+                Ok(state) => state,
+                Err(e) => {
+                    error!("Error reading {}: {}", state_path.display(), e);
+                    HashMap::new()
+                }
+            }
+        } else {
+            HashMap::new()
+        };
+
+        for notifier in &notifiers {
+            info!("Sending alerts to {}", notifier.name());
+        }
+
+        let (sender, receiver) = mpsc::sync_channel::<Alert>(QUEUE_LEN);
+        let handle = std::thread::spawn(move || {
+            let agent = Agent::new();
+            // Ends once `stop` drops the sender
+            for alert in receiver {
+                for notifier in &notifiers {
+                    let name = notifier.name();
+                    if let Err(e) = with_backoff(&name, || notifier.send(&agent, &alert.title, &alert.message)) {
+                        warn!("Giving up on alert \"{}\" to {}: {}", alert.title, name, e);
+                    }
+                }
+            }
+            debug!("Alert thread stopped");
+        });
+
+        Self { thresholds: AlertThresholds::from_env(), state, state_path, queue: Some(sender), handle: Some(handle) }
+    }
+
+    /// Runway below which a channel is nearly exhausted
+    pub fn low_runway_percent(&self) -> f64 {
+        self.thresholds.runway_percent
+    }
+
+    /// Off-peg and risk suspension alerts for a stable channel, after its stability check
+    pub fn check_channel(&mut self, sc: &StableChannel) {
+        let percent = stable::percent_from_par(sc);
+        self.observe(
+            format!("off_peg:{}", sc.channel_id),
+            percent > self.thresholds.off_peg_percent,
+            self.thresholds.off_peg_secs,
+            "Stable channel off peg",
+            || {
+                format!(
+                    "Channel {} is {:.2}% from its {} target",
+                    sc.channel_id,
+                    percent,
+                    sc.currency.format(sc.expected_usd.to_f64())
+                )
+            },
+        );
+        self.observe(
+            format!("risk_suspended:{}", sc.channel_id),
+            sc.risk_level > sc.max_risk_level,
+            0,
+            "Stability payments suspended",
+            || format!("Channel {} risk level {} exceeds its limit of {}", sc.channel_id, sc.risk_level, sc.max_risk_level),
+        );
+        self.observe(
+            format!("circuit_breaker:{}", sc.channel_id),
+            sc.suspended_until.is_some(),
+            0,
+            "Stable channel paused by circuit breaker",
+            || {
+                format!(
+                    "Channel {} paused stability payments: {}. Resume it once the price is confirmed.",
+                    sc.channel_id,
+                    sc.suspension_reason.as_deref().unwrap_or("price anomaly")
+                )
+            },
+        );
+        let runway = sc.runway.filter(|r| r.min_percent() < self.thresholds.runway_percent);
+        self.observe(
+            format!("low_runway:{}", sc.channel_id),
+            runway.is_some(),
+            0,
+            "Stable channel nearly exhausted",
+            || {
+                format!(
+                    "Channel {} can only follow the price {} before the paying side runs out",
+                    sc.channel_id,
+                    runway.as_ref().map(stable::format_runway).unwrap_or_default()
+                )
+            },
+        );
+    }
+
+    pub fn check_price_feed(&mut self, stats: &PriceStats) {
+        self.observe(
+            "stale_price".to_string(),
+            stats.age_secs > self.thresholds.stale_price_secs,
+            0,
+            "Price feed stale",
+            || format!("No successful price fetch for {} minutes", stats.age_secs / 60),
+        );
+    }
+
+    /// Alerts if the channel closed without a cooperative close
+    pub fn channel_closed(&mut self, channel_id: &ChannelId, reason: Option<&ClosureReason>) {
+        let force_closed = !matches!(
+            reason,
+            None | Some(ClosureReason::LegacyCooperativeClosure)
+                | Some(ClosureReason::CounterpartyInitiatedCooperativeClosure)
+                | Some(ClosureReason::LocallyInitiatedCooperativeClosure)
+        );
+        // The close event itself only arrives once, so there is no breach state to keep
+        if force_closed {
+            self.send(Alert {
+                title: "Channel force-closed".to_string(),
+                message: format!(
+                    "Channel {} was force-closed: {}",
+                    channel_id,
+                    reason.map(|r| r.to_string()).unwrap_or_default()
+                ),
+            });
+        }
+    }
+
+    /// Alerts that a stable channel was moved to a new channel id
+    pub fn channel_migrated(&mut self, old_channel_id: &ChannelId, new_channel_id: &ChannelId) {
+        self.send(Alert {
+            title: "Stable channel re-attached".to_string(),
+            message: format!(
+                "Channel {} disappeared without closing; its stable designation moved to {}",
+                old_channel_id, new_channel_id
+            ),
+        });
+    }
+
+    /// Sends an alert once `active` has held for `hold_secs`, and forgets the breach once
+    /// it clears
+    fn observe(&mut self, key: String, active: bool, hold_secs: u64, title: &str, message: impl FnOnce() -> String) {
+        if self.queue.is_none() {
+            return;
+        }
+
+        if !active {
+            if self.state.remove(&key).is_some() {
+                self.save();
+            }
+            return;
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let state = self.state.entry(key).or_insert(AlertState { since: now, sent: false });
+        if state.sent || now.saturating_sub(state.since) < hold_secs {
+            return;
+        }
+        state.sent = true;
+
+        self.send(Alert { title: title.to_string(), message: message() });
+        self.save();
+    }
+
+    fn send(&self, alert: Alert) {
+        let Some(queue) = &self.queue else {
+            return;
+        };
+        info!("Alert: {}: {}", alert.title, alert.message);
+        if let Err(TrySendError::Full(alert)) = queue.try_send(alert) {
+            warn!("Alert queue full; dropping \"{}\"", alert.title);
+        }
+    }
+
+    fn save(&self) {
+        match serde_json::to_string(&self.state) {
+            Ok(json) => {
+                if let Err(e) = encryption::write_state_file(&self.state_path, &json) {
+                    error!("Error writing {}: {}", self.state_path.display(), e);
+                }
+            }
+            Err(e) => error!("Error serializing alert state: {}", e),
+        }
+    }
+
+    /// Stops accepting alerts and waits for the queued ones to go out
+    pub fn stop(&mut self) {
+        self.queue.take();
+        if let Some(handle) = self.handle.take() {
+            shutdown::join_with_timeout(handle, shutdown::THREAD_JOIN_TIMEOUT);
+        }
+    }
+}
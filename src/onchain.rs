@@ -0,0 +1,201 @@
+use crate::ui_util;
+use ldk_node::bitcoin::Txid;
+use ldk_node::payment::{ConfirmationStatus, PaymentDirection, PaymentKind, PaymentStatus};
+use ldk_node::{BalanceDetails, Node, PendingSweepBalance};
+use std::time::{Duration, Instant};
+
+/// Confirmation counts change with every block, so the cache also expires on a timer
+const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Rows shown in the activity list
+const MAX_ROWS: usize = 100;
+
+/// On-chain balance split the way ldk-node reports it
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OnchainBalance {
+    pub total_sats: u64,
+    pub spendable_sats: u64,
+    /// Held back to fee-bump anchor channel closes
+    pub anchor_reserve_sats: u64,
+    /// Still being swept to the wallet from closed channels
+    pub closing_sweep_sats: u64,
+}
+
+impl OnchainBalance {
+    /// Unconfirmed funds that are neither spendable yet nor part of the anchor reserve
+    pub fn pending_sats(&self) -> u64 {
+        self.total_sats
+            .saturating_sub(self.spendable_sats)
+            .saturating_sub(self.anchor_reserve_sats)
+    }
+}
+
+struct OnchainTx {
+    txid: Txid,
+    direction: PaymentDirection,
+    status: PaymentStatus,
+    amount_sats: Option<u64>,
+    /// Zero while unconfirmed
+    confirmations: u32,
+    timestamp: u64,
+}
+
+/// On-chain transactions and balance breakdown, cached between refreshes
+pub struct OnchainActivity {
+    /// None when the chain source has no block explorer
+    explorer_url: Option<String>,
+    balance: OnchainBalance,
+    transactions: Vec<OnchainTx>,
+    stale: bool,
+    last_refresh: Option<Instant>,
+}
+
+impl OnchainActivity {
+    pub fn new(esplora_url: Option<&str>) -> Self {
+        Self {
+            explorer_url: esplora_url.map(explorer_url),
+            balance: OnchainBalance::default(),
+            transactions: Vec::new(),
+            stale: true,
+            last_refresh: None,
+        }
+    }
+
+    /// Takes the latest balance breakdown and schedules a transaction refresh
+    pub fn update_balance(&mut self, balances: &BalanceDetails) {
+        self.balance = OnchainBalance {
+            total_sats: balances.total_onchain_balance_sats,
+            spendable_sats: balances.spendable_onchain_balance_sats,
+            anchor_reserve_sats: balances.total_anchor_channels_reserve_sats,
+            closing_sweep_sats: pending_sweep_sats(balances),
+        };
+        self.stale = true;
+    }
+
+    /// Marks the transaction list as out of date, e.g. after a funding transaction
+    pub fn invalidate(&mut self) {
+        self.stale = true;
+    }
+
+    fn refresh_if_needed(&mut self, node: &Node) {
+        let expired = self.last_refresh.map_or(true, |t| t.elapsed() >= REFRESH_INTERVAL);
+        if !self.stale && !expired {
+            return;
+        }
+
+        self.update_balance(&node.list_balances());
+        let best_height = node.status().current_best_block.height;
+        let mut transactions: Vec<OnchainTx> = node
+            .list_payments()
+            .into_iter()
+            .filter_map(|p| match p.kind {
+                PaymentKind::Onchain { txid, status } => Some(OnchainTx {
+                    txid,
+                    direction: p.direction,
+                    status: p.status,
+                    amount_sats: p.amount_msat.map(|m| m / 1000),
+                    confirmations: match status {
+                        ConfirmationStatus::Confirmed { height, .. } => best_height.saturating_sub(height) + 1,
+                        ConfirmationStatus::Unconfirmed => 0,
+                    },
+                    timestamp: p.latest_update_timestamp,
+                }),
+                _ => None,
+            })
+            .collect();
+        transactions.sort_by(|a, b| a.confirmations.cmp(&b.confirmations).then(b.timestamp.cmp(&a.timestamp)));
+
+        self.transactions = transactions;
+        self.stale = false;
+        self.last_refresh = Some(Instant::now());
+    }
+
+    /// Spendable, pending and reserved amounts beneath the on-chain total
+    pub fn show_balance_breakdown(&self, ui: &mut egui::Ui) {
+        ui.label(
+            egui::RichText::new(format!(
+                "Spendable {} sats | Pending {} sats | Anchor reserve {} sats",
+                self.balance.spendable_sats,
+                self.balance.pending_sats(),
+                self.balance.anchor_reserve_sats
+            ))
+            .size(12.0)
+            .color(egui::Color32::GRAY),
+        );
+        if self.balance.closing_sweep_sats > 0 {
+            ui.label(
+                egui::RichText::new(format!("Sweeping {} sats from closed channels", self.balance.closing_sweep_sats))
+                    .size(12.0)
+                    .color(egui::Color32::YELLOW),
+            );
+        }
+    }
+
+    /// Collapsible list of on-chain transactions with confirmation counts and explorer links
+    pub fn show(&mut self, ui: &mut egui::Ui, node: &Node) {
+        egui::CollapsingHeader::new("On-chain Activity").show(ui, |ui| {
+            self.refresh_if_needed(node);
+            self.show_balance_breakdown(ui);
+
+            if self.transactions.is_empty() {
+                ui.label("No on-chain transactions yet.");
+                return;
+            }
+
+            egui::ScrollArea::vertical().max_height(250.0).show(ui, |ui| {
+                egui::Grid::new("onchain_activity").striped(true).num_columns(4).show(ui, |ui| {
+                    for tx in self.transactions.iter().take(MAX_ROWS) {
+                        let arrow = match tx.direction {
+                            PaymentDirection::Inbound => "⬇",
+                            PaymentDirection::Outbound => "⬆",
+                        };
+                        ui.label(match tx.amount_sats {
+                            Some(sats) => format!("{} {} sats", arrow, sats),
+                            None => arrow.to_string(),
+                        });
+
+                        if tx.status == PaymentStatus::Failed {
+                            ui.colored_label(egui::Color32::RED, "failed");
+                        } else if tx.confirmations == 0 {
+                            ui.colored_label(egui::Color32::YELLOW, "unconfirmed");
+                        } else {
+                            ui.label(format!("{} conf", tx.confirmations));
+                        }
+
+                        let txid = tx.txid.to_string();
+                        ui.monospace(format!("{}...{}", &txid[..8], &txid[txid.len() - 8..]));
+                        match &self.explorer_url {
+                            Some(url) => {
+                                ui.hyperlink_to("explorer", format!("{}/tx/{}", url, txid));
+                            }
+                            None => {
+                                ui_util::copy_button(ui, egui::Button::new("Copy txid").small(), || txid.clone());
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+    }
+}
+
+/// Funds from closed channels that haven't reached the on-chain wallet yet
+pub fn pending_sweep_sats(balances: &BalanceDetails) -> u64 {
+    balances
+        .pending_balances_from_channel_closures
+        .iter()
+        .map(|b| match b {
+            PendingSweepBalance::PendingBroadcast { amount_satoshis, .. }
+            | PendingSweepBalance::BroadcastAwaitingConfirmation { amount_satoshis, .. }
+            | PendingSweepBalance::AwaitingThresholdConfirmations { amount_satoshis, .. } => *amount_satoshis,
+        })
+        .sum()
+}
+
+/// Explorer base URL for an esplora API URL, e.g. `https://mutinynet.com/api/` becomes
+/// `https://mutinynet.com`
+fn explorer_url(esplora_url: &str) -> String {
+    let trimmed = esplora_url.trim_end_matches('/');
+    trimmed.strip_suffix("/api").unwrap_or(trimmed).to_string()
+}
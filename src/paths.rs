@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{error, info, warn};
+
+const APP_DIR_NAME: &str = "com.stablechannels";
+
+/// Where data lived before platform directories were used, relative to the working directory
+const LEGACY_DATA_DIR: &str = "data";
+
+lazy_static::lazy_static! {
+    static ref RESOLVED_DIRS: Mutex<HashMap<String, PathBuf>> = Mutex::new(HashMap::new());
+}
+
+/// The value of `--data-dir <path>` or `--data-dir=<path>`, if given
+fn data_dir_override() -> Option<PathBuf> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--data-dir" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--data-dir=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+fn platform_data_dir(mode: &str) -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(|| PathBuf::from(LEGACY_DATA_DIR))
+        .join(APP_DIR_NAME)
+        .join(mode)
+}
+
+/// The node data directory for `mode` ("user", "lsp" or "exchange"). `--data-dir` wins;
+/// otherwise the platform data directory is used, offering once to move an old
+/// `./data/<mode>` directory there. Resolved once per mode and cached.
+pub fn data_dir(mode: &str) -> PathBuf {
+    let mut resolved = RESOLVED_DIRS.lock().unwrap();
+    if let Some(path) = resolved.get(mode) {
+        return path.clone();
+    }
+
+    let path = match data_dir_override() {
+        Some(path) => path,
+        None => resolve_with_migration(mode),
+    };
+    info!("Using data directory {}", path.display());
+
+    resolved.insert(mode.to_string(), path.clone());
+    path
+}
+
+fn resolve_with_migration(mode: &str) -> PathBuf {
+    let target = platform_data_dir(mode);
+    let legacy = Path::new(LEGACY_DATA_DIR).join(mode);
+
+    if !legacy.is_dir() || target.exists() {
+        return target;
+    }
+
+    if !confirm_migration(&legacy, &target) {
+        warn!("Keeping data in {} for this run", legacy.display());
+        return legacy;
+    }
+
+    if let Some(parent) = target.parent() {
+        if let Err(e) = std::fs::create_dir_all(parent) {
+            error!("Failed to create {}: {}", parent.display(), e);
+            return legacy;
+        }
+    }
+
+    match std::fs::rename(&legacy, &target) {
+        Ok(()) => {
+            info!("Moved {} to {}", legacy.display(), target.display());
+            target
+        }
+        Err(e) => {
+            error!("Failed to move {} to {}: {}; keeping the old location", legacy.display(), target.display(), e);
+            legacy
+        }
+    }
+}
+
+/// Asks on the terminal whether to move the old directory. Without a terminal the old
+/// directory is kept so an existing wallet is never silently replaced by a new one.
+fn confirm_migration(legacy: &Path, target: &Path) -> bool {
+    if !std::io::stdin().is_terminal() {
+        warn!(
+            "Found existing node data in {}; run from a terminal to move it to {}",
+            legacy.display(),
+            target.display()
+        );
+        return false;
+    }
+
+    print!("Found node data in {}. Move it to {}? [Y/n] ", legacy.display(), target.display());
+    let _ = std::io::stdout().flush();
+    let mut input = String::new();
+    if std::io::stdin().read_line(&mut input).is_err() {
+        return false;
+    }
+    !input.trim().eq_ignore_ascii_case("n")
+}
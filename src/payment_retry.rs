@@ -0,0 +1,268 @@
+use crate::encryption;
+use ldk_node::payment::SendingParameters;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{error, info, warn};
+
+const RETRY_POLICY_FILE_NAME: &str = "retry_policy.json";
+
+lazy_static::lazy_static! {
+    /// The policy stability payments are sent under, set once the data dir is known
+    static ref POLICY: Mutex<RetryPolicy> = Mutex::new(RetryPolicy::default());
+}
+
+/// How a failed stability payment is retried. Each retry raises the routing fee and CLTV
+/// budgets; once those are used up the amount is sent in smaller pieces, and if even those
+/// fail it is queued as a missed settlement.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Retries of the whole amount after the first attempt
+    pub max_retries: u32,
+    /// Routing fee budget of the first attempt, in parts per million of the amount
+    pub base_fee_ppm: u64,
+    /// Smallest routing fee budget, so small payments can pay a base fee
+    pub min_fee_msats: u64,
+    /// CLTV budget of the first attempt, in blocks
+    pub base_cltv_expiry_delta: u32,
+    /// Largest CLTV budget any attempt gets
+    pub max_cltv_expiry_delta: u32,
+    /// Each retry multiplies the fee and CLTV budgets by this
+    pub budget_multiplier: f64,
+    /// Most pieces the amount is split into; zero never splits
+    pub max_split_parts: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_fee_ppm: 5_000,
+            min_fee_msats: 1_000,
+            base_cltv_expiry_delta: 1_008,
+            max_cltv_expiry_delta: 2_016,
+            budget_multiplier: 2.0,
+            max_split_parts: 4,
+        }
+    }
+}
+
+/// What the next attempt at a stability payment should do
+#[derive(Debug, Clone)]
+pub enum AttemptPlan {
+    /// Send the whole amount within these budgets
+    Whole(SendingParameters),
+    /// Send one piece of `1 / parts` of the amount; the rest is left for later checks
+    Split { parts: u32, params: SendingParameters },
+    /// Every attempt has failed; queue the amount as a missed settlement
+    GiveUp,
+}
+
+impl RetryPolicy {
+    fn path(data_dir: &Path) -> PathBuf {
+        data_dir.join(RETRY_POLICY_FILE_NAME)
+    }
+
+    /// The saved policy, or the default if none is saved or the saved one is invalid
+    pub fn load(data_dir: &Path) -> Self {
+        let path = Self::path(data_dir);
+        if !path.exists() {
+            return Self::default();
+        }
+        let policy = match encryption::read_state_file(&path).map(|s| serde_json::from_str::<Self>(&s)) {
+            Ok(Ok(policy)) => policy,
+            Ok(Err(e)) => {
+                error!("Ignoring unreadable {}: {}", path.display(), e);
+                return Self::default();
+            }
+            Err(e) => {
+                error!("Failed to read {}: {}", path.display(), e);
+                return Self::default();
+            }
+        };
+        match policy.validate() {
+            Ok(()) => policy,
+            Err(e) => {
+                warn!("Ignoring invalid retry policy in {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.budget_multiplier < 1.0 {
+            return Err("Budget multiplier must be at least 1".to_string());
+        }
+        if self.base_cltv_expiry_delta == 0 || self.base_cltv_expiry_delta > self.max_cltv_expiry_delta {
+            return Err("Base CLTV budget must be positive and at most the maximum".to_string());
+        }
+        Ok(())
+    }
+
+    /// What attempt number `attempt` (zero for the first) at sending `amount_msats` does.
+    /// Splitting only helps when no route could carry the amount, so `no_route` says
+    /// whether that is why the last attempt failed.
+    pub fn plan(&self, attempt: u32, amount_msats: u64, no_route: bool) -> AttemptPlan {
+        if attempt <= self.max_retries {
+            return AttemptPlan::Whole(self.sending_parameters(attempt, amount_msats));
+        }
+        if !no_route {
+            return AttemptPlan::GiveUp;
+        }
+        // Halve the piece with each further failure
+        let split_round = attempt - self.max_retries;
+        let parts = 2u32.saturating_pow(split_round);
+        if parts > self.max_split_parts {
+            return AttemptPlan::GiveUp;
+        }
+        let params = self.sending_parameters(self.max_retries, amount_msats / parts as u64);
+        AttemptPlan::Split { parts, params }
+    }
+
+    /// Fee and CLTV budgets for attempt `attempt` at sending `amount_msats`
+    pub fn sending_parameters(&self, attempt: u32, amount_msats: u64) -> SendingParameters {
+        let scale = self.budget_multiplier.powi(attempt as i32);
+        let fee_msats = (amount_msats as f64 * self.base_fee_ppm as f64 / 1_000_000.0 * scale) as u64;
+        let cltv = (self.base_cltv_expiry_delta as f64 * scale).min(self.max_cltv_expiry_delta as f64) as u32;
+        SendingParameters {
+            max_total_routing_fee_msat: Some(Some(fee_msats.max(self.min_fee_msats))),
+            max_total_cltv_expiry_delta: Some(cltv),
+            max_path_count: None,
+            max_channel_saturation_power_of_half: None,
+        }
+    }
+}
+
+/// Loads the policy from `data_dir` and makes it the one stability payments use
+pub fn init(data_dir: &Path) {
+    let policy = RetryPolicy::load(data_dir);
+    info!(
+        max_retries = policy.max_retries,
+        max_split_parts = policy.max_split_parts,
+        "Stability payment retry policy loaded"
+    );
+    *POLICY.lock().unwrap() = policy;
+}
+
+pub fn policy() -> RetryPolicy {
+    POLICY.lock().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Why a mock send failed: the route wanted more fee than budgeted, or no route was big
+    /// enough for the amount
+    #[derive(Debug, PartialEq)]
+    enum SendError {
+        FeeTooLow,
+        NoRoute,
+    }
+
+    /// A network whose only routes charge `fee_msats` and carry at most `max_amount_msats`
+    struct MockSender {
+        fee_msats: u64,
+        max_amount_msats: u64,
+        sent: Vec<u64>,
+    }
+
+    impl MockSender {
+        fn send(&mut self, amount_msats: u64, params: &SendingParameters) -> Result<(), SendError> {
+            if amount_msats > self.max_amount_msats {
+                return Err(SendError::NoRoute);
+            }
+            if params.max_total_routing_fee_msat.flatten().unwrap_or(0) < self.fee_msats {
+                return Err(SendError::FeeTooLow);
+            }
+            self.sent.push(amount_msats);
+            Ok(())
+        }
+    }
+
+    /// Follows the policy's plans against `sender` the way stability payments do, returning
+    /// the attempt that succeeded or None if the policy gave up
+    fn drive(policy: &RetryPolicy, sender: &mut MockSender, amount_msats: u64) -> Option<u32> {
+        let mut no_route = false;
+        for attempt in 0.. {
+            let result = match policy.plan(attempt, amount_msats, no_route) {
+                AttemptPlan::Whole(params) => sender.send(amount_msats, &params),
+                AttemptPlan::Split { parts, params } => sender.send(amount_msats / parts as u64, &params),
+                AttemptPlan::GiveUp => return None,
+            };
+            match result {
+                Ok(()) => return Some(attempt),
+                Err(e) => no_route = e == SendError::NoRoute,
+            }
+        }
+        unreachable!()
+    }
+
+    fn fee_budget(plan: &AttemptPlan) -> u64 {
+        match plan {
+            AttemptPlan::Whole(params) | AttemptPlan::Split { params, .. } => {
+                params.max_total_routing_fee_msat.flatten().unwrap()
+            }
+            AttemptPlan::GiveUp => panic!("gave up"),
+        }
+    }
+
+    #[test]
+    fn retries_raise_the_fee_budget_and_cap_the_cltv_budget() {
+        let policy = RetryPolicy::default();
+        let amount_msats = 10_000_000;
+        let fees: Vec<u64> = (0..=policy.max_retries).map(|a| fee_budget(&policy.plan(a, amount_msats, false))).collect();
+        assert_eq!(fees, vec![50_000, 100_000, 200_000, 400_000]);
+
+        let AttemptPlan::Whole(last) = policy.plan(policy.max_retries, amount_msats, false) else {
+            panic!("expected a whole attempt");
+        };
+        assert_eq!(last.max_total_cltv_expiry_delta, Some(policy.max_cltv_expiry_delta));
+    }
+
+    #[test]
+    fn small_payments_get_the_minimum_fee_budget() {
+        let policy = RetryPolicy::default();
+        assert_eq!(fee_budget(&policy.plan(0, 1_000, false)), policy.min_fee_msats);
+    }
+
+    #[test]
+    fn fee_failures_give_up_after_the_retries() {
+        let policy = RetryPolicy::default();
+        let mut sender = MockSender { fee_msats: u64::MAX, max_amount_msats: u64::MAX, sent: Vec::new() };
+        assert_eq!(drive(&policy, &mut sender, 10_000_000), None);
+        assert!(matches!(policy.plan(policy.max_retries + 1, 10_000_000, false), AttemptPlan::GiveUp));
+    }
+
+    #[test]
+    fn a_higher_fee_budget_gets_through() {
+        let policy = RetryPolicy::default();
+        let mut sender = MockSender { fee_msats: 150_000, max_amount_msats: u64::MAX, sent: Vec::new() };
+        assert_eq!(drive(&policy, &mut sender, 10_000_000), Some(2));
+        assert_eq!(sender.sent, vec![10_000_000]);
+    }
+
+    #[test]
+    fn no_route_splits_into_halves_then_quarters() {
+        let policy = RetryPolicy::default();
+        let amount_msats = 10_000_000;
+        let mut sender = MockSender { fee_msats: 0, max_amount_msats: 3_000_000, sent: Vec::new() };
+        assert_eq!(drive(&policy, &mut sender, amount_msats), Some(policy.max_retries + 2));
+        assert_eq!(sender.sent, vec![2_500_000]);
+    }
+
+    #[test]
+    fn no_route_gives_up_past_the_split_limit() {
+        let policy = RetryPolicy::default();
+        let mut sender = MockSender { fee_msats: 0, max_amount_msats: 1_000_000, sent: Vec::new() };
+        assert_eq!(drive(&policy, &mut sender, 10_000_000), None);
+        assert!(sender.sent.is_empty());
+    }
+
+    #[test]
+    fn zero_split_parts_never_splits() {
+        let policy = RetryPolicy { max_split_parts: 0, ..RetryPolicy::default() };
+        assert!(matches!(policy.plan(policy.max_retries + 1, 10_000_000, true), AttemptPlan::GiveUp));
+    }
+}
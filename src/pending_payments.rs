@@ -0,0 +1,163 @@
+use crate::display;
+use crate::types::StableChannel;
+use crate::ui_util;
+use ldk_node::lightning::events::PaymentFailureReason;
+use ldk_node::lightning::ln::channelmanager::PaymentId;
+use ldk_node::lightning::ln::types::ChannelId;
+use ldk_node::payment::PaymentStatus;
+use ldk_node::Node;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Bolt11's default invoice expiry. A payment unresolved for longer has most likely had
+/// its outcome event missed, so it is looked up instead.
+const PAYMENT_EXPIRY: Duration = Duration::from_secs(3600);
+
+/// How often payments past their expiry are looked up
+const REQUERY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Failures kept for inspection
+const MAX_FAILURES: usize = 10;
+
+struct PendingPayment {
+    id: PaymentId,
+    amount_msats: Option<u64>,
+    /// Stable channel a stability payment adjusts
+    stability: Option<ChannelId>,
+    sent_at: Instant,
+}
+
+struct FailedPayment {
+    id: PaymentId,
+    amount_msats: Option<u64>,
+    stability: Option<ChannelId>,
+    reason: String,
+}
+
+/// Outgoing payments from being sent until their outcome event, and recent failures with
+/// their reason. ldk-node reports only the reason a payment failed, not the hop it
+/// failed at.
+pub struct PendingPayments {
+    pending: Vec<PendingPayment>,
+    failures: VecDeque<FailedPayment>,
+    last_requery: Instant,
+}
+
+impl Default for PendingPayments {
+    fn default() -> Self {
+        Self { pending: Vec::new(), failures: VecDeque::new(), last_requery: Instant::now() }
+    }
+}
+
+impl PendingPayments {
+    /// Starts tracking a payment the user sent, taking its amount from the node
+    pub fn track(&mut self, node: &Node, id: PaymentId) {
+        let amount_msats = node.payment(&id).and_then(|p| p.amount_msat);
+        self.insert(id, amount_msats, None);
+    }
+
+    /// Picks up stability payments the channels are waiting on
+    pub fn track_stability<'a>(&mut self, channels: impl IntoIterator<Item = &'a StableChannel>) {
+        for sc in channels {
+            if let Some(id) = sc.pending_payment_id {
+                self.insert(id, Some(sc.pending_payment_msats), Some(sc.channel_id));
+            }
+        }
+    }
+
+    fn insert(&mut self, id: PaymentId, amount_msats: Option<u64>, stability: Option<ChannelId>) {
+        if self.pending.iter().all(|p| p.id != id) {
+            self.pending.push(PendingPayment { id, amount_msats, stability, sent_at: Instant::now() });
+        }
+    }
+
+    pub fn succeeded(&mut self, id: &PaymentId) {
+        self.pending.retain(|p| p.id != *id);
+    }
+
+    pub fn failed(&mut self, id: &PaymentId, reason: Option<PaymentFailureReason>) {
+        let reason = reason.map_or_else(|| "unknown reason".to_string(), |r| format!("{:?}", r));
+        self.fail(id, reason);
+    }
+
+    fn fail(&mut self, id: &PaymentId, reason: String) {
+        let Some(index) = self.pending.iter().position(|p| p.id == *id) else { return };
+        let payment = self.pending.remove(index);
+        self.failures.push_front(FailedPayment {
+            id: payment.id,
+            amount_msats: payment.amount_msats,
+            stability: payment.stability,
+            reason,
+        });
+        self.failures.truncate(MAX_FAILURES);
+    }
+
+    /// Looks up payments pending past their expiry, resolving any the node has settled
+    /// and dropping any it no longer knows
+    pub fn requery_stale(&mut self, node: &Node) {
+        if self.last_requery.elapsed() < REQUERY_INTERVAL {
+            return;
+        }
+        self.last_requery = Instant::now();
+        let stale: Vec<PaymentId> =
+            self.pending.iter().filter(|p| p.sent_at.elapsed() > PAYMENT_EXPIRY).map(|p| p.id).collect();
+        for id in stale {
+            match node.payment(&id).map(|p| p.status) {
+                Some(PaymentStatus::Pending) => {}
+                Some(PaymentStatus::Succeeded) => self.succeeded(&id),
+                Some(PaymentStatus::Failed) => self.fail(&id, "failed; the outcome event was missed".to_string()),
+                None => {
+                    warn!("Dropping pending payment {} the node no longer knows", hex::encode(id.0));
+                    self.pending.retain(|p| p.id != id);
+                }
+            }
+        }
+    }
+
+    /// Strip of in-flight payments with their elapsed time, then recent failures
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        if self.pending.is_empty() && self.failures.is_empty() {
+            return;
+        }
+        ui.group(|ui| {
+            ui.label("Pending");
+            for payment in &self.pending {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label(format!("{} for {}s", amount(payment.amount_msats), payment.sent_at.elapsed().as_secs()))
+                        .on_hover_text(hex::encode(payment.id.0));
+                    stability_tag(ui, payment.stability);
+                });
+            }
+            let mut dismissed = None;
+            for (i, failure) in self.failures.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.colored_label(egui::Color32::RED, format!("✗ {} failed: {}", amount(failure.amount_msats), failure.reason))
+                        .on_hover_text(hex::encode(failure.id.0));
+                    stability_tag(ui, failure.stability);
+                    ui_util::copy_button(ui, egui::Button::new("Copy ID").small(), || hex::encode(failure.id.0));
+                    if ui.small_button("Dismiss").clicked() {
+                        dismissed = Some(i);
+                    }
+                });
+            }
+            if let Some(i) = dismissed {
+                self.failures.remove(i);
+            }
+        });
+        if !self.pending.is_empty() {
+            ui.ctx().request_repaint_after(Duration::from_secs(1));
+        }
+    }
+}
+
+fn amount(amount_msats: Option<u64>) -> String {
+    amount_msats.map_or_else(|| "Payment".to_string(), |msats| display::sats(msats / 1000))
+}
+
+fn stability_tag(ui: &mut egui::Ui, stability: Option<ChannelId>) {
+    if let Some(channel_id) = stability {
+        ui.colored_label(egui::Color32::LIGHT_BLUE, "stability").on_hover_text(format!("Channel {}", channel_id));
+    }
+}
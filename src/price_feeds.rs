@@ -1,169 +1,281 @@
 use ureq::Agent;
 use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
 use std::error::Error;
-use std::sync::{Arc, Mutex};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
 use std::time::{Duration, Instant};
 use retry::{retry, delay::Fixed};
+use tracing::{debug, info, warn};
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+use crate::price_sources::{median, request_prices, set_price_feeds, HttpClient, OnJson, PriceFeed};
+use crate::types::{valid_price, Currency};
+use crate::{dev_mode, reconnect, shutdown};
 
 lazy_static::lazy_static! {
-    static ref PRICE_CACHE: Arc<Mutex<PriceCache>> = Arc::new(Mutex::new(PriceCache {
-        price: 0.0,
-        last_update: Instant::now() - Duration::from_secs(10),
-        updating: false,
-    }));
+    /// One cache per currency, created the first time that currency's price is asked for
+    static ref PRICE_CACHE: Arc<Mutex<HashMap<Currency, PriceCache>>> = Arc::new(Mutex::new(HashMap::new()));
+    /// Forced BTC/USD price in developer mode; bypasses the feeds and the cache while set
+    static ref PRICE_OVERRIDE: Mutex<Option<f64>> = Mutex::new(None);
 }
 
+// Number of recent median prices kept for volatility estimates
+const RECENT_PRICES_LEN: usize = 20;
+
+/// Coinbase Exchange ticker feed, streamed for the currencies it quotes BTC in
+const STREAM_URL: &str = "wss://ws-feed.exchange.coinbase.com";
+const STREAM_CURRENCIES: [Currency; 3] = [Currency::USD, Currency::EUR, Currency::GBP];
+
+/// A currency counts as streamed while its last tick is at most this old
+const STREAM_LIVE: Duration = Duration::from_secs(15);
+
+/// The socket is dropped and reopened after this long without any message
+const STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A connection that lasted this long resets the reconnect backoff
+const STREAM_STABLE_AFTER: Duration = Duration::from_secs(60);
+
+/// How long a blocked read waits before the stream thread checks for shutdown
+const STREAM_READ_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The feeds are still polled this often while streaming, to keep the spread, source
+/// count and volatility figures current
+const POLL_WHILE_STREAMING: Duration = Duration::from_secs(60);
+
 // A very simple price cache structure
 pub struct PriceCache {
     price: f64,
     last_update: Instant,
     updating: bool,
+    spread_percent: f64,
+    /// Feeds behind the last median
+    sources: usize,
+    recent_prices: VecDeque<f64>,
+    /// Last successful fetch from the polled feeds
+    last_poll: Instant,
+    /// Last streamed tick, if any
+    last_tick: Option<Instant>,
 }
 
-pub struct PriceFeed {
-    pub name: String,
-    pub urlformat: String,
-    pub jsonpath: Vec<String>,
+impl PriceCache {
+    fn new() -> Self {
+        Self {
+            price: 0.0,
+            last_update: Instant::now() - Duration::from_secs(10),
+            updating: false,
+            spread_percent: 0.0,
+            sources: 0,
+            recent_prices: VecDeque::new(),
+            last_poll: Instant::now() - POLL_WHILE_STREAMING,
+            last_tick: None,
+        }
+    }
+
+    fn transport(&self) -> PriceTransport {
+        if self.last_tick.map_or(false, |t| t.elapsed() <= STREAM_LIVE) {
+            PriceTransport::Stream
+        } else {
+            PriceTransport::Polling
+        }
+    }
+}
+
+/// Where the cached price currently comes from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PriceTransport {
+    /// The HTTP feeds, refreshed when the cache goes stale
+    #[default]
+    Polling,
+    /// The streamed ticker, with the HTTP feeds as a periodic cross-check
+    Stream,
 }
 
-impl PriceFeed {
-    pub fn new(name: &str, urlformat: &str, jsonpath: Vec<&str>) -> PriceFeed {
-        PriceFeed {
-            name: name.to_string(),
-            urlformat: urlformat.to_string(),
-            jsonpath: jsonpath.iter().map(|&s| s.to_string()).collect(),
+impl std::fmt::Display for PriceTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PriceTransport::Polling => write!(f, "polling"),
+            PriceTransport::Stream => write!(f, "streaming"),
         }
     }
 }
 
+/// Runs `f` on `currency`'s cache, creating it if needed
+fn with_cache<T>(currency: Currency, f: impl FnOnce(&mut PriceCache) -> T) -> T {
+    let mut caches = PRICE_CACHE.lock().unwrap();
+    f(caches.entry(currency).or_insert_with(PriceCache::new))
+}
+
+/// Feed quality figures used to assess stability risk
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PriceStats {
+    /// Spread between the highest and lowest feed, as a percentage of the median
+    pub spread_percent: f64,
+    /// Seconds since the last successful fetch or streamed tick
+    pub age_secs: u64,
+    /// Standard deviation of recent prices, as a percentage of their mean
+    pub volatility_percent: f64,
+    /// Feeds behind the last median; zero while the price is simulated
+    pub sources: usize,
+    pub transport: PriceTransport,
+}
+
+pub fn get_price_stats() -> PriceStats {
+    get_price_stats_in(Currency::USD)
+}
+
+pub fn get_price_stats_in(currency: Currency) -> PriceStats {
+    // A forced price is exact and current
+    if is_simulated(currency) {
+        return PriceStats::default();
+    }
+    with_cache(currency, |cache| price_stats(cache))
+}
+
+/// Forces the BTC/USD price returned by `get_cached_price` and `get_latest_price` until
+/// cleared with None. Other currencies keep using the feeds. Refused outside developer mode.
+pub fn set_price_override(price: Option<f64>) {
+    if price.is_some() && !dev_mode::enabled() {
+        warn!("Ignoring price override outside developer mode");
+        return;
+    }
+    if price.map_or(false, |price| valid_price(price).is_none()) {
+        warn!("Ignoring price override that isn't a positive number");
+        return;
+    }
+    match price {
+        Some(price) => info!(price, "Overriding BTC/USD price"),
+        None => info!("Cleared BTC/USD price override"),
+    }
+    *PRICE_OVERRIDE.lock().unwrap() = price;
+}
+
+pub fn price_override() -> Option<f64> {
+    *PRICE_OVERRIDE.lock().unwrap()
+}
+
+/// Whether prices in `currency` are currently forced rather than fetched. Forced prices
+/// stay out of persisted price history.
+pub fn is_simulated(currency: Currency) -> bool {
+    currency == Currency::USD && price_override().is_some()
+}
+
+/// The last BTC/USD price from the feeds, ignoring any override
+pub fn feed_price() -> f64 {
+    with_cache(Currency::USD, |cache| cache.price)
+}
+
+fn price_stats(cache: &PriceCache) -> PriceStats {
+    let n = cache.recent_prices.len() as f64;
+    let volatility_percent = if n >= 2.0 {
+        let mean = cache.recent_prices.iter().sum::<f64>() / n;
+        let variance = cache.recent_prices.iter().map(|p| (p - mean).powi(2)).sum::<f64>() / n;
+        variance.sqrt() / mean * 100.0
+    } else {
+        0.0
+    };
+
+    PriceStats {
+        spread_percent: cache.spread_percent,
+        age_secs: cache.last_update.elapsed().as_secs(),
+        volatility_percent,
+        sources: cache.sources,
+        transport: cache.transport(),
+    }
+}
+
+/// Whether `currency`'s price is currently arriving over the stream
+pub fn is_streaming(currency: Currency) -> bool {
+    with_cache(currency, |cache| cache.transport() == PriceTransport::Stream)
+}
+
+/// The latest streamed price and its age, without ever fetching; None while the stream
+/// isn't live or the price is forced
+pub fn streamed_price(currency: Currency) -> Option<(f64, Duration)> {
+    if is_simulated(currency) {
+        return None;
+    }
+    with_cache(currency, |cache| {
+        (cache.transport() == PriceTransport::Stream).then(|| (cache.price, cache.last_update.elapsed()))
+    })
+}
+
 // Get cached price or fetch a new one if needed
 pub fn get_cached_price() -> f64 {
-    // First check if we need to update
-    let should_update = {
-        let cache = PRICE_CACHE.lock().unwrap();
-        cache.last_update.elapsed() > Duration::from_secs(5) && !cache.updating
-    };
-    
-    // If update is needed
+    get_cached_price_in(Currency::USD)
+}
+
+/// The BTC price in `currency`: the override if one is set, else the cache, refreshed
+/// first if it is more than 5 seconds old or the feeds are due a cross-check
+pub fn get_cached_price_in(currency: Currency) -> f64 {
+    let forced = price_override().filter(|_| is_simulated(currency));
+    cached_price_from(&Agent::new(), currency, forced)
+}
+
+/// `get_cached_price_in` with the refresh going through `client`: `forced` wins, then a
+/// fresh fetch when the cache is due one, then the cache
+fn cached_price_from(client: &dyn HttpClient, currency: Currency, forced: Option<f64>) -> f64 {
+    if let Some(price) = forced {
+        return price;
+    }
+
+    // Check whether we need to update, and claim the update if so
+    let should_update = with_cache(currency, |cache| {
+        let stale = cache.last_update.elapsed() > Duration::from_secs(5)
+            || cache.last_poll.elapsed() > POLL_WHILE_STREAMING;
+        let should_update = stale && !cache.updating;
+        if should_update {
+            cache.updating = true;
+        }
+        should_update
+    });
+
     if should_update {
-        // Get the lock again and mark as updating
-        let mut cache = PRICE_CACHE.lock().unwrap();
-        cache.updating = true;
-        drop(cache); // Release the lock
-        
-        // Try to fetch a new price
-        let agent = Agent::new();
-        if let Ok(new_price) = get_latest_price(&agent) {
-            // Update the cache with new price
-            let mut cache = PRICE_CACHE.lock().unwrap();
-            cache.price = new_price;
-            cache.last_update = Instant::now();
-            cache.updating = false;
-            return new_price;
-        } else {
-            // Update failed, just clear the updating flag
-            let mut cache = PRICE_CACHE.lock().unwrap();
+        // Try to fetch a new price; a successful fetch updates the cache itself
+        let result = get_latest_price_in(client, currency);
+        return with_cache(currency, |cache| {
             cache.updating = false;
-            return cache.price; // Return the existing price
-        }
+            // On failure, return the existing price
+            result.unwrap_or(cache.price)
+        });
     }
-    
+
     // No update needed, just return current price
-    let cache = PRICE_CACHE.lock().unwrap();
-    cache.price
-}
-
-pub fn set_price_feeds() -> Vec<PriceFeed> {
-    vec![
-        PriceFeed::new(
-            "Bitstamp",
-            "https://www.bitstamp.net/api/v2/ticker/btcusd/",
-            vec!["last"],
-        ),
-        PriceFeed::new(
-            "CoinGecko",
-            "https://api.coingecko.com/api/v3/simple/price?ids=bitcoin&vs_currencies=usd",
-            vec!["bitcoin", "usd"],
-        ),
-        // PriceFeed::new(
-        //     "Coindesk",
-        //     "https://api.coindesk.com/v1/bpi/currentprice/USD.json",
-        //     vec!["bpi", "USD", "rate_float"],
-        // ),
-        PriceFeed::new(
-            "Coinbase",
-            "https://api.coinbase.com/v2/prices/spot?currency=USD",
-            vec!["data", "amount"],
-        ),
-        PriceFeed::new(
-            "Blockchain.com",
-            "https://blockchain.info/ticker",
-            vec!["USD", "last"],
-        ),
-    ]
+    with_cache(currency, |cache| cache.price)
+}
+
+/// Blocks on the request, retrying a few times before giving up on the feed
+impl HttpClient for Agent {
+    fn get_json(&self, url: &str, done: OnJson) {
+        let response = retry(Fixed::from_millis(300).take(3), || match self.get(url).call() {
+            Ok(resp) if (200..300).contains(&resp.status()) => Ok(resp),
+            Ok(resp) => Err(format!("Received status code: {}", resp.status())),
+            Err(e) => Err(e.to_string()),
+        });
+        done(match response {
+            Ok(resp) => resp.into_json::<Value>().map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        });
+    }
 }
 
+/// Fetches `currency`'s price from each feed that quotes it, keeping the valid ones
 pub fn fetch_prices(
-    agent: &Agent,
+    client: &dyn HttpClient,
     price_feeds: &[PriceFeed],
+    currency: Currency,
 ) -> Result<Vec<(String, f64)>, Box<dyn Error>> {
-    let mut prices = Vec::new();
-
-    for price_feed in price_feeds {
-        let url: String = price_feed
-            .urlformat
-            .replace("{currency_lc}", "usd")
-            .replace("{currency}", "USD");
-
-        let response = retry(Fixed::from_millis(300).take(3), || {
-            match agent.get(&url).call() {
-                Ok(resp) => {
-                    if resp.status() >= 200 && resp.status() < 300 {
-                        Ok(resp)
-                    } else {
-                        Err(format!("Received status code: {}", resp.status()))
-                    }
-                }
-                Err(e) => Err(e.to_string()),
-            }
-        })
-        .map_err(|e| -> Box<dyn Error> {
-            Box::new(std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))
-        })?;
-
-        let json: Value = response.into_json()?;
-        let mut data = &json;
-
-        for key in &price_feed.jsonpath {
-            if let Some(inner_data) = data.get(key) {
-                data = inner_data;
-            } else {
-                println!(
-                    "Key '{}' not found in the response from {}",
-                    key, price_feed.name
-                );
-                continue;
-            }
-        }
-
-        if let Some(price) = data.as_f64() {
-            prices.push((price_feed.name.clone(), price));
-        } else if let Some(price_str) = data.as_str() {
-            if let Ok(price) = price_str.parse::<f64>() {
-                prices.push((price_feed.name.clone(), price));
-            } else {
-                println!("Invalid price format for {}: {}", price_feed.name, price_str);
-            }
-        } else {
-            println!(
-                "Price data not found or invalid format for {}",
-                price_feed.name
-            );
-        }
-    }
+    // The native client answers before `request_prices` returns
+    let (tx, rx) = mpsc::channel();
+    request_prices(client, price_feeds, currency, Box::new(move |prices| {
+        let _ = tx.send(prices);
+    }));
+    let prices = rx.recv()?;
 
     if prices.len() < 5 {
-        // println!("Fewer than 5 prices fetched.");
+        debug!("Only {} of {} price feeds returned a price", prices.len(), price_feeds.len());
     }
 
     if prices.is_empty() {
@@ -173,24 +285,230 @@ pub fn fetch_prices(
     Ok(prices)
 }
 
-pub fn get_latest_price(agent: &Agent) -> Result<f64, Box<dyn Error>> {
+pub fn get_latest_price(client: &dyn HttpClient) -> Result<f64, Box<dyn Error>> {
+    get_latest_price_in(client, Currency::USD)
+}
+
+/// Fetches the median BTC price in `currency` from the feeds that quote it, and caches it.
+/// An override wins over the fetch and is not cached.
+pub fn get_latest_price_in(client: &dyn HttpClient, currency: Currency) -> Result<f64, Box<dyn Error>> {
+    if let Some(price) = price_override().filter(|_| is_simulated(currency)) {
+        return Ok(price);
+    }
     let price_feeds = set_price_feeds();
-    let prices = fetch_prices(agent, &price_feeds)?;
+    let prices = fetch_prices(client, &price_feeds, currency)?;
     
-    // Print all prices
     for (feed_name, price) in &prices {
-        println!("{:<25} ${:>1.2}", feed_name, price);
+        debug!(feed = %feed_name, price, "Fetched price");
     }
 
     // Calculate the median price
     let mut price_values: Vec<f64> = prices.iter().map(|(_, price)| *price).collect();
-    price_values.sort_by(|a, b| a.partial_cmp(b).unwrap());
-    let median_price = if price_values.len() % 2 == 0 {
-        (price_values[price_values.len() / 2 - 1] + price_values[price_values.len() / 2]) / 2.0
-    } else {
-        price_values[price_values.len() / 2]
-    };
+    let median_price = median(&mut price_values);
+
+    info!(median_price, feeds = prices.len(), "Updated BTC/{} price", currency);
+
+    let spread_percent = (price_values[price_values.len() - 1] - price_values[0]) / median_price * 100.0;
+    with_cache(currency, |cache| {
+        cache.price = median_price;
+        cache.last_update = Instant::now();
+        cache.last_poll = Instant::now();
+        cache.spread_percent = spread_percent;
+        cache.sources = prices.len();
+        cache.recent_prices.push_back(median_price);
+        if cache.recent_prices.len() > RECENT_PRICES_LEN {
+            cache.recent_prices.pop_front();
+        }
+    });
 
-    println!("\nMedian BTC/USD price:     ${:.2}\n", median_price);
     Ok(median_price)
-}
\ No newline at end of file
+}
+/// Streams ticker prices into the cache on a background thread, reconnecting with
+/// backoff when the socket drops. The polled feeds take over whenever the stream
+/// goes quiet, since the cache then goes stale.
+pub struct PriceStream {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl PriceStream {
+    pub fn start() -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        let handle = std::thread::spawn(move || {
+            let mut failures = 0;
+            while !thread_stop.load(Ordering::SeqCst) {
+                let connected_at = Instant::now();
+                match stream_prices(&thread_stop) {
+                    Ok(()) => break,
+                    Err(e) => warn!("Price stream dropped: {}", e),
+                }
+                // A connection that drops right away counts as a failure, so a flapping
+                // socket backs off instead of reconnecting in a tight loop
+                if connected_at.elapsed() >= STREAM_STABLE_AFTER {
+                    failures = 0;
+                } else {
+                    failures += 1;
+                }
+                if shutdown::sleep_unless_shutdown(&thread_stop, reconnect::backoff(failures)) {
+                    break;
+                }
+            }
+            debug!("Price stream stopped");
+        });
+        Self { stop, handle: Some(handle) }
+    }
+
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            shutdown::join_with_timeout(handle, shutdown::THREAD_JOIN_TIMEOUT);
+        }
+    }
+}
+
+/// Reads ticks until `stop` is set, which returns Ok, or the socket fails
+fn stream_prices(stop: &AtomicBool) -> Result<(), String> {
+    let (mut socket, _) = tungstenite::connect(STREAM_URL).map_err(|e| e.to_string())?;
+    set_read_timeout(&socket, STREAM_READ_TIMEOUT).map_err(|e| e.to_string())?;
+
+    let product_ids: Vec<String> = STREAM_CURRENCIES.iter().map(|c| format!("BTC-{}", c.code())).collect();
+    let subscribe = serde_json::json!({
+        "type": "subscribe",
+        "product_ids": product_ids,
+        "channels": ["ticker"],
+    });
+    socket.send(Message::text(subscribe.to_string())).map_err(|e| e.to_string())?;
+    info!("Streaming prices from {}", STREAM_URL);
+
+    let mut last_message = Instant::now();
+    loop {
+        if stop.load(Ordering::SeqCst) {
+            let _ = socket.close(None);
+            return Ok(());
+        }
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                last_message = Instant::now();
+                if let Some((currency, price)) = parse_tick(&text) {
+                    record_tick(currency, price);
+                }
+            }
+            Ok(Message::Close(_)) => return Err("closed by server".to_string()),
+            Ok(_) => last_message = Instant::now(),
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) =>
+            {
+                if last_message.elapsed() > STREAM_IDLE_TIMEOUT {
+                    return Err(format!("no message for {}s", STREAM_IDLE_TIMEOUT.as_secs()));
+                }
+            }
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+}
+
+fn set_read_timeout(socket: &WebSocket<MaybeTlsStream<TcpStream>>, timeout: Duration) -> std::io::Result<()> {
+    match socket.get_ref() {
+        MaybeTlsStream::Plain(stream) => stream.set_read_timeout(Some(timeout)),
+        MaybeTlsStream::Rustls(stream) => stream.sock.set_read_timeout(Some(timeout)),
+        _ => Ok(()),
+    }
+}
+
+/// The currency and price of a ticker message; None for anything else
+fn parse_tick(text: &str) -> Option<(Currency, f64)> {
+    let json: Value = serde_json::from_str(text).ok()?;
+    if json.get("type")?.as_str()? != "ticker" {
+        return None;
+    }
+    let currency = Currency::from_code(json.get("product_id")?.as_str()?.strip_prefix("BTC-")?)?;
+    let price = json.get("price")?.as_str()?.parse::<f64>().ok()?;
+    valid_price(price).map(|price| (currency, price))
+}
+
+fn record_tick(currency: Currency, price: f64) {
+    with_cache(currency, |cache| {
+        if cache.transport() == PriceTransport::Polling {
+            info!("Streaming BTC/{} price", currency);
+        }
+        cache.price = price;
+        cache.last_update = Instant::now();
+        cache.last_tick = Some(Instant::now());
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::price_sources::fill_currency;
+    use std::sync::atomic::AtomicUsize;
+
+    /// Answers every feed quoting `currency` with `price` where that feed expects it, or
+    /// fails every request without a price. Counts the requests.
+    struct FixedFeeds {
+        currency: Currency,
+        price: Option<f64>,
+        requests: AtomicUsize,
+    }
+
+    impl FixedFeeds {
+        fn new(currency: Currency, price: Option<f64>) -> Self {
+            Self { currency, price, requests: AtomicUsize::new(0) }
+        }
+
+        fn requests(&self) -> usize {
+            self.requests.load(Ordering::SeqCst)
+        }
+    }
+
+    impl HttpClient for FixedFeeds {
+        fn get_json(&self, url: &str, done: OnJson) {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            let feed = set_price_feeds().into_iter().find(|feed| fill_currency(&feed.urlformat, self.currency) == url);
+            let (Some(price), Some(feed)) = (self.price, feed) else {
+                return done(Err("feed down".to_string()));
+            };
+            let json = feed.jsonpath.iter().rev().fold(Value::from(price), |inner, key| {
+                let mut object = serde_json::Map::new();
+                object.insert(fill_currency(key, self.currency), inner);
+                Value::Object(object)
+            });
+            done(Ok(json));
+        }
+    }
+
+    // Each test has a currency of its own, as the caches are shared across tests
+
+    #[test]
+    fn forced_price_wins_without_fetching() {
+        let feeds = FixedFeeds::new(Currency::GBP, Some(40_000.0));
+
+        assert_eq!(cached_price_from(&feeds, Currency::GBP, Some(1_000.0)), 1_000.0);
+        assert_eq!(feeds.requests(), 0);
+    }
+
+    #[test]
+    fn fresh_fetch_wins_over_the_cache_and_the_cache_covers_a_failed_one() {
+        // A new cache is already due a fetch
+        let feeds = FixedFeeds::new(Currency::CHF, Some(60_000.0));
+        assert_eq!(cached_price_from(&feeds, Currency::CHF, None), 60_000.0);
+        assert!(feeds.requests() > 0);
+
+        // Within a few seconds the cache answers without asking the feeds
+        let moved = FixedFeeds::new(Currency::CHF, Some(61_000.0));
+        assert_eq!(cached_price_from(&moved, Currency::CHF, None), 60_000.0);
+        assert_eq!(moved.requests(), 0);
+
+        // Once stale the feeds are asked again, and win
+        with_cache(Currency::CHF, |cache| cache.last_update = Instant::now() - Duration::from_secs(10));
+        assert_eq!(cached_price_from(&moved, Currency::CHF, None), 61_000.0);
+
+        // With every feed down the last cached price stands
+        with_cache(Currency::CHF, |cache| cache.last_update = Instant::now() - Duration::from_secs(10));
+        let down = FixedFeeds::new(Currency::CHF, None);
+        assert_eq!(cached_price_from(&down, Currency::CHF, None), 61_000.0);
+        assert!(down.requests() > 0);
+        assert!(!with_cache(Currency::CHF, |cache| cache.updating));
+    }
+}
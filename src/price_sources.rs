@@ -0,0 +1,157 @@
+// The price feeds and how to read them, kept free of the node and of any one HTTP
+// client so the web build fetches through the same code
+
+use crate::currency::{valid_price, Currency};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+/// Called with the result of a request, on whatever thread or task the client finishes on
+pub type OnJson = Box<dyn FnOnce(Result<Value, String>) + Send>;
+
+/// Fetches JSON over HTTP. The desktop apps block on ureq; the browser can't block, so
+/// `done` is called whenever the response arrives.
+pub trait HttpClient {
+    fn get_json(&self, url: &str, done: OnJson);
+}
+
+#[derive(Clone)]
+pub struct PriceFeed {
+    pub name: String,
+    /// `{currency}` and `{currency_lc}` are replaced with the currency code
+    pub urlformat: String,
+    /// Path to the price in the response; segments may use the same placeholders
+    pub jsonpath: Vec<String>,
+    /// Currencies the source quotes BTC in
+    pub currencies: Vec<Currency>,
+}
+
+impl PriceFeed {
+    pub fn new(name: &str, urlformat: &str, jsonpath: Vec<&str>, currencies: &[Currency]) -> PriceFeed {
+        PriceFeed {
+            name: name.to_string(),
+            urlformat: urlformat.to_string(),
+            jsonpath: jsonpath.iter().map(|&s| s.to_string()).collect(),
+            currencies: currencies.to_vec(),
+        }
+    }
+}
+
+/// Replaces the currency placeholders in a feed's URL or JSON path
+pub fn fill_currency(template: &str, currency: Currency) -> String {
+    template
+        .replace("{currency_lc}", &currency.code().to_lowercase())
+        .replace("{currency}", currency.code())
+}
+
+/// The price `json` from `feed` quotes in `currency`, if it is a usable one
+pub fn price_at(feed: &PriceFeed, json: &Value, currency: Currency) -> Option<f64> {
+    let mut data = json;
+    for key in &feed.jsonpath {
+        let key = fill_currency(key, currency);
+        match data.get(&key) {
+            Some(inner_data) => data = inner_data,
+            None => {
+                warn!("Key '{}' not found in the response from {}", key, feed.name);
+                return None;
+            }
+        }
+    }
+    // A feed quoting zero, a negative or NaN is as good as no quote
+    let price = data.as_f64().or_else(|| data.as_str().and_then(|s| s.parse::<f64>().ok()));
+    if price.and_then(valid_price).is_none() {
+        warn!("Price data not found or invalid format for {}: {}", feed.name, data);
+    }
+    price.and_then(valid_price)
+}
+
+/// Asks every feed quoting `currency` for its price, then calls `done` once with the
+/// valid ones by feed name, after the last feed has answered
+pub fn request_prices(
+    client: &dyn HttpClient,
+    price_feeds: &[PriceFeed],
+    currency: Currency,
+    done: Box<dyn FnOnce(Vec<(String, f64)>) + Send>,
+) {
+    let feeds: Vec<&PriceFeed> = price_feeds.iter().filter(|feed| feed.currencies.contains(&currency)).collect();
+    if feeds.is_empty() {
+        done(Vec::new());
+        return;
+    }
+    // Prices so far, feeds still to answer, and who to tell once they all have
+    let state = Arc::new(Mutex::new((Vec::new(), feeds.len(), Some(done))));
+    for feed in feeds {
+        let state = Arc::clone(&state);
+        let url = fill_currency(&feed.urlformat, currency);
+        let feed = feed.clone();
+        client.get_json(
+            &url,
+            Box::new(move |result| {
+                let price = match result {
+                    Ok(json) => price_at(&feed, &json, currency),
+                    Err(e) => {
+                        warn!("Price feed {} failed: {}", feed.name, e);
+                        None
+                    }
+                };
+                let mut state = state.lock().unwrap();
+                if let Some(price) = price {
+                    state.0.push((feed.name, price));
+                }
+                state.1 -= 1;
+                if state.1 == 0 {
+                    let prices = std::mem::take(&mut state.0);
+                    if let Some(done) = state.2.take() {
+                        drop(state);
+                        done(prices);
+                    }
+                }
+            }),
+        );
+    }
+}
+
+/// Median of `prices`, which must not be empty
+pub fn median(prices: &mut [f64]) -> f64 {
+    prices.sort_by(|a, b| a.total_cmp(b));
+    let mid = prices.len() / 2;
+    if prices.len() % 2 == 0 {
+        (prices[mid - 1] + prices[mid]) / 2.0
+    } else {
+        prices[mid]
+    }
+}
+
+pub fn set_price_feeds() -> Vec<PriceFeed> {
+    vec![
+        PriceFeed::new(
+            "Bitstamp",
+            "https://www.bitstamp.net/api/v2/ticker/btc{currency_lc}/",
+            vec!["last"],
+            &[Currency::USD, Currency::EUR, Currency::GBP],
+        ),
+        PriceFeed::new(
+            "CoinGecko",
+            "https://api.coingecko.com/api/v3/simple/price?ids=bitcoin&vs_currencies={currency_lc}",
+            vec!["bitcoin", "{currency_lc}"],
+            &Currency::ALL,
+        ),
+        // PriceFeed::new(
+        //     "Coindesk",
+        //     "https://api.coindesk.com/v1/bpi/currentprice/USD.json",
+        //     vec!["bpi", "USD", "rate_float"],
+        // ),
+        PriceFeed::new(
+            "Coinbase",
+            "https://api.coinbase.com/v2/prices/spot?currency={currency}",
+            vec!["data", "amount"],
+            &Currency::ALL,
+        ),
+        PriceFeed::new(
+            "Blockchain.com",
+            "https://blockchain.info/ticker",
+            vec!["{currency}", "last"],
+            &Currency::ALL,
+        ),
+    ]
+}
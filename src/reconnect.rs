@@ -0,0 +1,134 @@
+use ldk_node::bitcoin::secp256k1::PublicKey;
+use ldk_node::Node;
+use std::time::{Duration, Instant};
+
+/// Wait before the first reconnect attempt; doubles with each failure
+const BASE_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Longest wait between reconnect attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Schedules reconnect attempts to a peer with exponential backoff. It only decides
+/// when to try; the caller makes the connection and reports the outcome.
+#[derive(Debug)]
+pub struct Reconnector {
+    failures: u32,
+    next_attempt: Option<Instant>,
+    connected: bool,
+}
+
+impl Reconnector {
+    pub fn new() -> Self {
+        Self { failures: 0, next_attempt: None, connected: false }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Records whether the peer is connected right now. Returns true if a reconnect
+    /// attempt is due, in which case the caller should make one and `record_attempt`.
+    pub fn poll(&mut self, connected: bool, now: Instant) -> bool {
+        self.connected = connected;
+        if connected {
+            self.failures = 0;
+            self.next_attempt = None;
+            return false;
+        }
+        if self.next_attempt.map_or(false, |t| now < t) {
+            return false;
+        }
+        // Hold off further attempts while this one is in flight
+        self.next_attempt = Some(now + backoff(self.failures));
+        true
+    }
+
+    pub fn record_attempt(&mut self, succeeded: bool, now: Instant) {
+        if succeeded {
+            self.connected = true;
+            self.failures = 0;
+            self.next_attempt = None;
+        } else {
+            self.failures = self.failures.saturating_add(1);
+            self.next_attempt = Some(now + backoff(self.failures));
+        }
+    }
+}
+
+/// Wait after `failures` failed attempts in a row
+pub fn backoff(failures: u32) -> Duration {
+    BASE_BACKOFF.saturating_mul(2u32.saturating_pow(failures)).min(MAX_BACKOFF)
+}
+
+pub fn is_peer_connected(node: &Node, node_id: &PublicKey) -> bool {
+    node.list_peers().iter().any(|p| p.node_id == *node_id && p.is_connected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let secs: Vec<u64> = (0..8).map(|failures| backoff(failures).as_secs()).collect();
+        assert_eq!(secs, vec![5, 10, 20, 40, 80, 160, 300, 300]);
+        assert_eq!(backoff(u32::MAX), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn missing_peer_is_retried_on_the_backoff_schedule() {
+        let start = Instant::now();
+        let mut reconnector = Reconnector::new();
+        let mut now = start;
+
+        // Each failure pushes the next attempt further out
+        for expected_wait in [10, 20, 40, 80, 160, 300, 300] {
+            assert!(reconnector.poll(false, now), "no attempt at {:?}", now - start);
+            reconnector.record_attempt(false, now);
+            assert!(!reconnector.poll(false, now + Duration::from_secs(expected_wait - 1)));
+            now += Duration::from_secs(expected_wait);
+        }
+        assert!(!reconnector.is_connected());
+    }
+
+    #[test]
+    fn attempt_in_flight_holds_off_another() {
+        let now = Instant::now();
+        let mut reconnector = Reconnector::new();
+
+        assert!(reconnector.poll(false, now));
+        assert!(!reconnector.poll(false, now + Duration::from_secs(1)));
+        assert!(reconnector.poll(false, now + BASE_BACKOFF));
+    }
+
+    #[test]
+    fn reconnecting_resets_the_schedule() {
+        let now = Instant::now();
+        let mut reconnector = Reconnector::new();
+        for i in 0..4 {
+            let at = now + Duration::from_secs(i * 1_000);
+            reconnector.poll(false, at);
+            reconnector.record_attempt(false, at);
+        }
+
+        reconnector.record_attempt(true, now + Duration::from_secs(4_000));
+        assert!(reconnector.is_connected());
+
+        // The next drop starts over at the shortest wait
+        let dropped = now + Duration::from_secs(5_000);
+        assert!(reconnector.poll(false, dropped));
+        reconnector.record_attempt(false, dropped);
+        assert!(reconnector.poll(false, dropped + backoff(1)));
+    }
+
+    #[test]
+    fn connected_peer_needs_no_attempt() {
+        let now = Instant::now();
+        let mut reconnector = Reconnector::new();
+
+        assert!(!reconnector.poll(true, now));
+        assert!(reconnector.is_connected());
+        assert!(reconnector.poll(false, now));
+        assert!(!reconnector.is_connected());
+    }
+}
@@ -0,0 +1,250 @@
+use crate::chain_source::{ChainSource, CHAIN_SOURCE_ENV};
+use crate::price_feeds::{get_cached_price, set_price_override};
+use crate::stable::{self, StabilityAction, STABILITY_THRESHOLD_PERCENT};
+use crate::types::StableChannel;
+use ldk_node::bitcoin::secp256k1::PublicKey;
+use ldk_node::bitcoin::Network;
+use ldk_node::lightning::ln::types::ChannelId;
+use ldk_node::{Builder, Event, Node};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+use ureq::Agent;
+
+const LSP_PORT: u16 = 19735;
+const USER_PORT: u16 = 19736;
+
+/// Channel opened by the LSP, and the part of it pushed to the user as the stable balance
+const CHANNEL_SATS: u64 = 10_000_000;
+const PUSH_MSATS: u64 = 5_000_000_000;
+
+/// Price at designation, and the price it moves to. A 1% drop keeps the adjustment under
+/// the default payment cap so it settles in one payment.
+const START_PRICE: f64 = 100_000.0;
+const MOVED_PRICE: f64 = 99_000.0;
+
+/// How long any single step may take before the run fails
+const STEP_TIMEOUT: Duration = Duration::from_secs(60);
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Runs the stable loop end to end on regtest: an LSP node and a user node in fresh data
+/// dirs open a channel, both designate it, the price drops and the provider's stability
+/// payment must bring the receiver back to within the stability threshold of the target.
+/// It runs twice on the same channel: first with the LSP providing stability to the user,
+/// then with the sides swapped.
+///
+/// Needs a regtest bitcoind to mine blocks, given the same way as for the apps, e.g.
+/// `STABLE_CHANNELS_CHAIN_SOURCE=bitcoind:127.0.0.1:18443` plus the RPC credentials.
+pub fn run() -> Result<(), String> {
+    with_channel(|lsp, user, channel_id| {
+        info!("Running with the LSP providing stability");
+        stable_loop(lsp, user, channel_id)?;
+        info!("Running with the user providing stability");
+        stable_loop(user, lsp, channel_id)
+    })
+}
+
+/// Starts an LSP node and a user node in fresh data dirs, opens a channel between them and
+/// runs `f` on the nodes and the channel, stopping both nodes afterwards
+fn with_channel(f: impl FnOnce(&Node, &Node, ChannelId) -> Result<(), String>) -> Result<(), String> {
+    let chain_source = ChainSource::from_args_or_env("")?;
+    if !matches!(chain_source, ChainSource::BitcoindRpc { .. }) {
+        return Err(format!("the regtest run needs a bitcoind chain source to mine blocks; set {}", CHAIN_SOURCE_ENV));
+    }
+    chain_source.validate(Network::Regtest)?;
+
+    let root = fresh_data_dir()?;
+    let lsp = start_node(&root.join("lsp"), LSP_PORT, &chain_source)?;
+    let user = start_node(&root.join("user"), USER_PORT, &chain_source)?;
+
+    let result = open_channel(&chain_source, &lsp, &user).and_then(|channel_id| f(&lsp, &user, channel_id));
+    set_price_override(None);
+    let _ = user.stop();
+    let _ = lsp.stop();
+    result
+}
+
+/// Funds the LSP and opens a channel from it to the user, pushing half to the user
+fn open_channel(chain_source: &ChainSource, lsp: &Node, user: &Node) -> Result<ChannelId, String> {
+    let agent = Agent::new();
+
+    // Coinbase outputs can be spent after 100 blocks
+    let lsp_address = lsp.onchain_payment().new_address().map_err(|e| e.to_string())?.to_string();
+    mine(chain_source, &agent, 101, &lsp_address)?;
+    wait_until("LSP on-chain funds", || {
+        let _ = lsp.sync_wallets();
+        lsp.list_balances().spendable_onchain_balance_sats > CHANNEL_SATS
+    })?;
+
+    let user_address = user
+        .listening_addresses()
+        .and_then(|addresses| addresses.first().cloned())
+        .ok_or("user node has no listening address")?;
+    lsp.open_channel(user.node_id(), user_address, CHANNEL_SATS, Some(PUSH_MSATS), None)
+        .map_err(|e| format!("failed to open channel: {}", e))?;
+    wait_for_event(lsp, "channel pending", |event| matches!(event, Event::ChannelPending { .. }).then_some(()))?;
+    mine(chain_source, &agent, 6, &lsp_address)?;
+    let channel_id = wait_for_event(lsp, "LSP channel ready", |event| match event {
+        Event::ChannelReady { channel_id, .. } => Some(*channel_id),
+        _ => None,
+    })?;
+    wait_for_event(user, "user channel ready", |event| matches!(event, Event::ChannelReady { .. }).then_some(()))?;
+    info!("Channel {} is ready", channel_id);
+    Ok(channel_id)
+}
+
+/// Pegs `receiver`'s side of the channel, drops the price and checks that `provider`'s
+/// payment brings `receiver` back to par
+fn stable_loop(provider: &Node, receiver: &Node, channel_id: ChannelId) -> Result<(), String> {
+    set_price_override(Some(START_PRICE));
+    let mut receiver_sc = designate(receiver, provider.node_id(), channel_id, true);
+    let mut provider_sc = designate(provider, receiver.node_id(), channel_id, false);
+    // Each side's view of the other's balance is off by the commitment fee the funder pays,
+    // so both peg what the receiver sees
+    provider_sc.expected_usd = receiver_sc.expected_usd;
+    provider_sc.expected_btc = receiver_sc.expected_btc;
+    info!("Designated channel with target {}", receiver_sc.expected_usd);
+
+    set_price_override(Some(MOVED_PRICE));
+    let price = get_cached_price();
+    match stable::check_stability_and_log(receiver, &mut receiver_sc, price) {
+        StabilityAction::WaitingOnCounterparty => {}
+        other => return Err(format!("receiver should wait for the provider after the drop, got: {}", other)),
+    }
+    let payment_id = match stable::check_stability_and_log(provider, &mut provider_sc, price) {
+        StabilityAction::Paid { payment_id, .. } => payment_id,
+        other => return Err(format!("provider should pay after the drop, got: {}", other)),
+    };
+
+    wait_for_event(provider, "stability payment sent", |event| match event {
+        Event::PaymentSuccessful { payment_id: Some(id), .. } if *id == payment_id => Some(Ok(())),
+        Event::PaymentFailed { payment_id: Some(id), reason, .. } if *id == payment_id => {
+            Some(Err(format!("stability payment failed: {:?}", reason)))
+        }
+        _ => None,
+    })??;
+    stable::record_successful_payment(&mut provider_sc, &payment_id);
+    let (received_id, received_msats) = wait_for_event(receiver, "stability payment received", |event| match event {
+        Event::PaymentReceived { payment_id, amount_msat, .. } => Some((*payment_id, *amount_msat)),
+        _ => None,
+    })?;
+    stable::record_received_payment(&mut receiver_sc, received_id, received_msats, 0);
+
+    // The payment is claimed before the commitment update settles the balance
+    wait_until("receiver back at par", || {
+        stable::check_stability_and_log(receiver, &mut receiver_sc, price) == StabilityAction::Stable
+    })?;
+    let off_par = stable::percent_from_par(&receiver_sc);
+    if off_par >= STABILITY_THRESHOLD_PERCENT {
+        return Err(format!("receiver is {:.4}% from par after the adjustment", off_par));
+    }
+    info!(
+        "Receiver holds {} against a target of {} ({:.4}% from par) after receiving {} msats",
+        receiver_sc.stable_receiver_usd, receiver_sc.expected_usd, off_par, received_msats
+    );
+    Ok(())
+}
+
+/// A pegged stable channel valuing the receiver's current balance at `START_PRICE`
+fn designate(node: &Node, counterparty: PublicKey, channel_id: ChannelId, is_stable_receiver: bool) -> StableChannel {
+    let mut sc = StableChannel {
+        channel_id,
+        counterparty,
+        is_stable_receiver,
+        latest_price: START_PRICE,
+        peg_agreed: true,
+        ..Default::default()
+    };
+    stable::update_balances(node, &mut sc);
+    sc.expected_usd = sc.stable_receiver_usd;
+    sc.expected_btc = sc.stable_receiver_btc;
+    sc
+}
+
+/// An empty directory for this run's nodes, at the same path every run
+fn fresh_data_dir() -> Result<PathBuf, String> {
+    let root = std::env::temp_dir().join("stable-channels-regtest");
+    if root.exists() {
+        std::fs::remove_dir_all(&root).map_err(|e| format!("failed to clear {}: {}", root.display(), e))?;
+    }
+    std::fs::create_dir_all(&root).map_err(|e| format!("failed to create {}: {}", root.display(), e))?;
+    Ok(root)
+}
+
+fn start_node(data_dir: &Path, port: u16, chain_source: &ChainSource) -> Result<Node, String> {
+    let mut builder = Builder::new();
+    builder.set_network(Network::Regtest);
+    chain_source.configure(&mut builder);
+    builder.set_storage_dir_path(data_dir.display().to_string());
+    builder
+        .set_listening_addresses(vec![format!("127.0.0.1:{}", port).parse().unwrap()])
+        .map_err(|e| format!("{:?}", e))?;
+    let node = builder.build().map_err(|e| format!("failed to build node in {}: {:?}", data_dir.display(), e))?;
+    node.start().map_err(|e| format!("failed to start node in {}: {}", data_dir.display(), e))?;
+    info!("Started node {} in {}", node.node_id(), data_dir.display());
+    Ok(node)
+}
+
+fn mine(chain_source: &ChainSource, agent: &Agent, blocks: u32, address: &str) -> Result<(), String> {
+    chain_source.rpc_call(agent, "generatetoaddress", json!([blocks, address]))?;
+    debug!("Mined {} blocks", blocks);
+    Ok(())
+}
+
+/// Handles `node`'s events until `matches` picks one out
+fn wait_for_event<T>(node: &Node, what: &str, mut matches: impl FnMut(&Event) -> Option<T>) -> Result<T, String> {
+    let deadline = Instant::now() + STEP_TIMEOUT;
+    while Instant::now() < deadline {
+        match node.next_event() {
+            Some(event) => {
+                let _ = node.event_handled();
+                if let Some(found) = matches(&event) {
+                    return Ok(found);
+                }
+                debug!("Skipping {:?} while waiting for {}", event, what);
+            }
+            None => std::thread::sleep(POLL_INTERVAL),
+        }
+    }
+    Err(format!("timed out waiting for {}", what))
+}
+
+fn wait_until(what: &str, mut done: impl FnMut() -> bool) -> Result<(), String> {
+    let deadline = Instant::now() + STEP_TIMEOUT;
+    while Instant::now() < deadline {
+        if done() {
+            return Ok(());
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    Err(format!("timed out waiting for {}", what))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Mutex, MutexGuard};
+
+    /// Runs share the ports, the data dir and the price override, so they take turns
+    static TURN: Mutex<()> = Mutex::new(());
+
+    fn take_turn() -> MutexGuard<'static, ()> {
+        TURN.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    #[test]
+    #[ignore = "needs a regtest bitcoind, given in STABLE_CHANNELS_CHAIN_SOURCE"]
+    fn lsp_payment_brings_the_user_back_to_par() {
+        let _turn = take_turn();
+        with_channel(|lsp, user, channel_id| stable_loop(lsp, user, channel_id)).unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs a regtest bitcoind, given in STABLE_CHANNELS_CHAIN_SOURCE"]
+    fn user_payment_brings_the_lsp_back_to_par() {
+        // The user provides stability and the LSP holds the pegged side
+        let _turn = take_turn();
+        with_channel(|lsp, user, channel_id| stable_loop(user, lsp, channel_id)).unwrap();
+    }
+}
@@ -0,0 +1,193 @@
+use crate::encryption;
+use crate::history;
+use ldk_node::lightning::ln::types::ChannelId;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::error;
+
+const ROUTING_STATS_FILE_NAME: &str = "routing_stats.json";
+
+/// Daily aggregates older than this are dropped
+const MAX_DAYS: usize = 365;
+
+/// Channels listed in the panel's top-by-volume table
+const TOP_CHANNELS: usize = 5;
+
+/// Days shown in the panel's daily table
+const RECENT_DAYS: usize = 7;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct RoutingTotals {
+    pub forwards: u64,
+    /// Amount forwarded out, excluding our fee
+    pub volume_msats: u64,
+    pub fees_msats: u64,
+}
+
+impl RoutingTotals {
+    fn add(&mut self, volume_msats: u64, fees_msats: u64) {
+        self.forwards += 1;
+        self.volume_msats += volume_msats;
+        self.fees_msats += fees_msats;
+    }
+}
+
+/// Forwarded volume and fees earned, per channel and per UTC day, persisted to the data dir
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RoutingData {
+    /// Keyed by "YYYY-MM-DD"
+    days: BTreeMap<String, RoutingTotals>,
+    /// Keyed by channel id. A forward counts towards both channels' volume; the fee is
+    /// credited to the outbound channel, whose liquidity it used.
+    channels: HashMap<String, RoutingTotals>,
+}
+
+pub struct RoutingStats {
+    path: PathBuf,
+    data: RoutingData,
+}
+
+impl RoutingStats {
+    pub fn load(data_dir: &Path) -> Self {
+        let path = data_dir.join(ROUTING_STATS_FILE_NAME);
+        let data = if path.exists() {
+            match encryption::read_state_file(&path).map(|s| serde_json::from_str(&s)) {
+                Ok(Ok(data)) => data,
+                Ok(Err(e)) => {
+                    error!("Ignoring unreadable {}: {}", path.display(), e);
+                    RoutingData::default()
+                }
+                Err(e) => {
+                    error!("Failed to read {}: {}", path.display(), e);
+                    RoutingData::default()
+                }
+            }
+        } else {
+            RoutingData::default()
+        };
+        Self { path, data }
+    }
+
+    fn save(&self) {
+        let result = serde_json::to_string(&self.data)
+            .map_err(|e| e.to_string())
+            .and_then(|json| encryption::write_state_file(&self.path, &json).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            error!("Failed to save {}: {}", self.path.display(), e);
+        }
+    }
+
+    /// Counts a `PaymentForwarded` event
+    pub fn record_forward(
+        &mut self,
+        prev_channel_id: &ChannelId,
+        next_channel_id: &ChannelId,
+        volume_msats: u64,
+        fees_msats: u64,
+    ) {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+        self.data.days.entry(history::format_date(now)).or_default().add(volume_msats, fees_msats);
+        while self.data.days.len() > MAX_DAYS {
+            self.data.days.pop_first();
+        }
+        self.data.channels.entry(prev_channel_id.to_string()).or_default().add(volume_msats, 0);
+        self.data.channels.entry(next_channel_id.to_string()).or_default().add(volume_msats, fees_msats);
+        self.save();
+    }
+
+    pub fn totals(&self) -> RoutingTotals {
+        self.data.days.values().fold(RoutingTotals::default(), |mut sum, day| {
+            sum.forwards += day.forwards;
+            sum.volume_msats += day.volume_msats;
+            sum.fees_msats += day.fees_msats;
+            sum
+        })
+    }
+
+    fn top_channels(&self, count: usize) -> Vec<(&String, &RoutingTotals)> {
+        let mut channels: Vec<_> = self.data.channels.iter().collect();
+        channels.sort_by(|a, b| b.1.volume_msats.cmp(&a.1.volume_msats));
+        channels.truncate(count);
+        channels
+    }
+
+    /// Counters for the admin API
+    pub fn to_json(&self, btc_price: f64) -> Value {
+        let totals = self.totals();
+        let channels: Vec<Value> = self
+            .top_channels(self.data.channels.len())
+            .into_iter()
+            .map(|(channel_id, t)| json!({
+                "channel_id": channel_id,
+                "forwards": t.forwards,
+                "volume_msats": t.volume_msats,
+                "fees_msats": t.fees_msats,
+            }))
+            .collect();
+        json!({
+            "forwards": totals.forwards,
+            "volume_msats": totals.volume_msats,
+            "fees_msats": totals.fees_msats,
+            "fees_usd": msats_to_usd(totals.fees_msats, btc_price),
+            "days": self.data.days,
+            "channels": channels,
+        })
+    }
+
+    /// "Routing" panel: totals, the last week by day and the busiest channels
+    pub fn show(&self, ui: &mut egui::Ui, btc_price: f64) {
+        egui::CollapsingHeader::new("Routing").show(ui, |ui| {
+            let totals = self.totals();
+            if totals.forwards == 0 {
+                ui.label("No payments forwarded yet.");
+                return;
+            }
+            ui.label(format!(
+                "{} forwards, {} sats routed, {} sats earned (${:.2})",
+                totals.forwards,
+                totals.volume_msats / 1000,
+                totals.fees_msats / 1000,
+                msats_to_usd(totals.fees_msats, btc_price)
+            ));
+
+            ui.add_space(5.0);
+            ui.strong("Last 7 days");
+            egui::Grid::new("routing_days").striped(true).num_columns(4).show(ui, |ui| {
+                for header in ["Day", "Forwards", "Volume (sats)", "Fees (sats)"] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+                for (day, t) in self.data.days.iter().rev().take(RECENT_DAYS) {
+                    ui.label(day);
+                    ui.label(t.forwards.to_string());
+                    ui.label((t.volume_msats / 1000).to_string());
+                    ui.label((t.fees_msats / 1000).to_string());
+                    ui.end_row();
+                }
+            });
+
+            ui.add_space(5.0);
+            ui.strong("Top channels by volume");
+            egui::Grid::new("routing_channels").striped(true).num_columns(4).show(ui, |ui| {
+                for header in ["Channel", "Forwards", "Volume (sats)", "Fees (sats / USD)"] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+                for (channel_id, t) in self.top_channels(TOP_CHANNELS) {
+                    ui.label(&channel_id[..channel_id.len().min(12)]);
+                    ui.label(t.forwards.to_string());
+                    ui.label((t.volume_msats / 1000).to_string());
+                    ui.label(format!("{} / ${:.2}", t.fees_msats / 1000, msats_to_usd(t.fees_msats, btc_price)));
+                    ui.end_row();
+                }
+            });
+        });
+    }
+}
+
+fn msats_to_usd(msats: u64, btc_price: f64) -> f64 {
+    msats as f64 / 100_000_000_000.0 * btc_price
+}
@@ -0,0 +1,412 @@
+use crate::encryption;
+use ldk_node::bip39::{Language, Mnemonic};
+use ldk_node::Builder;
+use serde::{Deserialize, Serialize};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{error, info, warn};
+
+const SEED_FILE_NAME: &str = "seed.json";
+
+/// Seed file ldk-node generates when no other entropy source is configured
+const LDK_KEYS_SEED_FILE: &str = "keys_seed";
+
+/// How long the reveal button has to be held before the phrase is shown
+const HOLD_TO_REVEAL: Duration = Duration::from_secs(2);
+
+/// Number of words the user has to re-enter to confirm their backup
+const QUIZ_WORDS: usize = 3;
+
+/// Files ldk-node keeps its wallet and channel state in
+const LDK_STATE_FILES: [&str; 2] = [LDK_KEYS_SEED_FILE, "ldk_node_data.sqlite"];
+
+/// Never moved aside when replacing a wallet: the lock is held by the running process
+const LOCK_FILE_NAME: &str = "app.lock";
+
+/// Prefix of the directory a replaced wallet's files are moved into
+const REPLACED_DIR_PREFIX: &str = "replaced-";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntropySourceKind {
+    Bip39,
+    /// Wallet created before mnemonic backups; its raw seed can't be shown as words
+    SeedFile,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SeedRecord {
+    entropy_source: EntropySourceKind,
+    #[serde(default)]
+    mnemonic: Option<String>,
+    #[serde(default)]
+    backup_confirmed: bool,
+}
+
+/// The wallet's entropy source, as persisted in the node data directory
+pub struct WalletSeed {
+    path: PathBuf,
+    record: SeedRecord,
+}
+
+impl WalletSeed {
+    /// Loads the recorded entropy source, or picks one for a data directory seen for the
+    /// first time: existing ldk-node seed files are kept, fresh wallets get a new mnemonic.
+    pub fn load_or_create(data_dir: &Path) -> Self {
+        let path = data_dir.join(SEED_FILE_NAME);
+
+        if path.exists() {
+            match encryption::read_state_file(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|contents| serde_json::from_str::<SeedRecord>(&contents).map_err(|e| e.to_string()))
+            {
+                Ok(record) => return Self { path, record },
+                Err(e) => {
+                    // Never fall through to generating a new wallet over an unreadable record
+                    error!("Cannot read {}: {}", path.display(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        let record = if data_dir.join(LDK_KEYS_SEED_FILE).exists() {
+            info!("Existing wallet uses a raw seed file; no recovery phrase is available");
+            SeedRecord { entropy_source: EntropySourceKind::SeedFile, mnemonic: None, backup_confirmed: false }
+        } else {
+            info!("Generating a new BIP39 recovery phrase");
+            let mnemonic = ldk_node::generate_entropy_mnemonic();
+            SeedRecord {
+                entropy_source: EntropySourceKind::Bip39,
+                mnemonic: Some(mnemonic.to_string()),
+                backup_confirmed: false,
+            }
+        };
+
+        let seed = Self { path, record };
+        seed.save();
+        seed
+    }
+
+    /// Creates the record for a wallet restored from `mnemonic`
+    pub fn from_mnemonic(data_dir: &Path, mnemonic: &Mnemonic) -> Self {
+        let seed = Self {
+            path: data_dir.join(SEED_FILE_NAME),
+            record: SeedRecord {
+                entropy_source: EntropySourceKind::Bip39,
+                mnemonic: Some(mnemonic.to_string()),
+                // The user just typed it in, so they evidently have it written down
+                backup_confirmed: true,
+            },
+        };
+        seed.save();
+        seed
+    }
+
+    fn save(&self) {
+        if let Some(parent) = self.path.parent() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                error!("Failed to create directory: {}", e);
+            }
+        }
+
+        match serde_json::to_string_pretty(&self.record) {
+            Ok(json) => {
+                if let Err(e) = encryption::write_state_file(&self.path, &json) {
+                    error!("Error writing seed file: {}", e);
+                }
+            }
+            Err(e) => error!("Error serializing seed record: {}", e),
+        }
+    }
+
+    pub fn entropy_source(&self) -> EntropySourceKind {
+        self.record.entropy_source
+    }
+
+    pub fn mnemonic(&self) -> Option<Mnemonic> {
+        self.record.mnemonic.as_deref().and_then(|words| Mnemonic::from_str(words).ok())
+    }
+
+    pub fn backup_confirmed(&self) -> bool {
+        self.record.backup_confirmed
+    }
+
+    pub fn confirm_backup(&mut self) {
+        self.record.backup_confirmed = true;
+        self.save();
+    }
+
+    /// Points the builder at this entropy source. Seed-file wallets need nothing, as
+    /// ldk-node reads its own seed file by default.
+    pub fn configure(&self, builder: &mut Builder) {
+        match (self.record.entropy_source, self.mnemonic()) {
+            (EntropySourceKind::Bip39, Some(mnemonic)) => {
+                builder.set_entropy_bip39_mnemonic(mnemonic, None);
+            }
+            (EntropySourceKind::Bip39, None) => {
+                error!("Seed record has an invalid mnemonic; refusing to start with a different wallet");
+                std::process::exit(1);
+            }
+            (EntropySourceKind::SeedFile, _) => {}
+        }
+    }
+}
+
+/// Whether `data_dir` already holds a wallet, from this app or from ldk-node alone
+pub fn wallet_exists(data_dir: &Path) -> bool {
+    data_dir.join(SEED_FILE_NAME).exists() || LDK_STATE_FILES.iter().any(|f| data_dir.join(f).exists())
+}
+
+/// Moves everything in `data_dir` except the lock file into a `replaced-<timestamp>`
+/// subdirectory, so a restore never deletes the wallet it replaces. Returns that directory.
+pub fn archive_existing_wallet(data_dir: &Path) -> std::io::Result<PathBuf> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let archive = data_dir.join(format!("{}{}", REPLACED_DIR_PREFIX, timestamp));
+    std::fs::create_dir_all(&archive)?;
+
+    for entry in std::fs::read_dir(data_dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name_str = name.to_string_lossy();
+        if name_str == LOCK_FILE_NAME || name_str.starts_with(REPLACED_DIR_PREFIX) {
+            continue;
+        }
+        std::fs::rename(entry.path(), archive.join(&name))?;
+    }
+
+    info!("Moved the existing wallet to {}", archive.display());
+    Ok(archive)
+}
+
+/// Whether the command line asked to restore a wallet (`--restore`)
+pub fn restore_requested() -> bool {
+    std::env::args().skip(1).any(|arg| arg == "--restore")
+}
+
+/// Whether the command line allows replacing an existing wallet (`--overwrite-wallet`)
+fn overwrite_allowed() -> bool {
+    std::env::args().skip(1).any(|arg| arg == "--overwrite-wallet")
+}
+
+/// Command line restore for the LSP and exchange, which have no onboarding screen. Reads
+/// the recovery phrase from stdin, refuses to replace an existing wallet without
+/// `--overwrite-wallet`, and exits on any failure rather than starting a different wallet.
+pub fn restore_from_stdin(data_dir: &Path) -> WalletSeed {
+    if wallet_exists(data_dir) {
+        if !overwrite_allowed() {
+            error!(
+                "{} already contains a wallet; pass --overwrite-wallet to replace it",
+                data_dir.display()
+            );
+            std::process::exit(1);
+        }
+        if let Err(e) = archive_existing_wallet(data_dir) {
+            error!("Failed to move the existing wallet aside: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if std::io::stdin().is_terminal() {
+        print!("Enter your 12 or 24 word recovery phrase: ");
+        let _ = std::io::stdout().flush();
+    }
+    let mut input = String::new();
+    if let Err(e) = std::io::stdin().read_line(&mut input) {
+        error!("Failed to read recovery phrase: {}", e);
+        std::process::exit(1);
+    }
+
+    let words: Vec<String> = input.split_whitespace().map(str::to_lowercase).collect();
+    for (i, word) in words.iter().enumerate() {
+        if Language::English.find_word(word).is_none() {
+            error!("Word #{} is not in the BIP39 word list", i + 1);
+            std::process::exit(1);
+        }
+    }
+
+    match Mnemonic::parse_in_normalized(Language::English, &words.join(" ")) {
+        Ok(mnemonic) => {
+            info!("Restoring wallet from recovery phrase");
+            WalletSeed::from_mnemonic(data_dir, &mnemonic)
+        }
+        Err(e) => {
+            error!("Invalid recovery phrase: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Word entry grid for restoring a wallet from its recovery phrase
+pub struct RestoreFlow {
+    words: Vec<String>,
+    overwrite_confirmed: bool,
+    error: Option<String>,
+}
+
+impl Default for RestoreFlow {
+    fn default() -> Self {
+        Self { words: vec![String::new(); 12], overwrite_confirmed: false, error: None }
+    }
+}
+
+impl RestoreFlow {
+    /// Draws the flow. Returns the mnemonic once the user submits a valid phrase and, if
+    /// `wallet_exists`, has confirmed replacing the current wallet.
+    pub fn show(&mut self, ui: &mut egui::Ui, wallet_exists: bool) -> Option<Mnemonic> {
+        ui.horizontal(|ui| {
+            ui.label("Phrase length:");
+            for count in [12, 24] {
+                if ui.selectable_label(self.words.len() == count, format!("{} words", count)).clicked() {
+                    self.words.resize(count, String::new());
+                }
+            }
+        });
+        ui.add_space(10.0);
+
+        egui::Grid::new("restore_phrase").num_columns(3).show(ui, |ui| {
+            for i in 0..self.words.len() {
+                let word = self.words[i].trim().to_lowercase();
+                let invalid = !word.is_empty() && Language::English.find_word(&word).is_none();
+                ui.horizontal(|ui| {
+                    ui.monospace(format!("{:>2}.", i + 1));
+                    let mut edit = egui::TextEdit::singleline(&mut self.words[i]).desired_width(90.0);
+                    if invalid {
+                        edit = edit.text_color(egui::Color32::RED);
+                    }
+                    let response = ui.add(edit);
+                    if invalid {
+                        response.on_hover_text("Not a BIP39 word");
+                    }
+                });
+                if (i + 1) % 3 == 0 {
+                    ui.end_row();
+                }
+            }
+        });
+
+        if wallet_exists {
+            ui.add_space(10.0);
+            ui.colored_label(
+                egui::Color32::YELLOW,
+                "A wallet already exists here. Restoring moves it aside and starts from the phrase.",
+            );
+            ui.checkbox(&mut self.overwrite_confirmed, "Replace the existing wallet");
+        }
+
+        ui.add_space(10.0);
+        let ready = self.words.iter().all(|w| !w.trim().is_empty()) && (!wallet_exists || self.overwrite_confirmed);
+        if ui.add_enabled(ready, egui::Button::new("Restore wallet")).clicked() {
+            let phrase = self.words.iter().map(|w| w.trim().to_lowercase()).collect::<Vec<_>>().join(" ");
+            match Mnemonic::parse_in_normalized(Language::English, &phrase) {
+                Ok(mnemonic) => return Some(mnemonic),
+                Err(e) => {
+                    warn!("Recovery phrase rejected: {}", e);
+                    self.error = Some(format!("Invalid recovery phrase: {}", e));
+                }
+            }
+        }
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+
+        None
+    }
+}
+
+/// Hold-to-reveal display of the recovery phrase followed by a word confirmation quiz
+#[derive(Default)]
+pub struct BackupFlow {
+    hold_started: Option<Instant>,
+    revealed: bool,
+    quiz: Vec<(usize, String)>,
+    quiz_error: bool,
+}
+
+impl BackupFlow {
+    /// Draws the flow. Returns true on the frame the user successfully confirms their backup.
+    pub fn show(&mut self, ui: &mut egui::Ui, seed: &mut WalletSeed) -> bool {
+        let Some(mnemonic) = seed.mnemonic() else {
+            ui.label("This wallet was created from a raw seed file and has no recovery phrase.");
+            ui.label("Back up the keys_seed file in the data directory instead.");
+            return false;
+        };
+        let words: Vec<&'static str> = mnemonic.word_iter().collect();
+
+        if !self.revealed {
+            ui.label("Make sure nobody can see your screen.");
+            let response = ui.add(egui::Button::new("Hold to reveal recovery phrase"));
+            if response.is_pointer_button_down_on() {
+                let started = *self.hold_started.get_or_insert_with(Instant::now);
+                ui.add(egui::ProgressBar::new(
+                    (started.elapsed().as_secs_f32() / HOLD_TO_REVEAL.as_secs_f32()).min(1.0),
+                ));
+                if started.elapsed() >= HOLD_TO_REVEAL {
+                    self.revealed = true;
+                    self.quiz = pick_quiz_words(words.len());
+                }
+                ui.ctx().request_repaint();
+            } else {
+                self.hold_started = None;
+            }
+            return false;
+        }
+
+        egui::Grid::new("recovery_phrase").num_columns(3).show(ui, |ui| {
+            for (i, word) in words.iter().enumerate() {
+                ui.monospace(format!("{:>2}. {}", i + 1, word));
+                if (i + 1) % 3 == 0 {
+                    ui.end_row();
+                }
+            }
+        });
+
+        if seed.backup_confirmed() {
+            ui.colored_label(egui::Color32::GREEN, "Backup confirmed");
+            return false;
+        }
+
+        ui.add_space(10.0);
+        ui.label("Write the words down, then confirm:");
+        for (index, input) in &mut self.quiz {
+            ui.horizontal(|ui| {
+                ui.label(format!("Word #{}:", *index + 1));
+                ui.text_edit_singleline(input);
+            });
+        }
+
+        if ui.button("Confirm backup").clicked() {
+            let correct = self
+                .quiz
+                .iter()
+                .all(|(index, input)| input.trim().eq_ignore_ascii_case(words[*index]));
+            if correct {
+                seed.confirm_backup();
+                return true;
+            }
+            warn!("Recovery phrase confirmation failed");
+            self.quiz_error = true;
+        }
+        if self.quiz_error {
+            ui.colored_label(egui::Color32::RED, "Those words don't match. Check your backup and try again.");
+        }
+
+        false
+    }
+}
+
+/// Picks distinct word positions to quiz on, without pulling in an RNG dependency
+fn pick_quiz_words(word_count: usize) -> Vec<(usize, String)> {
+    let mut state = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0) as usize;
+    let mut picked: Vec<usize> = Vec::new();
+    while picked.len() < QUIZ_WORDS.min(word_count) {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        let index = (state >> 8) % word_count;
+        if !picked.contains(&index) {
+            picked.push(index);
+        }
+    }
+    picked.sort_unstable();
+    picked.into_iter().map(|i| (i, String::new())).collect()
+}
@@ -1,166 +1,498 @@
 use eframe::{egui, App, Frame};
 use ldk_node::{
-    bitcoin::{Network, Address, secp256k1::PublicKey},
-    lightning_invoice::{Bolt11Invoice, Description, Bolt11InvoiceDescription},
-    lightning::ln::{msgs::SocketAddress},
-    config::ChannelConfig,
-    Builder, Node, Event, liquidity::LSPS2ServiceConfig
+    bitcoin::secp256k1::PublicKey,
+    lightning::ln::{msgs::SocketAddress, types::ChannelId},
+    Builder, ChannelDetails, Node, Event, UserChannelId, config::{ChannelConfig, Config}
 };
-use std::time::{Duration, Instant};
-use std::path::Path;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use serde::{Serialize, Deserialize};
 use std::fs;
 use hex;
 
 use crate::types::*;
-use crate::stable;
-use crate::price_feeds::get_cached_price;
+use crate::admin::{self, AdminRequest, AdminServer};
+use crate::console::{self, Console};
+use crate::stable::{self, PaymentResolution, StableChannelEntry};
+use crate::encryption;
+use crate::history::{self, PaymentHistory};
+use crate::notify::{Alerts, WebhookNotifier};
+use crate::logging;
+use crate::onchain::{self, OnchainActivity};
+use crate::pending_payments::PendingPayments;
+use crate::snapshot::ChannelSnapshot;
+use crate::chain_source::ChainSource;
+use crate::network;
+use crate::dev_mode::{self, PriceOverrideControl};
+use crate::display;
+use crate::settings::{self, SettingsScreen};
+use crate::simulation::SimulatorPanel;
+use crate::audit::{AuditLog, AuditPanel};
+use crate::tax::CostBasis;
+use crate::routing::RoutingStats;
+use crate::accounts::Accounts;
+use crate::status_log::{Severity, StatusLog};
+use crate::lsps2_settings::{Lsps2Settings, Lsps2SettingsForm};
+use crate::lsps2_tokens::{ServiceToken, TokenForm, TokenStore};
+use crate::sync_status::SyncMonitor;
+use crate::shutdown;
+use crate::paths;
+use crate::payment_retry;
+use crate::seed::{self, BackupFlow, WalletSeed};
+use crate::lockfile::DataDirLock;
+use crate::backup::{self, Backups};
+use crate::channel_policy::{ChannelPolicy, ChannelPolicyForm, InboundChannel};
+use crate::channel_table::{self, ChannelAction, PendingCloses};
+use crate::node_ui::{self, NodeBalances, NodeUi};
+use crate::reconnect::{self, Reconnector};
+use crate::ui_util;
+use crate::worker::{self, AppCommand, AppResult, CommandKind, Worker};
+use tracing::{debug, error, info, warn};
+use crate::price_feeds::{self, get_cached_price, get_cached_price_in, get_price_stats, get_price_stats_in, PriceStream};
 
-const LSP_DATA_DIR: &str = "data/lsp";
 const LSP_NODE_ALIAS: &str = "lsp";
 const LSP_PORT: u16 = 9737;
 
-const EXCHANGE_DATA_DIR: &str = "data/exchange";
 const EXCHANGE_NODE_ALIAS: &str = "exchange";
 const EXCHANGE_PORT: u16 = 9735;
 
 const DEFAULT_NETWORK: &str = "signet";
 const DEFAULT_CHAIN_SOURCE_URL: &str = "https://mutinynet.com/api/";
 const EXPECTED_USD: f64 = 15.0;
+const MAX_STABLE_USD: f64 = 500.0;
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-struct StableChannelEntry {
-    channel_id: String,
-    expected_usd: f64,
-    native_btc: f64,
+/// Overrides the cap on the summed targets of all stable channels
+const MAX_TOTAL_STABLE_USD_ENV: &str = "STABLE_CHANNELS_MAX_TOTAL_STABLE_USD";
+const DEFAULT_MAX_TOTAL_STABLE_USD: f64 = 10_000.0;
+
+/// Default stability fee for new stable channels, in parts per million of each payment
+const STABILITY_FEE_PPM_ENV: &str = "STABLE_CHANNELS_STABILITY_FEE_PPM";
+
+/// Exchange node to keep connected to, as `pubkey@host:port`
+const EXCHANGE_PEER_ENV: &str = "STABLE_CHANNELS_EXCHANGE_PEER";
+
+/// Pushing more than this to the peer at open needs the amount typed a second time
+const PUSH_CONFIRM_THRESHOLD_SATS: u64 = 50_000;
+/// Smallest reserve a peer will ask the funder to keep, as in LDK
+const MIN_CHANNEL_RESERVE_SATS: u64 = 1_000;
+
+/// Current on-disk format of stablechannels.json
+const STABLE_CHANNELS_FILE_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct StableChannelsFile {
+    version: u32,
+    channels: Vec<StableChannelEntry>,
+}
+
+/// Parses stablechannels.json, upgrading older formats to the current one. Returns the
+/// entries and a description of each migration applied. Unknown fields are ignored, but
+/// files written by a newer version are rejected.
+fn parse_stable_channels_file(contents: &str) -> Result<(Vec<StableChannelEntry>, Vec<String>), String> {
+    let value: serde_json::Value = serde_json::from_str(contents).map_err(|e| e.to_string())?;
+    let mut migrations = Vec::new();
+
+    // Version 0 files are a bare array keyed only by channel id
+    let file = if value.is_array() {
+        let channels: Vec<StableChannelEntry> = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        migrations.push(format!(
+            "v0 -> v1: wrapped {} bare entries in a versioned file and recorded each counterparty",
+            channels.len()
+        ));
+        StableChannelsFile { version: 1, channels }
+    } else {
+        serde_json::from_value::<StableChannelsFile>(value).map_err(|e| e.to_string())?
+    };
+
+    if file.version > STABLE_CHANNELS_FILE_VERSION {
+        return Err(format!(
+            "file version {} is newer than the supported version {}",
+            file.version, STABLE_CHANNELS_FILE_VERSION
+        ));
+    }
+
+    // Version 1 stored the target's BTC equivalent as `native_btc`. Without an original
+    // value the BTC leg restarts from the provider's balance when the entry is loaded.
+    let mut channels = file.channels;
+    if file.version < 2 {
+        for entry in &mut channels {
+            entry.native_btc_original = None;
+        }
+        migrations.push(format!(
+            "v1 -> v2: {} entries now track the provider's BTC leg from current balances",
+            channels.len()
+        ));
+    }
+
+    Ok((channels, migrations))
+}
+
+/// The BTC price in `currency`, reusing the already fetched USD price for USD pegs
+fn price_in(currency: Currency, usd_price: f64) -> f64 {
+    if currency == Currency::USD {
+        usd_price
+    } else {
+        get_cached_price_in(currency)
+    }
+}
+
+fn parse_channel_id(s: &str) -> Option<ChannelId> {
+    let bytes: [u8; 32] = hex::decode(s).ok()?.try_into().ok()?;
+    Some(ChannelId::from_bytes(bytes))
+}
+
+/// The largest ready channel with `peer`, used to resolve designations keyed by counterparty
+fn largest_ready_channel_with(channels: &[ChannelDetails], peer: &PublicKey) -> Option<ChannelDetails> {
+    channels
+        .iter()
+        .filter(|c| c.counterparty_node_id == *peer && c.is_channel_ready)
+        .max_by_key(|c| c.channel_value_sats)
+        .cloned()
+}
+
+/// Result of the most recent stability check on a channel
+struct StabilityOutcome {
+    checked_at: Instant,
+    action: stable::StabilityAction,
 }
 
 #[cfg(any(feature = "lsp", feature = "exchange"))]
 pub struct ServerApp {
     node: Arc<Node>,
     btc_price: f64,
-    status_message: String,
+    /// Messages from events and actions, newest last
+    status_log: StatusLog,
     last_update: Instant,
     last_stability_check: Instant,
-    lightning_balance_btc: f64,
-    onchain_balance_btc: f64,
-    lightning_balance_usd: f64,
-    onchain_balance_usd: f64,
-    total_balance_btc: f64,
-    total_balance_usd: f64,
-    invoice_amount: String,
-    invoice_result: String,
-    invoice_to_pay: String,
-    on_chain_address: String,
-    on_chain_amount: String,
+    stability_outcomes: HashMap<ChannelId, StabilityOutcome>,
+    pending_removals: HashSet<ChannelId>,
+    stable_channels_file_locked: bool,
+    shutdown: Arc<AtomicBool>,
+    data_dir_lock: Option<DataDirLock>,
+    data_dir: PathBuf,
+    /// Running as the LSP rather than the exchange; only the LSP sells JIT channels
+    is_lsp: bool,
+    port: u16,
+    /// Copies of the data dir, on request and on a timer
+    backups: Backups,
+    wallet_seed: WalletSeed,
+    backup_flow: Option<BackupFlow>,
+    payment_history: PaymentHistory,
+    pending_payments: PendingPayments,
+    onchain_activity: OnchainActivity,
+    channel_snapshot: ChannelSnapshot,
+    worker: Worker,
+    /// Block height against the chain tip, and sync failures
+    sync_monitor: SyncMonitor,
+    price_stream: PriceStream,
+    /// The stable channels file as last written, so unchanged state isn't rewritten
+    saved_stable_channels: Option<String>,
+    /// Entries whose channel wasn't found at load, written back unchanged so a channel the
+    /// node hasn't caught up on yet doesn't lose its designation
+    unresolved_stable_channels: Vec<StableChannelEntry>,
+    /// Backend the node reads the chain from
+    chain_source: ChainSource,
+    /// JIT channel parameters the node was built with
+    lsps2_settings: Lsps2Settings,
+    lsps2_form: Lsps2SettingsForm,
+    /// Which inbound channels are kept open
+    channel_policy: ChannelPolicy,
+    channel_policy_form: ChannelPolicyForm,
+    lsps2_tokens: TokenStore,
+    /// Token JIT requests must carry, if tokens are required
+    lsps2_service_token: Option<ServiceToken>,
+    lsps2_token_form: TokenForm,
+    /// Developer-mode control forcing the BTC price
+    price_override: PriceOverrideControl,
+    simulator: SimulatorPanel,
+    /// Every stability check, when the database could be opened
+    audit: Option<AuditLog>,
+    audit_panel: AuditPanel,
+    /// Forwarded volume and fees earned
+    routing: RoutingStats,
+    /// Customer accounts; only the exchange keeps them
+    accounts: Accounts,
+    /// Channels opened from the Open Channel form, so they aren't counted as JIT channels
+    manual_channel_ids: HashSet<UserChannelId>,
+    admin: Option<AdminServer>,
+    /// Stdin console, when started with `--console`
+    console: Option<Console>,
+    webhooks: WebhookNotifier,
+    alerts: Alerts,
+    max_total_stable_usd: f64,
+    stability_fee_ppm: u32,
+    settle_before_removal: bool,
+    exchange_peer: Option<(PublicKey, SocketAddress)>,
+    exchange_reconnector: Reconnector,
+    node_ui: NodeUi,
+    /// Open in place of the main screen while Some
+    settings_screen: Option<SettingsScreen>,
     channel_id_to_close: String,
     stable_channels: Vec<StableChannel>,
     selected_channel_id: String,
     stable_channel_amount: String,
+    stable_channel_max_payment_usd: String,
+    stable_channel_max_payment_percent: String,
+    stable_channel_require_approval: bool,
+    stable_channel_max_risk_level: String,
+    stable_channel_circuit_breaker_percent: String,
+    stable_channel_twap_window_secs: String,
+    stable_channel_min_payment_interval: String,
+    stable_channel_max_daily_usd: String,
+    stable_channel_key_by_counterparty: bool,
+    stable_channel_fee_ppm: String,
+    stable_channel_currency: Currency,
+    stable_channel_fraction: String,
     open_channel_node_id: String,
     open_channel_address: String,
     open_channel_amount: String,
-    channel_info: String,
+    /// Open the channel unannounced
+    open_channel_private: bool,
+    /// Sats given to the peer at open
+    open_channel_push: String,
+    /// The push amount typed again, required above `PUSH_CONFIRM_THRESHOLD_SATS`
+    open_channel_push_confirm: String,
+    /// Routing policy for the new channel; blank fields keep the node's defaults
+    open_channel_fee_base_msat: String,
+    open_channel_fee_ppm: String,
+    open_channel_cltv_delta: String,
+    /// Cooperative closes in flight, for offering a force-close when one stalls
+    pending_closes: PendingCloses,
+    /// Channel waiting on the operator to confirm a force-close
+    force_close_candidate: Option<ChannelDetails>,
 }
 
 #[cfg(any(feature = "lsp", feature = "exchange"))]
 impl ServerApp {
     pub fn new_with_mode(mode: &str) -> Self {
-        let (data_dir, node_alias, port) = match mode.to_lowercase().as_str() {
-            "exchange" => (EXCHANGE_DATA_DIR, EXCHANGE_NODE_ALIAS, EXCHANGE_PORT),
-            "lsp" => (LSP_DATA_DIR, LSP_NODE_ALIAS, LSP_PORT),
+        // Ask for the state passphrase up front, before the GUI takes over the terminal
+        encryption::passphrase();
+
+        let (node_alias, port) = match mode.to_lowercase().as_str() {
+            "exchange" => (EXCHANGE_NODE_ALIAS, EXCHANGE_PORT),
+            "lsp" => (LSP_NODE_ALIAS, LSP_PORT),
             _ => panic!("Invalid mode"),
         };
+        let data_dir = paths::data_dir(node_alias);
 
-        let mut builder = Builder::new();
+        let data_dir_lock = match DataDirLock::acquire(&data_dir) {
+            Ok(lock) => lock,
+            Err(e) => {
+                error!("Cannot start {} node: {} ({})", mode, e, data_dir.display());
+                std::process::exit(1);
+            }
+        };
 
-        let network = match DEFAULT_NETWORK.to_lowercase().as_str() {
-            "signet" => Network::Signet,
-            "testnet" => Network::Testnet,
-            "bitcoin" => Network::Bitcoin,
-            _ => {
-                println!("Warning: Unknown network in config, defaulting to Signet");
-                Network::Signet
+        backup::confirm_restored_start(&data_dir);
+
+        let wallet_seed = if seed::restore_requested() {
+            seed::restore_from_stdin(&data_dir)
+        } else {
+            WalletSeed::load_or_create(&data_dir)
+        };
+
+        payment_retry::init(&data_dir);
+        settings::init(&data_dir);
+        let channel_policy = ChannelPolicy::load(&data_dir);
+        let mut config = Config::default();
+        config.trusted_peers_0conf = channel_policy.zero_conf_peers();
+        let mut builder = Builder::from_config(config);
+        wallet_seed.configure(&mut builder);
+
+        let network = match network::from_args_or_env(DEFAULT_NETWORK) {
+            Ok(network) => network,
+            Err(e) => {
+                error!("Cannot start {} node: {}", mode, e);
+                std::process::exit(1);
             }
         };
 
-        println!("[Init] Setting network to: {:?}", network);
+        info!("Setting network to: {:?}", network);
         builder.set_network(network);
-        println!("[Init] Setting Esplora API URL: {}", DEFAULT_CHAIN_SOURCE_URL);
-        builder.set_chain_source_esplora(DEFAULT_CHAIN_SOURCE_URL.to_string(), None);
-        println!("[Init] Setting storage directory: {}", data_dir);
-        builder.set_storage_dir_path(data_dir.to_string());
+        let chain_source = match ChainSource::from_args_or_env(DEFAULT_CHAIN_SOURCE_URL)
+            .and_then(|source| source.validate(network).map(|()| source))
+        {
+            Ok(source) => source,
+            Err(e) => {
+                error!("Cannot start {} node: {}", mode, e);
+                std::process::exit(1);
+            }
+        };
+        chain_source.configure(&mut builder);
+        info!("Setting storage directory: {}", data_dir.display());
+        builder.set_storage_dir_path(data_dir.display().to_string());
 
+        let port = settings::get().node.listening_port.unwrap_or(port);
         let listen_addr = format!("127.0.0.1:{}", port).parse().unwrap();
-        println!("[Init] Setting listening address: {}", listen_addr);
+        info!("Setting listening address: {}", listen_addr);
         builder.set_listening_addresses(vec![listen_addr]).unwrap();
-        println!("[Init] Setting node alias: {}", node_alias);
+        info!("Setting node alias: {}", node_alias);
         let _ = builder.set_node_alias(node_alias.to_string()).ok();
 
+        let lsps2_settings = Lsps2Settings::load(&data_dir);
+        let lsps2_tokens = TokenStore::load(&data_dir);
+        let lsps2_service_token = lsps2_settings.require_token.then(|| lsps2_tokens.service_token());
         if node_alias == LSP_NODE_ALIAS {
-            println!("[Init] Configuring LSP parameters...");
-            let service_config = LSPS2ServiceConfig {
-                require_token: None,
-                advertise_service: true,
-                channel_opening_fee_ppm: 0,
-                channel_over_provisioning_ppm: 1_000_000,
-                min_channel_opening_fee_msat: 0,
-                min_channel_lifetime: 100,
-                max_client_to_self_delay: 1024,
-                min_payment_size_msat: 0,
-                max_payment_size_msat: 100_000_000_000,
-            };
-            builder.set_liquidity_provider_lsps2(service_config);
+            info!("Configuring LSP parameters: {:?}", lsps2_settings);
+            if let Some(ServiceToken { name: Some(name), .. }) = &lsps2_service_token {
+                info!("JIT channels require token {}", name);
+            }
+            let token = lsps2_service_token.as_ref().map(|t| t.token.clone());
+            builder.set_liquidity_provider_lsps2(lsps2_settings.service_config(token));
         }
 
         let node = Arc::new(match builder.build() {
             Ok(n) => {
-                println!("[Init] Node built successfully");
+                info!("Node built successfully");
                 n
             }
-            Err(e) => panic!("[Init] Failed to build node: {:?}", e),
+            Err(e) => panic!("Failed to build node: {:?}", e),
         });
         
         node.start().expect("Failed to start node");
 
         let btc_price = get_cached_price();
-        println!("[Init] Initial BTC price: {}", btc_price);
+        info!("Initial BTC price: {}", btc_price);
+
+        let payment_history = PaymentHistory::load(&data_dir);
+        let audit = AuditLog::open(&data_dir)
+            .map_err(|e| error!("Cannot open stability audit log: {}", e))
+            .ok();
+        let alerts = Alerts::load(&data_dir);
+        let routing = RoutingStats::load(&data_dir);
+        let accounts = Accounts::load(&data_dir, network);
+        let lsps2_form = Lsps2SettingsForm::new(&data_dir);
+        let channel_policy_form = ChannelPolicyForm::new(&channel_policy);
+        let node_ui = NodeUi::new(network, &data_dir, "1000", "10000", chain_source.clone());
+        let backups = Backups::from_env(&data_dir);
+        let stability_fee_ppm = std::env::var(STABILITY_FEE_PPM_ENV)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+        let worker = Worker::spawn(Arc::clone(&node));
+        let sync_monitor = SyncMonitor::spawn(Arc::clone(&node), chain_source.clone());
+
+        let admin = match admin::listen_addr() {
+            Some(addr) if node_alias == LSP_NODE_ALIAS => {
+                let Ok(token) = std::env::var(admin::ADMIN_TOKEN_ENV) else {
+                    error!("--admin-listen requires {} to be set", admin::ADMIN_TOKEN_ENV);
+                    std::process::exit(1);
+                };
+                match AdminServer::start(&addr, token, Arc::clone(&node)) {
+                    Ok(server) => Some(server),
+                    Err(e) => {
+                        error!("Cannot start admin API on {}: {}", addr, e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            Some(_) => {
+                warn!("The admin API is only available in LSP mode; ignoring --admin-listen");
+                None
+            }
+            None => None,
+        };
+        let console = console::enabled().then(|| Console::start(Arc::clone(&node)));
 
         let mut app = Self {
             node,
             btc_price,
-            status_message: String::new(),
+            status_log: StatusLog::default(),
             last_update: Instant::now(),
             last_stability_check: Instant::now(),
-            lightning_balance_btc: 0.0,
-            onchain_balance_btc: 0.0,
-            lightning_balance_usd: 0.0,
-            onchain_balance_usd: 0.0,
-            total_balance_btc: 0.0,
-            total_balance_usd: 0.0,
-            invoice_amount: "1000".into(),
-            invoice_result: String::new(),
-            invoice_to_pay: String::new(),
-            on_chain_address: String::new(),
-            on_chain_amount: "10000".into(),
+            stability_outcomes: HashMap::new(),
+            pending_removals: HashSet::new(),
+            stable_channels_file_locked: false,
+            shutdown: Arc::new(AtomicBool::new(false)),
+            data_dir_lock: Some(data_dir_lock),
+            data_dir,
+            is_lsp: node_alias == LSP_NODE_ALIAS,
+            port,
+            wallet_seed,
+            backup_flow: None,
+            payment_history,
+            pending_payments: PendingPayments::default(),
+            onchain_activity: OnchainActivity::new(chain_source.esplora_url()),
+            channel_snapshot: ChannelSnapshot::new(),
+            worker,
+            sync_monitor,
+            price_stream: PriceStream::start(),
+            saved_stable_channels: None,
+            unresolved_stable_channels: Vec::new(),
+            chain_source,
+            lsps2_form,
+            channel_policy,
+            channel_policy_form,
+            lsps2_settings,
+            lsps2_tokens,
+            lsps2_service_token,
+            lsps2_token_form: TokenForm::default(),
+            price_override: PriceOverrideControl::default(),
+            simulator: SimulatorPanel::default(),
+            audit,
+            audit_panel: AuditPanel::default(),
+            routing,
+            accounts,
+            manual_channel_ids: HashSet::new(),
+            admin,
+            console,
+            webhooks: WebhookNotifier::from_env(),
+            alerts,
+            max_total_stable_usd: std::env::var(MAX_TOTAL_STABLE_USD_ENV)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_MAX_TOTAL_STABLE_USD),
+            stability_fee_ppm,
+            settle_before_removal: true,
+            exchange_peer: std::env::var(EXCHANGE_PEER_ENV).ok().and_then(|peer| {
+                let parsed = node_ui::parse_peer(&peer);
+                if parsed.is_none() {
+                    error!("Ignoring {}: expected pubkey@host:port, got {}", EXCHANGE_PEER_ENV, peer);
+                }
+                parsed
+            }),
+            exchange_reconnector: Reconnector::new(),
+            node_ui,
+            settings_screen: None,
+            backups,
             channel_id_to_close: String::new(),
             stable_channels: Vec::new(),
             selected_channel_id: String::new(),
             stable_channel_amount: EXPECTED_USD.to_string(),
+            stable_channel_max_payment_usd: stable::DEFAULT_MAX_PAYMENT_USD.to_string(),
+            stable_channel_max_payment_percent: stable::DEFAULT_MAX_PAYMENT_PERCENT.to_string(),
+            stable_channel_require_approval: false,
+            stable_channel_max_risk_level: stable::DEFAULT_MAX_RISK_LEVEL.to_string(),
+            stable_channel_circuit_breaker_percent: stable::DEFAULT_CIRCUIT_BREAKER_PERCENT.to_string(),
+            stable_channel_twap_window_secs: stable::DEFAULT_TWAP_WINDOW_SECS.to_string(),
+            stable_channel_min_payment_interval: stable::DEFAULT_MIN_SECONDS_BETWEEN_PAYMENTS.to_string(),
+            stable_channel_max_daily_usd: stable::DEFAULT_MAX_DAILY_ADJUSTMENT_USD.to_string(),
+            stable_channel_key_by_counterparty: false,
+            stable_channel_fee_ppm: stability_fee_ppm.to_string(),
+            stable_channel_currency: display::fiat(),
+            stable_channel_fraction: stable::DEFAULT_STABLE_FRACTION.to_string(),
             open_channel_node_id: String::new(),
             open_channel_address: "127.0.0.1:9737".into(),
             open_channel_amount: "100000".into(),
-            channel_info: String::new(),
+            open_channel_private: true,
+            open_channel_push: "0".into(),
+            open_channel_push_confirm: String::new(),
+            open_channel_fee_base_msat: String::new(),
+            open_channel_fee_ppm: String::new(),
+            open_channel_cltv_delta: String::new(),
+            pending_closes: PendingCloses::from_env(),
+            force_close_candidate: None,
         };
 
         app.update_balances();
-        app.update_channel_info();
+        app.channel_snapshot.refresh_if_needed(&app.node);
 
-        if node_alias == LSP_NODE_ALIAS {
-            app.load_stable_channels();
-        }
+        // Both modes provide stability; the exchange's designations live in its own data dir
+        app.load_stable_channels();
 
         app
     }
@@ -179,12 +511,8 @@ impl ServerApp {
         }
 
         let balances = self.node.list_balances();
-        self.lightning_balance_btc = balances.total_lightning_balance_sats as f64 / 100_000_000.0;
-        self.onchain_balance_btc = balances.total_onchain_balance_sats as f64 / 100_000_000.0;
-        self.lightning_balance_usd = self.lightning_balance_btc * self.btc_price;
-        self.onchain_balance_usd = self.onchain_balance_btc * self.btc_price;
-        self.total_balance_btc = self.lightning_balance_btc + self.onchain_balance_btc;
-        self.total_balance_usd = self.lightning_balance_usd + self.onchain_balance_usd;
+        self.onchain_activity.update_balance(&balances);
+        self.node_ui.balances = NodeBalances::new(&balances, &self.node.list_channels(), self.btc_price);
     }
 
     pub fn check_and_update_stable_channels(&mut self) {
@@ -192,46 +520,275 @@ impl ServerApp {
         if current_price > 0.0 {
             self.btc_price = current_price;
         }
+        self.alerts.check_price_feed(&get_price_stats());
     
-        let mut channels_updated = false;
+        self.reattach_vanished_channels();
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+        let mut outcomes = Vec::new();
         for sc in &mut self.stable_channels {
-            if !stable::channel_exists(&self.node, &sc.channel_id) {
+            if sc.awaiting_reattach
+                || sc.unpegged_at.is_some()
+                || self.pending_removals.contains(&sc.channel_id)
+                || !stable::channel_exists(&*self.node, &sc.channel_id)
+            {
                 continue;
             }
     
-            sc.latest_price = current_price;
-            stable::check_stability(&self.node, sc, current_price);
-    
-            if sc.payment_made {
-                channels_updated = true;
+            let price = price_in(sc.currency, current_price);
+            stable::record_price(sc, now, price);
+            let action = stable::check_stability_and_log(&*self.node, sc, price);
+            if let Some(audit) = &mut self.audit {
+                audit.record_check(sc, price, &get_price_stats_in(sc.currency), &action);
             }
+            self.webhooks.notify(sc, &action, price);
+            self.alerts.check_channel(sc);
+            outcomes.push((sc.channel_id, action));
         }
-    
-        if channels_updated {
-            self.save_stable_channels();
+
+        for (channel_id, action) in &outcomes {
+            match action {
+                // A pause is reported once, when the breaker trips
+                stable::StabilityAction::Stable
+                | stable::StabilityAction::WaitingOnCounterparty
+                | stable::StabilityAction::Suspended { .. } => {}
+                _ => {
+                    self.status_log.push(Severity::of_action(action), format!("Channel {}: {}", channel_id, action));
+                }
+            }
+        }
+
+        let checked_at = Instant::now();
+        for (channel_id, action) in outcomes {
+            self.stability_outcomes.insert(channel_id, StabilityOutcome { checked_at, action });
+        }
+
+        // Only writes if a check changed something, such as a new price sample or payment
+        self.save_stable_channels();
+    }
+
+    /// Attributes an outbound channel we didn't open from the form, i.e. a JIT channel, to
+    /// the token in effect, the only one the service accepts
+    fn record_jit_channel(&mut self, channel_id: ChannelId, user_channel_id: UserChannelId) {
+        let Some(ServiceToken { name: Some(name), .. }) = self.lsps2_service_token.clone() else {
+            return;
+        };
+        if self.manual_channel_ids.remove(&user_channel_id) {
+            return;
+        }
+        if !self.node.list_channels().iter().any(|c| c.channel_id == channel_id && c.is_outbound) {
+            return;
+        }
+
+        // The service keeps accepting the token it was built with until the next start
+        if let Some(reason) = self.lsps2_tokens.unusable_reason(&name) {
+            warn!("JIT channel {} opened under token {}, which is {}", channel_id, name, reason.to_lowercase());
+            self.status_log.warn(format!(
+                "JIT channel {} opened under token {} ({}); restart to stop accepting it",
+                channel_id, name, reason
+            ));
+        } else {
+            info!("JIT channel {} opened under token {}", channel_id, name);
+        }
+        self.lsps2_tokens.record_channel(&name, channel_id.to_string());
+    }
+
+    /// Flags a channel we opened that the client started using before it confirmed, when
+    /// zero-conf JIT channels aren't offered
+    fn check_zero_conf_jit(&mut self, channel_id: ChannelId) {
+        let zero_conf = self
+            .node
+            .list_channels()
+            .iter()
+            .any(|c| c.channel_id == channel_id && c.is_outbound && channel_table::is_unconfirmed_zero_conf(c));
+        if zero_conf && !self.lsps2_settings.zero_conf {
+            warn!("Channel {} is in use before confirming, but zero-conf JIT channels are off", channel_id);
+            self.status_log.warn(format!("Channel {} was accepted as zero-conf by the client", channel_id));
+        } else if zero_conf {
+            info!("Zero-conf channel {} is usable before its funding confirms", channel_id);
         }
     }
 
     pub fn poll_events(&mut self) {
         while let Some(event) = self.node.next_event() {
             match event {
-                Event::ChannelReady { channel_id, .. } => {
-                    self.status_message = format!("Channel {} is now ready", channel_id);
+                Event::ChannelReady { channel_id, counterparty_node_id, .. } => {
+                    self.channel_snapshot.invalidate();
+                    self.status_log.info(format!("Channel {} is now ready", channel_id));
+                    self.check_zero_conf_jit(channel_id);
+                    if let Some(peer) = counterparty_node_id {
+                        self.reattach_stable_channel(peer, channel_id);
+                    }
+                    self.update_balances();
+                }
+
+                Event::PaymentSuccessful { payment_id, payment_hash, payment_preimage: _, fee_paid_msat } => {
+                    self.status_log.info(format!("Sent payment {}", payment_hash));
+                    if let Some(id) = payment_id {
+                        self.pending_payments.succeeded(&id);
+                        self.accounts.withdrawal_succeeded(&id, fee_paid_msat.unwrap_or(0));
+                        let resolution = stable::resolve_payment_event(&mut self.stable_channels, &event);
+                        let (recorded, fee_msats) = match resolution {
+                            Some(PaymentResolution::Succeeded { index, fee_msats }) => {
+                                (Some(self.stable_channels[index].channel_id), fee_msats)
+                            }
+                            _ => (None, 0),
+                        };
+                        if recorded.is_some() {
+                            self.save_stable_channels();
+                            if let Some(audit) = &mut self.audit {
+                                audit.record_payment_result(&id, "succeeded");
+                            }
+                        }
+                        self.payment_history.annotate(&id, self.btc_price, recorded.as_ref(), fee_msats);
+                    }
+                    self.finish_pending_removals();
                     self.update_balances();
                 }
 
-                Event::PaymentSuccessful { payment_id: _, payment_hash, payment_preimage: _, fee_paid_msat: _ } => {
-                    self.status_message = format!("Sent payment {}", payment_hash);
+                Event::PaymentFailed { payment_id, payment_hash, reason } => {
+                    self.status_log.error(format!("Payment {:?} failed: {:?}", payment_hash, reason));
+                    self.payment_history.invalidate();
+                    let mut failed = None;
+                    if let Some(id) = payment_id {
+                        self.pending_payments.failed(&id, reason);
+                        self.accounts.withdrawal_failed(&id, format!("{:?}", reason));
+                        if let Some(PaymentResolution::Failed { index }) =
+                            stable::resolve_payment_event(&mut self.stable_channels, &event)
+                        {
+                            let sc = &self.stable_channels[index];
+                            self.status_log.error(format!(
+                                "Stability payment on channel {} failed ({} in a row): {:?}",
+                                sc.channel_id, sc.consecutive_failures, reason
+                            ));
+                            if let Some(audit) = &mut self.audit {
+                                audit.record_payment_result(&id, &format!("failed: {:?}", reason));
+                            }
+                            failed = Some(index);
+                        }
+                    }
+                    // Retry now under the retry policy rather than at the next scheduled check
+                    if let Some(i) = failed {
+                        if !self.pending_removals.contains(&self.stable_channels[i].channel_id) {
+                            self.check_stable_channel_now(i);
+                        }
+                        self.save_stable_channels();
+                    }
+                    self.finish_pending_removals();
+                }
+
+                Event::PaymentClaimable { payment_hash, claimable_amount_msat, .. } => {
+                    // We only issue invoices we hold the preimage for, so a manual claim can
+                    // never complete; fail it back rather than leave the HTLC hanging
+                    self.status_log.warn(format!(
+                        "Failing back unclaimable payment {} of {} msats",
+                        payment_hash, claimable_amount_msat
+                    ));
+                    if let Err(e) = self.node.bolt11_payment().fail_for_hash(payment_hash) {
+                        error!("Failed to fail back payment {}: {}", payment_hash, e);
+                    }
+                }
+
+                Event::ChannelPending { channel_id, user_channel_id, counterparty_node_id, .. } => {
+                    self.onchain_activity.invalidate();
+                    self.channel_snapshot.invalidate();
+                    self.record_jit_channel(channel_id, user_channel_id);
+                    self.status_log.info(format!(
+                        "Channel {} with {} is pending confirmation",
+                        channel_id, counterparty_node_id
+                    ));
+                    self.enforce_channel_policy(channel_id, counterparty_node_id);
+                }
+
+                Event::PaymentReceived { payment_id, payment_hash, amount_msat, custom_records, .. } => {
+                    // Only count it as an adjustment if the channel's counterparty vouches for it
+                    let stability = stable::read_stability_payment(&custom_records).and_then(|claimed| {
+                        let sc = self.stable_channels.iter().find(|sc| sc.channel_id == claimed.channel_id)?;
+                        match stable::verify_stability_payment(&*self.node, sc, &custom_records, amount_msat) {
+                            Ok(info) => Some(info),
+                            Err(reason) => {
+                                warn!(
+                                    "Treating payment of {} msats tagged for channel {} as ordinary: {}",
+                                    amount_msat, claimed.channel_id, reason
+                                );
+                                None
+                            }
+                        }
+                    });
+                    if let Some(id) = payment_id {
+                        let fee_msats = stability.as_ref().map_or(0, |info| info.fee_msats);
+                        self.payment_history.annotate(&id, self.btc_price, stability.as_ref().map(|info| &info.channel_id), fee_msats);
+                        self.payment_history.label_received(&id, &payment_hash);
+                    }
+                    if let Some(envelope) = stable::read_stable_message(&self.node, &custom_records) {
+                        self.handle_stable_message(envelope);
+                    } else if let Some(info) = stability {
+                        self.status_log.info(format!(
+                            "Received stability adjustment of {} msats on channel {}",
+                            amount_msat, info.channel_id
+                        ));
+                        if let Some(sc) = self.stable_channels.iter_mut().find(|sc| sc.channel_id == info.channel_id) {
+                            stable::record_received_payment(sc, payment_id, amount_msat, info.fee_msats);
+                            self.save_stable_channels();
+                        }
+                    } else if payment_id.map_or(false, |id| history::is_offer_payment(&self.node, &id)) {
+                        self.status_log.info(format!("Received offer payment of {} msats", amount_msat));
+                        if !self.is_lsp {
+                            self.accounts.credit(&payment_hash, amount_msat);
+                        }
+                    } else {
+                        self.status_log.info(format!("Received payment of {} msats", amount_msat));
+                        if !self.is_lsp {
+                            self.accounts.credit(&payment_hash, amount_msat);
+                        }
+                    }
                     self.update_balances();
                 }
 
-                Event::PaymentReceived { amount_msat, .. } => {
-                    self.status_message = format!("Received payment of {} msats", amount_msat);
+                Event::ChannelClosed { channel_id, reason, .. } => {
+                    self.channel_snapshot.invalidate();
+                    self.pending_closes.finish(&channel_id);
+                    self.alerts.channel_closed(&channel_id, reason.as_ref());
+                    let sweep_sats = onchain::pending_sweep_sats(&self.node.list_balances());
+                    self.status_log.info(if sweep_sats > 0 {
+                        format!("Channel {} has been closed; {} sats sweeping on-chain", channel_id, sweep_sats)
+                    } else {
+                        format!("Channel {} has been closed", channel_id)
+                    });
+                    let mut detached = false;
+                    for sc in self.stable_channels.iter_mut().filter(|sc| sc.channel_id == channel_id) {
+                        sc.close_seen = true;
+                        if sc.keyed_by_counterparty {
+                            sc.awaiting_reattach = true;
+                            detached = true;
+                            self.status_log.warn(format!(
+                                "Stable channel {} closed; waiting to re-attach to the next channel with {}",
+                                channel_id, sc.counterparty
+                            ));
+                        }
+                    }
+                    if detached {
+                        self.save_stable_channels();
+                    }
                     self.update_balances();
                 }
 
-                Event::ChannelClosed { channel_id, .. } => {
-                    self.status_message = format!("Channel {} has been closed", channel_id);
+                Event::PaymentForwarded {
+                    prev_channel_id,
+                    next_channel_id,
+                    total_fee_earned_msat,
+                    outbound_amount_forwarded_msat,
+                    ..
+                } => {
+                    let fees_msats = total_fee_earned_msat.unwrap_or(0);
+                    let volume_msats = outbound_amount_forwarded_msat.unwrap_or(0);
+                    self.routing.record_forward(&prev_channel_id, &next_channel_id, volume_msats, fees_msats);
+                    self.status_log.info(format!(
+                        "Forwarded {} sats from {} to {}, earning {} msats",
+                        volume_msats / 1000, prev_channel_id, next_channel_id, fees_msats
+                    ));
+                    // Stable channels on either side now hold different balances
+                    self.channel_snapshot.invalidate();
                     self.update_balances();
                 }
 
@@ -241,405 +798,1142 @@ impl ServerApp {
         }
     }
 
-    pub fn generate_invoice(&mut self) -> bool {
-        if let Ok(amount) = self.invoice_amount.parse::<u64>() {
-            let msats = amount * 1000;
-            match self.node.bolt11_payment().receive(
-                msats,
-                &Bolt11InvoiceDescription::Direct(Description::new("Invoice".to_string()).unwrap()),
-                3600,
-            ) {
-                Ok(invoice) => {
-                    self.invoice_result = invoice.to_string();
-                    self.status_message = "Invoice generated".to_string();
-                    true
-                }
-                Err(e) => {
-                    self.status_message = format!("Error: {}", e);
-                    false
-                }
+    /// Answers a counterparty's stable channel proposal, designating the channel if accepted,
+    /// and ends the peg when the counterparty unpegs
+    fn handle_stable_message(&mut self, envelope: StableMessageEnvelope) {
+        let (expected_usd, currency, stable_fraction, proposer_receives) = match envelope.message {
+            StableMessage::ProposeStable { expected_usd, currency, stable_fraction, proposer_receives } => {
+                (expected_usd, currency, stable_fraction, proposer_receives)
             }
-        } else {
-            self.status_message = "Invalid amount".to_string();
-            false
-        }
-    }
+            StableMessage::UnpegStable => {
+                self.unpeg_counterparty(envelope.sender);
+                return;
+            }
+            message => {
+                warn!("Ignoring unexpected stable message from {}: {:?}", envelope.sender, message);
+                return;
+            }
+        };
 
-    pub fn pay_invoice(&mut self) -> bool {
-        match Bolt11Invoice::from_str(&self.invoice_to_pay) {
-            Ok(invoice) => match self.node.bolt11_payment().send(&invoice, None) {
-                Ok(payment_id) => {
-                    self.status_message = format!("Payment sent, ID: {}", payment_id);
-                    self.invoice_to_pay.clear();
-                    self.update_balances();
-                    true
-                }
-                Err(e) => {
-                    self.status_message = format!("Payment error: {}", e);
-                    false
+        // The LSP only ever provides stability
+        let evaluation = if proposer_receives {
+            self.evaluate_stable_proposal(&envelope.sender, expected_usd, currency, stable_fraction)
+        } else {
+            Err("This LSP only provides stability; it doesn't take the stable side".to_string())
+        };
+        let reply = match evaluation {
+            Ok(channel) => {
+                let stable_channel =
+                    self.new_stable_channel(&channel, USD::from_f64(expected_usd), currency, stable_fraction);
+                let float_sats = stable_channel.float_btc.to_sats();
+                self.upsert_stable_channel(stable_channel);
+                self.save_stable_channels();
+                self.status_log.info(format!(
+                    "Accepted {} stable proposal from {} on channel {}",
+                    currency.format(expected_usd), envelope.sender, channel.channel_id
+                ));
+                StableMessage::AcceptStable {
+                    expected_usd,
+                    fee_ppm: self.stability_fee_ppm,
+                    currency,
+                    stable_fraction,
+                    float_sats,
+                    proposer_receives,
                 }
-            },
-            Err(e) => {
-                self.status_message = format!("Invalid invoice: {}", e);
-                false
             }
+            Err(reason) => {
+                self.status_log.error(format!(
+                    "Rejected {} stable proposal from {}: {}",
+                    currency.format(expected_usd), envelope.sender, reason
+                ));
+                StableMessage::RejectStable { reason }
+            }
+        };
+
+        if let Err(e) = stable::send_stable_message(&self.node, envelope.sender, reply) {
+            error!("Failed to reply to stable proposal from {}: {}", envelope.sender, e);
         }
     }
 
-    pub fn get_address(&mut self) -> bool {
-        match self.node.onchain_payment().new_address() {
-            Ok(address) => {
-                self.on_chain_address = address.to_string();
-                self.status_message = "Address generated".to_string();
-                true
-            }
-            Err(e) => {
-                self.status_message = format!("Error: {}", e);
-                false
+    /// Settles and stops the stable channels with `peer` after it unpegged. The entries
+    /// are kept, marked unpegged, until the peer proposes a new peg or they are removed.
+    fn unpeg_counterparty(&mut self, peer: PublicKey) {
+        let mut unpegged = Vec::new();
+        for sc in &mut self.stable_channels {
+            if sc.counterparty != peer || sc.unpegged_at.is_some() {
+                continue;
             }
+            let action = stable::unpeg(&*self.node, sc, price_in(sc.currency, self.btc_price));
+            self.webhooks.notify(sc, &action, sc.latest_price);
+            unpegged.push((sc.channel_id, action));
+        }
+
+        if unpegged.is_empty() {
+            warn!("Ignoring unpeg from {}: no pegged stable channel", peer);
+            return;
+        }
+        for (channel_id, action) in unpegged {
+            self.status_log.info(format!("Channel {} unpegged by the user: final adjustment: {}", channel_id, action));
+            self.stability_outcomes.insert(channel_id, StabilityOutcome { checked_at: Instant::now(), action });
         }
+        self.save_stable_channels();
     }
 
-    pub fn send_onchain(&mut self) -> bool {
-        if let Ok(amount) = self.on_chain_amount.parse::<u64>() {
-            match Address::from_str(&self.on_chain_address) {
-                Ok(addr) => match addr.require_network(Network::Signet) {
-                    Ok(valid_addr) => match self.node.onchain_payment().send_to_address(&valid_addr, amount, None) {
-                        Ok(txid) => {
-                            self.status_message = format!("Transaction sent: {}", txid);
-                            self.update_balances();
-                            true
-                        }
-                        Err(e) => {
-                            self.status_message = format!("Transaction error: {}", e);
-                            false
-                        }
-                    },
-                    Err(_) => {
-                        self.status_message = "Invalid address for this network".to_string();
-                        false
-                    }
-                },
-                Err(_) => {
-                    self.status_message = "Invalid address".to_string();
-                    false
-                }
+    /// Checks a proposal against the LSP's policy, returning the channel to designate
+    fn evaluate_stable_proposal(
+        &self,
+        counterparty: &PublicKey,
+        expected_usd: f64,
+        currency: Currency,
+        stable_fraction: f64,
+    ) -> Result<ChannelDetails, String> {
+        if expected_usd <= 0.0 {
+            return Err("Target must be positive".to_string());
+        }
+        if !(stable_fraction > 0.0 && stable_fraction <= 100.0) {
+            return Err("Stable fraction must be above 0% and at most 100%".to_string());
+        }
+        let price = price_in(currency, self.btc_price);
+        if currency != Currency::USD && valid_price(price).is_none() {
+            return Err(format!("No {} price available", currency));
+        }
+        let target_usd = stable::fiat_to_usd(expected_usd, currency, price, self.btc_price);
+        if target_usd > MAX_STABLE_USD {
+            return Err(format!("LSP caps stable channels at ${:.2}", MAX_STABLE_USD));
+        }
+
+        let channel = self
+            .node
+            .list_channels()
+            .into_iter()
+            .filter(|c| c.counterparty_node_id == *counterparty && c.is_channel_ready)
+            .max_by_key(|c| c.channel_value_sats)
+            .ok_or_else(|| "No ready channel with this node".to_string())?;
+
+        self.check_stable_cap(&channel, false, target_usd)?;
+
+        if price > 0.0 {
+            let capacity = USD::from_bitcoin(Bitcoin::from_sats(channel.channel_value_sats), price);
+            if expected_usd > capacity.to_f64() {
+                return Err(format!("Target exceeds channel capacity of {}", currency.format(capacity.to_f64())));
             }
-        } else {
-            self.status_message = "Invalid amount".to_string();
-            false
         }
+
+        Ok(channel)
     }
 
     pub fn show_balance_section(&mut self, ui: &mut egui::Ui) {
         ui.group(|ui| {
             ui.heading("Balances");
+            ui.horizontal(display::unit_toggle);
             ui.add_space(5.0);
 
+            // Balances read before the first sync are zero, not empty
+            if !self.sync_monitor.state().first_sync_done {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Syncing with the chain; balances appear once the first sync completes");
+                });
+                return;
+            }
+
+            let balances = self.node_ui.balances;
             ui.horizontal(|ui| {
                 ui.label("Lightning:");
-                ui.monospace(format!("{:.8} BTC", self.lightning_balance_btc));
-                ui.monospace(format!("(${:.2})", self.lightning_balance_usd));
+                ui.monospace(display::btc(Bitcoin::from_btc(balances.lightning_btc)));
+                ui.monospace(format!("({})", USD::from_f64(balances.lightning_usd).format_usd()));
             });
 
             ui.horizontal(|ui| {
                 ui.label("On-chain:  ");
-                ui.monospace(format!("{:.8} BTC", self.onchain_balance_btc));
-                ui.monospace(format!("(${:.2})", self.onchain_balance_usd));
+                ui.monospace(display::btc(Bitcoin::from_btc(balances.onchain_btc)));
+                ui.monospace(format!("({})", USD::from_f64(balances.onchain_usd).format_usd()));
             });
+            self.onchain_activity.show_balance_breakdown(ui);
 
             ui.horizontal(|ui| {
                 ui.label("Total:     ");
-                ui.strong(format!("{:.8} BTC", self.total_balance_btc));
-                ui.strong(format!("(${:.2})", self.total_balance_usd));
+                ui.strong(display::btc(Bitcoin::from_btc(balances.total_btc)));
+                ui.strong(format!("({})", USD::from_f64(balances.total_usd).format_usd()));
             });
+            ui.add_space(5.0);
+            self.node_ui.balances.show_breakdown(ui);
 
             ui.add_space(5.0);
+            let stats = get_price_stats();
             ui.label(format!(
-                "Price: ${:.2} | Updated: {} seconds ago",
-                self.btc_price,
-                self.last_update.elapsed().as_secs()
+                "Price: {} | Updated: {} seconds ago ({})",
+                USD::from_f64(self.btc_price).format_usd(), stats.age_secs, stats.transport
             ));
         });
     }
 
-    pub fn show_invoice_section(&mut self, ui: &mut egui::Ui) {
+    pub fn show_node_info_section(&mut self, ui: &mut egui::Ui, port: u16) {
         ui.group(|ui| {
-            ui.label("Generate Invoice");
-            ui.horizontal(|ui| {
-                ui.label("Amount (sats):");
-                ui.text_edit_singleline(&mut self.invoice_amount);
-                if ui.button("Get Invoice").clicked() {
-                    self.generate_invoice();
-                }
-            });
-
-            if !self.invoice_result.is_empty() {
-                ui.text_edit_multiline(&mut self.invoice_result);
-                if ui.button("Copy").clicked() {
-                    ui.output_mut(|o| o.copied_text = self.invoice_result.clone());
-                }
+            let uri = format!("{}@127.0.0.1:{}", self.node.node_id(), port);
+            if let Some(qr) = ui_util::qr_texture(ui.ctx(), &uri) {
+                ui.image(&qr);
+            }
+            ui.label(format!("Node ID: {}", self.node.node_id()));
+            ui.label(format!("Listening on: 127.0.0.1:{}", port));
+            ui_util::copy_button(ui, egui::Button::new("Copy URI"), || uri.clone());
+            ui.label(format!("Data directory: {}", self.data_dir.display()));
+            ui.label(format!("Chain source: {}", self.chain_source));
+            self.sync_monitor.show(ui);
+            egui::CollapsingHeader::new("Channel backups").show(ui, |ui| self.backups.show(ui));
+            if let Some((node_id, _)) = &self.exchange_peer {
+                let (color, label) = if self.exchange_reconnector.is_connected() {
+                    (egui::Color32::GREEN, "connected")
+                } else {
+                    (egui::Color32::RED, "disconnected")
+                };
+                ui.colored_label(color, format!("● Exchange {} {}", node_id, label));
             }
-        });
-    }
 
-    pub fn show_pay_invoice_section(&mut self, ui: &mut egui::Ui) {
-        ui.group(|ui| {
-            ui.label("Pay Invoice");
-            ui.text_edit_multiline(&mut self.invoice_to_pay);
-            if ui.button("Pay Invoice").clicked() {
-                self.pay_invoice();
+            match self.backup_flow.as_mut() {
+                Some(flow) => {
+                    if flow.show(ui, &mut self.wallet_seed) {
+                        self.status_log.info("Recovery phrase backup confirmed".to_string());
+                    }
+                    if ui.button("Hide recovery phrase").clicked() {
+                        self.backup_flow = None;
+                    }
+                }
+                None => {
+                    if ui.button("Back up recovery phrase").clicked() {
+                        self.backup_flow = Some(BackupFlow::default());
+                    }
+                }
             }
         });
     }
 
-    pub fn show_onchain_address_section(&mut self, ui: &mut egui::Ui) {
+    pub fn show_channels_section(&mut self, ui: &mut egui::Ui) {
+        let mut action = None;
         ui.group(|ui| {
-            ui.label("On-chain Address");
-            if ui.button("Get Address").clicked() {
-                self.get_address();
+            ui.heading("Lightning Channels");
+            if ui.button("Refresh Channel List").clicked() {
+                self.channel_snapshot.invalidate();
+                self.channel_snapshot.refresh_if_needed(&self.node);
             }
-
-            if !self.on_chain_address.is_empty() {
-                ui.label(self.on_chain_address.clone());
-                if ui.button("Copy").clicked() {
-                    ui.output_mut(|o| o.copied_text = self.on_chain_address.clone());
+            let stable_ids: Vec<ChannelId> = self.stable_channels.iter().map(|sc| sc.channel_id).collect();
+            action = channel_table::show_channel_table(
+                ui,
+                "lsp_channels",
+                self.channel_snapshot.channels(),
+                &self.node_ui.address_book,
+                &stable_ids,
+                &self.pending_closes,
+                true,
+            );
+
+            if let Some(channel) = self.force_close_candidate.clone() {
+                let is_stable = stable_ids.contains(&channel.channel_id);
+                match channel_table::show_force_close_confirmation(ui, &channel, is_stable) {
+                    Some(true) => {
+                        self.force_close_candidate = None;
+                        self.force_close_channel(&channel);
+                    }
+                    Some(false) => self.force_close_candidate = None,
+                    None => {}
                 }
             }
         });
-    }
-
-    pub fn show_onchain_send_section(&mut self, ui: &mut egui::Ui) {
-        ui.group(|ui| {
-            ui.label("On-chain Send");
-            ui.horizontal(|ui| {
-                ui.label("Address:");
-                ui.text_edit_singleline(&mut self.on_chain_address);
-            });
-            ui.horizontal(|ui| {
-                ui.label("Amount (sats):");
-                ui.text_edit_singleline(&mut self.on_chain_amount);
-            });
 
-            if ui.button("Send On-chain").clicked() {
-                self.send_onchain();
+        match action {
+            Some(ChannelAction::Close(channel)) => self.close_channel(&channel),
+            Some(ChannelAction::ForceClose(channel)) => self.force_close_candidate = Some(channel),
+            Some(ChannelAction::Designate(channel_id)) => {
+                self.selected_channel_id = channel_id.to_string();
+                self.status_log.info("Channel selected; set the target and designate it below".to_string());
             }
-        });
-    }
-
-    pub fn show_node_info_section(&mut self, ui: &mut egui::Ui, port: u16) {
-        ui.group(|ui| {
-            ui.label(format!("Node ID: {}", self.node.node_id()));
-            ui.label(format!("Listening on: 127.0.0.1:{}", port));
-        });
+            None => {}
+        }
     }
 
-    pub fn show_channels_section(&mut self, ui: &mut egui::Ui, channel_info: &mut String) {
-        ui.group(|ui| {
-            ui.heading("Lightning Channels");
-            if ui.button("Refresh Channel List").clicked() {
-                *channel_info = self.update_channel_info();
+    /// Starts a cooperative close of `channel`
+    fn close_channel(&mut self, channel: &ChannelDetails) {
+        let result = self.node.close_channel(&channel.user_channel_id, channel.counterparty_node_id);
+        match result {
+            Ok(_) => {
+                self.pending_closes.start(channel.channel_id);
+                self.status_log.info(format!("Closing channel: {}", channel.channel_id));
             }
-            ui.text_edit_multiline(channel_info);
-        });
+            Err(e) => self.status_log.error(format!("Error closing channel: {}", e)),
+        }
+        self.channel_snapshot.invalidate();
     }
 
-    pub fn update_channel_info(&mut self) -> String {
+    /// Closes a channel a peer opened to us if it breaks the inbound channel policy. Our own
+    /// opens, JIT channels included, aren't checked.
+    fn enforce_channel_policy(&mut self, channel_id: ChannelId, counterparty: PublicKey) {
         let channels = self.node.list_channels();
-        if channels.is_empty() {
-            return "No channels found.".to_string();
-        } else {
-            let mut info = String::new();
-            for (i, channel) in channels.iter().enumerate() {
-                let is_stable = self.stable_channels.iter().any(|sc| sc.channel_id == channel.channel_id);
-                info.push_str(&format!(
-                    "Channel {}: ID: {}, Value: {} sats, Ready: {}{}\n",
-                    i + 1,
-                    channel.channel_id,
-                    channel.channel_value_sats,
-                    channel.is_channel_ready,
-                    if is_stable { " [STABLE]" } else { "" }
-                ));
+        let Some(channel) = channels.iter().find(|c| c.channel_id == channel_id) else {
+            return;
+        };
+        if channel.is_outbound {
+            return;
+        }
+        let inbound = InboundChannel {
+            counterparty,
+            channel_value_sats: channel.channel_value_sats,
+            zero_conf: channel.confirmations_required == Some(0),
+            existing_with_peer: channels
+                .iter()
+                .filter(|c| c.counterparty_node_id == counterparty && c.channel_id != channel_id)
+                .count(),
+        };
+        let Err(reason) = self.channel_policy.evaluate(&inbound) else {
+            return;
+        };
+        warn!("Rejecting inbound channel {} from {}: {}", channel_id, counterparty, reason);
+        // Nothing of ours is in an inbound channel, so force-closing costs us nothing
+        let result = self.node.force_close_channel(
+            &channel.user_channel_id,
+            counterparty,
+            Some(format!("Rejected by channel policy: {}", reason)),
+        );
+        match result {
+            Ok(()) => self.status_log.warn(format!("Rejected inbound channel {}: {}", channel_id, reason)),
+            Err(e) => self.status_log.error(format!(
+                "Inbound channel {} breaks the policy ({}) but could not be closed: {}",
+                channel_id, reason, e
+            )),
+        }
+    }
+
+    /// Broadcasts our commitment for `channel`, ending its peg first if it is a stable channel
+    fn force_close_channel(&mut self, channel: &ChannelDetails) {
+        let result = self.node.force_close_channel(
+            &channel.user_channel_id,
+            channel.counterparty_node_id,
+            Some("Force-closed by the operator".to_string()),
+        );
+        match result {
+            Ok(()) => {
+                self.pending_closes.finish(&channel.channel_id);
+                self.status_log.info(format!("Force-closing channel: {}", channel.channel_id));
+                let mut stable = false;
+                for sc in self.stable_channels.iter_mut().filter(|sc| sc.channel_id == channel.channel_id) {
+                    stable::record_force_close(sc, price_in(sc.currency, self.btc_price));
+                    stable = true;
+                }
+                if stable {
+                    self.save_stable_channels();
+                    self.status_log.info(format!(
+                        "Force-closing stable channel {}; peg ended at the current price",
+                        channel.channel_id
+                    ));
+                }
             }
-            info
+            Err(e) => self.status_log.error(format!("Error force-closing channel: {}", e)),
         }
+        self.channel_snapshot.invalidate();
     }
 
     pub fn open_channel(&mut self) -> bool {
-        match PublicKey::from_str(&self.open_channel_node_id) {
-            Ok(node_id) => match SocketAddress::from_str(&self.open_channel_address) {
-                Ok(net_address) => match self.open_channel_amount.parse::<u64>() {
-                    Ok(sats) => {
-                        let push_msat = (sats / 2) * 1000;
-                        let channel_config: Option<ChannelConfig> = None;
-
-                        match self.node.open_announced_channel(
-                            node_id,
-                            net_address,
-                            sats,
-                            Some(push_msat),
-                            channel_config,
-                        ) {
-                            Ok(_) => {
-                                self.status_message = format!("Channel opening initiated with {} for {} sats", node_id, sats);
-                                true
-                            }
-                            Err(e) => {
-                                self.status_message = format!("Error opening channel: {}", e);
-                                false
-                            }
-                        }
-                    }
-                    Err(_) => {
-                        self.status_message = "Invalid amount format".to_string();
-                        false
-                    }
-                },
-                Err(_) => {
-                    self.status_message = "Invalid network address format".to_string();
-                    false
+        match self.open_channel_command() {
+            Ok(command) => {
+                if let AppCommand::OpenChannel { node_id, .. } = &command {
+                    self.status_log.info(format!("Opening channel with {}...", node_id));
                 }
-            },
-            Err(_) => {
-                self.status_message = "Invalid node ID format".to_string();
+                self.worker.submit(command);
+                true
+            }
+            Err(e) => {
+                self.status_log.error(e);
                 false
             }
         }
     }
 
-    pub fn close_specific_channel(&mut self) {
-        if self.channel_id_to_close.is_empty() {
-            self.status_message = "Please enter a channel ID to close".to_string();
-            return;
+    /// Validates the Open Channel form
+    fn open_channel_command(&self) -> Result<AppCommand, String> {
+        let node_id = PublicKey::from_str(self.open_channel_node_id.trim())
+            .map_err(|_| "Invalid node ID format".to_string())?;
+        let address = SocketAddress::from_str(self.open_channel_address.trim())
+            .map_err(|_| "Invalid network address format".to_string())?;
+        let amount_sats = node_ui::parse_sats(&self.open_channel_amount)
+            .map_err(|_| "Invalid amount format".to_string())?;
+
+        let push_sats = node_ui::parse_sats(&self.open_channel_push)
+            .map_err(|_| "Invalid push amount".to_string())?;
+        let max_push_sats = amount_sats.saturating_sub((amount_sats / 100).max(MIN_CHANNEL_RESERVE_SATS));
+        if push_sats > max_push_sats {
+            return Err(format!("Push amount can be at most {} sats, keeping the channel reserve", max_push_sats));
+        }
+        if push_sats > PUSH_CONFIRM_THRESHOLD_SATS && self.open_channel_push_confirm.trim() != self.open_channel_push.trim() {
+            return Err(format!("Type the push amount again to confirm giving {} sats to the peer", push_sats));
         }
 
-        let input = self.channel_id_to_close.trim();
-        if input.len() == 64 && input.chars().all(|c| c.is_ascii_hexdigit()) {
-            if let Ok(bytes) = hex::decode(input) {
-                for channel in self.node.list_channels() {
-                    if channel.channel_id.0.to_vec() == bytes {
-                        let result = self.node.close_channel(&channel.user_channel_id, channel.counterparty_node_id);
-                        self.status_message = match result {
-                            Ok(_) => format!("Closing channel: {}", input),
-                            Err(e) => format!("Error closing channel: {}", e),
-                        };
-                        self.channel_id_to_close.clear();
-                        return;
-                    }
-                }
-            }
-            self.status_message = "Channel ID not found.".to_string();
-        } else {
-            for channel in self.node.list_channels() {
-                if channel.channel_id.to_string() == input {
-                    let result = self.node.close_channel(&channel.user_channel_id, channel.counterparty_node_id);
-                    self.status_message = match result {
-                        Ok(_) => format!("Closing channel: {}", input),
-                        Err(e) => format!("Error closing channel: {}", e),
-                    };
-                    self.channel_id_to_close.clear();
-                    return;
-                }
+        Ok(AppCommand::OpenChannel {
+            node_id,
+            address,
+            amount_sats,
+            push_msat: (push_sats > 0).then_some(push_sats * 1000),
+            announce: !self.open_channel_private,
+            channel_config: self.open_channel_config()?,
+        })
+    }
+
+    /// The routing policy from the advanced fields, or None if they are all blank
+    fn open_channel_config(&self) -> Result<Option<ChannelConfig>, String> {
+        fn parse<T: FromStr>(input: &str, name: &str) -> Result<Option<T>, String> {
+            let input = input.trim();
+            if input.is_empty() {
+                return Ok(None);
             }
-            self.status_message = "Channel not found.".to_string();
+            input.parse().map(Some).map_err(|_| format!("Invalid {}", name))
         }
-    }
 
-    pub fn designate_stable_channel(&mut self) {
-        if self.selected_channel_id.is_empty() {
-            self.status_message = "Please select a channel ID".to_string();
-            return;
+        let fee_base_msat = parse::<u32>(&self.open_channel_fee_base_msat, "base fee")?;
+        let fee_ppm = parse::<u32>(&self.open_channel_fee_ppm, "fee rate")?;
+        let cltv_delta = parse::<u16>(&self.open_channel_cltv_delta, "CLTV delta")?;
+        if fee_base_msat.is_none() && fee_ppm.is_none() && cltv_delta.is_none() {
+            return Ok(None);
         }
 
-        let amount = match self.stable_channel_amount.parse::<f64>() {
+        let mut config = ChannelConfig::default();
+        if let Some(fee_base_msat) = fee_base_msat {
+            config.forwarding_fee_base_msat = fee_base_msat;
+        }
+        if let Some(fee_ppm) = fee_ppm {
+            config.forwarding_fee_proportional_millionths = fee_ppm;
+        }
+        if let Some(cltv_delta) = cltv_delta {
+            config.cltv_expiry_delta = cltv_delta;
+        }
+        Ok(Some(config))
+    }
+
+    /// Reconnects to the exchange peer, if one is configured, with backoff between attempts
+    fn reconnect_exchange_if_needed(&mut self) {
+        let Some((node_id, address)) = self.exchange_peer.clone() else {
+            return;
+        };
+        let connected = reconnect::is_peer_connected(&self.node, &node_id);
+        if self.exchange_reconnector.poll(connected, Instant::now()) {
+            self.worker.submit(AppCommand::Connect { node_id, address, persist: true });
+        }
+    }
+
+    /// Applies the results of commands the worker has finished
+    fn process_worker_results(&mut self) {
+        while let Some(result) = self.worker.try_recv() {
+            match result {
+                AppResult::Connected { node_id, result } => {
+                    self.exchange_reconnector.record_attempt(result.is_ok(), Instant::now());
+                    match result {
+                        Ok(()) => info!("Reconnected to exchange {}", node_id),
+                        Err(e) => warn!("Failed to reconnect to exchange {}: {}", node_id, e),
+                    }
+                }
+                AppResult::ChannelOpened { node_id, amount_sats, result: Ok(user_channel_id) } => {
+                    self.manual_channel_ids.insert(user_channel_id);
+                    self.status_log.info(format!("Channel opening initiated with {} for {} sats", node_id, amount_sats));
+                    self.open_channel_node_id.clear();
+                    self.open_channel_amount = "100000".to_string();
+                    self.open_channel_push = "0".to_string();
+                    self.open_channel_push_confirm.clear();
+                }
+                AppResult::ChannelOpened { result: Err(e), .. } => {
+                    self.status_log.error(format!("Error opening channel: {}", e));
+                }
+                AppResult::DepositInvoiceGenerated { account, result: Ok(invoice) } => {
+                    self.accounts.record_deposit_invoice(&account, &invoice);
+                    self.payment_history.set_label(invoice.payment_hash().to_string(), format!("Deposit to {}", account));
+                    self.status_log.info(format!("Deposit invoice generated for {}", account));
+                }
+                AppResult::DepositInvoiceGenerated { account, result: Err(e) } => {
+                    self.status_log.error(format!("Error generating deposit invoice for {}: {}", account, e));
+                }
+                AppResult::AccountWithdrawSent { withdrawal_id, result } => {
+                    match &result {
+                        Ok(id) => {
+                            self.pending_payments.track(&self.node, *id);
+                            self.payment_history.set_label(hex::encode(id.0), format!("Withdrawal {}", withdrawal_id));
+                            self.payment_history.invalidate();
+                            self.status_log.info(format!("Withdrawal {} sent, ID: {}", withdrawal_id, id));
+                        }
+                        Err(e) => self.status_log.error(format!("Withdrawal {} failed: {}", withdrawal_id, e)),
+                    }
+                    self.accounts.withdrawal_sent(withdrawal_id, result.map_err(|e| e.to_string()));
+                }
+                AppResult::AccountOnchainSent { withdrawal_id, result } => {
+                    match &result {
+                        Ok(txid) => {
+                            self.onchain_activity.invalidate();
+                            self.status_log.info(format!("Withdrawal {} sent on-chain: {}", withdrawal_id, txid));
+                            self.update_balances();
+                        }
+                        Err(e) => self.status_log.error(format!("Withdrawal {} failed: {}", withdrawal_id, e)),
+                    }
+                    self.accounts.onchain_withdrawal_sent(withdrawal_id, result.map_err(|e| e.to_string()));
+                }
+                result => {
+                    let funds_moved = matches!(result, AppResult::InvoicePaid(Ok(_)) | AppResult::OnchainSent(Ok(_)));
+                    if let AppResult::InvoicePaid(Ok(id)) | AppResult::OfferPaid(Ok(id)) = &result {
+                        self.pending_payments.track(&self.node, *id);
+                    }
+                    if let Some(status) = self.node_ui.apply_result(result) {
+                        self.status_log.info(status);
+                    }
+                    for (key, label) in self.node_ui.take_new_labels() {
+                        self.payment_history.set_label(key, label);
+                    }
+                    if funds_moved {
+                        self.payment_history.invalidate();
+                        self.update_balances();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Answers admin API requests that need the stable channel state
+    /// Answers requests from the admin API and the console
+    fn process_admin_requests(&mut self) {
+        let mut pending = Vec::new();
+        if let Some(admin) = self.admin.as_ref() {
+            pending.extend(std::iter::from_fn(|| admin.try_recv()));
+        }
+        if let Some(console) = self.console.as_ref() {
+            pending.extend(std::iter::from_fn(|| console.try_recv()));
+        }
+
+        for pending in pending {
+            match &pending.request {
+                AdminRequest::ListStableChannels => {
+                    let channels: Vec<serde_json::Value> = self
+                        .stable_channels
+                        .iter()
+                        .map(|sc| serde_json::json!({
+                            "channel_id": sc.channel_id.to_string(),
+                            "counterparty": sc.counterparty.to_string(),
+                            "expected_usd": sc.expected_usd.to_f64(),
+                            "stable_receiver_usd": sc.stable_receiver_usd.to_f64(),
+                            "dollars_from_par": stable::dollars_from_par(sc).to_f64(),
+                            "percent_from_par": stable::percent_from_par(sc),
+                            "risk_level": sc.risk_level,
+                            "payment_count": sc.payment_count,
+                            "stability_fee_ppm": sc.stability_fee_ppm,
+                            "stable_fraction": sc.stable_fraction,
+                            "float_btc": sc.float_btc.to_btc(),
+                            "unpegged_at": sc.unpegged_at,
+                            "accrued_fee_msats": sc.accrued_fee_msats,
+                            "native_btc_original": sc.native_btc_original.to_btc(),
+                            "native_btc": sc.native_btc.to_btc(),
+                            "provider_pnl_btc": stable::provider_pnl_btc(sc),
+                            "awaiting_approval_msats": sc.pending_approval_msats,
+                            "suspended_until": sc.suspended_until,
+                            "suspension_reason": sc.suspension_reason,
+                            "runway_down_percent": sc.runway.map(|r| r.down_percent),
+                            "runway_up_percent": sc.runway.and_then(|r| r.up_percent),
+                            "pending_removal": self.pending_removals.contains(&sc.channel_id),
+                        }))
+                        .collect();
+                    pending.reply(200, serde_json::Value::Array(channels));
+                }
+                AdminRequest::Exposure => {
+                    let exposure = stable::stable_exposure(&self.stable_channels, self.btc_price);
+                    pending.reply(200, serde_json::json!({
+                        "channel_count": self.stable_channels.len(),
+                        "target_usd": exposure.target_usd,
+                        "receiver_usd": exposure.receiver_usd,
+                        "worst_case_btc": exposure.worst_case_btc,
+                        "max_total_stable_usd": self.max_total_stable_usd,
+                        "price": self.btc_price,
+                    }));
+                }
+                AdminRequest::Routing => {
+                    pending.reply(200, self.routing.to_json(self.btc_price));
+                }
+                AdminRequest::Designate { target, expected_usd, settings } => {
+                    let result = self.designate(target, *expected_usd, settings);
+                    match result {
+                        Ok(message) => {
+                            self.status_log.info(message.clone());
+                            pending.reply(200, serde_json::json!({ "message": message }));
+                        }
+                        Err(message) => pending.reply(400, serde_json::json!({ "error": message })),
+                    }
+                }
+                AdminRequest::Remove { channel_id, settle_first } => {
+                    let index = self.stable_channels.iter().position(|sc| sc.channel_id.to_string() == *channel_id);
+                    match index {
+                        Some(index) => {
+                            self.remove_stable_channel(index, *settle_first);
+                            let message = self.status_log.latest().to_string();
+                            pending.reply(200, serde_json::json!({ "message": message }));
+                        }
+                        None => pending.reply(404, serde_json::json!({ "error": "not a stable channel" })),
+                    }
+                }
+                AdminRequest::CloseChannel { channel_id } => {
+                    let channel = self.node.list_channels().into_iter().find(|c| c.channel_id.to_string() == *channel_id);
+                    match channel {
+                        Some(channel) => {
+                            self.close_channel(&channel);
+                            let message = self.status_log.latest().to_string();
+                            pending.reply(200, serde_json::json!({ "message": message }));
+                        }
+                        None => pending.reply(404, serde_json::json!({ "error": "no such channel" })),
+                    }
+                }
+                AdminRequest::ManualChannel(user_channel_id) => {
+                    self.manual_channel_ids.insert(*user_channel_id);
+                    pending.reply(200, serde_json::json!({}));
+                }
+            }
+        }
+    }
+
+    pub fn close_specific_channel(&mut self) {
+        if self.channel_id_to_close.is_empty() {
+            self.status_log.info("Please enter a channel ID to close".to_string());
+            return;
+        }
+
+        let input = self.channel_id_to_close.trim();
+        if input.len() == 64 && input.chars().all(|c| c.is_ascii_hexdigit()) {
+            if let Ok(bytes) = hex::decode(input) {
+                for channel in self.node.list_channels() {
+                    if channel.channel_id.0.to_vec() == bytes {
+                        self.close_channel(&channel);
+                        self.channel_id_to_close.clear();
+                        return;
+                    }
+                }
+            }
+            self.status_log.warn("Channel ID not found.".to_string());
+        } else {
+            for channel in self.node.list_channels() {
+                if channel.channel_id.to_string() == input {
+                    self.close_channel(&channel);
+                    self.channel_id_to_close.clear();
+                    return;
+                }
+            }
+            self.status_log.warn("Channel not found.".to_string());
+        }
+    }
+
+    /// Builds a stable channel for `channel` with the LSP as the stable provider, pegged
+    /// to `expected_usd` in `currency`. All but `stable_fraction` percent of the user's
+    /// current balance is recorded as floating.
+    fn new_stable_channel(
+        &self,
+        channel: &ChannelDetails,
+        expected_usd: USD,
+        currency: Currency,
+        stable_fraction: f64,
+    ) -> StableChannel {
+        let price = price_in(currency, self.btc_price);
+        let expected_btc = Bitcoin::from_usd(expected_usd, price).unwrap_or_default();
+
+        let (our_balance_sats, their_balance_sats) = stable::channel_balances(channel);
+
+        let stable_provider_btc = Bitcoin::from_sats(our_balance_sats);
+        let stable_receiver_btc = Bitcoin::from_sats(their_balance_sats);
+        let stable_provider_usd = USD::from_bitcoin(stable_provider_btc, price);
+        let stable_receiver_usd = USD::from_bitcoin(stable_receiver_btc, price);
+
+        StableChannel {
+            channel_id: channel.channel_id,
+            counterparty: channel.counterparty_node_id,
+            is_stable_receiver: false,
+            expected_usd,
+            expected_btc,
+            stable_receiver_btc,
+            stable_receiver_usd,
+            stable_provider_btc,
+            stable_provider_usd,
+            latest_price: price,
+            risk_level: 0,
+            last_payment: None,
+            timestamp: 0,
+            formatted_datetime: "".to_string(),
+            sc_dir: self.data_dir.display().to_string(),
+            prices: VecDeque::new(),
+            pending_payment_id: None,
+            max_payment_usd: stable::DEFAULT_MAX_PAYMENT_USD,
+            max_payment_percent: stable::DEFAULT_MAX_PAYMENT_PERCENT,
+            approval_required_above_cap: false,
+            pending_approval_msats: None,
+            payment_approved: false,
+            pending_payment_msats: 0,
+            total_paid_to_user_msats: 0,
+            total_paid_to_lsp_msats: 0,
+            payment_count: 0,
+            peg_agreed: true,
+            max_risk_level: stable::DEFAULT_MAX_RISK_LEVEL,
+            consecutive_failures: 0,
+            twap_window_secs: stable::DEFAULT_TWAP_WINDOW_SECS,
+            twap_price: 0.0,
+            min_seconds_between_payments: stable::DEFAULT_MIN_SECONDS_BETWEEN_PAYMENTS,
+            max_daily_adjustment_usd: stable::DEFAULT_MAX_DAILY_ADJUSTMENT_USD,
+            last_payment_timestamp: 0,
+            daily_payments: VecDeque::new(),
+            keyed_by_counterparty: false,
+            awaiting_reattach: false,
+            close_seen: false,
+            reattach_candidates: Vec::new(),
+            stability_fee_ppm: self.stability_fee_ppm,
+            accrued_fee_msats: 0,
+            pending_fee_msats: 0,
+            currency,
+            stable_fraction,
+            float_btc: stable::float_btc_for(stable_receiver_btc, stable_fraction),
+            unpegged_at: None,
+            missed_settlements: VecDeque::new(),
+            closing_price: None,
+            designation_price: Some(price),
+            native_btc_original: stable_provider_btc,
+            native_btc: stable_provider_btc,
+            circuit_breaker_percent: stable::DEFAULT_CIRCUIT_BREAKER_PERCENT,
+            suspended_until: None,
+            suspension_reason: None,
+            payment_attempt: 0,
+            last_failure_no_route: false,
+            runway: None,
+        }
+    }
+
+    /// Adds a stable channel, or replaces the existing entry while keeping its running totals
+    fn upsert_stable_channel(&mut self, mut stable_channel: StableChannel) {
+        let same_designation = |sc: &&mut StableChannel| {
+            sc.channel_id == stable_channel.channel_id
+                || (sc.keyed_by_counterparty && stable_channel.keyed_by_counterparty && sc.counterparty == stable_channel.counterparty)
+        };
+        match self.stable_channels.iter_mut().find(same_designation) {
+            Some(sc) => {
+                stable_channel.total_paid_to_user_msats = sc.total_paid_to_user_msats;
+                stable_channel.total_paid_to_lsp_msats = sc.total_paid_to_lsp_msats;
+                stable_channel.payment_count = sc.payment_count;
+                stable_channel.accrued_fee_msats = sc.accrued_fee_msats;
+                stable_channel.prices = std::mem::take(&mut sc.prices);
+                stable_channel.last_payment_timestamp = sc.last_payment_timestamp;
+                stable_channel.daily_payments = std::mem::take(&mut sc.daily_payments);
+                stable_channel.missed_settlements = std::mem::take(&mut sc.missed_settlements);
+                stable_channel.designation_price = sc.designation_price.or(stable_channel.designation_price);
+                stable_channel.native_btc_original = sc.native_btc_original;
+                stable_channel.native_btc = sc.native_btc;
+                *sc = stable_channel;
+            }
+            None => self.stable_channels.push(stable_channel),
+        }
+    }
+
+    /// Rejects a designation that would take the summed targets over `max_total_stable_usd`.
+    /// A designation that replaces a pegged one only counts the difference; an unpegged one
+    /// is already out of the total. Amounts are in USD whatever the pegs' currencies.
+    fn check_stable_cap(&self, channel: &ChannelDetails, keyed_by_counterparty: bool, expected_usd: f64) -> Result<(), String> {
+        let replaced_usd = self
+            .stable_channels
+            .iter()
+            .filter(|sc| sc.unpegged_at.is_none())
+            .find(|sc| {
+                sc.channel_id == channel.channel_id
+                    || (sc.keyed_by_counterparty && keyed_by_counterparty && sc.counterparty == channel.counterparty_node_id)
+            })
+            .map_or(0.0, |sc| stable::to_usd(sc, sc.expected_usd.to_f64(), self.btc_price));
+        let current_usd = stable::stable_exposure(&self.stable_channels, self.btc_price).target_usd;
+
+        let total_usd = stable::total_after_designation(current_usd, replaced_usd, expected_usd);
+        if total_usd > self.max_total_stable_usd {
+            return Err(format!(
+                "Designation would bring total stable liability to ${:.2}, over the ${:.2} cap (${:.2} designated)",
+                total_usd, self.max_total_stable_usd, current_usd
+            ));
+        }
+        Ok(())
+    }
+
+    /// Designates the channel from the form, as the "Designate" button does
+    pub fn designate_stable_channel(&mut self) {
+        if self.selected_channel_id.is_empty() {
+            self.status_log.info("Please select a channel ID".to_string());
+            return;
+        }
+
+        let amount = match self.stable_channel_amount.parse::<f64>() {
             Ok(val) => val,
             Err(_) => {
-                self.status_message = "Invalid amount format".to_string();
+                self.status_log.error("Invalid amount format".to_string());
                 return;
             }
         };
 
-        let channel_id_str = self.selected_channel_id.trim().to_string();
+        let max_payment_usd = match self.stable_channel_max_payment_usd.parse::<f64>() {
+            Ok(val) if val > 0.0 => val,
+            _ => {
+                self.status_log.error("Invalid max payment USD".to_string());
+                return;
+            }
+        };
 
-        for channel in self.node.list_channels() {
-            if channel.channel_id.to_string() == channel_id_str {
-                let expected_usd = USD::from_f64(amount);
-                let expected_btc = Bitcoin::from_usd(expected_usd, self.btc_price);
+        let max_payment_percent = match self.stable_channel_max_payment_percent.parse::<f64>() {
+            Ok(val) if val > 0.0 => val,
+            _ => {
+                self.status_log.error("Invalid max payment percent".to_string());
+                return;
+            }
+        };
 
-                let unspendable = channel.unspendable_punishment_reserve.unwrap_or(0);
-                let our_balance_sats = (channel.outbound_capacity_msat / 1000) + unspendable;
-                let their_balance_sats = channel.channel_value_sats - our_balance_sats;
+        let max_risk_level = match self.stable_channel_max_risk_level.parse::<i32>() {
+            Ok(val) if val > 0 => val,
+            _ => {
+                self.status_log.error("Invalid max risk level".to_string());
+                return;
+            }
+        };
 
-                let stable_provider_btc = Bitcoin::from_sats(our_balance_sats);
-                let stable_receiver_btc = Bitcoin::from_sats(their_balance_sats);
-                let stable_provider_usd = USD::from_bitcoin(stable_provider_btc, self.btc_price);
-                let stable_receiver_usd = USD::from_bitcoin(stable_receiver_btc, self.btc_price);
+        let circuit_breaker_percent = match self.stable_channel_circuit_breaker_percent.parse::<f64>() {
+            Ok(val) if val > 0.0 => val,
+            _ => {
+                self.status_log.error("Invalid circuit breaker percent".to_string());
+                return;
+            }
+        };
 
-                let stable_channel = StableChannel {
-                    channel_id: channel.channel_id,
-                    counterparty: channel.counterparty_node_id,
-                    is_stable_receiver: false,
-                    expected_usd,
-                    expected_btc,
-                    stable_receiver_btc,
-                    stable_receiver_usd,
-                    stable_provider_btc,
-                    stable_provider_usd,
-                    latest_price: self.btc_price,
-                    risk_level: 0,
-                    payment_made: false,
-                    timestamp: 0,
-                    formatted_datetime: "".to_string(),
-                    sc_dir: LSP_DATA_DIR.to_string(),
-                    prices: "".to_string(),
-                };
+        let twap_window_secs = match self.stable_channel_twap_window_secs.parse::<u64>() {
+            Ok(val) if val > 0 => val,
+            _ => {
+                self.status_log.error("Invalid TWAP window".to_string());
+                return;
+            }
+        };
 
-                let mut found = false;
-                for sc in &mut self.stable_channels {
-                    if sc.channel_id == channel.channel_id {
-                        *sc = stable_channel.clone();
-                        found = true;
-                        break;
-                    }
-                }
+        let min_seconds_between_payments = match self.stable_channel_min_payment_interval.parse::<u64>() {
+            Ok(val) => val,
+            Err(_) => {
+                self.status_log.error("Invalid minimum payment interval".to_string());
+                return;
+            }
+        };
 
-                if !found {
-                    self.stable_channels.push(stable_channel);
-                }
+        let max_daily_adjustment_usd = match self.stable_channel_max_daily_usd.parse::<f64>() {
+            Ok(val) if val > 0.0 => val,
+            _ => {
+                self.status_log.error("Invalid max daily adjustment USD".to_string());
+                return;
+            }
+        };
 
-                self.save_stable_channels();
+        let stable_fraction = match self.stable_channel_fraction.parse::<f64>() {
+            Ok(val) if val > 0.0 && val <= 100.0 => val,
+            _ => {
+                self.status_log.error("Invalid stable fraction".to_string());
+                return;
+            }
+        };
+
+        let stability_fee_ppm = match self.stable_channel_fee_ppm.parse::<u32>() {
+            Ok(val) if val < 1_000_000 => val,
+            _ => {
+                self.status_log.error("Invalid stability fee".to_string());
+                return;
+            }
+        };
 
-                self.status_message = format!(
-                    "Channel {} designated as stable with target ${}",
-                    channel_id_str, amount
-                );
+        let settings = stable::StableChannelSettings {
+            max_payment_usd,
+            max_payment_percent,
+            approval_required_above_cap: self.stable_channel_require_approval,
+            max_risk_level,
+            twap_window_secs,
+            min_seconds_between_payments,
+            max_daily_adjustment_usd,
+            key_by_counterparty: self.stable_channel_key_by_counterparty,
+            stability_fee_ppm: Some(stability_fee_ppm),
+            currency: self.stable_channel_currency,
+            stable_fraction,
+            circuit_breaker_percent,
+        };
+
+        let target = self.selected_channel_id.trim().to_string();
+        match self.designate(&target, amount, &settings) {
+            Ok(message) => {
+                self.status_log.info(message);
                 self.selected_channel_id.clear();
                 self.stable_channel_amount = EXPECTED_USD.to_string();
-                return;
             }
+            Err(message) => self.status_log.error(message),
+        }
+    }
+
+    /// Designates the channel with id `target` as stable and saves. A counterparty pubkey
+    /// designates that peer's largest ready channel and follows the peer.
+    pub fn designate(&mut self, target: &str, expected_usd: f64, settings: &stable::StableChannelSettings) -> Result<String, String> {
+        if settings.stability_fee_ppm.map_or(false, |ppm| ppm >= 1_000_000) {
+            return Err("Stability fee must be below 1,000,000 ppm".to_string());
+        }
+        if !(settings.stable_fraction > 0.0 && settings.stable_fraction <= 100.0) {
+            return Err("Stable fraction must be above 0% and at most 100%".to_string());
+        }
+        if settings.circuit_breaker_percent <= 0.0 {
+            return Err("Circuit breaker percent must be positive".to_string());
         }
 
-        self.status_message = format!("No channel found matching: {}", self.selected_channel_id);
+        let by_counterparty = PublicKey::from_str(target).ok();
+        let channels = match by_counterparty {
+            Some(peer) => largest_ready_channel_with(&self.node.list_channels(), &peer).into_iter().collect(),
+            None => self.node.list_channels(),
+        };
+
+        let channel = channels
+            .into_iter()
+            .find(|c| by_counterparty.is_some() || c.channel_id.to_string() == target)
+            .ok_or_else(|| format!("No channel found matching: {}", target))?;
+
+        let currency = settings.currency;
+        let price = price_in(currency, self.btc_price);
+        if currency != Currency::USD && valid_price(price).is_none() {
+            return Err(format!("No {} price available yet", currency));
+        }
+
+        let keyed_by_counterparty = by_counterparty.is_some() || settings.key_by_counterparty;
+        let target_usd = stable::fiat_to_usd(expected_usd, currency, price, self.btc_price);
+        self.check_stable_cap(&channel, keyed_by_counterparty, target_usd)?;
+
+        let mut stable_channel =
+            self.new_stable_channel(&channel, USD::from_f64(expected_usd), currency, settings.stable_fraction);
+        stable_channel.max_payment_usd = settings.max_payment_usd;
+        stable_channel.max_payment_percent = settings.max_payment_percent;
+        stable_channel.approval_required_above_cap = settings.approval_required_above_cap;
+        stable_channel.max_risk_level = settings.max_risk_level;
+        stable_channel.circuit_breaker_percent = settings.circuit_breaker_percent;
+        stable_channel.twap_window_secs = settings.twap_window_secs;
+        stable_channel.min_seconds_between_payments = settings.min_seconds_between_payments;
+        stable_channel.max_daily_adjustment_usd = settings.max_daily_adjustment_usd;
+        stable_channel.keyed_by_counterparty = keyed_by_counterparty;
+        if let Some(fee_ppm) = settings.stability_fee_ppm {
+            stable_channel.stability_fee_ppm = fee_ppm;
+        }
+        self.upsert_stable_channel(stable_channel);
+
+        self.save_stable_channels();
+
+        Ok(format!("Channel {} designated as stable with target {}", target, currency.format(expected_usd)))
     }
 
-    pub fn show_lsp_screen(&mut self, ctx: &egui::Context) {
-        let mut channel_info = self.update_channel_info();
+    pub fn approve_stability_payment(&mut self, index: usize) {
+        if let Some(sc) = self.stable_channels.get_mut(index) {
+            sc.payment_approved = true;
+            self.check_stable_channel_now(index);
+        }
+    }
+
+    /// Runs a stability check on the stable channel at `index` outside the regular cadence
+    fn check_stable_channel_now(&mut self, index: usize) {
+        let Some(sc) = self.stable_channels.get_mut(index) else {
+            return;
+        };
+
+        let price = price_in(sc.currency, self.btc_price);
+        let action = stable::check_stability_and_log(&*self.node, sc, price);
+        if let Some(audit) = &mut self.audit {
+            audit.record_check(sc, price, &get_price_stats_in(sc.currency), &action);
+        }
+        self.status_log.push(Severity::of_action(&action), format!("Channel {}: {}", sc.channel_id, action));
+    }
+
+    /// Lifts the circuit breaker pause on the stable channel at `index`, or on every
+    /// paused channel if None
+    pub fn resume_stable_channels(&mut self, index: Option<usize>) {
+        let mut resumed = 0;
+        for (i, sc) in self.stable_channels.iter_mut().enumerate() {
+            if index.map_or(true, |index| index == i) && sc.suspended_until.is_some() {
+                stable::resume(sc);
+                info!(channel_id = %sc.channel_id, "Stability payments resumed by hand");
+                resumed += 1;
+            }
+        }
+        if resumed > 0 {
+            self.status_log.info(format!("Resumed stability payments on {} channel(s)", resumed));
+            self.save_stable_channels();
+        }
+    }
 
+    /// Finds where stable channels whose id vanished without a close went, as after a
+    /// splice. A clear replacement is taken over at once; otherwise the candidates are
+    /// offered in the panel for the operator to pick.
+    fn reattach_vanished_channels(&mut self) {
+        let channels = self.node.list_channels();
+        let designated: Vec<ChannelId> = self.stable_channels.iter().map(|sc| sc.channel_id).collect();
+        let mut found = Vec::new();
+        for sc in &mut self.stable_channels {
+            if !stable::has_vanished(sc, &channels) {
+                sc.reattach_candidates.clear();
+                continue;
+            }
+            match stable::find_replacement(sc, &channels, &designated) {
+                stable::Replacement::Found(new_channel_id) => found.push((sc.channel_id, new_channel_id)),
+                stable::Replacement::Ambiguous(candidates) => {
+                    if sc.reattach_candidates != candidates {
+                        self.status_log.warn(format!(
+                            "Stable channel {} disappeared; confirm which of {}'s channels replaced it",
+                            sc.channel_id, sc.counterparty
+                        ));
+                    }
+                    sc.reattach_candidates = candidates;
+                }
+                stable::Replacement::None => sc.reattach_candidates.clear(),
+            }
+        }
+        for (old_channel_id, new_channel_id) in found {
+            self.migrate_stable_channel(old_channel_id, new_channel_id);
+        }
+    }
+
+    /// Points the stable channel on `old_channel_id` at `new_channel_id`, keeping its peg
+    fn migrate_stable_channel(&mut self, old_channel_id: ChannelId, new_channel_id: ChannelId) {
+        let Some(sc) = self.stable_channels.iter_mut().find(|sc| sc.channel_id == old_channel_id) else {
+            return;
+        };
+        sc.channel_id = new_channel_id;
+        sc.reattach_candidates.clear();
+        sc.pending_payment_id = None;
+        sc.pending_payment_msats = 0;
+        stable::update_balances(&*self.node, sc);
+
+        self.stability_outcomes.remove(&old_channel_id);
+        warn!(%old_channel_id, %new_channel_id, "Stable channel id changed; re-attached");
+        self.status_log.warn(format!("Stable channel {} re-attached to {}", old_channel_id, new_channel_id));
+        self.alerts.channel_migrated(&old_channel_id, &new_channel_id);
+        self.save_stable_channels();
+    }
+
+    /// Moves a designation waiting on `peer` over to its newly ready channel
+    fn reattach_stable_channel(&mut self, peer: PublicKey, channel_id: ChannelId) {
+        let Some(sc) = self
+            .stable_channels
+            .iter_mut()
+            .find(|sc| sc.awaiting_reattach && sc.counterparty == peer)
+        else {
+            return;
+        };
+
+        let old_channel_id = sc.channel_id;
+        sc.channel_id = channel_id;
+        sc.awaiting_reattach = false;
+        sc.pending_payment_id = None;
+        sc.pending_payment_msats = 0;
+        stable::update_balances(&*self.node, sc);
+
+        self.stability_outcomes.remove(&old_channel_id);
+        self.status_log.info(format!(
+            "Re-attached stable channel for {} from {} to {}",
+            peer, old_channel_id, channel_id
+        ));
+        self.save_stable_channels();
+    }
+
+    /// Stops treating a channel as stable. With `settle_first`, one last adjustment is
+    /// sent; the entry is only dropped once no stability payment is in flight.
+    pub fn remove_stable_channel(&mut self, index: usize, settle_first: bool) {
+        let Some(sc) = self.stable_channels.get_mut(index) else {
+            return;
+        };
+        let channel_id = sc.channel_id;
+
+        if settle_first && sc.pending_payment_id.is_none() {
+            sc.payment_approved = true;
+            let price = price_in(sc.currency, self.btc_price);
+            let action = stable::check_stability_and_log(&*self.node, sc, price);
+            if let Some(audit) = &mut self.audit {
+                audit.record_check(sc, price, &get_price_stats_in(sc.currency), &action);
+            }
+            self.status_log.push(Severity::of_action(&action), format!("Channel {}: final adjustment: {}", channel_id, action));
+        }
+
+        if sc.pending_payment_id.is_some() {
+            self.pending_removals.insert(channel_id);
+            self.status_log.info(format!(
+                "Channel {} will be removed once its stability payment resolves",
+                channel_id
+            ));
+            return;
+        }
+
+        self.stable_channels.remove(index);
+        self.stability_outcomes.remove(&channel_id);
+        self.save_stable_channels();
+        self.status_log.info(format!("Channel {} is no longer a stable channel", channel_id));
+    }
+
+    /// Removes channels queued for removal whose stability payment has since resolved
+    fn finish_pending_removals(&mut self) {
+        let ready: Vec<ChannelId> = self
+            .stable_channels
+            .iter()
+            .filter(|sc| self.pending_removals.contains(&sc.channel_id) && sc.pending_payment_id.is_none())
+            .map(|sc| sc.channel_id)
+            .collect();
+        if ready.is_empty() {
+            return;
+        }
+
+        self.stable_channels.retain(|sc| !ready.contains(&sc.channel_id));
+        for channel_id in &ready {
+            self.pending_removals.remove(channel_id);
+            self.stability_outcomes.remove(channel_id);
+        }
+        self.save_stable_channels();
+        self.status_log.info(format!("Removed {} stable channel(s)", ready.len()));
+    }
+
+    pub fn show_lsp_screen(&mut self, ctx: &egui::Context) {
         egui::CentralPanel::default().show(ctx, |ui| {
             egui::ScrollArea::vertical().show(ui, |ui| {
-                ui.heading("Lightning Service Provider");
+                ui.horizontal(|ui| {
+                    ui.heading(if self.is_lsp { "Lightning Service Provider" } else { "Exchange" });
+                    if ui.button("⚙ Settings").clicked() {
+                        self.settings_screen = Some(SettingsScreen::new());
+                    }
+                });
+                self.sync_monitor.show_error_banner(ui);
+                dev_mode::show_banner(ui);
                 ui.add_space(10.0);
 
-                self.show_node_info_section(ui, LSP_PORT);
+                self.show_node_info_section(ui, self.port);
                 ui.add_space(10.0);
                 self.show_balance_section(ui);
                 ui.add_space(10.0);
+                self.price_override.show(ui);
+                self.simulator.show(ui, &self.node, &self.stable_channels, self.btc_price);
+                self.audit_panel.show(ui, self.audit.as_ref(), &self.data_dir);
+                self.routing.show(ui, self.btc_price);
+                if !self.is_lsp {
+                    if let Some(status) = self.accounts.show(ui, &mut self.worker, self.btc_price) {
+                        self.status_log.info(status);
+                    }
+                }
+
+                egui::CollapsingHeader::new(if self.is_lsp { "LSP Settings" } else { "Channel Settings" }).show(ui, |ui| {
+                    if self.is_lsp {
+                        self.lsps2_form.show(ui, &self.data_dir, &self.lsps2_settings);
+                        ui.separator();
+                        ui.label("Access tokens");
+                        self.lsps2_token_form.show(ui, &mut self.lsps2_tokens, self.lsps2_service_token.as_ref());
+                        ui.separator();
+                    }
+                    ui.label("Inbound channel policy");
+                    self.channel_policy_form.show(ui, &self.data_dir, &mut self.channel_policy);
+                });
+                ui.add_space(10.0);
 
                 ui.group(|ui| {
                     ui.heading("Open Channel");
                     ui.horizontal(|ui| {
                         ui.label("Node ID:");
                         ui.text_edit_singleline(&mut self.open_channel_node_id);
+                        self.node_ui.address_book.picker(
+                            ui,
+                            "open_channel_address_book",
+                            &mut self.open_channel_node_id,
+                            Some(&mut self.open_channel_address),
+                        );
                     });
                     ui.horizontal(|ui| {
                         ui.label("Net Address:");
@@ -649,11 +1943,34 @@ impl ServerApp {
                         ui.label("Amount (sats):");
                         ui.text_edit_singleline(&mut self.open_channel_amount);
                     });
-                    if ui.button("Open Channel").clicked() {
-                        if self.open_channel() {
-                            self.open_channel_node_id.clear();
-                            self.open_channel_amount = "100000".to_string();
-                        }
+                    ui.horizontal(|ui| {
+                        ui.label("Push to peer (sats):");
+                        ui.text_edit_singleline(&mut self.open_channel_push);
+                    });
+                    if node_ui::parse_sats(&self.open_channel_push).is_ok_and(|sats| sats > PUSH_CONFIRM_THRESHOLD_SATS) {
+                        ui.horizontal(|ui| {
+                            ui.colored_label(egui::Color32::YELLOW, "Pushed sats are a gift. Type the amount again:");
+                            ui.text_edit_singleline(&mut self.open_channel_push_confirm);
+                        });
+                    }
+                    ui.checkbox(&mut self.open_channel_private, "Private channel (not announced to the network)");
+                    ui.collapsing("Advanced: routing policy", |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Base fee (msat):");
+                            ui.text_edit_singleline(&mut self.open_channel_fee_base_msat);
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Fee rate (ppm):");
+                            ui.text_edit_singleline(&mut self.open_channel_fee_ppm);
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("CLTV expiry delta:");
+                            ui.text_edit_singleline(&mut self.open_channel_cltv_delta);
+                        });
+                        ui.label("Leave blank to use the node's defaults");
+                    });
+                    if worker::command_button(ui, &self.worker, CommandKind::OpenChannel, "Open Channel") {
+                        self.open_channel();
                     }
                 });
 
@@ -661,48 +1978,342 @@ impl ServerApp {
 
                 ui.group(|ui| {
                     ui.heading("Stable Channels");
+                    let exposure = stable::stable_exposure(&self.stable_channels, self.btc_price);
+                    ui.label(format!(
+                        "Exposure: ${:.2} of ${:.2} cap designated | Receivers hold ${:.2} | {:.8} BTC owed if price halves",
+                        exposure.target_usd, self.max_total_stable_usd, exposure.receiver_usd, exposure.worst_case_btc
+                    ));
+                    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64;
+                    let mut resume_all = false;
+                    let low_runway_percent = self.alerts.low_runway_percent();
+                    let paused = self.stable_channels.iter().filter(|sc| sc.suspended_until.is_some()).count();
+                    if paused > 0 {
+                        ui.horizontal(|ui| {
+                            ui.colored_label(
+                                egui::Color32::RED,
+                                format!("{} channel(s) paused by the circuit breaker", paused),
+                            );
+                            if ui.button("Resume all").clicked() {
+                                resume_all = true;
+                            }
+                        });
+                    }
+                    ui.add_space(5.0);
+                    let mut approve_index = None;
+                    let mut remove_index = None;
+                    let mut confirm_reattach = None;
+                    let mut resume_index = None;
                     if self.stable_channels.is_empty() {
                         ui.label("No stable channels configured");
                     } else {
                         for (i, sc) in self.stable_channels.iter().enumerate() {
                             ui.horizontal(|ui| {
                                 ui.label(format!("{}. Channel: {}", i + 1, sc.channel_id));
-                                ui.label(format!("Target: ${:.2}", sc.expected_usd.0));
+                                ui.label(format!("Target: {}", sc.currency.format(sc.expected_usd.to_f64())));
+                                if let Some(unpegged_at) = sc.unpegged_at {
+                                    ui.colored_label(
+                                        egui::Color32::GRAY,
+                                        format!("(unpegged on {})", history::format_date(unpegged_at)),
+                                    );
+                                }
+                            });
+                            if sc.stable_fraction < stable::DEFAULT_STABLE_FRACTION {
+                                ui.horizontal(|ui| {
+                                    ui.label(format!(
+                                        "    Stable portion: {:.0}% | Floating: {}",
+                                        sc.stable_fraction,
+                                        display::btc(sc.float_btc)
+                                    ));
+                                });
+                            }
+                            if sc.keyed_by_counterparty {
+                                ui.horizontal(|ui| {
+                                    let counterparty = match self.node_ui.address_book.label_for(&sc.counterparty) {
+                                        Some(label) => format!("{} ({})", label, sc.counterparty),
+                                        None => sc.counterparty.to_string(),
+                                    };
+                                    ui.label(format!("    Follows counterparty: {}", counterparty));
+                                    if sc.awaiting_reattach {
+                                        ui.colored_label(egui::Color32::YELLOW, "(pending re-attach)");
+                                    }
+                                });
+                            }
+                            if !sc.reattach_candidates.is_empty() {
+                                ui.horizontal_wrapped(|ui| {
+                                    ui.colored_label(egui::Color32::YELLOW, "    Channel disappeared without closing. Re-attach to:");
+                                    for candidate in &sc.reattach_candidates {
+                                        if ui.button(candidate.to_string()).clicked() {
+                                            confirm_reattach = Some((sc.channel_id, *candidate));
+                                        }
+                                    }
+                                });
+                            }
+                            ui.horizontal(|ui| {
+                                let percent_from_par = stable::percent_from_par(sc);
+                                let par_color = if percent_from_par < stable::threshold_percent() {
+                                    egui::Color32::GREEN
+                                } else if percent_from_par < 1.0 {
+                                    egui::Color32::YELLOW
+                                } else {
+                                    egui::Color32::RED
+                                };
+                                let direction = if percent_from_par < stable::threshold_percent() {
+                                    "no payment due"
+                                } else if sc.stable_receiver_usd < stable::par_usd(sc) {
+                                    "LSP pays user"
+                                } else {
+                                    "user pays LSP"
+                                };
+                                ui.label("    From par:");
+                                ui.colored_label(
+                                    par_color,
+                                    format!(
+                                        "● {:.2}% ({}), {}",
+                                        percent_from_par,
+                                        sc.currency.format(stable::dollars_from_par(sc).to_f64()),
+                                        direction
+                                    ),
+                                );
+                            });
+                            ui.horizontal(|ui| match self.stability_outcomes.get(&sc.channel_id) {
+                                Some(outcome) => {
+                                    let paid = if matches!(outcome.action, stable::StabilityAction::Paid { .. }) {
+                                        "payment sent"
+                                    } else {
+                                        "no payment"
+                                    };
+                                    ui.label(format!(
+                                        "    Last check: {}s ago, {} ({})",
+                                        outcome.checked_at.elapsed().as_secs(),
+                                        paid,
+                                        outcome.action
+                                    ));
+                                }
+                                None => {
+                                    ui.label("    Last check: not yet run");
+                                }
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label(format!(
+                                    "    Spot: {} | {}s TWAP: {}",
+                                    sc.currency.format_price(sc.latest_price),
+                                    sc.twap_window_secs,
+                                    sc.currency.format_price(stable::stability_price(sc))
+                                ));
                             });
                             ui.horizontal(|ui| {
                                 ui.label("    User balance:");
-                                ui.label(format!("{:.8} BTC (${:.2})", sc.stable_receiver_btc.to_btc(), sc.stable_receiver_usd.0));
+                                ui.label(format!(
+                                    "{} ({})",
+                                    display::btc(sc.stable_receiver_btc),
+                                    sc.currency.format(sc.stable_receiver_usd.to_f64())
+                                ));
                             });
                             ui.horizontal(|ui| {
                                 ui.label("    LSP balance:");
-                                ui.label(format!("{:.8} BTC (${:.2})", sc.stable_provider_btc.to_btc(), sc.stable_provider_usd.0));
+                                ui.label(format!(
+                                    "{} ({})",
+                                    display::btc(sc.stable_provider_btc),
+                                    sc.currency.format(sc.stable_provider_usd.to_f64())
+                                ));
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label(format!(
+                                    "    Paid to user: {} | Paid to LSP: {} | Payments: {}",
+                                    display::sats(sc.total_paid_to_user_msats / 1000),
+                                    display::sats(sc.total_paid_to_lsp_msats / 1000),
+                                    sc.payment_count
+                                ));
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label(format!(
+                                    "    Stability fee: {} ppm | Fees earned: {}",
+                                    sc.stability_fee_ppm,
+                                    display::sats(sc.accrued_fee_msats / 1000)
+                                ));
+                            });
+                            ui.horizontal(|ui| {
+                                let pnl_btc = stable::provider_pnl_btc(sc);
+                                ui.label(format!(
+                                    "    Provider BTC: {} at designation, {} now |",
+                                    display::btc(sc.native_btc_original),
+                                    display::btc(sc.native_btc)
+                                ));
+                                let color = if pnl_btc < 0.0 { egui::Color32::RED } else { egui::Color32::GREEN };
+                                ui.colored_label(color, format!("P&L: {:+.8} BTC", pnl_btc))
+                                    .on_hover_text("BTC gained or given up providing stability, fees included");
+                            });
+                            ui.horizontal(|ui| {
+                                ui.label(format!(
+                                    "    Sent today: {} of {}",
+                                    sc.currency.format(stable::daily_adjustment_usd(sc)),
+                                    sc.currency.format(sc.max_daily_adjustment_usd)
+                                ));
+                            });
+                            if let Some(runway) = &sc.runway {
+                                ui.horizontal(|ui| {
+                                    let color = if runway.min_percent() < low_runway_percent {
+                                        egui::Color32::RED
+                                    } else {
+                                        ui.visuals().text_color()
+                                    };
+                                    ui.colored_label(color, format!("    Runway: {}", stable::format_runway(runway)))
+                                        .on_hover_text("How far the price can fall or rise before the paying side runs out");
+                                });
+                            }
+                            if !sc.missed_settlements.is_empty() {
+                                ui.horizontal(|ui| {
+                                    ui.colored_label(egui::Color32::YELLOW, format!(
+                                        "    Pending settlement: {}",
+                                        sc.currency.format(stable::missed_settlement_usd(sc))
+                                    ));
+                                });
+                            }
+                            ui.horizontal(|ui| {
+                                let risk_color = match stable::risk_band(sc) {
+                                    stable::RiskBand::Low => egui::Color32::GREEN,
+                                    stable::RiskBand::Elevated => egui::Color32::YELLOW,
+                                    stable::RiskBand::Suspended => egui::Color32::RED,
+                                };
+                                ui.label("    Risk:");
+                                ui.colored_label(risk_color, format!("● {} / {}", sc.risk_level, sc.max_risk_level));
+                            });
+                            if let Some(until) = sc.suspended_until {
+                                ui.horizontal(|ui| {
+                                    ui.colored_label(
+                                        egui::Color32::RED,
+                                        format!(
+                                            "    Paused for {}m more: {}",
+                                            (until - now).max(0) / 60 + 1,
+                                            sc.suspension_reason.as_deref().unwrap_or("circuit breaker")
+                                        ),
+                                    );
+                                    if ui.button("Resume").clicked() {
+                                        resume_index = Some(i);
+                                    }
+                                });
+                            }
+                            if let Some(amount_msats) = sc.pending_approval_msats {
+                                ui.horizontal(|ui| {
+                                    ui.colored_label(
+                                        egui::Color32::YELLOW,
+                                        format!("    Needs approval: {} msats", amount_msats),
+                                    );
+                                    if ui.button("Approve").clicked() {
+                                        approve_index = Some(i);
+                                    }
+                                });
+                            }
+                            ui.horizontal(|ui| {
+                                if self.pending_removals.contains(&sc.channel_id) {
+                                    ui.label("    Removing after in-flight payment resolves...");
+                                } else if ui.button("Remove").clicked() {
+                                    remove_index = Some(i);
+                                }
                             });
                             ui.add_space(5.0);
                         }
                     }
 
+                    if let Some(i) = approve_index {
+                        self.approve_stability_payment(i);
+                    }
+                    if let Some((old_channel_id, new_channel_id)) = confirm_reattach {
+                        self.migrate_stable_channel(old_channel_id, new_channel_id);
+                    }
+                    if resume_all {
+                        self.resume_stable_channels(None);
+                    } else if let Some(i) = resume_index {
+                        self.resume_stable_channels(Some(i));
+                    }
+                    if let Some(i) = remove_index {
+                        self.remove_stable_channel(i, self.settle_before_removal);
+                    }
+                    if !self.stable_channels.is_empty() {
+                        ui.checkbox(&mut self.settle_before_removal, "Settle before removing");
+                    }
+
                     ui.label("Designate Stable Channel:");
                     ui.horizontal(|ui| {
-                        ui.label("Channel ID:");
+                        ui.label("Channel ID or counterparty pubkey:");
                         ui.text_edit_singleline(&mut self.selected_channel_id);
+                        self.node_ui.address_book.picker(ui, "designate_address_book", &mut self.selected_channel_id, None);
                     });
                     ui.horizontal(|ui| {
-                        ui.label("Target USD amount:");
+                        ui.label("Target amount:");
                         ui.text_edit_singleline(&mut self.stable_channel_amount);
+                        egui::ComboBox::from_id_salt("stable_channel_currency")
+                            .selected_text(self.stable_channel_currency.code())
+                            .show_ui(ui, |ui| {
+                                for currency in Currency::ALL {
+                                    ui.selectable_value(&mut self.stable_channel_currency, currency, currency.code());
+                                }
+                            });
                     });
+                    ui.horizontal(|ui| {
+                        ui.label("Stable % of user balance:");
+                        ui.text_edit_singleline(&mut self.stable_channel_fraction);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Max payment USD:");
+                        ui.text_edit_singleline(&mut self.stable_channel_max_payment_usd);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Max payment % of target:");
+                        ui.text_edit_singleline(&mut self.stable_channel_max_payment_percent);
+                    });
+                    ui.checkbox(&mut self.stable_channel_require_approval, "Require approval above cap");
+                    ui.horizontal(|ui| {
+                        ui.label("Suspend above risk level:");
+                        ui.text_edit_singleline(&mut self.stable_channel_max_risk_level);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Pause on price move above %:");
+                        ui.text_edit_singleline(&mut self.stable_channel_circuit_breaker_percent);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("TWAP window (seconds):");
+                        ui.text_edit_singleline(&mut self.stable_channel_twap_window_secs);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Min seconds between payments:");
+                        ui.text_edit_singleline(&mut self.stable_channel_min_payment_interval);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Max daily adjustment USD:");
+                        ui.text_edit_singleline(&mut self.stable_channel_max_daily_usd);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Stability fee (ppm):");
+                        ui.text_edit_singleline(&mut self.stable_channel_fee_ppm);
+                    });
+                    ui.checkbox(&mut self.stable_channel_key_by_counterparty, "Follow counterparty across channel replacement");
                     if ui.button("Designate as Stable").clicked() {
                         self.designate_stable_channel();
                     }
                 });
 
                 ui.add_space(10.0);
-                self.show_invoice_section(ui);
+                if let Some(status) = self.node_ui.show_invoice_section(ui, &mut self.worker, self.channel_snapshot.channels()) {
+                    self.status_log.info(status);
+                }
                 ui.add_space(10.0);
-                self.show_pay_invoice_section(ui);
+                if let Some(status) = self.node_ui.show_pay_invoice_section(ui, &mut self.worker, self.channel_snapshot.channels()) {
+                    self.status_log.info(status);
+                }
+                self.pending_payments.show(ui);
                 ui.add_space(10.0);
-                self.show_onchain_address_section(ui);
+                if let Some(status) = self.node_ui.show_offer_section(ui, &mut self.worker) {
+                    self.status_log.info(status);
+                }
                 ui.add_space(10.0);
-                self.show_onchain_send_section(ui);
+                if let Some(status) = self.node_ui.show_onchain_address_section(ui, &mut self.worker) {
+                    self.status_log.info(status);
+                }
+                ui.add_space(10.0);
+                if let Some(status) = self.node_ui.show_onchain_send_section(ui, &mut self.worker) {
+                    self.status_log.info(status);
+                }
                 ui.add_space(10.0);
 
                 ui.group(|ui| {
@@ -717,123 +2328,210 @@ impl ServerApp {
                 });
 
                 ui.add_space(10.0);
-                self.show_channels_section(ui, &mut channel_info);
+                self.show_channels_section(ui);
+                if let Some(status) = self.node_ui.show_peers_section(ui, &self.node, &mut self.worker) {
+                    self.status_log.info(status);
+                }
+                self.node_ui.show_address_book_section(ui);
+                self.node_ui.show_sign_section(ui, &self.node);
                 ui.add_space(10.0);
 
-                if !self.status_message.is_empty() {
-                    ui.label(self.status_message.clone());
-                }
+                self.status_log.show_latest(ui);
+                self.status_log.show(ui);
+
+                ui.add_space(10.0);
+                self.onchain_activity.show(ui, &self.node);
+                self.payment_history.show(ui, &self.node, &CostBasis::from_channels(&self.stable_channels));
+                logging::show_logs_panel(ui);
             });
         });
     }
 
     pub fn save_stable_channels(&mut self) {
-        let entries: Vec<StableChannelEntry> = self.stable_channels.iter().map(|sc| StableChannelEntry {
-            channel_id: sc.channel_id.to_string(),
-            expected_usd: sc.expected_usd.0,
-            native_btc: sc.expected_btc.to_btc(),
-        }).collect();
+        if self.stable_channels_file_locked {
+            warn!("Not saving stable channels: existing file could not be read by this version");
+            return;
+        }
+
+        let mut entries: Vec<StableChannelEntry> = self.stable_channels.iter().map(StableChannelEntry::new).collect();
+        entries.extend(self.unresolved_stable_channels.iter().cloned());
+        let file = StableChannelsFile { version: STABLE_CHANNELS_FILE_VERSION, channels: entries };
 
-        let file_path = Path::new(LSP_DATA_DIR).join("stablechannels.json");
+        let file_path = self.data_dir.join("stablechannels.json");
 
         if let Some(parent) = file_path.parent() {
             fs::create_dir_all(parent).unwrap_or_else(|e| {
-                eprintln!("Failed to create directory: {}", e);
+                error!("Failed to create directory: {}", e);
             });
         }
 
-        match serde_json::to_string_pretty(&entries) {
+        match serde_json::to_string_pretty(&file) {
             Ok(json) => {
-                match fs::write(&file_path, json) {
-                    Ok(_) => {
-                        println!("Saved stable channels to {}", file_path.display());
-                        self.status_message = "Stable channels saved successfully".to_string();
-                    }
+                match encryption::write_state_file_if_changed(&file_path, json, &mut self.saved_stable_channels) {
+                    Ok(true) => debug!("Saved stable channels to {}", file_path.display()),
+                    Ok(false) => {}
                     Err(e) => {
-                        eprintln!("Error writing stable channels file: {}", e);
-                        self.status_message = format!("Failed to save stable channels: {}", e);
+                        error!("Error writing stable channels file: {}", e);
+                        self.status_log.error(format!("Failed to save stable channels: {}", e));
                     }
                 }
             }
             Err(e) => {
-                eprintln!("Error serializing stable channels: {}", e);
-                self.status_message = format!("Failed to serialize stable channels: {}", e);
+                error!("Error serializing stable channels: {}", e);
+                self.status_log.error(format!("Failed to serialize stable channels: {}", e));
             }
         }
     }
 
     pub fn load_stable_channels(&mut self) {
-        let file_path = Path::new(LSP_DATA_DIR).join("stablechannels.json");
+        let file_path = self.data_dir.join("stablechannels.json");
 
         if !file_path.exists() {
-            println!("No existing stable channels file found.");
+            info!("No existing stable channels file found.");
             return;
         }
 
-        match fs::read_to_string(&file_path) {
-            Ok(contents) => {
-                match serde_json::from_str::<Vec<StableChannelEntry>>(&contents) {
-                    Ok(entries) => {
-                        self.stable_channels.clear();
-
-                        for entry in entries {
-                            for channel in self.node.list_channels() {
-                                if channel.channel_id.to_string() == entry.channel_id {
-                                    let unspendable = channel.unspendable_punishment_reserve.unwrap_or(0);
-                                    let our_balance_sats = (channel.outbound_capacity_msat / 1000) + unspendable;
-                                    let their_balance_sats = channel.channel_value_sats - our_balance_sats;
-
-                                    let stable_provider_btc = Bitcoin::from_sats(our_balance_sats);
-                                    let stable_receiver_btc = Bitcoin::from_sats(their_balance_sats);
-                                    let stable_provider_usd = USD::from_bitcoin(stable_provider_btc, self.btc_price);
-                                    let stable_receiver_usd = USD::from_bitcoin(stable_receiver_btc, self.btc_price);
-
-                                    let stable_channel = StableChannel {
-                                        channel_id: channel.channel_id,
-                                        counterparty: channel.counterparty_node_id,
-                                        is_stable_receiver: false,
-                                        expected_usd: USD::from_f64(entry.expected_usd),
-                                        expected_btc: Bitcoin::from_btc(entry.native_btc),
-                                        stable_receiver_btc,
-                                        stable_receiver_usd,
-                                        stable_provider_btc,
-                                        stable_provider_usd,
-                                        latest_price: self.btc_price,
-                                        risk_level: 0,
-                                        payment_made: false,
-                                        timestamp: 0,
-                                        formatted_datetime: "".to_string(),
-                                        sc_dir: LSP_DATA_DIR.to_string(),
-                                        prices: "".to_string(),
-                                    };
+        let contents = match encryption::read_state_file(&file_path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                // Don't overwrite a file we couldn't decrypt
+                self.stable_channels_file_locked = true;
+                error!("Error reading stable channels file: {}", e);
+                self.status_log.error(format!("Failed to read stable channels file: {}", e));
+                return;
+            }
+        };
 
-                                    self.stable_channels.push(stable_channel);
-                                    break;
-                                }
-                            }
-                        }
+        let (entries, migrations) = match parse_stable_channels_file(&contents) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                // Leave the file untouched so a newer or hand-edited file isn't clobbered
+                self.stable_channels_file_locked = true;
+                error!("Error parsing stable channels file: {}", e);
+                self.status_log.error(format!("Failed to parse stable channels: {}", e));
+                return;
+            }
+        };
 
-                        println!("Loaded {} stable channels", self.stable_channels.len());
-                        self.status_message = format!("Loaded {} stable channels", self.stable_channels.len());
-                    }
-                    Err(e) => {
-                        eprintln!("Error parsing stable channels file: {}", e);
-                        self.status_message = format!("Failed to parse stable channels: {}", e);
-                    }
+        self.stable_channels.clear();
+        self.unresolved_stable_channels.clear();
+        let channels = self.node.list_channels();
+
+        for entry in entries {
+            let counterparty = entry.counterparty();
+
+            let channel = match counterparty {
+                Some(peer) if entry.keyed_by_counterparty => largest_ready_channel_with(&channels, &peer),
+                _ => channels.iter().find(|c| c.channel_id.to_string() == entry.channel_id).cloned(),
+            };
+
+            match (channel, counterparty) {
+                (Some(channel), _) => {
+                    let stable_channel = self.stable_channel_from_entry(&entry, Some(&channel), channel.counterparty_node_id);
+                    self.stable_channels.push(stable_channel);
+                }
+                (None, Some(peer)) if entry.keyed_by_counterparty => {
+                    // Keep the designation until the peer has a ready channel again
+                    let mut stable_channel = self.stable_channel_from_entry(&entry, None, peer);
+                    stable_channel.awaiting_reattach = true;
+                    info!("Stable channel for {} is waiting for a ready channel to re-attach", peer);
+                    self.stable_channels.push(stable_channel);
+                }
+                _ => {
+                    warn!("Keeping stable channel {} aside: channel not found", entry.channel_id);
+                    self.unresolved_stable_channels.push(entry);
                 }
             }
-            Err(e) => {
-                eprintln!("Error reading stable channels file: {}", e);
-                self.status_message = format!("Failed to read stable channels file: {}", e);
+        }
+
+        info!("Loaded {} stable channels", self.stable_channels.len());
+        self.status_log.info(format!("Loaded {} stable channels", self.stable_channels.len()));
+        if !self.unresolved_stable_channels.is_empty() {
+            self.status_log.warn(format!(
+                "{} stable channels not found on the node; kept in the file",
+                self.unresolved_stable_channels.len()
+            ));
+        }
+
+        if !migrations.is_empty() {
+            let backup_path = file_path.with_extension("json.bak");
+            if let Err(e) = fs::copy(&file_path, &backup_path) {
+                warn!("Failed to back up stable channels file, not migrating: {}", e);
+                self.stable_channels_file_locked = true;
+                return;
             }
+            info!("Backed up stable channels file to {}", backup_path.display());
+            for migration in &migrations {
+                info!("Migrated stable channels file: {}", migration);
+            }
+            self.save_stable_channels();
         }
     }
+
+    /// Rebuilds a persisted stable channel on top of `channel`'s current balances. Without a
+    /// channel the entry keeps its stored channel id and zero balances until it re-attaches.
+    fn stable_channel_from_entry(
+        &self,
+        entry: &StableChannelEntry,
+        channel: Option<&ChannelDetails>,
+        counterparty: PublicKey,
+    ) -> StableChannel {
+        let (channel_id, our_balance_sats, their_balance_sats) = match channel {
+            Some(channel) => {
+                let (our_balance_sats, their_balance_sats) = stable::channel_balances(channel);
+                (channel.channel_id, our_balance_sats, their_balance_sats)
+            }
+            None => (parse_channel_id(&entry.channel_id).unwrap_or(ChannelId::from_bytes([0; 32])), 0, 0),
+        };
+
+        let price = price_in(entry.currency, self.btc_price);
+        let stable_provider_btc = Bitcoin::from_sats(our_balance_sats);
+        let (native_btc_original, native_btc) = match entry.native_btc_original {
+            Some(original) => (Bitcoin::from_btc(original), Bitcoin::from_btc(entry.native_btc)),
+            None => (stable_provider_btc, stable_provider_btc),
+        };
+        let stable_receiver_btc = Bitcoin::from_sats(their_balance_sats);
+        let stable_provider_usd = USD::from_bitcoin(stable_provider_btc, price);
+        let stable_receiver_usd = USD::from_bitcoin(stable_receiver_btc, price);
+
+        let mut sc = StableChannel {
+            channel_id,
+            counterparty,
+            stable_receiver_btc,
+            stable_receiver_usd,
+            stable_provider_btc,
+            stable_provider_usd,
+            latest_price: price,
+            timestamp: 0,
+            sc_dir: self.data_dir.display().to_string(),
+            native_btc_original,
+            native_btc,
+            ..Default::default()
+        };
+        entry.restore_into(&mut sc);
+        sc.is_stable_receiver = false;
+        sc.peg_agreed = true;
+        sc.expected_btc = Bitcoin::from_usd(sc.expected_usd, price).unwrap_or_default();
+        sc
+    }
 }
 
 #[cfg(any(feature = "lsp", feature = "exchange"))]
 impl App for ServerApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut Frame) {
+        if self.shutdown.load(Ordering::SeqCst) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        }
+
         self.poll_events();
+        self.process_worker_results();
+        self.process_admin_requests();
+        self.channel_snapshot.refresh_if_needed(&self.node);
+
+        // Streamed ticks keep the cache fresh, so reading it doesn't fetch
+        if price_feeds::is_streaming(Currency::USD) {
+            self.btc_price = get_cached_price();
+        }
 
         if self.last_update.elapsed() > Duration::from_secs(30) {
             let current_price = get_cached_price();
@@ -841,22 +2539,70 @@ impl App for ServerApp {
                 self.btc_price = current_price;
             }
             self.update_balances();
+            self.reconnect_exchange_if_needed();
+            self.backups.run_if_due();
             self.last_update = Instant::now();
         }
 
-        if self.last_stability_check.elapsed() > Duration::from_secs(30) {
+        let check_interval = Duration::from_secs(settings::get().stability.check_interval_secs);
+        if self.last_stability_check.elapsed() > check_interval {
             self.check_and_update_stable_channels();
             self.last_stability_check = Instant::now();
         }
-
-        self.show_lsp_screen(ctx);
+        self.pending_payments.track_stability(&self.stable_channels);
+        self.pending_payments.requery_stale(&self.node);
+
+        display::apply_theme(ctx);
+        match self.settings_screen.as_mut() {
+            Some(screen) => {
+                let node_info = [
+                    ("Node ID", self.node.node_id().to_string()),
+                    ("Listening on", format!("127.0.0.1:{}", self.port)),
+                    ("Data directory", self.data_dir.display().to_string()),
+                    ("Chain source", self.chain_source.to_string()),
+                ];
+                let mut closed = false;
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    egui::ScrollArea::vertical().show(ui, |ui| closed = screen.show(ui, &node_info));
+                });
+                if closed {
+                    self.settings_screen = None;
+                }
+            }
+            None => self.show_lsp_screen(ctx),
+        }
         ctx.request_repaint_after(Duration::from_millis(100));
     }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        info!("Shutting down node...");
+        self.shutdown.store(true, Ordering::SeqCst);
+
+        if !self.stable_channels.is_empty() {
+            self.save_stable_channels();
+        }
+
+        if let Some(admin) = self.admin.as_mut() {
+            admin.stop();
+        }
+        self.worker.stop();
+        self.sync_monitor.stop();
+        self.price_stream.stop();
+        self.webhooks.stop();
+        self.alerts.stop();
+        if let Err(e) = self.node.stop() {
+            error!("Failed to stop node: {}", e);
+        }
+
+        // Release only once the node has stopped writing to the directory
+        self.data_dir_lock.take();
+    }
 }
 
 #[cfg(any(feature = "lsp", feature = "exchange"))]
 pub fn run_with_mode(mode: &str) {
     let app = ServerApp::new_with_mode(mode);
+    shutdown::install_sigint_handler(Arc::clone(&app.shutdown));
 
     let native_options = eframe::NativeOptions {
         viewport: eframe::egui::ViewportBuilder::default().with_inner_size([500.0, 800.0]),
@@ -869,6 +2615,72 @@ pub fn run_with_mode(mode: &str) {
         Box::new(move |_cc| Ok(Box::new(app))),
     )
     .unwrap_or_else(|e| {
-        eprintln!("Error starting app in {} mode: {:?}", mode, e);
+        error!("Error starting app in {} mode: {:?}", mode, e);
     });
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHANNEL_ID: &str = "0101010101010101010101010101010101010101010101010101010101010101";
+
+    #[test]
+    fn bare_array_is_migrated_from_v0() {
+        let contents = format!(r#"[{{"channel_id": "{}", "expected_usd": 100.0, "native_btc": 0.002}}]"#, CHANNEL_ID);
+
+        let (entries, migrations) = parse_stable_channels_file(&contents).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].channel_id, CHANNEL_ID);
+        assert_eq!(entries[0].expected_usd, 100.0);
+        assert_eq!(entries[0].native_btc_original, None);
+        assert_eq!(entries[0].max_payment_usd, stable::DEFAULT_MAX_PAYMENT_USD);
+        assert_eq!(entries[0].currency, Currency::USD);
+        assert_eq!(migrations.len(), 2);
+        assert!(migrations[0].starts_with("v0 -> v1"));
+        assert!(migrations[1].starts_with("v1 -> v2"));
+    }
+
+    #[test]
+    fn v1_file_drops_the_target_btc_equivalent() {
+        let contents = format!(
+            r#"{{"version": 1, "channels": [{{"channel_id": "{}", "expected_usd": 100.0, "native_btc": 0.002,
+                "native_btc_original": 0.002, "counterparty": "02aa", "keyed_by_counterparty": true}}]}}"#,
+            CHANNEL_ID
+        );
+
+        let (entries, migrations) = parse_stable_channels_file(&contents).unwrap();
+        assert_eq!(entries[0].native_btc_original, None);
+        assert_eq!(entries[0].counterparty.as_deref(), Some("02aa"));
+        assert!(entries[0].keyed_by_counterparty);
+        assert_eq!(migrations.len(), 1);
+        assert!(migrations[0].starts_with("v1 -> v2"));
+    }
+
+    #[test]
+    fn current_file_needs_no_migration() {
+        let contents = format!(
+            r#"{{"version": {}, "channels": [{{"channel_id": "{}", "expected_usd": 100.0, "native_btc": 0.001,
+                "native_btc_original": 0.002, "some_future_field": 1}}]}}"#,
+            STABLE_CHANNELS_FILE_VERSION, CHANNEL_ID
+        );
+
+        let (entries, migrations) = parse_stable_channels_file(&contents).unwrap();
+        assert_eq!(entries[0].native_btc, 0.001);
+        assert_eq!(entries[0].native_btc_original, Some(0.002));
+        assert!(migrations.is_empty());
+    }
+
+    #[test]
+    fn newer_file_version_is_rejected() {
+        let contents = format!(r#"{{"version": {}, "channels": []}}"#, STABLE_CHANNELS_FILE_VERSION + 1);
+
+        let error = parse_stable_channels_file(&contents).unwrap_err();
+        assert!(error.contains("newer than the supported version"), "{}", error);
+    }
+
+    #[test]
+    fn malformed_file_is_rejected() {
+        assert!(parse_stable_channels_file("{\"version\": 2").is_err());
+        assert!(parse_stable_channels_file(r#"{"channels": []}"#).is_err());
+    }
+}
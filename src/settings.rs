@@ -0,0 +1,290 @@
+use crate::display::{DisplaySettings, Theme};
+use crate::encryption;
+use crate::stable;
+use crate::types::{AmountUnit, Currency};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{error, warn};
+
+const SETTINGS_FILE_NAME: &str = "settings.json";
+
+lazy_static::lazy_static! {
+    /// The settings every screen reads, and the data dir they're saved to
+    static ref SETTINGS: Mutex<(Settings, Option<PathBuf>)> = Mutex::new((Settings::default(), None));
+}
+
+/// Preferences shared by all three apps, edited from the Settings screen
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub display: DisplaySettings,
+    pub payments: PaymentSettings,
+    pub stability: StabilitySettings,
+    pub node: NodeSettings,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PaymentSettings {
+    /// Routing fee cap for payments sent with the max fee left blank; zero leaves the
+    /// node's default
+    pub max_fee_ppm: u64,
+    /// Expiry of invoices generated from the UI
+    pub invoice_expiry_secs: u32,
+}
+
+impl Default for PaymentSettings {
+    fn default() -> Self {
+        Self { max_fee_ppm: 0, invoice_expiry_secs: 3600 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StabilitySettings {
+    /// Seconds between stability checks
+    pub check_interval_secs: u64,
+    /// Deviation from par, in percent, below which a channel counts as stable
+    pub threshold_percent: f64,
+}
+
+impl Default for StabilitySettings {
+    fn default() -> Self {
+        Self { check_interval_secs: 30, threshold_percent: stable::STABILITY_THRESHOLD_PERCENT }
+    }
+}
+
+/// Settings read only when the node is built
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodeSettings {
+    /// Port the node listens on, instead of the app's default
+    pub listening_port: Option<u16>,
+}
+
+impl Settings {
+    fn path(data_dir: &Path) -> PathBuf {
+        data_dir.join(SETTINGS_FILE_NAME)
+    }
+
+    /// The saved settings, or the defaults if none are saved or the saved ones are
+    /// invalid. Display settings saved before this file existed are carried over.
+    pub fn load(data_dir: &Path) -> Self {
+        let path = Self::path(data_dir);
+        if !path.exists() {
+            return Self { display: DisplaySettings::load(data_dir), ..Self::default() };
+        }
+        let settings = match encryption::read_state_file(&path).map(|s| serde_json::from_str::<Self>(&s)) {
+            Ok(Ok(settings)) => settings,
+            Ok(Err(e)) => {
+                error!("Ignoring unreadable {}: {}", path.display(), e);
+                return Self::default();
+            }
+            Err(e) => {
+                error!("Failed to read {}: {}", path.display(), e);
+                return Self::default();
+            }
+        };
+        match settings.validate() {
+            Ok(()) => settings,
+            Err(e) => {
+                warn!("Ignoring invalid settings in {}: {}", path.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, data_dir: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        encryption::write_state_file(&Self::path(data_dir), &json).map_err(|e| e.to_string())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.payments.invoice_expiry_secs < 60 {
+            return Err("Invoice expiry must be at least 60 seconds".to_string());
+        }
+        if self.stability.check_interval_secs == 0 {
+            return Err("Stability check interval must be positive".to_string());
+        }
+        if !(self.stability.threshold_percent > 0.0 && self.stability.threshold_percent < 100.0) {
+            return Err("Stability threshold must be between 0 and 100%".to_string());
+        }
+        if self.node.listening_port == Some(0) {
+            return Err("Listening port must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Loads the settings from `data_dir`, where changes to them are saved from then on
+pub fn init(data_dir: &Path) {
+    *SETTINGS.lock().unwrap() = (Settings::load(data_dir), Some(data_dir.to_path_buf()));
+}
+
+pub fn get() -> Settings {
+    SETTINGS.lock().unwrap().0.clone()
+}
+
+/// Changes the settings with `f` and saves them, if `init` has said where. Changes that
+/// would make them invalid are dropped.
+pub fn update(f: impl FnOnce(&mut Settings)) -> Result<(), String> {
+    let mut guard = SETTINGS.lock().unwrap();
+    let mut settings = guard.0.clone();
+    f(&mut settings);
+    if settings == guard.0 {
+        return Ok(());
+    }
+    settings.validate()?;
+    if let Some(data_dir) = &guard.1 {
+        if let Err(e) = settings.save(data_dir) {
+            error!("Failed to save settings: {}", e);
+        }
+    }
+    guard.0 = settings;
+    Ok(())
+}
+
+/// The Settings screen's form. Fields typed as text are checked and saved together with
+/// "Save"; choices from a list apply as soon as they are picked.
+#[derive(Default)]
+pub struct SettingsScreen {
+    max_fee_ppm: String,
+    invoice_expiry_secs: String,
+    check_interval_secs: String,
+    threshold_percent: String,
+    listening_port: String,
+    status: Option<Result<String, String>>,
+}
+
+impl SettingsScreen {
+    pub fn new() -> Self {
+        let settings = get();
+        Self {
+            max_fee_ppm: settings.payments.max_fee_ppm.to_string(),
+            invoice_expiry_secs: settings.payments.invoice_expiry_secs.to_string(),
+            check_interval_secs: settings.stability.check_interval_secs.to_string(),
+            threshold_percent: settings.stability.threshold_percent.to_string(),
+            listening_port: settings.node.listening_port.map(|p| p.to_string()).unwrap_or_default(),
+            status: None,
+        }
+    }
+
+    fn save(&mut self) -> Result<(), String> {
+        let max_fee_ppm = self.max_fee_ppm.trim().parse::<u64>().map_err(|_| "Invalid max fee")?;
+        let invoice_expiry_secs = self.invoice_expiry_secs.trim().parse::<u32>().map_err(|_| "Invalid invoice expiry")?;
+        let check_interval_secs = self.check_interval_secs.trim().parse::<u64>().map_err(|_| "Invalid check interval")?;
+        let threshold_percent = self.threshold_percent.trim().parse::<f64>().map_err(|_| "Invalid threshold")?;
+        let listening_port = match self.listening_port.trim() {
+            "" => None,
+            port => Some(port.parse::<u16>().map_err(|_| "Invalid port")?),
+        };
+        update(|settings| {
+            settings.payments.max_fee_ppm = max_fee_ppm;
+            settings.payments.invoice_expiry_secs = invoice_expiry_secs;
+            settings.stability.check_interval_secs = check_interval_secs;
+            settings.stability.threshold_percent = threshold_percent;
+            settings.node.listening_port = listening_port;
+        })
+    }
+
+    /// Draws the screen. `node_info` is shown read-only in the Node section. Returns true
+    /// once the user closes it.
+    pub fn show(&mut self, ui: &mut egui::Ui, node_info: &[(&str, String)]) -> bool {
+        let mut closed = false;
+        let settings = get();
+        ui.heading("Settings");
+        ui.add_space(10.0);
+
+        ui.group(|ui| {
+            ui.strong("Display");
+            let mut display = settings.display;
+            ui.horizontal(|ui| {
+                ui.label("Amounts in:");
+                ui.selectable_value(&mut display.unit, AmountUnit::Sats, "sats");
+                ui.selectable_value(&mut display.unit, AmountUnit::Btc, "BTC");
+            });
+            ui.horizontal(|ui| {
+                ui.label("Fiat currency:");
+                egui::ComboBox::from_id_salt("settings_fiat")
+                    .selected_text(display.fiat.code())
+                    .show_ui(ui, |ui| {
+                        for currency in Currency::ALL {
+                            ui.selectable_value(&mut display.fiat, currency, currency.code());
+                        }
+                    });
+                ui.label(egui::RichText::new("used for new pegs").size(12.0).color(egui::Color32::GRAY));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Theme:");
+                ui.selectable_value(&mut display.theme, Theme::Dark, "Dark");
+                ui.selectable_value(&mut display.theme, Theme::Light, "Light");
+            });
+            if display != settings.display {
+                let _ = update(|settings| settings.display = display);
+            }
+        });
+        ui.add_space(10.0);
+
+        ui.group(|ui| {
+            ui.strong("Payments");
+            ui.horizontal(|ui| {
+                ui.label("Default max routing fee (ppm, 0 for the node's default):");
+                ui.text_edit_singleline(&mut self.max_fee_ppm);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Invoice expiry (seconds):");
+                ui.text_edit_singleline(&mut self.invoice_expiry_secs);
+            });
+        });
+        ui.add_space(10.0);
+
+        ui.group(|ui| {
+            ui.strong("Stability");
+            ui.horizontal(|ui| {
+                ui.label("Check interval (seconds):");
+                ui.text_edit_singleline(&mut self.check_interval_secs);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Stable within (% from par):");
+                ui.text_edit_singleline(&mut self.threshold_percent);
+            });
+        });
+        ui.add_space(10.0);
+
+        ui.group(|ui| {
+            ui.strong("Node");
+            for (label, value) in node_info {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{}:", label));
+                    ui.monospace(value);
+                });
+            }
+            ui.horizontal(|ui| {
+                ui.label("Listening port:");
+                ui.add(egui::TextEdit::singleline(&mut self.listening_port).hint_text("app default").desired_width(80.0));
+                ui.label(egui::RichText::new("takes effect after restart").size(12.0).color(egui::Color32::YELLOW));
+            });
+        });
+        ui.add_space(10.0);
+
+        ui.horizontal(|ui| {
+            if ui.button("Save").clicked() {
+                self.status = Some(self.save().map(|()| "Settings saved".to_string()));
+            }
+            if ui.button("Close").clicked() {
+                closed = true;
+            }
+        });
+        match &self.status {
+            Some(Ok(message)) => {
+                ui.colored_label(egui::Color32::GREEN, message);
+            }
+            Some(Err(e)) => {
+                ui.colored_label(egui::Color32::RED, e);
+            }
+            None => {}
+        }
+        closed
+    }
+}
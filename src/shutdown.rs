@@ -0,0 +1,62 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// How often sleeping background loops wake up to check the shutdown flag
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long shutdown waits for a background thread before giving up on it
+pub const THREAD_JOIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Sets `flag` when the process receives SIGINT, so the app can close cleanly
+pub fn install_sigint_handler(flag: Arc<AtomicBool>) {
+    if let Err(e) = ctrlc::set_handler(move || {
+        info!("Received interrupt, shutting down");
+        flag.store(true, Ordering::SeqCst);
+    }) {
+        warn!("Failed to install interrupt handler: {}", e);
+    }
+}
+
+/// Sleeps for `duration` in short slices. Returns true if shutdown was requested meanwhile.
+pub fn sleep_unless_shutdown(flag: &AtomicBool, duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        if flag.load(Ordering::SeqCst) {
+            return true;
+        }
+        std::thread::sleep(SHUTDOWN_POLL_INTERVAL.min(deadline - Instant::now()));
+    }
+    flag.load(Ordering::SeqCst)
+}
+
+/// Like `sleep_unless_shutdown`, but also returns early, clearing `wake`, once `wake` is set
+pub fn sleep_until_woken(flag: &AtomicBool, wake: &AtomicBool, duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        if flag.load(Ordering::SeqCst) {
+            return true;
+        }
+        if wake.swap(false, Ordering::SeqCst) {
+            return false;
+        }
+        std::thread::sleep(SHUTDOWN_POLL_INTERVAL.min(deadline - Instant::now()));
+    }
+    flag.load(Ordering::SeqCst)
+}
+
+/// Joins `handle`, waiting at most `timeout`. Returns false if the thread was still running.
+pub fn join_with_timeout(handle: JoinHandle<()>, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    while !handle.is_finished() {
+        if Instant::now() >= deadline {
+            warn!("Background thread did not stop within {:?}", timeout);
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    let _ = handle.join();
+    true
+}
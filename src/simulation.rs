@@ -0,0 +1,148 @@
+use crate::price_feeds::get_cached_price_in;
+use crate::stable::{self, Payer, SimulationResult};
+use crate::types::{Currency, StableChannel};
+use ldk_node::lightning::ln::types::ChannelId;
+use ldk_node::Node;
+
+/// Standard price moves, in percent, shown for every snapshot
+pub const SCENARIOS: [f64; 4] = [-10.0, -25.0, -50.0, 50.0];
+
+/// "Simulate" panel projecting stability payments for a channel at hypothetical prices.
+/// Works on a snapshot, so nothing is sent and the channel's state is left alone.
+#[derive(Default)]
+pub struct SimulatorPanel {
+    selected: Option<ChannelId>,
+    /// Comma-separated prices to run in addition to the standard scenarios
+    prices_input: String,
+    error: Option<String>,
+}
+
+impl SimulatorPanel {
+    /// Draws the panel. Channels that aren't stable yet are simulated as if designated
+    /// now, with the counterparty's whole balance as the target.
+    pub fn show(&mut self, ui: &mut egui::Ui, node: &Node, stable_channels: &[StableChannel], btc_price: f64) {
+        egui::CollapsingHeader::new("Simulate").show(ui, |ui| {
+            let channels = node.list_channels();
+            let label = |channel_id: &ChannelId| {
+                let stable = stable_channels.iter().any(|sc| sc.channel_id == *channel_id);
+                format!("{}{}", channel_id, if stable { " (stable)" } else { "" })
+            };
+            egui::ComboBox::from_id_salt("simulate_channel")
+                .selected_text(self.selected.as_ref().map(&label).unwrap_or_else(|| "Select a channel".to_string()))
+                .show_ui(ui, |ui| {
+                    for channel in &channels {
+                        ui.selectable_value(&mut self.selected, Some(channel.channel_id), label(&channel.channel_id));
+                    }
+                });
+            ui.horizontal(|ui| {
+                ui.label("Other prices (in the peg's currency, comma separated):");
+                ui.add(egui::TextEdit::singleline(&mut self.prices_input).desired_width(200.0));
+            });
+
+            let Some(channel_id) = self.selected else {
+                return;
+            };
+            if btc_price <= 0.0 {
+                ui.label("Waiting for a price...");
+                return;
+            }
+            let Some(snapshot) = snapshot(node, stable_channels, channel_id, btc_price) else {
+                ui.label("Channel no longer exists, or no price in its currency");
+                return;
+            };
+
+            let currency = snapshot.currency;
+            let base_price = snapshot.latest_price;
+            let mut prices: Vec<f64> = SCENARIOS.iter().map(|change| base_price * (1.0 + change / 100.0)).collect();
+            self.error = None;
+            for price in self.prices_input.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+                match price.parse::<f64>() {
+                    Ok(price) if price > 0.0 => prices.push(price),
+                    _ => self.error = Some(format!("Invalid price {:?}", price)),
+                }
+            }
+            if let Some(error) = &self.error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
+
+            ui.label(format!(
+                "Target {} at {}; receiver holds {} sats, provider {} sats",
+                currency.format(snapshot.expected_usd.to_f64()),
+                currency.format(base_price),
+                snapshot.stable_receiver_btc.to_msats() / 1000,
+                snapshot.stable_provider_btc.to_msats() / 1000,
+            ));
+            match stable::exhaustion_price(&snapshot) {
+                Some(price) => ui.label(format!(
+                    "Channel exhausted below {} ({:+.1}%)",
+                    currency.format(price),
+                    change(price, base_price)
+                )),
+                None => ui.label("Channel can't cover the target at any price"),
+            };
+
+            egui::Grid::new("simulation_results").striped(true).num_columns(7).show(ui, |ui| {
+                for header in ["Price", "Change", "Receiver", "Par", "Who pays", "Amount", "Capacity"] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+                for price in prices {
+                    show_row(ui, &stable::simulate_stability(&snapshot, price), base_price, currency);
+                }
+            });
+        });
+    }
+}
+
+/// The channel's stable state with fresh balances, or a hypothetical designation of it
+fn snapshot(node: &Node, stable_channels: &[StableChannel], channel_id: ChannelId, btc_price: f64) -> Option<StableChannel> {
+    let channel = node.list_channels().into_iter().find(|c| c.channel_id == channel_id)?;
+    let mut sc = match stable_channels.iter().find(|sc| sc.channel_id == channel_id) {
+        Some(sc) => sc.clone(),
+        None => StableChannel {
+            channel_id,
+            counterparty: channel.counterparty_node_id,
+            is_stable_receiver: false,
+            ..Default::default()
+        },
+    };
+    let price = if sc.currency == Currency::USD { btc_price } else { get_cached_price_in(sc.currency) };
+    if price <= 0.0 {
+        return None;
+    }
+    sc.latest_price = price;
+    sc.twap_price = price;
+    stable::update_balances(node, &mut sc);
+    if !stable_channels.iter().any(|s| s.channel_id == channel_id) {
+        sc.expected_usd = sc.stable_receiver_usd;
+        sc.expected_btc = sc.stable_receiver_btc;
+    }
+    Some(sc)
+}
+
+fn show_row(ui: &mut egui::Ui, result: &SimulationResult, base_price: f64, currency: Currency) {
+    ui.label(currency.format(result.price));
+    ui.label(format!("{:+.1}%", change(result.price, base_price)));
+    ui.label(currency.format(result.receiver_usd.to_f64()));
+    ui.label(currency.format(result.par_usd.to_f64()));
+    ui.label(match result.payer {
+        Some(Payer::Provider) => "Provider → receiver",
+        Some(Payer::Receiver) => "Receiver → provider",
+        None => "Nobody (within threshold)",
+    });
+    let mut amount = format!("{} sats", result.amount_msats / 1000);
+    if result.exceeds_cap {
+        amount.push_str(" (over cap)");
+    }
+    ui.label(amount);
+    if result.payer.is_none() || result.has_capacity {
+        ui.label("OK");
+    } else {
+        ui.colored_label(egui::Color32::RED, "Insufficient");
+    }
+    ui.end_row();
+}
+
+fn change(price: f64, from: f64) -> f64 {
+    (price / from - 1.0) * 100.0
+}
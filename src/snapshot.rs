@@ -0,0 +1,39 @@
+use ldk_node::{ChannelDetails, Node};
+use std::time::{Duration, Instant};
+
+/// How long a snapshot is used before it is re-read even without a channel event
+const SNAPSHOT_MAX_AGE: Duration = Duration::from_secs(5);
+
+/// Cached `node.list_channels()` for the UI. Listing channels takes the channel manager's
+/// locks, which is too costly to do on every frame; actions that change channels must
+/// re-check the live node rather than trust the snapshot.
+pub struct ChannelSnapshot {
+    channels: Vec<ChannelDetails>,
+    taken_at: Option<Instant>,
+}
+
+impl ChannelSnapshot {
+    pub fn new() -> Self {
+        Self { channels: Vec::new(), taken_at: None }
+    }
+
+    pub fn channels(&self) -> &[ChannelDetails] {
+        &self.channels
+    }
+
+    /// Forces a re-read on the next refresh, e.g. after a channel event
+    pub fn invalidate(&mut self) {
+        self.taken_at = None;
+    }
+
+    /// Re-reads the channel list if invalidated or older than `SNAPSHOT_MAX_AGE`.
+    /// Returns true if it was re-read.
+    pub fn refresh_if_needed(&mut self, node: &Node) -> bool {
+        if self.taken_at.map_or(false, |t| t.elapsed() < SNAPSHOT_MAX_AGE) {
+            return false;
+        }
+        self.channels = node.list_channels();
+        self.taken_at = Some(Instant::now());
+        true
+    }
+}
@@ -1,14 +1,366 @@
-use crate::types::{Bitcoin, StableChannel, USD};
+use crate::types::{
+    valid_price, Bitcoin, Currency, MissedSettlement, PaymentDirection, PaymentRecord, Runway, StabilityPaymentInfo, StableChannel, StableMessage, StableMessageEnvelope,
+    STABILITY_PAYMENT_TLV_TYPE, STABILITY_SIGNATURE_TLV_TYPE, STABLE_MESSAGE_TLV_TYPE, USD,
+};
 use ldk_node::{
-    lightning::ln::types::ChannelId, Node,
+    bitcoin::secp256k1::PublicKey,
+    lightning::{
+        events::PaymentFailureReason,
+        ln::{channelmanager::PaymentId, types::ChannelId},
+    },
+    payment::SendingParameters,
+    ChannelDetails, CustomTlvRecord, Event, Node, NodeError,
 };
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info, info_span, warn};
 use ureq::Agent;
-use crate::price_feeds::get_cached_price;
+use crate::payment_retry::{self, AttemptPlan};
+use crate::price_feeds::{self, get_cached_price_in, get_price_stats_in, PriceStats};
+use crate::reconnect;
+use crate::settings;
+use serde::{Deserialize, Serialize};
+
+/// Default cap on a single stability payment, in USD
+pub const DEFAULT_MAX_PAYMENT_USD: f64 = 100.0;
+/// Default cap on a single stability payment, as a percentage of `expected_usd`
+pub const DEFAULT_MAX_PAYMENT_PERCENT: f64 = 25.0;
+
+/// Deviation from par, in percent, below which a channel counts as stable, unless the
+/// settings say otherwise
+pub const STABILITY_THRESHOLD_PERCENT: f64 = 0.1;
+
+/// The stability threshold in effect
+pub fn threshold_percent() -> f64 {
+    settings::get().stability.threshold_percent
+}
+
+/// Default risk level above which stability payments are suspended
+pub const DEFAULT_MAX_RISK_LEVEL: i32 = 100;
+
+/// Default minimum gap between two stability payments on a channel
+pub const DEFAULT_MIN_SECONDS_BETWEEN_PAYMENTS: u64 = 120;
+/// Default cap on the USD value of stability payments sent in any 24 hours
+pub const DEFAULT_MAX_DAILY_ADJUSTMENT_USD: f64 = 250.0;
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+/// Default price move between two checks, in percent, that pauses stability payments
+pub const DEFAULT_CIRCUIT_BREAKER_PERCENT: f64 = 20.0;
+/// Spread between the price feeds, in percent, beyond which the price isn't trusted
+const MAX_FEED_SPREAD_PERCENT: f64 = 5.0;
+/// How long a tripped circuit breaker holds unless resumed by hand sooner
+pub const CIRCUIT_BREAKER_HOLD_SECS: i64 = 60 * 60;
+/// Runway below which a channel is flagged as nearly exhausted
+pub const DEFAULT_LOW_RUNWAY_PERCENT: f64 = 10.0;
+/// Difference in value within which a channel with the same counterparty is taken to be
+/// the designated one under a new id, as after a splice
+pub const REATTACH_VALUE_TOLERANCE_PERCENT: f64 = 10.0;
+
+/// Default window over which the stability price is averaged
+pub const DEFAULT_TWAP_WINDOW_SECS: u64 = 300;
+/// How long price samples are kept in a channel's history
+pub const PRICE_HISTORY_RETENTION_SECS: i64 = 24 * 60 * 60;
+
+/// Percentage of the receiver's balance pegged unless agreed otherwise
+pub const DEFAULT_STABLE_FRACTION: f64 = 100.0;
+
+/// Amount attached to each negotiation keysend; the message itself travels in a custom TLV
+pub const STABLE_MESSAGE_AMOUNT_MSAT: u64 = 1_000;
+
+/// A received adjustment may be at most this multiple of our own view of the deviation, plus
+/// `ADJUSTMENT_SLACK_USD`, before it is treated as an ordinary payment. Each side values the
+/// channel at a slightly different price and time, so the bound is loose.
+const ADJUSTMENT_DEVIATION_FACTOR: f64 = 2.0;
+const ADJUSTMENT_SLACK_USD: f64 = 1.0;
+
+/// Per-channel limits applied when designating a stable channel
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StableChannelSettings {
+    pub max_payment_usd: f64,
+    pub max_payment_percent: f64,
+    pub approval_required_above_cap: bool,
+    pub max_risk_level: i32,
+    pub twap_window_secs: u64,
+    pub min_seconds_between_payments: u64,
+    pub max_daily_adjustment_usd: f64,
+    pub key_by_counterparty: bool,
+    /// None uses the LSP's configured fee
+    pub stability_fee_ppm: Option<u32>,
+    /// Currency the target is in
+    pub currency: Currency,
+    /// Percentage of the user's balance pegged; the rest floats with BTC
+    pub stable_fraction: f64,
+    /// Price move between checks, in percent, that pauses payments
+    pub circuit_breaker_percent: f64,
+}
+
+impl Default for StableChannelSettings {
+    fn default() -> Self {
+        Self {
+            max_payment_usd: DEFAULT_MAX_PAYMENT_USD,
+            max_payment_percent: DEFAULT_MAX_PAYMENT_PERCENT,
+            approval_required_above_cap: false,
+            max_risk_level: DEFAULT_MAX_RISK_LEVEL,
+            twap_window_secs: DEFAULT_TWAP_WINDOW_SECS,
+            min_seconds_between_payments: DEFAULT_MIN_SECONDS_BETWEEN_PAYMENTS,
+            max_daily_adjustment_usd: DEFAULT_MAX_DAILY_ADJUSTMENT_USD,
+            key_by_counterparty: false,
+            stability_fee_ppm: None,
+            currency: Currency::USD,
+            stable_fraction: DEFAULT_STABLE_FRACTION,
+            circuit_breaker_percent: DEFAULT_CIRCUIT_BREAKER_PERCENT,
+        }
+    }
+}
+
+/// A stable channel as the user and LSP apps persist it. Balances aren't saved; they come
+/// from the node's channel on load. Fields added over time default so older files load.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StableChannelEntry {
+    pub channel_id: String,
+    /// Missing from the LSP's files before version 1
+    #[serde(default)]
+    pub counterparty: Option<String>,
+    pub expected_usd: f64,
+    /// The LSP's files leave this out: it is always the provider
+    #[serde(default)]
+    pub is_stable_receiver: bool,
+    /// Provider's BTC leg now; in the LSP's files before version 2, the BTC equivalent of
+    /// the target
+    #[serde(default)]
+    pub native_btc: f64,
+    /// Provider's BTC leg when the channel was designated; missing from older files
+    #[serde(default)]
+    pub native_btc_original: Option<f64>,
+    #[serde(default = "default_max_payment_usd")]
+    pub max_payment_usd: f64,
+    #[serde(default = "default_max_payment_percent")]
+    pub max_payment_percent: f64,
+    #[serde(default)]
+    pub approval_required_above_cap: bool,
+    #[serde(default = "default_min_seconds_between_payments")]
+    pub min_seconds_between_payments: u64,
+    #[serde(default = "default_max_daily_adjustment_usd")]
+    pub max_daily_adjustment_usd: f64,
+    #[serde(default = "default_circuit_breaker_percent")]
+    pub circuit_breaker_percent: f64,
+    #[serde(default = "default_max_risk_level")]
+    pub max_risk_level: i32,
+    /// The LSP's files leave this out: everything it designates is agreed
+    #[serde(default)]
+    pub peg_agreed: bool,
+    #[serde(default)]
+    pub keyed_by_counterparty: bool,
+    #[serde(default)]
+    pub total_paid_to_user_msats: u64,
+    #[serde(default)]
+    pub total_paid_to_lsp_msats: u64,
+    #[serde(default)]
+    pub payment_count: u64,
+    #[serde(default)]
+    pub risk_level: i32,
+    #[serde(default)]
+    pub consecutive_failures: u32,
+    #[serde(default = "default_twap_window_secs")]
+    pub twap_window_secs: u64,
+    #[serde(default)]
+    pub prices: VecDeque<(i64, f64)>,
+    #[serde(default)]
+    pub last_payment_timestamp: i64,
+    #[serde(default)]
+    pub daily_payments: VecDeque<(i64, f64)>,
+    #[serde(default)]
+    pub stability_fee_ppm: u32,
+    #[serde(default)]
+    pub accrued_fee_msats: u64,
+    #[serde(default)]
+    pub currency: Currency,
+    #[serde(default = "default_stable_fraction")]
+    pub stable_fraction: f64,
+    #[serde(default)]
+    pub float_btc: f64,
+    #[serde(default)]
+    pub unpegged_at: Option<i64>,
+    #[serde(default)]
+    pub missed_settlements: VecDeque<MissedSettlement>,
+    #[serde(default)]
+    pub closing_price: Option<f64>,
+    #[serde(default)]
+    pub designation_price: Option<f64>,
+    #[serde(default)]
+    pub suspended_until: Option<i64>,
+    #[serde(default)]
+    pub suspension_reason: Option<String>,
+    #[serde(default)]
+    pub last_payment: Option<PaymentRecord>,
+}
+
+fn default_max_payment_usd() -> f64 {
+    DEFAULT_MAX_PAYMENT_USD
+}
+
+fn default_max_payment_percent() -> f64 {
+    DEFAULT_MAX_PAYMENT_PERCENT
+}
+
+fn default_min_seconds_between_payments() -> u64 {
+    DEFAULT_MIN_SECONDS_BETWEEN_PAYMENTS
+}
+
+fn default_max_daily_adjustment_usd() -> f64 {
+    DEFAULT_MAX_DAILY_ADJUSTMENT_USD
+}
+
+fn default_circuit_breaker_percent() -> f64 {
+    DEFAULT_CIRCUIT_BREAKER_PERCENT
+}
+
+fn default_max_risk_level() -> i32 {
+    DEFAULT_MAX_RISK_LEVEL
+}
 
-/// Get the current BTC/USD price, preferring cached value when available
-pub fn get_current_price(agent: &Agent) -> f64 {
+fn default_twap_window_secs() -> u64 {
+    DEFAULT_TWAP_WINDOW_SECS
+}
+
+fn default_stable_fraction() -> f64 {
+    DEFAULT_STABLE_FRACTION
+}
+
+impl StableChannelEntry {
+    pub fn new(sc: &StableChannel) -> Self {
+        Self {
+            channel_id: sc.channel_id.to_string(),
+            counterparty: Some(sc.counterparty.to_string()),
+            expected_usd: sc.expected_usd.to_f64(),
+            is_stable_receiver: sc.is_stable_receiver,
+            native_btc: sc.native_btc.to_btc(),
+            native_btc_original: Some(sc.native_btc_original.to_btc()),
+            max_payment_usd: sc.max_payment_usd,
+            max_payment_percent: sc.max_payment_percent,
+            approval_required_above_cap: sc.approval_required_above_cap,
+            min_seconds_between_payments: sc.min_seconds_between_payments,
+            max_daily_adjustment_usd: sc.max_daily_adjustment_usd,
+            circuit_breaker_percent: sc.circuit_breaker_percent,
+            max_risk_level: sc.max_risk_level,
+            peg_agreed: sc.peg_agreed,
+            keyed_by_counterparty: sc.keyed_by_counterparty,
+            total_paid_to_user_msats: sc.total_paid_to_user_msats,
+            total_paid_to_lsp_msats: sc.total_paid_to_lsp_msats,
+            payment_count: sc.payment_count,
+            risk_level: sc.risk_level,
+            consecutive_failures: sc.consecutive_failures,
+            twap_window_secs: sc.twap_window_secs,
+            prices: sc.prices.clone(),
+            last_payment_timestamp: sc.last_payment_timestamp,
+            daily_payments: sc.daily_payments.clone(),
+            stability_fee_ppm: sc.stability_fee_ppm,
+            accrued_fee_msats: sc.accrued_fee_msats,
+            currency: sc.currency,
+            stable_fraction: sc.stable_fraction,
+            float_btc: sc.float_btc.to_btc(),
+            unpegged_at: sc.unpegged_at,
+            missed_settlements: sc.missed_settlements.clone(),
+            closing_price: sc.closing_price,
+            designation_price: sc.designation_price,
+            suspended_until: sc.suspended_until,
+            suspension_reason: sc.suspension_reason.clone(),
+            last_payment: sc.last_payment.clone(),
+        }
+    }
+
+    /// The saved counterparty, if there is one and it parses
+    pub fn counterparty(&self) -> Option<PublicKey> {
+        self.counterparty.as_deref().and_then(|pk| pk.parse().ok())
+    }
+
+    /// Copies the saved peg, limits and running totals into `sc`. The channel id,
+    /// counterparty, balances and the provider's BTC leg are left to the caller, which
+    /// knows the channel they come from.
+    pub fn restore_into(&self, sc: &mut StableChannel) {
+        sc.currency = self.currency;
+        sc.expected_usd = USD::from_f64(self.expected_usd);
+        sc.is_stable_receiver = self.is_stable_receiver;
+        sc.max_payment_usd = self.max_payment_usd;
+        sc.max_payment_percent = self.max_payment_percent;
+        sc.approval_required_above_cap = self.approval_required_above_cap;
+        sc.min_seconds_between_payments = self.min_seconds_between_payments;
+        sc.max_daily_adjustment_usd = self.max_daily_adjustment_usd;
+        sc.circuit_breaker_percent = self.circuit_breaker_percent;
+        sc.max_risk_level = self.max_risk_level;
+        sc.peg_agreed = self.peg_agreed;
+        sc.keyed_by_counterparty = self.keyed_by_counterparty;
+        sc.total_paid_to_user_msats = self.total_paid_to_user_msats;
+        sc.total_paid_to_lsp_msats = self.total_paid_to_lsp_msats;
+        sc.payment_count = self.payment_count;
+        sc.risk_level = self.risk_level;
+        sc.consecutive_failures = self.consecutive_failures;
+        sc.twap_window_secs = self.twap_window_secs;
+        sc.prices = self.prices.clone();
+        sc.last_payment_timestamp = self.last_payment_timestamp;
+        sc.daily_payments = self.daily_payments.clone();
+        sc.stability_fee_ppm = self.stability_fee_ppm;
+        sc.accrued_fee_msats = self.accrued_fee_msats;
+        sc.stable_fraction = self.stable_fraction;
+        sc.float_btc = Bitcoin::from_btc(self.float_btc);
+        sc.unpegged_at = self.unpegged_at;
+        sc.missed_settlements = self.missed_settlements.clone();
+        sc.closing_price = self.closing_price;
+        sc.designation_price = self.designation_price;
+        sc.suspended_until = self.suspended_until;
+        sc.suspension_reason = self.suspension_reason.clone();
+        sc.last_payment = self.last_payment.clone();
+    }
+}
+
+/// The subset of node operations the stability logic depends on
+pub trait LightningOps {
+    fn list_channels(&self) -> Vec<ChannelDetails>;
+    fn spontaneous_payment_send(
+        &self,
+        amount_msat: u64,
+        node_id: PublicKey,
+        sending_parameters: Option<SendingParameters>,
+        custom_tlvs: Vec<CustomTlvRecord>,
+    ) -> Result<PaymentId, NodeError>;
+    fn sign_message(&self, msg: &[u8]) -> String;
+    fn verify_signature(&self, msg: &[u8], signature: &str, public_key: &PublicKey) -> bool;
+    fn is_peer_connected(&self, node_id: &PublicKey) -> bool;
+}
+
+impl LightningOps for Node {
+    fn list_channels(&self) -> Vec<ChannelDetails> {
+        Node::list_channels(self)
+    }
+
+    fn spontaneous_payment_send(
+        &self,
+        amount_msat: u64,
+        node_id: PublicKey,
+        sending_parameters: Option<SendingParameters>,
+        custom_tlvs: Vec<CustomTlvRecord>,
+    ) -> Result<PaymentId, NodeError> {
+        self.spontaneous_payment().send_with_custom_tlvs(amount_msat, node_id, sending_parameters, custom_tlvs)
+    }
+
+    fn sign_message(&self, msg: &[u8]) -> String {
+        Node::sign_message(self, msg)
+    }
+
+    fn verify_signature(&self, msg: &[u8], signature: &str, public_key: &PublicKey) -> bool {
+        Node::verify_signature(self, msg, signature, public_key)
+    }
+
+    fn is_peer_connected(&self, node_id: &PublicKey) -> bool {
+        reconnect::is_peer_connected(self, node_id)
+    }
+}
+
+/// Get the current BTC price in `currency`, preferring cached value when available
+pub fn get_current_price(agent: &Agent, currency: Currency) -> f64 {
     // First try the cached price
-    let cached_price = get_cached_price();
+    let cached_price = get_cached_price_in(currency);
     
     // Use the cached price if valid
     if cached_price > 0.0 {
@@ -16,35 +368,48 @@ pub fn get_current_price(agent: &Agent) -> f64 {
     }
     
     // Fall back to fetching a new price
-    match crate::price_feeds::get_latest_price(agent) {
+    match crate::price_feeds::get_latest_price_in(agent, currency) {
         Ok(price) => price,
         Err(_) => 0.0 
     }
 }
 
 /// Check if the given channel exists in the node's channel list
-pub fn channel_exists(node: &Node, channel_id: &ChannelId) -> bool {
+pub fn channel_exists<N: LightningOps + ?Sized>(node: &N, channel_id: &ChannelId) -> bool {
     let channels = node.list_channels();
     channels.iter().any(|c| c.channel_id == *channel_id)
 }
 
+/// Our and the counterparty's balances in `channel`, in sats. Each side's balance is what
+/// it can send plus its own channel reserve: the reserve is still that side's money, which
+/// the peg covers. Amounts in pending HTLCs belong to neither side until the HTLC
+/// resolves, so the two don't always add up to the channel value; counting the remainder
+/// towards one side would swing the split every time a payment is in flight.
+pub fn channel_balances(channel: &ChannelDetails) -> (u64, u64) {
+    let ours = channel.outbound_capacity_msat / 1000 + channel.unspendable_punishment_reserve.unwrap_or(0);
+    let theirs = channel.inbound_capacity_msat / 1000 + channel.counterparty_unspendable_punishment_reserve;
+    (ours, theirs)
+}
+
 // Can run in backgound
-pub fn update_balances<'update_balance_lifetime>(
-    node: &Node,
+pub fn update_balances<'update_balance_lifetime, N: LightningOps + ?Sized>(
+    node: &N,
     sc: &'update_balance_lifetime mut StableChannel,
 ) -> (bool, &'update_balance_lifetime mut StableChannel) {
     if sc.latest_price == 0.0 {
-        sc.latest_price = get_cached_price();
+        sc.latest_price = get_cached_price_in(sc.currency);
         
         if sc.latest_price == 0.0 {
             let agent = Agent::new();
-            sc.latest_price = get_current_price(&agent);
+            sc.latest_price = get_current_price(&agent, sc.currency);
         }
     }
     
     let channels = node.list_channels();
     let matching_channel = if sc.channel_id == ChannelId::from_bytes([0; 32]) {
-        channels.first()
+        // Only when the counterparty has a single channel, so it can't be another peg's
+        let mut with_counterparty = channels.iter().filter(|c| c.counterparty_node_id == sc.counterparty);
+        with_counterparty.next().filter(|_| with_counterparty.next().is_none())
     } else {
         channels.iter().find(|c| c.channel_id == sc.channel_id)
     };
@@ -52,12 +417,10 @@ pub fn update_balances<'update_balance_lifetime>(
     if let Some(channel) = matching_channel {
         if sc.channel_id == ChannelId::from_bytes([0; 32]) {
             sc.channel_id = channel.channel_id;
-            println!("Set active channel ID to: {}", sc.channel_id);
+            info!("Set active channel ID to: {}", sc.channel_id);
         }
         
-        let unspendable_punishment_sats = channel.unspendable_punishment_reserve.unwrap_or(0);
-        let our_balance_sats = (channel.outbound_capacity_msat / 1000) + unspendable_punishment_sats;
-        let their_balance_sats = channel.channel_value_sats - our_balance_sats;
+        let (our_balance_sats, their_balance_sats) = channel_balances(channel);
         
         if sc.is_stable_receiver {
             sc.stable_receiver_btc = Bitcoin::from_sats(our_balance_sats);
@@ -67,114 +430,2102 @@ pub fn update_balances<'update_balance_lifetime>(
             sc.stable_receiver_btc = Bitcoin::from_sats(their_balance_sats);
         }
         
-        sc.stable_receiver_usd = USD::from_bitcoin(sc.stable_receiver_btc, sc.latest_price);
-        sc.stable_provider_usd = USD::from_bitcoin(sc.stable_provider_btc, sc.latest_price);
+        sc.stable_receiver_usd = USD::from_bitcoin(sc.stable_receiver_btc, stability_price(sc));
+        sc.stable_provider_usd = USD::from_bitcoin(sc.stable_provider_btc, stability_price(sc));
+        // A target set before there was a price gets its BTC amount from the first one
+        if sc.expected_btc == Bitcoin::default() {
+            if let Some(expected_btc) = Bitcoin::from_usd(sc.expected_usd, stability_price(sc)) {
+                sc.expected_btc = expected_btc;
+            }
+        }
         
         return (true, sc);
     }
     
-    println!("No matching channel found for ID: {}", sc.channel_id);
+    warn!("No matching channel found for ID: {}", sc.channel_id);
     (true, sc)
 }
 
-pub fn check_stability(node: &Node, sc: &mut StableChannel, price: f64) {
-    println!("\n=== CHECKING CHANNEL STABILITY ===");
-    
-    let current_price = if price > 0.0 {
-        price
-    } else {
-        // Otherwise use cached price
-        let cached_price = get_cached_price();
-        if cached_price > 0.0 {
-            cached_price
-        } else {
-            println!("Skipping stability check: No valid price available");
-            return;
+/// Outcome of a single stability check, so callers can surface it in their UI
+#[derive(Debug, Clone, PartialEq)]
+pub enum StabilityAction {
+    Stable,
+    HighRisk(u32),
+    WaitingOnCounterparty,
+    Paid { amount_msats: u64, payment_id: PaymentId },
+    PaymentFailed(String),
+    PaymentPending(PaymentId),
+    NeedsApproval { amount_msats: u64 },
+    RateLimited { eligible_in_secs: u64 },
+    CannotRebalance,
+    NoPrice,
+    /// The counterparty isn't connected, so a payment would only fail
+    PeerDisconnected,
+    /// The price jumped or the feeds disagreed; payments pause until `suspended_until`
+    CircuitBreakerTripped(String),
+    /// Paused by the circuit breaker until the given unix time
+    Suspended { until: i64 },
+}
+
+impl std::fmt::Display for StabilityAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StabilityAction::Stable => write!(f, "Stable: difference from par less than {}%", threshold_percent()),
+            StabilityAction::HighRisk(level) => {
+                write!(f, "High risk: risk level ({}) exceeds threshold, action suspended", level)
+            }
+            StabilityAction::WaitingOnCounterparty => write!(f, "Waiting for payment from counterparty"),
+            StabilityAction::Paid { amount_msats, payment_id } => {
+                write!(f, "Stability payment of {} msats sent, ID: {}", amount_msats, payment_id)
+            }
+            StabilityAction::PaymentFailed(e) => write!(f, "Stability payment failed: {}", e),
+            StabilityAction::PaymentPending(payment_id) => {
+                write!(f, "Waiting for in-flight stability payment {}", payment_id)
+            }
+            StabilityAction::NeedsApproval { amount_msats } => {
+                write!(f, "Stability payment of {} msats exceeds the cap and needs approval", amount_msats)
+            }
+            StabilityAction::RateLimited { eligible_in_secs } => {
+                write!(f, "Next adjustment eligible in {}s", eligible_in_secs)
+            }
+            StabilityAction::CannotRebalance => write!(f, "Channel cannot rebalance further"),
+            StabilityAction::NoPrice => write!(f, "Skipping stability check: no valid price available"),
+            StabilityAction::PeerDisconnected => write!(f, "Skipping stability payment: counterparty is disconnected"),
+            StabilityAction::CircuitBreakerTripped(reason) => {
+                write!(f, "Circuit breaker tripped, stability payments paused: {}", reason)
+            }
+            StabilityAction::Suspended { until } => {
+                write!(f, "Stability payments paused by the circuit breaker for {}s more", (until - unix_now()).max(0))
+            }
         }
+    }
+}
+
+pub fn check_stability<N: LightningOps + ?Sized>(node: &N, sc: &mut StableChannel, price: f64) -> StabilityAction {
+    let Some(current_price) = valid_price(price).or_else(|| valid_price(get_cached_price_in(sc.currency))) else {
+        return StabilityAction::NoPrice;
     };
-    
-    // Update the price in the stable channel
+
+    // A price that jumped since the last check, or that the feeds disagree on, pauses
+    // payments rather than paying out a gap that may not be real
+    let now = unix_now();
+    let tripped = circuit_breaker_reason(sc, current_price, &get_price_stats_in(sc.currency));
+    let previous_price = sc.latest_price;
+    if let Some(reason) = &tripped {
+        sc.suspended_until = Some(now + CIRCUIT_BREAKER_HOLD_SECS);
+        sc.suspension_reason = Some(reason.clone());
+        sc.pending_approval_msats = None;
+        sc.payment_approved = false;
+    } else if sc.suspended_until.map_or(false, |until| until <= now) {
+        info!(channel_id = %sc.channel_id, "Circuit breaker hold expired; resuming stability payments");
+        resume(sc);
+    }
+
+    // Update the price in the stable channel; balances are valued at the TWAP so a
+    // single bad tick doesn't move sats
     sc.latest_price = current_price;
+    // A simulated price applies at once, and never enters the history the TWAP is built from
+    sc.twap_price = if price_feeds::is_simulated(sc.currency) {
+        current_price
+    } else {
+        twap(&sc.prices, unix_now(), sc.twap_window_secs).unwrap_or(current_price)
+    };
 
     // Get updated balances with the current price
-    let (success, _) = update_balances(node, sc);
-    
-    if success {
-        println!("Channel balances updated successfully");
-    } else {
-        println!("Failed to update channel balances");
+    update_balances(node, sc);
+
+    let channel = node.list_channels().into_iter().find(|c| c.channel_id == sc.channel_id);
+    sc.risk_level = compute_risk_level(&get_price_stats_in(sc.currency), channel.as_ref(), sc.consecutive_failures);
+    sc.runway = channel.as_ref().and_then(|c| channel_runway(sc, c));
+
+    if let Some(reason) = tripped {
+        warn!(channel_id = %sc.channel_id, previous_price, price = current_price, "Circuit breaker tripped: {}", reason);
+        return StabilityAction::CircuitBreakerTripped(reason);
     }
-    
-    // Calculate stability
-    let dollars_from_par = sc.stable_receiver_usd - sc.expected_usd;
-    let percent_from_par = ((dollars_from_par / sc.expected_usd) * 100.0).abs();
-    
-    println!("Channel status:");
-    println!("  Expected USD:      {}", sc.expected_usd);
-    println!("  Current user USD:  {}", sc.stable_receiver_usd);
-    println!("  Difference:        ${:.2}", dollars_from_par.0);
-    println!("  Percent from par:  {:.2}%", percent_from_par);
-    println!("  User BTC:          {}", sc.stable_receiver_btc);
-    println!("  LSP USD:           {}", sc.stable_provider_usd);
-    println!("  BTC price:         ${:.2}", sc.latest_price);
-    
-    // Determine action based on criteria
-    let is_receiver_below_expected = sc.stable_receiver_usd < sc.expected_usd;
-    
-    if percent_from_par < 0.1 {
-        println!("\n✓ STABLE: Difference from par less than 0.1%. No action needed.");
-        return;
-    } else if sc.risk_level > 100 {
-        println!("\n⚠ HIGH RISK: Risk level ({}) exceeds threshold. Action suspended.", sc.risk_level);
-        return;
-    } else if (sc.is_stable_receiver && is_receiver_below_expected) || 
-              (!sc.is_stable_receiver && !is_receiver_below_expected) {
-        println!("\n⏱ CHECKING: Balance conditions indicate we should check for payment from counterparty.");
-        if sc.is_stable_receiver {
-            println!("  We are the stable receiver and our balance is below expected.");
+    if let Some(until) = sc.suspended_until {
+        return StabilityAction::Suspended { until };
+    }
+
+    let ParDeviation { dollars_from_par, percent_from_par, receiver_below_par: is_receiver_below_expected } =
+        par_deviation(sc);
+    let Some(owed_msats) = dollars_from_par.to_msats(stability_price(sc)) else {
+        return StabilityAction::NoPrice;
+    };
+    let counterparty_owed = (sc.is_stable_receiver && !is_receiver_below_expected) ||
+                            (!sc.is_stable_receiver && is_receiver_below_expected);
+
+    // What was missed while the counterparty was away is settled by the ordinary payment
+    // below. The deviation comes from live balances, so it already is the net amount owed:
+    // the queue never counts for more, and a price back at par or past it owes nothing.
+    if !sc.missed_settlements.is_empty() {
+        if counterparty_owed && percent_from_par >= threshold_percent() {
+            consume_missed_settlements(sc, missed_settlement_msats(sc).saturating_sub(owed_msats));
         } else {
-            println!("  We are the stable provider and receiver balance is above expected.");
+            info!(channel_id = %sc.channel_id, "Price is back at par; nothing missed is owed any more");
+            sc.missed_settlements.clear();
+        }
+    }
+
+    if percent_from_par < threshold_percent() {
+        sc.pending_approval_msats = None;
+        sc.payment_approved = false;
+        sc.payment_attempt = 0;
+        return StabilityAction::Stable;
+    } else if sc.risk_level > sc.max_risk_level {
+        return StabilityAction::HighRisk(sc.risk_level as u32);
+    } else if (sc.is_stable_receiver && is_receiver_below_expected) ||
+              (!sc.is_stable_receiver && !is_receiver_below_expected) {
+        return StabilityAction::WaitingOnCounterparty;
+    }
+
+    // Don't pay the same deviation twice while a previous payment is unresolved
+    if let Some(payment_id) = sc.pending_payment_id {
+        return StabilityAction::PaymentPending(payment_id);
+    }
+
+    if !node.is_peer_connected(&sc.counterparty) {
+        record_missed_settlement(sc, owed_msats);
+        return StabilityAction::PeerDisconnected;
+    }
+
+    // Respect the minimum interval and the rolling daily budget; these skip the
+    // check rather than fail it. Retrying a failed payment isn't a new payment, so the
+    // interval doesn't hold it back.
+    // A clock stepped back counts as no time passed.
+    let now = unix_now();
+    let since_last_payment = (now - sc.last_payment_timestamp).max(0) as u64;
+    if sc.payment_attempt == 0 && since_last_payment < sc.min_seconds_between_payments {
+        let eligible_in_secs = sc.min_seconds_between_payments.saturating_sub(since_last_payment);
+        return StabilityAction::RateLimited { eligible_in_secs };
+    }
+
+    // The daily budget is in USD whatever the peg's currency
+    prune_daily_payments(sc, now);
+    let usd_per_unit = to_usd_at_cached_price(sc, 1.0);
+    let remaining_budget_usd = sc.max_daily_adjustment_usd - daily_adjustment_usd(sc);
+    if remaining_budget_usd <= 0.0 {
+        let oldest = sc.daily_payments.front().map_or(now, |&(ts, _)| ts);
+        let eligible_in_secs = (oldest + DAY_SECS - now).max(1) as u64;
+        return StabilityAction::RateLimited { eligible_in_secs };
+    }
+
+    // Only payment action remains. Amounts above the cap are either paid in
+    // cap-sized pieces over several checks or held for manual approval.
+    let amount_usd = dollars_from_par.abs();
+    let cap_usd = USD::from_f64(payment_cap_usd(sc));
+    let pay_usd = if amount_usd <= cap_usd || sc.payment_approved {
+        amount_usd
+    } else if sc.approval_required_above_cap {
+        sc.pending_approval_msats = Some(owed_msats);
+        return StabilityAction::NeedsApproval { amount_msats: owed_msats };
+    } else {
+        cap_usd
+    };
+    sc.pending_approval_msats = None;
+    sc.payment_approved = false;
+    let pay_usd = pay_usd.min(USD::from_f64(remaining_budget_usd / usd_per_unit));
+
+    let amt = pay_usd.to_msats(stability_price(sc)).unwrap_or_default();
+    let (amt, fee_msats) = apply_stability_fee(amt, sc.stability_fee_ppm, !sc.is_stable_receiver);
+    send_stability_payment(node, sc, amt, fee_msats, dollars_from_par)
+}
+
+/// Sends `amt` to the counterparty with the signed stability TLVs and marks it in flight
+fn send_stability_payment<N: LightningOps + ?Sized>(
+    node: &N,
+    sc: &mut StableChannel,
+    amt: u64,
+    fee_msats: u64,
+    dollars_from_par: USD,
+) -> StabilityAction {
+    // Only send what the channel can actually carry; the remainder is retried next check.
+    // Clamping after the fee keeps the fee from pushing a payment over capacity.
+    let (amt, fee_msats) = match node
+        .list_channels()
+        .iter()
+        .find(|c| c.channel_id == sc.channel_id)
+        .and_then(|c| clamp_to_spendable(amt, c))
+    {
+        Some(clamped) => (clamped, scale_fee(fee_msats, clamped, amt)),
+        None => return StabilityAction::CannotRebalance,
+    };
+
+    // Failed attempts raise the routing budgets, then send smaller pieces, then give up
+    let policy = payment_retry::policy();
+    let (amt, fee_msats, params) = match policy.plan(sc.payment_attempt, amt, sc.last_failure_no_route) {
+        AttemptPlan::Whole(params) => (amt, fee_msats, params),
+        AttemptPlan::Split { parts, params } => {
+            let piece = amt / parts as u64;
+            info!(parts, piece_msats = piece, "Splitting stability payment after repeated failures");
+            (piece, scale_fee(fee_msats, piece, amt), params)
+        }
+        AttemptPlan::GiveUp => {
+            let attempts = sc.payment_attempt;
+            record_missed_settlement(sc, dollars_from_par.to_msats(stability_price(sc)).unwrap_or_default());
+            sc.payment_attempt = 0;
+            sc.last_failure_no_route = false;
+            return StabilityAction::PaymentFailed(format!(
+                "gave up after {} attempts; queued as a missed settlement",
+                attempts
+            ));
         }
+    };
+
+    let info = StabilityPaymentInfo {
+        channel_id: sc.channel_id,
+        target_usd: sc.expected_usd.to_f64(),
+        price_used: stability_price(sc),
+        deviation_usd: dollars_from_par.to_f64(),
+        fee_msats,
+    };
+    let encoded = info.encode();
+    let signature = node.sign_message(&encoded);
+    let records = vec![
+        CustomTlvRecord { type_num: STABILITY_PAYMENT_TLV_TYPE, value: encoded },
+        CustomTlvRecord { type_num: STABILITY_SIGNATURE_TLV_TYPE, value: signature.into_bytes() },
+    ];
+
+    let now = unix_now();
+    match node.spontaneous_payment_send(amt, sc.counterparty, Some(params), records) {
+        Ok(payment_id) => {
+            sc.pending_payment_id = Some(payment_id);
+            sc.pending_payment_msats = amt;
+            sc.pending_fee_msats = fee_msats;
+            sc.last_payment_timestamp = now;
+            let paid = Bitcoin::from_msats(amt).to_btc() * stability_price(sc);
+            sc.daily_payments.push_back((now, to_usd_at_cached_price(sc, paid)));
+            StabilityAction::Paid { amount_msats: amt, payment_id }
+        },
+        Err(e) => {
+            sc.consecutive_failures += 1;
+            sc.payment_attempt += 1;
+            sc.last_failure_no_route = e == NodeError::PaymentSendingFailed;
+            StabilityAction::PaymentFailed(e.to_string())
+        },
+    }
+}
+
+/// Total owed to the counterparty from stability payments missed while it was unreachable
+pub fn missed_settlement_msats(sc: &StableChannel) -> u64 {
+    sc.missed_settlements.iter().map(|m| m.amount_msats).sum()
+}
+
+/// USD value of the missed settlements, at the prices they were missed at
+pub fn missed_settlement_usd(sc: &StableChannel) -> f64 {
+    sc.missed_settlements.iter().map(|m| m.owed_usd).sum()
+}
+
+/// Queues whatever part of `owed_msats` isn't queued already. Repeated checks during
+/// one outage only add to the queue when the deviation grows.
+pub fn record_missed_settlement(sc: &mut StableChannel, owed_msats: u64) {
+    let increase = owed_msats.saturating_sub(missed_settlement_msats(sc));
+    if increase == 0 {
         return;
     }
-    
-    // Only payment action remains
-    println!("\n💸 PAYING: Sending payment to maintain stability.");
+
+    let price = stability_price(sc);
+    sc.missed_settlements.push_back(MissedSettlement {
+        timestamp: unix_now(),
+        amount_msats: increase,
+        owed_usd: Bitcoin::from_msats(increase).to_btc() * price,
+        price,
+    });
+    info!("Counterparty unreachable; queued {} msats for settlement on reconnect", increase);
+}
+
+/// The in-flight stability payment without the stability fee it carries
+fn pending_owed_msats(sc: &StableChannel) -> u64 {
     if sc.is_stable_receiver {
-        println!("  We are the stable receiver and our balance is above expected.");
+        sc.pending_payment_msats.saturating_sub(sc.pending_fee_msats)
     } else {
-        println!("  We are the stable provider and receiver balance is below expected.");
+        sc.pending_payment_msats + sc.pending_fee_msats
     }
-    
-    let amt = USD::to_msats(dollars_from_par, sc.latest_price);
-    println!("  Amount to pay:     {} msats (${:.2})", amt, dollars_from_par.0.abs());
-    println!("  Counterparty:      {}", sc.counterparty);
-    
-    match node.spontaneous_payment().send(amt, sc.counterparty, None) {
-        Ok(payment_id) => {
-            println!("✓ Payment sent successfully!");
-            println!("  Payment ID: {}", payment_id);
-            sc.payment_made = true;
-        },
-        Err(e) => println!("✗ Failed to send payment: {}", e),
+}
+
+/// Removes `paid_msats` from the missed settlements, oldest first
+fn consume_missed_settlements(sc: &mut StableChannel, mut paid_msats: u64) {
+    while let Some(front) = sc.missed_settlements.front_mut() {
+        if front.amount_msats > paid_msats {
+            let remaining = front.amount_msats - paid_msats;
+            front.owed_usd *= remaining as f64 / front.amount_msats as f64;
+            front.amount_msats = remaining;
+            return;
+        }
+        paid_msats -= front.amount_msats;
+        sc.missed_settlements.pop_front();
     }
-    
-    println!("=== STABILITY CHECK COMPLETE ===");
 }
 
-// For backward compatibility with other code
-pub fn check_stability_with_price(node: &Node, sc: &mut StableChannel, price: f64) {
-    // Only use provided price if it's valid
-    if price > 0.0 {
-        sc.latest_price = price;
+/// Drops stability payments older than 24 hours from the daily budget
+fn prune_daily_payments(sc: &mut StableChannel, now: i64) {
+    while sc.daily_payments.front().is_some_and(|&(ts, _)| ts <= now - DAY_SECS) {
+        sc.daily_payments.pop_front();
+    }
+}
+
+/// USD value of stability payments sent in the last 24 hours
+pub fn daily_adjustment_usd(sc: &StableChannel) -> f64 {
+    sc.daily_payments.iter().map(|&(_, usd)| usd).sum()
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+/// Appends a fetched price to the channel's history and drops samples past retention
+pub fn record_price(sc: &mut StableChannel, timestamp: i64, price: f64) {
+    if price <= 0.0 || price_feeds::is_simulated(sc.currency) {
+        return;
+    }
+    // Each sample holds until the next, so repeating the latest price adds nothing to
+    // the TWAP and would only make an unchanged channel look changed
+    if sc.prices.back().is_some_and(|&(_, last)| last == price) {
+        return;
+    }
+
+    sc.prices.push_back((timestamp, price));
+    while sc.prices.front().is_some_and(|&(ts, _)| ts < timestamp - PRICE_HISTORY_RETENTION_SECS) {
+        sc.prices.pop_front();
+    }
+}
+
+/// Time-weighted average price over the trailing window. Each sample is held until
+/// the next one, so a brief outlier only counts for as long as it was the latest price.
+pub fn twap(prices: &VecDeque<(i64, f64)>, now: i64, window_secs: u64) -> Option<f64> {
+    let window_start = now - window_secs as i64;
+    let mut weighted_sum = 0.0;
+    let mut total_secs = 0.0;
+
+    for (i, &(ts, price)) in prices.iter().enumerate() {
+        let held_from = ts.max(window_start);
+        let held_until = prices.get(i + 1).map_or(now, |&(next_ts, _)| next_ts).min(now);
+        if held_until > held_from {
+            let secs = (held_until - held_from) as f64;
+            weighted_sum += price * secs;
+            total_secs += secs;
+        }
+    }
+
+    if total_secs > 0.0 {
+        Some(weighted_sum / total_secs)
+    } else {
+        prices.back().map(|&(_, price)| price)
+    }
+}
+
+/// The price stability is measured against: the TWAP once available, otherwise spot
+pub fn stability_price(sc: &StableChannel) -> f64 {
+    if sc.twap_price > 0.0 {
+        sc.twap_price
+    } else {
+        sc.latest_price
+    }
+}
+
+/// Scores how risky it is to keep paying on this channel. Each factor contributes
+/// up to roughly `DEFAULT_MAX_RISK_LEVEL` on its own:
+/// - feed disagreement: 20 points per percent of spread between feeds
+/// - staleness: 1 point per 6 seconds once the price is over 2 minutes old
+/// - volatility: 25 points per percent of standard deviation in recent prices
+/// - depletion: 5 points per percent of our outbound capacity used beyond 80%
+/// - failures: 25 points per consecutive failed payment
+pub fn compute_risk_level(
+    stats: &PriceStats,
+    channel: Option<&ChannelDetails>,
+    consecutive_failures: u32,
+) -> i32 {
+    let spread = stats.spread_percent * 20.0;
+    let staleness = (stats.age_secs.saturating_sub(120) as f64) / 6.0;
+    let volatility = stats.volatility_percent * 25.0;
+
+    let depletion = match channel {
+        Some(c) if c.channel_value_sats > 0 => {
+            let used_percent = 100.0 - c.outbound_capacity_msat as f64 / (c.channel_value_sats * 1000) as f64 * 100.0;
+            (used_percent - 80.0).max(0.0) * 5.0
+        }
+        _ => 0.0,
+    };
+
+    let failures = consecutive_failures as f64 * 25.0;
+
+    (spread + staleness + volatility + depletion + failures).round() as i32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RiskBand {
+    Low,
+    Elevated,
+    Suspended,
+}
+
+/// Buckets the channel's risk level for display
+pub fn risk_band(sc: &StableChannel) -> RiskBand {
+    if sc.risk_level > sc.max_risk_level {
+        RiskBand::Suspended
+    } else if sc.risk_level * 2 > sc.max_risk_level {
+        RiskBand::Elevated
     } else {
-        // Otherwise use cached price
-        let cached_price = get_cached_price();
-        if cached_price > 0.0 {
-            sc.latest_price = cached_price;
+        RiskBand::Low
+    }
+}
+
+/// Why `price` should trip the circuit breaker on `sc`, if it should: it moved more than
+/// the channel allows since the previous check, or the feeds behind it disagree. Forced
+/// prices never trip it.
+pub fn circuit_breaker_reason(sc: &StableChannel, price: f64, stats: &PriceStats) -> Option<String> {
+    if price_feeds::is_simulated(sc.currency) {
+        return None;
+    }
+    if sc.latest_price > 0.0 {
+        let move_percent = (price - sc.latest_price).abs() / sc.latest_price * 100.0;
+        if move_percent > sc.circuit_breaker_percent {
+            return Some(format!(
+                "price moved {:.1}% since the last check ({} to {})",
+                move_percent,
+                sc.currency.format(sc.latest_price),
+                sc.currency.format(price)
+            ));
         }
     }
-    
-    // Call the main implementation
-    check_stability(node, sc, sc.latest_price);
-}
\ No newline at end of file
+    if stats.sources >= 2 && stats.spread_percent > MAX_FEED_SPREAD_PERCENT {
+        return Some(format!("price feeds disagree by {:.1}%", stats.spread_percent));
+    }
+    None
+}
+
+/// Lifts a circuit breaker pause; the next check acts on the price as usual
+pub fn resume(sc: &mut StableChannel) {
+    sc.suspended_until = None;
+    sc.suspension_reason = None;
+}
+
+/// Ends the peg on `sc`: sends one last adjustment if we owe one, bypassing the cap, then
+/// marks the channel unpegged so no further checks run. Returns the final check's outcome.
+pub fn unpeg<N: LightningOps + ?Sized>(node: &N, sc: &mut StableChannel, price: f64) -> StabilityAction {
+    let action = match sc.pending_payment_id {
+        Some(payment_id) => StabilityAction::PaymentPending(payment_id),
+        None => {
+            sc.payment_approved = true;
+            check_stability_and_log(node, sc, price)
+        }
+    };
+    sc.payment_approved = false;
+    sc.pending_approval_msats = None;
+    sc.unpegged_at = Some(unix_now());
+    info!(channel_id = %sc.channel_id, "Stable channel unpegged");
+    action
+}
+
+/// Stops the peg on a channel that was force-closed and records the price it closed at.
+/// No final adjustment is attempted; the channel can no longer carry one.
+pub fn record_force_close(sc: &mut StableChannel, price: f64) {
+    sc.closing_price = Some(if price > 0.0 { price } else { stability_price(sc) });
+    sc.payment_approved = false;
+    sc.pending_approval_msats = None;
+    sc.unpegged_at = Some(unix_now());
+    info!(
+        channel_id = %sc.channel_id,
+        closing_price = sc.closing_price,
+        receiver_usd = sc.stable_receiver_usd.to_f64(),
+        expected_usd = sc.expected_usd.to_f64(),
+        "Stable channel force-closed"
+    );
+}
+
+/// Runs `check_stability` inside a span carrying the channel and price, and logs the outcome
+pub fn check_stability_and_log<N: LightningOps + ?Sized>(node: &N, sc: &mut StableChannel, price: f64) -> StabilityAction {
+    let _span = info_span!("stability_check", channel_id = %sc.channel_id, price).entered();
+
+    let action = check_stability(node, sc, price);
+
+    if action == StabilityAction::NoPrice {
+        warn!("Skipping stability check: no valid price available");
+        return action;
+    }
+
+    let dollars_from_par = dollars_from_par(sc);
+    let percent_from_par = percent_from_par(sc);
+
+    debug!(
+        expected_usd = %sc.expected_usd,
+        receiver_usd = %sc.stable_receiver_usd,
+        provider_usd = %sc.stable_provider_usd,
+        receiver_btc = %sc.stable_receiver_btc,
+        dollars_from_par = dollars_from_par.to_f64(),
+        percent_from_par,
+        spot_price = sc.latest_price,
+        twap_price = stability_price(sc),
+        risk_level = sc.risk_level,
+        "Channel status"
+    );
+
+    match &action {
+        StabilityAction::Stable | StabilityAction::WaitingOnCounterparty => {
+            debug!(percent_from_par, "{}", action);
+        }
+        StabilityAction::Paid { amount_msats, payment_id } => {
+            info!(amount_msats, %payment_id, counterparty = %sc.counterparty, percent_from_par, "Sent stability payment");
+        }
+        StabilityAction::PaymentFailed(e) => {
+            error!(counterparty = %sc.counterparty, error = %e, "Failed to send stability payment");
+        }
+        StabilityAction::HighRisk(_) | StabilityAction::CannotRebalance => {
+            warn!(percent_from_par, "{}", action);
+        }
+        StabilityAction::PeerDisconnected => {
+            warn!(counterparty = %sc.counterparty, percent_from_par, "{}", action);
+        }
+        // Logged where it trips
+        StabilityAction::CircuitBreakerTripped(_) => {}
+        StabilityAction::Suspended { .. } => debug!("{}", action),
+        StabilityAction::NeedsApproval { .. } => {
+            warn!(cap_usd = payment_cap_usd(sc), "{}", action);
+        }
+        StabilityAction::PaymentPending(_) | StabilityAction::RateLimited { .. } => {
+            info!("{}", action);
+        }
+        StabilityAction::NoPrice => {}
+    }
+
+    action
+}
+
+/// How far the receiver's valued balance is from par; shared by the stability check and
+/// the dry-run simulation so the two can't disagree
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParDeviation {
+    pub dollars_from_par: USD,
+    pub percent_from_par: f64,
+    /// The provider owes the receiver; otherwise the receiver owes the provider
+    pub receiver_below_par: bool,
+}
+
+pub fn par_deviation(sc: &StableChannel) -> ParDeviation {
+    ParDeviation {
+        dollars_from_par: dollars_from_par(sc),
+        percent_from_par: percent_from_par(sc),
+        receiver_below_par: sc.stable_receiver_usd < par_usd(sc),
+    }
+}
+
+/// Which side a stability payment would go from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Payer {
+    Provider,
+    Receiver,
+}
+
+/// What a stability check would do at a hypothetical price, without touching the channel
+#[derive(Debug, Clone, Copy)]
+pub struct SimulationResult {
+    pub price: f64,
+    pub receiver_usd: USD,
+    pub par_usd: USD,
+    pub percent_from_par: f64,
+    /// None while within the stability threshold
+    pub payer: Option<Payer>,
+    /// The whole adjustment, including the stability fee
+    pub amount_msats: u64,
+    pub fee_msats: u64,
+    /// Above the per-payment cap, so it would be paid in pieces or held for approval
+    pub exceeds_cap: bool,
+    /// The payer's side of the channel holds the amount
+    pub has_capacity: bool,
+}
+
+/// Dry run of a stability check on a snapshot of `sc` with the price at `price`. Uses the
+/// balances last read from the channel and values them at `price` directly, with no TWAP.
+pub fn simulate_stability(sc: &StableChannel, price: f64) -> SimulationResult {
+    let mut sim = sc.clone();
+    sim.latest_price = price;
+    sim.twap_price = price;
+    sim.stable_receiver_usd = USD::from_bitcoin(sim.stable_receiver_btc, price);
+    sim.stable_provider_usd = USD::from_bitcoin(sim.stable_provider_btc, price);
+
+    let deviation = par_deviation(&sim);
+    let payer = if deviation.percent_from_par < threshold_percent() {
+        None
+    } else if deviation.receiver_below_par {
+        Some(Payer::Provider)
+    } else {
+        Some(Payer::Receiver)
+    };
+
+    let amount_usd = deviation.dollars_from_par.abs();
+    let (amount_msats, fee_msats) = match payer {
+        Some(payer) => {
+            apply_stability_fee(amount_usd.to_msats(price).unwrap_or_default(), sim.stability_fee_ppm, payer == Payer::Provider)
+        }
+        None => (0, 0),
+    };
+    let payer_btc = match payer {
+        Some(Payer::Provider) => sim.stable_provider_btc,
+        Some(Payer::Receiver) | None => sim.stable_receiver_btc,
+    };
+    SimulationResult {
+        price,
+        receiver_usd: sim.stable_receiver_usd,
+        par_usd: par_usd(&sim),
+        percent_from_par: deviation.percent_from_par,
+        payer,
+        amount_msats,
+        fee_msats,
+        exceeds_cap: payer.is_some() && amount_usd > USD::from_f64(payment_cap_usd(&sim)),
+        has_capacity: amount_msats <= payer_btc.to_msats(),
+    }
+}
+
+/// The price below which the whole channel no longer covers the receiver's par, so the
+/// provider can't keep it stable. None if the channel can't cover par at any price.
+pub fn exhaustion_price(sc: &StableChannel) -> Option<f64> {
+    let pegged_btc = sc.stable_receiver_btc.to_btc() + sc.stable_provider_btc.to_btc() - sc.float_btc.to_btc();
+    if pegged_btc <= 0.0 {
+        return None;
+    }
+    Some(sc.expected_usd.to_f64() / pegged_btc)
+}
+
+/// How far the price can move from `price` before keeping the receiver at par would need
+/// more than the paying side holds above its channel reserve. The receiver's par in BTC
+/// is `expected_usd / price` plus `float_btc`: a fall makes the provider pay it up, a rise
+/// makes the receiver pay the difference back. None without a price or target.
+pub fn runway(
+    expected_usd: USD,
+    float_btc: Bitcoin,
+    price: f64,
+    receiver_btc: Bitcoin,
+    provider_btc: Bitcoin,
+    receiver_reserve: Bitcoin,
+    provider_reserve: Bitcoin,
+) -> Option<Runway> {
+    let expected = expected_usd.to_f64();
+    let price = valid_price(price)?;
+    if expected <= 0.0 {
+        return None;
+    }
+
+    // Lowest price the provider can pay down to: par takes the receiver's balance and all
+    // the provider can send
+    let coverable_btc = receiver_btc.to_btc() + (provider_btc - provider_reserve).to_btc() - float_btc.to_btc();
+    let down_percent = if coverable_btc > 0.0 { (1.0 - expected / coverable_btc / price) * 100.0 } else { 0.0 };
+
+    // Highest price the receiver can pay up to: par leaves the receiver only its reserve.
+    // Par never drops below the floating part, so a reserve covered by it is never reached.
+    let up_percent = match receiver_reserve.to_btc() - float_btc.to_btc() {
+        pegged_reserve_btc if pegged_reserve_btc > 0.0 => Some((expected / pegged_reserve_btc / price - 1.0) * 100.0),
+        _ => None,
+    };
+
+    Some(Runway { down_percent: down_percent.max(0.0), up_percent: up_percent.map(|up| up.max(0.0)) })
+}
+
+/// `runway` of `sc` at its stability price, with the reserves of `channel`
+pub fn channel_runway(sc: &StableChannel, channel: &ChannelDetails) -> Option<Runway> {
+    let ours = Bitcoin::from_sats(channel.unspendable_punishment_reserve.unwrap_or(0));
+    let theirs = Bitcoin::from_sats(channel.counterparty_unspendable_punishment_reserve);
+    let (receiver_reserve, provider_reserve) = if sc.is_stable_receiver { (ours, theirs) } else { (theirs, ours) };
+    runway(
+        sc.expected_usd,
+        sc.float_btc,
+        stability_price(sc),
+        sc.stable_receiver_btc,
+        sc.stable_provider_btc,
+        receiver_reserve,
+        provider_reserve,
+    )
+}
+
+/// "-35.2% / +80.1%", the falls and rises a channel can pay for; "∞" for an unbounded rise
+pub fn format_runway(runway: &Runway) -> String {
+    match runway.up_percent {
+        Some(up) => format!("-{:.1}% / +{:.1}%", runway.down_percent, up),
+        None => format!("-{:.1}% / +∞", runway.down_percent),
+    }
+}
+
+/// Where a stable channel whose id vanished without closing may have gone
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Replacement {
+    None,
+    /// The counterparty's only other channel, at about the same value
+    Found(ChannelId),
+    /// Channels it may be, for the operator to confirm
+    Ambiguous(Vec<ChannelId>),
+}
+
+/// Whether `sc`'s channel id is gone from `channels` though it was neither closed nor
+/// unpegged, as after a splice
+pub fn has_vanished(sc: &StableChannel, channels: &[ChannelDetails]) -> bool {
+    !sc.awaiting_reattach
+        && !sc.close_seen
+        && sc.unpegged_at.is_none()
+        && !channels.iter().any(|c| c.channel_id == sc.channel_id)
+}
+
+/// The channel that replaced `sc`'s among `channels`, skipping those already `designated`.
+/// Only a lone ready channel with the same counterparty, within
+/// `REATTACH_VALUE_TOLERANCE_PERCENT` of the balances last read, is taken without asking.
+pub fn find_replacement(sc: &StableChannel, channels: &[ChannelDetails], designated: &[ChannelId]) -> Replacement {
+    let candidates: Vec<&ChannelDetails> = channels
+        .iter()
+        .filter(|c| c.counterparty_node_id == sc.counterparty && c.is_channel_ready && !designated.contains(&c.channel_id))
+        .collect();
+    let held_sats = (sc.stable_receiver_btc.to_sats() + sc.stable_provider_btc.to_sats()) as f64;
+    let similar_value = |c: &ChannelDetails| {
+        held_sats > 0.0
+            && (c.channel_value_sats as f64 - held_sats).abs() / held_sats * 100.0 <= REATTACH_VALUE_TOLERANCE_PERCENT
+    };
+    match candidates.as_slice() {
+        [] => Replacement::None,
+        [only] if similar_value(only) => Replacement::Found(only.channel_id),
+        _ => Replacement::Ambiguous(candidates.iter().map(|c| c.channel_id).collect()),
+    }
+}
+
+/// Signed difference between the stable receiver's USD balance and the target
+pub fn dollars_from_par(sc: &StableChannel) -> USD {
+    sc.stable_receiver_usd - par_usd(sc)
+}
+
+/// What the stable receiver should hold: the pegged target plus the floating BTC at the
+/// stability price
+pub fn par_usd(sc: &StableChannel) -> USD {
+    sc.expected_usd + USD::from_bitcoin(sc.float_btc, stability_price(sc))
+}
+
+/// The part of `receiver_btc` left floating when `stable_fraction` percent of it is pegged
+pub fn float_btc_for(receiver_btc: Bitcoin, stable_fraction: f64) -> Bitcoin {
+    let floating = (100.0 - stable_fraction.clamp(0.0, 100.0)) / 100.0;
+    Bitcoin::from_msats((receiver_btc.to_msats() as f64 * floating).round() as u64)
+}
+
+/// How far the stable receiver's balance is from par, as an absolute percentage of the
+/// pegged target
+pub fn percent_from_par(sc: &StableChannel) -> f64 {
+    if sc.expected_usd == USD::default() {
+        return 0.0;
+    }
+    ((dollars_from_par(sc) / sc.expected_usd) * 100.0).abs()
+}
+
+/// Totals across a provider's stable channels
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StableExposure {
+    /// Sum of the channels' targets
+    pub target_usd: f64,
+    /// Sum of what the stable receivers currently hold
+    pub receiver_usd: f64,
+    /// BTC the provider would have to pay out for every receiver to stay at target if the
+    /// price halved
+    pub worst_case_btc: f64,
+}
+
+/// Sums the channels' liabilities in USD, converting other pegs at `usd_price` over the
+/// channel's own BTC price
+pub fn stable_exposure(channels: &[StableChannel], usd_price: f64) -> StableExposure {
+    let mut exposure = StableExposure::default();
+    for sc in channels.iter().filter(|sc| sc.unpegged_at.is_none()) {
+        exposure.target_usd += to_usd(sc, sc.expected_usd.to_f64(), usd_price);
+        exposure.receiver_usd += to_usd(sc, sc.stable_receiver_usd.to_f64(), usd_price);
+        let price = if sc.currency == Currency::USD { usd_price } else { stability_price(sc) };
+        if let Some(price) = valid_price(price) {
+            let needed_btc = sc.expected_usd.to_f64() / (price / 2.0) + sc.float_btc.to_btc();
+            exposure.worst_case_btc += (needed_btc - sc.stable_receiver_btc.to_btc()).max(0.0);
+        }
+    }
+    exposure
+}
+
+/// `amount` in the channel's currency, valued in USD
+pub fn to_usd(sc: &StableChannel, amount: f64, usd_price: f64) -> f64 {
+    fiat_to_usd(amount, sc.currency, stability_price(sc), usd_price)
+}
+
+/// `amount` in the channel's currency, valued in USD at the cached BTC/USD price. USD pegs
+/// need no price, so they never fetch one.
+fn to_usd_at_cached_price(sc: &StableChannel, amount: f64) -> f64 {
+    if sc.currency == Currency::USD {
+        return amount;
+    }
+    to_usd(sc, amount, get_cached_price_in(Currency::USD))
+}
+
+/// `amount` of `currency` valued in USD through the BTC price in each. Without both
+/// prices the amount is taken as-is.
+pub fn fiat_to_usd(amount: f64, currency: Currency, btc_price: f64, usd_price: f64) -> f64 {
+    match (valid_price(btc_price), valid_price(usd_price)) {
+        (Some(btc_price), Some(usd_price)) if currency != Currency::USD => amount * usd_price / btc_price,
+        _ => amount,
+    }
+}
+
+/// Total stable liability once a designation of `expected_usd` replaces one currently
+/// worth `replaced_usd` (zero for a new designation)
+pub fn total_after_designation(current_total_usd: f64, replaced_usd: f64, expected_usd: f64) -> f64 {
+    current_total_usd - replaced_usd + expected_usd
+}
+
+/// Applies the provider's stability fee to a payment of `amount_msats`. The provider pays
+/// the amount less the fee; the receiver pays the amount plus the fee. Returns the amount
+/// to send and the fee within it, rounding the fee down.
+pub fn apply_stability_fee(amount_msats: u64, fee_ppm: u32, provider_pays: bool) -> (u64, u64) {
+    let fee_msats = (amount_msats as u128 * fee_ppm as u128 / 1_000_000) as u64;
+    if provider_pays {
+        (amount_msats - fee_msats.min(amount_msats), fee_msats.min(amount_msats))
+    } else {
+        (amount_msats.saturating_add(fee_msats), fee_msats)
+    }
+}
+
+/// The fee on a payment cut from `original_msats` down to `clamped_msats`, in proportion
+fn scale_fee(fee_msats: u64, clamped_msats: u64, original_msats: u64) -> u64 {
+    if clamped_msats >= original_msats || original_msats == 0 {
+        return fee_msats;
+    }
+    (fee_msats as u128 * clamped_msats as u128 / original_msats as u128) as u64
+}
+
+/// The largest stability payment allowed without approval, in USD
+pub fn payment_cap_usd(sc: &StableChannel) -> f64 {
+    let percent_cap = sc.expected_usd.to_f64() * sc.max_payment_percent / 100.0;
+    sc.max_payment_usd.min(percent_cap)
+}
+
+/// Clamps a payment to what the channel can currently send in a single HTLC.
+/// Returns `None` if not even a partial payment is possible.
+pub fn clamp_to_spendable(amount_msats: u64, channel: &ChannelDetails) -> Option<u64> {
+    let spendable_msats = channel.next_outbound_htlc_limit_msat.min(channel.outbound_capacity_msat);
+    let amount = amount_msats.min(spendable_msats);
+
+    if amount == 0 || amount < channel.next_outbound_htlc_minimum_msat {
+        None
+    } else {
+        Some(amount)
+    }
+}
+
+/// Clears the in-flight payment once its `PaymentSuccessful` or `PaymentFailed` event arrives.
+/// Returns true if the payment belonged to this channel.
+pub fn resolve_pending_payment(sc: &mut StableChannel, payment_id: &PaymentId) -> bool {
+    if sc.pending_payment_id.as_ref() == Some(payment_id) {
+        sc.pending_payment_id = None;
+        sc.pending_payment_msats = 0;
+        sc.pending_fee_msats = 0;
+        true
+    } else {
+        false
+    }
+}
+
+/// Adds a settled stability payment to the channel's running totals.
+/// Returns the stability fee it carried if the payment belonged to this channel.
+pub fn record_successful_payment(sc: &mut StableChannel, payment_id: &PaymentId) -> Option<u64> {
+    let amount_msats = sc.pending_payment_msats;
+    let fee_msats = sc.pending_fee_msats;
+    let owed_msats = pending_owed_msats(sc);
+    if !resolve_pending_payment(sc, payment_id) {
+        return None;
+    }
+
+    // While anything is queued, stability payments go out as settlements of the queue
+    consume_missed_settlements(sc, owed_msats);
+
+    // The stable receiver pays the provider when BTC rises, and vice versa
+    if sc.is_stable_receiver {
+        sc.total_paid_to_lsp_msats += amount_msats;
+    } else {
+        sc.total_paid_to_user_msats += amount_msats;
+    }
+    move_native_btc(sc, sc.is_stable_receiver, amount_msats);
+    sc.payment_count += 1;
+    sc.consecutive_failures = 0;
+    sc.last_payment = Some(PaymentRecord {
+        id: *payment_id,
+        amount_msats,
+        direction: PaymentDirection::Sent,
+        time: unix_now(),
+    });
+    // A piece of a split payment succeeding keeps the rest going out in pieces
+    if sc.payment_attempt <= payment_retry::policy().max_retries {
+        sc.payment_attempt = 0;
+        sc.last_failure_no_route = false;
+    }
+    sc.accrued_fee_msats += fee_msats;
+    Some(fee_msats)
+}
+
+/// Clears the in-flight payment after a `PaymentFailed` event and counts the failure
+/// towards the channel's risk level and its retry policy. The failed payment moved
+/// nothing, so it comes off the daily budget. Returns true if the payment belonged to
+/// this channel, in which case the caller should check the channel again to retry.
+pub fn record_failed_payment(
+    sc: &mut StableChannel,
+    payment_id: &PaymentId,
+    reason: Option<PaymentFailureReason>,
+) -> bool {
+    if !resolve_pending_payment(sc, payment_id) {
+        return false;
+    }
+
+    if sc.daily_payments.back().map_or(false, |&(ts, _)| ts == sc.last_payment_timestamp) {
+        sc.daily_payments.pop_back();
+    }
+    sc.consecutive_failures += 1;
+    sc.payment_attempt += 1;
+    sc.last_failure_no_route = reason == Some(PaymentFailureReason::RouteNotFound);
+    true
+}
+
+/// A stable channel's in-flight payment, settled or failed by a payment event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaymentResolution {
+    /// The payment to `channels[index]` settled, carrying this stability fee
+    Succeeded { index: usize, fee_msats: u64 },
+    /// The payment to `channels[index]` failed; check the channel again to retry
+    Failed { index: usize },
+}
+
+/// Applies a `PaymentSuccessful` or `PaymentFailed` event to the channel in `channels` that
+/// was waiting on the payment. Other events, and payments no channel was waiting on, resolve
+/// nothing.
+pub fn resolve_payment_event(channels: &mut [StableChannel], event: &Event) -> Option<PaymentResolution> {
+    match event {
+        Event::PaymentSuccessful { payment_id: Some(id), .. } => {
+            channels.iter_mut().enumerate().find_map(|(index, sc)| {
+                record_successful_payment(sc, id).map(|fee_msats| PaymentResolution::Succeeded { index, fee_msats })
+            })
+        }
+        Event::PaymentFailed { payment_id: Some(id), reason, .. } => channels
+            .iter_mut()
+            .position(|sc| record_failed_payment(sc, id, *reason))
+            .map(|index| PaymentResolution::Failed { index }),
+        _ => None,
+    }
+}
+
+/// Adds a stability payment received from the counterparty, and the fee it carried, to the
+/// channel's running totals
+pub fn record_received_payment(sc: &mut StableChannel, payment_id: Option<PaymentId>, amount_msats: u64, fee_msats: u64) {
+    if sc.is_stable_receiver {
+        sc.total_paid_to_user_msats += amount_msats;
+    } else {
+        sc.total_paid_to_lsp_msats += amount_msats;
+    }
+    move_native_btc(sc, !sc.is_stable_receiver, amount_msats);
+    sc.payment_count += 1;
+    sc.accrued_fee_msats += fee_msats;
+    if let Some(id) = payment_id {
+        sc.last_payment = Some(PaymentRecord { id, amount_msats, direction: PaymentDirection::Received, time: unix_now() });
+    }
+}
+
+/// Moves a settled stability payment into or out of the provider's BTC leg
+fn move_native_btc(sc: &mut StableChannel, to_provider: bool, amount_msats: u64) {
+    let msats = if to_provider {
+        sc.native_btc.msats + amount_msats
+    } else {
+        sc.native_btc.msats.saturating_sub(amount_msats)
+    };
+    sc.native_btc = Bitcoin::from_msats(msats);
+}
+
+/// BTC the provider has gained (positive) or given up (negative) providing stability on
+/// this channel since it was designated, fees included
+pub fn provider_pnl_btc(sc: &StableChannel) -> f64 {
+    sc.native_btc.to_btc() - sc.native_btc_original.to_btc()
+}
+
+/// Extracts the stability adjustment metadata from a received payment, if present
+pub fn read_stability_payment(custom_records: &[CustomTlvRecord]) -> Option<StabilityPaymentInfo> {
+    custom_records
+        .iter()
+        .find(|r| r.type_num == STABILITY_PAYMENT_TLV_TYPE)
+        .and_then(|r| StabilityPaymentInfo::decode(&r.value))
+}
+
+/// Checks that a received payment tagged as a stability adjustment for `sc` really is one:
+/// signed by the channel's counterparty, and not far larger than the deviation we see.
+/// Anything failing these checks should be booked as an ordinary payment.
+pub fn verify_stability_payment<N: LightningOps + ?Sized>(
+    node: &N,
+    sc: &StableChannel,
+    custom_records: &[CustomTlvRecord],
+    amount_msats: u64,
+) -> Result<StabilityPaymentInfo, String> {
+    let record = custom_records
+        .iter()
+        .find(|r| r.type_num == STABILITY_PAYMENT_TLV_TYPE)
+        .ok_or("not tagged as a stability adjustment")?;
+    let info = StabilityPaymentInfo::decode(&record.value).ok_or("malformed stability adjustment record")?;
+    if info.channel_id != sc.channel_id {
+        return Err(format!("tagged for channel {}", info.channel_id));
+    }
+
+    let signature = custom_records
+        .iter()
+        .find(|r| r.type_num == STABILITY_SIGNATURE_TLV_TYPE)
+        .and_then(|r| String::from_utf8(r.value.clone()).ok())
+        .ok_or("unsigned")?;
+    if !node.verify_signature(&record.value, &signature, &sc.counterparty) {
+        return Err(format!("not signed by counterparty {}", sc.counterparty));
+    }
+
+    if sc.latest_price > 0.0 {
+        let amount_usd = Bitcoin::from_msats(amount_msats).to_btc() * sc.latest_price;
+        let bound_usd = dollars_from_par(sc).abs().to_f64() * ADJUSTMENT_DEVIATION_FACTOR + ADJUSTMENT_SLACK_USD;
+        if amount_usd > bound_usd {
+            return Err(format!("${:.2} is more than the ${:.2} deviation allows", amount_usd, bound_usd));
+        }
+    }
+
+    Ok(info)
+}
+
+/// Signs a negotiation message with our node key and sends it to the counterparty
+pub fn send_stable_message(
+    node: &Node,
+    counterparty: PublicKey,
+    message: StableMessage,
+) -> Result<PaymentId, NodeError> {
+    let signature = node.sign_message(&message.encode());
+    let envelope = StableMessageEnvelope { sender: node.node_id(), message, signature };
+    let record = CustomTlvRecord { type_num: STABLE_MESSAGE_TLV_TYPE, value: envelope.encode() };
+    node.spontaneous_payment().send_with_custom_tlvs(
+        STABLE_MESSAGE_AMOUNT_MSAT,
+        counterparty,
+        None,
+        vec![record],
+    )
+}
+
+/// Extracts a negotiation message from a received payment, if present and validly signed
+pub fn read_stable_message(node: &Node, custom_records: &[CustomTlvRecord]) -> Option<StableMessageEnvelope> {
+    let record = custom_records.iter().find(|r| r.type_num == STABLE_MESSAGE_TLV_TYPE)?;
+    let envelope = StableMessageEnvelope::decode(&record.value)?;
+
+    if node.verify_signature(&envelope.message.encode(), &envelope.signature, &envelope.sender) {
+        Some(envelope)
+    } else {
+        warn!("Ignoring stable message with invalid signature from {}", envelope.sender);
+        None
+    }
+}
+
+// For backward compatibility with other code
+pub fn check_stability_with_price<N: LightningOps + ?Sized>(node: &N, sc: &mut StableChannel, price: f64) -> StabilityAction {
+    // The check falls back to the cached price itself, and sets `latest_price` only after
+    // the circuit breaker has compared the new price with it
+    check_stability_and_log(node, sc, price)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ldk_node::bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use ldk_node::lightning::ln::types::PaymentHash;
+    use ldk_node::lightning::util::message_signing;
+    use ldk_node::UserChannelId;
+    use std::cell::RefCell;
+
+    const PRICE: f64 = 50_000.0;
+    const CHANNEL_SATS: u64 = 1_000_000;
+
+    /// A node with one channel to the default counterparty, which records what it sends
+    struct MockNode {
+        channels: Vec<ChannelDetails>,
+        connected: bool,
+        sent: RefCell<Vec<u64>>,
+    }
+
+    impl MockNode {
+        fn new(our_sats: u64) -> Self {
+            Self { channels: vec![channel(our_sats)], connected: true, sent: RefCell::new(Vec::new()) }
+        }
+    }
+
+    impl LightningOps for MockNode {
+        fn list_channels(&self) -> Vec<ChannelDetails> {
+            self.channels.clone()
+        }
+
+        fn spontaneous_payment_send(
+            &self,
+            amount_msat: u64,
+            _node_id: PublicKey,
+            _sending_parameters: Option<SendingParameters>,
+            _custom_tlvs: Vec<CustomTlvRecord>,
+        ) -> Result<PaymentId, NodeError> {
+            self.sent.borrow_mut().push(amount_msat);
+            Ok(PaymentId([7; 32]))
+        }
+
+        fn sign_message(&self, msg: &[u8]) -> String {
+            message_signing::sign(msg, &secret_key(1))
+        }
+
+        fn verify_signature(&self, msg: &[u8], signature: &str, public_key: &PublicKey) -> bool {
+            message_signing::verify(msg, signature, public_key)
+        }
+
+        fn is_peer_connected(&self, _node_id: &PublicKey) -> bool {
+            self.connected
+        }
+    }
+
+    fn secret_key(byte: u8) -> SecretKey {
+        SecretKey::from_slice(&[byte; 32]).unwrap()
+    }
+
+    /// A channel with the default counterparty in which we hold `our_sats`
+    fn channel(our_sats: u64) -> ChannelDetails {
+        let their_sats = CHANNEL_SATS - our_sats;
+        ChannelDetails {
+            channel_id: ChannelId::from_bytes([1; 32]),
+            counterparty_node_id: StableChannel::default().counterparty,
+            funding_txo: None,
+            channel_type: None,
+            channel_value_sats: CHANNEL_SATS,
+            unspendable_punishment_reserve: None,
+            user_channel_id: UserChannelId(1),
+            feerate_sat_per_1000_weight: 1_000,
+            outbound_capacity_msat: our_sats * 1000,
+            inbound_capacity_msat: their_sats * 1000,
+            confirmations_required: None,
+            confirmations: Some(6),
+            is_outbound: false,
+            is_channel_ready: true,
+            is_usable: true,
+            is_announced: false,
+            cltv_expiry_delta: None,
+            counterparty_unspendable_punishment_reserve: 0,
+            counterparty_outbound_htlc_minimum_msat: None,
+            counterparty_outbound_htlc_maximum_msat: None,
+            counterparty_forwarding_info_fee_base_msat: None,
+            counterparty_forwarding_info_fee_proportional_millionths: None,
+            counterparty_forwarding_info_cltv_expiry_delta: None,
+            next_outbound_htlc_limit_msat: our_sats * 1000,
+            next_outbound_htlc_minimum_msat: 1,
+            force_close_spend_delay: None,
+            inbound_htlc_minimum_msat: 1,
+            inbound_htlc_maximum_msat: None,
+            config: Default::default(),
+        }
+    }
+
+    /// A receiver's channel pegged at $100, i.e. 200,000 sats at `PRICE`
+    fn stable_channel() -> StableChannel {
+        StableChannel {
+            channel_id: ChannelId::from_bytes([1; 32]),
+            expected_usd: USD::from_f64(100.0),
+            expected_btc: Bitcoin::from_sats(200_000),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn at_par_is_stable() {
+        let node = MockNode::new(200_000);
+        let mut sc = stable_channel();
+
+        assert_eq!(check_stability(&node, &mut sc, PRICE), StabilityAction::Stable);
+        assert!(node.sent.borrow().is_empty());
+    }
+
+    #[test]
+    fn receiver_below_par_waits_for_the_provider() {
+        let node = MockNode::new(190_000);
+        let mut sc = stable_channel();
+
+        assert_eq!(check_stability(&node, &mut sc, PRICE), StabilityAction::WaitingOnCounterparty);
+        assert!(node.sent.borrow().is_empty());
+    }
+
+    #[test]
+    fn receiver_above_par_pays_the_difference() {
+        let node = MockNode::new(210_000);
+        let mut sc = stable_channel();
+
+        // 10,000 sats above par is $5 at 50,000
+        let action = check_stability(&node, &mut sc, PRICE);
+        assert_eq!(action, StabilityAction::Paid { amount_msats: 10_000_000, payment_id: PaymentId([7; 32]) });
+        assert_eq!(*node.sent.borrow(), vec![10_000_000]);
+        assert_eq!(sc.pending_payment_id, Some(PaymentId([7; 32])));
+    }
+
+    #[test]
+    fn unresolved_payment_is_not_paid_again() {
+        let node = MockNode::new(210_000);
+        let mut sc = stable_channel();
+        sc.pending_payment_id = Some(PaymentId([3; 32]));
+
+        assert_eq!(check_stability(&node, &mut sc, PRICE), StabilityAction::PaymentPending(PaymentId([3; 32])));
+        assert!(node.sent.borrow().is_empty());
+    }
+
+    #[test]
+    fn payment_too_soon_after_the_last_is_rate_limited() {
+        let node = MockNode::new(210_000);
+        let mut sc = stable_channel();
+        sc.last_payment_timestamp = unix_now() - 30;
+
+        let StabilityAction::RateLimited { eligible_in_secs } = check_stability(&node, &mut sc, PRICE) else {
+            panic!("expected the payment to be rate limited");
+        };
+        assert!((89..=90).contains(&eligible_in_secs), "eligible in {}", eligible_in_secs);
+        assert!(node.sent.borrow().is_empty());
+    }
+
+    #[test]
+    fn clock_stepped_back_waits_the_whole_interval() {
+        let node = MockNode::new(210_000);
+        let mut sc = stable_channel();
+        sc.last_payment_timestamp = unix_now() + 3_600;
+
+        assert_eq!(
+            check_stability(&node, &mut sc, PRICE),
+            StabilityAction::RateLimited { eligible_in_secs: DEFAULT_MIN_SECONDS_BETWEEN_PAYMENTS }
+        );
+    }
+
+    #[test]
+    fn retry_is_not_held_back_by_the_interval() {
+        let node = MockNode::new(210_000);
+        let mut sc = stable_channel();
+        sc.last_payment_timestamp = unix_now() - 30;
+        sc.payment_attempt = 1;
+
+        assert!(matches!(check_stability(&node, &mut sc, PRICE), StabilityAction::Paid { .. }));
+    }
+
+    #[test]
+    fn spent_daily_budget_is_rate_limited_until_the_oldest_payment_ages_out() {
+        let node = MockNode::new(210_000);
+        let mut sc = stable_channel();
+        let now = unix_now();
+        sc.daily_payments.push_back((now - 3_600, DEFAULT_MAX_DAILY_ADJUSTMENT_USD));
+
+        let StabilityAction::RateLimited { eligible_in_secs } = check_stability(&node, &mut sc, PRICE) else {
+            panic!("expected the payment to be rate limited");
+        };
+        let expected = (DAY_SECS - 3_600) as u64;
+        assert!((expected - 1..=expected).contains(&eligible_in_secs), "eligible in {}", eligible_in_secs);
+        assert!(node.sent.borrow().is_empty());
+    }
+
+    #[test]
+    fn payment_is_cut_to_the_rest_of_the_daily_budget() {
+        let node = MockNode::new(210_000);
+        let mut sc = stable_channel();
+        sc.daily_payments.push_back((unix_now() - 3_600, DEFAULT_MAX_DAILY_ADJUSTMENT_USD - 2.0));
+
+        // $5 is owed but only $2 of budget is left: 4,000 sats at 50,000
+        let action = check_stability(&node, &mut sc, PRICE);
+        assert_eq!(action, StabilityAction::Paid { amount_msats: 4_000_000, payment_id: PaymentId([7; 32]) });
+        assert!((daily_adjustment_usd(&sc) - DEFAULT_MAX_DAILY_ADJUSTMENT_USD).abs() < 1e-9);
+    }
+
+    #[test]
+    fn payments_older_than_a_day_free_the_budget() {
+        let node = MockNode::new(210_000);
+        let mut sc = stable_channel();
+        sc.daily_payments.push_back((unix_now() - DAY_SECS - 1, DEFAULT_MAX_DAILY_ADJUSTMENT_USD));
+
+        assert_eq!(
+            check_stability(&node, &mut sc, PRICE),
+            StabilityAction::Paid { amount_msats: 10_000_000, payment_id: PaymentId([7; 32]) }
+        );
+        assert_eq!(sc.daily_payments.len(), 1);
+    }
+
+    #[test]
+    fn payment_above_the_cap_is_held_for_approval() {
+        // $30 above par against a $25 cap (25% of $100)
+        let node = MockNode::new(260_000);
+        let mut sc = stable_channel();
+        sc.approval_required_above_cap = true;
+
+        let action = check_stability(&node, &mut sc, PRICE);
+        assert_eq!(action, StabilityAction::NeedsApproval { amount_msats: 60_000_000 });
+        assert_eq!(sc.pending_approval_msats, Some(60_000_000));
+        assert!(node.sent.borrow().is_empty());
+
+        // Approved, the whole amount goes out once and the approval is used up
+        sc.payment_approved = true;
+        let action = check_stability(&node, &mut sc, PRICE);
+        assert_eq!(action, StabilityAction::Paid { amount_msats: 60_000_000, payment_id: PaymentId([7; 32]) });
+        assert_eq!(sc.pending_approval_msats, None);
+        assert!(!sc.payment_approved);
+    }
+
+    #[test]
+    fn payment_within_the_cap_needs_no_approval() {
+        let node = MockNode::new(210_000);
+        let mut sc = stable_channel();
+        sc.approval_required_above_cap = true;
+
+        assert_eq!(
+            check_stability(&node, &mut sc, PRICE),
+            StabilityAction::Paid { amount_msats: 10_000_000, payment_id: PaymentId([7; 32]) }
+        );
+        assert_eq!(sc.pending_approval_msats, None);
+    }
+
+    #[test]
+    fn payment_above_the_cap_without_approval_pays_the_cap() {
+        let node = MockNode::new(260_000);
+        let mut sc = stable_channel();
+
+        // $25 at 50,000 is 50,000 sats; the other $5 waits for the next check
+        assert_eq!(
+            check_stability(&node, &mut sc, PRICE),
+            StabilityAction::Paid { amount_msats: 50_000_000, payment_id: PaymentId([7; 32]) }
+        );
+        assert_eq!(sc.pending_approval_msats, None);
+    }
+
+    #[test]
+    fn price_back_at_par_drops_the_pending_approval() {
+        let mut sc = stable_channel();
+        sc.approval_required_above_cap = true;
+        check_stability(&MockNode::new(260_000), &mut sc, PRICE);
+        sc.payment_approved = true;
+
+        assert_eq!(check_stability(&MockNode::new(200_000), &mut sc, PRICE), StabilityAction::Stable);
+        assert_eq!(sc.pending_approval_msats, None);
+        assert!(!sc.payment_approved);
+    }
+
+    #[test]
+    fn payment_within_spendable_is_not_clamped() {
+        assert_eq!(clamp_to_spendable(10_000_000, &channel(210_000)), Some(10_000_000));
+        assert_eq!(clamp_to_spendable(210_000_000, &channel(210_000)), Some(210_000_000));
+    }
+
+    #[test]
+    fn payment_is_clamped_to_the_smaller_of_htlc_limit_and_capacity() {
+        let mut c = channel(210_000);
+        c.next_outbound_htlc_limit_msat = 5_000_000;
+        assert_eq!(clamp_to_spendable(10_000_000, &c), Some(5_000_000));
+
+        c.next_outbound_htlc_limit_msat = 500_000_000;
+        assert_eq!(clamp_to_spendable(300_000_000, &c), Some(210_000_000));
+    }
+
+    #[test]
+    fn nothing_spendable_cannot_be_clamped() {
+        let mut c = channel(210_000);
+        c.next_outbound_htlc_limit_msat = 0;
+        assert_eq!(clamp_to_spendable(10_000_000, &c), None);
+
+        c.next_outbound_htlc_limit_msat = 5_000_000;
+        c.next_outbound_htlc_minimum_msat = 6_000_000;
+        assert_eq!(clamp_to_spendable(10_000_000, &c), None);
+    }
+
+    #[test]
+    fn stability_payment_sends_only_what_the_channel_can_carry() {
+        let mut node = MockNode::new(210_000);
+        node.channels[0].next_outbound_htlc_limit_msat = 4_000_000;
+        let mut sc = stable_channel();
+
+        assert_eq!(
+            check_stability(&node, &mut sc, PRICE),
+            StabilityAction::Paid { amount_msats: 4_000_000, payment_id: PaymentId([7; 32]) }
+        );
+
+        node.channels[0].next_outbound_htlc_limit_msat = 0;
+        sc.pending_payment_id = None;
+        sc.last_payment_timestamp = 0;
+        assert_eq!(check_stability(&node, &mut sc, PRICE), StabilityAction::CannotRebalance);
+    }
+
+    #[test]
+    fn disconnected_counterparty_is_owed_a_missed_settlement() {
+        let node = MockNode { connected: false, ..MockNode::new(210_000) };
+        let mut sc = stable_channel();
+
+        assert_eq!(check_stability(&node, &mut sc, PRICE), StabilityAction::PeerDisconnected);
+        assert!(node.sent.borrow().is_empty());
+        assert_eq!(missed_settlement_msats(&sc), 10_000_000);
+    }
+
+    /// The TLVs of an adjustment for `sc`'s channel, signed with `signer` unless None
+    fn adjustment_records(sc: &StableChannel, signer: Option<SecretKey>) -> Vec<CustomTlvRecord> {
+        let info = StabilityPaymentInfo {
+            channel_id: sc.channel_id,
+            target_usd: sc.expected_usd.to_f64(),
+            price_used: PRICE,
+            deviation_usd: 5.0,
+            fee_msats: 0,
+        };
+        let encoded = info.encode();
+        let mut records = Vec::new();
+        if let Some(signer) = signer {
+            let signature = message_signing::sign(&encoded, &signer);
+            records.push(CustomTlvRecord { type_num: STABILITY_SIGNATURE_TLV_TYPE, value: signature.into_bytes() });
+        }
+        records.push(CustomTlvRecord { type_num: STABILITY_PAYMENT_TLV_TYPE, value: encoded });
+        records
+    }
+
+    /// A receiver $5 below par, whose counterparty signs with `secret_key(2)`
+    fn owed_channel() -> StableChannel {
+        let mut sc = stable_channel();
+        sc.counterparty = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key(2));
+        sc.latest_price = PRICE;
+        sc.twap_price = PRICE;
+        sc.stable_receiver_btc = Bitcoin::from_sats(190_000);
+        sc.stable_receiver_usd = USD::from_bitcoin(sc.stable_receiver_btc, PRICE);
+        sc
+    }
+
+    #[test]
+    fn signed_adjustment_within_the_deviation_is_accepted() {
+        let node = MockNode::new(190_000);
+        let sc = owed_channel();
+        let records = adjustment_records(&sc, Some(secret_key(2)));
+
+        assert!(verify_stability_payment(&node, &sc, &records, 10_000_000).is_ok());
+    }
+
+    #[test]
+    fn unsigned_adjustment_is_rejected() {
+        let node = MockNode::new(190_000);
+        let sc = owed_channel();
+        let records = adjustment_records(&sc, None);
+
+        assert_eq!(verify_stability_payment(&node, &sc, &records, 10_000_000).unwrap_err(), "unsigned");
+    }
+
+    #[test]
+    fn adjustment_signed_by_another_key_is_rejected() {
+        let node = MockNode::new(190_000);
+        let sc = owed_channel();
+        let records = adjustment_records(&sc, Some(secret_key(3)));
+
+        let err = verify_stability_payment(&node, &sc, &records, 10_000_000).unwrap_err();
+        assert!(err.starts_with("not signed by counterparty"), "{}", err);
+    }
+
+    #[test]
+    fn adjustment_above_the_deviation_bound_is_rejected() {
+        let node = MockNode::new(190_000);
+        let sc = owed_channel();
+        let records = adjustment_records(&sc, Some(secret_key(2)));
+
+        // The bound is twice the $5 deviation plus $1; $20 is past it
+        let err = verify_stability_payment(&node, &sc, &records, 40_000_000).unwrap_err();
+        assert!(err.contains("more than the"), "{}", err);
+    }
+
+    #[test]
+    fn provider_btc_round_trips_through_a_price_cycle() {
+        // We provide stability on a $100 peg, charging 1%; the receiver holds 200,000 sats
+        let mut node = MockNode::new(800_000);
+        let mut sc = StableChannel {
+            is_stable_receiver: false,
+            latest_price: PRICE,
+            stability_fee_ppm: 10_000,
+            native_btc: Bitcoin::from_sats(800_000),
+            native_btc_original: Bitcoin::from_sats(800_000),
+            ..stable_channel()
+        };
+
+        // BTC falls 10%: the receiver is $10 short, and we pay that less our fee
+        let StabilityAction::Paid { amount_msats, payment_id } = check_stability(&node, &mut sc, 45_000.0) else {
+            panic!("provider didn't pay on a price fall");
+        };
+        assert_eq!(amount_msats, 22_000_000);
+        assert_eq!(record_successful_payment(&mut sc, &payment_id), Some(222_222));
+        node.channels = vec![channel(778_000)];
+
+        // BTC recovers: the receiver is $11 over par, and it's the receiver's turn to pay
+        assert_eq!(check_stability(&node, &mut sc, PRICE), StabilityAction::WaitingOnCounterparty);
+        record_received_payment(&mut sc, Some(PaymentId([8; 32])), 22_220_000, 220_000);
+
+        // Our BTC leg is back where it started, plus the fee the receiver paid on the way up
+        assert_eq!(sc.native_btc.to_msats() - sc.native_btc_original.to_msats(), 220_000);
+        assert_eq!(sc.payment_count, 2);
+        assert_eq!(sc.accrued_fee_msats, 222_222 + 220_000);
+    }
+
+    #[test]
+    fn stability_fee_rounds_down() {
+        assert_eq!(apply_stability_fee(999, 1_000, true), (999, 0));
+        assert_eq!(apply_stability_fee(999, 1_000, false), (999, 0));
+        assert_eq!(apply_stability_fee(1_000_000, 2_500, true), (997_500, 2_500));
+        assert_eq!(apply_stability_fee(1_000_000, 2_500, false), (1_002_500, 2_500));
+        assert_eq!(apply_stability_fee(1_999, 500_000, false), (2_998, 999));
+    }
+
+    #[test]
+    fn provider_fee_never_exceeds_the_payment() {
+        assert_eq!(apply_stability_fee(1_000, 2_000_000, true), (0, 1_000));
+        assert_eq!(apply_stability_fee(u64::MAX, 1_000_000, true), (0, u64::MAX));
+        assert_eq!(apply_stability_fee(u64::MAX, 1, false).0, u64::MAX);
+    }
+
+    #[test]
+    fn fee_on_top_never_pushes_a_payment_past_capacity() {
+        let mut c = channel(210_000);
+        for spendable in [1, 999, 10_049_999, 10_050_000, 10_100_000, 210_000_000] {
+            c.next_outbound_htlc_limit_msat = spendable;
+            for fee_ppm in [0, 1, 999, 10_000, 999_999] {
+                let (with_fee, fee) = apply_stability_fee(10_000_000, fee_ppm, false);
+                let Some(sent) = clamp_to_spendable(with_fee, &c) else { continue };
+                assert!(sent <= spendable, "{} sent of {} spendable at {} ppm", sent, spendable, fee_ppm);
+                assert!(scale_fee(fee, sent, with_fee) <= sent);
+            }
+        }
+    }
+
+    #[test]
+    fn receiver_fee_is_cut_with_a_clamped_payment() {
+        let mut node = MockNode::new(210_000);
+        node.channels[0].next_outbound_htlc_limit_msat = 10_050_000;
+        let mut sc = StableChannel { stability_fee_ppm: 10_000, ..stable_channel() };
+
+        // $5 owed plus 1% is 10,100,000 msats, cut to what the channel can send
+        assert_eq!(
+            check_stability(&node, &mut sc, PRICE),
+            StabilityAction::Paid { amount_msats: 10_050_000, payment_id: PaymentId([7; 32]) }
+        );
+        assert_eq!(sc.pending_fee_msats, 99_504);
+    }
+
+    #[test]
+    fn twap_weights_each_price_by_how_long_it_held() {
+        let prices = VecDeque::from(vec![(1_000, 40_000.0), (1_100, PRICE), (1_290, 60_000.0)]);
+
+        // The window opens at 1,000: 100s at 40k, 190s at 50k, 10s at 60k
+        assert_eq!(twap(&prices, 1_300, 300), Some(47_000.0));
+    }
+
+    #[test]
+    fn twap_ignores_what_ended_before_the_window() {
+        let prices = VecDeque::from(vec![(0, 10_000.0), (1_000, PRICE)]);
+
+        assert_eq!(twap(&prices, 1_300, 300), Some(PRICE));
+        assert_eq!(twap(&VecDeque::new(), 1_300, 300), None);
+    }
+
+    #[test]
+    fn single_outlier_inside_the_window_does_not_pay() {
+        let node = MockNode::new(200_000);
+        let mut sc = stable_channel();
+        let now = unix_now();
+        sc.latest_price = PRICE;
+        sc.prices = VecDeque::from(vec![(now - 300, PRICE), (now - 5, 52_000.0)]);
+
+        // At spot a 4% jump would owe $4, but it has held for 5 of the 300 seconds
+        assert_eq!(check_stability(&node, &mut sc, 52_000.0), StabilityAction::Stable);
+        assert!(sc.twap_price < PRICE * 1.001, "twap {}", sc.twap_price);
+        assert!(node.sent.borrow().is_empty());
+    }
+
+    #[test]
+    fn calm_feeds_and_a_full_channel_carry_no_risk() {
+        assert_eq!(compute_risk_level(&PriceStats::default(), Some(&channel(500_000)), 0), 0);
+        assert_eq!(compute_risk_level(&PriceStats { age_secs: 120, ..Default::default() }, None, 0), 0);
+    }
+
+    #[test]
+    fn each_risk_factor_alone_can_pass_the_limit() {
+        let over = |stats: PriceStats, failures: u32| {
+            compute_risk_level(&stats, Some(&channel(500_000)), failures) > DEFAULT_MAX_RISK_LEVEL
+        };
+
+        assert!(over(PriceStats { spread_percent: 6.0, ..Default::default() }, 0));
+        assert!(!over(PriceStats { spread_percent: 4.0, ..Default::default() }, 0));
+        assert!(over(PriceStats { age_secs: 120 + 612, ..Default::default() }, 0));
+        assert!(!over(PriceStats { age_secs: 120 + 588, ..Default::default() }, 0));
+        assert!(over(PriceStats { volatility_percent: 5.0, ..Default::default() }, 0));
+        assert!(!over(PriceStats { volatility_percent: 3.0, ..Default::default() }, 0));
+        assert!(over(PriceStats::default(), 5));
+        assert!(!over(PriceStats::default(), 4));
+    }
+
+    #[test]
+    fn depleted_channel_reaches_the_limit_and_anything_more_passes_it() {
+        let stats = PriceStats::default();
+
+        // Nothing counts until 80% of the channel is spent, then 5 points per percent
+        assert_eq!(compute_risk_level(&stats, Some(&channel(200_000)), 0), 0);
+        assert_eq!(compute_risk_level(&stats, Some(&channel(100_000)), 0), 50);
+        assert_eq!(compute_risk_level(&stats, Some(&channel(0)), 0), DEFAULT_MAX_RISK_LEVEL);
+        assert_eq!(compute_risk_level(&stats, Some(&channel(0)), 1), DEFAULT_MAX_RISK_LEVEL + 25);
+    }
+
+    #[test]
+    fn risk_above_the_limit_holds_the_payment() {
+        let node = MockNode::new(210_000);
+        let mut sc = stable_channel();
+        sc.consecutive_failures = 5;
+
+        assert_eq!(check_stability(&node, &mut sc, PRICE), StabilityAction::HighRisk(125));
+        assert_eq!(risk_band(&sc), RiskBand::Suspended);
+        assert!(node.sent.borrow().is_empty());
+    }
+
+    fn payment_succeeded(id: PaymentId) -> Event {
+        Event::PaymentSuccessful {
+            payment_id: Some(id),
+            payment_hash: PaymentHash([2; 32]),
+            payment_preimage: None,
+            fee_paid_msat: Some(1_000),
+        }
+    }
+
+    fn payment_failed(id: PaymentId, reason: PaymentFailureReason) -> Event {
+        Event::PaymentFailed { payment_id: Some(id), payment_hash: Some(PaymentHash([2; 32])), reason: Some(reason) }
+    }
+
+    #[test]
+    fn failed_then_retried_payment_settles_through_events() {
+        let node = MockNode::new(210_000);
+        let mut channels = vec![StableChannel { channel_id: ChannelId::from_bytes([9; 32]), ..Default::default() }];
+        channels.push(stable_channel());
+        let paid = PaymentId([7; 32]);
+
+        assert!(matches!(check_stability(&node, &mut channels[1], PRICE), StabilityAction::Paid { .. }));
+        let failed = resolve_payment_event(&mut channels, &payment_failed(paid, PaymentFailureReason::RouteNotFound));
+        assert_eq!(failed, Some(PaymentResolution::Failed { index: 1 }));
+        let sc = &channels[1];
+        assert_eq!((sc.pending_payment_id, sc.consecutive_failures, sc.payment_attempt), (None, 1, 1));
+        assert!(sc.last_failure_no_route);
+        assert!(sc.daily_payments.is_empty(), "a failed payment moved nothing");
+
+        // The retry goes out at once, and its success clears the failure streak
+        assert!(matches!(check_stability(&node, &mut channels[1], PRICE), StabilityAction::Paid { .. }));
+        let settled = resolve_payment_event(&mut channels, &payment_succeeded(paid));
+        assert_eq!(settled, Some(PaymentResolution::Succeeded { index: 1, fee_msats: 0 }));
+        let sc = &channels[1];
+        assert_eq!((sc.pending_payment_id, sc.consecutive_failures, sc.payment_attempt), (None, 0, 0));
+        assert_eq!((sc.payment_count, sc.total_paid_to_lsp_msats), (1, 10_000_000));
+
+        // A second event for the same payment finds nothing waiting on it
+        assert_eq!(resolve_payment_event(&mut channels, &payment_succeeded(paid)), None);
+    }
+
+    #[test]
+    fn events_for_other_payments_resolve_nothing() {
+        let node = MockNode::new(210_000);
+        let mut channels = vec![stable_channel()];
+        check_stability(&node, &mut channels[0], PRICE);
+
+        let other = PaymentId([8; 32]);
+        assert_eq!(resolve_payment_event(&mut channels, &payment_failed(other, PaymentFailureReason::RetriesExhausted)), None);
+        assert_eq!(resolve_payment_event(&mut channels, &payment_succeeded(other)), None);
+        assert_eq!(channels[0].pending_payment_id, Some(PaymentId([7; 32])));
+        assert_eq!(channels[0].consecutive_failures, 0);
+    }
+
+    /// A pegged channel of `expected` in `currency` whose receiver holds `receiver_sats`
+    fn liability(currency: Currency, expected: f64, btc_price: f64, receiver_sats: u64) -> StableChannel {
+        let receiver_btc = Bitcoin::from_sats(receiver_sats);
+        StableChannel {
+            currency,
+            expected_usd: USD::from_f64(expected),
+            latest_price: btc_price,
+            stable_receiver_btc: receiver_btc,
+            stable_receiver_usd: USD::from_f64(receiver_btc.to_btc() * btc_price),
+            ..stable_channel()
+        }
+    }
+
+    #[test]
+    fn exposure_sums_pegged_channels_in_usd() {
+        let mut unpegged = liability(Currency::USD, 500.0, PRICE, 1_000_000);
+        unpegged.unpegged_at = Some(1);
+        let channels = vec![
+            liability(Currency::USD, 100.0, PRICE, 200_000),
+            // €90 at 45,000 EUR/BTC against 50,000 USD/BTC is $100
+            liability(Currency::EUR, 90.0, 45_000.0, 200_000),
+            unpegged,
+        ];
+
+        let exposure = stable_exposure(&channels, PRICE);
+        assert!((exposure.target_usd - 200.0).abs() < 1e-9, "{:?}", exposure);
+        assert!((exposure.receiver_usd - 200.0).abs() < 1e-9, "{:?}", exposure);
+    }
+
+    #[test]
+    fn worst_case_is_the_btc_to_keep_targets_at_half_the_price() {
+        // $100 at 25,000 is 400,000 sats, 200,000 more than the receiver holds
+        let channels = vec![liability(Currency::USD, 100.0, PRICE, 200_000)];
+        assert!((stable_exposure(&channels, PRICE).worst_case_btc - 0.002).abs() < 1e-12);
+
+        // A receiver already holding enough needs nothing more
+        let channels = vec![liability(Currency::USD, 100.0, PRICE, 500_000)];
+        assert_eq!(stable_exposure(&channels, PRICE).worst_case_btc, 0.0);
+
+        // Without a price there is nothing to value the BTC at
+        assert_eq!(stable_exposure(&channels, 0.0).worst_case_btc, 0.0);
+    }
+
+    #[test]
+    fn designation_counts_only_the_change_it_makes() {
+        assert_eq!(total_after_designation(400.0, 0.0, 100.0), 500.0);
+        assert_eq!(total_after_designation(400.0, 100.0, 150.0), 450.0);
+        assert_eq!(total_after_designation(400.0, 100.0, 50.0), 350.0);
+        assert_eq!(total_after_designation(0.0, 0.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn foreign_pegs_are_valued_in_usd_only_with_both_prices() {
+        assert!((fiat_to_usd(90.0, Currency::EUR, 45_000.0, PRICE) - 100.0).abs() < 1e-9);
+        assert_eq!(fiat_to_usd(90.0, Currency::EUR, 0.0, PRICE), 90.0);
+        assert_eq!(fiat_to_usd(90.0, Currency::EUR, 45_000.0, f64::NAN), 90.0);
+        assert_eq!(fiat_to_usd(90.0, Currency::USD, 45_000.0, PRICE), 90.0);
+    }
+
+    /// `channel(our_sats)` with 10,000 sat reserves on both sides taken out of what each can send
+    fn reserved_channel(our_sats: u64) -> ChannelDetails {
+        let mut c = channel(our_sats);
+        c.unspendable_punishment_reserve = Some(10_000);
+        c.counterparty_unspendable_punishment_reserve = 10_000;
+        c.outbound_capacity_msat -= 10_000_000;
+        c.inbound_capacity_msat -= 10_000_000;
+        c
+    }
+
+    #[test]
+    fn balances_without_reserves_or_htlcs_split_the_channel() {
+        assert_eq!(channel_balances(&channel(300_000)), (300_000, 700_000));
+        assert_eq!(channel_balances(&channel(0)), (0, CHANNEL_SATS));
+    }
+
+    #[test]
+    fn each_side_keeps_its_own_reserve() {
+        assert_eq!(channel_balances(&reserved_channel(300_000)), (300_000, 700_000));
+        assert_eq!(channel_balances(&reserved_channel(10_000)), (10_000, 990_000));
+    }
+
+    #[test]
+    fn pending_htlcs_count_for_neither_side() {
+        // 50,000 sats on their way out of our side, then 20,000 on their way in
+        let mut c = reserved_channel(300_000);
+        c.outbound_capacity_msat -= 50_000_000;
+        assert_eq!(channel_balances(&c), (250_000, 700_000));
+        c.inbound_capacity_msat -= 20_000_000;
+        assert_eq!(channel_balances(&c), (250_000, 680_000));
+    }
+
+    #[test]
+    fn balances_round_down_to_whole_sats() {
+        let mut c = channel(300_000);
+        c.outbound_capacity_msat += 999;
+        c.inbound_capacity_msat -= 999;
+        assert_eq!(channel_balances(&c), (300_000, 699_999));
+    }
+
+    #[test]
+    fn payment_in_flight_does_not_swing_the_split() {
+        let mut node = MockNode::new(200_000);
+        node.channels = vec![reserved_channel(200_000)];
+        let mut sc = StableChannel { latest_price: PRICE, ..stable_channel() };
+        update_balances(&node, &mut sc);
+        assert_eq!(sc.stable_receiver_btc, Bitcoin::from_sats(200_000));
+        assert_eq!(sc.stable_provider_btc, Bitcoin::from_sats(800_000));
+        assert_eq!(percent_from_par(&sc), 0.0);
+
+        // The counterparty's 30,000 sat payment to us is still an HTLC: it isn't ours yet,
+        // and no longer theirs to spend
+        node.channels[0].inbound_capacity_msat -= 30_000_000;
+        update_balances(&node, &mut sc);
+        assert_eq!(sc.stable_receiver_btc, Bitcoin::from_sats(200_000));
+        assert_eq!(sc.stable_provider_btc, Bitcoin::from_sats(770_000));
+        assert_eq!(percent_from_par(&sc), 0.0);
+
+        // The provider sees the same channel from the other side
+        let mut provider = StableChannel { is_stable_receiver: false, latest_price: PRICE, ..stable_channel() };
+        update_balances(&node, &mut provider);
+        assert_eq!(provider.stable_provider_btc, Bitcoin::from_sats(200_000));
+        assert_eq!(provider.stable_receiver_btc, Bitcoin::from_sats(770_000));
+    }
+
+    /// A channel that owes a payment, checked once at `PRICE` and then at a 25% jump
+    fn tripped(node: &MockNode) -> (StableChannel, StabilityAction) {
+        let mut sc = stable_channel();
+        sc.latest_price = PRICE;
+        let action = check_stability(node, &mut sc, PRICE * 1.25);
+        (sc, action)
+    }
+
+    #[test]
+    fn price_jump_trips_the_circuit_breaker() {
+        let node = MockNode::new(210_000);
+        let (mut sc, action) = tripped(&node);
+
+        let StabilityAction::CircuitBreakerTripped(reason) = action else {
+            panic!("expected the breaker to trip, got {:?}", action);
+        };
+        assert!(reason.contains("moved 25.0%"), "{}", reason);
+        let until = sc.suspended_until.expect("suspended");
+        assert!((until - unix_now() - CIRCUIT_BREAKER_HOLD_SECS).abs() <= 1);
+        assert_eq!(sc.suspension_reason, Some(reason));
+
+        // Checks at the new price stay paused until the hold ends
+        assert_eq!(check_stability(&node, &mut sc, PRICE * 1.25), StabilityAction::Suspended { until });
+        assert!(node.sent.borrow().is_empty());
+    }
+
+    #[test]
+    fn breaker_trips_only_past_its_limits() {
+        let sc = StableChannel { latest_price: PRICE, ..stable_channel() };
+        let calm = PriceStats::default();
+
+        assert_eq!(circuit_breaker_reason(&sc, PRICE * 1.19, &calm), None);
+        assert_eq!(circuit_breaker_reason(&sc, PRICE * 0.81, &calm), None);
+        assert!(circuit_breaker_reason(&sc, PRICE * 1.21, &calm).is_some());
+        assert!(circuit_breaker_reason(&sc, PRICE * 0.79, &calm).is_some());
+
+        // The first check has no previous price to compare with
+        assert_eq!(circuit_breaker_reason(&stable_channel(), PRICE * 10.0, &calm), None);
+    }
+
+    #[test]
+    fn feeds_disagreeing_trip_the_breaker() {
+        let sc = StableChannel { latest_price: PRICE, ..stable_channel() };
+        let split = PriceStats { sources: 2, spread_percent: 6.0, ..Default::default() };
+
+        let reason = circuit_breaker_reason(&sc, PRICE, &split).unwrap();
+        assert!(reason.contains("disagree by 6.0%"), "{}", reason);
+        assert_eq!(circuit_breaker_reason(&sc, PRICE, &PriceStats { sources: 1, ..split }), None);
+        assert_eq!(circuit_breaker_reason(&sc, PRICE, &PriceStats { spread_percent: 5.0, ..split }), None);
+    }
+
+    #[test]
+    fn suspension_survives_a_restart() {
+        let node = MockNode::new(210_000);
+        let (sc, _) = tripped(&node);
+
+        let json = serde_json::to_string(&StableChannelEntry::new(&sc)).unwrap();
+        let entry: StableChannelEntry = serde_json::from_str(&json).unwrap();
+        let mut restored = stable_channel();
+        entry.restore_into(&mut restored);
+        restored.latest_price = PRICE * 1.25;
+
+        assert_eq!(restored.suspended_until, sc.suspended_until);
+        assert_eq!(restored.suspension_reason, sc.suspension_reason);
+        assert_eq!(
+            check_stability(&node, &mut restored, PRICE * 1.25),
+            StabilityAction::Suspended { until: sc.suspended_until.unwrap() }
+        );
+        assert!(node.sent.borrow().is_empty());
+    }
+
+    #[test]
+    fn manual_resume_pays_at_the_new_price() {
+        let node = MockNode::new(210_000);
+        let (mut sc, _) = tripped(&node);
+
+        resume(&mut sc);
+        assert_eq!((sc.suspended_until, sc.suspension_reason.as_deref()), (None, None));
+        // $31.25 over par at 62,500 is capped at $25, 40,000 sats
+        assert_eq!(
+            check_stability(&node, &mut sc, PRICE * 1.25),
+            StabilityAction::Paid { amount_msats: 40_000_000, payment_id: PaymentId([7; 32]) }
+        );
+    }
+
+    #[test]
+    fn expired_hold_resumes_by_itself() {
+        let node = MockNode::new(210_000);
+        let mut sc = stable_channel();
+        sc.suspended_until = Some(unix_now() - 1);
+        sc.suspension_reason = Some("price moved 25.0% since the last check".to_string());
+
+        assert!(matches!(check_stability(&node, &mut sc, PRICE), StabilityAction::Paid { .. }));
+        assert_eq!(sc.suspension_reason, None);
+    }
+
+    #[test]
+    fn no_op_checks_do_not_rewrite_the_file() {
+        let path = std::env::temp_dir().join(format!("stable-channels-no-op-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut last_written = None;
+        let mut check_and_save = |node: &MockNode, sc: &mut StableChannel| {
+            record_price(sc, unix_now(), PRICE);
+            let action = check_stability(node, sc, PRICE);
+            let json = serde_json::to_string_pretty(&[StableChannelEntry::new(sc)]).unwrap();
+            let written = crate::encryption::write_state_file_if_changed(&path, json, &mut last_written).unwrap();
+            (action, written)
+        };
+        let mut sc = stable_channel();
+
+        // The first check records a price sample, so it saves
+        assert_eq!(check_and_save(&MockNode::new(200_000), &mut sc), (StabilityAction::Stable, true));
+        let saved_at = std::fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(check_and_save(&MockNode::new(200_000), &mut sc), (StabilityAction::Stable, false));
+        assert_eq!(check_and_save(&MockNode::new(200_000), &mut sc), (StabilityAction::Stable, false));
+        assert_eq!(std::fs::metadata(&path).unwrap().modified().unwrap(), saved_at);
+
+        // A payment is a change
+        let (action, written) = check_and_save(&MockNode::new(210_000), &mut sc);
+        assert!(matches!(action, StabilityAction::Paid { .. }));
+        assert!(written);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    fn runway_at(receiver_sats: u64, provider_sats: u64, receiver_reserve: u64, provider_reserve: u64) -> Runway {
+        runway(
+            USD::from_f64(100.0),
+            Bitcoin::default(),
+            PRICE,
+            Bitcoin::from_sats(receiver_sats),
+            Bitcoin::from_sats(provider_sats),
+            Bitcoin::from_sats(receiver_reserve),
+            Bitcoin::from_sats(provider_reserve),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn provider_pays_for_a_fall_down_to_its_reserve() {
+        let runway = runway_at(200_000, 800_000, 10_000, 10_000);
+        assert!((runway.down_percent - (1.0 - 0.2 / 0.99) * 100.0).abs() < 1e-9, "{:?}", runway);
+
+        // At the lowest price, par is the receiver's balance plus all the provider can send
+        let floor = PRICE * (1.0 - runway.down_percent / 100.0);
+        assert!((100.0 / floor - 0.0099).abs() < 1e-12);
+    }
+
+    #[test]
+    fn receiver_pays_back_a_rise_down_to_its_reserve() {
+        let runway = runway_at(200_000, 800_000, 10_000, 10_000);
+        assert!((runway.up_percent.unwrap() - 1_900.0).abs() < 1e-9, "{:?}", runway);
+
+        // At the highest price, par leaves the receiver only its reserve
+        let ceiling = PRICE * (1.0 + runway.up_percent.unwrap() / 100.0);
+        assert!((100.0 / ceiling - 0.0001).abs() < 1e-12);
+        assert_eq!(runway.min_percent(), runway.down_percent);
+    }
+
+    #[test]
+    fn receiver_without_a_reserve_can_pay_back_any_rise() {
+        assert_eq!(runway_at(200_000, 800_000, 0, 10_000).up_percent, None);
+    }
+
+    #[test]
+    fn exhausted_provider_has_no_runway_down() {
+        // Below par with the provider down to its reserve
+        let runway = runway_at(150_000, 10_000, 10_000, 10_000);
+        assert_eq!(runway.down_percent, 0.0);
+        assert_eq!(runway.min_percent(), 0.0);
+    }
+
+    #[test]
+    fn runway_needs_a_price_and_a_target() {
+        let sats = Bitcoin::from_sats(200_000);
+        assert_eq!(runway(USD::from_f64(100.0), Bitcoin::default(), 0.0, sats, sats, sats, sats), None);
+        assert_eq!(runway(USD::default(), Bitcoin::default(), PRICE, sats, sats, sats, sats), None);
+    }
+
+    #[test]
+    fn channel_runway_reads_each_sides_reserve_by_role() {
+        let mut c = channel(200_000);
+        c.unspendable_punishment_reserve = Some(20_000);
+        c.counterparty_unspendable_punishment_reserve = 10_000;
+        let mut sc = stable_channel();
+        sc.latest_price = PRICE;
+        sc.stable_receiver_btc = Bitcoin::from_sats(200_000);
+        sc.stable_provider_btc = Bitcoin::from_sats(800_000);
+
+        // As the receiver our reserve caps the rise we pay back
+        let runway = channel_runway(&sc, &c).unwrap();
+        assert!((runway.up_percent.unwrap() - 900.0).abs() < 1e-9, "{:?}", runway);
+        assert!((runway.down_percent - (1.0 - 0.2 / 0.99) * 100.0).abs() < 1e-9, "{:?}", runway);
+
+        // As the provider it caps the fall we pay for
+        sc.is_stable_receiver = false;
+        let runway = channel_runway(&sc, &c).unwrap();
+        assert!((runway.up_percent.unwrap() - 1_900.0).abs() < 1e-9, "{:?}", runway);
+        assert!((runway.down_percent - (1.0 - 0.2 / 0.98) * 100.0).abs() < 1e-9, "{:?}", runway);
+    }
+
+    fn spliced(channel_id: u8, value_sats: u64) -> ChannelDetails {
+        let mut c = channel(200_000);
+        c.channel_id = ChannelId::from_bytes([channel_id; 32]);
+        c.channel_value_sats = value_sats;
+        c.inbound_capacity_msat = (value_sats - 200_000) * 1000;
+        c
+    }
+
+    fn held_stable_channel() -> StableChannel {
+        let mut sc = stable_channel();
+        sc.latest_price = PRICE;
+        sc.stable_receiver_btc = Bitcoin::from_sats(200_000);
+        sc.stable_provider_btc = Bitcoin::from_sats(800_000);
+        sc
+    }
+
+    #[test]
+    fn spliced_channel_is_reattached_by_counterparty_and_value() {
+        let mut sc = held_stable_channel();
+        let before = vec![channel(200_000)];
+        assert!(!has_vanished(&sc, &before));
+
+        let after = vec![spliced(2, 1_050_000)];
+        assert!(has_vanished(&sc, &after));
+        let new_channel_id = ChannelId::from_bytes([2; 32]);
+        assert_eq!(find_replacement(&sc, &after, &[sc.channel_id]), Replacement::Found(new_channel_id));
+
+        // Pointed at the new id, the peg reads its balances from the spliced channel
+        sc.channel_id = new_channel_id;
+        let node = MockNode { channels: after, connected: true, sent: RefCell::new(Vec::new()) };
+        update_balances(&node, &mut sc);
+        assert_eq!(sc.stable_receiver_btc, Bitcoin::from_sats(200_000));
+        assert_eq!(sc.stable_provider_btc, Bitcoin::from_sats(850_000));
+    }
+
+    #[test]
+    fn value_outside_the_tolerance_needs_confirmation() {
+        let sc = held_stable_channel();
+        let after = vec![spliced(2, 1_200_000)];
+        assert_eq!(
+            find_replacement(&sc, &after, &[sc.channel_id]),
+            Replacement::Ambiguous(vec![ChannelId::from_bytes([2; 32])])
+        );
+    }
+
+    #[test]
+    fn several_channels_with_the_peer_need_confirmation() {
+        let sc = held_stable_channel();
+        let after = vec![spliced(2, 1_000_000), spliced(3, 1_000_000)];
+        assert_eq!(
+            find_replacement(&sc, &after, &[sc.channel_id]),
+            Replacement::Ambiguous(vec![ChannelId::from_bytes([2; 32]), ChannelId::from_bytes([3; 32])])
+        );
+    }
+
+    #[test]
+    fn other_peers_and_taken_or_unready_channels_are_not_candidates() {
+        let sc = held_stable_channel();
+        let mut stranger = spliced(2, 1_000_000);
+        stranger.counterparty_node_id = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key(9));
+        let mut unready = spliced(3, 1_000_000);
+        unready.is_channel_ready = false;
+        let taken = spliced(4, 1_000_000);
+
+        let after = vec![stranger, unready, taken];
+        let designated = [sc.channel_id, ChannelId::from_bytes([4; 32])];
+        assert_eq!(find_replacement(&sc, &after, &designated), Replacement::None);
+    }
+
+    #[test]
+    fn closed_or_unpegged_channels_have_not_vanished_into_a_splice() {
+        let after = vec![spliced(2, 1_000_000)];
+        let mut sc = held_stable_channel();
+        sc.close_seen = true;
+        assert!(!has_vanished(&sc, &after));
+
+        let mut sc = held_stable_channel();
+        sc.awaiting_reattach = true;
+        assert!(!has_vanished(&sc, &after));
+
+        let mut sc = held_stable_channel();
+        sc.unpegged_at = Some(unix_now());
+        assert!(!has_vanished(&sc, &after));
+    }
+}
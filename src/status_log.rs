@@ -0,0 +1,137 @@
+use crate::stable::StabilityAction;
+use crate::ui_util;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Entries kept before the oldest are dropped
+const MAX_ENTRIES: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    /// How prominently a stability check's outcome should show
+    pub fn of_action(action: &StabilityAction) -> Self {
+        match action {
+            StabilityAction::PaymentFailed(_)
+            | StabilityAction::CannotRebalance
+            | StabilityAction::CircuitBreakerTripped(_) => Severity::Error,
+            StabilityAction::HighRisk(_)
+            | StabilityAction::Suspended { .. }
+            | StabilityAction::NeedsApproval { .. }
+            | StabilityAction::RateLimited { .. }
+            | StabilityAction::NoPrice
+            | StabilityAction::PeerDisconnected => Severity::Warning,
+            _ => Severity::Info,
+        }
+    }
+
+    fn color(self) -> egui::Color32 {
+        match self {
+            Severity::Info => egui::Color32::GRAY,
+            Severity::Warning => egui::Color32::YELLOW,
+            Severity::Error => egui::Color32::RED,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StatusEntry {
+    /// Unix seconds
+    pub time: i64,
+    pub severity: Severity,
+    pub text: String,
+}
+
+/// What the app has been doing, newest last. Replaces a single status line so messages
+/// from the same poll don't overwrite each other.
+#[derive(Default)]
+pub struct StatusLog {
+    entries: VecDeque<StatusEntry>,
+    /// Latest error, kept on screen until dismissed however many messages follow it
+    pinned_error: Option<StatusEntry>,
+}
+
+impl StatusLog {
+    pub fn push(&mut self, severity: Severity, text: impl Into<String>) {
+        let entry = StatusEntry {
+            time: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0),
+            severity,
+            text: text.into(),
+        };
+        if severity == Severity::Error {
+            self.pinned_error = Some(entry.clone());
+        }
+        self.entries.push_back(entry);
+        while self.entries.len() > MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn info(&mut self, text: impl Into<String>) {
+        self.push(Severity::Info, text);
+    }
+
+    pub fn warn(&mut self, text: impl Into<String>) {
+        self.push(Severity::Warning, text);
+    }
+
+    pub fn error(&mut self, text: impl Into<String>) {
+        self.push(Severity::Error, text);
+    }
+
+    /// The most recent message, or "" before any
+    pub fn latest(&self) -> &str {
+        self.entries.back().map_or("", |e| e.text.as_str())
+    }
+
+    /// The latest message, preceded by any undismissed error
+    pub fn show_latest(&mut self, ui: &mut egui::Ui) {
+        let latest = self.entries.back();
+        if let Some(error) = &self.pinned_error {
+            let mut dismissed = false;
+            ui.horizontal(|ui| {
+                ui.colored_label(Severity::Error.color(), &error.text);
+                dismissed = ui.small_button("✕").on_hover_text("Dismiss").clicked();
+            });
+            if dismissed {
+                self.pinned_error = None;
+            }
+        }
+        if let Some(entry) = latest.filter(|e| e.severity != Severity::Error) {
+            ui.label(&entry.text);
+        }
+    }
+
+    /// Scrollable log of every kept message, newest first, with a copy-all button
+    pub fn show(&self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Event log").show(ui, |ui| {
+            ui_util::copy_button(ui, egui::Button::new("Copy all"), || {
+                let mut text = String::new();
+                for entry in &self.entries {
+                    let _ = writeln!(text, "{} {:?} {}", format_time(entry.time), entry.severity, entry.text);
+                }
+                text
+            });
+            egui::ScrollArea::vertical().max_height(200.0).id_salt("event_log").show(ui, |ui| {
+                for entry in self.entries.iter().rev() {
+                    ui.horizontal(|ui| {
+                        ui.label(egui::RichText::new(format_time(entry.time)).monospace().color(egui::Color32::GRAY));
+                        ui.colored_label(entry.severity.color(), &entry.text);
+                    });
+                }
+            });
+        });
+    }
+}
+
+/// Time of day in UTC, e.g. "14:03:27"
+fn format_time(time: i64) -> String {
+    let secs = time.rem_euclid(86_400);
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
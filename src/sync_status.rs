@@ -0,0 +1,146 @@
+use crate::chain_source::ChainSource;
+use crate::shutdown;
+use ldk_node::Node;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tracing::{debug, error, warn};
+use ureq::Agent;
+
+/// How often heights are re-read
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Blocks the node may trail the chain tip by and still count as synced
+const MAX_LAG_BLOCKS: u32 = 2;
+
+#[derive(Debug, Clone, Default)]
+pub struct SyncState {
+    /// Height of the node's best block
+    pub local_height: u32,
+    /// Tip height reported by the chain source, once fetched
+    pub tip_height: Option<u32>,
+    /// Both wallets have completed a sync since the node started
+    pub first_sync_done: bool,
+    /// Why the last tip query or manual sync failed
+    pub last_error: Option<String>,
+    /// A manual sync is running
+    pub syncing: bool,
+}
+
+impl SyncState {
+    pub fn is_synced(&self) -> bool {
+        self.first_sync_done
+            && self.tip_height.map_or(true, |tip| tip.saturating_sub(self.local_height) <= MAX_LAG_BLOCKS)
+    }
+}
+
+enum SyncRequest {
+    /// Sync the wallets now rather than waiting for ldk-node's background sync
+    Retry,
+}
+
+/// Background thread tracking the node's block height against the chain source's tip
+pub struct SyncMonitor {
+    state: Arc<Mutex<SyncState>>,
+    requests: Option<mpsc::Sender<SyncRequest>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl SyncMonitor {
+    pub fn spawn(node: Arc<Node>, chain_source: ChainSource) -> Self {
+        let state = Arc::new(Mutex::new(SyncState::default()));
+        let (request_tx, request_rx) = mpsc::channel();
+
+        let thread_state = Arc::clone(&state);
+        let handle = std::thread::spawn(move || {
+            let agent = Agent::new();
+            loop {
+                refresh(&node, &agent, &chain_source, &thread_state);
+                match request_rx.recv_timeout(POLL_INTERVAL) {
+                    Ok(SyncRequest::Retry) => {
+                        thread_state.lock().unwrap().syncing = true;
+                        let result = node.sync_wallets();
+                        let mut state = thread_state.lock().unwrap();
+                        state.syncing = false;
+                        if let Err(e) = result {
+                            error!("Wallet sync failed: {}", e);
+                            state.last_error = Some(format!("Wallet sync failed: {}", e));
+                        }
+                    }
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    // Ends once `stop` drops the sender
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            debug!("Sync monitor stopped");
+        });
+
+        Self { state, requests: Some(request_tx), handle: Some(handle) }
+    }
+
+    pub fn state(&self) -> SyncState {
+        self.state.lock().unwrap().clone()
+    }
+
+    pub fn retry(&self) {
+        if let Some(requests) = &self.requests {
+            let _ = requests.send(SyncRequest::Retry);
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.requests.take();
+        if let Some(handle) = self.handle.take() {
+            shutdown::join_with_timeout(handle, shutdown::THREAD_JOIN_TIMEOUT);
+        }
+    }
+
+    /// Block heights, with a spinner while syncing
+    pub fn show(&self, ui: &mut egui::Ui) {
+        let state = self.state();
+        ui.horizontal(|ui| {
+            let tip = state.tip_height.map(|h| h.to_string()).unwrap_or_else(|| "?".to_string());
+            ui.label(format!("Block height: {} (tip {})", state.local_height, tip));
+            if state.syncing || !state.is_synced() {
+                ui.spinner();
+                ui.label("Syncing…");
+            }
+        });
+    }
+
+    /// The last sync error, if any, with a Retry button
+    pub fn show_error_banner(&self, ui: &mut egui::Ui) {
+        let state = self.state();
+        let Some(error) = state.last_error else {
+            return;
+        };
+        egui::Frame::none().fill(egui::Color32::DARK_RED).inner_margin(6.0).show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.colored_label(egui::Color32::WHITE, error);
+                if ui.add_enabled(!state.syncing, egui::Button::new("Retry")).clicked() {
+                    self.retry();
+                }
+            });
+        });
+    }
+}
+
+fn refresh(node: &Node, agent: &Agent, chain_source: &ChainSource, state: &Mutex<SyncState>) {
+    let status = node.status();
+    let tip = chain_source.tip_height(agent);
+
+    let mut state = state.lock().unwrap();
+    state.local_height = status.current_best_block.height;
+    state.first_sync_done = status.latest_onchain_wallet_sync_timestamp.is_some()
+        && status.latest_lightning_wallet_sync_timestamp.is_some();
+    match tip {
+        Ok(height) => {
+            state.tip_height = Some(height);
+            state.last_error = None;
+        }
+        Err(e) => {
+            warn!("Failed to fetch chain tip from {}: {}", chain_source, e);
+            state.last_error = Some(format!("Chain source unreachable: {}", e));
+        }
+    }
+}
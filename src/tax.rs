@@ -0,0 +1,219 @@
+use crate::types::{Bitcoin, StableChannel, USD};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+const CSV_FILE_NAME: &str = "tax_export.csv";
+const JSON_FILE_NAME: &str = "tax_export.json";
+
+/// A settled payment as it matters for accounting
+#[derive(Debug, Clone)]
+pub struct TaxEvent {
+    pub timestamp: u64,
+    pub inbound: bool,
+    pub stability: bool,
+    pub amount_msats: u64,
+    /// BTC/USD price when the payment completed
+    pub price: f64,
+    /// Stable channel a stability payment adjusted; ordinary payments have none
+    pub channel_id: Option<String>,
+}
+
+/// One line of the export
+#[derive(Debug, Clone, Serialize)]
+pub struct TaxRow {
+    pub timestamp: u64,
+    pub direction: &'static str,
+    pub kind: &'static str,
+    pub btc: Bitcoin,
+    pub usd_value: USD,
+    pub price: f64,
+    /// Gain or loss realized by this payment; zero for acquisitions
+    pub realized_gain_usd: USD,
+    pub cumulative_gain_usd: USD,
+    pub channel_id: Option<String>,
+}
+
+/// Designation prices of stable channels, the cost basis of BTC held from before the peg
+#[derive(Debug, Clone, Default)]
+pub struct CostBasis {
+    by_channel: HashMap<String, f64>,
+    /// Used for ordinary payments, which aren't tied to a channel; only set when a single
+    /// channel is designated, so the basis is unambiguous
+    default: Option<f64>,
+}
+
+impl CostBasis {
+    pub fn from_channels<'a>(channels: impl IntoIterator<Item = &'a StableChannel>) -> Self {
+        let by_channel: HashMap<String, f64> = channels
+            .into_iter()
+            .filter_map(|sc| Some((sc.channel_id.to_string(), sc.designation_price.filter(|p| *p > 0.0)?)))
+            .collect();
+        let default = if by_channel.len() == 1 { by_channel.values().next().copied() } else { None };
+        Self { by_channel, default }
+    }
+
+    fn price_for(&self, channel_id: Option<&str>) -> Option<f64> {
+        channel_id.and_then(|id| self.by_channel.get(id).copied()).or(self.default)
+    }
+}
+
+/// First-in-first-out lots of BTC acquired through payments, as (msats, price) pairs
+#[derive(Debug, Default)]
+struct FifoLedger {
+    lots: VecDeque<(u64, f64)>,
+}
+
+/// Gain on `msats` bought at `cost_price` and spent at `price`
+fn gain_on(msats: u64, price: f64, cost_price: f64) -> USD {
+    let btc = Bitcoin::from_msats(msats);
+    USD::from_bitcoin(btc, price) - USD::from_bitcoin(btc, cost_price)
+}
+
+impl FifoLedger {
+    fn acquire(&mut self, msats: u64, price: f64) {
+        if msats > 0 {
+            self.lots.push_back((msats, price));
+        }
+    }
+
+    /// Gain realized by spending `msats` at `price`. BTC beyond the recorded lots was held
+    /// before the first recorded payment and costs `basis_price`, or nothing is realized on
+    /// it when that isn't known.
+    fn dispose(&mut self, mut msats: u64, price: f64, basis_price: Option<f64>) -> USD {
+        let mut gain = USD::default();
+        while msats > 0 {
+            let Some(lot) = self.lots.front_mut() else {
+                gain = gain + gain_on(msats, price, basis_price.unwrap_or(price));
+                break;
+            };
+            let used = msats.min(lot.0);
+            gain = gain + gain_on(used, price, lot.1);
+            msats -= used;
+            lot.0 -= used;
+            if lot.0 == 0 {
+                self.lots.pop_front();
+            }
+        }
+        gain
+    }
+}
+
+/// Rows in time order with realized gains against each channel's designation price.
+/// Inbound payments are acquisitions at the execution price, outbound ones disposals.
+pub fn build_rows(events: &[TaxEvent], basis: &CostBasis) -> Vec<TaxRow> {
+    let mut events: Vec<&TaxEvent> = events.iter().collect();
+    events.sort_by_key(|e| e.timestamp);
+
+    let mut ledger = FifoLedger::default();
+    let mut cumulative = USD::default();
+    events
+        .into_iter()
+        .map(|event| {
+            let realized = if event.inbound {
+                ledger.acquire(event.amount_msats, event.price);
+                USD::default()
+            } else {
+                ledger.dispose(event.amount_msats, event.price, basis.price_for(event.channel_id.as_deref()))
+            };
+            cumulative = cumulative + realized;
+            let btc = Bitcoin::from_msats(event.amount_msats);
+            TaxRow {
+                timestamp: event.timestamp,
+                direction: if event.inbound { "inbound" } else { "outbound" },
+                kind: if event.stability { "stability" } else { "payment" },
+                btc,
+                usd_value: USD::from_bitcoin(btc, event.price),
+                price: event.price,
+                realized_gain_usd: realized,
+                cumulative_gain_usd: cumulative,
+                channel_id: event.channel_id.clone(),
+            }
+        })
+        .collect()
+}
+
+/// Writes `tax_export.csv` and `tax_export.json` to the data directory, returning the CSV path
+pub fn export(data_dir: &Path, rows: &[TaxRow]) -> Result<PathBuf, String> {
+    let mut csv = String::from("timestamp,direction,kind,btc,usd_value,price,realized_gain_usd,cumulative_gain_usd,channel_id\n");
+    for row in rows {
+        let _ = writeln!(
+            csv,
+            "{},{},{},{:.8},{:.2},{:.2},{:.2},{:.2},{}",
+            row.timestamp,
+            row.direction,
+            row.kind,
+            row.btc.to_btc(),
+            row.usd_value.to_f64(),
+            row.price,
+            row.realized_gain_usd.to_f64(),
+            row.cumulative_gain_usd.to_f64(),
+            row.channel_id.as_deref().unwrap_or_default(),
+        );
+    }
+    let csv_path = data_dir.join(CSV_FILE_NAME);
+    std::fs::write(&csv_path, csv).map_err(|e| e.to_string())?;
+
+    let json = serde_json::to_string_pretty(rows).map_err(|e| e.to_string())?;
+    std::fs::write(data_dir.join(JSON_FILE_NAME), json).map_err(|e| e.to_string())?;
+    info!("Exported {} payments for tax to {}", rows.len(), csv_path.display());
+    Ok(csv_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(timestamp: u64, inbound: bool, amount_msats: u64, price: f64, channel_id: &str) -> TaxEvent {
+        TaxEvent { timestamp, inbound, stability: true, amount_msats, price, channel_id: Some(channel_id.to_string()) }
+    }
+
+    #[test]
+    fn gains_follow_fifo_lots_and_the_designation_basis() {
+        let sc = StableChannel { designation_price: Some(30_000.0), ..Default::default() };
+        let channel_id = sc.channel_id.to_string();
+        let basis = CostBasis::from_channels([&sc]);
+        // Out of order on purpose; rows come back in time order
+        let events = [
+            event(4, false, 20_000_000, 40_000.0, &channel_id),
+            event(1, true, 10_000_000, 50_000.0, &channel_id),
+            event(3, false, 15_000_000, 70_000.0, &channel_id),
+            event(2, true, 20_000_000, 60_000.0, &channel_id),
+        ];
+
+        let rows = build_rows(&events, &basis);
+        let gains: Vec<(u64, USD, USD)> =
+            rows.iter().map(|r| (r.timestamp, r.realized_gain_usd, r.cumulative_gain_usd)).collect();
+        assert_eq!(
+            gains,
+            vec![
+                (1, USD::default(), USD::default()),
+                (2, USD::default(), USD::default()),
+                // 10,000 sats bought at 50k and 5,000 at 60k, sold at 70k: $2.00 + $0.50
+                (3, USD::from_f64(2.5), USD::from_f64(2.5)),
+                // 15,000 sats bought at 60k sold at 40k, -$3.00, and 5,000 held from before
+                // the first payment at the 30k designation price, +$0.50
+                (4, USD::from_f64(-2.5), USD::default()),
+            ]
+        );
+        assert_eq!(rows[2].usd_value, USD::from_f64(10.5));
+        assert_eq!(rows[3].btc, Bitcoin::from_sats(20_000));
+    }
+
+    #[test]
+    fn unknown_basis_realizes_nothing_beyond_the_lots() {
+        let events = [TaxEvent {
+            timestamp: 1,
+            inbound: false,
+            stability: false,
+            amount_msats: 1_000_000,
+            price: 50_000.0,
+            channel_id: None,
+        }];
+
+        let rows = build_rows(&events, &CostBasis::default());
+        assert_eq!(rows[0].realized_gain_usd, USD::default());
+    }
+}
@@ -1,7 +1,10 @@
 use ldk_node::bitcoin::secp256k1::PublicKey;
+use ldk_node::lightning::ln::channelmanager::PaymentId;
 use ldk_node::lightning::ln::types::ChannelId;
-use std::{ops::{Div, Sub}, time::{SystemTime, UNIX_EPOCH}};
+use std::{collections::VecDeque, ops::{Add, Div, Sub}, time::{SystemTime, UNIX_EPOCH}};
 use serde::{Deserialize, Serialize};
+use crate::currency::{format_fiat, MICROS_PER_UNIT};
+pub use crate::currency::{group_thousands, valid_price, Currency};
 
 // Custom serialization for ChannelId
 mod channel_id_serde {
@@ -50,45 +53,186 @@ mod pubkey_serde {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+// Custom serialization for PaymentId
+mod payment_id_serde {
+    use ldk_node::lightning::ln::channelmanager::PaymentId;
+    use serde::{Deserialize, Deserializer, Serializer, Serialize};
+
+    pub fn serialize<S>(payment_id: &PaymentId, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        hex::encode(payment_id.0).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<PaymentId, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let hex_str = String::deserialize(deserializer)?;
+        let bytes = hex::decode(&hex_str).map_err(serde::de::Error::custom)?;
+        let bytes: [u8; 32] = bytes.try_into().map_err(|_| serde::de::Error::custom("payment id must be 32 bytes"))?;
+        Ok(PaymentId(bytes))
+    }
+}
+
+/// Convert a BTC/USD price to integer micro-dollars per BTC for fixed-point math. None
+/// without a valid price, or one that rounds to nothing.
+fn price_micros(btcusd_price: f64) -> Option<i128> {
+    let micros = (valid_price(btcusd_price)? * USD::MICROS_PER_USD as f64).round() as i128;
+    (micros > 0).then_some(micros)
+}
+
+/// Drops the separators people type into amounts: commas, underscores and spaces
+fn strip_separators(input: &str) -> String {
+    input.chars().filter(|c| !matches!(c, ',' | '_' | ' ')).collect()
+}
+
+/// Unit BTC amounts are shown in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AmountUnit {
+    #[default]
+    Sats,
+    Btc,
+}
+
+impl std::fmt::Display for AmountUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            AmountUnit::Sats => "sats",
+            AmountUnit::Btc => "BTC",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(from = "BitcoinRepr", into = "BitcoinRepr")]
 pub struct Bitcoin {
-    pub sats: u64, // Stored in Satoshis for precision
+    pub msats: u64, // Stored in millisatoshis so conversions don't lose precision
+}
+
+/// How `Bitcoin` is serialized. Amounts used to be whole sats, so `sats` is still written
+/// for older readers, and `msats`, when present, carries the precise amount.
+#[derive(Serialize, Deserialize)]
+struct BitcoinRepr {
+    sats: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    msats: Option<u64>,
+}
+
+impl From<BitcoinRepr> for Bitcoin {
+    fn from(repr: BitcoinRepr) -> Self {
+        Self { msats: repr.msats.unwrap_or(repr.sats.saturating_mul(1000)) }
+    }
+}
+
+impl From<Bitcoin> for BitcoinRepr {
+    fn from(btc: Bitcoin) -> Self {
+        Self { sats: btc.to_sats(), msats: Some(btc.msats) }
+    }
 }
 
 impl Default for Bitcoin {
     fn default() -> Self {
-        Self { sats: 0 }
+        Self { msats: 0 }
     }
 }
 
 impl Bitcoin {
     const SATS_IN_BTC: u64 = 100_000_000;
+    const MSATS_IN_BTC: u64 = Self::SATS_IN_BTC * 1000;
 
     pub fn from_sats(sats: u64) -> Self {
-        Self { sats }
+        Self { msats: sats * 1000 }
+    }
+
+    pub fn from_msats(msats: u64) -> Self {
+        Self { msats }
     }
 
     pub fn from_btc(btc: f64) -> Self {
-        let sats = (btc * Self::SATS_IN_BTC as f64).round() as u64;
-        Self::from_sats(sats)
+        let msats = (btc * Self::MSATS_IN_BTC as f64).round() as u64;
+        Self::from_msats(msats)
+    }
+
+    pub fn to_sats(self) -> u64 {
+        self.msats / 1000
+    }
+
+    pub fn to_msats(self) -> u64 {
+        self.msats
     }
 
     pub fn to_btc(self) -> f64 {
-        self.sats as f64 / Self::SATS_IN_BTC as f64
+        self.msats as f64 / Self::MSATS_IN_BTC as f64
     }
 
-    pub fn from_usd(usd: USD, btcusd_price: f64) -> Self {
-        let btc = usd.0 / btcusd_price;
-        Bitcoin::from_btc(btc)
+    /// The absolute amount of `usd` at `btcusd_price`; None until there is a price
+    pub fn from_usd(usd: USD, btcusd_price: f64) -> Option<Self> {
+        usd.to_msats(btcusd_price).map(Bitcoin::from_msats)
     }
 
+    /// Whole sats with separators, e.g. "1,234,567 sats"
+    pub fn format_sats(self) -> String {
+        format!("{} sats", group_thousands(self.to_sats()))
+    }
+
+    /// Eight decimals with separators in the whole part, e.g. "1,234.50000000 BTC"
+    pub fn format_btc(self) -> String {
+        let sats = self.to_sats();
+        format!("{}.{:08} BTC", group_thousands(sats / Self::SATS_IN_BTC), sats % Self::SATS_IN_BTC)
+    }
+
+    pub fn format(self, unit: AmountUnit) -> String {
+        match unit {
+            AmountUnit::Sats => self.format_sats(),
+            AmountUnit::Btc => self.format_btc(),
+        }
+    }
+
+    /// Parses an amount as people type it. Separators are ignored, and a "sats" or "BTC"
+    /// suffix overrides `default_unit`.
+    pub fn parse(input: &str, default_unit: AmountUnit) -> Result<Self, String> {
+        let cleaned = strip_separators(input).to_ascii_lowercase();
+        let (number, unit) = if let Some(number) = cleaned.strip_suffix("sats").or_else(|| cleaned.strip_suffix("sat")) {
+            (number, AmountUnit::Sats)
+        } else if let Some(number) = cleaned.strip_suffix("btc") {
+            (number, AmountUnit::Btc)
+        } else {
+            (cleaned.as_str(), default_unit)
+        };
+        let invalid = || format!("Invalid amount: {}", input.trim());
+        match unit {
+            AmountUnit::Sats => match number.parse::<u64>() {
+                Ok(sats) if sats <= u64::MAX / 1000 => Ok(Self::from_sats(sats)),
+                _ => Err(invalid()),
+            },
+            // Parsed as a decimal rather than a float, which can't hold every msat amount
+            AmountUnit::Btc => match Self::parse_btc_msats(number) {
+                Some(msats) if msats <= 21_000_000 * Self::MSATS_IN_BTC => Ok(Self::from_msats(msats)),
+                _ => Err(invalid()),
+            },
+        }
+    }
+
+    /// A plain decimal BTC amount in msats; None for anything finer than a msat
+    fn parse_btc_msats(number: &str) -> Option<u64> {
+        let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+        let digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+        if (whole.is_empty() && fraction.is_empty()) || fraction.len() > 11 || !digits(whole) || !digits(fraction) {
+            return None;
+        }
+        let whole = if whole.is_empty() { 0 } else { whole.parse::<u64>().ok()? };
+        let fraction = format!("{:0<11}", fraction).parse::<u64>().ok()?;
+        whole.checked_mul(Self::MSATS_IN_BTC)?.checked_add(fraction)
+    }
 }
 
 impl Sub for Bitcoin {
     type Output = Bitcoin;
 
     fn sub(self, other: Bitcoin) -> Bitcoin {
-        Bitcoin::from_sats(self.sats.saturating_sub(other.sats))
+        Bitcoin::from_msats(self.msats.saturating_sub(other.msats))
     }
 }
 
@@ -108,29 +252,105 @@ impl std::fmt::Display for Bitcoin {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
-pub struct USD(pub f64);
+/// A fiat amount stored as integer micro-units, so repeated conversions don't
+/// accumulate rounding error. Cents would be too coarse for the 0.1% par threshold
+/// on small pegs. Named for the original USD-only peg; the currency of a stable
+/// channel's amounts is its `StableChannel::currency`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(from = "UsdRepr", into = "UsdRepr")]
+pub struct USD(i64);
+
+/// How `USD` is serialized: a plain number of units, as before amounts were fixed-point.
+/// An f64 holds every micro-unit exactly up to about nine billion.
+#[derive(Serialize, Deserialize)]
+struct UsdRepr(f64);
+
+impl From<UsdRepr> for USD {
+    fn from(repr: UsdRepr) -> Self {
+        USD::from_f64(repr.0)
+    }
+}
+
+impl From<USD> for UsdRepr {
+    fn from(usd: USD) -> Self {
+        UsdRepr(usd.to_f64())
+    }
+}
 
 impl Default for USD {
     fn default() -> Self {
-        Self(0.0)
+        Self(0)
     }
 }
 
 impl USD {
+    const MICROS_PER_USD: i64 = MICROS_PER_UNIT;
+
+    /// `btc` valued at `btcusd_price`, or nothing without a price
     pub fn from_bitcoin(btc: Bitcoin, btcusd_price: f64) -> Self {
-        Self(btc.to_btc() * btcusd_price)
+        let price = price_micros(btcusd_price).unwrap_or(0);
+        let micros = (btc.msats as i128).saturating_mul(price) / Bitcoin::MSATS_IN_BTC as i128;
+        Self(micros.min(i64::MAX as i128) as i64)
     }
 
     pub fn from_f64(amount: f64) -> Self {
-        Self(amount)
+        Self((amount * Self::MICROS_PER_USD as f64).round() as i64)
+    }
+
+    pub fn from_micros(micros: i64) -> Self {
+        Self(micros)
     }
 
-    pub fn to_msats(self, btcusd_price: f64) -> u64 {
-        let btc_value = self.0 / btcusd_price;
-        let sats = btc_value * Bitcoin::SATS_IN_BTC as f64;
-        let millisats = sats * 1000.0;
-        millisats.abs().floor() as u64
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / Self::MICROS_PER_USD as f64
+    }
+
+    pub fn to_micros(self) -> i64 {
+        self.0
+    }
+
+    pub fn abs(self) -> Self {
+        Self(self.0.abs())
+    }
+
+    /// Converts the absolute amount to millisatoshis, rounding down so we never overpay.
+    /// None until there is a price to divide by.
+    pub fn to_msats(self, btcusd_price: f64) -> Option<u64> {
+        let price = price_micros(btcusd_price)?;
+        let msats = (self.0 as i128).abs() * Bitcoin::MSATS_IN_BTC as i128 / price;
+        Some(msats.min(u64::MAX as i128) as u64)
+    }
+
+    /// Converts the absolute amount to whole satoshis at `btcusd_price`, rounding down
+    pub fn to_sats_at(self, btcusd_price: f64) -> Option<u64> {
+        self.to_msats(btcusd_price).map(|msats| msats / 1000)
+    }
+
+    /// Dollars to the cent with separators, e.g. "$183,456.21"
+    pub fn format_usd(self) -> String {
+        format_fiat("$", self.0)
+    }
+
+    /// Parses a dollar amount as people type it, ignoring separators and a leading "$",
+    /// and reading back what `format_usd` shows
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let cleaned = strip_separators(input);
+        let number = match cleaned.strip_prefix("-$") {
+            Some(unsigned) => format!("-{}", unsigned),
+            None => cleaned.strip_prefix('$').unwrap_or(&cleaned).to_string(),
+        };
+        match number.parse::<f64>() {
+            Ok(usd) if usd.is_finite() => Ok(Self::from_f64(usd)),
+            _ => Err(format!("Invalid amount: {}", input.trim())),
+        }
+    }
+}
+
+impl Add for USD {
+    type Output = USD;
+
+    fn add(self, other: USD) -> USD {
+        USD(self.0 + other.0)
     }
 }
 
@@ -145,22 +365,30 @@ impl Sub for USD {
 impl Div<f64> for USD {
     type Output = USD;
 
+    /// Zero for a zero or non-finite `scalar`, rather than an infinite amount
     fn div(self, scalar: f64) -> USD {
-        USD(self.0 / scalar)
+        if !scalar.is_finite() || scalar == 0.0 {
+            return USD::default();
+        }
+        USD::from_f64(self.to_f64() / scalar)
     }
 }
 
 impl Div for USD {
     type Output = f64;
 
+    /// The ratio of the two amounts, zero when `other` is
     fn div(self, other: USD) -> f64 {
-        self.0 / other.0
+        if other.0 == 0 {
+            return 0.0;
+        }
+        self.0 as f64 / other.0 as f64
     }
 }
 
 impl std::fmt::Display for USD {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "${:.2}", self.0)
+        write!(f, "${:.2}", self.to_f64())
     }
 }
 
@@ -173,6 +401,8 @@ pub struct StableChannel {
     pub counterparty: PublicKey,
     pub expected_usd: USD,
     pub expected_btc: Bitcoin,
+    /// Each side's sendable balance plus its channel reserve, excluding pending HTLCs;
+    /// see `stable::channel_balances`. The receiver's is what the peg holds at `expected_usd`.
     pub stable_receiver_btc: Bitcoin,
     pub stable_provider_btc: Bitcoin,
     pub stable_receiver_usd: USD,
@@ -180,11 +410,156 @@ pub struct StableChannel {
     pub risk_level: i32,
     pub timestamp: i64,
     pub formatted_datetime: String,
-    pub payment_made: bool,
+    /// The last stability payment that settled, either way
+    #[serde(default)]
+    pub last_payment: Option<PaymentRecord>,
     pub sc_dir: String,
     pub latest_price: f64,
-    pub prices: String,
-    
+    /// Timestamped (unix seconds, price) samples, oldest first
+    pub prices: VecDeque<(i64, f64)>,
+    #[serde(skip)]
+    pub pending_payment_id: Option<PaymentId>,
+    pub max_payment_usd: f64,
+    pub max_payment_percent: f64,
+    pub approval_required_above_cap: bool,
+    #[serde(skip)]
+    pub pending_approval_msats: Option<u64>,
+    #[serde(skip)]
+    pub payment_approved: bool,
+    #[serde(skip)]
+    pub pending_payment_msats: u64,
+    pub total_paid_to_user_msats: u64,
+    pub total_paid_to_lsp_msats: u64,
+    pub payment_count: u64,
+    pub peg_agreed: bool,
+    pub max_risk_level: i32,
+    pub consecutive_failures: u32,
+    pub twap_window_secs: u64,
+    #[serde(skip)]
+    pub twap_price: f64,
+    pub min_seconds_between_payments: u64,
+    pub max_daily_adjustment_usd: f64,
+    pub last_payment_timestamp: i64,
+    /// (unix seconds, USD value) of stability payments sent in the last 24 hours
+    pub daily_payments: VecDeque<(i64, f64)>,
+    /// Designation follows the counterparty rather than a specific channel
+    pub keyed_by_counterparty: bool,
+    /// The designated channel closed; waiting for the next ready channel with the counterparty
+    #[serde(skip)]
+    pub awaiting_reattach: bool,
+    /// The channel was seen closing, so its id vanishing isn't a splice
+    #[serde(skip)]
+    pub close_seen: bool,
+    /// Channels the designation may have moved to after its id vanished, for the operator
+    /// to pick from
+    #[serde(skip)]
+    pub reattach_candidates: Vec<ChannelId>,
+    /// Provider's cut of each stability payment, in parts per million, as agreed for the channel
+    pub stability_fee_ppm: u32,
+    /// Stability fees the provider has earned on this channel
+    pub accrued_fee_msats: u64,
+    /// Fee portion of the in-flight stability payment
+    #[serde(skip)]
+    pub pending_fee_msats: u64,
+    /// Currency of `expected_usd`, the USD-named balances and `latest_price`
+    #[serde(default)]
+    pub currency: Currency,
+    /// Percentage of the receiver's balance that is pegged; the rest floats with BTC
+    #[serde(default = "default_stable_fraction")]
+    pub stable_fraction: f64,
+    /// BTC the receiver keeps unpegged, fixed when the channel was designated
+    #[serde(default)]
+    pub float_btc: Bitcoin,
+    /// Unix time the peg was ended; no stability checks run after it
+    #[serde(default)]
+    pub unpegged_at: Option<i64>,
+    /// Stability payments owed while the counterparty was unreachable, oldest first
+    #[serde(default)]
+    pub missed_settlements: VecDeque<MissedSettlement>,
+    /// Stability price when the channel was force-closed, for final accounting
+    #[serde(default)]
+    pub closing_price: Option<f64>,
+    /// Price when the peg was first agreed, the cost basis of BTC held from before it
+    #[serde(default)]
+    pub designation_price: Option<f64>,
+    /// Provider's BTC in the channel when it was designated
+    #[serde(default)]
+    pub native_btc_original: Bitcoin,
+    /// Provider's BTC leg: `native_btc_original` moved by every stability payment since,
+    /// so its change is what providing stability has gained or cost the provider in BTC
+    #[serde(default)]
+    pub native_btc: Bitcoin,
+    /// Price move between two checks, in percent, that trips the circuit breaker
+    #[serde(default = "default_circuit_breaker_percent")]
+    pub circuit_breaker_percent: f64,
+    /// Unix time until which the circuit breaker holds off stability payments
+    #[serde(default)]
+    pub suspended_until: Option<i64>,
+    /// Why the circuit breaker tripped
+    #[serde(default)]
+    pub suspension_reason: Option<String>,
+    /// Failed attempts at the current adjustment; see `payment_retry::RetryPolicy`
+    #[serde(skip)]
+    pub payment_attempt: u32,
+    /// The last failed attempt found no route for its amount
+    #[serde(skip)]
+    pub last_failure_no_route: bool,
+    /// How far the price can move before the paying side runs out, as of the last check
+    #[serde(skip)]
+    pub runway: Option<Runway>,
+}
+
+/// How far the price can move, in percent of the current price, before a stability
+/// payment would need more than the paying side can send
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Runway {
+    /// Fall the provider can pay for
+    pub down_percent: f64,
+    /// Rise the receiver can pay for; None if no rise exhausts it
+    pub up_percent: Option<f64>,
+}
+
+impl Runway {
+    /// The shorter of the two directions
+    pub fn min_percent(&self) -> f64 {
+        self.up_percent.map_or(self.down_percent, |up| up.min(self.down_percent))
+    }
+}
+
+/// Which way a stability payment went, from this node's side
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PaymentDirection {
+    Sent,
+    Received,
+}
+
+/// A settled stability payment
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PaymentRecord {
+    #[serde(with = "payment_id_serde")]
+    pub id: PaymentId,
+    pub amount_msats: u64,
+    pub direction: PaymentDirection,
+    /// Unix seconds it settled at
+    pub time: i64,
+}
+
+/// A stability payment that couldn't be sent because the counterparty was offline or
+/// unreachable, settled once it is back
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MissedSettlement {
+    pub timestamp: i64,
+    pub amount_msats: u64,
+    pub owed_usd: f64,
+    pub price: f64,
+}
+
+fn default_stable_fraction() -> f64 {
+    crate::stable::DEFAULT_STABLE_FRACTION
+}
+
+fn default_circuit_breaker_percent() -> f64 {
+    crate::stable::DEFAULT_CIRCUIT_BREAKER_PERCENT
 }
 
 // Implement manual Default for StableChannel
@@ -202,19 +577,665 @@ impl Default for StableChannel {
                     0x8A, 0x04, 0x88, 0x7E, 0x5B, 0x23, 0x52,
                 ]).unwrap()
             }),
-            expected_usd: USD(0.0),
+            expected_usd: USD::default(),
             expected_btc: Bitcoin::from_sats(0),
             stable_receiver_btc: Bitcoin::from_sats(0),
             stable_provider_btc: Bitcoin::from_sats(0),
-            stable_receiver_usd: USD(0.0),
-            stable_provider_usd: USD(0.0),
+            stable_receiver_usd: USD::default(),
+            stable_provider_usd: USD::default(),
             risk_level: 0,
             timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64,
             formatted_datetime: "".to_string(),
-            payment_made: false,
+            last_payment: None,
             sc_dir: ".data".to_string(),
             latest_price: 0.0,
-            prices: "".to_string(),
+            prices: VecDeque::new(),
+            pending_payment_id: None,
+            max_payment_usd: crate::stable::DEFAULT_MAX_PAYMENT_USD,
+            max_payment_percent: crate::stable::DEFAULT_MAX_PAYMENT_PERCENT,
+            approval_required_above_cap: false,
+            pending_approval_msats: None,
+            payment_approved: false,
+            pending_payment_msats: 0,
+            total_paid_to_user_msats: 0,
+            total_paid_to_lsp_msats: 0,
+            payment_count: 0,
+            peg_agreed: false,
+            max_risk_level: crate::stable::DEFAULT_MAX_RISK_LEVEL,
+            consecutive_failures: 0,
+            twap_window_secs: crate::stable::DEFAULT_TWAP_WINDOW_SECS,
+            twap_price: 0.0,
+            min_seconds_between_payments: crate::stable::DEFAULT_MIN_SECONDS_BETWEEN_PAYMENTS,
+            max_daily_adjustment_usd: crate::stable::DEFAULT_MAX_DAILY_ADJUSTMENT_USD,
+            last_payment_timestamp: 0,
+            daily_payments: VecDeque::new(),
+            keyed_by_counterparty: false,
+            awaiting_reattach: false,
+            close_seen: false,
+            reattach_candidates: Vec::new(),
+            stability_fee_ppm: 0,
+            accrued_fee_msats: 0,
+            pending_fee_msats: 0,
+            currency: Currency::USD,
+            stable_fraction: crate::stable::DEFAULT_STABLE_FRACTION,
+            float_btc: Bitcoin::default(),
+            unpegged_at: None,
+            missed_settlements: VecDeque::new(),
+            closing_price: None,
+            designation_price: None,
+            native_btc_original: Bitcoin::default(),
+            native_btc: Bitcoin::default(),
+            circuit_breaker_percent: crate::stable::DEFAULT_CIRCUIT_BREAKER_PERCENT,
+            suspended_until: None,
+            suspension_reason: None,
+            payment_attempt: 0,
+            last_failure_no_route: false,
+            runway: None,
         }
     }
-}
\ No newline at end of file
+}
+/// Custom TLV type identifying a payment as a stability adjustment
+pub const STABILITY_PAYMENT_TLV_TYPE: u64 = 65537;
+
+/// Attached to stability payments so the receiver can account for them
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StabilityPaymentInfo {
+    #[serde(with = "channel_id_serde")]
+    pub channel_id: ChannelId,
+    pub target_usd: f64,
+    pub price_used: f64,
+    pub deviation_usd: f64,
+    /// Stability fee included in (or withheld from) the payment
+    pub fee_msats: u64,
+}
+
+impl StabilityPaymentInfo {
+    /// Length before the fee was added, still accepted from older peers
+    const LEGACY_ENCODED_LEN: usize = 32 + 3 * 8;
+    const ENCODED_LEN: usize = Self::LEGACY_ENCODED_LEN + 8;
+
+    /// Wire layout: channel id (32 bytes), then target, price and deviation as big endian f64s,
+    /// then the fee as a big endian u64
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::ENCODED_LEN);
+        bytes.extend_from_slice(&self.channel_id.0);
+        bytes.extend_from_slice(&self.target_usd.to_be_bytes());
+        bytes.extend_from_slice(&self.price_used.to_be_bytes());
+        bytes.extend_from_slice(&self.deviation_usd.to_be_bytes());
+        bytes.extend_from_slice(&self.fee_msats.to_be_bytes());
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::ENCODED_LEN && bytes.len() != Self::LEGACY_ENCODED_LEN {
+            return None;
+        }
+        let f64_at = |offset: usize| -> Option<f64> {
+            Some(f64::from_be_bytes(bytes[offset..offset + 8].try_into().ok()?))
+        };
+        let fee_msats = match bytes.get(Self::LEGACY_ENCODED_LEN..) {
+            Some(fee) if !fee.is_empty() => u64::from_be_bytes(fee.try_into().ok()?),
+            _ => 0,
+        };
+        Some(Self {
+            channel_id: ChannelId(bytes[..32].try_into().ok()?),
+            target_usd: f64_at(32)?,
+            price_used: f64_at(40)?,
+            deviation_usd: f64_at(48)?,
+            fee_msats,
+        })
+    }
+}
+
+/// Custom TLV type carrying the sender's node signature over the `StabilityPaymentInfo` record,
+/// since keysends don't reveal who sent them
+pub const STABILITY_SIGNATURE_TLV_TYPE: u64 = 65541;
+
+/// Custom TLV type carrying stable channel negotiation messages
+pub const STABLE_MESSAGE_TLV_TYPE: u64 = 65539;
+
+/// Messages used to agree on stable channel parameters with the counterparty
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum StableMessage {
+    /// `proposer_receives` says whether the proposer takes the stable side, leaving the
+    /// other node to provide stability, or the reverse
+    ProposeStable { expected_usd: f64, currency: Currency, stable_fraction: f64, proposer_receives: bool },
+    /// `float_sats` is the unpegged BTC recorded for the receiver. `proposer_receives`
+    /// repeats the sides of the proposal being accepted.
+    AcceptStable {
+        expected_usd: f64,
+        fee_ppm: u32,
+        currency: Currency,
+        stable_fraction: f64,
+        float_sats: u64,
+        proposer_receives: bool,
+    },
+    RejectStable { reason: String },
+    /// The sender has ended the peg; the channel stays open
+    UnpegStable,
+}
+
+impl StableMessage {
+    const PROPOSE_TAG: u8 = 1;
+    const ACCEPT_TAG: u8 = 2;
+    const REJECT_TAG: u8 = 3;
+    const UNPEG_TAG: u8 = 4;
+
+    /// Compact wire encoding: a one byte tag followed by cents (u64, big endian) or a
+    /// UTF-8 reason. An accept then carries the stability fee in ppm (u32, big endian).
+    /// Both continue with the three letter currency code and the stable fraction in basis
+    /// points (u16, big endian), an accept then has the floating sats (u64, big endian), and
+    /// both end with one byte that is 1 if the proposer takes the stable side. Fields missing
+    /// from older peers' messages decode as no fee, USD, fully stable and the proposer
+    /// receiving.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            StableMessage::ProposeStable { expected_usd, currency, stable_fraction, proposer_receives } => {
+                bytes.push(Self::PROPOSE_TAG);
+                bytes.extend_from_slice(&Self::to_cents(*expected_usd).to_be_bytes());
+                bytes.extend_from_slice(currency.code().as_bytes());
+                bytes.extend_from_slice(&Self::to_basis_points(*stable_fraction).to_be_bytes());
+                bytes.push(*proposer_receives as u8);
+            }
+            StableMessage::AcceptStable { expected_usd, fee_ppm, currency, stable_fraction, float_sats, proposer_receives } => {
+                bytes.push(Self::ACCEPT_TAG);
+                bytes.extend_from_slice(&Self::to_cents(*expected_usd).to_be_bytes());
+                bytes.extend_from_slice(&fee_ppm.to_be_bytes());
+                bytes.extend_from_slice(currency.code().as_bytes());
+                bytes.extend_from_slice(&Self::to_basis_points(*stable_fraction).to_be_bytes());
+                bytes.extend_from_slice(&float_sats.to_be_bytes());
+                bytes.push(*proposer_receives as u8);
+            }
+            StableMessage::RejectStable { reason } => {
+                bytes.push(Self::REJECT_TAG);
+                bytes.extend_from_slice(reason.as_bytes());
+            }
+            StableMessage::UnpegStable => bytes.push(Self::UNPEG_TAG),
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let (tag, payload) = bytes.split_first()?;
+        match *tag {
+            Self::PROPOSE_TAG => {
+                let (cents, rest) = payload.split_at(payload.len().min(8));
+                let (currency, rest) = rest.split_at(rest.len().min(3));
+                let (fraction, role) = rest.split_at(rest.len().min(2));
+                Some(StableMessage::ProposeStable {
+                    expected_usd: Self::from_cents(cents)?,
+                    currency: Self::currency_from(currency)?,
+                    stable_fraction: Self::fraction_from(fraction)?,
+                    proposer_receives: Self::role_from(role)?,
+                })
+            }
+            Self::ACCEPT_TAG => {
+                let (cents, rest) = payload.split_at(payload.len().min(8));
+                let (fee, rest) = rest.split_at(rest.len().min(4));
+                let (currency, rest) = rest.split_at(rest.len().min(3));
+                let (fraction, rest) = rest.split_at(rest.len().min(2));
+                let (float_sats, role) = rest.split_at(rest.len().min(8));
+                let fee_ppm = match fee.len() {
+                    0 => 0,
+                    _ => u32::from_be_bytes(fee.try_into().ok()?),
+                };
+                let float_sats = match float_sats.len() {
+                    0 => 0,
+                    _ => u64::from_be_bytes(float_sats.try_into().ok()?),
+                };
+                Some(StableMessage::AcceptStable {
+                    expected_usd: Self::from_cents(cents)?,
+                    fee_ppm,
+                    currency: Self::currency_from(currency)?,
+                    stable_fraction: Self::fraction_from(fraction)?,
+                    float_sats,
+                    proposer_receives: Self::role_from(role)?,
+                })
+            }
+            Self::REJECT_TAG => Some(StableMessage::RejectStable {
+                reason: String::from_utf8(payload.to_vec()).ok()?,
+            }),
+            Self::UNPEG_TAG => Some(StableMessage::UnpegStable),
+            _ => None,
+        }
+    }
+
+    fn to_cents(usd: f64) -> u64 {
+        (usd * 100.0).round().max(0.0) as u64
+    }
+
+    fn from_cents(payload: &[u8]) -> Option<f64> {
+        let cents = u64::from_be_bytes(payload.try_into().ok()?);
+        Some(cents as f64 / 100.0)
+    }
+
+    fn currency_from(code: &[u8]) -> Option<Currency> {
+        if code.is_empty() {
+            return Some(Currency::USD);
+        }
+        Currency::from_code(std::str::from_utf8(code).ok()?)
+    }
+
+    fn to_basis_points(percent: f64) -> u16 {
+        (percent * 100.0).round().clamp(0.0, 10_000.0) as u16
+    }
+
+    fn role_from(payload: &[u8]) -> Option<bool> {
+        match payload {
+            [] | [1] => Some(true),
+            [0] => Some(false),
+            _ => None,
+        }
+    }
+
+    fn fraction_from(payload: &[u8]) -> Option<f64> {
+        if payload.is_empty() {
+            return Some(crate::stable::DEFAULT_STABLE_FRACTION);
+        }
+        let basis_points = u16::from_be_bytes(payload.try_into().ok()?);
+        (basis_points <= 10_000).then(|| basis_points as f64 / 100.0)
+    }
+}
+
+/// A `StableMessage` together with its sender and the sender's node signature over
+/// the encoded message, since keysends don't reveal who sent them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StableMessageEnvelope {
+    pub sender: PublicKey,
+    pub message: StableMessage,
+    pub signature: String,
+}
+
+impl StableMessageEnvelope {
+    /// Wire layout: sender pubkey (33 bytes), signature length (1 byte), signature, message
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = self.sender.serialize().to_vec();
+        bytes.push(self.signature.len() as u8);
+        bytes.extend_from_slice(self.signature.as_bytes());
+        bytes.extend_from_slice(&self.message.encode());
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 34 {
+            return None;
+        }
+        let sender = PublicKey::from_slice(&bytes[..33]).ok()?;
+        let sig_len = bytes[33] as usize;
+        let sig_end = 34 + sig_len;
+        let signature = String::from_utf8(bytes.get(34..sig_end)?.to_vec()).ok()?;
+        let message = StableMessage::decode(bytes.get(sig_end..)?)?;
+        Some(Self { sender, message, signature })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRICES: [f64; 12] =
+        [0.0, -1.0, 1e-12, 1e-6, 0.5, 1.0, 50_000.0, 1e12, f64::MAX, f64::MIN_POSITIVE, f64::INFINITY, f64::NAN];
+    const MSATS: [u64; 6] = [0, 1, 1_000, 100_000_000_000, 2_100_000_000_000_000_000, u64::MAX];
+    const AMOUNTS: [f64; 8] = [0.0, 1e-9, 0.01, 1.0, 100.0, 1e15, f64::MAX, f64::INFINITY];
+
+    #[test]
+    fn bitcoin_conversions_stay_finite() {
+        for msats in MSATS {
+            let btc = Bitcoin::from_msats(msats);
+            assert!(btc.to_btc().is_finite(), "{} msats", msats);
+            for price in PRICES {
+                let usd = USD::from_bitcoin(btc, price);
+                assert!(usd.to_f64().is_finite(), "{} msats at {}", msats, price);
+                assert!(usd.to_micros() >= 0, "{} msats at {}", msats, price);
+            }
+        }
+        for amount in AMOUNTS {
+            assert!(Bitcoin::from_btc(amount).to_btc().is_finite(), "{} BTC", amount);
+        }
+    }
+
+    #[test]
+    fn usd_conversions_stay_finite() {
+        for amount in AMOUNTS {
+            let usd = USD::from_f64(amount);
+            assert!(usd.to_f64().is_finite(), "${}", amount);
+            for price in PRICES {
+                let msats = usd.to_msats(price);
+                assert_eq!(msats.is_some(), price_micros(price).is_some(), "${} at {}", amount, price);
+                assert_eq!(Bitcoin::from_usd(usd, price).map(Bitcoin::to_msats), msats);
+                assert_eq!(usd.to_sats_at(price), msats.map(|msats| msats / 1000));
+                assert!((usd / price).to_f64().is_finite(), "${} / {}", amount, price);
+            }
+            for other in AMOUNTS {
+                assert!((usd / USD::from_f64(other)).is_finite(), "${} / ${}", amount, other);
+            }
+        }
+    }
+
+    #[test]
+    fn no_price_converts_to_nothing() {
+        for price in [0.0, -1.0, 1e-12, f64::INFINITY, f64::NAN] {
+            assert_eq!(USD::from_f64(100.0).to_msats(price), None, "{}", price);
+            assert_eq!(USD::from_bitcoin(Bitcoin::from_sats(100_000), price), USD::default(), "{}", price);
+        }
+    }
+
+    /// Prices from $1 to $10M, about 40 per decade
+    fn price_sweep() -> Vec<f64> {
+        let mut prices: Vec<f64> =
+            std::iter::successors(Some(1.0), |p| Some(p * 1.06)).take_while(|p| *p < 1e7).collect();
+        prices.push(1e7);
+        prices
+    }
+
+    /// Fiat amounts in micro-units up to $1M, mixing edge values with a fixed pseudo-random spread
+    fn micros_sweep() -> Vec<i64> {
+        let mut seed: u64 = 0x5eed;
+        let mut micros = vec![0, 1, 999_999, 1_000_000, 1_000_001, 1_000_000_000_000];
+        micros.extend((0..200).map(|_| {
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+            ((seed >> 24) % 1_000_000_000_000) as i64
+        }));
+        micros
+    }
+
+    #[test]
+    fn fiat_converts_to_bitcoin_within_a_msat() {
+        for price in price_sweep() {
+            let price_micros = price_micros(price).unwrap();
+            for micros in micros_sweep() {
+                let msats = USD::from_micros(micros).to_msats(price).unwrap();
+                // The exact amount is micros * MSATS_IN_BTC / price_micros; we must be its floor
+                let exact_numerator = micros as i128 * Bitcoin::MSATS_IN_BTC as i128;
+                assert!(msats as i128 * price_micros <= exact_numerator, "{} micros at {}", micros, price);
+                assert!((msats as i128 + 1) * price_micros > exact_numerator, "{} micros at {}", micros, price);
+            }
+        }
+    }
+
+    #[test]
+    fn fiat_round_trips_through_bitcoin_losing_at_most_a_msat() {
+        for price in price_sweep() {
+            let price_micros = price_micros(price).unwrap();
+            // What one msat is worth, rounded up, plus the micro-unit lost rounding back down
+            let msat_in_micros = (price_micros + Bitcoin::MSATS_IN_BTC as i128 - 1) / Bitcoin::MSATS_IN_BTC as i128;
+            for micros in micros_sweep() {
+                let usd = USD::from_micros(micros);
+                let back = USD::from_bitcoin(Bitcoin::from_usd(usd, price).unwrap(), price);
+                let lost = (usd - back).to_micros() as i128;
+                assert!((0..=msat_in_micros + 1).contains(&lost), "{} micros at {} lost {}", micros, price, lost);
+            }
+        }
+    }
+
+    #[test]
+    fn amounts_survive_serialization_exactly() {
+        for price in price_sweep() {
+            for micros in micros_sweep() {
+                let usd = USD::from_micros(micros);
+                let btc = Bitcoin::from_usd(usd, price).unwrap();
+                assert_eq!(serde_json::from_str::<USD>(&serde_json::to_string(&usd).unwrap()).unwrap(), usd);
+                assert_eq!(serde_json::from_str::<Bitcoin>(&serde_json::to_string(&btc).unwrap()).unwrap(), btc);
+            }
+        }
+    }
+
+    #[test]
+    fn amounts_keep_their_original_serialized_form() {
+        assert_eq!(serde_json::to_string(&USD::from_f64(12.345678)).unwrap(), "12.345678");
+        assert_eq!(serde_json::from_str::<USD>("100.5").unwrap(), USD::from_micros(100_500_000));
+
+        let btc = Bitcoin::from_msats(1_234_567);
+        assert_eq!(serde_json::to_value(btc).unwrap(), serde_json::json!({ "sats": 1_234, "msats": 1_234_567 }));
+        assert_eq!(serde_json::from_str::<Bitcoin>(r#"{"sats": 5}"#).unwrap(), Bitcoin::from_sats(5));
+    }
+
+    #[test]
+    fn usd_converts_to_whole_sats_rounding_down() {
+        assert_eq!(USD::from_f64(25.0).to_sats_at(50_000.0), Some(50_000));
+        assert_eq!(USD::from_f64(0.01).to_sats_at(100_000.0), Some(10));
+        // $1 at 30,000 is 3,333.33 sats
+        assert_eq!(USD::from_f64(1.0).to_sats_at(30_000.0), Some(3_333));
+        assert_eq!(USD::from_f64(0.0001).to_sats_at(50_000.0), Some(0));
+        assert_eq!(USD::from_f64(-25.0).to_sats_at(50_000.0), Some(50_000));
+        assert_eq!(USD::from_f64(25.0).to_sats_at(0.0), None);
+    }
+
+    #[test]
+    fn sats_at_a_price_are_worth_at_most_the_dollars_entered() {
+        for price in price_sweep() {
+            let sat_in_micros = (price_micros(price).unwrap() + 99_999_999) / 100_000_000;
+            for micros in micros_sweep() {
+                let usd = USD::from_micros(micros);
+                let sats = usd.to_sats_at(price).unwrap();
+                let back = USD::from_bitcoin(Bitcoin::from_sats(sats), price);
+                let short = (usd - back).to_micros() as i128;
+                assert!((0..=sat_in_micros + 1).contains(&short), "{} micros at {} short {}", micros, price, short);
+            }
+        }
+    }
+
+    #[test]
+    fn amounts_format_with_separators() {
+        assert_eq!(Bitcoin::from_sats(0).format_sats(), "0 sats");
+        assert_eq!(Bitcoin::from_sats(999).format_sats(), "999 sats");
+        assert_eq!(Bitcoin::from_sats(1_234_567).format_sats(), "1,234,567 sats");
+        assert_eq!(Bitcoin::from_sats(213_456_789).format_btc(), "2.13456789 BTC");
+        assert_eq!(Bitcoin::from_sats(123_450_000_000).format_btc(), "1,234.50000000 BTC");
+        assert_eq!(Bitcoin::from_msats(999).format(AmountUnit::Btc), "0.00000000 BTC");
+
+        assert_eq!(USD::from_f64(183_456.21).format_usd(), "$183,456.21");
+        assert_eq!(USD::from_f64(-1_234.5).format_usd(), "-$1,234.50");
+        assert_eq!(USD::from_f64(0.005).format_usd(), "$0.01");
+        assert_eq!(USD::from_f64(-0.004).format_usd(), "$0.00");
+    }
+
+    /// Sat amounts from nothing to all the bitcoin there will be, edges and a spread between
+    fn sats_sweep() -> Vec<u64> {
+        let mut seed: u64 = 0xb7c;
+        let mut sats = vec![0, 1, 999, 1_000, 99_999_999, 100_000_000, 100_000_001, 2_099_999_999_999_999];
+        sats.extend((0..200).map(|_| {
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+            (seed >> 12) % 2_100_000_000_000_000
+        }));
+        sats
+    }
+
+    #[test]
+    fn bitcoin_amounts_parse_back_from_either_format() {
+        for sats in sats_sweep() {
+            let btc = Bitcoin::from_sats(sats);
+            for unit in [AmountUnit::Sats, AmountUnit::Btc] {
+                let shown = btc.format(unit);
+                assert_eq!(Bitcoin::parse(&shown, AmountUnit::Sats), Ok(btc), "{}", shown);
+                assert_eq!(Bitcoin::parse(&shown, AmountUnit::Btc), Ok(btc), "{}", shown);
+            }
+        }
+    }
+
+    #[test]
+    fn usd_amounts_parse_back_from_their_format() {
+        for micros in micros_sweep() {
+            // Shown to the cent, so only whole cents come back exactly
+            let usd = USD::from_micros(micros / 10_000 * 10_000);
+            for usd in [usd, USD::default() - usd] {
+                assert_eq!(USD::parse(&usd.format_usd()), Ok(usd), "{}", usd.format_usd());
+            }
+        }
+    }
+
+    #[test]
+    fn bitcoin_input_takes_either_unit_and_separators() {
+        let parse = |input: &str, unit: AmountUnit| Bitcoin::parse(input, unit).map(Bitcoin::to_msats);
+
+        assert_eq!(parse("1,000 sats", AmountUnit::Btc), Ok(1_000_000));
+        assert_eq!(parse("1 sat", AmountUnit::Btc), Ok(1_000));
+        assert_eq!(parse("50_000", AmountUnit::Sats), Ok(50_000_000));
+        assert_eq!(parse("0.5 BTC", AmountUnit::Sats), Ok(50_000_000_000));
+        assert_eq!(parse(".5", AmountUnit::Btc), Ok(50_000_000_000));
+        assert_eq!(parse("0.00000000001", AmountUnit::Btc), Ok(1));
+        assert_eq!(parse("21,000,000 btc", AmountUnit::Sats), Ok(2_100_000_000_000_000_000));
+
+        for invalid in ["", "sats", "abc", "-1", "1.5 sats", "1e-3 btc", "0.000000000001 btc", "21000001 btc", "."] {
+            assert!(Bitcoin::parse(invalid, AmountUnit::Sats).is_err(), "{:?}", invalid);
+            assert!(Bitcoin::parse(invalid, AmountUnit::Btc).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn usd_input_takes_separators_and_a_dollar_sign() {
+        assert_eq!(USD::parse("$1,234.50"), Ok(USD::from_micros(1_234_500_000)));
+        assert_eq!(USD::parse(" 1 234.5 "), Ok(USD::from_micros(1_234_500_000)));
+        assert_eq!(USD::parse("-$3.20"), Ok(USD::from_micros(-3_200_000)));
+        assert_eq!(USD::parse("-3.20"), Ok(USD::from_micros(-3_200_000)));
+
+        for invalid in ["", "$", "abc", "inf", "NaN", "-$-5", "$$5"] {
+            assert!(USD::parse(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    fn channel_id() -> ChannelId {
+        ChannelId::from_bytes([9; 32])
+    }
+
+    fn sender() -> PublicKey {
+        use ldk_node::bitcoin::secp256k1::{Secp256k1, SecretKey};
+        PublicKey::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&[1; 32]).unwrap())
+    }
+
+    fn propose() -> StableMessage {
+        StableMessage::ProposeStable {
+            expected_usd: 123.45,
+            currency: Currency::EUR,
+            stable_fraction: 75.5,
+            proposer_receives: false,
+        }
+    }
+
+    fn accept() -> StableMessage {
+        StableMessage::AcceptStable {
+            expected_usd: 123.45,
+            fee_ppm: 2_500,
+            currency: Currency::GBP,
+            stable_fraction: 60.0,
+            float_sats: 42_000,
+            proposer_receives: false,
+        }
+    }
+
+    #[test]
+    fn payment_info_round_trips() {
+        let info = StabilityPaymentInfo {
+            channel_id: channel_id(),
+            target_usd: 100.0,
+            price_used: 50_000.0,
+            deviation_usd: -1.25,
+            fee_msats: 7_000,
+        };
+
+        assert_eq!(StabilityPaymentInfo::decode(&info.encode()), Some(info.clone()));
+        // Older peers send no fee
+        let legacy = &info.encode()[..StabilityPaymentInfo::LEGACY_ENCODED_LEN];
+        assert_eq!(StabilityPaymentInfo::decode(legacy), Some(StabilityPaymentInfo { fee_msats: 0, ..info }));
+    }
+
+    #[test]
+    fn payment_info_of_any_other_length_is_rejected() {
+        let encoded = StabilityPaymentInfo {
+            channel_id: channel_id(),
+            target_usd: 100.0,
+            price_used: 50_000.0,
+            deviation_usd: 1.0,
+            fee_msats: 0,
+        }
+        .encode();
+
+        for len in (0..encoded.len()).filter(|len| *len != StabilityPaymentInfo::LEGACY_ENCODED_LEN) {
+            assert_eq!(StabilityPaymentInfo::decode(&encoded[..len]), None, "{} bytes", len);
+        }
+        let mut longer = encoded.clone();
+        longer.push(0);
+        assert_eq!(StabilityPaymentInfo::decode(&longer), None);
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        for message in [
+            propose(),
+            accept(),
+            StableMessage::RejectStable { reason: "target too large".to_string() },
+            StableMessage::UnpegStable,
+        ] {
+            assert_eq!(StableMessage::decode(&message.encode()), Some(message.clone()), "{:?}", message);
+        }
+    }
+
+    #[test]
+    fn truncated_proposal_decodes_only_at_older_peers_boundaries() {
+        let encoded = propose().encode();
+        let older = |len: usize| StableMessage::decode(&encoded[..len]);
+
+        // Tag and amount only: USD, fully stable, proposer receiving
+        assert_eq!(
+            older(9),
+            Some(StableMessage::ProposeStable {
+                expected_usd: 123.45,
+                currency: Currency::USD,
+                stable_fraction: crate::stable::DEFAULT_STABLE_FRACTION,
+                proposer_receives: true,
+            })
+        );
+        assert!(matches!(older(12), Some(StableMessage::ProposeStable { currency: Currency::EUR, .. })));
+        assert!(matches!(older(14), Some(StableMessage::ProposeStable { proposer_receives: true, .. })));
+        for len in [0, 1, 5, 8, 10, 11, 13] {
+            assert_eq!(older(len), None, "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn truncated_accept_is_rejected_mid_field() {
+        let encoded = accept().encode();
+        // Tag 1, cents 8, fee 4, currency 3, fraction 2, float 8, role 1
+        for len in [1, 4, 11, 14, 17, 20, 25] {
+            assert_eq!(StableMessage::decode(&encoded[..len]), None, "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn malformed_messages_are_rejected() {
+        let mut bad_tag = propose().encode();
+        bad_tag[0] = 9;
+        assert_eq!(StableMessage::decode(&bad_tag), None);
+
+        let mut bad_role = propose().encode();
+        *bad_role.last_mut().unwrap() = 2;
+        assert_eq!(StableMessage::decode(&bad_role), None);
+
+        let mut bad_currency = propose().encode();
+        bad_currency[9..12].copy_from_slice(b"XYZ");
+        assert_eq!(StableMessage::decode(&bad_currency), None);
+
+        let mut bad_fraction = propose().encode();
+        bad_fraction[12..14].copy_from_slice(&10_001u16.to_be_bytes());
+        assert_eq!(StableMessage::decode(&bad_fraction), None);
+
+        assert_eq!(StableMessage::decode(&[StableMessage::REJECT_TAG, 0xff, 0xfe]), None);
+        assert_eq!(StableMessage::decode(&[]), None);
+    }
+
+    #[test]
+    fn envelope_round_trips_and_rejects_truncation() {
+        let envelope = StableMessageEnvelope { sender: sender(), message: accept(), signature: "d".repeat(104) };
+        let encoded = envelope.encode();
+
+        assert_eq!(StableMessageEnvelope::decode(&encoded), Some(envelope));
+        // Short of the pubkey and length, and cut inside the signature
+        for len in [0, 20, 33, 34, 100] {
+            assert_eq!(StableMessageEnvelope::decode(&encoded[..len]), None, "{} bytes", len);
+        }
+        let mut bad_sender = encoded.clone();
+        bad_sender[0] = 5;
+        assert_eq!(StableMessageEnvelope::decode(&bad_sender), None);
+    }
+}
@@ -0,0 +1,93 @@
+use egui::{ColorImage, Context, Id, TextureHandle, TextureOptions};
+use qrcode::{Color, QrCode};
+use std::time::Duration;
+
+/// Pixels per QR module
+const QR_SCALE: usize = 4;
+
+/// Seconds the result of a copy stays next to its button
+const COPY_FEEDBACK_SECS: f64 = 2.0;
+
+#[cfg(not(target_arch = "wasm32"))]
+lazy_static::lazy_static! {
+    /// Kept for the whole run: on Linux the copied text is served from it until something
+    /// else is copied
+    static ref CLIPBOARD: std::sync::Mutex<Option<arboard::Clipboard>> = std::sync::Mutex::new(None);
+}
+
+/// Puts `text` on the system clipboard. Falls back to egui's clipboard if that fails, and
+/// uses only egui's in the browser, where it is the one that works.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn copy_to_clipboard(ctx: &Context, text: &str) -> Result<(), String> {
+    let mut clipboard = CLIPBOARD.lock().unwrap();
+    let result = match clipboard.as_mut() {
+        Some(clipboard) => clipboard.set_text(text),
+        None => arboard::Clipboard::new().and_then(|mut new| {
+            new.set_text(text)?;
+            *clipboard = Some(new);
+            Ok(())
+        }),
+    };
+    result.map_err(|e| {
+        ctx.output_mut(|o| o.copied_text = text.to_string());
+        e.to_string()
+    })
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn copy_to_clipboard(ctx: &Context, text: &str) -> Result<(), String> {
+    ctx.output_mut(|o| o.copied_text = text.to_string());
+    Ok(())
+}
+
+/// Adds `button`, copying `text()` when it is clicked and then saying beside it for a
+/// moment whether that worked
+pub fn copy_button(ui: &mut egui::Ui, button: impl egui::Widget, text: impl FnOnce() -> String) -> egui::Response {
+    let response = ui.add(button);
+    let id = response.id.with("copied");
+    let now = ui.input(|i| i.time);
+    if response.clicked() {
+        let error = copy_to_clipboard(ui.ctx(), &text()).err();
+        ui.data_mut(|d| d.insert_temp(id, (now, error)));
+    }
+    if let Some((copied_at, error)) = ui.data(|d| d.get_temp::<(f64, Option<String>)>(id)) {
+        if now - copied_at < COPY_FEEDBACK_SECS {
+            match error {
+                None => ui.colored_label(egui::Color32::GREEN, "Copied ✓"),
+                Some(e) => ui.colored_label(egui::Color32::RED, format!("Copy failed: {}", e)),
+            };
+            ui.ctx().request_repaint_after(Duration::from_millis(250));
+        }
+    }
+    response
+}
+
+/// A QR code texture for `data`, cached in egui's memory by content so it is built once
+/// rather than every frame. None if `data` is too long to encode.
+pub fn qr_texture(ctx: &Context, data: &str) -> Option<TextureHandle> {
+    let id = Id::new(("qr_texture", data));
+    if let Some(texture) = ctx.data(|d| d.get_temp::<TextureHandle>(id)) {
+        return Some(texture);
+    }
+    let texture = ctx.load_texture(format!("qr_{:?}", id), qr_image(data)?, TextureOptions::LINEAR);
+    ctx.data_mut(|d| d.insert_temp(id, texture.clone()));
+    Some(texture)
+}
+
+/// `data` as a black-on-white QR code image
+fn qr_image(data: &str) -> Option<ColorImage> {
+    let code = QrCode::new(data).ok()?;
+    let width = code.width();
+    let size = width * QR_SCALE;
+    let colors = code.to_colors();
+    let mut pixels = Vec::with_capacity(size * size);
+    for y in 0..size {
+        for x in 0..size {
+            pixels.push(match colors[(y / QR_SCALE) * width + x / QR_SCALE] {
+                Color::Dark => egui::Color32::BLACK,
+                Color::Light => egui::Color32::WHITE,
+            });
+        }
+    }
+    Some(ColorImage { size: [size, size], pixels })
+}
@@ -1,92 +1,667 @@
 // src/user.rs
 use eframe::{egui, App, Frame};
 use ldk_node::bitcoin::Network;
+use ldk_node::lightning::ln::channelmanager::PaymentId;
+use ldk_node::lightning::ln::types::ChannelId;
 use ldk_node::lightning_invoice::Bolt11Invoice;
-use ldk_node::{Builder, Node};
+use ldk_node::payment::{PaymentDirection, PaymentKind};
+use ldk_node::bip39::Mnemonic;
+use ldk_node::config::Config;
+use ldk_node::{BuildError, Builder, ChannelDetails, Node};
 use ldk_node::{
     bitcoin::secp256k1::PublicKey,
     lightning::ln::msgs::SocketAddress,
 };
 use ureq::Agent;
-// use std::path::PathBuf;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
 use std::str::FromStr;
-use std::sync::{Arc, Mutex};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use image::{GrayImage, Luma};
-use qrcode::{QrCode, Color};
-use egui::TextureOptions;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use crate::stable::update_balances;
 use crate::types::*;
-use crate::price_feeds::{get_cached_price, get_latest_price};
+use crate::price_feeds::{self, get_cached_price, get_cached_price_in, get_latest_price, PriceStream};
 use crate::stable;
+use crate::stable::{PaymentResolution, StabilityAction, StableChannelEntry};
+use crate::encryption;
+use crate::history::{self, PaymentHistory};
+use crate::balance_chart::{BalanceChart, BalanceHistory};
+use crate::tax::CostBasis;
+use crate::status_log::{Severity, StatusLog};
+use crate::jit_fee::{self, JitFeeQuote};
+use crate::notify::WebhookNotifier;
+use crate::logging;
+use crate::onchain::{self, OnchainActivity};
+use crate::pending_payments::PendingPayments;
+use crate::snapshot::ChannelSnapshot;
+use crate::chain_source::ChainSource;
+use crate::network;
+use crate::dev_mode::{self, PriceOverrideControl};
+use crate::display;
+use crate::settings::{self, SettingsScreen};
+use crate::sync_status::SyncMonitor;
+use crate::shutdown;
+use crate::paths;
+use crate::payment_retry;
+use crate::seed::{self, BackupFlow, RestoreFlow, WalletSeed};
+use crate::lockfile::DataDirLock;
+use crate::lsp_config::{LspConfig, LspProfile, LspSettingsFlow};
+use crate::lsps1_order::{Lsps1OrderFlow, OrderAction};
+use crate::lsps2_settings::Lsps2Settings;
+use crate::reconnect::{self, Reconnector};
+use crate::backup::{self, Backups};
+use crate::channel_table::{self, ChannelAction, PendingCloses};
+use crate::node_ui::{self, NodeBalances, NodeUi};
+use crate::ui_util;
+use crate::vss::VssConfig;
+use crate::worker::{self, AppCommand, AppResult, CommandKind, Worker};
+use crate::admin::{self, AdminRequest, AdminServer};
+use tracing::{debug, error, info, warn};
 
-const USER_DATA_DIR: &str = "data/user";
 const USER_NODE_ALIAS: &str = "user";
 const USER_PORT: u16 = 9736;
-const DEFAULT_LSP_PUBKEY: &str = "02d3db21cb7de67f543c6bfa576e5122109325e308013d11cdfda18c6ce4f91a89";
-const DEFAULT_LSP_ADDRESS: &str = "54.210.112.22:9737";
 const EXPECTED_USD: f64 = 8.0;
 const DEFAULT_GATEWAY_PUBKEY: &str = "03809c504e5b078daeaa0052a1b10bd3f48f4d6547fcf7d689965de299b76988f2";
 const DEFAULT_NETWORK: &str = "signet";
 const DEFAULT_CHAIN_SOURCE_URL: &str = "https://mutinynet.com/api/";
 
+/// Smallest and largest JIT payment, in msats, of an LSP on the default LSPS2 settings.
+/// ldk-node doesn't expose the limits an LSP advertises, so an LSP configured otherwise
+/// refuses the request and the user sees that instead.
+fn jit_payment_limits_msats() -> (u64, u64) {
+    let settings = Lsps2Settings::default();
+    (settings.min_payment_size_msat, settings.max_payment_size_msat)
+}
+
+/// The stable channels file as last written, so unchanged state isn't rewritten
+static SAVED_STABLE_CHANNELS: Mutex<Option<String>> = Mutex::new(None);
+
+/// Set when the stable channels file couldn't be read, so it isn't overwritten with fresh state
+static STABLE_CHANNELS_FILE_LOCKED: AtomicBool = AtomicBool::new(false);
+
+/// Id of an entry from onboarding whose channel isn't open yet
+fn unassigned_channel_id() -> ChannelId {
+    ChannelId::from_bytes([0; 32])
+}
+
+/// The entry for `channel_id` among the stable channels
+fn find_stable_channel(channels: &mut [StableChannel], channel_id: ChannelId) -> Option<&mut StableChannel> {
+    channels.iter_mut().find(|sc| sc.channel_id == channel_id)
+}
+
+/// Gives the onboarding entry waiting on `counterparty` the channel that just became
+/// ready, unless the channel is already pegged. Returns whether an entry took it.
+fn assign_ready_channel(channels: &mut [StableChannel], channel_id: ChannelId, counterparty: PublicKey) -> bool {
+    if channels.iter().any(|sc| sc.channel_id == channel_id) {
+        return false;
+    }
+    match channels.iter_mut().find(|sc| sc.channel_id == unassigned_channel_id() && sc.counterparty == counterparty) {
+        Some(sc) => {
+            sc.channel_id = channel_id;
+            info!("Stable channel assigned to {}", channel_id);
+            true
+        }
+        None => false,
+    }
+}
+
+fn save_stable_channels(channels: &[StableChannel]) {
+    if STABLE_CHANNELS_FILE_LOCKED.load(Ordering::SeqCst) {
+        warn!("Not saving stable channels: existing file could not be read");
+        return;
+    }
+
+    let entries: Vec<StableChannelEntry> = channels.iter().map(StableChannelEntry::new).collect();
+
+    let file_path = paths::data_dir(USER_NODE_ALIAS).join("stablechannel.json");
+
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent).unwrap_or_else(|e| {
+            error!("Failed to create directory: {}", e);
+        });
+    }
+
+    match serde_json::to_string_pretty(&entries) {
+        Ok(json) => {
+            // Checks run every 30 seconds and mostly change nothing
+            let mut saved = SAVED_STABLE_CHANNELS.lock().unwrap();
+            match encryption::write_state_file_if_changed(&file_path, json, &mut saved) {
+                Ok(true) => debug!("Saved {} stable channels to {}", entries.len(), file_path.display()),
+                Ok(false) => {}
+                Err(e) => error!("Error writing stable channel file: {}", e),
+            }
+        }
+        Err(e) => error!("Error serializing stable channels: {}", e),
+    }
+}
+
+/// Restores the saved stable channels, each starting from `template`. Channels no longer
+/// on the node are dropped; the second value says whether any were. A file that can't be
+/// read or parsed is an error, and is left alone from then on.
+fn load_stable_channels(node: &Node, template: &StableChannel) -> Result<(Vec<StableChannel>, bool), String> {
+    let file_path = paths::data_dir(USER_NODE_ALIAS).join("stablechannel.json");
+
+    if !file_path.exists() {
+        info!("No existing stable channel file found.");
+        return Ok((Vec::new(), false));
+    }
+
+    let contents = match encryption::read_state_file(&file_path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            // Carrying on with saves would overwrite the saved channels with fresh ones
+            STABLE_CHANNELS_FILE_LOCKED.store(true, Ordering::SeqCst);
+            error!("Cannot open {}: {}", file_path.display(), e);
+            return Err(format!("Failed to read stable channels file: {}", e));
+        }
+    };
+
+    // Files from before several channels could be pegged hold a single entry
+    let entries = match serde_json::from_str::<Vec<StableChannelEntry>>(&contents)
+        .or_else(|_| serde_json::from_str::<StableChannelEntry>(&contents).map(|entry| vec![entry]))
+    {
+        Ok(entries) => entries,
+        Err(e) => {
+            STABLE_CHANNELS_FILE_LOCKED.store(true, Ordering::SeqCst);
+            error!("Error loading stable channel file: {}", e);
+            return Err(format!("Failed to parse stable channels file: {}", e));
+        }
+    };
+
+    let node_channels = node.list_channels();
+    let mut channels = Vec::new();
+    let mut vanished = false;
+    for entry in entries {
+        let channel_id = if entry.channel_id == unassigned_channel_id().to_string() {
+            unassigned_channel_id()
+        } else {
+            match node_channels.iter().find(|c| c.channel_id.to_string() == entry.channel_id) {
+                Some(channel) => channel.channel_id,
+                None => {
+                    warn!("Saved stable channel {} no longer exists", entry.channel_id);
+                    vanished = true;
+                    continue;
+                }
+            }
+        };
+        let mut sc = template.clone();
+        sc.channel_id = channel_id;
+        let untracked = entry.native_btc_original.is_none();
+        restore_stable_channel(entry, &mut sc);
+        if untracked && channel_id != unassigned_channel_id() {
+            // Files from before the provider's BTC leg was saved start tracking it now
+            update_balances(node, &mut sc);
+            sc.native_btc_original = sc.stable_provider_btc;
+            sc.native_btc = sc.stable_provider_btc;
+        }
+        info!("Loaded stable channel {}", sc.channel_id);
+        channels.push(sc);
+    }
+    Ok((channels, vanished))
+}
+
+/// Copies a saved entry's peg and running totals into `sc`
+fn restore_stable_channel(entry: StableChannelEntry, sc: &mut StableChannel) {
+    if let Some(counterparty) = entry.counterparty() {
+        sc.counterparty = counterparty;
+    }
+    entry.restore_into(sc);
+    if sc.currency != Currency::USD {
+        sc.latest_price = get_cached_price_in(sc.currency);
+    }
+    // Zero until there is a price; the first balance update fills it in
+    sc.expected_btc = Bitcoin::from_usd(sc.expected_usd, sc.latest_price).unwrap_or_default();
+    if let Some(original) = entry.native_btc_original {
+        sc.native_btc_original = Bitcoin::from_btc(original);
+        sc.native_btc = Bitcoin::from_btc(entry.native_btc);
+    }
+}
+
+/// The admin API, if `--admin-listen` asks for it, so the browser build can show this node
+fn start_admin(node: &Arc<Node>) -> Result<Option<AdminServer>, String> {
+    let Some(addr) = admin::listen_addr() else {
+        return Ok(None);
+    };
+    let token = std::env::var(admin::ADMIN_TOKEN_ENV)
+        .map_err(|_| format!("--admin-listen requires {} to be set", admin::ADMIN_TOKEN_ENV))?;
+    AdminServer::start(&addr, token, Arc::clone(node))
+        .map(Some)
+        .map_err(|e| format!("Cannot start admin API on {}: {}", addr, e))
+}
+
+#[cfg(feature = "user")]
+fn build_node(
+    data_dir: &Path,
+    wallet_seed: &WalletSeed,
+    network: Network,
+    chain_source: &ChainSource,
+    (lsp_pubkey, lsp_address): (PublicKey, SocketAddress),
+    lsp_token: Option<String>,
+    trust_zero_conf: bool,
+) -> Result<Arc<Node>, BuildError> {
+    let mut config = Config::default();
+    if trust_zero_conf {
+        info!("Accepting zero-conf channels from LSP {}", lsp_pubkey);
+        config.trusted_peers_0conf.push(lsp_pubkey);
+    }
+    let mut builder = Builder::from_config(config);
+    wallet_seed.configure(&mut builder);
+    builder.set_network(network);
+    chain_source.configure(&mut builder);
+    builder.set_storage_dir_path(data_dir.display().to_string());
+    let port = settings::get().node.listening_port.unwrap_or(USER_PORT);
+    builder.set_listening_addresses(vec![format!("127.0.0.1:{}", port).parse().unwrap()]).unwrap();
+    builder.set_node_alias(USER_NODE_ALIAS.to_string());
+
+    builder.set_liquidity_source_lsps2(lsp_pubkey, lsp_address.clone(), lsp_token);
+    builder.set_liquidity_source_lsps1(lsp_pubkey, lsp_address, None);
+
+    match VssConfig::from_env() {
+        Some(vss) => {
+            info!("Persisting node state to {}", vss);
+            vss.build(&builder).map(Arc::new)
+        }
+        None => builder.build().map(Arc::new),
+    }
+}
+
+/// Where node state is persisted, for the UI
+fn storage_label(data_dir: &Path) -> String {
+    match VssConfig::from_env() {
+        Some(vss) => vss.to_string(),
+        None => format!("local ({})", data_dir.display()),
+    }
+}
+
+/// Card for one stable channel on the main screen. Returns whether the user picked it
+/// for the target, top-up and withdraw forms.
+fn show_stable_card(ui: &mut egui::Ui, sc: &StableChannel, snapshot: &[ChannelDetails], synced_once: bool, selected: bool) -> bool {
+    let mut picked = false;
+    ui.group(|ui| {
+        ui.horizontal(|ui| {
+            let channel = if sc.channel_id == unassigned_channel_id() {
+                "New channel (opening)".to_string()
+            } else {
+                format!("Channel {}…", &sc.channel_id.to_string()[..8])
+            };
+            ui.label(egui::RichText::new(channel).strong());
+            if selected {
+                ui.label(egui::RichText::new("(managing)").size(12.0).color(egui::Color32::GRAY));
+            } else if ui.small_button("Manage").clicked() {
+                picked = true;
+            }
+        });
+        let stable_btc = if sc.is_stable_receiver {
+            sc.stable_receiver_btc
+        } else {
+            sc.stable_provider_btc
+        };
+        let stable_usd = if sc.is_stable_receiver {
+            sc.stable_receiver_usd
+        } else {
+            sc.stable_provider_usd
+        };
+        let unconfirmed = snapshot
+            .iter()
+            .any(|c| c.channel_id == sc.channel_id && channel_table::is_unconfirmed_zero_conf(c));
+        if synced_once {
+            let mut balance = egui::RichText::new(sc.currency.format(stable_usd.to_f64())).size(24.0).strong();
+            if unconfirmed {
+                balance = balance.color(egui::Color32::YELLOW);
+            }
+            ui.add(egui::Label::new(balance));
+            if unconfirmed {
+                ui.colored_label(egui::Color32::YELLOW, "Held in a 0-conf channel whose funding hasn't confirmed yet");
+            }
+        } else {
+            // Balances read before the first sync are zero, not empty
+            ui.label(egui::RichText::new("Syncing…").size(24.0).strong());
+        }
+        if sc.is_stable_receiver {
+            ui.label(format!("Agreed Peg {}: {}", sc.currency, sc.currency.format(sc.expected_usd.to_f64())));
+        } else {
+            // Our side floats; the peg is the counterparty's
+            ui.label(format!("Pegged for your peer: {}", sc.currency.format(sc.expected_usd.to_f64())));
+            ui.label(
+                egui::RichText::new("You provide stability, so your balance absorbs the price moves")
+                    .size(12.0)
+                    .color(egui::Color32::GRAY),
+            );
+        }
+        if let Some(unpegged_at) = sc.unpegged_at {
+            ui.colored_label(
+                egui::Color32::YELLOW,
+                format!("Unpegged on {}: balance floats with BTC", history::format_date(unpegged_at)),
+            );
+        }
+        if let Some(until) = sc.suspended_until {
+            let minutes_left = (until - SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64).max(0) / 60 + 1;
+            egui::Frame::none().fill(egui::Color32::DARK_RED).inner_margin(6.0).show(ui, |ui| {
+                ui.colored_label(
+                    egui::Color32::WHITE,
+                    format!(
+                        "Peg paused: {}. No stability payments are made for the next {} minutes, in case the price is wrong.",
+                        sc.suspension_reason.as_deref().unwrap_or("unusual price move"),
+                        minutes_left
+                    ),
+                );
+            });
+        }
+        if sc.stable_fraction < stable::DEFAULT_STABLE_FRACTION {
+            let float_usd = USD::from_bitcoin(sc.float_btc, sc.latest_price);
+            ui.label(format!(
+                "Stable part ({:.0}%): {}",
+                sc.stable_fraction,
+                sc.currency.format((stable_usd - float_usd).to_f64())
+            ));
+            ui.label(format!(
+                "Floating part: {} ({})",
+                display::btc(sc.float_btc),
+                sc.currency.format(float_usd.to_f64())
+            ));
+        }
+        ui.label(format!("Bitcoin: {}", display::btc(stable_btc)));
+        if let Some(runway) = &sc.runway {
+            let color = if runway.min_percent() < stable::DEFAULT_LOW_RUNWAY_PERCENT {
+                egui::Color32::YELLOW
+            } else {
+                egui::Color32::GRAY
+            };
+            ui.label(
+                egui::RichText::new(format!("Peg holds through price moves of {}", stable::format_runway(runway)))
+                    .size(12.0)
+                    .color(color),
+            )
+            .on_hover_text("Beyond this the side that must pay runs out of funds, and your balance starts to float");
+        }
+        // The peg covers our whole side of the channel, reserve included
+        let reserve_sats = snapshot
+            .iter()
+            .find(|c| c.channel_id == sc.channel_id)
+            .and_then(|c| c.unspendable_punishment_reserve)
+            .unwrap_or(0);
+        if reserve_sats > 0 {
+            ui.label(
+                egui::RichText::new(format!(
+                    "Includes {} of channel reserve that can't be sent",
+                    display::sats(reserve_sats)
+                ))
+                .size(12.0)
+                .color(egui::Color32::GRAY),
+            );
+        }
+        ui.label(
+            egui::RichText::new(format!(
+                "Stability payments: {} | Received: {} | Sent: {}",
+                sc.payment_count,
+                display::sats(sc.total_paid_to_user_msats / 1000),
+                display::sats(sc.total_paid_to_lsp_msats / 1000),
+            ))
+            .size(12.0)
+            .color(egui::Color32::GRAY),
+        );
+        let risk_color = match stable::risk_band(sc) {
+            stable::RiskBand::Low => egui::Color32::GREEN,
+            stable::RiskBand::Elevated => egui::Color32::YELLOW,
+            stable::RiskBand::Suspended => egui::Color32::RED,
+        };
+        ui.label(
+            egui::RichText::new(format!("● Risk level: {}", sc.risk_level))
+                .size(12.0)
+                .color(risk_color),
+        );
+        if sc.stability_fee_ppm > 0 {
+            ui.label(
+                egui::RichText::new(format!(
+                    "Stability fee: {:.2}% per adjustment ({} paid so far)",
+                    sc.stability_fee_ppm as f64 / 10_000.0,
+                    display::sats(sc.accrued_fee_msats / 1000)
+                ))
+                .size(12.0)
+                .color(egui::Color32::GRAY),
+            );
+        }
+        if !sc.missed_settlements.is_empty() {
+            ui.label(
+                egui::RichText::new(format!(
+                    "Pending settlement: {}",
+                    sc.currency.format(stable::missed_settlement_usd(sc))
+                ))
+                .size(12.0)
+                .color(egui::Color32::YELLOW),
+            );
+        }
+    });
+    picked
+}
+
+/// Our stable balance summed per currency across the pegged channels, e.g. "$12.00 + €5.00".
+/// Channels where we provide stability hold no stable balance of ours, so are left out.
+fn total_stable_balance(channels: &[StableChannel]) -> String {
+    let mut totals: Vec<(Currency, f64)> = Vec::new();
+    for sc in channels.iter().filter(|sc| sc.unpegged_at.is_none() && sc.is_stable_receiver) {
+        match totals.iter_mut().find(|(currency, _)| *currency == sc.currency) {
+            Some((_, total)) => *total += sc.stable_receiver_usd.to_f64(),
+            None => totals.push((sc.currency, sc.stable_receiver_usd.to_f64())),
+        }
+    }
+    totals.iter().map(|(currency, total)| currency.format(*total)).collect::<Vec<_>>().join(" + ")
+}
+
+/// What the invoice on the waiting screen was requested as, so it can be requested again
+/// for the same amount once it expires
+#[derive(Clone, Copy)]
+enum WaitingInvoice {
+    Jit,
+    TopUp { jit: bool },
+}
+
+/// How long the "invoice refreshed" notice stays up
+const REFRESH_NOTICE: Duration = Duration::from_secs(10);
+
+/// Least time between requests for a new invoice when one keeps failing
+const REFRESH_RETRY: Duration = Duration::from_secs(30);
+
+/// A peer's proposal to peg one of our channels with it
+struct PegRequest {
+    channel_id: ChannelId,
+    counterparty: PublicKey,
+    expected_usd: f64,
+    currency: Currency,
+    stable_fraction: f64,
+    /// Whether we would hold the stable side; otherwise we provide stability
+    we_receive: bool,
+}
+
 #[cfg(feature = "user")]
 pub struct UserApp {
     pub node: Arc<Node>,
-    pub status_message: String,
+    /// Messages from events and actions, newest last
+    pub status_log: StatusLog,
     pub btc_price: f64,
     show_onboarding: bool,
     qr_texture: Option<egui::TextureHandle>,
     waiting_for_payment: bool,
-    stable_channel: Arc<Mutex<StableChannel>>,
-    background_started: bool,
+    /// What the invoice on the waiting screen is for, and the invoice itself
+    waiting_request: Option<WaitingInvoice>,
+    waiting_invoice: Option<Bolt11Invoice>,
+    /// When an expired invoice was last requested again, until the new one arrives
+    refresh_requested_at: Option<Instant>,
+    /// When a new invoice replaced an expired one, for a brief notice
+    invoice_refreshed_at: Option<Instant>,
+    /// Pegged channels, one entry per channel id. An entry from onboarding has the zero
+    /// id until its channel is ready.
+    stable_channels: Arc<Mutex<Vec<StableChannel>>>,
+    /// Channel whose card the target, top-up and withdraw forms act on
+    selected_channel: ChannelId,
+    /// The LSP the node was built with, counterparty of onboarding channels
+    lsp_pubkey: PublicKey,
+    background_thread: Option<JoinHandle<()>>,
+    shutdown: Arc<AtomicBool>,
+    /// Set when a stability payment fails, so the background loop retries it without waiting
+    /// out the check interval
+    retry_now: Arc<AtomicBool>,
+    data_dir_lock: Option<DataDirLock>,
+    wallet_seed: WalletSeed,
+    backup_flow: Option<BackupFlow>,
+    restore_flow: Option<RestoreFlow>,
+    /// The data directory was empty at startup, so the current wallet was created this run
+    fresh_wallet: bool,
+    /// Showing chain resync progress after a restore
+    restore_syncing: bool,
+    payment_history: PaymentHistory,
+    pending_payments: PendingPayments,
+    onchain_activity: OnchainActivity,
+    channel_snapshot: ChannelSnapshot,
+    /// Copies of the data dir, on request and on a timer
+    backups: Backups,
+    worker: Worker,
+    /// JSON API for the browser build, when started with --admin-listen
+    admin: Option<AdminServer>,
+    /// Block height against the chain tip, and sync failures
+    sync_monitor: SyncMonitor,
+    price_stream: PriceStream,
+    network: Network,
+    /// Backend the node reads the chain from
+    chain_source: ChainSource,
+    stability_tx: mpsc::Sender<StabilityAction>,
+    /// Off unless webhook URLs are configured
+    webhooks: WebhookNotifier,
+    stability_rx: mpsc::Receiver<StabilityAction>,
+    negotiation_status: String,
+    last_proposal_attempt: Option<Instant>,
+    proposal_rejected: bool,
 
     // Common UI fields
-    pub invoice_amount: String,
-    pub invoice_result: String,
-    pub invoice_to_pay: String,
-    pub on_chain_address: String,
-    pub on_chain_amount: String,
-    
-    // Balance fields
-    pub lightning_balance_btc: f64,
-    pub onchain_balance_btc: f64,
-    pub lightning_balance_usd: f64,
-    pub onchain_balance_usd: f64,
-    pub total_balance_btc: f64,
-    pub total_balance_usd: f64,
+    pub node_ui: NodeUi,
+    pub target_usd_input: String,
+    /// Currency of the target in `target_usd_input`
+    pub target_currency: Currency,
+    /// Percentage of the balance to peg, as typed
+    pub stable_fraction_input: String,
+    /// The unpeg button was pressed once and is waiting for confirmation
+    confirm_unpeg: bool,
+    /// Amount to stabilize typed on the onboarding screen, in USD
+    jit_amount_input: String,
+    /// Payment hash of the onboarding JIT invoice; its payment sets the target
+    jit_hash: Option<String>,
+    /// Amount typed into the top-up form, in the peg's currency
+    top_up_input: String,
+    /// Payment hash of the outstanding top-up invoice
+    top_up_hash: Option<String>,
+    /// Channel the outstanding top-up invoice adds to
+    top_up_channel: Option<ChannelId>,
+    /// Channel a received top-up raised and its target from before, kept until the LSP answers
+    top_up_previous_target: Option<(ChannelId, USD)>,
+    /// Invoice pasted into the withdraw form
+    withdraw_invoice: String,
+    /// Amount for invoices without one, in the peg's currency
+    withdraw_amount_input: String,
+    /// Payment hash of the withdrawal in flight
+    withdraw_hash: Option<String>,
+    /// Channel the withdrawal in flight lowered and its target from before, restored if
+    /// the payment fails
+    withdraw_previous_target: Option<(ChannelId, USD)>,
+    /// Amount in msats of an ordinary payment sent while pegged, waiting for the user to
+    /// say whether it should lower the target
+    spend_prompt: Option<u64>,
+    /// A peer's peg proposal waiting for the user to accept or reject it
+    peg_request: Option<PegRequest>,
+    /// Channels designated from the table are pegged for the counterparty, with us
+    /// providing stability, instead of for us
+    provide_stability: bool,
+    lsp_config: LspConfig,
+    /// The profile the running node was built with
+    lsp_in_use: LspProfile,
+    lsp_settings: Option<LspSettingsFlow>,
+    /// Open in place of the main screen while Some
+    settings_screen: Option<SettingsScreen>,
+    lsp_test_status: String,
+    /// LSP fee quoted for the JIT invoice being shown, checked against what the LSP takes
+    jit_quote: Option<JitFeeQuote>,
+    /// Inbound liquidity order with the LSP, and whether its screen is open
+    lsps1_flow: Lsps1OrderFlow,
+    show_lsps1: bool,
+    /// Developer-mode control forcing the BTC price
+    price_override: PriceOverrideControl,
+    /// Balances sampled by the background loop, charted on the main screen
+    balance_history: Arc<Mutex<BalanceHistory>>,
+    balance_chart: BalanceChart,
+    /// Cooperative closes in flight, for offering a force-close when one stalls
+    pending_closes: PendingCloses,
+    /// Channel waiting on the user to confirm a force-close
+    force_close_candidate: Option<ChannelDetails>,
+    /// Where node state is persisted: the local data dir or a VSS server
+    storage: String,
+    /// Whether the LSP was connected at the background loop's last check
+    lsp_connected: Arc<AtomicBool>,
 }
 
 #[cfg(feature = "user")]
 impl UserApp {
-    pub fn new() -> Self {
-        println!("Initializing user node...");
+    /// Builds and starts the node. Errors are shown in place of the wallet rather than
+    /// panicking, since a misconfigured or unreachable VSS server is a likely cause.
+    pub fn new() -> Result<Self, String> {
+        // Ask for the state passphrase up front, before the GUI takes over the terminal
+        encryption::passphrase();
+
+        info!("Initializing user node...");
+
+        let user_data_dir = paths::data_dir(USER_NODE_ALIAS);
+        let data_dir_lock = match DataDirLock::acquire(&user_data_dir) {
+            Ok(lock) => lock,
+            Err(e) => {
+                error!("Cannot start: {} ({})", e, user_data_dir.display());
+                std::process::exit(1);
+            }
+        };
+        payment_retry::init(&user_data_dir);
+        settings::init(&user_data_dir);
+        let lsp_config = LspConfig::load(&user_data_dir);
+        let lsp_endpoint = lsp_config.active_endpoint();
+        let lsp_pubkey = lsp_endpoint.0;
+        info!("Using LSP {}", lsp_config.active_or_default());
 
-        let user_data_dir = USER_DATA_DIR;
-        let lsp_pubkey = PublicKey::from_str(DEFAULT_LSP_PUBKEY).unwrap();
+        backup::confirm_restored_start(&user_data_dir);
 
-        let mut builder = Builder::new();
-        builder.set_network(Network::Signet);
-        builder.set_chain_source_esplora(DEFAULT_CHAIN_SOURCE_URL.to_string(), None);
-        builder.set_storage_dir_path(user_data_dir.to_string());
-        builder.set_listening_addresses(vec![format!("127.0.0.1:{}", USER_PORT).parse().unwrap()]).unwrap();
-        builder.set_node_alias(USER_NODE_ALIAS.to_string());
+        let fresh_wallet = !seed::wallet_exists(&user_data_dir);
+        let wallet_seed = WalletSeed::load_or_create(&user_data_dir);
 
-        builder.set_liquidity_source_lsps2(
-            lsp_pubkey,
-            SocketAddress::from_str(DEFAULT_LSP_ADDRESS).unwrap(),
-            None,
-        );
-        builder.set_liquidity_source_lsps1(
-            lsp_pubkey,
-            SocketAddress::from_str(DEFAULT_LSP_ADDRESS).unwrap(),
-            None,
-        );
+        let network = network::from_args_or_env(DEFAULT_NETWORK).map_err(|e| {
+            error!("Invalid network: {}", e);
+            e
+        })?;
+        info!("Using network {}", network);
+        let chain_source = ChainSource::from_args_or_env(DEFAULT_CHAIN_SOURCE_URL)
+            .and_then(|source| source.validate(network).map(|()| source))
+            .map_err(|e| {
+                error!("Invalid chain source: {}", e);
+                e
+            })?;
 
-        let node = Arc::new(builder.build().expect("Failed to build node"));
-        node.start().expect("Failed to start node");
-        println!("User node started: {}", node.node_id());
+        let lsp_profile = lsp_config.active_or_default();
+        let node = build_node(
+            &user_data_dir,
+            &wallet_seed,
+            network,
+            &chain_source,
+            lsp_endpoint,
+            lsp_profile.lsps2_token(),
+            lsp_profile.trust_zero_conf,
+        )
+        .map_err(|e| {
+            error!("Failed to build node: {:?}", e);
+            format!("Failed to build the node: {:?}", e)
+        })?;
+        node.start().map_err(|e| {
+            error!("Failed to start node: {}", e);
+            format!("Failed to start the node: {}", e)
+        })?;
+        info!("User node started: {}", node.node_id());
 
         let mut btc_price = crate::price_feeds::get_cached_price();
         if btc_price <= 0.0 {
@@ -95,61 +670,138 @@ impl UserApp {
             }
         }
 
-        let sc_init = StableChannel {
-            channel_id: ldk_node::lightning::ln::types::ChannelId::from_bytes([0; 32]),
+        let template = StableChannel {
             counterparty: lsp_pubkey,
             is_stable_receiver: true,
             expected_usd: USD::from_f64(EXPECTED_USD),
-            expected_btc: Bitcoin::from_usd(USD::from_f64(EXPECTED_USD), btc_price),
-            stable_receiver_btc: Bitcoin::default(),
-            stable_receiver_usd: USD::default(),
-            stable_provider_btc: Bitcoin::default(),
-            stable_provider_usd: USD::default(),
+            expected_btc: Bitcoin::from_usd(USD::from_f64(EXPECTED_USD), btc_price).unwrap_or_default(),
             latest_price: btc_price,
-            risk_level: 0,
-            payment_made: false,
             timestamp: 0,
-            formatted_datetime: "2021-06-01 12:00:00".to_string(),
-            sc_dir: "/".to_string(),
-            prices: String::new(),
+            sc_dir: user_data_dir.display().to_string(),
+            ..Default::default()
         };
-        let stable_channel = Arc::new(Mutex::new(sc_init));
-
-        let show_onboarding = node.list_channels().is_empty();
+        let (stable_channels, vanished, load_error) = match load_stable_channels(&node, &template) {
+            Ok((stable_channels, vanished)) => (stable_channels, vanished, None),
+            Err(e) => (Vec::new(), false, Some(e)),
+        };
+        let show_onboarding = node.list_channels().is_empty() || (stable_channels.is_empty() && vanished);
+        let selected = stable_channels.first().unwrap_or(&template);
+        let selected_channel = selected.channel_id;
+        let target_usd_input = format!("{:.2}", selected.expected_usd.to_f64());
+        let target_currency = if stable_channels.is_empty() { display::fiat() } else { selected.currency };
+        let stable_fraction_input = format!("{}", selected.stable_fraction);
+        let (stability_tx, stability_rx) = mpsc::channel();
 
-        let app = Self {
+        let mut app = Self {
             node: Arc::clone(&node),
-            status_message: String::new(),
-            invoice_result: String::new(),
+            status_log: StatusLog::default(),
             show_onboarding,
             qr_texture: None,
             waiting_for_payment: false,
-            stable_channel: Arc::clone(&stable_channel),
-            background_started: false,
+            waiting_request: None,
+            waiting_invoice: None,
+            refresh_requested_at: None,
+            invoice_refreshed_at: None,
+            stable_channels: Arc::new(Mutex::new(stable_channels)),
+            selected_channel,
+            lsp_pubkey,
+            background_thread: None,
+            shutdown: Arc::new(AtomicBool::new(false)),
+            retry_now: Arc::new(AtomicBool::new(false)),
+            data_dir_lock: Some(data_dir_lock),
+            wallet_seed,
+            backup_flow: None,
+            restore_flow: None,
+            fresh_wallet,
+            restore_syncing: false,
+            payment_history: PaymentHistory::load(&user_data_dir),
+            pending_payments: PendingPayments::default(),
+            balance_history: Arc::new(Mutex::new(BalanceHistory::load(&user_data_dir))),
+            balance_chart: BalanceChart::default(),
+            onchain_activity: OnchainActivity::new(chain_source.esplora_url()),
+            channel_snapshot: ChannelSnapshot::new(),
+            backups: Backups::from_env(&user_data_dir),
+            worker: Worker::spawn(Arc::clone(&node)),
+            admin: start_admin(&node)?,
+            sync_monitor: SyncMonitor::spawn(Arc::clone(&node), chain_source.clone()),
+            price_stream: PriceStream::start(),
+            node_ui: NodeUi::new(network, &user_data_dir, "0", "0", chain_source.clone()),
+            network,
+            chain_source,
+            stability_tx,
+            webhooks: WebhookNotifier::from_env(),
+            stability_rx,
+            negotiation_status: String::new(),
+            last_proposal_attempt: None,
+            proposal_rejected: false,
             btc_price,
-            invoice_amount: "0".to_string(),        
-            invoice_to_pay: String::new(),
-            on_chain_address: String::new(),
-            on_chain_amount: "0".to_string(),  
-            lightning_balance_btc: 0.0,
-            onchain_balance_btc: 0.0,
-            lightning_balance_usd: 0.0,
-            onchain_balance_usd: 0.0,
-            total_balance_btc: 0.0,
-            total_balance_usd: 0.0,
+            target_usd_input,
+            target_currency,
+            stable_fraction_input,
+            confirm_unpeg: false,
+            jit_amount_input: format!("{:.2}", EXPECTED_USD),
+            jit_hash: None,
+            top_up_input: String::new(),
+            top_up_hash: None,
+            top_up_channel: None,
+            top_up_previous_target: None,
+            withdraw_invoice: String::new(),
+            withdraw_amount_input: String::new(),
+            withdraw_hash: None,
+            withdraw_previous_target: None,
+            spend_prompt: None,
+            peg_request: None,
+            provide_stability: false,
+            lsp_in_use: lsp_config.active_or_default(),
+            lsp_connected: Arc::new(AtomicBool::new(false)),
+            lsp_config,
+            lsp_settings: None,
+            settings_screen: None,
+            pending_closes: PendingCloses::from_env(),
+            force_close_candidate: None,
+            lsp_test_status: String::new(),
+            jit_quote: None,
+            lsps1_flow: Lsps1OrderFlow::load(&user_data_dir),
+            show_lsps1: false,
+            price_override: PriceOverrideControl::default(),
+            storage: storage_label(&user_data_dir),
         };
+        if let Some(e) = load_error {
+            app.status_log.error(e);
+        }
 
         {
-            let mut sc = app.stable_channel.lock().unwrap();
-            stable::check_stability(&app.node, &mut sc, btc_price);
-            update_balances(&app.node, &mut sc);
+            let mut channels = app.stable_channels.lock().unwrap();
+            for sc in channels.iter_mut().filter(|sc| sc.channel_id != unassigned_channel_id()) {
+                if sc.peg_agreed && sc.unpegged_at.is_none() {
+                    let price = get_cached_price_in(sc.currency);
+                    let action = stable::check_stability_and_log(&*app.node, sc, price);
+                    app.status_log.push(Severity::of_action(&action), action.to_string());
+                }
+                update_balances(&*app.node, sc);
+            }
+            save_stable_channels(&channels);
         }
 
-        let node_arc = Arc::clone(&app.node);
-        let sc_arc = Arc::clone(&app.stable_channel);
+        Ok(app)
+    }
+    fn start_background_if_needed(&mut self) {
+        if self.background_thread.is_some() {
+            return;
+        }
+
+        let node_arc = Arc::clone(&self.node);
+        let channels_arc = Arc::clone(&self.stable_channels);
+        let balance_history = Arc::clone(&self.balance_history);
+        let stability_tx = self.stability_tx.clone();
+        let webhooks = self.webhooks.queue();
+        let shutdown_flag = Arc::clone(&self.shutdown);
+        let retry_now = Arc::clone(&self.retry_now);
+        let lsp_connected = Arc::clone(&self.lsp_connected);
+        let lsp_endpoint = self.lsp_in_use.endpoint().ok();
 
-        std::thread::spawn(move || {
-            use std::{thread::sleep, time::{Duration, SystemTime, UNIX_EPOCH}};
+        let handle = std::thread::spawn(move || {
+            let mut reconnector = Reconnector::new();
 
             fn current_unix_time() -> i64 {
                 SystemTime::now()
@@ -160,264 +812,1380 @@ impl UserApp {
                     .unwrap_or(0)
             }
 
-            loop {
-                let price = match get_latest_price(&ureq::Agent::new()) {
-                    Ok(p) if p > 0.0 => p,
-                    _ => crate::price_feeds::get_cached_price()
-                };
+            while !shutdown_flag.load(Ordering::SeqCst) {
+                // Stability payments and JIT invoices both need the LSP, so keep it connected
+                if let Some((lsp_pubkey, lsp_address)) = &lsp_endpoint {
+                    let now = Instant::now();
+                    if reconnector.poll(reconnect::is_peer_connected(&node_arc, lsp_pubkey), now) {
+                        let result = node_arc.connect(*lsp_pubkey, lsp_address.clone(), true);
+                        match &result {
+                            Ok(()) => info!("Reconnected to LSP {}", lsp_pubkey),
+                            Err(e) => warn!("Failed to reconnect to LSP {}: {}", lsp_pubkey, e),
+                        }
+                        reconnector.record_attempt(result.is_ok(), now);
+                    }
+                    lsp_connected.store(reconnector.is_connected(), Ordering::SeqCst);
+                }
 
-                if price > 0.0 && !node_arc.list_channels().is_empty() {
-                    if let Ok(mut sc) = sc_arc.lock() {
-                        stable::check_stability(&*node_arc, &mut sc, price);
-                        update_balances(&*node_arc, &mut sc);
+                // Use the streamed price while it is live; otherwise try to get the latest
+                // price in each peg's currency first
+                let currencies: Vec<Currency> =
+                    channels_arc.lock().map(|channels| channels.iter().map(|sc| sc.currency).collect()).unwrap_or_default();
+                let mut prices: HashMap<Currency, f64> = HashMap::new();
+                for currency in currencies {
+                    prices.entry(currency).or_insert_with(|| {
+                        if price_feeds::is_streaming(currency) {
+                            price_feeds::get_cached_price_in(currency)
+                        } else {
+                            match price_feeds::get_latest_price_in(&ureq::Agent::new(), currency) {
+                                Ok(p) if p > 0.0 => p,
+                                _ => price_feeds::get_cached_price_in(currency)
+                            }
+                        }
+                    });
+                }
 
-                        sc.latest_price = price;
-                        sc.timestamp = current_unix_time();
+                // Only proceed with active channels, and for each peg only with a valid price
+                if !node_arc.list_channels().is_empty() {
+                    if let Ok(mut channels) = channels_arc.lock() {
+                        let now = current_unix_time();
+                        for sc in channels.iter_mut().filter(|sc| sc.channel_id != unassigned_channel_id()) {
+                            let price = prices.get(&sc.currency).copied().unwrap_or(0.0);
+                            if price <= 0.0 {
+                                continue;
+                            }
+                            crate::stable::record_price(sc, now, price);
+                            if sc.peg_agreed && sc.unpegged_at.is_none() {
+                                let action = crate::stable::check_stability_and_log(&*node_arc, sc, price);
+                                webhooks.notify(sc, &action, price);
+                                let _ = stability_tx.send(action);
+                            }
+                            crate::stable::update_balances(&*node_arc, sc);
+                            sc.latest_price = price;
+                            sc.timestamp = now;
+                        }
+                        save_stable_channels(&channels);
+
+                        if let Ok(mut history) = balance_history.lock() {
+                            history.record(&channels, now);
+                        }
                     }
                 }
-                sleep(Duration::from_secs(30));
+
+                // Sleep between checks, waking early on shutdown or to retry a failed payment
+                let check_interval = Duration::from_secs(settings::get().stability.check_interval_secs);
+                if shutdown::sleep_until_woken(&shutdown_flag, &retry_now, check_interval) {
+                    break;
+                }
             }
         });
 
-        app
+        self.background_thread = Some(handle);
     }
-    // fn get_app_data_dir(component: &str) -> PathBuf {
-    //     let mut path = dirs::data_local_dir()
-    //         .unwrap_or_else(|| PathBuf::from("./data"))
-    //         .join("com.stablechannels");
-        
-    //     if !component.is_empty() {
-    //         path = path.join(component);
-    //     }
-        
-    //     // Ensure the directory exists
-    //     std::fs::create_dir_all(&path).unwrap_or_else(|e| {
-    //         eprintln!("Warning: Failed to create data directory: {}", e);
-    //     });
-        
-    //     path
-    // }
-  
-    fn start_background_if_needed(&mut self) {
-        if self.background_started {
+
+    /// Answers admin API requests that need the stable channel state. Designating and
+    /// removing are for the LSP; the user app pegs through negotiation with its counterparty.
+    fn process_admin_requests(&mut self) {
+        let Some(admin) = self.admin.as_ref() else {
             return;
+        };
+        while let Some(pending) = admin.try_recv() {
+            match &pending.request {
+                AdminRequest::ListStableChannels => {
+                    let channels: Vec<serde_json::Value> = self
+                        .stable_channels
+                        .lock()
+                        .unwrap()
+                        .iter()
+                        .filter(|sc| sc.channel_id != unassigned_channel_id())
+                        .map(|sc| serde_json::json!({
+                            "channel_id": sc.channel_id.to_string(),
+                            "counterparty": sc.counterparty.to_string(),
+                            "is_stable_receiver": sc.is_stable_receiver,
+                            "currency": sc.currency.code(),
+                            "expected_usd": sc.expected_usd.to_f64(),
+                            "stable_receiver_usd": sc.stable_receiver_usd.to_f64(),
+                            "stable_receiver_btc": sc.stable_receiver_btc.to_btc(),
+                            "dollars_from_par": stable::dollars_from_par(sc).to_f64(),
+                            "percent_from_par": stable::percent_from_par(sc),
+                            "peg_agreed": sc.peg_agreed,
+                            "latest_price": sc.latest_price,
+                            "suspension_reason": sc.suspension_reason,
+                        }))
+                        .collect();
+                    pending.reply(200, serde_json::Value::Array(channels));
+                }
+                _ => pending.reply(404, serde_json::json!({ "error": "not available in the user app" })),
+            }
         }
+    }
 
-        let node_arc = Arc::clone(&self.node);
-        let sc_arc = Arc::clone(&self.stable_channel);
-
-        std::thread::spawn(move || {
-            loop {
-                // Always try to get the latest price first
-                let price = match crate::price_feeds::get_latest_price(&ureq::Agent::new()) {
-                    Ok(p) if p > 0.0 => p,
-                    _ => crate::price_feeds::get_cached_price()
-                };
+    /// Stops the background thread, persists the stable channel and stops the node
+    fn shutdown(&mut self) {
+        info!("Shutting down user node...");
+        self.shutdown.store(true, Ordering::SeqCst);
+
+        if let Some(handle) = self.background_thread.take() {
+            shutdown::join_with_timeout(handle, shutdown::THREAD_JOIN_TIMEOUT);
+        }
+
+        if let Ok(channels) = self.stable_channels.lock() {
+            save_stable_channels(&channels);
+        }
+        if let Ok(mut history) = self.balance_history.lock() {
+            history.save();
+        }
+
+        self.worker.stop();
+        if let Some(admin) = self.admin.as_mut() {
+            admin.stop();
+        }
+        self.sync_monitor.stop();
+        self.price_stream.stop();
+        self.webhooks.stop();
+        if let Err(e) = self.node.stop() {
+            error!("Failed to stop node: {}", e);
+        }
+
+        // Release only once the node has stopped writing to the directory
+        self.data_dir_lock.take();
+    }
+
+    /// Whether restoring would replace a wallet the user may care about. A wallet generated
+    /// this run into an empty directory, still without funds or channels, is not one.
+    fn restore_would_replace_wallet(&self) -> bool {
+        !self.fresh_wallet
+            || !self.channel_snapshot.channels().is_empty()
+            || self.node.list_balances().total_onchain_balance_sats > 0
+    }
+
+    /// Replaces the running node with one built from `mnemonic`. The current wallet's files
+    /// are moved aside, never deleted.
+    fn restore_wallet(&mut self, mnemonic: Mnemonic) {
+        info!("Restoring wallet from recovery phrase...");
+
+        // Nothing may touch the node while it is swapped out
+        self.shutdown.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.background_thread.take() {
+            shutdown::join_with_timeout(handle, shutdown::THREAD_JOIN_TIMEOUT);
+        }
+        self.shutdown.store(false, Ordering::SeqCst);
+        self.worker.stop();
+        if let Some(mut admin) = self.admin.take() {
+            admin.stop();
+        }
+        self.sync_monitor.stop();
+
+        if let Err(e) = self.node.stop() {
+            error!("Failed to stop node: {}", e);
+        }
+
+        let data_dir = paths::data_dir(USER_NODE_ALIAS);
+        if let Err(e) = seed::archive_existing_wallet(&data_dir) {
+            error!("Failed to move the existing wallet aside: {}", e);
+            self.status_log.error(format!("Restore failed: {}", e));
+            if let Err(e) = self.node.start() {
+                error!("Failed to restart node: {}", e);
+            }
+            self.worker = Worker::spawn(Arc::clone(&self.node));
+            return;
+        }
+
+        self.wallet_seed = WalletSeed::from_mnemonic(&data_dir, &mnemonic);
+        self.payment_history = PaymentHistory::load(&data_dir);
+        self.onchain_activity = OnchainActivity::new(self.chain_source.esplora_url());
+        self.channel_snapshot.invalidate();
+        let node = match build_node(
+            &data_dir,
+            &self.wallet_seed,
+            self.network,
+            &self.chain_source,
+            self.lsp_config.active_endpoint(),
+            self.lsp_config.active_or_default().lsps2_token(),
+            self.lsp_config.active_or_default().trust_zero_conf,
+        ) {
+            Ok(node) => node,
+            Err(e) => {
+                error!("Failed to build restored node: {:?}", e);
+                self.status_log.error("Restore failed; restart the app to try again".to_string());
+                return;
+            }
+        };
+        if let Err(e) = node.start() {
+            error!("Failed to start restored node: {}", e);
+            self.status_log.error("Restore failed; restart the app to try again".to_string());
+            return;
+        }
+        info!("Restored node started: {}", node.node_id());
+        self.lsp_in_use = self.lsp_config.active_or_default();
+        self.lsp_pubkey = self.lsp_config.active_endpoint().0;
+        self.worker = Worker::spawn(Arc::clone(&node));
+        self.sync_monitor = SyncMonitor::spawn(Arc::clone(&node), self.chain_source.clone());
+        self.admin = start_admin(&node).unwrap_or_else(|e| {
+            self.status_log.error(e);
+            None
+        });
+        self.node = node;
+
+        // Any stable channel on record belonged to the replaced wallet
+        if let Ok(mut channels) = self.stable_channels.lock() {
+            channels.clear();
+        }
+        self.selected_channel = unassigned_channel_id();
+
+        self.fresh_wallet = false;
+        self.restore_flow = None;
+        self.restore_syncing = true;
+        self.show_onboarding = true;
+    }
 
-                // Only proceed if we have a valid price and active channels
-                if price > 0.0 && !node_arc.list_channels().is_empty() {
-                    if let Ok(mut sc) = sc_arc.lock() {
-                        crate::stable::check_stability(&*node_arc, &mut sc, price);
-                        crate::stable::update_balances(&*node_arc, &mut sc);
+    /// Requests a JIT invoice for the amount on the onboarding screen, replacing any
+    /// invoice shown before
+    fn get_jit_invoice(&mut self) {
+        let amount_msats = match self.jit_amount_msats(self.btc_price) {
+            Ok(amount_msats) => amount_msats,
+            Err(e) => {
+                self.status_log.error(e);
+                return;
+            }
+        };
+        self.node_ui.invoice_result.clear();
+        self.qr_texture = None;
+        self.jit_quote = None;
+        self.jit_hash = None;
+        self.waiting_request = Some(WaitingInvoice::Jit);
+        self.worker.submit(AppCommand::JitInvoice {
+            amount_msats,
+            description: "Stable Channel JIT payment".to_string(),
+            max_lsp_fee_msats: Some(self.max_jit_fee_msats()),
+        });
+        self.status_log.info("Getting JIT channel invoice...".to_string());
+    }
+
+    /// The onboarding amount in msats, within the LSP's payment size limits
+    fn jit_amount_msats(&self, price: f64) -> Result<u64, String> {
+        let usd = match self.jit_amount_input.trim().parse::<f64>() {
+            Ok(val) if val > 0.0 => val,
+            _ => return Err("Invalid amount".to_string()),
+        };
+        let Some(amount_msats) = USD::from_f64(usd).to_msats(price) else {
+            return Err("Waiting for price before getting an invoice".to_string());
+        };
+        let (min_msats, max_msats) = jit_payment_limits_msats();
+        if amount_msats < min_msats.max(1000) {
+            return Err(format!("Amount is below the LSP's minimum of {} sats", min_msats.max(1000) / 1000));
+        }
+        if amount_msats > max_msats {
+            return Err(format!(
+                "Amount is above the LSP's maximum of {} sats (${:.2})",
+                max_msats / 1000,
+                max_msats as f64 / 100_000_000_000.0 * price
+            ));
+        }
+        Ok(amount_msats)
+    }
+
+    /// The JIT fee cap of the selected profile, or of the one in use while switching LSPs
+    /// waits for a restart
+    fn max_jit_fee_msats(&self) -> u64 {
+        if self.lsp_config.active().needs_restart(&self.lsp_in_use) {
+            self.lsp_in_use.max_jit_fee_msats()
+        } else {
+            self.lsp_config.active().max_jit_fee_msats()
+        }
+    }
+
+    /// Requests an invoice adding the amount in the top-up form to the stable balance,
+    /// through a JIT channel when the channel can't receive that much
+    fn top_up(&mut self) {
+        let amount = match self.top_up_input.trim().parse::<f64>() {
+            Ok(val) if val > 0.0 => val,
+            _ => {
+                self.status_log.error("Invalid top-up amount".to_string());
+                return;
+            }
+        };
+        let Some((price, counterparty)) = self.with_selected(|sc| (sc.latest_price, sc.counterparty)) else {
+            return;
+        };
+        let Some(amount_msats) = USD::from_f64(amount).to_msats(price) else {
+            self.status_log.warn("Waiting for price before topping up".to_string());
+            return;
+        };
+        let inbound_msats: u64 = self
+            .node
+            .list_channels()
+            .iter()
+            .filter(|c| c.counterparty_node_id == counterparty && c.is_usable)
+            .map(|c| c.inbound_capacity_msat)
+            .sum();
+        let jit = inbound_msats < amount_msats;
+        self.top_up_channel = Some(self.selected_channel);
+        self.waiting_request = Some(WaitingInvoice::TopUp { jit });
+        self.worker.submit(AppCommand::TopUpInvoice {
+            amount_msats,
+            jit,
+            max_lsp_fee_msats: Some(self.max_jit_fee_msats()),
+        });
+        self.status_log.info(if jit {
+            "Not enough inbound capacity; getting a JIT channel invoice...".to_string()
+        } else {
+            "Getting top-up invoice...".to_string()
+        });
+    }
+
+    /// Sets the target to what the onboarding payment actually delivered, after the LSP's
+    /// fee, so the peg proposed once the channel is usable matches the balance
+    fn set_target_from_jit_payment(&mut self, amount_msats: u64) {
+        let mut channels = self.stable_channels.lock().unwrap();
+        let Some(sc) = find_stable_channel(&mut channels, self.selected_channel) else {
+            return;
+        };
+        if sc.latest_price <= 0.0 {
+            warn!("No price when the JIT payment arrived; keeping target {}", sc.expected_usd);
+            return;
+        }
+        sc.expected_btc = Bitcoin::from_sats(amount_msats / 1000);
+        sc.expected_usd = USD::from_bitcoin(sc.expected_btc, sc.latest_price);
+        info!("Target set to {} from the JIT payment of {} msats", sc.expected_usd, amount_msats);
+        update_balances(&*self.node, sc);
+        self.target_usd_input = format!("{:.2}", sc.expected_usd.to_f64());
+        save_stable_channels(&channels);
+    }
+
+    /// Raises the target by a received top-up, valued at the stability price, and asks the
+    /// LSP to agree. Checks pause until it answers. An adjustment still in flight settles
+    /// the deviation from before the top-up, so the target rises by the top-up alone either way.
+    fn apply_top_up(&mut self, channel_id: ChannelId, amount_msats: u64) {
+        let mut channels = self.stable_channels.lock().unwrap();
+        let Some(sc) = find_stable_channel(&mut channels, channel_id) else {
+            return;
+        };
+        let price = stable::stability_price(sc);
+        if price <= 0.0 || sc.unpegged_at.is_some() {
+            self.status_log.info(format!("Received top-up of {} msats; kept as BTC", amount_msats));
+            return;
+        }
+
+        let top_up = USD::from_bitcoin(Bitcoin::from_msats(amount_msats), price);
+        self.top_up_previous_target.get_or_insert((channel_id, sc.expected_usd));
+        sc.expected_usd = sc.expected_usd + top_up;
+        sc.expected_btc = Bitcoin::from_usd(sc.expected_usd, price).unwrap_or_default();
+        sc.peg_agreed = false;
+        let (expected_usd, currency, stable_fraction) = (sc.expected_usd.to_f64(), sc.currency, sc.stable_fraction);
+        save_stable_channels(&channels);
+        drop(channels);
+
+        if channel_id == self.selected_channel {
+            self.target_usd_input = format!("{:.2}", expected_usd);
+        }
+        self.status_log.info(format!(
+            "Topped up by {}; asking the LSP to raise the target to {}",
+            currency.format(top_up.to_f64()),
+            currency.format(expected_usd)
+        ));
+        self.proposal_rejected = false;
+        self.propose_stable(channel_id, expected_usd, currency, stable_fraction);
+    }
+
+    /// Restores the target from before a top-up the LSP wouldn't peg, leaving the top-up
+    /// as floating BTC
+    fn keep_top_up_as_btc(&mut self, channel_id: ChannelId, previous_target: USD) {
+        let mut channels = self.stable_channels.lock().unwrap();
+        let Some(sc) = find_stable_channel(&mut channels, channel_id) else {
+            return;
+        };
+        let price = stable::stability_price(sc);
+        let top_up = Bitcoin::from_usd(sc.expected_usd - previous_target, price).unwrap_or_default();
+        sc.float_btc = Bitcoin::from_msats(sc.float_btc.to_msats() + top_up.to_msats());
+        sc.expected_usd = previous_target;
+        sc.expected_btc = Bitcoin::from_usd(previous_target, price).unwrap_or_default();
+        sc.peg_agreed = true;
+        save_stable_channels(&channels);
+        if channel_id == self.selected_channel {
+            self.target_usd_input = format!("{:.2}", previous_target.to_f64());
+        }
+    }
+
+    /// Pays the invoice in the withdraw form out of the stable balance. The target is
+    /// lowered and proposed to the LSP before sending, so the LSP doesn't refill the
+    /// balance the payment leaves behind; it is restored if the payment fails.
+    fn withdraw(&mut self) {
+        if self.withdraw_hash.is_some() {
+            self.status_log.info("A withdrawal is already in flight".to_string());
+            return;
+        }
+        let invoice = match Bolt11Invoice::from_str(self.withdraw_invoice.trim()) {
+            Ok(invoice) => invoice,
+            Err(e) => {
+                self.status_log.error(format!("Invalid invoice: {}", e));
+                return;
+            }
+        };
+        let Some(price) = self.with_selected(|sc| stable::stability_price(sc)) else {
+            return;
+        };
+        let (amount_msats, amount_override) = match invoice.amount_milli_satoshis() {
+            Some(amount_msats) => (amount_msats, None),
+            None => match self.withdraw_amount_input.trim().parse::<f64>() {
+                Ok(val) if val > 0.0 => match USD::from_f64(val).to_msats(price) {
+                    Some(amount_msats) => (amount_msats, Some(amount_msats)),
+                    None => {
+                        self.status_log.warn("Waiting for price before withdrawing".to_string());
+                        return;
                     }
+                },
+                _ => {
+                    self.status_log.info("Invoice has no amount; enter how much to withdraw".to_string());
+                    return;
                 }
+            },
+        };
 
-                // Sleep between checks, but be ready to interrupt if needed
-                std::thread::sleep(Duration::from_secs(30));
+        let channel_id = self.selected_channel;
+        let previous_target = match self.lower_target(channel_id, amount_msats) {
+            Ok(previous_target) => previous_target,
+            Err(e) => {
+                self.status_log.error(format!("Cannot withdraw: {}", e));
+                return;
             }
-        });
+        };
+        self.withdraw_previous_target = Some((channel_id, previous_target));
+        self.withdraw_hash = Some(invoice.payment_hash().to_string());
+        self.worker.submit(AppCommand::Withdraw { invoice, amount_msats: amount_override });
+        self.status_log.info(format!("Withdrawing {} sats...", amount_msats / 1000));
+    }
 
-        self.background_started = true;
+    /// Lowers the target by `amount_msats`, valued at the stability price, and asks the LSP
+    /// to agree. Checks pause until it answers. Returns the target from before.
+    fn lower_target(&mut self, channel_id: ChannelId, amount_msats: u64) -> Result<USD, String> {
+        let mut channels = self.stable_channels.lock().unwrap();
+        let sc = find_stable_channel(&mut channels, channel_id).ok_or_else(|| "the channel isn't stable".to_string())?;
+        let price = stable::stability_price(sc);
+        if sc.unpegged_at.is_some() {
+            return Err("the channel is unpegged".to_string());
+        }
+        if price <= 0.0 {
+            return Err("no price yet".to_string());
+        }
+        let previous_target = sc.expected_usd;
+        let amount = USD::from_bitcoin(Bitcoin::from_msats(amount_msats), price);
+        if amount >= previous_target {
+            return Err(format!(
+                "{} is your whole stable balance; unpeg instead",
+                sc.currency.format(amount.to_f64())
+            ));
+        }
+
+        sc.expected_usd = previous_target - amount;
+        sc.expected_btc = Bitcoin::from_usd(sc.expected_usd, price).unwrap_or_default();
+        sc.peg_agreed = false;
+        let (expected_usd, currency, stable_fraction) = (sc.expected_usd.to_f64(), sc.currency, sc.stable_fraction);
+        save_stable_channels(&channels);
+        drop(channels);
+
+        if channel_id == self.selected_channel {
+            self.target_usd_input = format!("{:.2}", expected_usd);
+        }
+        self.proposal_rejected = false;
+        self.propose_stable(channel_id, expected_usd, currency, stable_fraction);
+        Ok(previous_target)
     }
 
-        fn get_jit_invoice(&mut self, ctx: &egui::Context) {
-        let latest_price = {
-            let sc = self.stable_channel.lock().unwrap();
-            sc.latest_price
+    /// Puts back the target a failed withdrawal lowered and re-proposes it to the LSP
+    fn restore_withdrawn_target(&mut self) {
+        self.withdraw_hash = None;
+        let Some((channel_id, previous_target)) = self.withdraw_previous_target.take() else {
+            return;
         };
-        let description = ldk_node::lightning_invoice::Bolt11InvoiceDescription::Direct(
-            ldk_node::lightning_invoice::Description::new(
-                "Stable Channel JIT payment".to_string(),
-            )
-            .unwrap(),
-        );
-        let result = self.node.bolt11_payment().receive_via_jit_channel(
-            USD::to_msats(USD::from_f64(EXPECTED_USD), latest_price),
-            &description,
-            3600,
-            Some(10_000_000),
-        );
+        let mut channels = self.stable_channels.lock().unwrap();
+        let Some(sc) = find_stable_channel(&mut channels, channel_id) else {
+            return;
+        };
+        let price = stable::stability_price(sc);
+        sc.expected_usd = previous_target;
+        if let Some(expected_btc) = Bitcoin::from_usd(previous_target, price) {
+            sc.expected_btc = expected_btc;
+        }
+        sc.peg_agreed = false;
+        let (currency, stable_fraction) = (sc.currency, sc.stable_fraction);
+        save_stable_channels(&channels);
+        drop(channels);
+
+        if channel_id == self.selected_channel {
+            self.target_usd_input = format!("{:.2}", previous_target.to_f64());
+        }
+        self.proposal_rejected = false;
+        self.propose_stable(channel_id, previous_target.to_f64(), currency, stable_fraction);
+    }
+
+    /// True if `payment_id` is an outgoing invoice payment, as opposed to a keysend
+    /// stability adjustment or negotiation message
+    fn is_ordinary_spend(&self, payment_id: &PaymentId) -> bool {
+        self.node.payment(payment_id).map_or(false, |p| {
+            p.direction == PaymentDirection::Outbound
+                && matches!(p.kind, PaymentKind::Bolt11 { .. } | PaymentKind::Bolt12Offer { .. } | PaymentKind::Bolt12Refund { .. })
+        })
+    }
+
+    /// Shows an invoice from the worker as a QR code and waits for it to be paid
+    fn show_payment_qr(
+        &mut self,
+        ctx: &egui::Context,
+        result: Result<Bolt11Invoice, ldk_node::NodeError>,
+        generated_status: &str,
+    ) {
+        self.jit_quote = None;
+        // A failed refresh is left to be retried after REFRESH_RETRY
+        let refreshed = self.refresh_requested_at.is_some();
+        if refreshed && !self.waiting_for_payment {
+            // The user left the waiting screen while the new invoice was being made
+            self.refresh_requested_at = None;
+            return;
+        }
         match result {
             Ok(invoice) => {
-                self.invoice_result = invoice.to_string();
-                let code = QrCode::new(&self.invoice_result).unwrap();
-                let bits = code.to_colors();
-                let width = code.width();
-                let scale = 4;
-                let mut imgbuf =
-                    GrayImage::new((width * scale) as u32, (width * scale) as u32);
-                for y in 0..width {
-                    for x in 0..width {
-                        let color = if bits[y * width + x] == Color::Dark {
-                            0
-                        } else {
-                            255
-                        };
-                        for dy in 0..scale {
-                            for dx in 0..scale {
-                                imgbuf.put_pixel(
-                                    (x * scale + dx) as u32,
-                                    (y * scale + dy) as u32,
-                                    Luma([color]),
-                                );
-                            }
-                        }
-                    }
+                self.refresh_requested_at = None;
+                let quote = JitFeeQuote::for_invoice(&self.node, &invoice);
+                if let Some(quote) = quote.filter(|q| q.fee_msats > self.max_jit_fee_msats()) {
+                    self.node_ui.invoice_result.clear();
+                    self.waiting_invoice = None;
+                    self.waiting_for_payment = false;
+                    self.status_log.warn(format!(
+                        "The LSP wants {} sats to open a channel, above your max of {} sats",
+                        quote.fee_msats / 1000,
+                        self.max_jit_fee_msats() / 1000
+                    ));
+                    return;
                 }
-                let (w, h) = (imgbuf.width() as usize, imgbuf.height() as usize);
-                let mut rgba = Vec::with_capacity(w * h * 4);
-                for p in imgbuf.pixels() {
-                    let lum = p[0];
-                    rgba.extend_from_slice(&[lum, lum, lum, 255]);
+                self.jit_quote = quote;
+                self.node_ui.invoice_result = invoice.to_string();
+                self.qr_texture = ui_util::qr_texture(ctx, &self.node_ui.invoice_result);
+                self.waiting_invoice = Some(invoice);
+                if refreshed {
+                    self.invoice_refreshed_at = Some(Instant::now());
+                    self.status_log.info("The invoice expired unpaid; showing a new one for the same amount".to_string());
+                } else {
+                    self.invoice_refreshed_at = None;
+                    self.status_log.info(generated_status.to_string());
                 }
-                let tex = ctx.load_texture(
-                    "qr_code",
-                    egui::ColorImage::from_rgba_unmultiplied([w, h], &rgba),
-                    TextureOptions::LINEAR,
-                );
-                self.qr_texture = Some(tex);
-                self.status_message =
-                    "Invoice generated. Pay it to create a JIT channel.".to_string();
                 self.waiting_for_payment = true;
             }
+            Err(ldk_node::NodeError::LiquidityRequestFailed) => {
+                self.node_ui.invoice_result.clear();
+                self.status_log.error("The LSP refused the JIT channel; the amount may be outside its limits".to_string());
+            }
+            Err(ldk_node::NodeError::LiquidityFeeTooHigh) => {
+                self.node_ui.invoice_result.clear();
+                self.status_log.warn(format!(
+                    "The LSP's channel fee is above your max of {} sats; raise it in LSP settings",
+                    self.max_jit_fee_msats() / 1000
+                ));
+            }
             Err(e) => {
-                self.invoice_result = format!("Error: {e:?}");
-                self.status_message = format!("Failed to generate invoice: {}", e);
-            }
-        }
-    }
-
-    pub fn generate_invoice(&mut self) -> bool {
-        if let Ok(amount) = self.invoice_amount.parse::<u64>() {
-            let msats = amount * 1000;
-            match self.node.bolt11_payment().receive(
-                msats,
-                &ldk_node::lightning_invoice::Bolt11InvoiceDescription::Direct(
-                    ldk_node::lightning_invoice::Description::new("Invoice".to_string()).unwrap()
-                ),
-                3600,
-            ) {
-                Ok(invoice) => {
-                    self.invoice_result = invoice.to_string();
-                    self.status_message = "Invoice generated".to_string();
-                    true
-                },
-                Err(e) => {
-                    self.status_message = format!("Error: {}", e);
-                    false
+                self.node_ui.invoice_result = format!("Error: {e:?}");
+                self.status_log.error(format!("Failed to generate invoice: {}", e));
+            }
+        }
+    }
+
+    /// Requests a new invoice for the same amount once the one on the waiting screen
+    /// expires. The invoice's own expiry decides, not a timer of ours.
+    fn refresh_expired_invoice(&mut self) {
+        if !self.waiting_for_payment {
+            return;
+        }
+        let (Some(request), Some(invoice)) = (self.waiting_request, &self.waiting_invoice) else {
+            return;
+        };
+        if !invoice.is_expired() || self.refresh_requested_at.map_or(false, |t| t.elapsed() < REFRESH_RETRY) {
+            return;
+        }
+        let Some(amount_msats) = invoice.amount_milli_satoshis() else {
+            return;
+        };
+        let max_lsp_fee_msats = Some(self.max_jit_fee_msats());
+        self.worker.submit(match request {
+            WaitingInvoice::Jit => AppCommand::JitInvoice {
+                amount_msats,
+                description: "Stable Channel JIT payment".to_string(),
+                max_lsp_fee_msats,
+            },
+            WaitingInvoice::TopUp { jit } => AppCommand::TopUpInvoice { amount_msats, jit, max_lsp_fee_msats },
+        });
+        self.refresh_requested_at = Some(Instant::now());
+        info!("Invoice {} expired unpaid; requesting a new one", invoice.payment_hash());
+    }
+
+    pub fn update_balances(&mut self) {
+        let current_price = get_cached_price();
+        if current_price > 0.0 {
+            self.btc_price = current_price;
+        }
+        
+        let balances = self.node.list_balances();
+        self.onchain_activity.update_balance(&balances);
+        self.node_ui.balances = NodeBalances::new(&balances, &self.node.list_channels(), self.btc_price);
+    }
+    
+    /// Proposes a new peg target to the LSP. The target only changes once the LSP accepts.
+    pub fn update_target_usd(&mut self) {
+        let target = match self.target_usd_input.trim().parse::<f64>() {
+            Ok(val) if val > 0.0 => val,
+            _ => {
+                self.status_log.error("Invalid target amount".to_string());
+                return;
+            }
+        };
+
+        let stable_fraction = match self.stable_fraction_input.trim().parse::<f64>() {
+            Ok(val) if val > 0.0 && val <= 100.0 => val,
+            _ => {
+                self.status_log.error("Stable percentage must be above 0 and at most 100".to_string());
+                return;
+            }
+        };
+
+        let currency = self.target_currency;
+        let mut channels = self.stable_channels.lock().unwrap();
+        let Some(sc) = find_stable_channel(&mut channels, self.selected_channel) else {
+            return;
+        };
+        let price = if currency == sc.currency {
+            sc.latest_price
+        } else {
+            get_cached_price_in(currency)
+        };
+        if price <= 0.0 {
+            self.status_log.warn("Waiting for price before changing the target".to_string());
+            return;
+        }
+
+        let capacity = self
+            .node
+            .list_channels()
+            .into_iter()
+            .find(|c| c.channel_id == sc.channel_id)
+            .map(|c| USD::from_bitcoin(Bitcoin::from_sats(c.channel_value_sats), price));
+        if let Some(capacity) = capacity {
+            if target > capacity.to_f64() {
+                self.status_log.warn(format!(
+                    "Target {} exceeds the channel capacity of {}",
+                    currency.format(target),
+                    currency.format(capacity.to_f64())
+                ));
+                return;
+            }
+        }
+        drop(channels);
+
+        self.proposal_rejected = false;
+        self.propose_stable(self.selected_channel, target, currency, stable_fraction);
+    }
+
+    /// Applies the peg the LSP agreed to: target, currency, stability fee and the BTC it
+    /// recorded as floating. Lowering the target settles immediately so the excess goes
+    /// back to BTC exposure; switching currency re-values the channel at that currency's
+    /// price.
+    fn apply_agreed_target(
+        &mut self,
+        channel_id: ChannelId,
+        expected_usd: f64,
+        fee_ppm: u32,
+        currency: Currency,
+        stable_fraction: f64,
+        float_sats: u64,
+    ) {
+        let mut channels = self.stable_channels.lock().unwrap();
+        let Some(sc) = find_stable_channel(&mut channels, channel_id) else {
+            return;
+        };
+        sc.stability_fee_ppm = fee_ppm;
+        sc.stable_fraction = stable_fraction;
+        sc.float_btc = Bitcoin::from_sats(float_sats);
+        if sc.currency != currency {
+            sc.currency = currency;
+            sc.latest_price = get_cached_price_in(currency);
+            // Samples in the old currency would skew the TWAP
+            sc.prices.clear();
+            sc.twap_price = 0.0;
+            sc.daily_payments.clear();
+            update_balances(&*self.node, sc);
+        }
+        let price = sc.latest_price;
+        let lowered = sc.peg_agreed && expected_usd < sc.expected_usd.to_f64();
+
+        sc.expected_usd = USD::from_f64(expected_usd);
+        if let Some(expected_btc) = Bitcoin::from_usd(sc.expected_usd, price) {
+            sc.expected_btc = expected_btc;
+            sc.designation_price.get_or_insert(price);
+        }
+        sc.peg_agreed = true;
+        sc.unpegged_at = None;
+        if channel_id == self.selected_channel {
+            self.target_usd_input = format!("{:.2}", expected_usd);
+            self.target_currency = currency;
+            self.stable_fraction_input = format!("{}", stable_fraction);
+        }
+        self.status_log.info(format!("Stable target set to {}", currency.format(expected_usd)));
+
+        if lowered {
+            let action = stable::check_stability_and_log(&*self.node, sc, price);
+            self.status_log.info(format!("Stable target set to {}. {}", currency.format(expected_usd), action));
+        }
+        save_stable_channels(&channels);
+    }
+
+    /// Proposes a peg for `channel_id` to its counterparty, with us on the side the entry
+    /// says. The counterparty applies it to the channel it shares with us.
+    fn propose_stable(&mut self, channel_id: ChannelId, expected_usd: f64, currency: Currency, stable_fraction: f64) {
+        let Some((counterparty, proposer_receives)) = self
+            .stable_channels
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|sc| sc.channel_id == channel_id)
+            .map(|sc| (sc.counterparty, sc.is_stable_receiver))
+        else {
+            return;
+        };
+        self.last_proposal_attempt = Some(Instant::now());
+
+        let message = StableMessage::ProposeStable { expected_usd, currency, stable_fraction, proposer_receives };
+        match stable::send_stable_message(&self.node, counterparty, message) {
+            Ok(_) => {
+                let waiting_on = if counterparty == self.lsp_pubkey { "the LSP" } else { "your peer" };
+                self.negotiation_status =
+                    format!("Proposed {} peg, waiting for {}", currency.format(expected_usd), waiting_on);
+            }
+            Err(e) => {
+                self.negotiation_status = format!("Failed to propose peg: {}", e);
+            }
+        }
+    }
+
+    /// Starts a cooperative close of `channel`
+    fn close_channel(&mut self, channel: &ChannelDetails) {
+        let result = self.node.close_channel(&channel.user_channel_id, channel.counterparty_node_id);
+        match result {
+            Ok(_) => {
+                self.pending_closes.start(channel.channel_id);
+                self.status_log.info(format!("Closing channel: {}", channel.channel_id));
+            }
+            Err(e) => self.status_log.error(format!("Error closing channel: {}", e)),
+        }
+        self.channel_snapshot.invalidate();
+    }
+
+    /// Broadcasts our commitment for `channel`, ending the peg first if it is the stable channel
+    fn force_close_channel(&mut self, channel: &ChannelDetails) {
+        let result = self.node.force_close_channel(
+            &channel.user_channel_id,
+            channel.counterparty_node_id,
+            Some("Force-closed by the user".to_string()),
+        );
+        match result {
+            Ok(()) => {
+                self.pending_closes.finish(&channel.channel_id);
+                self.status_log.info(format!("Force-closing channel: {}", channel.channel_id));
+                let mut channels = self.stable_channels.lock().unwrap();
+                if let Some(sc) = find_stable_channel(&mut channels, channel.channel_id).filter(|sc| sc.unpegged_at.is_none()) {
+                    let price = sc.latest_price;
+                    stable::record_force_close(sc, price);
+                    save_stable_channels(&channels);
+                    self.status_log.info("Force-closing the stable channel; peg ended at the current price".to_string());
                 }
             }
+            Err(e) => self.status_log.error(format!("Error force-closing channel: {}", e)),
+        }
+        self.channel_snapshot.invalidate();
+    }
+
+    /// Ends the peg: settles any deviation once, stops stability checks and tells the LSP.
+    /// The channel stays open and the whole balance floats with BTC from here on.
+    fn unpeg(&mut self) {
+        let mut channels = self.stable_channels.lock().unwrap();
+        let Some(sc) = find_stable_channel(&mut channels, self.selected_channel) else {
+            return;
+        };
+        let price = sc.latest_price;
+        let action = stable::unpeg(&*self.node, sc, price);
+        let counterparty = sc.counterparty;
+        save_stable_channels(&channels);
+        drop(channels);
+
+        self.status_log.info(format!("Unpegged; your balance now floats with BTC. Final adjustment: {}", action));
+        if let Err(e) = stable::send_stable_message(&self.node, counterparty, StableMessage::UnpegStable) {
+            error!("Failed to tell the LSP about the unpeg: {}", e);
+            self.status_log.warn(format!("Unpegged locally, but the LSP could not be told: {}", e));
+        }
+    }
+
+    /// Keeps proposing the selected channel's target until its counterparty answers
+    fn propose_stable_if_needed(&mut self) {
+        if self.show_onboarding || self.proposal_rejected {
+            return;
+        }
+        if self.last_proposal_attempt.map_or(false, |t| t.elapsed() < Duration::from_secs(60)) {
+            return;
+        }
+
+        let Some((agreed, counterparty, expected_usd, currency, stable_fraction)) = self.with_selected(|sc| {
+            (sc.peg_agreed, sc.counterparty, sc.expected_usd.to_f64(), sc.currency, sc.stable_fraction)
+        }) else {
+            return;
+        };
+        let usable = self.channel_snapshot.channels().iter().any(|c| c.counterparty_node_id == counterparty && c.is_usable);
+        if agreed || !usable {
+            return;
+        }
+
+        self.propose_stable(self.selected_channel, expected_usd, currency, stable_fraction);
+    }
+
+    /// The channel a peg message from `counterparty` answers for: the selected one if it is
+    /// theirs, or else their first channel still waiting on agreement
+    fn negotiating_channel(&self, counterparty: PublicKey) -> Option<ChannelId> {
+        let channels = self.stable_channels.lock().unwrap();
+        channels
+            .iter()
+            .find(|sc| sc.channel_id == self.selected_channel && sc.counterparty == counterparty)
+            .or_else(|| channels.iter().find(|sc| sc.counterparty == counterparty && !sc.peg_agreed))
+            .map(|sc| sc.channel_id)
+    }
+
+    fn handle_stable_message(&mut self, envelope: StableMessageEnvelope) {
+        // A peer asking us to peg, or ending a peg, needn't be answering anything of ours
+        match envelope.message {
+            StableMessage::ProposeStable { expected_usd, currency, stable_fraction, proposer_receives } => {
+                self.receive_peg_request(envelope.sender, expected_usd, currency, stable_fraction, proposer_receives);
+                return;
+            }
+            StableMessage::UnpegStable => {
+                self.unpeg_counterparty(envelope.sender);
+                return;
+            }
+            _ => {}
+        }
+
+        let Some(channel_id) = self.negotiating_channel(envelope.sender) else {
+            warn!("Ignoring stable message from non-counterparty {}", envelope.sender);
+            return;
+        };
+        let who = if envelope.sender == self.lsp_pubkey { "LSP" } else { "Peer" };
+
+        match envelope.message {
+            StableMessage::AcceptStable { expected_usd, fee_ppm, currency, stable_fraction, float_sats, proposer_receives } => {
+                let we_receive = self.stable_channels.lock().unwrap().iter().any(|sc| sc.channel_id == channel_id && sc.is_stable_receiver);
+                if proposer_receives != we_receive {
+                    warn!("Ignoring acceptance from {} with the sides swapped", envelope.sender);
+                    return;
+                }
+                self.top_up_previous_target = None;
+                self.apply_agreed_target(channel_id, expected_usd, fee_ppm, currency, stable_fraction, float_sats);
+                self.negotiation_status = if fee_ppm > 0 {
+                    format!(
+                        "{} agreed to a {} peg with a {:.2}% stability fee",
+                        who,
+                        currency.format(expected_usd),
+                        fee_ppm as f64 / 10_000.0
+                    )
+                } else {
+                    format!("{} agreed to a {} peg", who, currency.format(expected_usd))
+                };
+            }
+            StableMessage::RejectStable { reason } => {
+                self.proposal_rejected = true;
+                self.negotiation_status = match self.top_up_previous_target.take() {
+                    Some((top_up_channel, previous_target)) => {
+                        self.keep_top_up_as_btc(top_up_channel, previous_target);
+                        format!("{} rejected the raised target: {}. The top-up stays as BTC", who, reason)
+                    }
+                    None => format!("{} rejected the peg: {}", who, reason),
+                };
+                self.status_log.info(self.negotiation_status.clone());
+            }
+            StableMessage::ProposeStable { .. } | StableMessage::UnpegStable => {}
+        }
+    }
+
+    /// Holds a peer's peg proposal for the user to answer. The LSP only ever answers
+    /// proposals, so one from it is ignored.
+    fn receive_peg_request(
+        &mut self,
+        sender: PublicKey,
+        expected_usd: f64,
+        currency: Currency,
+        stable_fraction: f64,
+        proposer_receives: bool,
+    ) {
+        let channel = self
+            .node
+            .list_channels()
+            .into_iter()
+            .filter(|c| c.counterparty_node_id == sender && c.is_usable)
+            .min_by_key(|c| self.stable_channels.lock().unwrap().iter().any(|sc| sc.channel_id == c.channel_id));
+        let Some(channel) = channel.filter(|_| sender != self.lsp_pubkey) else {
+            warn!("Ignoring peg proposal from {}: no usable channel with a peer", sender);
+            return;
+        };
+        self.status_log.info(format!(
+            "{} proposes a {} peg on channel {}",
+            sender,
+            currency.format(expected_usd),
+            channel.channel_id
+        ));
+        self.peg_request = Some(PegRequest {
+            channel_id: channel.channel_id,
+            counterparty: sender,
+            expected_usd,
+            currency,
+            stable_fraction,
+            we_receive: !proposer_receives,
+        });
+    }
+
+    /// Answers the held peg proposal, pegging the channel if `accept`
+    fn answer_peg_request(&mut self, accept: bool) {
+        let Some(request) = self.peg_request.take() else {
+            return;
+        };
+        if !accept {
+            let reply = StableMessage::RejectStable { reason: "Declined by the user".to_string() };
+            if let Err(e) = stable::send_stable_message(&self.node, request.counterparty, reply) {
+                error!("Failed to decline the peg from {}: {}", request.counterparty, e);
+            }
+            return;
+        }
+
+        let mut channels = self.stable_channels.lock().unwrap();
+        if find_stable_channel(&mut channels, request.channel_id).is_none() {
+            channels.push(self.new_stable_channel(request.channel_id, request.counterparty));
+        }
+        let Some(sc) = find_stable_channel(&mut channels, request.channel_id) else {
+            return;
+        };
+        sc.is_stable_receiver = request.we_receive;
+        sc.currency = request.currency;
+        sc.latest_price = get_cached_price_in(request.currency);
+        sc.prices.clear();
+        sc.twap_price = 0.0;
+        sc.stable_fraction = request.stable_fraction;
+        sc.expected_usd = USD::from_f64(request.expected_usd);
+        sc.expected_btc = Bitcoin::from_usd(sc.expected_usd, sc.latest_price).unwrap_or_default();
+        sc.peg_agreed = true;
+        sc.unpegged_at = None;
+        sc.pending_payment_id = None;
+        if sc.latest_price > 0.0 {
+            sc.designation_price = Some(sc.latest_price);
+        }
+        update_balances(&*self.node, sc);
+        sc.native_btc_original = sc.stable_provider_btc;
+        sc.native_btc = sc.stable_provider_btc;
+        sc.float_btc = stable::float_btc_for(sc.stable_receiver_btc, request.stable_fraction);
+        let float_sats = sc.float_btc.to_sats();
+        save_stable_channels(&channels);
+        drop(channels);
+
+        let reply = StableMessage::AcceptStable {
+            expected_usd: request.expected_usd,
+            fee_ppm: 0,
+            currency: request.currency,
+            stable_fraction: request.stable_fraction,
+            float_sats,
+            proposer_receives: !request.we_receive,
+        };
+        if let Err(e) = stable::send_stable_message(&self.node, request.counterparty, reply) {
+            error!("Failed to accept the peg from {}: {}", request.counterparty, e);
+            self.status_log.error(format!("Pegged locally, but {} could not be told: {}", request.counterparty, e));
+        }
+        self.select_channel(request.channel_id);
+        self.status_log.info(format!(
+            "Pegged channel {} at {}, {}",
+            request.channel_id,
+            request.currency.format(request.expected_usd),
+            if request.we_receive { "holding the stable side" } else { "providing stability" }
+        ));
+    }
+
+    /// Ends our pegs with `peer` after it unpegged, settling any deviation once
+    fn unpeg_counterparty(&mut self, peer: PublicKey) {
+        let mut channels = self.stable_channels.lock().unwrap();
+        let mut unpegged = false;
+        for sc in channels.iter_mut().filter(|sc| sc.counterparty == peer && sc.unpegged_at.is_none()) {
+            let price = sc.latest_price;
+            let action = stable::unpeg(&*self.node, sc, price);
+            self.status_log.info(format!("Channel {} unpegged by {}: final adjustment: {}", sc.channel_id, peer, action));
+            unpegged = true;
+        }
+        if unpegged {
+            save_stable_channels(&channels);
         } else {
-            self.status_message = "Invalid amount".to_string();
-            false
+            warn!("Ignoring unpeg from {}: no pegged stable channel", peer);
         }
     }
 
-    pub fn pay_invoice(&mut self) -> bool {
-        match Bolt11Invoice::from_str(&self.invoice_to_pay) {
-            Ok(invoice) => {
-                match self.node.bolt11_payment().send(&invoice, None) {
-                    Ok(payment_id) => {
-                        self.status_message = format!("Payment sent, ID: {}", payment_id);
-                        self.invoice_to_pay.clear();
+    /// Applies the results of commands the worker has finished
+    fn process_worker_results(&mut self, ctx: &egui::Context) {
+        while let Some(result) = self.worker.try_recv() {
+            match result {
+                AppResult::JitInvoiceGenerated(result) => {
+                    if let Ok(invoice) = &result {
+                        self.jit_hash = Some(invoice.payment_hash().to_string());
+                    }
+                    self.show_payment_qr(ctx, result, "Invoice generated. Pay it to create a JIT channel.")
+                }
+                AppResult::TopUpInvoiceGenerated(result) => {
+                    if let Ok(invoice) = &result {
+                        self.top_up_hash = Some(invoice.payment_hash().to_string());
+                    }
+                    self.show_payment_qr(ctx, result, "Top-up invoice generated. Pay it to add to your stable balance.")
+                }
+                AppResult::WithdrawSent(Ok(id)) => {
+                    self.pending_payments.track(&self.node, id);
+                    self.withdraw_invoice.clear();
+                    self.withdraw_amount_input.clear();
+                    self.payment_history.invalidate();
+                }
+                AppResult::Connected { node_id, result } => {
+                    self.lsp_test_status = match result {
+                        Ok(()) => format!("Connected to {}", node_id),
+                        Err(e) => format!("Could not connect to {}: {}", node_id, e),
+                    };
+                }
+                AppResult::Lsps1OrderStatus(result) => self.lsps1_flow.apply_status(result),
+                AppResult::Lsps1OrderPaid(result) => {
+                    if result.is_ok() {
+                        self.payment_history.invalidate();
+                        self.update_balances();
+                    }
+                    self.lsps1_flow.apply_payment(result);
+                }
+                AppResult::WithdrawSent(Err(e)) => {
+                    self.restore_withdrawn_target();
+                    self.status_log.error(format!("Withdrawal failed: {}. Target restored", e));
+                }
+                result => {
+                    let funds_moved = matches!(result, AppResult::InvoicePaid(Ok(_)) | AppResult::OnchainSent(Ok(_)));
+                    if let AppResult::InvoicePaid(Ok(id)) | AppResult::OfferPaid(Ok(id)) = &result {
+                        self.pending_payments.track(&self.node, *id);
+                    }
+                    if let Some(status) = self.node_ui.apply_result(result) {
+                        self.status_log.info(status);
+                    }
+                    for (key, label) in self.node_ui.take_new_labels() {
+                        self.payment_history.set_label(key, label);
+                    }
+                    if funds_moved {
+                        self.payment_history.invalidate();
                         self.update_balances();
-                        true
-                    },
-                    Err(e) => {
-                        self.status_message = format!("Payment error: {}", e);
-                        false
                     }
                 }
-            },
-            Err(e) => {
-                self.status_message = format!("Invalid invoice: {}", e);
-                false
             }
         }
     }
 
-    pub fn update_balances(&mut self) {
-        let current_price = get_cached_price();
-        if current_price > 0.0 {
-            self.btc_price = current_price;
+    /// Runs `f` on the selected stable channel, if there is one
+    fn with_selected<R>(&self, f: impl FnOnce(&mut StableChannel) -> R) -> Option<R> {
+        let mut channels = self.stable_channels.lock().unwrap();
+        find_stable_channel(&mut channels, self.selected_channel).map(f)
+    }
+
+    /// Points the target, top-up and withdraw forms at `channel_id`'s card
+    fn select_channel(&mut self, channel_id: ChannelId) {
+        self.selected_channel = channel_id;
+        self.confirm_unpeg = false;
+        if let Some((expected_usd, currency, stable_fraction)) =
+            self.with_selected(|sc| (sc.expected_usd.to_f64(), sc.currency, sc.stable_fraction))
+        {
+            self.target_usd_input = format!("{:.2}", expected_usd);
+            self.target_currency = currency;
+            self.stable_fraction_input = format!("{}", stable_fraction);
+        }
+    }
+
+    /// Starts the onboarding entry pegging `stable_fraction` of the JIT channel the LSP is
+    /// about to open, reusing one already waiting
+    fn start_onboarding_channel(&mut self, stable_fraction: f64) {
+        let mut channels = self.stable_channels.lock().unwrap();
+        match find_stable_channel(&mut channels, unassigned_channel_id()) {
+            Some(sc) => sc.stable_fraction = stable_fraction,
+            None => {
+                let mut sc = self.new_stable_channel(unassigned_channel_id(), self.lsp_pubkey);
+                sc.stable_fraction = stable_fraction;
+                channels.push(sc);
+            }
+        }
+        drop(channels);
+        self.select_channel(unassigned_channel_id());
+    }
+
+    /// A fresh entry for `channel_id` at the default target, until a peg is agreed
+    fn new_stable_channel(&self, channel_id: ChannelId, counterparty: PublicKey) -> StableChannel {
+        StableChannel {
+            channel_id,
+            counterparty,
+            is_stable_receiver: true,
+            expected_usd: USD::from_f64(EXPECTED_USD),
+            expected_btc: Bitcoin::from_usd(USD::from_f64(EXPECTED_USD), self.btc_price).unwrap_or_default(),
+            latest_price: self.btc_price,
+            timestamp: 0,
+            sc_dir: paths::data_dir(USER_NODE_ALIAS).display().to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Makes `channel_id` stable and proposes the peg to its counterparty. With `receive`
+    /// our current side is pegged; otherwise the counterparty's is and we provide
+    /// stability. A channel that was stable before keeps its running totals.
+    fn make_stable(&mut self, channel_id: ChannelId, receive: bool) {
+        let Some(counterparty) =
+            self.node.list_channels().iter().find(|c| c.channel_id == channel_id).map(|c| c.counterparty_node_id)
+        else {
+            self.status_log.error(format!("Channel {} not found", channel_id));
+            return;
+        };
+        let mut channels = self.stable_channels.lock().unwrap();
+        if find_stable_channel(&mut channels, channel_id).is_none() {
+            channels.push(self.new_stable_channel(channel_id, counterparty));
+        }
+        if let Some(sc) = find_stable_channel(&mut channels, channel_id) {
+            sc.is_stable_receiver = receive;
+            sc.peg_agreed = false;
+            sc.unpegged_at = None;
+            sc.pending_payment_id = None;
+            update_balances(&*self.node, sc);
+            sc.native_btc_original = sc.stable_provider_btc;
+            sc.native_btc = sc.stable_provider_btc;
+            if sc.stable_receiver_usd.to_f64() > 0.0 {
+                sc.expected_usd = sc.stable_receiver_usd;
+                sc.expected_btc = sc.stable_receiver_btc;
+            }
+        }
+        save_stable_channels(&channels);
+        drop(channels);
+
+        self.select_channel(channel_id);
+        self.proposal_rejected = false;
+        self.last_proposal_attempt = None;
+        self.show_onboarding = false;
+        self.status_log.info(format!("Proposing a peg for channel {}", channel_id));
+    }
+
+    fn show_lsps1_screen(&mut self, ctx: &egui::Context) {
+        let mut action = None;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.add_space(30.0);
+                    ui.heading("Buy inbound liquidity");
+                    ui.label(format!("From {}", self.lsp_in_use.name));
+                    ui.add_space(20.0);
+                    action = self.lsps1_flow.show(ui, &mut self.worker, self.btc_price);
+                });
+            });
+        });
+
+        match action {
+            Some(OrderAction::Close) => self.show_lsps1 = false,
+            Some(OrderAction::MakeStable(channel_id)) => {
+                self.make_stable(channel_id, true);
+                self.show_lsps1 = false;
+            }
+            None => {}
         }
-        
-        let balances = self.node.list_balances();
-        
-        self.lightning_balance_btc = balances.total_lightning_balance_sats as f64 / 100_000_000.0;
-        self.onchain_balance_btc = balances.total_onchain_balance_sats as f64 / 100_000_000.0;
-        
-        // Calculate USD values
-        self.lightning_balance_usd = self.lightning_balance_btc * self.btc_price;
-        self.onchain_balance_usd = self.onchain_balance_btc * self.btc_price;
-        
-        self.total_balance_btc = self.lightning_balance_btc + self.onchain_balance_btc;
-        self.total_balance_usd = self.lightning_balance_usd + self.onchain_balance_usd;
     }
-    
-    pub fn get_address(&mut self) -> bool {
-        match self.node.onchain_payment().new_address() {
-            Ok(address) => {
-                self.on_chain_address = address.to_string();
-                self.status_message = "Address generated".to_string();
-                true
-            },
-            Err(e) => {
-                self.status_message = format!("Error: {}", e);
-                false
+
+    fn process_stability_actions(&mut self) {
+        while let Ok(action) = self.stability_rx.try_recv() {
+            match action {
+                StabilityAction::Stable | StabilityAction::WaitingOnCounterparty => {}
+                _ => self.status_log.push(Severity::of_action(&action), action.to_string()),
             }
         }
     }
 
-    // fn get_lsps1_channel(&mut self) {
-    //     let lsp_balance_sat = 10_000;
-    //     let client_balance_sat = 10_000;
-    //     let lsps1 = self.node.lsps1_liquidity();
-    //     match lsps1.request_channel(lsp_balance_sat, client_balance_sat, 2016, false) {
-    //         Ok(status) => {
-    //             self.status_message =
-    //                 format!("LSPS1 channel order initiated! Status: {status:?}");
-    //         }
-    //         Err(e) => {
-    //             self.status_message = format!("LSPS1 channel request failed: {e:?}");
-    //         }
-    //     }
-    // }
-
     fn process_events(&mut self) {
         while let Some(event) = self.node.next_event() {
             match event {
-                ldk_node::Event::ChannelReady { channel_id, .. } => {
-                    self.status_message =
-                        format!("Channel {channel_id} is now ready");
+                ldk_node::Event::ChannelReady { channel_id, counterparty_node_id, .. } => {
+                    self.channel_snapshot.invalidate();
+                    self.status_log.info(format!("Channel {channel_id} is now ready"));
+                    if let Some(counterparty) = counterparty_node_id {
+                        let mut channels = self.stable_channels.lock().unwrap();
+                        if assign_ready_channel(&mut channels, channel_id, counterparty) {
+                            save_stable_channels(&channels);
+                            drop(channels);
+                            if self.selected_channel == unassigned_channel_id() {
+                                self.selected_channel = channel_id;
+                            }
+                        }
+                    }
+                    if self.lsps1_flow.channel_ready(channel_id, counterparty_node_id, self.lsp_pubkey) {
+                        // Offer the new channel for stable designation
+                        self.show_lsps1 = true;
+                    }
                     self.show_onboarding = false;
                     self.waiting_for_payment = false;
                 }
-                ldk_node::Event::PaymentReceived { amount_msat, .. } => {
-                    self.status_message = format!("Received payment of {} msats", amount_msat);
-                    let mut sc = self.stable_channel.lock().unwrap();
-                    update_balances(&self.node, &mut sc);
+                ldk_node::Event::PaymentReceived { payment_id, payment_hash, amount_msat, custom_records, .. } => {
+                    // Only count it as an adjustment if the LSP vouches for it
+                    let stability_info = stable::read_stability_payment(&custom_records).and_then(|claimed| {
+                        let channels = self.stable_channels.lock().unwrap();
+                        let Some(sc) = channels.iter().find(|sc| sc.channel_id == claimed.channel_id) else {
+                            warn!(
+                                "Treating payment of {} msats tagged for channel {} as ordinary: not a stable channel",
+                                amount_msat, claimed.channel_id
+                            );
+                            return None;
+                        };
+                        match stable::verify_stability_payment(&*self.node, sc, &custom_records, amount_msat) {
+                            Ok(info) => Some(info),
+                            Err(reason) => {
+                                warn!(
+                                    "Treating payment of {} msats tagged for channel {} as ordinary: {}",
+                                    amount_msat, claimed.channel_id, reason
+                                );
+                                None
+                            }
+                        }
+                    });
+                    if let Some(id) = payment_id {
+                        let fee_msats = stability_info.as_ref().map_or(0, |info| info.fee_msats);
+                        let channel_id = stability_info.as_ref().map(|info| &info.channel_id);
+                        self.payment_history.annotate(&id, self.btc_price, channel_id, fee_msats);
+                        self.payment_history.label_received(&id, &payment_hash);
+                    }
+                    if let Some(envelope) = stable::read_stable_message(&self.node, &custom_records) {
+                        self.handle_stable_message(envelope);
+                    } else if stability_info.is_some() {
+                        self.status_log.info(format!("Received stability adjustment of {} msats", amount_msat));
+                    } else if payment_id.map_or(false, |id| history::is_offer_payment(&self.node, &id)) {
+                        self.status_log.info(format!("Received offer payment of {} msats", amount_msat));
+                    } else {
+                        self.status_log.info(format!("Received payment of {} msats", amount_msat));
+                    }
+                    if let Some(quote) = self.jit_quote.filter(|q| Some(q.payment_id) == payment_id) {
+                        self.jit_quote = None;
+                        let fees = self.node.payment(&quote.payment_id).and_then(|p| jit_fee::lsp_fees(&p.kind));
+                        if let Some((_, Some(skimmed_msats))) = fees {
+                            let reconciled = quote.reconcile(skimmed_msats);
+                            info!("JIT payment {}: {}", payment_hash, reconciled);
+                            self.status_log.info(reconciled);
+                        }
+                    }
+                    if self.top_up_hash.as_deref() == Some(payment_hash.to_string().as_str()) {
+                        self.top_up_hash = None;
+                        if let Some(channel_id) = self.top_up_channel.take() {
+                            self.apply_top_up(channel_id, amount_msat);
+                        }
+                    }
+                    if self.jit_hash.as_deref() == Some(payment_hash.to_string().as_str()) {
+                        self.jit_hash = None;
+                        self.set_target_from_jit_payment(amount_msat);
+                    }
+                    let mut channels = self.stable_channels.lock().unwrap();
+                    if let Some(info) = stability_info {
+                        if let Some(sc) = find_stable_channel(&mut channels, info.channel_id) {
+                            stable::record_received_payment(sc, payment_id, amount_msat, info.fee_msats);
+                        }
+                    }
+                    for sc in channels.iter_mut().filter(|sc| sc.channel_id != unassigned_channel_id()) {
+                        update_balances(&*self.node, sc);
+                    }
+                    save_stable_channels(&channels);
+                    drop(channels);
                     self.show_onboarding = false;
                     self.waiting_for_payment = false;
                 }
-                ldk_node::Event::PaymentSuccessful { payment_id: _, payment_hash, payment_preimage: _, fee_paid_msat: _ } => {
-                    self.status_message = format!("Sent payment {}", payment_hash);
-                    let mut sc = self.stable_channel.lock().unwrap();
-                    update_balances(&self.node, &mut sc);
+                ldk_node::Event::PaymentSuccessful { payment_id, payment_hash, payment_preimage: _, fee_paid_msat: _ } => {
+                    self.status_log.info(format!("Sent payment {}", payment_hash));
+                    let withdrawal = self.withdraw_hash.as_deref() == Some(payment_hash.to_string().as_str());
+                    if withdrawal {
+                        self.withdraw_hash = None;
+                        self.withdraw_previous_target = None;
+                        self.status_log.info(format!("Withdrawal {} sent", payment_hash));
+                    }
+                    let mut channels = self.stable_channels.lock().unwrap();
+                    if let Some(id) = payment_id {
+                        self.pending_payments.succeeded(&id);
+                        let stability = match stable::resolve_payment_event(&mut channels, &event) {
+                            Some(PaymentResolution::Succeeded { index, fee_msats }) => {
+                                Some((channels[index].channel_id, fee_msats))
+                            }
+                            _ => None,
+                        };
+                        if stability.is_some() {
+                            save_stable_channels(&channels);
+                        }
+                        let channel_id = stability.as_ref().map(|(channel_id, _)| channel_id);
+                        let fee_msats = stability.map_or(0, |(_, fee_msats)| fee_msats);
+                        self.payment_history.annotate(&id, self.btc_price, channel_id, fee_msats);
+
+                        // A spend from outside the withdraw form may or may not have been meant
+                        // to come out of the stable balance; ask rather than guess
+                        let selected_pegged = channels.iter().any(|sc| {
+                            sc.channel_id == self.selected_channel && sc.unpegged_at.is_none() && sc.is_stable_receiver
+                        });
+                        if stability.is_none() && !withdrawal && selected_pegged && self.is_ordinary_spend(&id) {
+                            self.spend_prompt = self.node.payment(&id).and_then(|p| p.amount_msat);
+                        }
+                    }
+                    for sc in channels.iter_mut().filter(|sc| sc.channel_id != unassigned_channel_id()) {
+                        update_balances(&*self.node, sc);
+                    }
+                }
+                ldk_node::Event::PaymentFailed { payment_id, payment_hash, reason } => {
+                    self.status_log.error(format!("Payment {:?} failed: {:?}", payment_hash, reason));
+                    let hash = payment_hash.map(|h| h.to_string());
+                    if hash.is_some() && self.withdraw_hash == hash {
+                        self.restore_withdrawn_target();
+                        self.status_log.error(format!("Withdrawal failed: {:?}. Target restored", reason));
+                    }
+                    self.payment_history.invalidate();
+                    let mut channels = self.stable_channels.lock().unwrap();
+                    if let Some(id) = payment_id {
+                        self.pending_payments.failed(&id, reason);
+                        if let Some(PaymentResolution::Failed { index }) = stable::resolve_payment_event(&mut channels, &event) {
+                            let sc = &channels[index];
+                            self.status_log.error(format!(
+                                "Stability payment failed ({} in a row): {:?}",
+                                sc.consecutive_failures, reason
+                            ));
+                            // Retry now under the retry policy rather than at the next check.
+                            // The background loop sends it, off this thread and outside the lock.
+                            if sc.peg_agreed && sc.unpegged_at.is_none() {
+                                self.retry_now.store(true, Ordering::SeqCst);
+                            }
+                            save_stable_channels(&channels);
+                        }
+                    }
+                }
+                ldk_node::Event::PaymentClaimable { payment_hash, claimable_amount_msat, .. } => {
+                    // We only issue invoices we hold the preimage for, so a manual claim can
+                    // never complete; fail it back rather than leave the HTLC hanging
+                    self.status_log.warn(format!(
+                        "Failing back unclaimable payment {} of {} msats",
+                        payment_hash, claimable_amount_msat
+                    ));
+                    if let Err(e) = self.node.bolt11_payment().fail_for_hash(payment_hash) {
+                        error!("Failed to fail back payment {}: {}", payment_hash, e);
+                    }
+                }
+                ldk_node::Event::ChannelPending { channel_id, .. } => {
+                    self.onchain_activity.invalidate();
+                    self.channel_snapshot.invalidate();
+                    self.status_log.info(format!("Channel {channel_id} is pending confirmation"));
                 }
                 ldk_node::Event::ChannelClosed { channel_id, .. } => {
-                    self.status_message =
-                        format!("Channel {channel_id} has been closed");
+                    self.channel_snapshot.invalidate();
+                    self.pending_closes.finish(&channel_id);
+                    let sweep_sats = onchain::pending_sweep_sats(&self.node.list_balances());
+                    self.status_log.info(if sweep_sats > 0 {
+                        format!("Channel {channel_id} has been closed; {sweep_sats} sats sweeping on-chain")
+                    } else {
+                        format!("Channel {channel_id} has been closed")
+                    });
                     if self.node.list_channels().is_empty() {
                         self.show_onboarding = true;
                         self.waiting_for_payment = false;
@@ -429,6 +2197,152 @@ impl UserApp {
         }
     }
 
+    fn show_backup_screen(&mut self, ctx: &egui::Context) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.add_space(30.0);
+                    ui.heading("Recovery Phrase");
+                    ui.add_space(10.0);
+                    ui.label("These words are the only way to recover your funds. Keep them offline and private.");
+                    ui.add_space(20.0);
+
+                    if let Some(flow) = self.backup_flow.as_mut() {
+                        if flow.show(ui, &mut self.wallet_seed) {
+                            self.status_log.info("Recovery phrase backup confirmed".to_string());
+                        }
+                    }
+
+                    ui.add_space(20.0);
+                    if ui.button("Done").clicked() {
+                        self.backup_flow = None;
+                    }
+                });
+            });
+        });
+    }
+
+    fn show_settings_screen(&mut self, ctx: &egui::Context) {
+        let Some(screen) = self.settings_screen.as_mut() else {
+            return;
+        };
+        let node_info = [
+            ("Node ID", self.node.node_id().to_string()),
+            ("Network", format!("{:?}", self.network)),
+            ("Data directory", paths::data_dir(USER_NODE_ALIAS).display().to_string()),
+            ("Storage", self.storage.clone()),
+            ("LSP", self.lsp_in_use.name.clone()),
+        ];
+        let mut closed = false;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| closed = screen.show(ui, &node_info));
+        });
+        if closed {
+            self.settings_screen = None;
+        }
+    }
+
+    fn show_lsp_settings_screen(&mut self, ctx: &egui::Context) {
+        let mut test = None;
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.add_space(30.0);
+                    ui.heading("LSP Settings");
+                    ui.add_space(10.0);
+                    ui.label(format!("In use: {}", self.lsp_in_use));
+                    ui.add_space(20.0);
+
+                    if let Some(flow) = self.lsp_settings.as_mut() {
+                        test = flow.show(ui, &mut self.lsp_config, &self.lsp_in_use);
+                    }
+                    if self.worker.is_busy(CommandKind::Connect) {
+                        ui.spinner();
+                    } else if !self.lsp_test_status.is_empty() {
+                        ui.label(&self.lsp_test_status);
+                    }
+
+                    ui.add_space(20.0);
+                    if ui.button("Done").clicked() {
+                        self.lsp_settings = None;
+                        self.lsp_test_status.clear();
+                    }
+                });
+            });
+        });
+
+        if let Some((node_id, address)) = test {
+            self.worker.submit(AppCommand::Connect { node_id, address, persist: false });
+        }
+    }
+
+    fn show_restore_screen(&mut self, ctx: &egui::Context) {
+        let replaces_wallet = self.restore_would_replace_wallet();
+        let mut restored = None;
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.vertical_centered(|ui| {
+                    ui.add_space(30.0);
+                    ui.heading("Restore from Seed");
+                    ui.add_space(10.0);
+                    ui.label("Enter your recovery phrase. Words not in the BIP39 list are shown in red.");
+                    ui.add_space(20.0);
+
+                    if let Some(flow) = self.restore_flow.as_mut() {
+                        restored = flow.show(ui, replaces_wallet);
+                    }
+
+                    ui.add_space(20.0);
+                    if ui.button("Cancel").clicked() {
+                        self.restore_flow = None;
+                    }
+                });
+            });
+        });
+
+        if let Some(mnemonic) = restored {
+            self.restore_wallet(mnemonic);
+        }
+    }
+
+    fn show_restore_sync_screen(&mut self, ctx: &egui::Context) {
+        let status = self.node.status();
+        let steps_done = [
+            status.latest_onchain_wallet_sync_timestamp.is_some(),
+            status.latest_lightning_wallet_sync_timestamp.is_some(),
+        ]
+        .iter()
+        .filter(|done| **done)
+        .count();
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.add_space(30.0);
+                ui.heading("Restoring Wallet");
+                ui.add_space(20.0);
+                ui.label("Scanning the chain for your funds. This can take a few minutes.");
+                ui.add_space(10.0);
+                ui.add(
+                    egui::ProgressBar::new(steps_done as f32 / 2.0)
+                        .text(format!("{} of 2 wallets synced", steps_done)),
+                );
+                ui.label(format!("Best block: {}", status.current_best_block.height));
+
+                ui.add_space(20.0);
+                if steps_done == 2 {
+                    if ui.button("Continue").clicked() {
+                        self.restore_syncing = false;
+                        self.update_balances();
+                    }
+                } else {
+                    ui.spinner();
+                }
+            });
+        });
+    }
+
     fn show_waiting_for_payment_screen(&mut self, ctx: &egui::Context) {
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.add_space(10.0);
@@ -442,35 +2356,45 @@ impl UserApp {
                 ui.add_space(3.0);
                 ui.label("This is a Bolt11 Lightning invoice.");
                 ui.add_space(8.0);
+                if let Some(quote) = &self.jit_quote {
+                    quote.show(ui, self.btc_price);
+                    ui.add_space(8.0);
+                }
                 if let Some(ref qr) = self.qr_texture {
                     ui.image(qr);
                 } else {
                     ui.label("Lightning QR Missing");
                 }
+                if let Some(invoice) = &self.waiting_invoice {
+                    if self.refresh_requested_at.is_some() {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.colored_label(egui::Color32::YELLOW, "Invoice expired; getting a new one");
+                        });
+                    } else {
+                        ui.label(node_ui::expiry_label(invoice));
+                    }
+                }
+                if self.invoice_refreshed_at.map_or(false, |t| t.elapsed() < REFRESH_NOTICE) {
+                    ui.colored_label(egui::Color32::GREEN, "Invoice refreshed: the last one expired, so this is a new one");
+                }
                 ui.add_space(8.0);
                 ui.add(
-                    egui::TextEdit::multiline(&mut self.invoice_result)
+                    egui::TextEdit::multiline(&mut self.node_ui.invoice_result)
                         .frame(true)
                         .desired_width(400.0)
                         .desired_rows(3)
                         .hint_text("Invoice..."),
                 );
                 ui.add_space(8.0);
-                if ui
-                    .add(
-                        egui::Button::new(
-                            egui::RichText::new("Copy Invoice")
-                                .color(egui::Color32::BLACK)
-                                .size(16.0),
-                        )
+                ui_util::copy_button(
+                    ui,
+                    egui::Button::new(egui::RichText::new("Copy Invoice").color(egui::Color32::BLACK).size(16.0))
                         .min_size(egui::vec2(120.0, 36.0))
                         .fill(egui::Color32::from_gray(220))
                         .rounding(6.0),
-                    )
-                    .clicked()
-                {
-                    ui.output_mut(|o| o.copied_text = self.invoice_result.clone());
-                }
+                    || self.node_ui.invoice_result.clone(),
+                );
                 ui.add_space(5.0);
                 if ui
                     .add(
@@ -528,7 +2452,23 @@ impl UserApp {
                     egui::RichText::new("Self-custody. Your keys, your coins.")
                         .color(egui::Color32::GRAY),
                 );
-                ui.add_space(50.0);
+                ui.add_space(30.0);
+                ui.horizontal(|ui| {
+                    ui.label(egui::RichText::new("Amount (USD):").color(egui::Color32::GRAY));
+                    ui.add(egui::TextEdit::singleline(&mut self.jit_amount_input).desired_width(60.0));
+                    if let Ok(usd) = self.jit_amount_input.trim().parse::<f64>() {
+                        let estimate = match USD::from_f64(usd).to_sats_at(self.btc_price) {
+                            Some(sats) => format!("≈ {} sats", sats),
+                            None => "waiting for price…".to_string(),
+                        };
+                        ui.label(egui::RichText::new(estimate).color(egui::Color32::GRAY));
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label(egui::RichText::new("Keep stable (% of balance):").color(egui::Color32::GRAY));
+                    ui.add(egui::TextEdit::singleline(&mut self.stable_fraction_input).desired_width(40.0));
+                });
+                ui.add_space(20.0);
                 let subtle_orange =
                     egui::Color32::from_rgba_premultiplied(247, 147, 26, 200);
                 let btn = egui::Button::new(
@@ -540,16 +2480,39 @@ impl UserApp {
                 .min_size(egui::vec2(200.0, 55.0))
                 .fill(subtle_orange)
                 .rounding(8.0);
-                if ui.add(btn).clicked() {
-                    self.status_message =
-                        "Getting JIT channel invoice...".to_string();
-                    self.get_jit_invoice(ctx);
+                let busy = self.worker.is_busy(CommandKind::JitInvoice);
+                if ui.add_enabled(!busy, btn).clicked() {
+                    match self.stable_fraction_input.trim().parse::<f64>() {
+                        Ok(fraction) if fraction > 0.0 && fraction <= 100.0 => {
+                            // Proposed to the LSP once the channel is usable
+                            self.start_onboarding_channel(fraction);
+                            self.get_jit_invoice();
+                        }
+                        _ => {
+                            self.status_log.error("Stable percentage must be above 0 and at most 100".to_string());
+                        }
+                    }
                 }
-                if !self.status_message.is_empty() {
-                    ui.add_space(20.0);
-                    ui.label(self.status_message.clone());
+                if busy {
+                    ui.spinner();
+                }
+                ui.add_space(10.0);
+                if ui.link("Restore from seed").clicked() {
+                    self.restore_flow = Some(RestoreFlow::default());
+                }
+                ui.label(
+                    egui::RichText::new(format!("LSP: {}", self.lsp_in_use.name))
+                        .color(egui::Color32::GRAY),
+                );
+                if ui.link("⚙ LSP settings").clicked() {
+                    self.lsp_settings = Some(LspSettingsFlow::new(&self.lsp_config));
+                }
+                if ui.link("Buy inbound liquidity").clicked() {
+                    self.show_lsps1 = true;
                 }
                 ui.add_space(20.0);
+                self.status_log.show_latest(ui);
+                ui.add_space(20.0);
                 ui.horizontal(|ui| {
                     ui.label("Node ID: ");
                     let node_id = self.node.node_id().to_string();
@@ -559,9 +2522,7 @@ impl UserApp {
                         &node_id[node_id.len() - 10..]
                     );
                     ui.monospace(node_id_short);
-                    if ui.small_button("Copy").clicked() {
-                        ui.output_mut(|o| o.copied_text = node_id);
-                    }
+                    ui_util::copy_button(ui, egui::Button::new("Copy").small(), || node_id);
                 });
             });
         });
@@ -571,49 +2532,189 @@ impl UserApp {
         egui::CentralPanel::default().show(ctx, |ui| {
             egui::ScrollArea::vertical().show(ui, |ui| {
                 ui.vertical_centered(|ui| {
+                    self.sync_monitor.show_error_banner(ui);
+                    dev_mode::show_banner(ui);
                     ui.add_space(30.0);
                     ui.group(|ui| {
                         ui.add_space(20.0);
                         ui.heading("Your Stable Balance");
-                        let sc = self.stable_channel.lock().unwrap();
-                        let stable_btc = if sc.is_stable_receiver {
-                            sc.stable_receiver_btc
+                        let (color, label) = if self.lsp_connected.load(Ordering::SeqCst) {
+                            (egui::Color32::GREEN, "● LSP connected")
                         } else {
-                            sc.stable_provider_btc
+                            (egui::Color32::RED, "● LSP disconnected")
                         };
-                        let stable_usd = if sc.is_stable_receiver {
-                            sc.stable_receiver_usd
-                        } else {
-                            sc.stable_provider_usd
-                        };
-                        ui.add(
-                            egui::Label::new(
-                                egui::RichText::new(format!("{}", stable_usd))
-                                    .size(36.0)
-                                    .strong(),
-                            ),
-                        );
-                        ui.label(format!("Agreed Peg USD: {}", sc.expected_usd));
-                        ui.label(format!("Bitcoin: {:.8}", stable_btc));
+                        ui.label(egui::RichText::new(label).size(12.0).color(color));
+                        self.sync_monitor.show(ui);
+                        let synced_once = self.sync_monitor.state().first_sync_done;
+                        let mut pick = None;
+                        {
+                            let channels = self.stable_channels.lock().unwrap();
+                            if channels.is_empty() {
+                                ui.label("No stable channels yet. Peg one from the channel list below, or create a new one.");
+                            } else if synced_once && channels.len() > 1 {
+                                ui.label("Total");
+                                ui.add(egui::Label::new(egui::RichText::new(total_stable_balance(&channels)).size(36.0).strong()));
+                            }
+                            ui.horizontal(display::unit_toggle);
+                            for sc in channels.iter() {
+                                let selected = sc.channel_id == self.selected_channel;
+                                if show_stable_card(ui, sc, self.channel_snapshot.channels(), synced_once, selected) {
+                                    pick = Some(sc.channel_id);
+                                }
+                            }
+                        }
+                        if let Some(channel_id) = pick {
+                            self.select_channel(channel_id);
+                        }
+                        egui::CollapsingHeader::new("Wallet balance").show(ui, |ui| {
+                            self.node_ui.balances.show_breakdown(ui);
+                        });
+                        ui.add_space(10.0);
+                        let selected_pegged = self.with_selected(|sc| sc.unpegged_at.is_none());
+                        if selected_pegged.is_some() {
+                            ui.horizontal(|ui| {
+                                ui.label("Target:");
+                                ui.add(egui::TextEdit::singleline(&mut self.target_usd_input).desired_width(80.0));
+                                egui::ComboBox::from_id_salt("target_currency")
+                                    .selected_text(self.target_currency.code())
+                                    .show_ui(ui, |ui| {
+                                        for currency in Currency::ALL {
+                                            ui.selectable_value(&mut self.target_currency, currency, currency.code());
+                                        }
+                                    });
+                                ui.label("Stable %:");
+                                ui.add(egui::TextEdit::singleline(&mut self.stable_fraction_input).desired_width(40.0));
+                                if ui.button("Update").clicked() {
+                                    self.update_target_usd();
+                                }
+                            });
+                        }
+                        let pegged = selected_pegged.unwrap_or(false);
+                        let receiving = self.with_selected(|sc| sc.is_stable_receiver).unwrap_or(false);
+                        if pegged && self.confirm_unpeg {
+                            ui.label("Convert your stable balance back to BTC? The channel stays open.");
+                            ui.horizontal(|ui| {
+                                if ui.button("Unpeg").clicked() {
+                                    self.confirm_unpeg = false;
+                                    self.unpeg();
+                                }
+                                if ui.button("Cancel").clicked() {
+                                    self.confirm_unpeg = false;
+                                }
+                            });
+                        } else if pegged && ui.button("Unstable").clicked() {
+                            self.confirm_unpeg = true;
+                        }
+                        if pegged && receiving {
+                            ui.horizontal(|ui| {
+                                ui.label("Top up stable balance:");
+                                ui.add(egui::TextEdit::singleline(&mut self.top_up_input).desired_width(80.0));
+                                if worker::command_button(ui, &self.worker, CommandKind::TopUpInvoice, "Top up") {
+                                    self.top_up();
+                                }
+                            });
+                            ui.label("Withdraw to invoice:");
+                            ui.add(egui::TextEdit::singleline(&mut self.withdraw_invoice).desired_width(300.0));
+                            ui.horizontal(|ui| {
+                                ui.label("Amount (if the invoice has none):");
+                                ui.add(egui::TextEdit::singleline(&mut self.withdraw_amount_input).desired_width(80.0));
+                                if worker::command_button(ui, &self.worker, CommandKind::Withdraw, "Withdraw") {
+                                    self.withdraw();
+                                }
+                            });
+                        }
+                        if let Some(amount_msats) = self.spend_prompt {
+                            ui.label(format!(
+                                "You sent {} sats. Should it come out of your stable balance?",
+                                amount_msats / 1000
+                            ));
+                            ui.horizontal(|ui| {
+                                if ui.button("Lower target").clicked() {
+                                    self.spend_prompt = None;
+                                    match self.lower_target(self.selected_channel, amount_msats) {
+                                        Ok(_) => self.status_log.info("Lowering the stable target by the payment"),
+                                        Err(e) => self.status_log.error(format!("Target unchanged: {}", e)),
+                                    }
+                                }
+                                if ui.button("Keep target").clicked() {
+                                    self.spend_prompt = None;
+                                }
+                            });
+                        }
+                        if let Some(request) = &self.peg_request {
+                            ui.label(format!(
+                                "Your peer proposes pegging {} on channel {}, with {}",
+                                request.currency.format(request.expected_usd),
+                                request.channel_id,
+                                if request.we_receive { "you holding the stable side" } else { "you providing stability" }
+                            ));
+                            let mut answer = None;
+                            ui.horizontal(|ui| {
+                                if ui.button("Accept").clicked() {
+                                    answer = Some(true);
+                                }
+                                if ui.button("Reject").clicked() {
+                                    answer = Some(false);
+                                }
+                            });
+                            if let Some(accept) = answer {
+                                self.answer_peg_request(accept);
+                            }
+                        }
+                        if !self.negotiation_status.is_empty() {
+                            ui.label(
+                                egui::RichText::new(&self.negotiation_status)
+                                    .size(12.0)
+                                    .color(egui::Color32::GRAY),
+                            );
+                        }
                         ui.add_space(20.0);
                     });
                     ui.add_space(20.0);
                     ui.group(|ui| {
-                        let sc = self.stable_channel.lock().unwrap();
+                        ui.heading("Balance History");
+                        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+                        if let Ok(history) = self.balance_history.lock() {
+                            self.balance_chart.show(ui, &history, now);
+                        }
+                    });
+                    ui.add_space(20.0);
+                    ui.group(|ui| {
+                        let channels = self.stable_channels.lock().unwrap();
+                        let fallback = StableChannel { latest_price: self.btc_price, ..Default::default() };
+                        let sc = channels
+                            .iter()
+                            .find(|sc| sc.channel_id == self.selected_channel)
+                            .or(channels.first())
+                            .unwrap_or(&fallback);
                         ui.add_space(20.0);
                         ui.heading("Bitcoin Price");
-                        ui.label(format!("${:.2}", sc.latest_price));
+                        let streamed = price_feeds::streamed_price(sc.currency);
+                        ui.label(sc.currency.format_price(streamed.map_or(sc.latest_price, |(price, _)| price)));
+                        ui.label(
+                            egui::RichText::new(format!(
+                                "{}-minute TWAP: {}",
+                                sc.twap_window_secs / 60,
+                                sc.currency.format_price(stable::stability_price(sc))
+                            ))
+                            .size(12.0)
+                            .color(egui::Color32::GRAY),
+                        );
                         ui.add_space(20.0);
 
-                        let last_updated = match SystemTime::now().duration_since(UNIX_EPOCH + std::time::Duration::from_secs(sc.timestamp as u64)) {
-                            Ok(duration) => duration.as_secs(),
-                            Err(_) => 0,
-                        };                        
+                        let last_updated = match streamed {
+                            Some((_, age)) => age.as_secs(),
+                            None => match SystemTime::now().duration_since(UNIX_EPOCH + std::time::Duration::from_secs(sc.timestamp as u64)) {
+                                Ok(duration) => duration.as_secs(),
+                                Err(_) => 0,
+                            },
+                        };
                         ui.add_space(5.0);
                         ui.label(
                             egui::RichText::new(format!(
-                                "Last updated: {}s ago",
-                                last_updated
+                                "Last updated: {}s ago ({})",
+                                last_updated,
+                                if streamed.is_some() { "streaming" } else { "polling" }
                             ))
                             .size(12.0)
                             .color(egui::Color32::GRAY),
@@ -623,54 +2724,108 @@ impl UserApp {
                     ui.group(|ui| {
                         ui.heading("Lightning Channels");
                         ui.add_space(5.0);
-                        let channels = self.node.list_channels();
-                        if channels.is_empty() {
-                            ui.label("No channels found.");
-                        } else {
-                            for ch in channels {
-                                ui.label(format!(
-                                    "Channel: {} - {} sats",
-                                    ch.channel_id, ch.channel_value_sats
-                                ));
+                        let stable_ids: Vec<ChannelId> =
+                            self.stable_channels.lock().unwrap().iter().map(|sc| sc.channel_id).collect();
+                        let action = channel_table::show_channel_table(
+                            ui,
+                            "user_channels",
+                            self.channel_snapshot.channels(),
+                            &self.node_ui.address_book,
+                            &stable_ids,
+                            &self.pending_closes,
+                            true,
+                        );
+                        match action {
+                            Some(ChannelAction::Close(channel)) => self.close_channel(&channel),
+                            Some(ChannelAction::ForceClose(channel)) => self.force_close_candidate = Some(channel),
+                            Some(ChannelAction::Designate(channel_id)) => {
+                                self.make_stable(channel_id, !self.provide_stability)
                             }
+                            None => {}
                         }
-                    });
-                    ui.add_space(20.0);
-                    if !self.status_message.is_empty() {
-                        ui.label(self.status_message.clone());
-                        ui.add_space(10.0);
-                    }
-                    ui.group(|ui| {
-                        ui.label("Generate Invoice");
-                        ui.horizontal(|ui| {
-                            ui.label("Amount (sats):");
-                            ui.text_edit_singleline(&mut self.invoice_amount);
-                            if ui.button("Get Invoice").clicked() {
-                                self.generate_invoice();
-                            }
-                        });
-                        if !self.invoice_result.is_empty() {
-                            ui.text_edit_multiline(&mut self.invoice_result);
-                            if ui.button("Copy").clicked() {
-                                ui.output_mut(|o| {
-                                    o.copied_text = self.invoice_result.clone()
-                                });
+                        ui.checkbox(&mut self.provide_stability, "Provide stability on designated channels")
+                            .on_hover_text("Peg your peer's balance instead of yours; your side absorbs the price moves");
+                        if let Some(channel) = self.force_close_candidate.clone() {
+                            let is_stable = stable_ids.contains(&channel.channel_id);
+                            match channel_table::show_force_close_confirmation(ui, &channel, is_stable) {
+                                Some(true) => {
+                                    self.force_close_candidate = None;
+                                    self.force_close_channel(&channel);
+                                }
+                                Some(false) => self.force_close_candidate = None,
+                                None => {}
                             }
                         }
                     });
-                    ui.group(|ui| {
-                        ui.label("Pay Invoice");
-                        ui.text_edit_multiline(&mut self.invoice_to_pay);
-                        if ui.button("Pay Invoice").clicked() {
-                            self.pay_invoice();
-                        }
-                    });
+                    ui.add_space(20.0);
+                    self.status_log.show_latest(ui);
+                    ui.add_space(10.0);
+                    if let Some(status) = self.node_ui.show_invoice_section(ui, &mut self.worker, self.channel_snapshot.channels()) {
+                        self.status_log.info(status);
+                    }
+                    if let Some(status) = self.node_ui.show_pay_invoice_section(ui, &mut self.worker, self.channel_snapshot.channels()) {
+                        self.status_log.info(status);
+                    }
+                    self.pending_payments.show(ui);
+                    if let Some(status) = self.node_ui.show_offer_section(ui, &mut self.worker) {
+                        self.status_log.info(status);
+                    }
                     if ui.button("Create New Channel").clicked() {
                         self.show_onboarding = true;
                     }
-                    if ui.button("Get On-chain Address").clicked() {
-                        self.get_address();
+                    if ui.button("⚙ LSP settings").clicked() {
+                        self.lsp_settings = Some(LspSettingsFlow::new(&self.lsp_config));
                     }
+                    if ui.button("⚙ Settings").clicked() {
+                        self.settings_screen = Some(SettingsScreen::new());
+                    }
+                    let lsps1_label = if self.lsps1_flow.has_open_order() {
+                        "Buy inbound liquidity (order open)"
+                    } else {
+                        "Buy inbound liquidity"
+                    };
+                    if ui.button(lsps1_label).clicked() {
+                        self.show_lsps1 = true;
+                    }
+                    let backup_label = if self.wallet_seed.backup_confirmed() {
+                        "Backup"
+                    } else {
+                        "Backup (not yet confirmed)"
+                    };
+                    if ui.button(backup_label).clicked() {
+                        self.backup_flow = Some(BackupFlow::default());
+                    }
+                    if let Some(status) = self.node_ui.show_onchain_address_section(ui, &mut self.worker) {
+                        self.status_log.info(status);
+                    }
+                    ui.add_space(20.0);
+                    ui.label(
+                        egui::RichText::new(format!("Data directory: {}", paths::data_dir(USER_NODE_ALIAS).display()))
+                            .size(12.0)
+                            .color(egui::Color32::GRAY),
+                    );
+                    ui.label(
+                        egui::RichText::new(format!("Storage: {}", self.storage))
+                            .size(12.0)
+                            .color(egui::Color32::GRAY),
+                    );
+                    ui.label(
+                        egui::RichText::new(format!("Chain source: {}", self.chain_source))
+                            .size(12.0)
+                            .color(egui::Color32::GRAY),
+                    );
+                    egui::CollapsingHeader::new("Channel backups").show(ui, |ui| self.backups.show(ui));
+                    if let Some(status) = self.node_ui.show_peers_section(ui, &self.node, &mut self.worker) {
+                        self.status_log.info(status);
+                    }
+                    self.node_ui.show_address_book_section(ui);
+                    self.node_ui.show_sign_section(ui, &self.node);
+                    self.onchain_activity.show(ui, &self.node);
+                    let basis = CostBasis::from_channels(self.stable_channels.lock().unwrap().iter());
+                    self.payment_history.show(ui, &self.node, &basis);
+                    self.price_override.show(ui);
+                    self.status_log.show(ui);
+                    logging::show_logs_panel(ui);
                 });
             });
         });
@@ -680,9 +2835,36 @@ impl UserApp {
 #[cfg(feature = "user")]
 impl App for UserApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut Frame) {
+        if self.shutdown.load(Ordering::SeqCst) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        }
+
         self.process_events();
+        self.process_admin_requests();
+        self.process_worker_results(ctx);
+        self.channel_snapshot.refresh_if_needed(&self.node);
+        self.backups.run_if_due();
+        self.process_stability_actions();
+        self.pending_payments.track_stability(self.stable_channels.lock().unwrap().iter());
+        self.pending_payments.requery_stale(&self.node);
+        self.propose_stable_if_needed();
+        self.lsps1_flow.poll_if_due(&mut self.worker);
+        self.refresh_expired_invoice();
         self.start_background_if_needed();
-        if self.waiting_for_payment {
+        display::apply_theme(ctx);
+        if self.restore_flow.is_some() {
+            self.show_restore_screen(ctx);
+        } else if self.restore_syncing {
+            self.show_restore_sync_screen(ctx);
+        } else if self.backup_flow.is_some() {
+            self.show_backup_screen(ctx);
+        } else if self.lsp_settings.is_some() {
+            self.show_lsp_settings_screen(ctx);
+        } else if self.settings_screen.is_some() {
+            self.show_settings_screen(ctx);
+        } else if self.show_lsps1 {
+            self.show_lsps1_screen(ctx);
+        } else if self.waiting_for_payment {
             self.show_waiting_for_payment_screen(ctx);
         } else if self.show_onboarding {
             self.show_onboarding_screen(ctx);
@@ -691,11 +2873,44 @@ impl App for UserApp {
         }
         ctx.request_repaint_after(Duration::from_millis(100));
     }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.shutdown();
+    }
+}
+
+#[cfg(feature = "user")]
+/// Shown instead of the wallet when the node can't be built or started
+#[cfg(feature = "user")]
+struct StartupErrorApp {
+    message: String,
 }
 
 #[cfg(feature = "user")]
+impl App for StartupErrorApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut Frame) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("The wallet couldn't start");
+            ui.add_space(10.0);
+            ui.colored_label(egui::Color32::RED, &self.message);
+            ui.add_space(10.0);
+            if let Some(vss) = VssConfig::from_env() {
+                ui.label(format!(
+                    "Node state is stored on {}. Check that the server is reachable and the credentials \
+                     are right, or unset {} to use local storage.",
+                    vss,
+                    crate::vss::VSS_URL_ENV
+                ));
+            }
+            if ui.button("Quit").clicked() {
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            }
+        });
+    }
+}
+
 pub fn run() {
-    println!("Starting User Interface...");
+    info!("Starting User Interface...");
     let native_options = eframe::NativeOptions {
         viewport: eframe::egui::ViewportBuilder::default()
             .with_inner_size([460.0, 700.0]),
@@ -704,7 +2919,85 @@ pub fn run() {
     eframe::run_native(
         "Stable Channels",
         native_options,
-        Box::new(|_| Ok(Box::new(UserApp::new()))),
+        Box::new(|_| {
+            match UserApp::new() {
+                Ok(app) => {
+                    shutdown::install_sigint_handler(Arc::clone(&app.shutdown));
+                    Ok(Box::new(app))
+                }
+                Err(message) => Ok(Box::new(StartupErrorApp { message })),
+            }
+        }),
     )
     .unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `sc` as it comes back from the stable channels file
+    fn reload(sc: &StableChannel) -> StableChannel {
+        let json = serde_json::to_string(&StableChannelEntry::new(sc)).unwrap();
+        let entry: StableChannelEntry = serde_json::from_str(&json).unwrap();
+        let mut restored = StableChannel { channel_id: sc.channel_id, ..Default::default() };
+        restore_stable_channel(entry, &mut restored);
+        restored
+    }
+
+    #[test]
+    fn provider_state_survives_a_reload_through_a_price_cycle() {
+        let mut sc = StableChannel {
+            channel_id: ChannelId::from_bytes([1; 32]),
+            is_stable_receiver: false,
+            expected_usd: USD::from_f64(100.0),
+            native_btc: Bitcoin::from_sats(800_000),
+            native_btc_original: Bitcoin::from_sats(800_000),
+            max_payment_usd: 40.0,
+            max_payment_percent: 30.0,
+            min_seconds_between_payments: 600,
+            max_daily_adjustment_usd: 75.0,
+            circuit_breaker_percent: 12.5,
+            max_risk_level: 60,
+            approval_required_above_cap: true,
+            keyed_by_counterparty: true,
+            stability_fee_ppm: 10_000,
+            ..Default::default()
+        };
+
+        // Price down: we pay the receiver 22,000 sats, having withheld our fee
+        sc.pending_payment_id = Some(PaymentId([1; 32]));
+        sc.pending_payment_msats = 22_000_000;
+        sc.pending_fee_msats = 222_222;
+        stable::record_successful_payment(&mut sc, &PaymentId([1; 32]));
+        let mut sc = reload(&sc);
+        assert_eq!(sc.native_btc, Bitcoin::from_sats(778_000));
+        assert_eq!(sc.native_btc_original, Bitcoin::from_sats(800_000));
+
+        // Price back up: the receiver pays it back with its fee on top
+        stable::record_received_payment(&mut sc, Some(PaymentId([2; 32])), 22_220_000, 220_000);
+        let sc = reload(&sc);
+        assert_eq!(sc.native_btc.to_msats() - sc.native_btc_original.to_msats(), 220_000);
+
+        assert!(!sc.is_stable_receiver);
+        assert_eq!(sc.max_payment_usd, 40.0);
+        assert_eq!(sc.max_payment_percent, 30.0);
+        assert_eq!(sc.min_seconds_between_payments, 600);
+        assert_eq!(sc.max_daily_adjustment_usd, 75.0);
+        assert_eq!(sc.circuit_breaker_percent, 12.5);
+        assert_eq!(sc.max_risk_level, 60);
+        assert!(sc.approval_required_above_cap);
+        assert!(sc.keyed_by_counterparty);
+    }
+
+    #[test]
+    fn older_file_gets_default_limits() {
+        let json = r#"{"channel_id":"00","counterparty":"","expected_usd":100.0,"is_stable_receiver":true}"#;
+        let entry: StableChannelEntry = serde_json::from_str(json).unwrap();
+
+        assert_eq!(entry.native_btc_original, None);
+        assert_eq!(entry.max_payment_usd, stable::DEFAULT_MAX_PAYMENT_USD);
+        assert_eq!(entry.circuit_breaker_percent, stable::DEFAULT_CIRCUIT_BREAKER_PERCENT);
+        assert_eq!(entry.max_risk_level, stable::DEFAULT_MAX_RISK_LEVEL);
+    }
+}
@@ -0,0 +1,190 @@
+use ldk_node::{BuildError, Builder, Node};
+use std::collections::HashMap;
+
+/// VSS server to persist node state to; local storage is used when unset
+pub const VSS_URL_ENV: &str = "STABLE_CHANNELS_VSS_URL";
+/// Namespace for this node's keys on the VSS server
+pub const VSS_STORE_ID_ENV: &str = "STABLE_CHANNELS_VSS_STORE_ID";
+/// Sent as `Authorization: Bearer <token>` with every VSS request
+pub const VSS_AUTH_TOKEN_ENV: &str = "STABLE_CHANNELS_VSS_AUTH_TOKEN";
+/// LNURL-auth server issuing VSS tokens, used instead of a fixed token
+pub const VSS_LNURL_AUTH_URL_ENV: &str = "STABLE_CHANNELS_VSS_LNURL_AUTH_URL";
+
+const DEFAULT_STORE_ID: &str = "stable-channels";
+
+/// Remote persistence of the node's channel state, so a lost device doesn't lose channels.
+/// The wallet seed still has to be restored from the recovery phrase.
+#[derive(Debug, Clone)]
+pub struct VssConfig {
+    pub url: String,
+    pub store_id: String,
+    auth_token: Option<String>,
+    lnurl_auth_url: Option<String>,
+}
+
+impl VssConfig {
+    /// The configured VSS server, or None to keep state on local disk
+    pub fn from_env() -> Option<Self> {
+        let url = std::env::var(VSS_URL_ENV).ok().filter(|s| !s.trim().is_empty())?;
+        let non_empty = |name: &str| std::env::var(name).ok().filter(|s| !s.trim().is_empty());
+        Some(Self {
+            url,
+            store_id: non_empty(VSS_STORE_ID_ENV).unwrap_or_else(|| DEFAULT_STORE_ID.to_string()),
+            auth_token: non_empty(VSS_AUTH_TOKEN_ENV),
+            lnurl_auth_url: non_empty(VSS_LNURL_AUTH_URL_ENV),
+        })
+    }
+
+    pub fn build(&self, builder: &Builder) -> Result<Node, BuildError> {
+        let mut headers = HashMap::new();
+        if let Some(token) = &self.auth_token {
+            headers.insert("Authorization".to_string(), format!("Bearer {}", token));
+        }
+        match &self.lnurl_auth_url {
+            Some(lnurl_auth_url) => {
+                builder.build_with_vss_store(self.url.clone(), self.store_id.clone(), lnurl_auth_url.clone(), headers)
+            }
+            None => builder.build_with_vss_store_and_fixed_headers(self.url.clone(), self.store_id.clone(), headers),
+        }
+    }
+}
+
+impl std::fmt::Display for VssConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "VSS {} (store {})", self.url, self.store_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ldk_node::bip39::Mnemonic;
+    use ldk_node::bitcoin::hashes::Hash;
+    use ldk_node::bitcoin::Network;
+    use ldk_node::lightning::ln::channelmanager::PaymentId;
+    use ldk_node::lightning_invoice::{Bolt11InvoiceDescription, Description};
+    use prost::Message;
+    use std::io::Read;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use vss_client::types::{
+        DeleteObjectRequest, DeleteObjectResponse, ErrorCode, ErrorResponse, GetObjectRequest, GetObjectResponse,
+        KeyValue, ListKeyVersionsRequest, ListKeyVersionsResponse, PutObjectRequest, PutObjectResponse,
+    };
+
+    /// Stored objects by store and key, with their version
+    type Objects = Arc<Mutex<HashMap<(String, String), (i64, Vec<u8>)>>>;
+
+    /// Serves the VSS API from memory on a local port: enough of it for ldk-node's store,
+    /// with no auth and no version conflicts. Returns the base URL and the objects.
+    fn start_mock_vss() -> (String, Objects) {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/vss", server.server_addr().to_ip().unwrap());
+        let objects = Objects::default();
+        let stored = Arc::clone(&objects);
+        std::thread::spawn(move || {
+            for mut request in server.incoming_requests() {
+                let mut body = Vec::new();
+                request.as_reader().read_to_end(&mut body).unwrap();
+                let (status, reply) = handle(&stored, request.url(), &body);
+                let _ = request.respond(tiny_http::Response::from_data(reply).with_status_code(status));
+            }
+        });
+        (url, objects)
+    }
+
+    fn handle(objects: &Objects, url: &str, body: &[u8]) -> (u16, Vec<u8>) {
+        let mut objects = objects.lock().unwrap();
+        let endpoint = url.rsplit('/').next().unwrap_or_default();
+        match endpoint {
+            "getObject" => {
+                let request = GetObjectRequest::decode(body).unwrap();
+                match objects.get(&(request.store_id, request.key.clone())) {
+                    Some((version, value)) => {
+                        let value = KeyValue { key: request.key, version: *version, value: value.clone() };
+                        (200, GetObjectResponse { value: Some(value) }.encode_to_vec())
+                    }
+                    None => {
+                        let error = ErrorResponse {
+                            error_code: ErrorCode::NoSuchKeyException as i32,
+                            message: format!("no such key {}", request.key),
+                        };
+                        (404, error.encode_to_vec())
+                    }
+                }
+            }
+            "putObjects" => {
+                let request = PutObjectRequest::decode(body).unwrap();
+                for item in request.transaction_items {
+                    let key = (request.store_id.clone(), item.key);
+                    let version = objects.get(&key).map_or(0, |(version, _)| version + 1);
+                    objects.insert(key, (version, item.value));
+                }
+                for item in request.delete_items {
+                    objects.remove(&(request.store_id.clone(), item.key));
+                }
+                (200, PutObjectResponse {}.encode_to_vec())
+            }
+            "deleteObject" => {
+                let request = DeleteObjectRequest::decode(body).unwrap();
+                if let Some(item) = request.key_value {
+                    objects.remove(&(request.store_id, item.key));
+                }
+                (200, DeleteObjectResponse {}.encode_to_vec())
+            }
+            "listKeyVersions" => {
+                let request = ListKeyVersionsRequest::decode(body).unwrap();
+                let prefix = request.key_prefix.unwrap_or_default();
+                let key_versions = objects
+                    .iter()
+                    .filter(|((store_id, key), _)| *store_id == request.store_id && key.starts_with(&prefix))
+                    .map(|((_, key), (version, _))| KeyValue { key: key.clone(), version: *version, value: Vec::new() })
+                    .collect();
+                let response = ListKeyVersionsResponse { key_versions, next_page_token: None, global_version: None };
+                (200, response.encode_to_vec())
+            }
+            _ => (404, Vec::new()),
+        }
+    }
+
+    /// A regtest node backed by `vss`, never started, so nothing needs a chain
+    fn build_node(vss: &VssConfig, mnemonic: &Mnemonic, data_dir: &Path) -> Node {
+        let mut builder = Builder::new();
+        builder.set_network(Network::Regtest);
+        builder.set_entropy_bip39_mnemonic(mnemonic.clone(), None);
+        builder.set_chain_source_esplora("http://127.0.0.1:1".to_string(), None);
+        builder.set_storage_dir_path(data_dir.display().to_string());
+        vss.build(&builder).unwrap()
+    }
+
+    #[test]
+    fn fresh_data_dir_resumes_the_node_from_vss() {
+        let (url, objects) = start_mock_vss();
+        let vss = VssConfig {
+            url,
+            store_id: DEFAULT_STORE_ID.to_string(),
+            auth_token: Some("token".to_string()),
+            lnurl_auth_url: None,
+        };
+        let mnemonic = ldk_node::generate_entropy_mnemonic();
+        let root = std::env::temp_dir().join(format!("stable-channels-vss-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+
+        let first = build_node(&vss, &mnemonic, &root.join("first"));
+        let description = Bolt11InvoiceDescription::Direct(Description::new("resume".to_string()).unwrap());
+        let invoice = first.bolt11_payment().receive(10_000_000, &description, 3_600).unwrap();
+        let payment_id = PaymentId(invoice.payment_hash().to_byte_array());
+        let node_id = first.node_id();
+        drop(first);
+        assert!(!objects.lock().unwrap().is_empty(), "nothing was persisted to VSS");
+
+        // Only the recovery phrase comes along; everything else is read back from VSS
+        let second = build_node(&vss, &mnemonic, &root.join("second"));
+        assert_eq!(second.node_id(), node_id);
+        let payment = second.payment(&payment_id).expect("the first node's invoice wasn't resumed");
+        assert_eq!(payment.amount_msat, Some(10_000_000));
+
+        drop(second);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
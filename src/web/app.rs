@@ -0,0 +1,122 @@
+use crate::currency::Currency;
+use crate::node::NodePanel;
+use crate::price_sources::{self, HttpClient, OnJson};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Seconds between price fetches
+const REFRESH_SECS: f64 = 60.0;
+
+/// Fetches through the browser, answering once the response arrives
+struct FetchClient;
+
+impl HttpClient for FetchClient {
+    fn get_json(&self, url: &str, done: OnJson) {
+        ehttp::fetch(ehttp::Request::get(url), move |response| {
+            done(response.and_then(|r| {
+                if r.ok {
+                    serde_json::from_slice(&r.bytes).map_err(|e| e.to_string())
+                } else {
+                    Err(format!("HTTP {}", r.status))
+                }
+            }))
+        });
+    }
+}
+
+/// The last fetch's median price and how many feeds it came from
+#[derive(Clone, Copy)]
+struct FetchedPrice {
+    price: f64,
+    sources: usize,
+}
+
+pub struct WebApp {
+    currency: Currency,
+    price: Arc<Mutex<Option<FetchedPrice>>>,
+    /// Egui time of the last fetch, None before the first
+    fetched_at: Option<f64>,
+    node: NodePanel,
+}
+
+impl WebApp {
+    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
+        Self { currency: Currency::USD, price: Arc::new(Mutex::new(None)), fetched_at: None, node: NodePanel::new() }
+    }
+
+    /// Asks the feeds for the price in the chosen currency; the answer lands in `price`
+    fn fetch_price(&mut self, ctx: &egui::Context) {
+        self.fetched_at = Some(ctx.input(|i| i.time));
+        let price = Arc::clone(&self.price);
+        let ctx = ctx.clone();
+        price_sources::request_prices(
+            &FetchClient,
+            &price_sources::set_price_feeds(),
+            self.currency,
+            Box::new(move |prices| {
+                let mut values: Vec<f64> = prices.iter().map(|(_, price)| *price).collect();
+                if !values.is_empty() {
+                    let sources = values.len();
+                    *price.lock().unwrap() = Some(FetchedPrice { price: price_sources::median(&mut values), sources });
+                }
+                ctx.request_repaint();
+            }),
+        );
+    }
+}
+
+impl eframe::App for WebApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let now = ctx.input(|i| i.time);
+        if self.fetched_at.map_or(true, |at| now - at >= REFRESH_SECS) {
+            self.fetch_price(ctx);
+        }
+        ctx.request_repaint_after(Duration::from_secs(REFRESH_SECS as u64));
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.heading(egui::RichText::new("Stable Channels v0.1").size(28.0).strong());
+                ui.add_space(30.0);
+
+                let fetched = *self.price.lock().unwrap();
+                ui.heading(match fetched {
+                    Some(fetched) => self.currency.format(fetched.price),
+                    None => "waiting for price…".to_string(),
+                });
+                if let Some(fetched) = fetched {
+                    ui.label(
+                        egui::RichText::new(format!("BTC/{}, median of {} feeds", self.currency, fetched.sources))
+                            .color(egui::Color32::GRAY),
+                    );
+                }
+                ui.horizontal(|ui| {
+                    let previous = self.currency;
+                    for currency in Currency::ALL {
+                        ui.selectable_value(&mut self.currency, currency, currency.code());
+                    }
+                    if self.currency != previous {
+                        *self.price.lock().unwrap() = None;
+                        self.fetch_price(ctx);
+                    }
+                });
+                ui.add_space(40.0);
+
+                for (step, detail) in [
+                    ("Step 1: Get a Lightning invoice ⚡", "Press \"Make stable\" in the Stable Channels app."),
+                    ("Step 2: Send yourself bitcoin 💸", "Over Lightning, from an app or an exchange."),
+                    ("Step 3: Stable channel created 🔧", "Self-custody. Your keys, your coins."),
+                ] {
+                    ui.heading(step);
+                    ui.label(egui::RichText::new(detail).color(egui::Color32::GRAY));
+                    ui.add_space(20.0);
+                }
+                ui.label(
+                    egui::RichText::new("Your node runs in the desktop app; this page can't hold funds yet.")
+                        .color(egui::Color32::YELLOW),
+                );
+                ui.add_space(20.0);
+                self.node.show(ui);
+            });
+        });
+    }
+}
@@ -0,0 +1,46 @@
+// The user app's onboarding screen in the browser. Build and serve it with `trunk serve`
+// from the repository root, which reads index.html. The node doesn't run here yet: this
+// shows live prices and how to get started, and talks to a running user app through its
+// admin API (`--admin-listen`) for balances, stable channels and invoices.
+
+// Shared with the desktop apps, which use more of them than this does
+#[cfg(target_arch = "wasm32")]
+#[allow(dead_code)]
+#[path = "../currency.rs"]
+mod currency;
+
+#[cfg(target_arch = "wasm32")]
+#[allow(dead_code)]
+#[path = "../price_sources.rs"]
+mod price_sources;
+
+#[cfg(target_arch = "wasm32")]
+mod app;
+
+#[cfg(target_arch = "wasm32")]
+mod node;
+
+#[cfg(target_arch = "wasm32")]
+fn main() {
+    use eframe::wasm_bindgen::JsCast as _;
+
+    tracing_wasm::set_as_global_default();
+    wasm_bindgen_futures::spawn_local(async {
+        let canvas = web_sys::window()
+            .and_then(|window| window.document())
+            .and_then(|document| document.get_element_by_id("stable_channels_canvas"))
+            .and_then(|element| element.dyn_into::<web_sys::HtmlCanvasElement>().ok())
+            .expect("index.html has no stable_channels_canvas");
+        let started = eframe::WebRunner::new()
+            .start(canvas, eframe::WebOptions::default(), Box::new(|cc| Ok(Box::new(app::WebApp::new(cc)))))
+            .await;
+        if let Err(e) = started {
+            tracing::error!("Failed to start the web app: {:?}", e);
+        }
+    });
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    eprintln!("This is the browser build; run `trunk serve` to build and open it");
+}
@@ -0,0 +1,176 @@
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+
+/// Seconds between balance and stable channel polls
+const POLL_SECS: f64 = 10.0;
+
+/// What the node's admin API last said; each request fills in its own part
+#[derive(Default)]
+struct Fetched {
+    balances: Option<Value>,
+    stable_channels: Option<Value>,
+    invoice: Option<String>,
+    error: Option<String>,
+}
+
+/// Shows a desktop or headless user node through its admin API, started with
+/// `--admin-listen` and STABLE_CHANNELS_ADMIN_TOKEN
+pub struct NodePanel {
+    url: String,
+    token: String,
+    connected: bool,
+    invoice_sats: String,
+    fetched: Arc<Mutex<Fetched>>,
+    /// Egui time of the last poll, None before the first
+    polled_at: Option<f64>,
+}
+
+impl NodePanel {
+    pub fn new() -> Self {
+        Self {
+            url: "http://127.0.0.1:9740".to_string(),
+            token: String::new(),
+            connected: false,
+            invoice_sats: String::new(),
+            fetched: Arc::new(Mutex::new(Fetched::default())),
+            polled_at: None,
+        }
+    }
+
+    /// Sends `request` with the bearer token; `store` gets the JSON body of a 2xx answer
+    fn send(&self, ctx: &egui::Context, mut request: ehttp::Request, store: fn(&mut Fetched, Value)) {
+        request.headers.insert("Authorization", format!("Bearer {}", self.token));
+        let fetched = Arc::clone(&self.fetched);
+        let ctx = ctx.clone();
+        ehttp::fetch(request, move |response| {
+            let result = response.and_then(|r| {
+                let body: Value = serde_json::from_slice(&r.bytes).map_err(|e| e.to_string())?;
+                if r.ok {
+                    Ok(body)
+                } else {
+                    Err(body["error"].as_str().map(str::to_string).unwrap_or_else(|| format!("HTTP {}", r.status)))
+                }
+            });
+            let mut fetched = fetched.lock().unwrap();
+            match result {
+                Ok(body) => {
+                    fetched.error = None;
+                    store(&mut fetched, body);
+                }
+                Err(e) => fetched.error = Some(e),
+            }
+            ctx.request_repaint();
+        });
+    }
+
+    fn poll(&mut self, ctx: &egui::Context) {
+        self.polled_at = Some(ctx.input(|i| i.time));
+        let base = self.url.trim_end_matches('/');
+        self.send(ctx, ehttp::Request::get(format!("{}/balances", base)), |f, body| f.balances = Some(body));
+        self.send(ctx, ehttp::Request::get(format!("{}/stable-channels", base)), |f, body| {
+            f.stable_channels = Some(body)
+        });
+    }
+
+    fn request_invoice(&self, ctx: &egui::Context, amount_sats: u64) {
+        let body = serde_json::json!({ "amount_sats": amount_sats, "description": "Stable Channels" });
+        let mut request = ehttp::Request::post(
+            format!("{}/invoice", self.url.trim_end_matches('/')),
+            serde_json::to_vec(&body).unwrap(),
+        );
+        request.headers.insert("Content-Type", "application/json");
+        self.send(ctx, request, |f, body| f.invoice = body["invoice"].as_str().map(str::to_string));
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        let ctx = ui.ctx().clone();
+        if !self.connected {
+            ui.heading("Your node");
+            egui::Grid::new("node_connect").num_columns(2).show(ui, |ui| {
+                ui.label("Admin API");
+                ui.text_edit_singleline(&mut self.url);
+                ui.end_row();
+                ui.label("Token");
+                ui.add(egui::TextEdit::singleline(&mut self.token).password(true));
+                ui.end_row();
+            });
+            if ui.add_enabled(!self.token.is_empty(), egui::Button::new("Connect")).clicked() {
+                self.connected = true;
+                self.poll(&ctx);
+            }
+            return;
+        }
+
+        let now = ctx.input(|i| i.time);
+        if self.polled_at.map_or(true, |at| now - at >= POLL_SECS) {
+            self.poll(&ctx);
+        }
+        ctx.request_repaint_after(std::time::Duration::from_secs(POLL_SECS as u64));
+
+        ui.heading("Your node");
+        let fetched = self.fetched.lock().unwrap();
+        if let Some(error) = &fetched.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+        if let Some(balances) = &fetched.balances {
+            ui.label(format!(
+                "Lightning {:.8} BTC (${:.2}), on-chain {:.8} BTC (${:.2})",
+                balances["lightning_btc"].as_f64().unwrap_or_default(),
+                balances["lightning_usd"].as_f64().unwrap_or_default(),
+                balances["onchain_btc"].as_f64().unwrap_or_default(),
+                balances["onchain_usd"].as_f64().unwrap_or_default(),
+            ));
+        }
+        if let Some(Value::Array(channels)) = &fetched.stable_channels {
+            if channels.is_empty() {
+                ui.label(egui::RichText::new("No stable channels yet").color(egui::Color32::GRAY));
+            }
+            for sc in channels {
+                let code = sc["currency"].as_str().unwrap_or("USD");
+                let status = if sc["suspension_reason"].is_string() {
+                    "suspended"
+                } else if sc["peg_agreed"].as_bool().unwrap_or(false) {
+                    "pegged"
+                } else {
+                    "waiting for the LSP"
+                };
+                ui.label(format!(
+                    "{:.2} {} stable, {:.2} {} from par ({:.2}%), {}",
+                    sc["expected_usd"].as_f64().unwrap_or_default(),
+                    code,
+                    sc["dollars_from_par"].as_f64().unwrap_or_default(),
+                    code,
+                    sc["percent_from_par"].as_f64().unwrap_or_default(),
+                    status,
+                ));
+            }
+        }
+        let invoice = fetched.invoice.clone();
+        drop(fetched);
+
+        ui.add_space(10.0);
+        ui.horizontal(|ui| {
+            ui.label("Receive sats");
+            ui.add(egui::TextEdit::singleline(&mut self.invoice_sats).desired_width(100.0));
+            let amount = self.invoice_sats.trim().parse::<u64>().ok().filter(|sats| *sats > 0);
+            if ui.add_enabled(amount.is_some(), egui::Button::new("Get invoice")).clicked() {
+                if let Some(amount) = amount {
+                    self.request_invoice(&ctx, amount);
+                }
+            }
+        });
+        if let Some(invoice) = invoice {
+            ui.horizontal(|ui| {
+                ui.label(egui::RichText::new(format!("{}…", &invoice[..invoice.len().min(40)])).monospace());
+                if ui.button("Copy").clicked() {
+                    ctx.copy_text(invoice.clone());
+                }
+            });
+        }
+        if ui.button("Disconnect").clicked() {
+            self.connected = false;
+            self.polled_at = None;
+            *self.fetched.lock().unwrap() = Fetched::default();
+        }
+    }
+}
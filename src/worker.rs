@@ -0,0 +1,356 @@
+use crate::settings;
+use crate::shutdown;
+use ldk_node::bitcoin::secp256k1::PublicKey;
+use ldk_node::bitcoin::{Address, FeeRate, Txid};
+use ldk_node::lightning::ln::channelmanager::PaymentId;
+use ldk_node::lightning::ln::msgs::SocketAddress;
+use ldk_node::lightning::offers::offer::Offer;
+use ldk_node::lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescription, Description};
+use ldk_node::config::ChannelConfig;
+use ldk_node::lightning_liquidity::lsps1::msgs::OrderId;
+use ldk_node::liquidity::LSPS1OrderStatus;
+use ldk_node::payment::SendingParameters;
+use ldk_node::{Node, NodeError, UserChannelId};
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+use tracing::{debug, error};
+
+/// Which button a command belongs to, for showing it as busy while in flight
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CommandKind {
+    GenerateInvoice,
+    JitInvoice,
+    TopUpInvoice,
+    PayInvoice,
+    Withdraw,
+    CreateOffer,
+    PayOffer,
+    NewAddress,
+    SendOnchain,
+    OpenChannel,
+    Connect,
+    /// Connections made from the Peers panel
+    ConnectPeer,
+    Lsps1Order,
+    DepositInvoice,
+    AccountWithdraw,
+}
+
+/// Node calls that can block on the network, run off the UI thread
+pub enum AppCommand {
+    GenerateInvoice { amount_msats: u64, description: String },
+    JitInvoice { amount_msats: u64, description: String, max_lsp_fee_msats: Option<u64> },
+    /// Invoice for adding to the stable balance, through a JIT channel if `jit`
+    TopUpInvoice { amount_msats: u64, jit: bool, max_lsp_fee_msats: Option<u64> },
+    /// `amount_msats` is for invoices without an amount; `max_fee_msats` caps routing fees
+    PayInvoice { invoice: Bolt11Invoice, amount_msats: Option<u64>, max_fee_msats: Option<u64> },
+    /// Payment out of the stable balance; `amount_msats` is for invoices without an amount
+    Withdraw { invoice: Bolt11Invoice, amount_msats: Option<u64> },
+    /// Reusable BOLT12 offer, for any amount if `amount_msats` is None
+    CreateOffer { amount_msats: Option<u64>, description: String },
+    /// `amount_msats` is for offers without an amount
+    PayOffer { offer: Offer, amount_msats: Option<u64> },
+    NewAddress,
+    /// Sends the whole spendable balance, less the anchor reserve, if `amount_sats` is None
+    SendOnchain { address: Address, amount_sats: Option<u64>, fee_rate: FeeRate },
+    /// Announced to the network's gossip only if `announce`
+    OpenChannel {
+        node_id: PublicKey,
+        address: SocketAddress,
+        amount_sats: u64,
+        push_msat: Option<u64>,
+        announce: bool,
+        /// Routing policy for the channel; the node's default if None
+        channel_config: Option<ChannelConfig>,
+    },
+    /// Connects to a peer, remembering it across restarts if `persist`
+    Connect { node_id: PublicKey, address: SocketAddress, persist: bool },
+    /// Like `Connect`, but answered to the Peers panel rather than the app
+    ConnectPeer { node_id: PublicKey, address: SocketAddress, persist: bool },
+    /// Orders a channel from the LSP over LSPS1; the reply carries the order's invoice
+    RequestLsps1Channel { lsp_balance_sat: u64, client_balance_sat: u64, channel_expiry_blocks: u32 },
+    CheckLsps1Order { order_id: OrderId },
+    PayLsps1Order { invoice: Bolt11Invoice },
+    /// Invoice crediting `account` when paid
+    DepositInvoice { account: String, amount_msats: u64 },
+    /// Approved withdrawal from an account; `amount_msats` is for invoices without an amount
+    AccountWithdraw { withdrawal_id: u64, invoice: Bolt11Invoice, amount_msats: Option<u64> },
+    /// Approved on-chain withdrawal from an account, at the node's fee estimate
+    AccountSendOnchain { withdrawal_id: u64, address: Address, amount_sats: u64 },
+}
+
+impl AppCommand {
+    fn kind(&self) -> CommandKind {
+        match self {
+            AppCommand::GenerateInvoice { .. } => CommandKind::GenerateInvoice,
+            AppCommand::JitInvoice { .. } => CommandKind::JitInvoice,
+            AppCommand::TopUpInvoice { .. } => CommandKind::TopUpInvoice,
+            AppCommand::PayInvoice { .. } => CommandKind::PayInvoice,
+            AppCommand::Withdraw { .. } => CommandKind::Withdraw,
+            AppCommand::CreateOffer { .. } => CommandKind::CreateOffer,
+            AppCommand::PayOffer { .. } => CommandKind::PayOffer,
+            AppCommand::NewAddress => CommandKind::NewAddress,
+            AppCommand::SendOnchain { .. } => CommandKind::SendOnchain,
+            AppCommand::OpenChannel { .. } => CommandKind::OpenChannel,
+            AppCommand::Connect { .. } => CommandKind::Connect,
+            AppCommand::ConnectPeer { .. } => CommandKind::ConnectPeer,
+            AppCommand::RequestLsps1Channel { .. }
+            | AppCommand::CheckLsps1Order { .. }
+            | AppCommand::PayLsps1Order { .. } => CommandKind::Lsps1Order,
+            AppCommand::DepositInvoice { .. } => CommandKind::DepositInvoice,
+            AppCommand::AccountWithdraw { .. } | AppCommand::AccountSendOnchain { .. } => CommandKind::AccountWithdraw,
+        }
+    }
+}
+
+pub enum AppResult {
+    InvoiceGenerated(Result<Bolt11Invoice, NodeError>),
+    JitInvoiceGenerated(Result<Bolt11Invoice, NodeError>),
+    TopUpInvoiceGenerated(Result<Bolt11Invoice, NodeError>),
+    InvoicePaid(Result<PaymentId, NodeError>),
+    WithdrawSent(Result<PaymentId, NodeError>),
+    OfferCreated(Result<Offer, NodeError>),
+    OfferPaid(Result<PaymentId, NodeError>),
+    AddressGenerated(Result<Address, NodeError>),
+    OnchainSent(Result<Txid, NodeError>),
+    ChannelOpened { node_id: PublicKey, amount_sats: u64, result: Result<UserChannelId, NodeError> },
+    Connected { node_id: PublicKey, result: Result<(), NodeError> },
+    PeerConnected { node_id: PublicKey, result: Result<(), NodeError> },
+    Lsps1OrderStatus(Result<LSPS1OrderStatus, NodeError>),
+    Lsps1OrderPaid(Result<PaymentId, NodeError>),
+    DepositInvoiceGenerated { account: String, result: Result<Bolt11Invoice, NodeError> },
+    AccountWithdrawSent { withdrawal_id: u64, result: Result<PaymentId, NodeError> },
+    AccountOnchainSent { withdrawal_id: u64, result: Result<Txid, NodeError> },
+}
+
+impl AppResult {
+    fn kind(&self) -> CommandKind {
+        match self {
+            AppResult::InvoiceGenerated(_) => CommandKind::GenerateInvoice,
+            AppResult::JitInvoiceGenerated(_) => CommandKind::JitInvoice,
+            AppResult::TopUpInvoiceGenerated(_) => CommandKind::TopUpInvoice,
+            AppResult::InvoicePaid(_) => CommandKind::PayInvoice,
+            AppResult::WithdrawSent(_) => CommandKind::Withdraw,
+            AppResult::OfferCreated(_) => CommandKind::CreateOffer,
+            AppResult::OfferPaid(_) => CommandKind::PayOffer,
+            AppResult::AddressGenerated(_) => CommandKind::NewAddress,
+            AppResult::OnchainSent(_) => CommandKind::SendOnchain,
+            AppResult::ChannelOpened { .. } => CommandKind::OpenChannel,
+            AppResult::Connected { .. } => CommandKind::Connect,
+            AppResult::PeerConnected { .. } => CommandKind::ConnectPeer,
+            AppResult::Lsps1OrderStatus(_) | AppResult::Lsps1OrderPaid(_) => CommandKind::Lsps1Order,
+            AppResult::DepositInvoiceGenerated { .. } => CommandKind::DepositInvoice,
+            AppResult::AccountWithdrawSent { .. } | AppResult::AccountOnchainSent { .. } => CommandKind::AccountWithdraw,
+        }
+    }
+}
+
+/// Background thread executing `AppCommand`s against the node. Results are drained from
+/// the UI thread with `try_recv`.
+pub struct Worker {
+    commands: Option<mpsc::Sender<AppCommand>>,
+    results: mpsc::Receiver<AppResult>,
+    in_flight: HashMap<CommandKind, usize>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    pub fn spawn(node: Arc<Node>) -> Self {
+        let (command_tx, command_rx) = mpsc::channel::<AppCommand>();
+        let (result_tx, result_rx) = mpsc::channel();
+
+        let handle = std::thread::spawn(move || {
+            // Ends once the app drops its sender
+            for command in command_rx {
+                if result_tx.send(execute(&node, command)).is_err() {
+                    break;
+                }
+            }
+            debug!("Worker thread stopped");
+        });
+
+        Self { commands: Some(command_tx), results: result_rx, in_flight: HashMap::new(), handle: Some(handle) }
+    }
+
+    pub fn submit(&mut self, command: AppCommand) {
+        let kind = command.kind();
+        match &self.commands {
+            Some(commands) if commands.send(command).is_ok() => {
+                *self.in_flight.entry(kind).or_insert(0) += 1;
+            }
+            _ => error!("Worker is not running; dropping {:?} command", kind),
+        }
+    }
+
+    pub fn is_busy(&self, kind: CommandKind) -> bool {
+        self.in_flight.get(&kind).map_or(false, |count| *count > 0)
+    }
+
+    /// The next finished command, if any, without blocking
+    pub fn try_recv(&mut self) -> Option<AppResult> {
+        let result = self.results.try_recv().ok()?;
+        if let Some(count) = self.in_flight.get_mut(&result.kind()) {
+            *count = count.saturating_sub(1);
+        }
+        Some(result)
+    }
+
+    /// Lets the queued commands finish, then joins the thread
+    pub fn stop(&mut self) {
+        self.commands.take();
+        if let Some(handle) = self.handle.take() {
+            shutdown::join_with_timeout(handle, shutdown::THREAD_JOIN_TIMEOUT);
+        }
+    }
+}
+
+/// Runs `command` on the calling thread
+pub fn execute(node: &Node, command: AppCommand) -> AppResult {
+    match command {
+        AppCommand::GenerateInvoice { amount_msats, description } => {
+            let result = invoice_description(description).and_then(|description| {
+                node.bolt11_payment().receive(amount_msats, &description, invoice_expiry_secs())
+            });
+            AppResult::InvoiceGenerated(result)
+        }
+        AppCommand::JitInvoice { amount_msats, description, max_lsp_fee_msats } => {
+            let result = invoice_description(description).and_then(|description| {
+                node.bolt11_payment().receive_via_jit_channel(
+                    amount_msats,
+                    &description,
+                    invoice_expiry_secs(),
+                    max_lsp_fee_msats,
+                )
+            });
+            AppResult::JitInvoiceGenerated(result)
+        }
+        AppCommand::TopUpInvoice { amount_msats, jit, max_lsp_fee_msats } => {
+            let result = invoice_description("Stable balance top-up".to_string()).and_then(|description| {
+                let payment = node.bolt11_payment();
+                if jit {
+                    payment.receive_via_jit_channel(amount_msats, &description, invoice_expiry_secs(), max_lsp_fee_msats)
+                } else {
+                    payment.receive(amount_msats, &description, invoice_expiry_secs())
+                }
+            });
+            AppResult::TopUpInvoiceGenerated(result)
+        }
+        AppCommand::PayInvoice { invoice, amount_msats, max_fee_msats } => {
+            let params = max_fee_msats.map(|fee| SendingParameters {
+                max_total_routing_fee_msat: Some(Some(fee)),
+                max_total_cltv_expiry_delta: None,
+                max_path_count: None,
+                max_channel_saturation_power_of_half: None,
+            });
+            let payment = node.bolt11_payment();
+            let result = match amount_msats {
+                Some(amount_msats) => payment.send_using_amount(&invoice, amount_msats, params),
+                None => payment.send(&invoice, params),
+            };
+            AppResult::InvoicePaid(result)
+        }
+        AppCommand::Withdraw { invoice, amount_msats } => {
+            let payment = node.bolt11_payment();
+            let result = match amount_msats {
+                Some(amount_msats) => payment.send_using_amount(&invoice, amount_msats, None),
+                None => payment.send(&invoice, None),
+            };
+            AppResult::WithdrawSent(result)
+        }
+        AppCommand::CreateOffer { amount_msats, description } => {
+            let payment = node.bolt12_payment();
+            let result = match amount_msats {
+                Some(amount_msats) => payment.receive(amount_msats, &description, None, None),
+                None => payment.receive_variable_amount(&description, None),
+            };
+            AppResult::OfferCreated(result)
+        }
+        AppCommand::PayOffer { offer, amount_msats } => {
+            let payment = node.bolt12_payment();
+            let result = match amount_msats {
+                Some(amount_msats) => payment.send_using_amount(&offer, amount_msats, None, None),
+                None => payment.send(&offer, None, None),
+            };
+            AppResult::OfferPaid(result)
+        }
+        AppCommand::NewAddress => AppResult::AddressGenerated(node.onchain_payment().new_address()),
+        AppCommand::SendOnchain { address, amount_sats, fee_rate } => {
+            let payment = node.onchain_payment();
+            let result = match amount_sats {
+                Some(amount_sats) => payment.send_to_address(&address, amount_sats, Some(fee_rate)),
+                None => payment.send_all_to_address(&address, true, Some(fee_rate)),
+            };
+            AppResult::OnchainSent(result)
+        }
+        AppCommand::OpenChannel { node_id, address, amount_sats, push_msat, announce, channel_config } => {
+            let result = if announce {
+                node.open_announced_channel(node_id, address, amount_sats, push_msat, channel_config)
+            } else {
+                node.open_channel(node_id, address, amount_sats, push_msat, channel_config)
+            };
+            AppResult::ChannelOpened { node_id, amount_sats, result }
+        }
+        AppCommand::Connect { node_id, address, persist } => {
+            AppResult::Connected { node_id, result: node.connect(node_id, address, persist) }
+        }
+        AppCommand::ConnectPeer { node_id, address, persist } => {
+            AppResult::PeerConnected { node_id, result: node.connect(node_id, address, persist) }
+        }
+        AppCommand::RequestLsps1Channel { lsp_balance_sat, client_balance_sat, channel_expiry_blocks } => {
+            AppResult::Lsps1OrderStatus(node.lsps1_liquidity().request_channel(
+                lsp_balance_sat,
+                client_balance_sat,
+                channel_expiry_blocks,
+                false,
+            ))
+        }
+        AppCommand::CheckLsps1Order { order_id } => {
+            AppResult::Lsps1OrderStatus(node.lsps1_liquidity().check_order_status(order_id))
+        }
+        AppCommand::PayLsps1Order { invoice } => AppResult::Lsps1OrderPaid(node.bolt11_payment().send(&invoice, None)),
+        AppCommand::DepositInvoice { account, amount_msats } => {
+            let result = invoice_description(format!("Deposit to {}", account)).and_then(|description| {
+                node.bolt11_payment().receive(amount_msats, &description, invoice_expiry_secs())
+            });
+            AppResult::DepositInvoiceGenerated { account, result }
+        }
+        AppCommand::AccountWithdraw { withdrawal_id, invoice, amount_msats } => {
+            let payment = node.bolt11_payment();
+            let result = match amount_msats {
+                Some(amount_msats) => payment.send_using_amount(&invoice, amount_msats, None),
+                None => payment.send(&invoice, None),
+            };
+            AppResult::AccountWithdrawSent { withdrawal_id, result }
+        }
+        AppCommand::AccountSendOnchain { withdrawal_id, address, amount_sats } => {
+            let result = node.onchain_payment().send_to_address(&address, amount_sats, None);
+            AppResult::AccountOnchainSent { withdrawal_id, result }
+        }
+    }
+}
+
+/// Expiry of invoices created from the UI, as set in the settings
+fn invoice_expiry_secs() -> u32 {
+    settings::get().payments.invoice_expiry_secs
+}
+
+fn invoice_description(description: String) -> Result<Bolt11InvoiceDescription, NodeError> {
+    Description::new(description)
+        .map(Bolt11InvoiceDescription::Direct)
+        .map_err(|_| NodeError::InvalidInvoice)
+}
+
+/// A button that is disabled and shows a spinner while a command of `kind` is in flight.
+/// Returns true if it was clicked.
+pub fn command_button(ui: &mut egui::Ui, worker: &Worker, kind: CommandKind, label: &str) -> bool {
+    let busy = worker.is_busy(kind);
+    ui.horizontal(|ui| {
+        let clicked = ui.add_enabled(!busy, egui::Button::new(label)).clicked();
+        if busy {
+            ui.spinner();
+        }
+        clicked
+    })
+    .inner
+}